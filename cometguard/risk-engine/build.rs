@@ -0,0 +1,25 @@
+//! Embeds the git commit the engine is built from as `COMETGUARD_GIT_HASH`, "unknown" outside
+//! a git checkout (e.g. a crate tarball)
+
+use std::path::Path;
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !text.trim().is_empty()).then(|| text.trim().to_string())
+}
+
+fn main() {
+    let hash = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=COMETGUARD_GIT_HASH={}", hash);
+    println!("cargo:rerun-if-changed=build.rs");
+    // Rebuild when HEAD moves: a checkout changes HEAD, a commit the branch it points to
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}", git_dir.join(branch).display());
+        }
+    }
+}
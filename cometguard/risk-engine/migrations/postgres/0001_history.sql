@@ -0,0 +1,43 @@
+-- Assessment history shared by several engines; `instance_id` tells their rows apart
+
+CREATE TABLE assessments (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id TEXT NOT NULL,
+    chain_id BIGINT NOT NULL,
+    market_address TEXT NOT NULL,
+    market_name TEXT NOT NULL,
+    assessed_at TIMESTAMPTZ NOT NULL,
+    risk_score SMALLINT NOT NULL,
+    mock_data BOOLEAN NOT NULL,
+    watchlist_min_health_factor DOUBLE PRECISION,
+    -- A retried write whose first commit did go through must not be stored twice
+    UNIQUE (instance_id, market_address, assessed_at)
+);
+
+CREATE INDEX assessments_market_time ON assessments (market_address, assessed_at);
+
+CREATE TABLE findings (
+    id BIGSERIAL PRIMARY KEY,
+    assessment_id BIGINT NOT NULL REFERENCES assessments (id) ON DELETE CASCADE,
+    fingerprint TEXT NOT NULL,
+    category TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    severity_rank SMALLINT NOT NULL,
+    description TEXT NOT NULL,
+    metadata JSONB NOT NULL,
+    found_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX findings_assessment ON findings (assessment_id);
+CREATE INDEX findings_fingerprint ON findings (fingerprint);
+
+CREATE TABLE market_snapshots (
+    assessment_id BIGINT PRIMARY KEY REFERENCES assessments (id) ON DELETE CASCADE,
+    base_symbol TEXT NOT NULL,
+    base_price DOUBLE PRECISION NOT NULL,
+    total_supply DOUBLE PRECISION NOT NULL,
+    total_borrow DOUBLE PRECISION NOT NULL,
+    utilization_rate DOUBLE PRECISION NOT NULL,
+    supply_apr DOUBLE PRECISION NOT NULL,
+    borrow_apr DOUBLE PRECISION NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Parameters of each collateral asset at each assessment, as a JSON array of
+-- `CollateralParameters`, for the changes governance makes to them; NULL for snapshots stored
+-- before they were recorded
+
+ALTER TABLE market_snapshots ADD COLUMN collateral JSONB;
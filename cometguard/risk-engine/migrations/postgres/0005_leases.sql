@@ -0,0 +1,10 @@
+-- Leases engines sharing the database elect a leader with (`storage.postgres.leader_election`);
+-- expiry is compared with the database's clock only, so the engines' clocks may disagree
+
+CREATE TABLE leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- Counts up with every change of holder
+    term BIGINT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Notes operators attach to findings, by fingerprint; a finding can have several, the newest
+-- is shown with it. `instance_id` is the engine the note was made through
+
+CREATE TABLE annotations (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    note TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX annotations_fingerprint ON annotations (fingerprint, created_at);
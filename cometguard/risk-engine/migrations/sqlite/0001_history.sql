@@ -0,0 +1,40 @@
+-- Assessment history: one row per market assessment, with its findings and a market snapshot
+
+CREATE TABLE assessments (
+    id INTEGER PRIMARY KEY,
+    chain_id INTEGER NOT NULL,
+    market_address TEXT NOT NULL,
+    market_name TEXT NOT NULL,
+    assessed_at INTEGER NOT NULL,
+    risk_score INTEGER NOT NULL,
+    mock_data INTEGER NOT NULL,
+    watchlist_min_health_factor REAL
+);
+
+CREATE INDEX assessments_market_time ON assessments (market_address, assessed_at);
+
+CREATE TABLE findings (
+    id INTEGER PRIMARY KEY,
+    assessment_id INTEGER NOT NULL REFERENCES assessments (id) ON DELETE CASCADE,
+    fingerprint TEXT NOT NULL,
+    category TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    severity_rank INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    metadata TEXT NOT NULL,
+    found_at INTEGER NOT NULL
+);
+
+CREATE INDEX findings_assessment ON findings (assessment_id);
+CREATE INDEX findings_fingerprint ON findings (fingerprint);
+
+CREATE TABLE market_snapshots (
+    assessment_id INTEGER PRIMARY KEY REFERENCES assessments (id) ON DELETE CASCADE,
+    base_symbol TEXT NOT NULL,
+    base_price REAL NOT NULL,
+    total_supply REAL NOT NULL,
+    total_borrow REAL NOT NULL,
+    utilization_rate REAL NOT NULL,
+    supply_apr REAL NOT NULL,
+    borrow_apr REAL NOT NULL
+);
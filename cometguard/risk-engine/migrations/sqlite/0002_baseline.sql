@@ -0,0 +1,5 @@
+-- Reserves and collateral price volatility at each assessment, for per-market baselines;
+-- NULL where the assessment did not read them
+
+ALTER TABLE market_snapshots ADD COLUMN reserves REAL;
+ALTER TABLE market_snapshots ADD COLUMN price_volatility REAL;
@@ -0,0 +1,7 @@
+-- Engine version and configuration hash of each assessment, so history written by different
+-- engine versions can be told apart; NULL for assessments stored before they were recorded
+
+ALTER TABLE assessments ADD COLUMN engine_version TEXT;
+ALTER TABLE assessments ADD COLUMN config_hash TEXT;
+
+CREATE INDEX assessments_engine_version ON assessments (engine_version);
@@ -0,0 +1,4 @@
+-- Sub-score of each risk category at each assessment, as a JSON object from category to score,
+-- null for categories no check evaluated; NULL for assessments stored before they were recorded
+
+ALTER TABLE assessments ADD COLUMN category_scores TEXT;
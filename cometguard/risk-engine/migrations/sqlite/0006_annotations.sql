@@ -0,0 +1,12 @@
+-- Notes operators attach to findings, by fingerprint; a finding can have several, the newest
+-- is shown with it
+
+CREATE TABLE annotations (
+    id INTEGER PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    note TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX annotations_fingerprint ON annotations (fingerprint, created_at);
@@ -0,0 +1,305 @@
+//! What absorbing one position does to its market
+//!
+//! `absorb` clears the position's debt and credits it its collateral's value less each asset's
+//! liquidation penalty (Comet's `liquidationFactor`); credit above the debt stays in the market
+//! as the account's base supply.
+//! The protocol then sells the collateral at the store-front price, `collateral_discount` below
+//! the oracle price, and the proceeds go to reserves. Reserves so change by the proceeds minus
+//! the debt and the account's surplus: a position whose collateral sells for less than that
+//! leaves a shortfall the reserves pay, and past the reserves the suppliers.
+//!
+//! Buyers of the collateral sell it on DEXes. Where the asset's `dex_depth_usd` is configured,
+//! the price impact of that sale grows linearly from `DEPTH_PRICE_IMPACT` at the depth, which
+//! understates it well past the depth; otherwise the asset's `dex_slippage` stands in. The
+//! knock-on effect moves every collateral price down by its impact and counts the other
+//! positions it pushes below a health factor of 1.
+
+use crate::absorption::collateral_discount;
+use crate::config::Config;
+use crate::models::{Market, UserPosition};
+use crate::utils;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+/// Price move at which `dex_depth_usd` is measured
+pub const DEPTH_PRICE_IMPACT: f64 = 0.02;
+
+/// Position whose absorption is simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbsorbTarget {
+    Account(Address),
+    /// The tracked position borrowing the most
+    Largest,
+}
+
+/// Sale of one collateral asset of the absorbed position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollateralSale {
+    pub symbol: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub address: Address,
+    /// In asset units
+    pub amount: f64,
+    /// At the oracle price
+    pub value_usd: f64,
+    /// Credited to the account: `value_usd` less the liquidation penalty
+    pub credited_usd: f64,
+    /// Paid by buyers at the store-front price
+    pub proceeds_usd: f64,
+    /// `collateral_discount` of the asset
+    pub discount: f64,
+    /// `dex_depth_usd` setting of the asset
+    pub dex_depth_usd: Option<f64>,
+    /// Expected price impact of selling `value_usd` on DEXes; `None` without depth or slippage
+    pub price_impact: Option<f64>,
+}
+
+impl CollateralSale {
+    /// The sale is larger than the DEX depth of the asset
+    pub fn exceeds_depth(&self) -> bool {
+        self.dex_depth_usd.is_some_and(|depth| self.value_usd > depth)
+    }
+
+    /// The price impact eats the whole discount, so buying the collateral does not pay
+    pub fn unprofitable(&self) -> bool {
+        self.price_impact.is_some_and(|impact| impact >= self.discount)
+    }
+}
+
+/// Positions the price impact of the sale would make liquidatable
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KnockOn {
+    /// Other borrowing positions checked
+    pub positions_checked: usize,
+    /// Healthy now, below a health factor of 1 at the impacted prices
+    #[serde(serialize_with = "crate::utils::checksummed_list")]
+    pub newly_liquidatable: Vec<Address>,
+    /// Borrow of those positions, in USD
+    pub borrow_usd: f64,
+}
+
+/// Outcome of absorbing one position at current prices
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AbsorptionSimulation {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub account: Address,
+    /// Debt cleared by the absorption, in USD
+    pub debt_usd: f64,
+    pub health_factor: f64,
+    pub collateral: Vec<CollateralSale>,
+    /// Credit above the debt, kept by the account as base supply, in USD
+    pub surplus_usd: f64,
+    pub reserves_before_usd: f64,
+    pub reserves_after_usd: f64,
+    /// What reserves pay towards the debt, in USD; 0 when the sale covers it
+    pub shortfall_usd: f64,
+    pub utilization_before: f64,
+    pub utilization_after: f64,
+    pub knock_on: KnockOn,
+    pub timestamp: DateTime<Utc>,
+    /// Whether the simulation started from mock or fixture data
+    #[serde(default)]
+    pub mock_data: bool,
+}
+
+impl AbsorptionSimulation {
+    /// Change in reserves, in USD
+    pub fn reserves_change_usd(&self) -> f64 {
+        self.reserves_after_usd - self.reserves_before_usd
+    }
+
+    /// Reserves pay the whole shortfall, if any
+    pub fn reserves_cover_shortfall(&self) -> bool {
+        self.reserves_after_usd >= 0.0
+    }
+
+    /// Whether reserves absorb the shortfall, in one sentence
+    pub fn verdict(&self) -> String {
+        if self.shortfall_usd <= 0.0 {
+            format!(
+                "No shortfall: the collateral covers the debt and reserves gain {}",
+                utils::format_money(self.reserves_change_usd(), "$")
+            )
+        } else if self.reserves_cover_shortfall() {
+            format!(
+                "Reserves absorb the {} shortfall, {} left",
+                utils::format_money(self.shortfall_usd, "$"),
+                utils::format_money(self.reserves_after_usd, "$")
+            )
+        } else {
+            format!(
+                "Reserves cannot absorb the {} shortfall: {} of bad debt falls on suppliers",
+                utils::format_money(self.shortfall_usd, "$"),
+                utils::format_money(-self.reserves_after_usd, "$")
+            )
+        }
+    }
+}
+
+/// Position of `target` among `positions`, the largest borrow for `AbsorbTarget::Largest`
+pub fn select(positions: &[UserPosition], target: AbsorbTarget) -> Option<&UserPosition> {
+    match target {
+        AbsorbTarget::Account(account) => positions.iter().find(|p| p.address == account),
+        AbsorbTarget::Largest => positions
+            .iter()
+            .filter(|p| p.base_balance < 0.0)
+            .max_by(|a, b| a.total_borrow_value.total_cmp(&b.total_borrow_value)),
+    }
+}
+
+/// Absorption of `position` in `market` with `reserves_usd` of reserves, as
+/// `ProtocolMetrics::reserves` reports them; `others` are the positions the price impact is
+/// applied to
+pub fn simulate(market: &Market, position: &UserPosition, others: &[UserPosition], reserves_usd: f64, config: &Config) -> AbsorptionSimulation {
+    let base_price = market.base_asset.price;
+    let debt = (-position.base_balance).max(0.0);
+    let debt_usd = debt * base_price;
+
+    let mut collateral: Vec<CollateralSale> = position
+        .collateral_balances
+        .iter()
+        .filter(|(_, &amount)| amount > 0.0)
+        .filter_map(|(address, &amount)| {
+            let asset = market.collateral_assets.get(address)?;
+            let settings = config.asset_settings(asset);
+            let value_usd = amount * asset.price;
+            let discount = collateral_discount(market, asset);
+            let depth_impact = settings
+                .dex_depth_usd
+                .filter(|depth| *depth > 0.0)
+                .map(|depth| DEPTH_PRICE_IMPACT * value_usd / depth);
+            Some(CollateralSale {
+                symbol: asset.symbol.clone(),
+                address: *address,
+                amount,
+                value_usd,
+                credited_usd: value_usd * (1.0 - asset.liquidation_penalty),
+                proceeds_usd: value_usd * (1.0 - discount),
+                discount,
+                dex_depth_usd: settings.dex_depth_usd,
+                price_impact: depth_impact.or(settings.dex_slippage).map(|impact| impact.min(1.0)),
+            })
+        })
+        .collect();
+    collateral.sort_by(|a, b| b.value_usd.total_cmp(&a.value_usd));
+
+    let credited_usd: f64 = collateral.iter().map(|sale| sale.credited_usd).sum();
+    let proceeds_usd: f64 = collateral.iter().map(|sale| sale.proceeds_usd).sum();
+    let surplus_usd = (credited_usd - debt_usd).max(0.0);
+    let reserves_before_usd = reserves_usd;
+    let reserves_change_usd = proceeds_usd - debt_usd - surplus_usd;
+
+    let (total_supply, total_borrow) = if base_price > 0.0 {
+        (market.total_supply + surplus_usd / base_price, market.total_borrow - debt)
+    } else {
+        (market.total_supply, market.total_borrow - debt)
+    };
+
+    AbsorptionSimulation {
+        market_name: market.name.clone(),
+        market_address: market.comet_address,
+        account: position.address,
+        debt_usd,
+        health_factor: position.health_factor,
+        surplus_usd,
+        reserves_before_usd,
+        reserves_after_usd: reserves_before_usd + reserves_change_usd,
+        shortfall_usd: (-reserves_change_usd).max(0.0),
+        utilization_before: market.utilization(),
+        utilization_after: Market::utilization_of(total_supply, total_borrow),
+        knock_on: knock_on(market, &collateral, position.address, others),
+        collateral,
+        timestamp: Utc::now(),
+        mock_data: false,
+    }
+}
+
+/// Other positions in `market` that the price impact of `sales` makes liquidatable
+fn knock_on(market: &Market, sales: &[CollateralSale], absorbed: Address, others: &[UserPosition]) -> KnockOn {
+    let mut impacted = market.clone();
+    for sale in sales {
+        if let (Some(asset), Some(impact)) = (impacted.collateral_assets.get_mut(&sale.address), sale.price_impact) {
+            asset.price *= 1.0 - impact;
+        }
+    }
+    let mut result = KnockOn::default();
+    for position in others.iter().filter(|p| p.address != absorbed && p.base_balance < 0.0) {
+        result.positions_checked += 1;
+        let before = market.health_factor(position.base_balance, &position.collateral_balances);
+        let after = impacted.health_factor(position.base_balance, &position.collateral_balances);
+        if before >= 1.0 && after < 1.0 {
+            result.newly_liquidatable.push(position.address);
+            result.borrow_usd += position.total_borrow_value;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AssetConfig;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+
+    #[test]
+    fn test_absorbing_reports_reserves_utilization_and_knock_on() {
+        let data = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().clone();
+        let market = &data.markets[0];
+        let positions: Vec<UserPosition> = data.positions.iter().map(|p| p.position.clone()).collect();
+        let reserves = 25_000_000.0;
+
+        // 1750 USDC against 1 WETH at $2000: credited 1900 after the 5% liquidation penalty and,
+        // without a store-front factor, sold for as much, so reserves keep 1900 - 1750 - 150
+        let largest = select(&positions, AbsorbTarget::Largest).unwrap();
+        assert_eq!(largest.address, Address::repeat_byte(0x22));
+        let simulation = simulate(market, largest, &positions, reserves, &Config::default());
+        assert!((simulation.surplus_usd - 150.0).abs() < 1e-9);
+        assert!(simulation.reserves_change_usd().abs() < 1e-6);
+        assert_eq!(simulation.shortfall_usd, 0.0);
+        assert!(simulation.utilization_after < simulation.utilization_before);
+        assert_eq!(simulation.collateral[0].price_impact, None);
+        assert!(simulation.verdict().starts_with("No shortfall"), "{}", simulation.verdict());
+
+        // Underwater at 1950: the sale falls 50 short, which reserves pay. Selling $2000 into
+        // $80 of depth moves WETH 50%, taking the other position from 1.82 to 0.91
+        let mut config = Config::default();
+        config.assets.insert("WETH".to_string(), AssetConfig { dex_depth_usd: Some(80.0), ..AssetConfig::default() });
+        let underwater = UserPosition { base_balance: -1950.0, total_borrow_value: 1950.0, ..largest.clone() };
+        let simulation = simulate(market, &underwater, &positions, reserves, &config);
+        assert!((simulation.shortfall_usd - 50.0).abs() < 1e-6);
+        assert!(simulation.reserves_cover_shortfall());
+        assert!(simulation.verdict().starts_with("Reserves absorb the $50.00 shortfall"), "{}", simulation.verdict());
+        let sale = &simulation.collateral[0];
+        assert!((sale.price_impact.unwrap() - 0.5).abs() < 1e-9);
+        assert!(sale.exceeds_depth() && sale.unprofitable());
+        assert_eq!(simulation.knock_on.positions_checked, 1);
+        assert_eq!(simulation.knock_on.newly_liquidatable, vec![Address::repeat_byte(0x11)]);
+
+        // Without reserves the shortfall is bad debt
+        let simulation = simulate(market, &underwater, &positions, 20.0, &config);
+        assert!(!simulation.reserves_cover_shortfall());
+        assert!(simulation.verdict().contains("$30.00 of bad debt"), "{}", simulation.verdict());
+    }
+
+    #[test]
+    fn test_collateral_is_credited_less_the_liquidation_penalty() {
+        let data = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().clone();
+        let mut market = data.markets[0].clone();
+        market.store_front_price_factor = Some(0.6);
+        let position = &data.positions.iter().find(|p| p.position.address == Address::repeat_byte(0x22)).unwrap().position;
+
+        // 1 WETH at $2000 with a 5% penalty is credited 1900, not 1820 at the 0.91 liquidation
+        // factor, and sold at 0.6 of the penalty below the price: reserves keep 1940 - 1750 - 150
+        let simulation = simulate(&market, position, &[], 25_000_000.0, &Config::default());
+        let sale = &simulation.collateral[0];
+        assert!((sale.credited_usd - 2000.0 * (1.0 - 0.05)).abs() < 1e-9, "{}", sale.credited_usd);
+        assert!((sale.discount - collateral_discount(&market, &market.collateral_assets[&sale.address])).abs() < 1e-12);
+        assert!((sale.proceeds_usd - 1940.0).abs() < 1e-9);
+        assert!((simulation.surplus_usd - 150.0).abs() < 1e-9);
+        assert!((simulation.reserves_change_usd() - 40.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,177 @@
+//! Whether liquidating pays
+//!
+//! Absorbing a position moves its collateral to the protocol, which sells it at a discount of
+//! the asset's liquidation penalty times the store-front price factor; that discount is all a
+//! liquidator earns. A position just above `baseBorrowMin` with little collateral earns less
+//! than the gas of absorbing it and buying its collateral, so nobody liquidates it and it can
+//! turn into bad debt. Nor does anyone buy collateral whose discount a day's price move and the
+//! DEX slippage of selling it wipe out, which stalls absorptions in a crash.
+
+use crate::models::{Asset, Market, UserPosition};
+use serde::{Deserialize, Serialize};
+
+/// Upper edges, in USD, of the borrow size buckets of `BorrowBucket::histogram`; the last
+/// bucket has no upper edge
+pub const BUCKET_EDGES_USD: [f64; 6] = [100.0, 250.0, 500.0, 1_000.0, 2_500.0, 5_000.0];
+
+/// Estimated liquidator profit of absorbing a position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AbsorptionEstimate {
+    /// Discount on the position's collateral, in USD
+    pub collateral_discount_usd: f64,
+    pub gas_cost_usd: f64,
+    /// Discount minus gas; negative when absorbing does not pay
+    pub profit_usd: f64,
+}
+
+/// Discount on `asset` bought from `market` after an absorption, as a fraction of its price
+///
+/// Without a store-front price factor the whole liquidation penalty is assumed, an upper bound.
+pub fn collateral_discount(market: &Market, asset: &Asset) -> f64 {
+    asset.liquidation_penalty * market.store_front_price_factor.unwrap_or(1.0)
+}
+
+/// Profit of absorbing `position` in `market` at `gas_cost_usd`
+pub fn estimate(position: &UserPosition, market: &Market, gas_cost_usd: f64) -> AbsorptionEstimate {
+    let collateral_discount_usd: f64 = position
+        .collateral_balances
+        .iter()
+        .filter_map(|(address, &balance)| {
+            let asset = market.collateral_assets.get(address)?;
+            Some(balance * asset.price * collateral_discount(market, asset))
+        })
+        .sum();
+    AbsorptionEstimate {
+        collateral_discount_usd,
+        gas_cost_usd,
+        profit_usd: collateral_discount_usd - gas_cost_usd,
+    }
+}
+
+/// Borrowing positions of one size range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BorrowBucket {
+    pub min_usd: f64,
+    /// `None` for the last bucket
+    pub max_usd: Option<f64>,
+    pub positions: usize,
+    /// Positions in the bucket not worth absorbing
+    pub unprofitable: usize,
+    /// Borrow of those positions, in USD
+    pub unprofitable_borrow_usd: f64,
+}
+
+impl BorrowBucket {
+    /// Borrowing `positions` by `BUCKET_EDGES_USD`, with the unprofitable ones at `gas_cost_usd`
+    pub fn histogram(positions: &[UserPosition], market: &Market, gas_cost_usd: f64) -> Vec<BorrowBucket> {
+        let mut buckets: Vec<BorrowBucket> = std::iter::once(0.0)
+            .chain(BUCKET_EDGES_USD)
+            .zip(BUCKET_EDGES_USD.map(Some).into_iter().chain([None]))
+            .map(|(min_usd, max_usd)| BorrowBucket { min_usd, max_usd, positions: 0, unprofitable: 0, unprofitable_borrow_usd: 0.0 })
+            .collect();
+        for position in positions.iter().filter(|p| p.total_borrow_value > 0.0) {
+            let borrow = position.total_borrow_value;
+            let index = BUCKET_EDGES_USD.iter().take_while(|&&edge| borrow >= edge).count();
+            let bucket = &mut buckets[index];
+            bucket.positions += 1;
+            if estimate(position, market, gas_cost_usd).profit_usd < 0.0 {
+                bucket.unprofitable += 1;
+                bucket.unprofitable_borrow_usd += borrow;
+            }
+        }
+        buckets
+    }
+}
+
+/// How many inputs of a `LiquidatorMargin` were available, or how closely a total sampled
+/// by `sampling::PositionSample` is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// Two or more inputs missing, or a wide sampling margin
+    Low,
+    /// One input missing, or a narrow sampling margin
+    Medium,
+    /// Every input available, or every position read
+    High,
+}
+
+/// A collateral asset's liquidator margin against the cost of buying it in a crash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiquidatorMargin {
+    /// `collateral_discount` of the asset
+    pub margin: f64,
+    /// Absolute daily price move at the configured percentile; `None` without price history
+    pub daily_move: Option<f64>,
+    /// `dex_slippage` setting of the asset
+    pub slippage: Option<f64>,
+    /// Inputs that were unavailable: `store_front_price_factor`, `price_history`, `dex_slippage`
+    pub missing: Vec<String>,
+}
+
+impl LiquidatorMargin {
+    /// Margin of `asset` in `market` given the inputs that are available
+    pub fn new(market: &Market, asset: &Asset, daily_move: Option<f64>, slippage: Option<f64>) -> Self {
+        let missing = [
+            (market.store_front_price_factor.is_none(), "store_front_price_factor"),
+            (daily_move.is_none(), "price_history"),
+            (slippage.is_none(), "dex_slippage"),
+        ];
+        Self {
+            margin: collateral_discount(market, asset),
+            daily_move,
+            slippage,
+            missing: missing.into_iter().filter(|(absent, _)| *absent).map(|(_, name)| name.to_string()).collect(),
+        }
+    }
+
+    /// Daily move plus slippage, counting what is missing as 0
+    pub fn expected_cost(&self) -> f64 {
+        self.daily_move.unwrap_or(0.0) + self.slippage.unwrap_or(0.0)
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        match self.missing.len() {
+            0 => Confidence::High,
+            1 => Confidence::Medium,
+            _ => Confidence::Low,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+    use ethers::types::Address;
+
+    #[tokio::test]
+    async fn test_small_positions_do_not_pay_for_gas() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let weth = *market.collateral_assets.keys().next().unwrap();
+        // WETH at $2000 with a 5% penalty: $100 of discount per WETH
+        let position = |borrow: f64, collateral: f64| UserPosition {
+            address: Address::zero(),
+            base_balance: -borrow,
+            collateral_balances: [(weth, collateral)].into_iter().collect(),
+            total_collateral_value: collateral * 2000.0,
+            total_borrow_value: borrow,
+            health_factor: collateral * 2000.0 * 0.825 / borrow,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+
+        let small = estimate(&position(150.0, 0.1), &market, 25.0);
+        assert!((small.collateral_discount_usd - 10.0).abs() < 1e-9 && small.profit_usd < 0.0, "{:?}", small);
+        assert!(estimate(&position(1_000.0, 1.0), &market, 25.0).profit_usd > 0.0);
+
+        let positions = [position(150.0, 0.1), position(200.0, 0.12), position(1_000.0, 1.0), position(9_000.0, 0.2)];
+        let buckets = BorrowBucket::histogram(&positions, &market, 25.0);
+        let counts: Vec<(usize, usize)> = buckets.iter().map(|b| (b.positions, b.unprofitable)).collect();
+        assert_eq!(counts, [(0, 0), (2, 2), (0, 0), (0, 0), (1, 0), (0, 0), (1, 1)]);
+        assert_eq!((buckets[1].min_usd, buckets[1].max_usd, buckets[1].unprofitable_borrow_usd), (100.0, Some(250.0), 350.0));
+        assert_eq!(buckets[6].max_usd, None);
+    }
+}
@@ -0,0 +1,1303 @@
+//! Alert delivery for new and escalated findings
+//!
+//! `AlertDispatcher` listens to `RiskEvent`s, matches each finding against the configured
+//! routes and delivers it through the route's `AlertSink`, retrying transient failures and
+//! suppressing repeats of the same finding within the cooldown. With `alerts.rules` set, the
+//! routing rules pick the sinks instead of the routes' own filters. An `AlertStateStore` keeps
+//! ongoing and acknowledged findings from alerting again after a restart.
+
+mod email;
+mod pagerduty;
+mod routing;
+mod state;
+
+pub use email::EmailSink;
+pub use pagerduty::PagerDutySink;
+pub use routing::{render_template, unknown_placeholders, RoutingRules, RuleOutcome, PLACEHOLDERS};
+pub use state::{Acknowledgement, AlertRecord, AlertStateStore};
+
+use crate::config::{Config, EmailMode, RouteFilter, TelegramConfig, WebhookFormat};
+use crate::denomination::{MarketUnits, Unit};
+use crate::leader::Leadership;
+use crate::error::{rpc_host, Result, RiskEngineError};
+use crate::events::RiskEvent;
+use crate::report;
+use crate::risk::{RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+use crate::utils;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Attempts made per alert and route before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Why an alert is being sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertKind {
+    /// The finding was not present in the previous run
+    New,
+    /// The finding persisted with a higher severity than before
+    Escalated { previous: RiskSeverity },
+    /// Synthetic finding sent by `alert-test` to verify delivery
+    Test,
+    /// A previously alerted finding is no longer present
+    Resolved,
+}
+
+/// A finding on its way to alert sinks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub chain_id: u64,
+    pub finding: RiskFinding,
+    /// Block explorer links for the market and, when known, the affected account
+    pub links: BTreeMap<String, String>,
+    /// Text rendered from the template of the routing rule that sent the alert
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Unit of `{amount.KEY}` in templates, the market's in `display.denomination`
+    #[serde(skip)]
+    pub unit: Unit,
+}
+
+impl Alert {
+    /// Alert for a new or escalated finding; other events do not alert
+    pub fn from_event(event: &RiskEvent, chain_id: u64) -> Option<Self> {
+        let (kind, market_name, market_address, finding) = match event {
+            RiskEvent::NewFinding { market_name, market_address, finding } => {
+                (AlertKind::New, market_name, market_address, finding)
+            }
+            RiskEvent::SeverityEscalated { market_name, market_address, previous, finding } => {
+                (AlertKind::Escalated { previous: *previous }, market_name, market_address, finding)
+            }
+            _ => return None,
+        };
+        Some(Self::new(kind, market_name.clone(), *market_address, chain_id, finding.clone()))
+    }
+
+    /// Resolution notice for a `FindingResolved` event
+    pub fn resolution(event: &RiskEvent, chain_id: u64) -> Option<Self> {
+        match event {
+            RiskEvent::FindingResolved { market_name, market_address, finding } => Some(Self::new(
+                AlertKind::Resolved,
+                market_name.clone(),
+                *market_address,
+                chain_id,
+                finding.clone(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Critical synthetic finding for the configured market, used to test routes end to end
+    pub fn synthetic(config: &Config) -> Self {
+        Self::synthetic_for(config, RiskSeverity::Critical, RiskCategory::HighUtilization, None, None)
+    }
+
+    /// Synthetic finding with the given severity and category, for `market` (the configured
+    /// market when `None`) and, like a watchlist finding, about `account`
+    pub fn synthetic_for(
+        config: &Config,
+        severity: RiskSeverity,
+        category: RiskCategory,
+        market: Option<Address>,
+        account: Option<Address>,
+    ) -> Self {
+        let market_address = market
+            .unwrap_or_else(|| utils::parse_address(&config.compound.comet_proxy_address).unwrap_or_default());
+        let finding = RiskFinding {
+            category,
+            severity,
+            description: "Test alert from risk-engine-cli alert-test; no action needed".to_string(),
+            metadata: match account {
+                Some(account) => json!({ "account": utils::to_checksum_address(&account) }),
+                None => json!({}),
+            },
+            timestamp: Utc::now(),
+            fingerprint: "alert-test".to_string(),
+            score_contribution: 0,
+        };
+        Self::new(AlertKind::Test, "alert-test".to_string(), market_address, config.compound.chain_id, finding)
+    }
+
+    fn new(kind: AlertKind, market_name: String, market_address: Address, chain_id: u64, finding: RiskFinding) -> Self {
+        let mut links = BTreeMap::new();
+        if let Some(url) = utils::explorer_address_url(chain_id, &market_address) {
+            links.insert("market".to_string(), url);
+        }
+        let account = finding.metadata.get("account").and_then(Value::as_str);
+        if let Some(url) = account
+            .and_then(|a| utils::parse_address(a).ok())
+            .and_then(|a| utils::explorer_address_url(chain_id, &a))
+        {
+            links.insert("account".to_string(), url);
+        }
+
+        Self { kind, market_name, market_address, chain_id, finding, links, message: None, unit: Unit::usd() }
+    }
+
+    /// One-line summary, e.g. `[Critical] New HighUtilization finding in USDC`
+    pub fn headline(&self) -> String {
+        let what = match self.kind {
+            AlertKind::New => "New".to_string(),
+            AlertKind::Escalated { previous } => format!("Escalated (was {:?})", previous),
+            AlertKind::Test => "Test".to_string(),
+            AlertKind::Resolved => "Resolved".to_string(),
+        };
+        format!(
+            "[{:?}] {} {:?} finding in {}",
+            self.finding.severity, what, self.finding.category, self.market_name
+        )
+    }
+
+    /// The routing rule's rendered template if one applied, the headline otherwise
+    pub fn summary(&self) -> String {
+        self.message.clone().unwrap_or_else(|| self.headline())
+    }
+}
+
+/// Destination alerts can be delivered to
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    /// Name used in logs and reports; must not contain secrets such as tokens in URLs
+    fn name(&self) -> String;
+
+    /// Deliver one alert, without retrying
+    async fn send(&self, alert: &Alert) -> Result<()>;
+
+    /// Clear an open alert (see `open_alerts`) whose finding is gone
+    ///
+    /// Only sinks with stateful incidents (PagerDuty) keep alerts open; messages cannot be unsent.
+    async fn resolve(&self, _alert: &Alert) -> Result<()> {
+        Ok(())
+    }
+
+    /// Alerts for `market` this sink still considers open, including ones restored after a restart
+    fn open_alerts(&self, _market: Address) -> Vec<Alert> {
+        Vec::new()
+    }
+}
+
+/// Sink that POSTs alerts to an HTTP endpoint
+pub struct WebhookSink {
+    url: String,
+    format: WebhookFormat,
+    client: reqwest::Client,
+}
+
+impl WebhookSink {
+    /// Sink posting to `url`, giving up on a request after `timeout`
+    pub fn new(url: impl Into<String>, format: WebhookFormat, timeout: Duration) -> Result<Self> {
+        let url = url.into();
+        reqwest::Url::parse(&url)
+            .map_err(|e| RiskEngineError::config("alerts.webhooks.url", format!("invalid URL: {}", e)))?;
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| RiskEngineError::config("alerts.webhooks", e.to_string()))?;
+        Ok(Self { url, format, client })
+    }
+
+    /// Request body for `alert` in the configured format
+    pub fn body(&self, alert: &Alert) -> Value {
+        match self.format {
+            WebhookFormat::Json => json!({
+                "kind": alert.kind,
+                "severity": alert.finding.severity,
+                "category": alert.finding.category,
+                "summary": alert.headline(),
+                "message": alert.message,
+                "market": {
+                    "name": alert.market_name,
+                    "address": alert.market_address,
+                    "chain_id": alert.chain_id,
+                },
+                "finding": alert.finding,
+                "links": alert.links,
+            }),
+            WebhookFormat::Slack => {
+                let mut text = match &alert.message {
+                    Some(message) => message.clone(),
+                    None => format!("*{}*\n{}", alert.headline(), alert.finding.description),
+                };
+                for (label, url) in &alert.links {
+                    text.push_str(&format!("\n<{}|View {} on explorer>", url, label));
+                }
+                json!({ "text": text })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookSink {
+    fn name(&self) -> String {
+        format!("webhook {}", rpc_host(&self.url))
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        post_json(&self.client, &self.url, &self.body(alert), self.name()).await
+    }
+}
+
+/// Sink that sends alerts as Telegram bot messages, formatted for small screens
+pub struct TelegramSink {
+    api_url: String,
+    bot_token: String,
+    chat_id: String,
+    client: reqwest::Client,
+}
+
+impl TelegramSink {
+    /// Sink posting to `chat_id` as the bot at `api_url`, giving up on a request after `timeout`
+    pub fn new(config: &TelegramConfig, timeout: Duration) -> Result<Self> {
+        if config.bot_token.is_empty() {
+            return Err(RiskEngineError::config("alerts.telegram.bot_token", "the bot token is empty"));
+        }
+        reqwest::Url::parse(&config.api_url)
+            .map_err(|e| RiskEngineError::config("alerts.telegram.api_url", format!("invalid URL: {}", e)))?;
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| RiskEngineError::config("alerts.telegram", e.to_string()))?;
+        Ok(Self {
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            bot_token: config.bot_token.clone(),
+            chat_id: config.chat_id.clone(),
+            client,
+        })
+    }
+
+    /// HTML message text for `alert`
+    ///
+    /// Watchlist findings lead with the health factor and the liquidation price of the
+    /// position's dominant collateral. A routing rule's rendered template replaces everything
+    /// but the links.
+    pub fn message(alert: &Alert) -> String {
+        let mut lines = Vec::new();
+        if let Some(message) = &alert.message {
+            lines.push(escape_html(message));
+        } else {
+            lines.extend(Self::finding_lines(alert));
+        }
+        let links: Vec<String> = alert
+            .links
+            .iter()
+            .map(|(label, url)| format!("<a href=\"{}\">{}</a>", url, label))
+            .collect();
+        if !links.is_empty() {
+            lines.push(links.join(" · "));
+        }
+        lines.join("\n")
+    }
+
+    fn finding_lines(alert: &Alert) -> Vec<String> {
+        let finding = &alert.finding;
+        let metadata = &finding.metadata;
+        let mut lines = vec![format!(
+            "{} <b>{:?}</b> · {}",
+            severity_emoji(finding.severity),
+            finding.severity,
+            escape_html(&alert.market_name)
+        )];
+        match alert.kind {
+            AlertKind::Escalated { previous } => lines.push(format!("Escalated from {:?}", previous)),
+            AlertKind::Test => lines.push("Test alert".to_string()),
+            AlertKind::Resolved => lines.push("✅ Resolved".to_string()),
+            AlertKind::New => {}
+        }
+        if let Some(health_factor) = metadata.get("health_factor").and_then(Value::as_f64) {
+            lines.push(format!("Health factor: <b>{:.2}</b>", health_factor));
+        }
+        let collateral = metadata.get("dominant_collateral").and_then(Value::as_str);
+        let liquidation_price = metadata.get("liquidation_price").and_then(Value::as_f64);
+        if let (Some(symbol), Some(price)) = (collateral, liquidation_price) {
+            lines.push(format!("Liq. price {}: {}", escape_html(symbol), utils::format_money(price, "$")));
+        }
+        let account = metadata.get("account").and_then(Value::as_str).and_then(|a| utils::parse_address(a).ok());
+        if let Some(account) = account {
+            lines.push(format!("Account: <code>{}</code>", escape_html(&utils::format_address_labeled(&account))));
+        }
+        if metadata.get("health_factor").is_none() {
+            lines.push(format!("{:?}", finding.category));
+        }
+        lines.push(escape_html(&finding.description));
+        lines
+    }
+}
+
+#[async_trait]
+impl AlertSink for TelegramSink {
+    fn name(&self) -> String {
+        format!("telegram chat {}", self.chat_id)
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let url = format!("{}/bot{}/sendMessage", self.api_url, self.bot_token);
+        let body = json!({
+            "chat_id": self.chat_id,
+            "text": Self::message(alert),
+            "parse_mode": "HTML",
+            "disable_web_page_preview": true,
+        });
+        post_json(&self.client, &url, &body, self.name()).await
+    }
+}
+
+fn severity_emoji(severity: RiskSeverity) -> &'static str {
+    match severity {
+        RiskSeverity::Critical => "🔴",
+        RiskSeverity::High => "🟠",
+        RiskSeverity::Medium => "🟡",
+        RiskSeverity::Low => "🔵",
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// POST `body` to `url`, classifying failures as retryable or not
+///
+/// Errors never include the URL, which may embed a token.
+async fn post_json(client: &reqwest::Client, url: &str, body: &Value, sink: String) -> Result<()> {
+    let response = client
+        .post(url)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| RiskEngineError::Delivery {
+            sink: sink.clone(),
+            message: e.without_url().to_string(),
+            retryable: true,
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(RiskEngineError::Delivery {
+        sink,
+        message: format!("endpoint answered {}", status),
+        retryable: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    })
+}
+
+/// A sink together with the findings it receives
+#[derive(Clone)]
+pub struct AlertRoute {
+    sink: Arc<dyn AlertSink>,
+    filter: RouteFilter,
+    /// Name routing rules refer to the sink by
+    sink_name: Option<String>,
+}
+
+impl AlertRoute {
+    pub fn new(sink: Arc<dyn AlertSink>, filter: RouteFilter) -> Self {
+        Self { sink, filter, sink_name: None }
+    }
+
+    /// Route that routing rules refer to as `sink_name`
+    pub fn named(mut self, sink_name: impl Into<String>) -> Self {
+        self.sink_name = Some(sink_name.into());
+        self
+    }
+
+    /// Name of the route's sink
+    pub fn name(&self) -> String {
+        self.sink.name()
+    }
+}
+
+/// Findings collected over one digest period
+#[derive(Debug, Clone)]
+pub struct Digest {
+    /// Start of the period
+    pub since: DateTime<Utc>,
+    /// End of the period
+    pub until: DateTime<Utc>,
+    /// Latest assessment of every market seen so far, in the order markets were first seen
+    pub assessments: Vec<RiskAssessment>,
+    /// New and escalated findings of the period that passed the route filter
+    pub alerts: Vec<Alert>,
+}
+
+impl Digest {
+    /// Plain-text digest with a report section per market, followed by its new findings with
+    /// the latest operator note on each; times are written in `timezone`
+    pub fn render_text(&self, timezone: Tz) -> String {
+        let mut text = format!(
+            "Risk digest {} to {}\n{} new or escalated finding(s)\n",
+            utils::format_timestamp(&self.since, timezone),
+            utils::format_timestamp(&self.until, timezone),
+            self.alerts.len()
+        );
+
+        let mut markets: Vec<(Address, String)> =
+            self.assessments.iter().map(|a| (a.market_address, a.market_name.clone())).collect();
+        for alert in &self.alerts {
+            if !markets.iter().any(|(address, _)| *address == alert.market_address) {
+                markets.push((alert.market_address, alert.market_name.clone()));
+            }
+        }
+
+        for (address, name) in markets {
+            text.push_str(&format!("\n== {} ==\n", utils::sanitize_inline(&name)));
+            if let Some(assessment) = self.assessments.iter().find(|a| a.market_address == address) {
+                text.push_str(&report::market_section(assessment));
+            }
+            let alerts: Vec<&Alert> = self.alerts.iter().filter(|a| a.market_address == address).collect();
+            if !alerts.is_empty() {
+                text.push_str("\nNew since the last digest:\n");
+                let annotations = self.assessments.iter().find(|a| a.market_address == address).map(|a| &a.annotations);
+                for alert in alerts {
+                    text.push_str(&format!(
+                        "  - {}: {}\n",
+                        utils::sanitize_inline(&alert.headline()),
+                        utils::sanitize_inline(&alert.finding.description)
+                    ));
+                    if let Some(annotation) = annotations.and_then(|annotations| annotations.get(&alert.finding.fingerprint)) {
+                        text.push_str(&format!("    Note: {}\n", annotation));
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Destination that receives periodic digests instead of individual alerts
+#[async_trait]
+pub trait DigestSink: Send + Sync {
+    /// Name used in logs and reports; must not contain secrets
+    fn name(&self) -> String;
+
+    /// Deliver one digest, without retrying
+    async fn send_digest(&self, digest: &Digest) -> Result<()>;
+}
+
+/// A digest sink, the findings it collects and how often it is sent
+pub struct DigestRoute {
+    sink: Arc<dyn DigestSink>,
+    filter: RouteFilter,
+    interval: Duration,
+    pending: Mutex<PendingDigest>,
+    /// Name routing rules refer to the sink by
+    sink_name: Option<String>,
+}
+
+struct PendingDigest {
+    started: Instant,
+    since: DateTime<Utc>,
+    assessments: Vec<RiskAssessment>,
+    alerts: Vec<Alert>,
+}
+
+impl DigestRoute {
+    pub fn new(sink: Arc<dyn DigestSink>, filter: RouteFilter, interval: Duration) -> Self {
+        let pending = PendingDigest {
+            started: Instant::now(),
+            since: Utc::now(),
+            assessments: Vec::new(),
+            alerts: Vec::new(),
+        };
+        Self { sink, filter, interval, pending: Mutex::new(pending), sink_name: None }
+    }
+
+    /// Digest route that routing rules refer to as `sink_name`
+    pub fn named(mut self, sink_name: impl Into<String>) -> Self {
+        self.sink_name = Some(sink_name.into());
+        self
+    }
+
+    /// Name of the route's sink
+    pub fn name(&self) -> String {
+        format!("{} (digest)", self.sink.name())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PendingDigest> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Collect `alert` if it passes the filter, returning whether it did
+    fn collect_alert(&self, alert: &Alert) -> bool {
+        let matches = self.filter.matches(&alert.finding);
+        if matches {
+            self.push_alert(alert);
+        }
+        matches
+    }
+
+    fn push_alert(&self, alert: &Alert) {
+        self.lock().alerts.push(alert.clone());
+    }
+
+    fn collect_assessment(&self, assessment: &RiskAssessment) {
+        let mut pending = self.lock();
+        match pending.assessments.iter_mut().find(|a| a.market_address == assessment.market_address) {
+            Some(slot) => *slot = assessment.clone(),
+            None => pending.assessments.push(assessment.clone()),
+        }
+    }
+
+    /// The digest to send if the period is over and it has findings
+    ///
+    /// A finished period without findings is restarted silently.
+    fn due(&self) -> Option<Digest> {
+        let mut pending = self.lock();
+        if pending.started.elapsed() < self.interval {
+            return None;
+        }
+        if pending.alerts.is_empty() {
+            pending.started = Instant::now();
+            pending.since = Utc::now();
+            return None;
+        }
+        Some(Digest {
+            since: pending.since,
+            until: Utc::now(),
+            assessments: pending.assessments.clone(),
+            alerts: pending.alerts.clone(),
+        })
+    }
+
+    /// Start a new period after `digest` was delivered
+    fn sent(&self, digest: &Digest) {
+        let mut pending = self.lock();
+        pending.started = Instant::now();
+        pending.since = digest.until;
+        pending.alerts.drain(..digest.alerts.len());
+    }
+}
+
+/// What happened to an alert on one route
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum DeliveryStatus {
+    /// The sink accepted the alert
+    Delivered,
+    /// The same finding was alerted on this route within the cooldown
+    Suppressed,
+    /// Every attempt failed; the rendered last error
+    Failed(String),
+}
+
+/// Latest outcomes of the deliveries through one route
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SinkDeliveries {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    /// Error of the last failed delivery
+    pub last_error: Option<String>,
+}
+
+impl SinkDeliveries {
+    /// Whether the latest delivery failed
+    pub fn failing(&self) -> bool {
+        self.last_failure.is_some_and(|failed| self.last_success.is_none_or(|sent| failed > sent))
+    }
+}
+
+/// Delivery outcomes by route name, kept by the engine for its diagnostics
+#[derive(Debug, Default)]
+pub struct DeliveryLog {
+    routes: Mutex<BTreeMap<String, SinkDeliveries>>,
+}
+
+impl DeliveryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of a delivery through `route`; suppressed alerts are not deliveries
+    pub fn record(&self, route: &str, status: &DeliveryStatus, at: DateTime<Utc>) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        match status {
+            DeliveryStatus::Delivered => routes.entry(route.to_string()).or_default().last_success = Some(at),
+            DeliveryStatus::Failed(error) => {
+                let deliveries = routes.entry(route.to_string()).or_default();
+                deliveries.last_failure = Some(at);
+                deliveries.last_error = Some(error.clone());
+            }
+            DeliveryStatus::Suppressed => {}
+        }
+    }
+
+    /// Outcomes through `route`, if anything was delivered or attempted on it
+    pub fn get(&self, route: &str) -> Option<SinkDeliveries> {
+        self.routes.lock().unwrap_or_else(|e| e.into_inner()).get(route).cloned()
+    }
+}
+
+/// Delivers alerts to every matching route
+pub struct AlertDispatcher {
+    routes: Vec<AlertRoute>,
+    digests: Vec<DigestRoute>,
+    rules: RoutingRules,
+    state: Option<Arc<AlertStateStore>>,
+    deliveries: Option<Arc<DeliveryLog>>,
+    /// Units of the assessed markets; alerts are rendered in USD without them
+    units: Option<Arc<MarketUnits>>,
+    /// Election deciding whether this engine alerts; it always does without one
+    leadership: Option<Arc<Leadership>>,
+    chain_id: u64,
+    cooldown: Duration,
+    retry_delay: Duration,
+    /// Last successful delivery per (route, fingerprint)
+    sent: Mutex<HashMap<(usize, String), (Instant, RiskSeverity)>>,
+}
+
+impl AlertDispatcher {
+    /// Dispatcher without routes for `chain_id`; alerts are dropped until routes are added
+    pub fn new(chain_id: u64, cooldown: Duration) -> Self {
+        Self {
+            routes: Vec::new(),
+            digests: Vec::new(),
+            rules: RoutingRules::default(),
+            state: None,
+            deliveries: None,
+            units: None,
+            leadership: None,
+            chain_id,
+            cooldown,
+            retry_delay: Duration::from_millis(500),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Dispatcher with a route per configured webhook, Telegram chat, email recipient list and
+    /// PagerDuty service, and the configured routing rules
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let timeout = Duration::from_secs(config.performance.timeout_seconds);
+        let alerts = &config.alerts;
+        let mut dispatcher = Self::new(config.compound.chain_id, Duration::from_secs(alerts.cooldown_seconds));
+        for (i, webhook) in alerts.webhooks.iter().enumerate() {
+            let sink = WebhookSink::new(webhook.url.clone(), webhook.format, timeout)?;
+            let route = AlertRoute::new(Arc::new(sink), webhook.filter.clone()).named(webhook.sink_name(i));
+            dispatcher = dispatcher.with_route(route);
+        }
+        if let Some(telegram) = &alerts.telegram {
+            let sink = TelegramSink::new(telegram, timeout)?;
+            dispatcher = dispatcher.with_route(AlertRoute::new(Arc::new(sink), telegram.filter.clone()).named("telegram"));
+        }
+        if let Some(pagerduty) = &alerts.pagerduty {
+            let sink = PagerDutySink::new(pagerduty, timeout)?;
+            dispatcher = dispatcher.with_route(AlertRoute::new(Arc::new(sink), pagerduty.filter.clone()).named("pagerduty"));
+        }
+        if let Some(email) = &alerts.email {
+            let sink = Arc::new(EmailSink::new(email, timeout)?.with_timezone(config.display.tz()));
+            dispatcher = match email.mode {
+                EmailMode::Immediate => dispatcher.with_route(AlertRoute::new(sink, email.filter.clone()).named("email")),
+                EmailMode::Digest => dispatcher.with_digest(
+                    DigestRoute::new(sink, email.filter.clone(), Duration::from_secs(email.digest_interval_seconds))
+                        .named("email"),
+                ),
+            };
+        }
+        Ok(dispatcher.with_rules(RoutingRules::new(&alerts.rules, &alerts.sink_names())?))
+    }
+
+    /// Add a route
+    pub fn with_route(mut self, route: AlertRoute) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Add a digest route
+    pub fn with_digest(mut self, route: DigestRoute) -> Self {
+        self.digests.push(route);
+        self
+    }
+
+    /// Route with `rules` instead of the routes' filters; rules refer to routes by their `named` name
+    pub fn with_rules(mut self, rules: RoutingRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Render `{amount.KEY}` in templates in the unit `units` holds for the alert's market
+    pub fn with_units(mut self, units: Arc<MarketUnits>) -> Self {
+        self.units = Some(units);
+        self
+    }
+
+    /// Consult and update `state` before alerting, so alerts already sent or acknowledged are
+    /// not sent again
+    pub fn with_state(mut self, state: Arc<AlertStateStore>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Record the outcome of every delivery in `deliveries`
+    pub fn with_delivery_log(mut self, deliveries: Arc<DeliveryLog>) -> Self {
+        self.deliveries = Some(deliveries);
+        self
+    }
+
+    /// Handle events only while this engine leads `leadership`; standbys drop them
+    pub fn with_leadership(mut self, leadership: Arc<Leadership>) -> Self {
+        self.leadership = Some(leadership);
+        self
+    }
+
+    /// Base delay between attempts; doubles after every failed attempt
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Configured alert routes
+    pub fn routes(&self) -> &[AlertRoute] {
+        &self.routes
+    }
+
+    /// Whether no alert or digest route is configured
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty() && self.digests.is_empty()
+    }
+
+    /// Names of the alert and digest routes, as recorded in the delivery log
+    pub fn route_names(&self) -> Vec<String> {
+        self.routes.iter().map(AlertRoute::name).chain(self.digests.iter().map(DigestRoute::name)).collect()
+    }
+
+    /// Deliver `alert` to every route whose filter it passes, or that the routing rules send it
+    /// to, returning the outcome per route
+    ///
+    /// A finding already alerted on a route within the cooldown is suppressed unless its
+    /// severity is now higher than when it was last sent. Digest routes only collect it.
+    /// With alert state, a finding it reports as already notified or acknowledged goes nowhere.
+    pub async fn dispatch(&self, alert: &Alert) -> Vec<(String, DeliveryStatus)> {
+        let finding = &alert.finding;
+        if let Some(state) = &self.state {
+            match state.observe(alert, Utc::now()) {
+                Ok(Some(reason)) => {
+                    debug!("Not alerting {}: {}", finding.fingerprint, reason);
+                    return Vec::new();
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Alert state unavailable, alerting {} anyway: {}", finding.fingerprint, e),
+            }
+        }
+
+        let (outcomes, collected) = if self.rules.is_empty() {
+            self.dispatch_by_filters(alert).await
+        } else {
+            self.dispatch_by_rules(alert).await
+        };
+        let delivered = outcomes.iter().any(|(_, status)| *status == DeliveryStatus::Delivered);
+        if let Some(state) = self.state.as_ref().filter(|_| delivered || collected) {
+            if let Err(e) = state.notified(alert, Utc::now()) {
+                warn!("Could not record alert {} in the alert state: {}", finding.fingerprint, e);
+            }
+        }
+        outcomes
+    }
+
+    /// Outcomes per route, and whether a digest collected the alert
+    async fn dispatch_by_filters(&self, alert: &Alert) -> (Vec<(String, DeliveryStatus)>, bool) {
+        let mut collected = false;
+        for digest in &self.digests {
+            collected |= digest.collect_alert(alert);
+        }
+        let mut outcomes = Vec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            if route.filter.matches(&alert.finding) {
+                outcomes.push(self.send_on_route(index, route, alert).await);
+            }
+        }
+        (outcomes, collected)
+    }
+
+    async fn dispatch_by_rules(&self, alert: &Alert) -> (Vec<(String, DeliveryStatus)>, bool) {
+        let mut reached: HashSet<String> = HashSet::new();
+        let mut collected = false;
+        let mut outcomes = Vec::new();
+        for rule in self.rules.route(alert) {
+            if !reached.insert(rule.sink.clone()) {
+                continue;
+            }
+            let alert = Alert { message: rule.message, ..alert.clone() };
+            let sink_name = Some(&rule.sink);
+            if let Some(digest) = self.digests.iter().find(|d| d.sink_name.as_ref() == sink_name) {
+                digest.push_alert(&alert);
+                collected = true;
+            } else if let Some((index, route)) =
+                self.routes.iter().enumerate().find(|(_, r)| r.sink_name.as_ref() == sink_name)
+            {
+                outcomes.push(self.send_on_route(index, route, &alert).await);
+            } else {
+                warn!("Routing rule {} names sink {}, which has no route", rule.rule, rule.sink);
+            }
+        }
+        (outcomes, collected)
+    }
+
+    /// Send `alert` on the route at `index` unless it is in cooldown there
+    async fn send_on_route(&self, index: usize, route: &AlertRoute, alert: &Alert) -> (String, DeliveryStatus) {
+        let finding = &alert.finding;
+        let key = (index, finding.fingerprint.clone());
+        if self.in_cooldown(&key, finding.severity) {
+            debug!("Suppressing repeat alert {} on {}", finding.fingerprint, route.name());
+            return (route.name(), DeliveryStatus::Suppressed);
+        }
+
+        let status = self.deliver(&finding.fingerprint, &route.name(), || route.sink.send(alert)).await;
+        if status == DeliveryStatus::Delivered {
+            self.lock_sent().insert(key, (Instant::now(), finding.severity));
+        }
+        (route.name(), status)
+    }
+
+    /// How `alert` would be routed, without sending it
+    ///
+    /// With routing rules, one outcome per rule; otherwise one per route and digest route,
+    /// named `filter` and matched against the route's own filter.
+    pub fn explain(&self, alert: &Alert) -> Vec<RuleOutcome> {
+        if !self.rules.is_empty() {
+            return self.rules.explain(alert);
+        }
+        let filters = self
+            .routes
+            .iter()
+            .map(|r| (r.sink_name.clone().unwrap_or_else(|| r.name()), &r.filter))
+            .chain(self.digests.iter().map(|d| (d.sink_name.clone().unwrap_or_else(|| d.name()), &d.filter)));
+        filters
+            .map(|(sink, filter)| RuleOutcome {
+                rule: "filter".to_string(),
+                sink,
+                mismatch: (!filter.matches(&alert.finding)).then(|| "the sink's filter does not pass it".to_string()),
+                evaluated: true,
+                message: None,
+            })
+            .collect()
+    }
+
+    /// Resolve `alert` on every route that still has it open
+    ///
+    /// A route that resolved the finding forgets its cooldown, so a recurrence alerts again.
+    pub async fn resolve(&self, alert: &Alert) -> Vec<(String, DeliveryStatus)> {
+        let mut outcomes = Vec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            let open = route.sink.open_alerts(alert.market_address);
+            if !open.iter().any(|a| a.finding.fingerprint == alert.finding.fingerprint) {
+                continue;
+            }
+            let status = self.deliver(&alert.finding.fingerprint, &route.name(), || route.sink.resolve(alert)).await;
+            if status == DeliveryStatus::Delivered {
+                self.lock_sent().remove(&(index, alert.finding.fingerprint.clone()));
+            }
+            outcomes.push((route.name(), status));
+        }
+        outcomes
+    }
+
+    /// Resolve alerts routes still hold open for the market of `assessment` that it no longer contains
+    ///
+    /// This catches findings that disappeared while the engine was not running, which produce
+    /// no `FindingResolved` event; the alert state marks them resolved as well.
+    pub async fn reconcile(&self, assessment: &RiskAssessment) -> Vec<(String, DeliveryStatus)> {
+        let current: HashSet<&str> = assessment.findings.iter().map(|f| f.fingerprint.as_str()).collect();
+        if let Some(state) = &self.state {
+            if let Err(e) = state.resolve_missing(assessment.market_address, &current, Utc::now()) {
+                warn!("Could not record resolved findings in the alert state: {}", e);
+            }
+        }
+        let mut stale: Vec<Alert> = Vec::new();
+        for route in &self.routes {
+            for alert in route.sink.open_alerts(assessment.market_address) {
+                let known = stale.iter().any(|a| a.finding.fingerprint == alert.finding.fingerprint);
+                if !current.contains(alert.finding.fingerprint.as_str()) && !known {
+                    stale.push(Alert { kind: AlertKind::Resolved, ..alert });
+                }
+            }
+        }
+
+        let mut outcomes = Vec::new();
+        for alert in stale {
+            outcomes.extend(self.resolve(&alert).await);
+        }
+        outcomes
+    }
+
+    /// Send every digest whose period is over, returning the outcome per digest route
+    ///
+    /// A digest that could not be delivered keeps its findings and is retried on the next call.
+    pub async fn flush_digests(&self) -> Vec<(String, DeliveryStatus)> {
+        let mut outcomes = Vec::new();
+        for route in &self.digests {
+            let Some(digest) = route.due() else { continue };
+            let status = self.deliver("digest", &route.name(), || route.sink.send_digest(&digest)).await;
+            if status == DeliveryStatus::Delivered {
+                route.sent(&digest);
+            }
+            outcomes.push((route.name(), status));
+        }
+        outcomes
+    }
+
+    /// Deliver `alert` to every route, ignoring filters and the cooldown
+    ///
+    /// Digest routes receive a digest containing only `alert`.
+    pub async fn send_test(&self, alert: &Alert) -> Vec<(String, DeliveryStatus)> {
+        let mut outcomes = Vec::new();
+        for route in &self.routes {
+            outcomes.push((route.name(), self.deliver("test", &route.name(), || route.sink.send(alert)).await));
+        }
+        let now = Utc::now();
+        let digest = Digest { since: now, until: now, assessments: Vec::new(), alerts: vec![alert.clone()] };
+        for route in &self.digests {
+            let status = self.deliver("test", &route.name(), || route.sink.send_digest(&digest)).await;
+            outcomes.push((route.name(), status));
+        }
+        outcomes
+    }
+
+    /// Alert on events from `events` until `cancel` fires or the channel closes
+    ///
+    /// Events already queued when `cancel` fires are still delivered. Digests are sent
+    /// once their period is over, when the scheduler completes the next assessment.
+    pub async fn run(self, mut events: broadcast::Receiver<RiskEvent>, cancel: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                received = events.recv() => match received {
+                    Ok(event) => self.handle(&event).await,
+                    Err(RecvError::Lagged(skipped)) => warn!("Alert dispatcher fell behind and skipped {} event(s)", skipped),
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+
+        loop {
+            match events.try_recv() {
+                Ok(event) => self.handle(&event).await,
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn handle(&self, event: &RiskEvent) {
+        if self.leadership.as_ref().is_some_and(|leadership| !leadership.is_leader()) {
+            debug!("Standby: not alerting on {}", event.kind());
+            return;
+        }
+        if let RiskEvent::AssessmentCompleted(assessment) = event {
+            for digest in &self.digests {
+                digest.collect_assessment(assessment);
+            }
+            self.reconcile(assessment).await;
+            self.flush_digests().await;
+        } else if let Some(alert) = Alert::resolution(event, self.chain_id) {
+            if let Some(state) = &self.state {
+                if let Err(e) = state.resolved(&alert.finding.fingerprint, Utc::now()) {
+                    warn!("Could not record resolved finding in the alert state: {}", e);
+                }
+            }
+            self.resolve(&alert).await;
+        } else if let Some(mut alert) = Alert::from_event(event, self.chain_id) {
+            if let Some(units) = &self.units {
+                alert.unit = units.of(&alert.market_address);
+            }
+            self.dispatch(&alert).await;
+        }
+    }
+
+    /// Call `send` until it succeeds, fails permanently or runs out of attempts, recording
+    /// the outcome in the delivery log
+    async fn deliver<F, Fut>(&self, what: &str, sink: &str, send: F) -> DeliveryStatus
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let status = self.attempt_delivery(what, sink, send).await;
+        if let Some(deliveries) = &self.deliveries {
+            deliveries.record(sink, &status, Utc::now());
+        }
+        status
+    }
+
+    async fn attempt_delivery<F, Fut>(&self, what: &str, sink: &str, mut send: F) -> DeliveryStatus
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match send().await {
+                Ok(()) => {
+                    info!("Sent alert {} via {}", what, sink);
+                    return DeliveryStatus::Delivered;
+                }
+                Err(e) => {
+                    let retryable = matches!(e, RiskEngineError::Delivery { retryable: true, .. });
+                    if !retryable || attempt >= MAX_ATTEMPTS {
+                        warn!("Giving up on alert {} via {} after {} attempt(s): {}", what, sink, attempt, e);
+                        return DeliveryStatus::Failed(e.to_string());
+                    }
+                    debug!("Retrying alert {} via {} after attempt {}: {}", what, sink, attempt, e);
+                    tokio::time::sleep(self.retry_delay * 2u32.pow(attempt - 1)).await;
+                }
+            }
+        }
+    }
+
+    fn in_cooldown(&self, key: &(usize, String), severity: RiskSeverity) -> bool {
+        match self.lock_sent().get(key) {
+            Some((sent_at, sent_severity)) => sent_at.elapsed() < self.cooldown && severity <= *sent_severity,
+            None => false,
+        }
+    }
+
+    fn lock_sent(&self) -> std::sync::MutexGuard<'_, HashMap<(usize, String), (Instant, RiskSeverity)>> {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{finding, CaptureServer};
+
+    fn new_finding(severity: RiskSeverity) -> RiskEvent {
+        RiskEvent::NewFinding {
+            market_name: "USDC".to_string(),
+            market_address: Address::repeat_byte(0xc3),
+            finding: RiskFinding {
+                description: "Utilization at 95%".to_string(),
+                metadata: json!({ "account": "0x00000000000000000000000000000000000000aa" }),
+                ..finding("fp-1", severity)
+            },
+        }
+    }
+
+    async fn webhook(server: &CaptureServer, format: WebhookFormat) -> Arc<dyn AlertSink> {
+        Arc::new(WebhookSink::new(server.serve().await, format, Duration::from_secs(5)).unwrap())
+    }
+
+    fn dispatcher_with(routes: Vec<AlertRoute>) -> AlertDispatcher {
+        routes
+            .into_iter()
+            .fold(AlertDispatcher::new(1, Duration::from_secs(3600)), AlertDispatcher::with_route)
+            .with_retry_delay(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn test_only_new_and_escalated_findings_alert() {
+        let alert = Alert::from_event(&new_finding(RiskSeverity::High), 1).unwrap();
+        assert_eq!(alert.kind, AlertKind::New);
+        assert!(alert.links["market"].starts_with("https://etherscan.io/address/0xc3c3"));
+        assert!(alert.links["account"].to_lowercase().ends_with("0x00000000000000000000000000000000000000aa"));
+        assert_eq!(alert.headline(), "[High] New HighUtilization finding in USDC");
+
+        let resolved = RiskEvent::FindingResolved {
+            market_name: "USDC".to_string(),
+            market_address: Address::zero(),
+            finding: finding("fp-1", RiskSeverity::High),
+        };
+        assert!(Alert::from_event(&resolved, 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_routes_filter_by_severity_and_category() {
+        let critical_only = CaptureServer::new();
+        let utilization = CaptureServer::new();
+        let dispatcher = dispatcher_with(vec![
+            AlertRoute::new(
+                webhook(&critical_only, WebhookFormat::Json).await,
+                RouteFilter { min_severity: RiskSeverity::Critical, ..RouteFilter::default() },
+            ),
+            AlertRoute::new(
+                webhook(&utilization, WebhookFormat::Json).await,
+                RouteFilter {
+                    min_severity: RiskSeverity::Low,
+                    categories: vec![RiskCategory::HighUtilization],
+                    ..RouteFilter::default()
+                },
+            ),
+        ]);
+
+        let alert = Alert::from_event(&new_finding(RiskSeverity::High), 1).unwrap();
+        let outcomes = dispatcher.dispatch(&alert).await;
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].1, DeliveryStatus::Delivered);
+        assert!(critical_only.requests().is_empty());
+
+        let body = &utilization.requests()[0];
+        assert_eq!(body["severity"], "High");
+        assert_eq!(body["kind"]["type"], "new");
+        assert_eq!(body["market"]["name"], "USDC");
+        assert_eq!(body["finding"]["fingerprint"], "fp-1");
+        assert!(body["links"]["account"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_suppresses_repeats_but_not_escalations() {
+        let server = CaptureServer::new();
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(
+            webhook(&server, WebhookFormat::Json).await,
+            RouteFilter { min_severity: RiskSeverity::Medium, ..RouteFilter::default() },
+        )]);
+
+        let high = Alert::from_event(&new_finding(RiskSeverity::High), 1).unwrap();
+        assert_eq!(dispatcher.dispatch(&high).await[0].1, DeliveryStatus::Delivered);
+        assert_eq!(dispatcher.dispatch(&high).await[0].1, DeliveryStatus::Suppressed);
+
+        let escalated = RiskEvent::SeverityEscalated {
+            market_name: "USDC".to_string(),
+            market_address: Address::repeat_byte(0xc3),
+            previous: RiskSeverity::High,
+            finding: finding("fp-1", RiskSeverity::Critical),
+        };
+        let escalated = Alert::from_event(&escalated, 1).unwrap();
+        assert_eq!(dispatcher.dispatch(&escalated).await[0].1, DeliveryStatus::Delivered);
+        assert_eq!(dispatcher.dispatch(&high).await[0].1, DeliveryStatus::Suppressed);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_and_failures_reported() {
+        let server = CaptureServer::new();
+        server.respond_with(&[500, 503]);
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(webhook(&server, WebhookFormat::Json).await, RouteFilter::default())]);
+        let alert = Alert::from_event(&new_finding(RiskSeverity::High), 1).unwrap();
+        assert_eq!(dispatcher.dispatch(&alert).await[0].1, DeliveryStatus::Delivered);
+        assert_eq!(server.requests().len(), 3);
+
+        // Client errors are not retried, and a failed alert is not put in cooldown
+        let rejecting = CaptureServer::new();
+        rejecting.respond_with(&[400]);
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(webhook(&rejecting, WebhookFormat::Json).await, RouteFilter::default())]);
+        let outcome = &dispatcher.dispatch(&alert).await[0].1;
+        assert!(matches!(outcome, DeliveryStatus::Failed(message) if message.contains("400")), "{:?}", outcome);
+        assert_eq!(rejecting.requests().len(), 1);
+        assert_eq!(dispatcher.dispatch(&alert).await[0].1, DeliveryStatus::Delivered);
+    }
+
+    #[tokio::test]
+    async fn test_slack_format_and_alert_test_bypass_filters() {
+        let server = CaptureServer::new();
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(
+            webhook(&server, WebhookFormat::Slack).await,
+            RouteFilter {
+                min_severity: RiskSeverity::Critical,
+                categories: vec![RiskCategory::Concentration],
+                ..RouteFilter::default()
+            },
+        )]);
+
+        let alert = Alert::synthetic(&Config::default());
+        let outcomes = dispatcher.send_test(&alert).await;
+        assert_eq!(outcomes, vec![(dispatcher.routes()[0].name(), DeliveryStatus::Delivered)]);
+        assert!(outcomes[0].0.starts_with("webhook 127.0.0.1"));
+
+        let text = server.requests()[0]["text"].as_str().unwrap().to_string();
+        assert!(text.starts_with("*[Critical] Test HighUtilization finding in alert-test*"), "{}", text);
+        assert!(text.contains("|View market on explorer>"));
+    }
+
+    #[tokio::test]
+    async fn test_routing_rules_replace_route_filters() {
+        let pager = CaptureServer::new();
+        let chat = CaptureServer::new();
+        let critical_only = RouteFilter { min_severity: RiskSeverity::Critical, ..RouteFilter::default() };
+        let rules: Vec<crate::config::RoutingRule> = serde_json::from_value(json!([
+            { "match": { "min_severity": "Critical" }, "sink": "pager", "continue": true },
+            { "sink": "chat", "template": "{severity} in {market}: {description}" },
+            { "sink": "pager" },
+        ]))
+        .unwrap();
+        let sinks = ["pager".to_string(), "chat".to_string()];
+        let dispatcher = dispatcher_with(vec![
+            AlertRoute::new(webhook(&pager, WebhookFormat::Json).await, RouteFilter::default()).named("pager"),
+            AlertRoute::new(webhook(&chat, WebhookFormat::Slack).await, critical_only).named("chat"),
+        ])
+        .with_rules(RoutingRules::new(&rules, &sinks).unwrap());
+
+        // The chat route's own filter would drop a High finding; the rule sends it
+        let high = Alert::from_event(&new_finding(RiskSeverity::High), 1).unwrap();
+        assert_eq!(dispatcher.dispatch(&high).await.len(), 1);
+        assert!(pager.requests().is_empty());
+        assert_eq!(chat.requests()[0]["text"].as_str().unwrap().lines().next(), Some("High in USDC: Utilization at 95%"));
+
+        let mut critical = Alert::from_event(&new_finding(RiskSeverity::Critical), 1).unwrap();
+        critical.finding.fingerprint = "fp-2".to_string();
+        let outcomes = dispatcher.explain(&critical);
+        let sends: Vec<bool> = outcomes.iter().map(RuleOutcome::sends).collect();
+        assert_eq!(sends, [true, true, false]);
+        assert_eq!(dispatcher.dispatch(&critical).await.len(), 2);
+        assert_eq!((pager.requests().len(), chat.requests().len()), (1, 2));
+        assert!(pager.requests()[0]["message"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_telegram_watchlist_message() {
+        let server = CaptureServer::new();
+        server.respond_with(&[429]);
+        let config = TelegramConfig {
+            bot_token: "123:SECRET".to_string(),
+            chat_id: "42".to_string(),
+            filter: RouteFilter { min_severity: RiskSeverity::Medium, watchlist_only: true, ..RouteFilter::default() },
+            api_url: server.serve().await,
+        };
+        let sink = TelegramSink::new(&config, Duration::from_secs(5)).unwrap();
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(Arc::new(sink), config.filter.clone())]);
+
+        let mut finding = RiskFinding { category: RiskCategory::LiquidationCascade, ..finding("fp-1", RiskSeverity::High) };
+        finding.description = "User position has a health factor of 1.03".to_string();
+        finding.metadata = json!({
+            "health_factor": 1.03,
+            "account": "0x2222222222222222222222222222222222222222",
+            "dominant_collateral": "WETH",
+            "liquidation_price": 1941.82,
+        });
+        let event = RiskEvent::NewFinding {
+            market_name: "USDC".to_string(),
+            market_address: Address::repeat_byte(0xc3),
+            finding,
+        };
+        let outcomes = dispatcher.dispatch(&Alert::from_event(&event, 1).unwrap()).await;
+        assert_eq!(outcomes, vec![("telegram chat 42".to_string(), DeliveryStatus::Delivered)]);
+
+        // The rate-limited first attempt was retried
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["chat_id"], "42");
+        assert_eq!(requests[1]["parse_mode"], "HTML");
+        let text = requests[1]["text"].as_str().unwrap();
+        assert!(text.starts_with("🟠 <b>High</b> · USDC\nHealth factor: <b>1.03</b>\nLiq. price WETH: $1,941.82\n"), "{}", text);
+        assert!(text.contains("Account: <code>0x2222...2222</code>"));
+    }
+
+    #[tokio::test]
+    async fn test_run_alerts_on_scheduler_events() {
+        let server = CaptureServer::new();
+        let dispatcher = dispatcher_with(vec![AlertRoute::new(webhook(&server, WebhookFormat::Json).await, RouteFilter::default())]);
+        let (sender, receiver) = broadcast::channel(16);
+        let cancel = CancellationToken::new();
+
+        sender.send(new_finding(RiskSeverity::Low)).unwrap();
+        sender.send(new_finding(RiskSeverity::Critical)).unwrap();
+        cancel.cancel();
+        dispatcher.run(receiver, cancel).await;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["severity"], "Critical");
+    }
+}
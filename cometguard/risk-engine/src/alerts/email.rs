@@ -0,0 +1,335 @@
+//! SMTP email sink for immediate alerts and digests
+
+use super::{Alert, AlertSink, Digest, DigestSink};
+use crate::config::{EmailConfig, SmtpTls};
+use crate::error::{Result, RiskEngineError};
+use crate::utils::{expand_env, format_timestamp, sanitize_inline, to_checksum_address};
+use chrono_tz::Tz;
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::header::{ContentTransferEncoding, ContentType};
+use lettre::message::{Body, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Sink that emails alerts and digests over SMTP, or writes them to files in dry-run mode
+pub struct EmailSink {
+    host: String,
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    dry_run_dir: Option<PathBuf>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    /// Timezone of the times in messages
+    timezone: Tz,
+    written: AtomicU64,
+}
+
+impl EmailSink {
+    /// Sink for `config`, expanding `${NAME}` environment references in the connection settings
+    pub fn new(config: &EmailConfig, timeout: Duration) -> Result<Self> {
+        let host = expand_env("alerts.email.host", &config.host)?;
+        let from = parse_mailbox("alerts.email.from", &config.from)?;
+        let to = config
+            .to
+            .iter()
+            .map(|address| parse_mailbox("alerts.email.to", address))
+            .collect::<Result<Vec<_>>>()?;
+        if to.is_empty() {
+            return Err(RiskEngineError::config("alerts.email.to", "at least one recipient is required"));
+        }
+
+        let transport = match &config.dry_run_dir {
+            Some(_) => None,
+            None => {
+                let builder = match config.tls {
+                    SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host),
+                    SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&host),
+                    SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host)),
+                }
+                .map_err(|e| RiskEngineError::config("alerts.email.host", e.to_string()))?;
+                let mut builder = builder.port(config.port).timeout(Some(timeout));
+                if let Some(username) = &config.username {
+                    let password = config.password.as_deref().unwrap_or_default();
+                    builder = builder.credentials(Credentials::new(
+                        expand_env("alerts.email.username", username)?,
+                        expand_env("alerts.email.password", password)?,
+                    ));
+                }
+                Some(builder.build())
+            }
+        };
+
+        Ok(Self {
+            host,
+            transport,
+            dry_run_dir: config.dry_run_dir.clone(),
+            from,
+            to,
+            timezone: Tz::UTC,
+            written: AtomicU64::new(0),
+        })
+    }
+
+    /// Write the times in messages in `timezone` rather than UTC
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Email for a single alert
+    pub fn alert_message(&self, alert: &Alert) -> Result<Message> {
+        let finding = &alert.finding;
+        let mut body = format!(
+            "{}\n\nMarket: {} ({})\nChain ID: {}\nSeverity: {:?}\nCategory: {:?}\nFingerprint: {}\nDetected: {}\n\n{}\n",
+            sanitize_inline(&alert.summary()),
+            sanitize_inline(&alert.market_name),
+            to_checksum_address(&alert.market_address),
+            alert.chain_id,
+            finding.severity,
+            finding.category,
+            sanitize_inline(&finding.fingerprint),
+            format_timestamp(&finding.timestamp, self.timezone),
+            finding
+        );
+        if let Some(details) = finding.metadata.as_object().filter(|m| !m.is_empty()) {
+            body.push_str("\nDetails:\n");
+            for (key, value) in details {
+                body.push_str(&format!("  {}: {}\n", sanitize_inline(key), sanitize_inline(&value.to_string())));
+            }
+        }
+        if !alert.links.is_empty() {
+            body.push_str("\nLinks:\n");
+            for (label, url) in &alert.links {
+                body.push_str(&format!("  {}: {}\n", label, url));
+            }
+        }
+        self.message(&format!("[CometGuard] {}", alert.headline()), body)
+    }
+
+    /// Email for a digest
+    pub fn digest_message(&self, digest: &Digest) -> Result<Message> {
+        let markets = digest
+            .alerts
+            .iter()
+            .map(|a| a.market_address)
+            .collect::<std::collections::HashSet<_>>()
+            .len();
+        let subject = format!(
+            "[CometGuard] Risk digest: {} finding(s) in {} market(s)",
+            digest.alerts.len(),
+            markets
+        );
+        self.message(&subject, digest.render_text(self.timezone))
+    }
+
+    fn sink_name(&self) -> String {
+        match self.dry_run_dir {
+            Some(_) => "email (dry run)".to_string(),
+            None => format!("email via {}", self.host),
+        }
+    }
+
+    fn message(&self, subject: &str, body: String) -> Result<Message> {
+        let mut builder = Message::builder().from(self.from.clone()).subject(sanitize_inline(subject));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        // Report lines exceed the 76 characters 7bit/8bit allow, so always encode the same way
+        let body = Body::new_with_encoding(body, ContentTransferEncoding::Base64).expect("base64 fits any body");
+        builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| RiskEngineError::Delivery { sink: self.sink_name(), message: e.to_string(), retryable: false })
+    }
+
+    async fn deliver(&self, kind: &str, message: Message) -> Result<()> {
+        if let Some(dir) = &self.dry_run_dir {
+            std::fs::create_dir_all(dir).map_err(|e| RiskEngineError::io(dir, e))?;
+            let sequence = self.written.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("{}-{:04}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S"), sequence, kind));
+            return std::fs::write(&path, message.formatted()).map_err(|e| RiskEngineError::io(&path, e));
+        }
+
+        let transport = self.transport.as_ref().expect("transport is built unless in dry-run mode");
+        transport.send(message).await.map_err(|e| RiskEngineError::Delivery {
+            sink: self.sink_name(),
+            message: e.to_string(),
+            retryable: !e.is_permanent(),
+        })?;
+        Ok(())
+    }
+}
+
+fn parse_mailbox(field: &str, address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| RiskEngineError::config(field, format!("invalid mailbox `{}`: {}", address, e)))
+}
+
+#[async_trait]
+impl AlertSink for EmailSink {
+    fn name(&self) -> String {
+        self.sink_name()
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        self.deliver("alert", self.alert_message(alert)?).await
+    }
+}
+
+#[async_trait]
+impl DigestSink for EmailSink {
+    fn name(&self) -> String {
+        self.sink_name()
+    }
+
+    async fn send_digest(&self, digest: &Digest) -> Result<()> {
+        self.deliver("digest", self.digest_message(digest)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertDispatcher, DeliveryStatus, DigestRoute};
+    use crate::config::{EmailMode, RouteFilter};
+    use crate::events::RiskEvent;
+    use crate::risk::{RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+    use crate::testing::{assessment, finding};
+    use ethers::types::Address;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn config(dry_run_dir: PathBuf) -> EmailConfig {
+        EmailConfig {
+            host: "smtp.example.com".to_string(),
+            port: 587,
+            tls: SmtpTls::StartTls,
+            username: None,
+            password: None,
+            from: "CometGuard <alerts@example.com>".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            filter: RouteFilter::default(),
+            mode: EmailMode::Digest,
+            digest_interval_seconds: 0,
+            dry_run_dir: Some(dry_run_dir),
+        }
+    }
+
+    fn event(symbol: &str, severity: RiskSeverity) -> RiskEvent {
+        RiskEvent::NewFinding {
+            market_name: "USDC".to_string(),
+            market_address: Address::repeat_byte(0xc3),
+            finding: RiskFinding {
+                category: RiskCategory::PriceVolatility,
+                description: format!("{} 30d volatility is 40%", symbol),
+                metadata: serde_json::json!({ "symbol": symbol }),
+                ..finding(&format!("fp-{}", symbol), severity)
+            },
+        }
+    }
+
+    /// Emails written to `dir`, each as its headers followed by the decoded body
+    fn written(dir: &std::path::Path) -> Vec<String> {
+        use base64::Engine;
+
+        let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| {
+                let email = std::fs::read_to_string(path).unwrap();
+                let (headers, body) = email.split_once("\r\n\r\n").unwrap();
+                let body = base64::engine::general_purpose::STANDARD.decode(body.replace("\r\n", "")).unwrap();
+                format!("{}\r\n\r\n{}", headers, String::from_utf8(body).unwrap())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_writes_alert_email_without_injection() {
+        let dir = tempdir().unwrap();
+        let sink = EmailSink::new(&config(dir.path().to_path_buf()), Duration::from_secs(5)).unwrap();
+        let evil = "WETH\r\nBcc: victim@example.com\r\n\r\nPhishing";
+        let alert = Alert::from_event(&event(evil, RiskSeverity::Critical), 1).unwrap();
+
+        sink.send(&alert).await.unwrap();
+        let emails = written(dir.path());
+        assert_eq!(emails.len(), 1);
+        let email = &emails[0];
+        assert!(email.contains("To: ops@example.com"), "{}", email);
+        assert!(!email.contains("\r\nBcc:"), "{}", email);
+        assert!(email.contains("Subject: [CometGuard] [Critical] New PriceVolatility finding in USDC"));
+        assert!(email.contains("WETH  Bcc: victim@example.com    Phishing 30d volatility is 40%"), "{}", email);
+        assert!(email.contains("https://etherscan.io/address/0xc3c3"));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let dir = tempdir().unwrap();
+        let mut bad = config(dir.path().to_path_buf());
+        bad.to.clear();
+        assert!(matches!(
+            EmailSink::new(&bad, Duration::from_secs(5)),
+            Err(RiskEngineError::Config { field, .. }) if field == "alerts.email.to"
+        ));
+
+        let mut bad = config(dir.path().to_path_buf());
+        bad.from = "not a mailbox".to_string();
+        assert!(EmailSink::new(&bad, Duration::from_secs(5)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_digest_collects_findings_between_scheduled_runs() {
+        let dir = tempdir().unwrap();
+        let sink = Arc::new(EmailSink::new(&config(dir.path().to_path_buf()), Duration::from_secs(5)).unwrap());
+        let filter = RouteFilter { min_severity: RiskSeverity::Medium, ..RouteFilter::default() };
+        let dispatcher = AlertDispatcher::new(1, Duration::from_secs(3600))
+            .with_digest(DigestRoute::new(sink, filter, Duration::ZERO));
+
+        let completed = RiskEvent::AssessmentCompleted(Box::new(RiskAssessment {
+            market_address: Address::repeat_byte(0xc3),
+            ..assessment(35, Vec::new())
+        }));
+
+        // Nothing collected yet: the finished period restarts without an email
+        dispatcher.handle(&completed).await;
+        assert!(written(dir.path()).is_empty());
+
+        dispatcher.handle(&event("WETH", RiskSeverity::High)).await;
+        dispatcher.handle(&event("WBTC", RiskSeverity::Low)).await;
+        dispatcher.handle(&event("COMP", RiskSeverity::Critical)).await;
+        dispatcher.handle(&completed).await;
+
+        let emails = written(dir.path());
+        assert_eq!(emails.len(), 1);
+        let email = &emails[0];
+        assert!(email.contains("Subject: [CometGuard] Risk digest: 2 finding(s) in 1 market(s)"), "{}", email);
+        assert!(email.contains("== USDC =="), "{}", email);
+        assert!(email.contains("Risk Score: 35/100"));
+        assert!(email.contains("WETH 30d volatility"));
+        assert!(email.contains("COMP 30d volatility"));
+        assert!(!email.contains("WBTC"));
+
+        // The next digest only covers findings after the previous one
+        dispatcher.handle(&completed).await;
+        assert_eq!(written(dir.path()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_alert_test_reaches_digest_routes() {
+        let dir = tempdir().unwrap();
+        let mut email = config(dir.path().to_path_buf());
+        email.digest_interval_seconds = 86_400;
+        let mut config = crate::config::Config::default();
+        config.alerts.email = Some(email);
+
+        let dispatcher = AlertDispatcher::from_config(&config).unwrap();
+        let outcomes = dispatcher.send_test(&Alert::synthetic(&config)).await;
+        assert_eq!(outcomes, vec![("email (dry run) (digest)".to_string(), DeliveryStatus::Delivered)]);
+        assert!(written(dir.path())[0].contains("Test HighUtilization finding in alert-test"));
+    }
+}
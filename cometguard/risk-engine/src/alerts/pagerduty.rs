@@ -0,0 +1,311 @@
+//! PagerDuty Events API v2 sink with incident auto-resolve
+
+use super::{post_json, Alert, AlertKind, AlertSink};
+use crate::config::{PagerDutyConfig, PagerDutySeverityMap};
+use crate::error::{Result, RiskEngineError};
+use crate::utils::expand_env;
+use async_trait::async_trait;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Longest `payload.summary` PagerDuty accepts
+const MAX_SUMMARY_LEN: usize = 1024;
+
+/// Incidents triggered and not yet resolved, keyed by dedup key (the finding fingerprint)
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OpenIncidents {
+    open: BTreeMap<String, Alert>,
+}
+
+impl OpenIncidents {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| RiskEngineError::serialization(format!("PagerDuty state {}", path.display()), e))
+    }
+
+    /// Write the state to disk atomically (temp file + rename)
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| RiskEngineError::serialization("PagerDuty state", e))?;
+        fs::write(&tmp, content).map_err(|e| RiskEngineError::io(&tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| RiskEngineError::io(path, e))?;
+        Ok(())
+    }
+}
+
+/// Sink that triggers a PagerDuty incident per finding and resolves it once the finding is gone
+///
+/// Incidents are keyed by finding fingerprint, which is derived from the market, category
+/// and subject, so the same condition always maps to the same incident. Open incidents are
+/// persisted to `state_path` so they can be resolved after a restart.
+pub struct PagerDutySink {
+    events_url: String,
+    routing_key: String,
+    severities: PagerDutySeverityMap,
+    client: reqwest::Client,
+    state_path: Option<PathBuf>,
+    incidents: Mutex<OpenIncidents>,
+}
+
+impl PagerDutySink {
+    /// Sink for `config`, restoring open incidents from its state file
+    pub fn new(config: &PagerDutyConfig, timeout: Duration) -> Result<Self> {
+        let routing_key = expand_env("alerts.pagerduty.routing_key", &config.routing_key)?;
+        if routing_key.is_empty() {
+            return Err(RiskEngineError::config("alerts.pagerduty.routing_key", "the routing key is empty"));
+        }
+        reqwest::Url::parse(&config.events_url)
+            .map_err(|e| RiskEngineError::config("alerts.pagerduty.events_url", format!("invalid URL: {}", e)))?;
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| RiskEngineError::config("alerts.pagerduty", e.to_string()))?;
+        let incidents = match &config.state_path {
+            Some(path) => OpenIncidents::load(path)?,
+            None => OpenIncidents::default(),
+        };
+
+        Ok(Self {
+            events_url: config.events_url.clone(),
+            routing_key,
+            severities: config.severities.clone(),
+            client,
+            state_path: config.state_path.clone(),
+            incidents: Mutex::new(incidents),
+        })
+    }
+
+    /// Trigger event for `alert`
+    pub fn trigger_event(&self, alert: &Alert) -> Value {
+        let finding = &alert.finding;
+        let mut summary = alert.summary();
+        if summary.len() > MAX_SUMMARY_LEN {
+            let mut end = MAX_SUMMARY_LEN;
+            while !summary.is_char_boundary(end) {
+                end -= 1;
+            }
+            summary.truncate(end);
+        }
+        let links: Vec<Value> = alert
+            .links
+            .iter()
+            .map(|(label, url)| json!({ "href": url, "text": format!("{} on block explorer", label) }))
+            .collect();
+
+        json!({
+            "routing_key": self.routing_key,
+            "event_action": "trigger",
+            "dedup_key": finding.fingerprint,
+            "payload": {
+                "summary": summary,
+                "source": format!("cometguard/{}", crate::utils::to_checksum_address(&alert.market_address)),
+                "severity": self.severities.get(finding.severity),
+                "timestamp": finding.timestamp.to_rfc3339(),
+                "component": alert.market_name,
+                "group": format!("chain-{}", alert.chain_id),
+                "class": format!("{:?}", finding.category),
+                "custom_details": {
+                    "description": finding.description,
+                    "market_address": alert.market_address,
+                    "chain_id": alert.chain_id,
+                    "metadata": finding.metadata,
+                },
+            },
+            "links": links,
+        })
+    }
+
+    /// Resolve event for the incident of `alert`
+    pub fn resolve_event(&self, alert: &Alert) -> Value {
+        json!({
+            "routing_key": self.routing_key,
+            "event_action": "resolve",
+            "dedup_key": alert.finding.fingerprint,
+        })
+    }
+
+    /// Apply `change` to the open incidents and persist them
+    fn update(&self, change: impl FnOnce(&mut OpenIncidents)) -> Result<()> {
+        let mut incidents = self.incidents.lock().unwrap_or_else(|e| e.into_inner());
+        change(&mut incidents);
+        match &self.state_path {
+            Some(path) => incidents.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for PagerDutySink {
+    fn name(&self) -> String {
+        "pagerduty".to_string()
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        post_json(&self.client, &self.events_url, &self.trigger_event(alert), self.name()).await?;
+        // Test incidents are not tracked; nothing would ever resolve them
+        if alert.kind != AlertKind::Test {
+            self.update(|incidents| {
+                incidents.open.insert(alert.finding.fingerprint.clone(), alert.clone());
+            })?;
+        }
+        Ok(())
+    }
+
+    async fn resolve(&self, alert: &Alert) -> Result<()> {
+        post_json(&self.client, &self.events_url, &self.resolve_event(alert), self.name()).await?;
+        self.update(|incidents| {
+            incidents.open.remove(&alert.finding.fingerprint);
+        })
+    }
+
+    fn open_alerts(&self, market: Address) -> Vec<Alert> {
+        let incidents = self.incidents.lock().unwrap_or_else(|e| e.into_inner());
+        incidents.open.values().filter(|a| a.market_address == market).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertDispatcher, AlertRoute, DeliveryStatus};
+    use crate::config::RouteFilter;
+    use crate::events::RiskEvent;
+    use crate::risk::{RiskAssessment, RiskFinding, RiskSeverity};
+    use crate::testing::{assessment, finding, CaptureServer};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    const MARKET: Address = Address::repeat_byte(0xc3);
+
+    fn config(events_url: String, state_path: Option<PathBuf>) -> PagerDutyConfig {
+        PagerDutyConfig {
+            routing_key: "R0UT1NG".to_string(),
+            filter: RouteFilter { min_severity: RiskSeverity::High, ..RouteFilter::default() },
+            severities: PagerDutySeverityMap::default(),
+            events_url,
+            state_path,
+        }
+    }
+
+    fn dispatcher(config: &PagerDutyConfig) -> AlertDispatcher {
+        let sink = PagerDutySink::new(config, Duration::from_secs(5)).unwrap();
+        AlertDispatcher::new(1, Duration::from_secs(3600))
+            .with_route(AlertRoute::new(Arc::new(sink), config.filter.clone()))
+            .with_retry_delay(Duration::from_millis(1))
+    }
+
+    fn new_finding(fingerprint: &str, severity: RiskSeverity) -> RiskEvent {
+        RiskEvent::NewFinding {
+            market_name: "USDC".to_string(),
+            market_address: MARKET,
+            finding: finding(fingerprint, severity),
+        }
+    }
+
+    fn market_assessment(findings: Vec<RiskFinding>) -> RiskAssessment {
+        RiskAssessment { market_address: MARKET, ..assessment(50, findings) }
+    }
+
+    fn completed(findings: Vec<RiskFinding>) -> RiskEvent {
+        RiskEvent::AssessmentCompleted(Box::new(market_assessment(findings)))
+    }
+
+    fn actions(server: &CaptureServer) -> Vec<(String, String)> {
+        server
+            .requests()
+            .iter()
+            .map(|r| (r["event_action"].as_str().unwrap().to_string(), r["dedup_key"].as_str().unwrap().to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_trigger_then_resolve_when_finding_disappears() {
+        let server = CaptureServer::new();
+        let dispatcher = dispatcher(&config(server.serve().await, None));
+
+        dispatcher.handle(&new_finding("fp-1", RiskSeverity::High)).await;
+        dispatcher.handle(&new_finding("fp-low", RiskSeverity::Low)).await;
+        dispatcher.handle(&completed(vec![finding("fp-1", RiskSeverity::High)])).await;
+        dispatcher
+            .handle(&RiskEvent::FindingResolved {
+                market_name: "USDC".to_string(),
+                market_address: MARKET,
+                finding: finding("fp-1", RiskSeverity::High),
+            })
+            .await;
+        dispatcher.handle(&completed(vec![])).await;
+
+        assert_eq!(
+            actions(&server),
+            vec![("trigger".to_string(), "fp-1".to_string()), ("resolve".to_string(), "fp-1".to_string())]
+        );
+        let trigger = &server.requests()[0];
+        assert_eq!(trigger["routing_key"], "R0UT1NG");
+        assert_eq!(trigger["payload"]["severity"], "error");
+        assert_eq!(trigger["payload"]["component"], "USDC");
+        assert_eq!(trigger["payload"]["class"], "HighUtilization");
+
+        // A recurrence after resolution pages again despite the cooldown
+        dispatcher.handle(&new_finding("fp-1", RiskSeverity::High)).await;
+        assert_eq!(actions(&server).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_open_incidents_resolve_after_restart() {
+        let server = CaptureServer::new();
+        let url = server.serve().await;
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("pagerduty.json");
+
+        let before = dispatcher(&config(url.clone(), Some(state_path.clone())));
+        before.handle(&new_finding("fp-1", RiskSeverity::Critical)).await;
+        before.handle(&new_finding("fp-2", RiskSeverity::High)).await;
+        drop(before);
+
+        // fp-1 cleared while the engine was down, so no FindingResolved event is ever published;
+        // the first assessment after the restart resolves it from the persisted state
+        let after = dispatcher(&config(url, Some(state_path.clone())));
+        after.handle(&new_finding("fp-2", RiskSeverity::High)).await;
+        after.handle(&completed(vec![finding("fp-2", RiskSeverity::High)])).await;
+
+        assert_eq!(
+            actions(&server),
+            vec![
+                ("trigger".to_string(), "fp-1".to_string()),
+                ("trigger".to_string(), "fp-2".to_string()),
+                ("trigger".to_string(), "fp-2".to_string()),
+                ("resolve".to_string(), "fp-1".to_string()),
+            ]
+        );
+        assert_eq!(server.requests()[0]["payload"]["severity"], "critical");
+        let state: OpenIncidents = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+        assert_eq!(state.open.keys().collect::<Vec<_>>(), vec!["fp-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_resolve_is_retried_on_next_assessment() {
+        let server = CaptureServer::new();
+        let dispatcher = dispatcher(&config(server.serve().await, None));
+        dispatcher.handle(&new_finding("fp-1", RiskSeverity::High)).await;
+
+        server.respond_with(&[500, 500, 500]);
+        let outcomes = dispatcher.reconcile(&market_assessment(vec![])).await;
+        assert!(matches!(&outcomes[0].1, DeliveryStatus::Failed(_)));
+
+        dispatcher.handle(&completed(vec![])).await;
+        assert_eq!(actions(&server).iter().filter(|(action, _)| action == "resolve").count(), 4);
+        assert!(dispatcher.reconcile(&market_assessment(vec![])).await.is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+//! Rule-based alert routing for `alerts.rules`
+//!
+//! Rules are evaluated in order against each alert. A rule whose criteria all match sends the
+//! alert to its sink, with the message rendered from the rule's template if it has one, and
+//! ends the evaluation unless it sets `continue`. A sink reached by several rules receives the
+//! alert once, from the first of them.
+
+use super::{Alert, AlertKind};
+use crate::config::{RoutingRule, RuleMatch};
+use crate::error::{Result, RiskEngineError};
+use crate::utils;
+use ethers::types::Address;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Template placeholders besides `{metadata.KEY}` and `{amount.KEY}`
+pub const PLACEHOLDERS: [&str; 10] = [
+    "severity",
+    "category",
+    "kind",
+    "market",
+    "market_address",
+    "chain_id",
+    "description",
+    "fingerprint",
+    "account",
+    "headline",
+];
+
+/// A routing rule with its market addresses parsed
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    criteria: RuleMatch,
+    markets: Vec<Address>,
+    sink: String,
+    template: Option<String>,
+    continue_matching: bool,
+}
+
+/// How one rule treated an alert
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleOutcome {
+    pub rule: String,
+    pub sink: String,
+    /// First criterion the alert fails; `None` when the rule matches
+    pub mismatch: Option<String>,
+    /// Whether evaluation reached the rule; a matching rule without `continue` ends it
+    pub evaluated: bool,
+    /// Rendered template of a rule that sends the alert
+    pub message: Option<String>,
+}
+
+impl RuleOutcome {
+    /// Whether the rule sends the alert to its sink
+    pub fn sends(&self) -> bool {
+        self.evaluated && self.mismatch.is_none()
+    }
+}
+
+/// The configured routing rules, in evaluation order
+#[derive(Debug, Clone, Default)]
+pub struct RoutingRules {
+    rules: Vec<Rule>,
+}
+
+impl RoutingRules {
+    /// Rules from `alerts.rules`, failing on a rule whose sink is not among `sinks`
+    pub fn new(rules: &[RoutingRule], sinks: &[String]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                if !sinks.contains(&rule.sink) {
+                    return Err(RiskEngineError::config(
+                        format!("alerts.rules[{}].sink", i),
+                        format!("no sink named `{}`", rule.sink),
+                    ));
+                }
+                let markets = rule
+                    .criteria
+                    .markets
+                    .iter()
+                    .map(|market| {
+                        utils::parse_address(market).map_err(|e| {
+                            RiskEngineError::config(format!("alerts.rules[{}].match.markets", i), e.to_string())
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Rule {
+                    name: rule.rule_name(i),
+                    criteria: rule.criteria.clone(),
+                    markets,
+                    sink: rule.sink.clone(),
+                    template: rule.template.clone(),
+                    continue_matching: rule.continue_matching,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// How every rule treats `alert`, in order
+    pub fn explain(&self, alert: &Alert) -> Vec<RuleOutcome> {
+        let mut evaluated = true;
+        let mut outcomes = Vec::new();
+        for rule in &self.rules {
+            let mismatch = rule.mismatch(alert);
+            let sends = evaluated && mismatch.is_none();
+            outcomes.push(RuleOutcome {
+                rule: rule.name.clone(),
+                sink: rule.sink.clone(),
+                mismatch,
+                evaluated,
+                message: rule.template.as_deref().filter(|_| sends).map(|t| render_template(t, alert)),
+            });
+            if sends && !rule.continue_matching {
+                evaluated = false;
+            }
+        }
+        outcomes
+    }
+
+    /// The rules that send `alert`, in order
+    pub fn route(&self, alert: &Alert) -> Vec<RuleOutcome> {
+        self.explain(alert).into_iter().filter(RuleOutcome::sends).collect()
+    }
+}
+
+impl Rule {
+    /// The first criterion `alert` fails, if any
+    fn mismatch(&self, alert: &Alert) -> Option<String> {
+        let criteria = &self.criteria;
+        let finding = &alert.finding;
+        if let Some(min) = criteria.min_severity.filter(|min| finding.severity < *min) {
+            return Some(format!("severity {:?} is below {:?}", finding.severity, min));
+        }
+        if let Some(max) = criteria.max_severity.filter(|max| finding.severity > *max) {
+            return Some(format!("severity {:?} is above {:?}", finding.severity, max));
+        }
+        if !criteria.categories.is_empty() && !criteria.categories.contains(&finding.category) {
+            return Some(format!("category {:?} is not listed", finding.category));
+        }
+        if !self.markets.is_empty() && !self.markets.contains(&alert.market_address) {
+            return Some(format!("market {} is not listed", utils::to_checksum_address(&alert.market_address)));
+        }
+        if criteria.watchlist_only && finding.metadata.get("account").is_none() {
+            return Some("the finding is not about a watchlist account".to_string());
+        }
+        if let Some(chain_id) = criteria.chain_id.filter(|chain_id| alert.chain_id != *chain_id) {
+            return Some(format!("chain {} is not {}", alert.chain_id, chain_id));
+        }
+        None
+    }
+}
+
+/// `template` with every `{placeholder}` replaced by the field of `alert` it names
+///
+/// Unknown placeholders are kept as written; `Config::validate` rejects them. A missing
+/// `{metadata.KEY}` or `{account}` renders empty. `{amount.KEY}` renders the USD amount of
+/// metadata `KEY` in the alert's unit, or as `{metadata.KEY}` does if it is not a number.
+pub fn render_template(template: &str, alert: &Alert) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some((before, name, after)) = next_placeholder(rest) {
+        rendered.push_str(before);
+        match placeholder_value(name, alert) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&format!("{{{}}}", name)),
+        }
+        rest = after;
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Placeholders in `template` that `render_template` does not know
+pub fn unknown_placeholders(template: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut rest = template;
+    while let Some((_, name, after)) = next_placeholder(rest) {
+        let metadata_key = ["metadata.", "amount."]
+            .iter()
+            .any(|prefix| name.strip_prefix(prefix).is_some_and(|key| !key.is_empty()));
+        if !PLACEHOLDERS.contains(&name) && !metadata_key {
+            unknown.push(name.to_string());
+        }
+        rest = after;
+    }
+    unknown
+}
+
+/// Text before the next `{name}`, the name and the text after it
+fn next_placeholder(text: &str) -> Option<(&str, &str, &str)> {
+    let start = text.find('{')?;
+    let length = text[start + 1..].find('}')?;
+    let end = start + 1 + length;
+    Some((&text[..start], &text[start + 1..end], &text[end + 1..]))
+}
+
+fn placeholder_value(name: &str, alert: &Alert) -> Option<String> {
+    let finding = &alert.finding;
+    let text = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let value = match name {
+        "severity" => format!("{:?}", finding.severity),
+        "category" => format!("{:?}", finding.category),
+        "kind" => match alert.kind {
+            AlertKind::New => "new",
+            AlertKind::Escalated { .. } => "escalated",
+            AlertKind::Test => "test",
+            AlertKind::Resolved => "resolved",
+        }
+        .to_string(),
+        "market" => alert.market_name.clone(),
+        "market_address" => utils::to_checksum_address(&alert.market_address),
+        "chain_id" => alert.chain_id.to_string(),
+        "description" => finding.description.clone(),
+        "fingerprint" => finding.fingerprint.clone(),
+        "account" => finding.metadata.get("account").map(text).unwrap_or_default(),
+        "headline" => alert.headline(),
+        _ => {
+            if let Some(key) = name.strip_prefix("amount.").filter(|key| !key.is_empty()) {
+                return Some(match finding.metadata.get(key) {
+                    Some(Value::Number(usd)) => alert.unit.format(usd.as_f64().unwrap_or_default(), utils::NumberStyle::Us),
+                    other => other.map(text).unwrap_or_default(),
+                });
+            }
+            let key = name.strip_prefix("metadata.").filter(|key| !key.is_empty())?;
+            finding.metadata.get(key).map(text).unwrap_or_default()
+        }
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denomination::{Denomination, Unit};
+    use crate::risk::{RiskCategory, RiskFinding, RiskSeverity};
+    use crate::testing::finding;
+    use serde_json::json;
+
+    fn alert(severity: RiskSeverity, category: RiskCategory, metadata: Value) -> Alert {
+        let finding = RiskFinding { category, description: "Price moved 12%".to_string(), metadata, ..finding("fp-1", severity) };
+        Alert::new(AlertKind::New, "USDC".to_string(), Address::repeat_byte(0xc3), 1, finding)
+    }
+
+    fn rules(json: Value) -> Vec<RoutingRule> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_rules_evaluate_in_order_until_one_stops() {
+        let sinks = ["pagerduty", "telegram", "archive"].map(String::from);
+        let rules = RoutingRules::new(
+            &rules(json!([
+                { "name": "oracle pages", "match": { "min_severity": "Critical", "categories": ["OracleReliability"] }, "sink": "pagerduty" },
+                { "name": "watchlist", "match": { "watchlist_only": true }, "sink": "telegram", "continue": true,
+                  "template": "{severity} {account} in {market}: {metadata.health_factor}{unknown}, {amount.debt} borrowed" },
+                { "match": { "max_severity": "High", "chain_id": 1 }, "sink": "archive" },
+            ])),
+            &sinks,
+        )
+        .unwrap();
+
+        let oracle = alert(RiskSeverity::Critical, RiskCategory::OracleReliability, json!({ "account": "0xaa" }));
+        let outcomes = rules.explain(&oracle);
+        let sent: Vec<&str> = outcomes.iter().filter(|o| o.sends()).map(|o| o.sink.as_str()).collect();
+        assert_eq!(sent, ["pagerduty"]);
+        // Rules after a stopping match are not reached, even when they would match
+        assert_eq!((outcomes[1].mismatch.as_ref(), outcomes[1].evaluated), (None, false));
+
+        let mut watchlist = alert(RiskSeverity::High, RiskCategory::LiquidationCascade, json!({ "account": "0xaa", "health_factor": 1.02, "debt": 250_000.0 }));
+        let routed = rules.route(&watchlist);
+        let sent: Vec<&str> = routed.iter().map(|o| o.sink.as_str()).collect();
+        assert_eq!(sent, ["telegram", "archive"]);
+        assert_eq!(routed[0].message.as_deref(), Some("High 0xaa in USDC: 1.02{unknown}, $250K borrowed"));
+        // Amounts are converted to the unit of the alert's market, and only in the message
+        watchlist.unit = Unit { denomination: Denomination::Base, symbol: "WETH".to_string(), price_usd: 2_500.0 };
+        assert_eq!(rules.route(&watchlist)[0].message.as_deref(), Some("High 0xaa in USDC: 1.02{unknown}, 100 WETH borrowed"));
+        assert_eq!((routed[1].rule.as_str(), routed[1].message.as_ref()), ("rules[2]", None));
+
+        let outcomes = rules.explain(&alert(RiskSeverity::Critical, RiskCategory::HighUtilization, json!({})));
+        let reasons: Vec<Option<&str>> = outcomes.iter().map(|o| o.mismatch.as_deref()).collect();
+        assert_eq!(
+            reasons,
+            [
+                Some("category HighUtilization is not listed"),
+                Some("the finding is not about a watchlist account"),
+                Some("severity Critical is above High"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules_reject_undefined_sinks_and_unknown_placeholders() {
+        let error = RoutingRules::new(&rules(json!([{ "sink": "slack" }])), &["telegram".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("alerts.rules[0].sink"), "{}", error);
+        assert!(error.to_string().contains("no sink named `slack`"), "{}", error);
+
+        assert_eq!(unknown_placeholders("{severity} {metadata.} {metadata.price} {sevrity}"), ["metadata.", "sevrity"]);
+        assert!(unknown_placeholders("no placeholders {").is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+//! Alert state that survives restarts
+//!
+//! The scheduler diffs every run against the previous one, so after a restart each ongoing
+//! finding looks new. `AlertStateStore` remembers per fingerprint when a finding was first seen,
+//! last notified, acknowledged and resolved, and `AlertDispatcher` consults it before sending:
+//! a notified finding is not sent again until it escalates or resolves and recurs, and an
+//! acknowledged one until it escalates past the acknowledged severity. With a state file the
+//! state is re-read before and written after every change, so acknowledgements made by
+//! `risk-engine-cli ack` reach a running scheduler. The state also remembers when each market
+//! was last assessed, so startup reconciliation can resolve findings whose data went stale
+//! while the engine was down.
+
+use super::Alert;
+use crate::error::{Result, RiskEngineError};
+use crate::risk::{RiskCategory, RiskSeverity};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// An operator's acknowledgement of a finding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Acknowledgement {
+    pub at: DateTime<Utc>,
+    /// Severity acknowledged; only a higher one alerts again
+    pub severity: RiskSeverity,
+}
+
+/// What the alert state knows about one finding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub category: RiskCategory,
+    /// Severity of the latest sighting
+    pub severity: RiskSeverity,
+    /// First sighting since the finding last recurred
+    pub first_seen: DateTime<Utc>,
+    /// Last time a route or digest took the alert
+    pub last_notified: Option<DateTime<Utc>>,
+    /// Severity of that alert
+    pub notified_severity: Option<RiskSeverity>,
+    pub acknowledged: Option<Acknowledgement>,
+    /// When the finding disappeared; a recurrence starts a new record
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// Resolved by startup reconciliation because its data was older than
+    /// `reconcile.max_data_age_secs`, not because an assessment no longer reported it
+    #[serde(default)]
+    pub stale: bool,
+}
+
+impl AlertRecord {
+    fn new(alert: &Alert, now: DateTime<Utc>) -> Self {
+        Self {
+            market_name: alert.market_name.clone(),
+            market_address: alert.market_address,
+            category: alert.finding.category.clone(),
+            severity: alert.finding.severity,
+            first_seen: now,
+            last_notified: None,
+            notified_severity: None,
+            acknowledged: None,
+            resolved_at: None,
+            stale: false,
+        }
+    }
+}
+
+/// Records by fingerprint, as saved to the state file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AlertState {
+    findings: BTreeMap<String, AlertRecord>,
+    /// Last assessment seen of each market; missing from files written before it was kept
+    #[serde(default)]
+    assessed: BTreeMap<Address, DateTime<Utc>>,
+}
+
+impl AlertState {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| RiskEngineError::serialization(format!("alert state {}", path.display()), e))
+    }
+
+    /// Write the state to disk atomically (temp file + rename)
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| RiskEngineError::serialization("alert state", e))?;
+        fs::write(&tmp, content).map_err(|e| RiskEngineError::io(&tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| RiskEngineError::io(path, e))?;
+        Ok(())
+    }
+}
+
+/// Alert state, kept in `alerts.state_path` or in memory
+pub struct AlertStateStore {
+    path: Option<PathBuf>,
+    state: Mutex<AlertState>,
+}
+
+impl AlertStateStore {
+    /// Store backed by the file at `path`, or in memory when `None`; the file is created on
+    /// the first change
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path, state: Mutex::new(AlertState::default()) }
+    }
+
+    /// State file, if the state outlives the process
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Record of `fingerprint`, if the finding was ever alerted on
+    pub fn get(&self, fingerprint: &str) -> Result<Option<AlertRecord>> {
+        self.read(|state| state.findings.get(fingerprint).cloned())
+    }
+
+    /// Record a sighting of the finding of `alert`, returning why it must not be sent, if so
+    ///
+    /// A finding seen again after it resolved starts over, acknowledgement included.
+    pub fn observe(&self, alert: &Alert, now: DateTime<Utc>) -> Result<Option<&'static str>> {
+        self.update(|state| {
+            let finding = &alert.finding;
+            let record = state
+                .findings
+                .entry(finding.fingerprint.clone())
+                .or_insert_with(|| AlertRecord::new(alert, now));
+            if record.resolved_at.is_some() {
+                *record = AlertRecord::new(alert, now);
+            }
+            record.severity = finding.severity;
+            record.market_name = alert.market_name.clone();
+
+            if record.acknowledged.as_ref().is_some_and(|ack| finding.severity <= ack.severity) {
+                Some("acknowledged")
+            } else if record.notified_severity.is_some_and(|notified| finding.severity <= notified) {
+                Some("already notified")
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Record that `alert` reached a route or digest; an escalation past the acknowledged
+    /// severity ends the acknowledgement
+    pub fn notified(&self, alert: &Alert, now: DateTime<Utc>) -> Result<()> {
+        self.update(|state| {
+            let finding = &alert.finding;
+            let record = state
+                .findings
+                .entry(finding.fingerprint.clone())
+                .or_insert_with(|| AlertRecord::new(alert, now));
+            record.last_notified = Some(now);
+            record.notified_severity = Some(finding.severity);
+            if record.acknowledged.as_ref().is_some_and(|ack| finding.severity > ack.severity) {
+                record.acknowledged = None;
+            }
+        })
+    }
+
+    /// Record that the finding `fingerprint` is gone
+    pub fn resolved(&self, fingerprint: &str, now: DateTime<Utc>) -> Result<()> {
+        self.update(|state| {
+            if let Some(record) = state.findings.get_mut(fingerprint) {
+                record.resolved_at.get_or_insert(now);
+            }
+        })
+    }
+
+    /// Resolve the open findings of `market` whose fingerprint is not in `current`, such as
+    /// ones that disappeared while the engine was not running; returns their fingerprints
+    pub fn resolve_missing(&self, market: Address, current: &HashSet<&str>, now: DateTime<Utc>) -> Result<Vec<String>> {
+        self.update(|state| {
+            state.assessed.insert(market, now);
+            let mut resolved = Vec::new();
+            for (fingerprint, record) in &mut state.findings {
+                let open = record.market_address == market && record.resolved_at.is_none();
+                if open && !current.contains(fingerprint.as_str()) {
+                    record.resolved_at = Some(now);
+                    resolved.push(fingerprint.clone());
+                }
+            }
+            resolved
+        })
+    }
+
+    /// Resolve the open findings whose data is older than `cutoff`, marking them `stale`;
+    /// returns their fingerprints
+    ///
+    /// A finding's data is as recent as the last assessment of its market, or its last
+    /// sighting or alert where the market's assessments were not recorded. A stale finding
+    /// that a fresh assessment still reports alerts again as new.
+    pub fn resolve_stale(&self, cutoff: DateTime<Utc>, now: DateTime<Utc>) -> Result<Vec<String>> {
+        self.update(|state| {
+            let mut resolved = Vec::new();
+            for (fingerprint, record) in &mut state.findings {
+                let data_at = [Some(record.first_seen), record.last_notified, state.assessed.get(&record.market_address).copied()]
+                    .into_iter()
+                    .flatten()
+                    .max()
+                    .unwrap_or(record.first_seen);
+                if record.resolved_at.is_none() && data_at < cutoff {
+                    record.resolved_at = Some(now);
+                    record.stale = true;
+                    resolved.push(fingerprint.clone());
+                }
+            }
+            resolved
+        })
+    }
+
+    /// Stop alerting on `fingerprint` until it escalates past its current severity
+    pub fn acknowledge(&self, fingerprint: &str, now: DateTime<Utc>) -> Result<AlertRecord> {
+        self.update(|state| {
+            let record = state
+                .findings
+                .get_mut(fingerprint)
+                .ok_or_else(|| RiskEngineError::NotFound { kind: "alert", id: fingerprint.to_string() })?;
+            record.acknowledged = Some(Acknowledgement { at: now, severity: record.severity });
+            Ok(record.clone())
+        })?
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AlertState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read<T>(&self, query: impl FnOnce(&AlertState) -> T) -> Result<T> {
+        let mut state = self.lock();
+        if let Some(path) = &self.path {
+            *state = AlertState::load(path)?;
+        }
+        Ok(query(&state))
+    }
+
+    /// Apply `change` to the latest state and persist it
+    fn update<T>(&self, change: impl FnOnce(&mut AlertState) -> T) -> Result<T> {
+        let mut state = self.lock();
+        if let Some(path) = &self.path {
+            *state = AlertState::load(path)?;
+        }
+        let result = change(&mut state);
+        if let Some(path) = &self.path {
+            state.save(path)?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertKind;
+    use crate::risk::RiskFinding;
+    use crate::testing::finding;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    fn alert(severity: RiskSeverity) -> Alert {
+        let finding = RiskFinding { description: "Utilization at 95%".to_string(), ..finding("fp-1", severity) };
+        Alert::new(AlertKind::New, "USDC".to_string(), Address::repeat_byte(0xc3), 1, finding)
+    }
+
+    #[test]
+    fn test_state_file_outlives_the_store() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("alert-state.json");
+        let now = Utc::now();
+        let high = alert(RiskSeverity::High);
+
+        let store = AlertStateStore::new(Some(path.clone()));
+        assert_eq!(store.observe(&high, now).unwrap(), None);
+        store.notified(&high, now).unwrap();
+
+        // A restarted process reads the same file
+        let restarted = AlertStateStore::new(Some(path.clone()));
+        let later = now + Duration::days(2);
+        assert_eq!(restarted.observe(&high, later).unwrap(), Some("already notified"));
+        assert_eq!(restarted.get("fp-1").unwrap().map(|r| r.first_seen), Some(now));
+        assert_eq!(restarted.observe(&alert(RiskSeverity::Critical), later).unwrap(), None);
+
+        // Gone while nothing ran, then back: alerted again as a new finding
+        let resolved = restarted.resolve_missing(high.market_address, &HashSet::new(), later).unwrap();
+        assert_eq!(resolved, ["fp-1"]);
+        assert_eq!(store.get("fp-1").unwrap().and_then(|r| r.resolved_at), Some(later));
+        assert_eq!(store.observe(&high, later).unwrap(), None);
+        assert_eq!(store.get("fp-1").unwrap().map(|r| (r.first_seen, r.resolved_at)), Some((later, None)));
+    }
+
+    #[test]
+    fn test_acknowledged_findings_alert_again_only_when_they_escalate() {
+        let store = AlertStateStore::new(None);
+        let now = Utc::now();
+        let medium = alert(RiskSeverity::Medium);
+        let error = store.acknowledge("fp-1", now).unwrap_err();
+        assert!(matches!(error, RiskEngineError::NotFound { kind: "alert", .. }));
+
+        store.observe(&medium, now).unwrap();
+        let record = store.acknowledge("fp-1", now).unwrap();
+        assert_eq!(record.acknowledged.map(|ack| ack.severity), Some(RiskSeverity::Medium));
+        // Acknowledged before it was ever sent
+        assert_eq!(store.observe(&medium, now).unwrap(), Some("acknowledged"));
+
+        let high = alert(RiskSeverity::High);
+        assert_eq!(store.observe(&high, now).unwrap(), None);
+        store.notified(&high, now).unwrap();
+        assert_eq!(store.get("fp-1").unwrap().and_then(|r| r.acknowledged), None);
+        assert_eq!(store.observe(&high, now).unwrap(), Some("already notified"));
+    }
+
+    #[test]
+    fn test_findings_of_markets_not_assessed_for_too_long_resolve_as_stale() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("alert-state.json");
+        let week_ago = Utc::now() - Duration::days(7);
+        let store = AlertStateStore::new(Some(path.clone()));
+        let high = alert(RiskSeverity::High);
+        store.observe(&high, week_ago).unwrap();
+        store.notified(&high, week_ago).unwrap();
+        let other = Address::repeat_byte(0x46);
+        store.resolve_missing(other, &HashSet::new(), week_ago).unwrap();
+
+        // Assessed an hour ago: the finding's data is fresh, whenever it was first seen
+        let now = Utc::now();
+        let restarted = AlertStateStore::new(Some(path.clone()));
+        restarted.resolve_missing(high.market_address, &HashSet::from(["fp-1"]), now - Duration::hours(1)).unwrap();
+        assert!(restarted.resolve_stale(now - Duration::days(1), now).unwrap().is_empty());
+
+        let resolved = restarted.resolve_stale(now - Duration::minutes(10), now).unwrap();
+        assert_eq!(resolved, ["fp-1"]);
+        let record = store.get("fp-1").unwrap().unwrap();
+        assert_eq!((record.resolved_at, record.stale), (Some(now), true));
+        // Recurring on fresh data starts a new record that alerts
+        assert_eq!(store.observe(&high, now).unwrap(), None);
+        assert!(!store.get("fp-1").unwrap().unwrap().stale);
+    }
+}
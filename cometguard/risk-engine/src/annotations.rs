@@ -0,0 +1,111 @@
+//! Operator notes attached to findings
+//!
+//! During incident review an operator records what a finding turned out to be ("confirmed
+//! false positive, feed migrated") with `risk-engine-cli annotate` or
+//! `POST /findings/{fingerprint}/annotations`. Notes are kept in the history store by
+//! fingerprint, so they follow the finding across assessments: each assessment carries the
+//! latest note of each of its findings in `RiskAssessment::annotations`, and reports, the
+//! `history` command and email digests print it under the finding. Notes are informational
+//! only and never change a score or whether a finding alerts.
+
+use crate::error::{Result, RiskEngineError};
+use crate::utils::sanitize_inline;
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Longest note accepted, in characters
+pub const MAX_NOTE_CHARS: usize = 2000;
+
+/// Note an operator attached to the finding with `fingerprint`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub fingerprint: String,
+    pub note: String,
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Annotation {
+    /// Note by `author` on `fingerprint`, made now; fails on an empty fingerprint, note or
+    /// author, and on a note over `MAX_NOTE_CHARS`
+    pub fn new(fingerprint: &str, note: &str, author: &str) -> Result<Self> {
+        let (fingerprint, note, author) = (fingerprint.trim(), note.trim(), author.trim());
+        let invalid = |what, input: &str, message: &str| RiskEngineError::Parse {
+            what,
+            input: input.to_string(),
+            message: message.to_string(),
+        };
+        if fingerprint.is_empty() {
+            return Err(invalid("finding fingerprint", fingerprint, "is empty"));
+        }
+        if note.is_empty() {
+            return Err(invalid("annotation note", note, "is empty"));
+        }
+        if note.chars().count() > MAX_NOTE_CHARS {
+            return Err(invalid("annotation note", "", &format!("is longer than {} characters", MAX_NOTE_CHARS)));
+        }
+        if author.is_empty() {
+            return Err(invalid("annotation author", author, "is empty"));
+        }
+        Ok(Self {
+            fingerprint: fingerprint.to_string(),
+            note: note.to_string(),
+            author: author.to_string(),
+            // Stores keep milliseconds, so the note compares equal once read back
+            created_at: Utc::now().trunc_subsecs(3),
+        })
+    }
+}
+
+/// `confirmed false positive, feed migrated (alice, 2024-05-01 12:00 UTC)`, on one line
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {})",
+            sanitize_inline(&self.note),
+            sanitize_inline(&self.author),
+            self.created_at.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+}
+
+/// Newest annotation of each fingerprint among `annotations`
+pub fn latest(annotations: impl IntoIterator<Item = Annotation>) -> BTreeMap<String, Annotation> {
+    let mut latest: BTreeMap<String, Annotation> = BTreeMap::new();
+    for annotation in annotations {
+        match latest.get(&annotation.fingerprint) {
+            Some(newer) if newer.created_at > annotation.created_at => {}
+            _ => {
+                latest.insert(annotation.fingerprint.clone(), annotation);
+            }
+        }
+    }
+    latest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_annotations_are_validated_and_the_newest_wins() {
+        let note = Annotation::new(" 3f2a9c0d8e7b6a51 ", " confirmed false positive,\nfeed migrated ", "alice").unwrap();
+        assert_eq!((note.fingerprint.as_str(), note.note.as_str()), ("3f2a9c0d8e7b6a51", "confirmed false positive,\nfeed migrated"));
+        let shown = Annotation { created_at: "2024-05-01T12:00:00Z".parse().unwrap(), ..note.clone() }.to_string();
+        assert_eq!(shown, "confirmed false positive, feed migrated (alice, 2024-05-01 12:00 UTC)");
+
+        assert!(matches!(Annotation::new("f", "  ", "alice"), Err(RiskEngineError::Parse { what: "annotation note", .. })));
+        assert!(matches!(Annotation::new("f", "note", ""), Err(RiskEngineError::Parse { what: "annotation author", .. })));
+        assert!(Annotation::new("f", &"x".repeat(MAX_NOTE_CHARS + 1), "alice").is_err());
+
+        let older = Annotation { note: "looking into it".to_string(), created_at: note.created_at - Duration::hours(1), ..note.clone() };
+        let other = Annotation { fingerprint: "0123456789abcdef".to_string(), ..note.clone() };
+        let latest = latest([note.clone(), older, other.clone()]);
+        assert_eq!(latest.len(), 2);
+        assert_eq!((&latest["3f2a9c0d8e7b6a51"], &latest["0123456789abcdef"]), (&note, &other));
+    }
+}
@@ -0,0 +1,136 @@
+//! Saved results: the file envelope written by `--out` and read back for offline analysis
+//!
+//! A file holds one `Envelope`: the schema and engine versions, the chain and block the
+//! results were computed at, and the results themselves. Files are written to a sibling
+//! temporary file and renamed into place, so a crash leaves the previous file or the new one,
+//! never a truncated one. Bare results as printed by `--output json` are read too.
+
+use crate::config::Config;
+use crate::error::{Result, RiskEngineError};
+use crate::version::ENGINE_VERSION;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Version of the envelope format written by this engine; files with a newer one are refused
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What an envelope holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    /// A list of `RiskAssessment`s
+    Assessments,
+    /// One `SimulationResult`
+    Simulation,
+    /// One `PositionScan`
+    PositionScan,
+}
+
+/// Results with where and when they were computed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub schema_version: u32,
+    /// Version of the engine that wrote the file
+    pub engine_version: String,
+    pub kind: ContentKind,
+    pub created_at: DateTime<Utc>,
+    pub chain_id: u64,
+    /// Chain head when the results were saved, if the provider reads from chain
+    pub head_block: Option<u64>,
+    pub content: T,
+}
+
+impl<T> Envelope<T> {
+    /// Envelope of `content` computed now on the chain of `config`
+    pub fn new(kind: ContentKind, config: &Config, head_block: Option<u64>, content: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            engine_version: ENGINE_VERSION.to_string(),
+            kind,
+            created_at: Utc::now(),
+            chain_id: config.compound.chain_id,
+            head_block,
+            content,
+        }
+    }
+}
+
+/// Write `envelope` to `path` as pretty JSON, replacing the file atomically
+pub fn write<T: Serialize>(path: &Path, envelope: &Envelope<T>) -> Result<()> {
+    let content = serde_json::to_string_pretty(envelope).map_err(|e| RiskEngineError::serialization("saved results", e))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = Path::new(&tmp);
+    fs::write(tmp, content).map_err(|e| RiskEngineError::io(tmp, e))?;
+    fs::rename(tmp, path).map_err(|e| RiskEngineError::io(path, e))?;
+    Ok(())
+}
+
+/// Content of the `kind` envelope in `path`, or the whole file if it holds bare results
+pub fn read<T: DeserializeOwned>(path: &Path, kind: ContentKind) -> Result<T> {
+    let invalid = |message: String| RiskEngineError::Parse {
+        what: "saved results",
+        input: path.display().to_string(),
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+    let value: serde_json::Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    if value.get("schema_version").is_none() {
+        return serde_json::from_value(value).map_err(|e| invalid(e.to_string()));
+    }
+
+    let envelope: Envelope<serde_json::Value> = serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?;
+    if envelope.schema_version > SCHEMA_VERSION {
+        return Err(invalid(format!(
+            "schema version {} was written by engine {}; this engine reads up to version {}",
+            envelope.schema_version, envelope.engine_version, SCHEMA_VERSION
+        )));
+    }
+    if envelope.kind != kind {
+        return Err(invalid(format!("holds {:?} results, expected {:?}", envelope.kind, kind)));
+    }
+    serde_json::from_value(envelope.content).map_err(|e| invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::risk::{RiskAssessment, SimulationResult};
+    use ethers::types::Address;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_envelope_round_trip_and_version_checks() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("assessment.json");
+        let assessments = vec![RiskAssessment {
+            risk_score: 40,
+            watchlist_min_health_factor: Some(1.2),
+            ..RiskAssessment::new("USDC", Address::repeat_byte(0xc3), Vec::new())
+        }];
+        let envelope = Envelope::new(ContentKind::Assessments, &Config::default(), Some(19_000_000), &assessments);
+        write(&path, &envelope).unwrap();
+        assert!(!dir.path().join("assessment.json.tmp").exists());
+
+        let loaded = RiskAssessment::load_many(&path).unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&assessments).unwrap());
+        let saved: Envelope<serde_json::Value> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!((saved.schema_version, saved.chain_id, saved.head_block), (SCHEMA_VERSION, 1, Some(19_000_000)));
+
+        let err = read::<SimulationResult>(&path, ContentKind::Simulation).unwrap_err().to_string();
+        assert!(err.contains("holds Assessments results, expected Simulation"), "{}", err);
+
+        let mut newer = serde_json::to_value(&envelope).unwrap();
+        newer["schema_version"] = (SCHEMA_VERSION + 1).into();
+        fs::write(&path, newer.to_string()).unwrap();
+        let err = RiskAssessment::load_many(&path).unwrap_err().to_string();
+        assert!(err.contains("this engine reads up to version 1"), "{}", err);
+
+        // Output of `--output json assess`, one market or several
+        fs::write(&path, serde_json::to_string(&assessments[0]).unwrap()).unwrap();
+        assert_eq!(RiskAssessment::load_many(&path).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,398 @@
+//! Append-only JSON Lines record of every assessment and the data it was computed from
+//!
+//! Entries are queued and written by a dedicated thread, so a slow or full disk never
+//! delays an assessment: failed writes are logged and counted, and once the queue is full
+//! further entries are dropped.
+
+use crate::config::{AuditConfig, AuditRotation, Config};
+use crate::error::{Result, RiskEngineError};
+use crate::risk::RiskAssessment;
+use crate::version::ENGINE_VERSION;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the entry was written; never decreases within a log
+    pub recorded_at: DateTime<Utc>,
+    /// Version of the engine that produced the assessment
+    pub engine_version: String,
+    /// SHA-256 of the engine's configuration (see `config_hash`)
+    pub config_hash: String,
+    pub chain_id: u64,
+    /// Chain head read alongside the market data, if the provider reads from chain
+    pub head_block: Option<u64>,
+    /// Last background refresh of the market data cache, if any
+    pub data_refreshed_at: Option<DateTime<Utc>>,
+    pub assessment: RiskAssessment,
+}
+
+pub use crate::version::config_hash;
+
+enum WriteOp {
+    Entry(Box<AuditEntry>),
+    /// Answered once every entry queued before it has been written
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle to the audit writer thread
+pub struct AuditLog {
+    queue: SyncSender<WriteOp>,
+    config_hash: String,
+    chain_id: u64,
+    failed: Arc<AtomicU64>,
+    dropped: AtomicU64,
+}
+
+impl AuditLog {
+    /// Audit log configured by `config.audit`, if enabled
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match &config.audit.directory {
+            Some(directory) => Ok(Some(Self::open(directory, &config.audit, config_hash(config), config.compound.chain_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Start writing entries stamped with `config_hash` and `chain_id` to `directory`
+    pub fn open(directory: &Path, settings: &AuditConfig, config_hash: String, chain_id: u64) -> Result<Self> {
+        fs::create_dir_all(directory).map_err(|e| RiskEngineError::io(directory, e))?;
+        let (queue, entries) = mpsc::sync_channel(settings.queue_capacity.max(1));
+        let failed = Arc::new(AtomicU64::new(0));
+
+        let mut writer = Writer {
+            directory: directory.to_path_buf(),
+            rotation: settings.rotation,
+            max_file_bytes: settings.max_file_bytes,
+            fsync: settings.fsync,
+            sequence: None,
+            current: None,
+        };
+        let writer_failed = failed.clone();
+        std::thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || {
+                let mut last_recorded = None;
+                for op in entries {
+                    match op {
+                        WriteOp::Entry(mut entry) => {
+                            // Never go backwards, even if the wall clock does
+                            entry.recorded_at = last_recorded.map_or(entry.recorded_at, |last| entry.recorded_at.max(last));
+                            last_recorded = Some(entry.recorded_at);
+                            if let Err(e) = writer.write(&entry) {
+                                writer_failed.fetch_add(1, Ordering::Relaxed);
+                                warn!("Failed to write audit entry for {}: {}", entry.assessment.market_name, e);
+                            }
+                        }
+                        WriteOp::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .map_err(|e| RiskEngineError::io(directory, e))?;
+
+        Ok(Self {
+            queue,
+            config_hash,
+            chain_id,
+            failed,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Queue `assessment` for writing; never blocks and never fails
+    pub fn record(&self, assessment: &RiskAssessment, head_block: Option<u64>, data_refreshed_at: Option<DateTime<Utc>>) {
+        let entry = AuditEntry {
+            recorded_at: Utc::now(),
+            engine_version: ENGINE_VERSION.to_string(),
+            config_hash: self.config_hash.clone(),
+            chain_id: self.chain_id,
+            head_block,
+            data_refreshed_at,
+            assessment: assessment.clone(),
+        };
+        match self.queue.try_send(WriteOp::Entry(Box::new(entry))) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("Audit queue is full; dropped the entry for {}", assessment.market_name);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                warn!("Audit writer has stopped; lost the entry for {}", assessment.market_name);
+            }
+        }
+    }
+
+    /// Wait until every entry queued so far has been written (or has failed)
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        // Blocks only while the queue is full, and the writer drains it
+        let queue = self.queue.clone();
+        let queued = tokio::task::spawn_blocking(move || queue.send(WriteOp::Flush(done)).is_ok()).await;
+        if matches!(queued, Ok(true)) {
+            let _ = written.await;
+        }
+    }
+
+    /// Entries that could not be written
+    pub fn failed_writes(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Entries dropped because the queue was full
+    pub fn dropped_entries(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct OpenFile {
+    name: String,
+    file: File,
+    size: u64,
+}
+
+/// Appends entries to the current file, rotating as configured
+struct Writer {
+    directory: PathBuf,
+    rotation: AuditRotation,
+    max_file_bytes: u64,
+    fsync: bool,
+    /// Number of the current file with size rotation
+    sequence: Option<u64>,
+    current: Option<OpenFile>,
+}
+
+impl Writer {
+    fn write(&mut self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let len = line.len() as u64;
+
+        let name = match self.rotation {
+            AuditRotation::Daily => format!("audit-{}.jsonl", entry.recorded_at.format("%Y-%m-%d")),
+            AuditRotation::Size => {
+                let sequence = match self.sequence {
+                    Some(sequence) => sequence,
+                    None => latest_sequence(&self.directory)?.unwrap_or(1),
+                };
+                let size = self.open(&numbered_name(sequence))?.size;
+                let sequence = if size > 0 && size + len > self.max_file_bytes { sequence + 1 } else { sequence };
+                self.sequence = Some(sequence);
+                numbered_name(sequence)
+            }
+        };
+
+        let fsync = self.fsync;
+        let current = self.open(&name)?;
+        current.file.write_all(&line)?;
+        current.size += len;
+        if fsync {
+            current.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// The file called `name`, opened for appending if it is not the current one
+    fn open(&mut self, name: &str) -> io::Result<&mut OpenFile> {
+        if self.current.as_ref().is_some_and(|c| c.name == name) {
+            return Ok(self.current.as_mut().expect("checked above"));
+        }
+        let file = OpenOptions::new().create(true).append(true).open(self.directory.join(name))?;
+        let size = file.metadata()?.len();
+        Ok(self.current.insert(OpenFile { name: name.to_string(), file, size }))
+    }
+}
+
+fn numbered_name(sequence: u64) -> String {
+    format!("audit-{:06}.jsonl", sequence)
+}
+
+/// Highest `audit-NNNNNN.jsonl` number in `directory`
+fn latest_sequence(directory: &Path) -> io::Result<Option<u64>> {
+    let mut latest = None;
+    for entry in fs::read_dir(directory)? {
+        let name = entry?.file_name();
+        let sequence = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("audit-"))
+            .and_then(|n| n.strip_suffix(".jsonl"))
+            .filter(|n| n.len() == 6)
+            .and_then(|n| n.parse::<u64>().ok());
+        latest = latest.max(sequence);
+    }
+    Ok(latest)
+}
+
+/// Malformed or out-of-order line found by `verify`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditProblem {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+/// Outcome of checking one audit file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Well-formed entries
+    pub entries: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Well-formed entries by the engine version that wrote them
+    pub engine_versions: BTreeMap<String, usize>,
+    pub problems: Vec<AuditProblem>,
+}
+
+impl VerifyReport {
+    /// Whether every line is a well-formed entry and timestamps never decrease
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check that every line of `path` is a well-formed `AuditEntry` with non-decreasing `recorded_at`
+pub fn verify(path: &Path) -> Result<VerifyReport> {
+    let file = File::open(path).map_err(|e| RiskEngineError::io(path, e))?;
+    let mut report = VerifyReport::default();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| RiskEngineError::io(path, e))?;
+        let mut problem = |message: String| report.problems.push(AuditProblem { line: index + 1, message });
+        let entry: AuditEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                problem(format!("not an audit entry: {}", e));
+                continue;
+            }
+        };
+        if let Some(last) = report.last.filter(|last| entry.recorded_at < *last) {
+            problem(format!("recorded at {} before the previous entry ({})", entry.recorded_at, last));
+        }
+        report.entries += 1;
+        *report.engine_versions.entry(entry.engine_version).or_default() += 1;
+        report.first.get_or_insert(entry.recorded_at);
+        report.last = report.last.max(Some(entry.recorded_at));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assessment;
+
+    fn sample_assessment() -> RiskAssessment {
+        RiskAssessment { mock_data: true, ..assessment(45, Vec::new()) }
+    }
+
+    fn settings(rotation: AuditRotation, max_file_bytes: u64) -> AuditConfig {
+        AuditConfig { rotation, max_file_bytes, fsync: true, ..AuditConfig::default() }
+    }
+
+    fn files(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(directory)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_written_to_a_daily_file_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(dir.path(), &settings(AuditRotation::Daily, 0), "abc".to_string(), 1).unwrap();
+        let assessment = sample_assessment();
+
+        log.record(&assessment, Some(19_000_000), None);
+        log.record(&assessment, None, Some(Utc::now()));
+        log.flush().await;
+
+        let names = files(dir.path());
+        assert_eq!(names, [format!("audit-{}.jsonl", Utc::now().format("%Y-%m-%d"))]);
+        let path = dir.path().join(&names[0]);
+        let report = verify(&path).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.entries, 2);
+
+        let first: AuditEntry = serde_json::from_str(fs::read_to_string(&path).unwrap().lines().next().unwrap()).unwrap();
+        assert_eq!((first.config_hash.as_str(), first.chain_id, first.head_block), ("abc", 1, Some(19_000_000)));
+        assert_eq!(first.engine_version, ENGINE_VERSION);
+        assert_eq!(first.assessment.risk_score, assessment.risk_score);
+        assert_eq!(log.failed_writes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_size_rotation_continues_numbered_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let assessment = sample_assessment();
+        {
+            // Every entry is larger than 10 bytes, so each one starts a new file
+            let log = AuditLog::open(dir.path(), &settings(AuditRotation::Size, 10), String::new(), 1).unwrap();
+            log.record(&assessment, None, None);
+            log.record(&assessment, None, None);
+            log.flush().await;
+        }
+        let log = AuditLog::open(dir.path(), &settings(AuditRotation::Size, 1 << 20), String::new(), 1).unwrap();
+        log.record(&assessment, None, None);
+        log.flush().await;
+
+        assert_eq!(files(dir.path()), ["audit-000001.jsonl", "audit-000002.jsonl"]);
+        assert_eq!(verify(&dir.path().join("audit-000002.jsonl")).unwrap().entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_write_failures_are_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().join("audit");
+        let log = AuditLog::open(&directory, &settings(AuditRotation::Daily, 0), String::new(), 1).unwrap();
+        fs::remove_dir(&directory).unwrap();
+
+        log.record(&sample_assessment(), None, None);
+        log.flush().await;
+        assert_eq!(log.failed_writes(), 1);
+    }
+
+    #[test]
+    fn test_verify_reports_malformed_and_out_of_order_lines() {
+        let entry = |recorded_at: &str| {
+            let mut entry = serde_json::to_value(AuditEntry {
+                recorded_at: Utc::now(),
+                engine_version: "0.1.0".to_string(),
+                config_hash: String::new(),
+                chain_id: 1,
+                head_block: None,
+                data_refreshed_at: None,
+                assessment: sample_assessment(),
+            })
+            .unwrap();
+            entry["recorded_at"] = recorded_at.into();
+            entry.to_string()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let lines = [
+            entry("2024-05-01T10:00:00Z"),
+            "{\"truncated\":".to_string(),
+            entry("2024-05-01T09:00:00Z"),
+            entry("2024-05-01T11:00:00Z"),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let report = verify(&path).unwrap();
+        assert_eq!(report.entries, 3);
+        let lines: Vec<usize> = report.problems.iter().map(|p| p.line).collect();
+        assert_eq!(lines, [2, 3]);
+        assert_eq!(report.last.unwrap().to_rfc3339(), "2024-05-01T11:00:00+00:00");
+    }
+}
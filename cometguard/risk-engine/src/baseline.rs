@@ -0,0 +1,459 @@
+//! Key metrics of a market against its own stored history
+//!
+//! Whether 82% utilization is unusual depends on the market: one that has run hot for months
+//! is not one that spikes. `MarketBaseline::load` summarizes the last `risk.baseline.windows_days`
+//! of a market's stored assessments, the mean and percentiles of its utilization, reserves,
+//! risk score and collateral price volatility. `BaselineComparison` places a new assessment's
+//! values in the longest window by percentile rank, and `check_deviation_from_baseline` flags
+//! those beyond `risk.baseline.percentile` of their history.
+//!
+//! A market whose history spans fewer than `min_history_days` is not compared, since a
+//! two-day baseline makes anything look unusual; the assessment gets a Low `DataQuality`
+//! note instead. Reserves and volatility are stored from the assessments that read them, so
+//! they join the comparison once `min_samples` of them are.
+
+use crate::config::BaselineConfig;
+use crate::error::Result;
+use crate::models::DataIssueKind;
+use crate::risk::{finding_fingerprint, RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+use crate::storage::{MarketSnapshot, ScorePoint, Storage};
+use crate::utils;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Metric of a market compared with its history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BaselineMetric {
+    Utilization,
+    /// Reserves in USD; low is the risk
+    Reserves,
+    RiskScore,
+    /// Highest 30-day price volatility among the collateral assets
+    PriceVolatility,
+}
+
+impl BaselineMetric {
+    /// Every metric, in report order
+    pub const ALL: [BaselineMetric; 4] = [Self::Utilization, Self::Reserves, Self::RiskScore, Self::PriceVolatility];
+
+    /// Name in reports, e.g. `utilization`
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Utilization => "utilization",
+            Self::Reserves => "reserves",
+            Self::RiskScore => "score",
+            Self::PriceVolatility => "volatility",
+        }
+    }
+
+    /// Whether a high value is the unusual risk; for reserves it is a low one
+    pub fn higher_is_riskier(self) -> bool {
+        self != Self::Reserves
+    }
+
+    /// Category of the finding for a value beyond the metric's baseline
+    pub fn category(self) -> RiskCategory {
+        match self {
+            Self::Utilization => RiskCategory::HighUtilization,
+            Self::Reserves => RiskCategory::BadDebt,
+            Self::RiskScore => RiskCategory::RiskTrend,
+            Self::PriceVolatility => RiskCategory::PriceVolatility,
+        }
+    }
+
+    /// `value` as reports show the metric: a fraction, USD or points
+    pub fn format(self, value: f64) -> String {
+        match self {
+            Self::Utilization | Self::PriceVolatility => format!("{:.2}", value),
+            Self::Reserves => utils::format_money(value, "$"),
+            Self::RiskScore => format!("{:.0}", value),
+        }
+    }
+
+    /// The metric's value in a stored snapshot or score, or in a new assessment
+    fn of_snapshot(self, snapshot: &MarketSnapshot) -> Option<f64> {
+        match self {
+            Self::Utilization => Some(snapshot.utilization_rate),
+            Self::Reserves => snapshot.reserves,
+            Self::PriceVolatility => snapshot.price_volatility,
+            Self::RiskScore => None,
+        }
+    }
+
+    fn of_assessment(self, assessment: &RiskAssessment, utilization: f64) -> Option<f64> {
+        match self {
+            Self::Utilization => Some(utilization),
+            Self::Reserves => assessment.reserves_usd,
+            Self::RiskScore => Some(f64::from(assessment.risk_score)),
+            Self::PriceVolatility => assessment.price_volatility,
+        }
+    }
+}
+
+/// Mean and percentiles of one metric over a window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricStats {
+    pub metric: BaselineMetric,
+    pub samples: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub p5: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub max: f64,
+    /// Values in ascending order, for percentile ranks
+    #[serde(skip)]
+    sorted: Vec<f64>,
+}
+
+impl MetricStats {
+    /// Figures of the finite `values`; `None` if there are none
+    pub fn of(metric: BaselineMetric, values: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut sorted: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        Some(Self {
+            metric,
+            samples: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            min: sorted[0],
+            p5: quantile(&sorted, 0.05),
+            p50: quantile(&sorted, 0.5),
+            p95: quantile(&sorted, 0.95),
+            max: sorted[sorted.len() - 1],
+            sorted,
+        })
+    }
+
+    /// Value at `q` (0-1) of the history, interpolated between neighbouring values
+    pub fn quantile(&self, q: f64) -> f64 {
+        quantile(&self.sorted, q)
+    }
+
+    /// Share of the history at or below `value`, from 0 to 100
+    pub fn percentile_rank(&self, value: f64) -> f64 {
+        let at_or_below = self.sorted.partition_point(|v| *v <= value);
+        at_or_below as f64 / self.sorted.len() as f64 * 100.0
+    }
+}
+
+/// Linear interpolation of `q` in `sorted`, which is not empty
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// A market's metrics over the `days` before an assessment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowBaseline {
+    pub days: u32,
+    /// Stored assessments in the window
+    pub assessments: usize,
+    /// Oldest of them; `None` without any
+    pub oldest: Option<DateTime<Utc>>,
+    /// Metrics with at least one stored value, in `BaselineMetric::ALL` order
+    pub metrics: Vec<MetricStats>,
+}
+
+impl WindowBaseline {
+    /// Summary of `scores` and `snapshots`, all in the window
+    pub fn of(days: u32, scores: &[ScorePoint], snapshots: &[MarketSnapshot]) -> Self {
+        let metrics = BaselineMetric::ALL
+            .into_iter()
+            .filter_map(|metric| match metric {
+                BaselineMetric::RiskScore => MetricStats::of(metric, scores.iter().map(|p| f64::from(p.risk_score))),
+                _ => MetricStats::of(metric, snapshots.iter().filter_map(|s| metric.of_snapshot(s))),
+            })
+            .collect();
+        Self { days, assessments: scores.len(), oldest: scores.first().map(|p| p.timestamp), metrics }
+    }
+
+    pub fn metric(&self, metric: BaselineMetric) -> Option<&MetricStats> {
+        self.metrics.iter().find(|stats| stats.metric == metric)
+    }
+}
+
+/// A market's stored history before an assessment, over each configured window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketBaseline {
+    /// Shortest window first
+    pub windows: Vec<WindowBaseline>,
+}
+
+impl MarketBaseline {
+    /// Baselines of `assessment`'s market from `storage`, over the windows before it
+    pub async fn load(storage: &dyn Storage, assessment: &RiskAssessment, config: &BaselineConfig) -> Result<Self> {
+        let mut days = config.windows_days.clone();
+        days.sort_unstable();
+        days.dedup();
+        let until = assessment.timestamp;
+        let since = until - Duration::days(i64::from(days.last().copied().unwrap_or_default()));
+        let scores = storage.score_series(assessment.market_address, since, until).await?;
+        let snapshots = storage.snapshots(assessment.market_address, since, until).await?;
+        Ok(Self::of(&days, &scores, &snapshots, until))
+    }
+
+    /// Baselines over each of `days` before `until`, from history sorted oldest first
+    pub fn of(days: &[u32], scores: &[ScorePoint], snapshots: &[MarketSnapshot], until: DateTime<Utc>) -> Self {
+        let windows = days
+            .iter()
+            .map(|&days| {
+                let since = until - Duration::days(i64::from(days));
+                let scores: Vec<ScorePoint> = scores.iter().filter(|p| p.timestamp >= since).cloned().collect();
+                let snapshots: Vec<MarketSnapshot> = snapshots.iter().filter(|s| s.timestamp >= since).cloned().collect();
+                WindowBaseline::of(days, &scores, &snapshots)
+            })
+            .collect();
+        Self { windows }
+    }
+
+    /// Longest window, the one assessments are compared with
+    pub fn longest(&self) -> Option<&WindowBaseline> {
+        self.windows.last()
+    }
+}
+
+/// One metric of an assessment against the longest window of its baseline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub metric: BaselineMetric,
+    pub value: f64,
+    /// Share of the history at or below `value`, from 0 to 100
+    pub percentile_rank: f64,
+    pub window_days: u32,
+    pub mean: f64,
+    /// Value at `risk.baseline.percentile` of the history (at `1 - percentile` for reserves)
+    pub threshold: f64,
+    /// Whether `value` is beyond `threshold`, on the risky side
+    pub unusual: bool,
+}
+
+impl MetricComparison {
+    /// e.g. `utilization 0.82 (97th pct of last 90d)`
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} ({} pct of last {}d)",
+            self.metric.label(),
+            self.metric.format(self.value),
+            ordinal(self.percentile_rank.round() as u32),
+            self.window_days
+        )
+    }
+}
+
+/// `n` with its English ordinal suffix, e.g. `97th`, `1st`, `22nd`
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// An assessment's key metrics against its market's history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineComparison {
+    pub baseline: MarketBaseline,
+    /// Metrics with enough history, in `BaselineMetric::ALL` order
+    pub metrics: Vec<MetricComparison>,
+    /// Why the assessment was not compared, e.g. history too short; `None` when it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+impl BaselineComparison {
+    /// Place `assessment`, of a market at `utilization`, in the longest window of `baseline`
+    pub fn compare(baseline: MarketBaseline, assessment: &RiskAssessment, utilization: f64, config: &BaselineConfig) -> Self {
+        let Some(window) = baseline.longest() else {
+            return Self { baseline, metrics: Vec::new(), skipped: Some("no baseline windows".to_string()) };
+        };
+        let history_days = window.oldest.map_or(0.0, |oldest| (assessment.timestamp - oldest).num_seconds() as f64 / 86_400.0);
+        if history_days < f64::from(config.min_history_days) {
+            let skipped = format!(
+                "{:.1} days of history, fewer than the {} a baseline needs",
+                history_days, config.min_history_days
+            );
+            return Self { baseline, metrics: Vec::new(), skipped: Some(skipped) };
+        }
+
+        let metrics = BaselineMetric::ALL
+            .into_iter()
+            .filter_map(|metric| {
+                let stats = window.metric(metric).filter(|stats| stats.samples >= config.min_samples)?;
+                let value = metric.of_assessment(assessment, utilization).filter(|v| v.is_finite())?;
+                let (threshold, unusual) = if metric.higher_is_riskier() {
+                    let threshold = stats.quantile(config.percentile);
+                    (threshold, value > threshold)
+                } else {
+                    let threshold = stats.quantile(1.0 - config.percentile);
+                    (threshold, value < threshold)
+                };
+                Some(MetricComparison {
+                    metric,
+                    value,
+                    percentile_rank: stats.percentile_rank(value),
+                    window_days: window.days,
+                    mean: stats.mean,
+                    threshold,
+                    unusual,
+                })
+            })
+            .collect();
+        Self { baseline, metrics, skipped: None }
+    }
+
+    /// Every compared metric, e.g. `utilization 0.82 (97th pct of last 90d), score 40 (…)`;
+    /// `None` when nothing was compared
+    pub fn summary(&self) -> Option<String> {
+        (!self.metrics.is_empty()).then(|| self.metrics.iter().map(MetricComparison::summary).collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// Findings of `assessment` for `comparison`: a Medium finding for each metric beyond its
+/// baseline, or a Low `DataQuality` note when the history was too short to compare with
+pub fn check_deviation_from_baseline(assessment: &RiskAssessment, comparison: &BaselineComparison, config: &BaselineConfig) -> Vec<RiskFinding> {
+    if let Some(reason) = &comparison.skipped {
+        let category = RiskCategory::DataQuality;
+        return vec![RiskFinding {
+            fingerprint: finding_fingerprint(&assessment.market_address, &category, "baseline"),
+            category,
+            severity: RiskSeverity::Low,
+            description: format!("Not compared with its history: {}", reason),
+            metadata: serde_json::json!({
+                "kind": DataIssueKind::ShortHistory,
+                "check": "baseline",
+                "min_history_days": config.min_history_days,
+            }),
+            timestamp: assessment.timestamp,
+            score_contribution: 0,
+        }];
+    }
+
+    comparison
+        .metrics
+        .iter()
+        .filter(|metric| metric.unusual)
+        .map(|metric| {
+            let category = metric.metric.category();
+            let side = if metric.metric.higher_is_riskier() { "above" } else { "below" };
+            let percentile = if metric.metric.higher_is_riskier() { config.percentile } else { 1.0 - config.percentile };
+            RiskFinding {
+                fingerprint: finding_fingerprint(&assessment.market_address, &category, &format!("baseline:{}", metric.metric.label())),
+                category,
+                severity: RiskSeverity::Medium,
+                description: format!(
+                    "{}, {} its {} percentile of {} over the last {} days",
+                    capitalize(&metric.summary()),
+                    side,
+                    ordinal((percentile * 100.0).round() as u32),
+                    metric.metric.format(metric.threshold),
+                    metric.window_days
+                ),
+                metadata: serde_json::json!({
+                    "metric": metric.metric,
+                    "value": metric.value,
+                    "percentile_rank": metric.percentile_rank,
+                    "threshold": metric.threshold,
+                    "percentile": percentile,
+                    "mean": metric.mean,
+                    "window_days": metric.window_days,
+                }),
+                timestamp: assessment.timestamp,
+                score_contribution: 0,
+            }
+        })
+        .collect()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: DateTime<Utc>, utilization_rate: f64, reserves: Option<f64>) -> MarketSnapshot {
+        MarketSnapshot {
+            timestamp,
+            base_symbol: "USDC".to_string(),
+            base_price: 1.0,
+            total_supply: 1_000.0,
+            total_borrow: 1_000.0 * utilization_rate,
+            utilization_rate,
+            supply_apr: 0.03,
+            borrow_apr: 0.05,
+            reserves,
+            price_volatility: None,
+            collateral: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_unusual_metrics_are_ranked_against_their_own_history() {
+        let now = Utc::now();
+        let config = BaselineConfig::default();
+        // 90 daily assessments with utilization from 0.60 to 0.80 and reserves from $10m up
+        let days: Vec<DateTime<Utc>> = (1..=90).rev().map(|d| now - Duration::days(d)).collect();
+        let scores: Vec<ScorePoint> = days.iter().map(|&timestamp| ScorePoint { timestamp, risk_score: 30, engine_version: None, category_scores: Default::default() }).collect();
+        let snapshots: Vec<MarketSnapshot> = days
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| snapshot(t, 0.60 + 0.2 * i as f64 / 89.0, Some(10_000_000.0 + 100_000.0 * i as f64)))
+            .collect();
+        let baseline = MarketBaseline::of(&[30, 90], &scores, &snapshots, now);
+        assert_eq!(baseline.windows.iter().map(|w| (w.days, w.assessments)).collect::<Vec<_>>(), [(30, 30), (90, 90)]);
+        let stats = baseline.longest().unwrap().metric(BaselineMetric::Utilization).unwrap();
+        assert!((stats.mean - 0.70).abs() < 1e-9 && (stats.p50 - 0.70).abs() < 1e-9);
+
+        let mut assessment: RiskAssessment = serde_json::from_value(serde_json::json!({
+            "market_name": "USDC",
+            "market_address": "0xc3d688b66703497daa19211eedff47f25384cdc3",
+            "findings": [],
+            "risk_score": 30,
+            "timestamp": now,
+        }))
+        .unwrap();
+        assessment.reserves_usd = Some(9_000_000.0);
+        let comparison = BaselineComparison::compare(baseline.clone(), &assessment, 0.82, &config);
+        let ranks: Vec<(BaselineMetric, bool)> = comparison.metrics.iter().map(|m| (m.metric, m.unusual)).collect();
+        assert_eq!(
+            ranks,
+            [(BaselineMetric::Utilization, true), (BaselineMetric::Reserves, true), (BaselineMetric::RiskScore, false)]
+        );
+        assert_eq!(comparison.metrics[0].summary(), "utilization 0.82 (100th pct of last 90d)");
+        assert_eq!(comparison.metrics[2].summary(), "score 30 (100th pct of last 90d)");
+
+        let findings = check_deviation_from_baseline(&assessment, &comparison, &config);
+        assert_eq!(findings.iter().map(|f| f.category.clone()).collect::<Vec<_>>(), [RiskCategory::HighUtilization, RiskCategory::BadDebt]);
+        assert!(findings[0].description.starts_with("Utilization 0.82 (100th pct of last 90d), above its 95th percentile of 0.79"), "{}", findings[0].description);
+        assert_eq!(findings[1].metadata["percentile_rank"], 0.0);
+
+        // Utilization within its history ranks in between
+        let usual = BaselineComparison::compare(baseline, &assessment, 0.70, &config);
+        assert_eq!(usual.metrics[0].summary(), "utilization 0.70 (50th pct of last 90d)");
+        assert!(!usual.metrics[0].unusual);
+
+        // Two days of history are no baseline
+        let recent = MarketBaseline::of(&[30, 90], &scores[88..], &snapshots[88..], now);
+        let skipped = BaselineComparison::compare(recent, &assessment, 0.82, &config);
+        assert!(skipped.metrics.is_empty());
+        let note = check_deviation_from_baseline(&assessment, &skipped, &config);
+        assert_eq!((note[0].category.clone(), note[0].severity), (RiskCategory::DataQuality, RiskSeverity::Low));
+        assert!(note[0].description.contains("2.0 days of history, fewer than the 14"), "{}", note[0].description);
+    }
+
+    #[test]
+    fn test_ordinals() {
+        let ordinals: Vec<String> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 97, 100].into_iter().map(ordinal).collect();
+        assert_eq!(ordinals, ["1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "97th", "100th"]);
+    }
+}
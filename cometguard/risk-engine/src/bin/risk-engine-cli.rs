@@ -1,16 +1,88 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use futures::StreamExt;
 use risk_engine::{
-    config::Config,
+    absorb::{AbsorbTarget, AbsorptionSimulation},
+    alerts::{Alert, AlertDispatcher, DeliveryStatus},
+    archive::{self, ContentKind, Envelope},
+    audit,
+    cli::render::{self, RenderOptions},
+    compare::{self, AssessmentPoint, FindingChange, MarketMetrics},
+    ens,
+    export,
+    incidents::SelfTest,
+    inventory::DeploymentSource,
+    position_scan::AddressList,
+    projection::{self, LiquidationProjection},
+    ranking::{HealthFactorFilter, PositionSort},
+    registry,
+    trend::{write_trend_csv, Trend},
+    denomination::{Denomination, Units},
+    config::{Config, ConfigFormat, ConfigProblem, DataSource, Network, RpcMode, SettingOverride},
+    error::rpc_host,
+    report::{self, mock_tag},
+    rpc::RecordingClient,
+    AssessmentEvent,
     RiskEngine,
-    utils::{init_logger, format_address},
+    RiskEngineError,
+    telemetry::{self, init_telemetry_to},
+    utils::{self, format_address, format_address_labeled, format_named_address, sanitize_inline},
+    watch::{WatchView, CLEAR_SCREEN},
+    RiskEvent,
 };
-use std::path::PathBuf;
-use std::str::FromStr;
+use risk_engine::models::{ManagerPermissions, Market, UserPosition};
+use risk_engine::{risk::{self, RiskAssessment, RiskCategory, RiskSeverity, SimulationResult}, storage::FindingQuery};
+use risk_engine::diagnostics::CheckStatus;
+use serde::Serialize;
+use serde_json::json;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono_tz::Tz;
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use ethers::providers::{Middleware, Provider};
 use ethers::types::Address;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
-#[derive(Parser)]
+/// `check-user` result: the position, with the base asset the market can pay out, how long
+/// until interest makes it liquidatable and the managers allowed to act for the account (`null`
+/// when the data source cannot tell)
+#[derive(Serialize)]
+struct UserCheck {
+    #[serde(flatten)]
+    position: UserPosition,
+    available_liquidity: f64,
+    time_to_liquidation: LiquidationProjection,
+    managers: Option<ManagerPermissions>,
+}
+
+/// Arguments of `top-borrowers` and `top-suppliers`
+#[derive(Clone, Args)]
+struct TopArgs {
+    /// Address of the Comet proxy (default: the first market)
+    #[arg(short, long, value_parser = parse_market)]
+    market: Option<Address>,
+
+    /// Number of positions to show
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+
+    /// Only rank positions with at least this health factor
+    #[arg(long)]
+    min_hf: Option<f64>,
+
+    /// Only rank positions with at most this health factor
+    #[arg(long)]
+    max_hf: Option<f64>,
+}
+
+#[derive(Clone, Parser)]
 #[command(
     name = "CometGuard Risk Engine",
     about = "Predictive risk management toolkit for Compound V3",
@@ -21,177 +93,2314 @@ struct Cli {
     #[arg(short, long, default_value = "config.json")]
     config: PathBuf,
     
-    /// Log level (error, warn, info, debug, trace)
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+    /// Log level (error, warn, info, debug, trace) or filter directives such as `risk_engine::compound=debug,info` (default: `RUST_LOG`, then `log_level`)
+    #[arg(short, long)]
+    log_level: Option<String>,
+
+    /// Use this profile of the configuration file, over its `default` profile (default: `COMETGUARD_PROFILE`)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Override one setting, e.g. `--set risk.max_utilization_threshold=0.8`; repeatable, applied after every other source
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<SettingOverride>,
+
+    /// Use the bundled deterministic demo dataset instead of chain data
+    #[arg(long)]
+    mock: bool,
+
+    /// Print the work `assess` or `watch` would do (markets, checks, estimated RPC calls and compute units, data sources, alert routes) without reading anything over the network
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Record every RPC request/response to this session file
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Serve RPC requests from a recorded session file instead of the network
+    #[arg(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
+
+    /// Leave positions borrowing less than this many USD, such as 250k, out of per-account findings and listings (default: `risk.min_position_usd`)
+    #[arg(long, value_name = "USD", value_parser = utils::parse_amount)]
+    min_borrow: Option<f64>,
+
+    /// Read every tracked position, even in markets large enough for `risk.sampling` to sample
+    #[arg(long)]
+    exhaustive: bool,
+
+    /// Accept mixed-case addresses that fail their EIP-55 checksum, in arguments and in the configuration file
+    #[arg(long)]
+    lenient_addresses: bool,
+
+    /// Start alerting without first reconciling the borrower index, alert state and history with the chain (`watch`, `serve` and `dashboard`)
+    #[arg(long)]
+    skip_reconcile: bool,
+
+    /// Apply changes to the configuration file without restarting (`watch`, `serve` and `dashboard`)
+    #[cfg(feature = "reload")]
+    #[arg(long)]
+    reload_config: bool,
+
+    /// Print results as a human-readable report or as one JSON document
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
     
     #[command(subcommand)]
     command: Command,
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum Command {
     /// Assess risks for a Compound V3 market
+    #[command(after_help = "Examples:
+  risk-engine-cli assess
+  risk-engine-cli assess --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --fail-on High
+  risk-engine-cli --mock assess --stream --output json
+  risk-engine-cli assess --out monday.json
+  NO_COLOR=1 risk-engine-cli assess")]
     Assess {
         /// Address of the Comet proxy
-        #[arg(short, long)]
-        market: Option<String>,
+        #[arg(short, long, value_parser = parse_market)]
+        market: Option<Address>,
+
+        /// Print findings progressively as each market's checks complete
+        #[arg(long)]
+        stream: bool,
+
+        /// Exit with code 2 if any finding is at or above this severity (e.g. `High`; default: `risk.fail_on`)
+        #[arg(long, value_parser = parse_variant::<RiskSeverity>)]
+        fail_on: Option<RiskSeverity>,
+
+        /// Also exit with code 2 if a check timed out, leaving an assessment partial (default: `timeouts.fail_on_partial`)
+        #[arg(long)]
+        fail_on_partial: bool,
+
+        /// Print the report tables without colors (also when `NO_COLOR` is set or stdout is not a terminal)
+        #[arg(long)]
+        no_color: bool,
+
+        /// Also save the assessments to this file, for `compare` and offline analysis
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
     },
     
+    /// Assess all markets on a schedule, printing only what changed since the previous run
+    #[command(after_help = "Examples:
+  risk-engine-cli watch --interval 5m --fail-on High
+  risk-engine-cli --reload-config watch --redraw
+  risk-engine-cli --mock watch --interval 10s --max-iterations 3")]
+    Watch {
+        /// Time between runs (e.g. `30s`, `5m30s`, `1h`)
+        #[arg(long, default_value = "60s", value_parser = utils::parse_duration)]
+        interval: Duration,
+
+        /// Stop after this many runs instead of at ctrl-c
+        #[arg(long)]
+        max_iterations: Option<u64>,
+
+        /// Flag findings at or above this severity (default: `risk.fail_on`); watch keeps running
+        #[arg(long, value_parser = parse_variant::<RiskSeverity>)]
+        fail_on: Option<RiskSeverity>,
+
+        /// Clear the screen and redraw a summary table above each market's changes
+        #[arg(long)]
+        redraw: bool,
+    },
+
     /// Check a user's position for liquidation risk
+    #[command(after_help = "Examples:
+  risk-engine-cli check-user --user vitalik.eth
+  risk-engine-cli check-user --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --user 0x1234567890abcdef1234567890abcdef12345678")]
     CheckUser {
-        /// Address of the Comet proxy
-        #[arg(short, long)]
-        market: Option<String>,
+        /// Address of the Comet proxy; without it the user's positions in every market are summed
+        #[arg(short, long, value_parser = parse_market)]
+        market: Option<Address>,
         
-        /// Address of the user to check
-        #[arg(short, long)]
+        /// Address or ENS name (e.g. `vitalik.eth`) of the user to check
+        #[arg(short, long, value_parser = parse_account)]
         user: String,
     },
     
     /// Simulate market conditions
+    #[command(after_help = "Examples:
+  risk-engine-cli simulate
+  risk-engine-cli --mock simulate --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --output json
+  risk-engine-cli simulate --out simulation.json
+  risk-engine-cli simulate --absorb-largest
+  risk-engine-cli simulate --absorb 0x1111111111111111111111111111111111111111")]
     Simulate {
         /// Address of the Comet proxy
+        #[arg(short, long, value_parser = parse_market)]
+        market: Option<Address>,
+
+        /// Also absorb this account (address or ENS name) at current prices and show what it
+        /// does to reserves, utilization and the other tracked positions
+        #[arg(long, value_name = "ACCOUNT", value_parser = parse_account, conflicts_with = "absorb_largest")]
+        absorb: Option<String>,
+
+        /// Also absorb the tracked position borrowing the most
+        #[arg(long)]
+        absorb_largest: bool,
+
+        /// Also save the simulation to this file
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+
+    /// Discover borrowers by scanning Comet logs (resumes from the configured index file)
+    DiscoverBorrowers,
+
+    /// Check the positions of the accounts listed in a file for liquidation risk
+    #[command(after_help = "Examples:
+  risk-engine-cli scan-positions --file wallets.txt
+  risk-engine-cli scan-positions --file wallets.csv --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --out scan.json")]
+    ScanPositions {
+        /// Addresses, one per line or in the first column of a CSV file; other lines are reported and skipped
+        #[arg(short, long, value_name = "PATH")]
+        file: PathBuf,
+
+        /// Address of the Comet proxy (default: the first market)
+        #[arg(short, long, value_parser = parse_market)]
+        market: Option<Address>,
+
+        /// Also save the scan to this file
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+
+    /// Largest tracked borrowers of a market, with their collateral mix
+    #[command(after_help = "Examples:
+  risk-engine-cli top-borrowers --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --limit 20
+  risk-engine-cli top-borrowers --max-hf 1.2 --output json")]
+    TopBorrowers(TopArgs),
+
+    /// Largest tracked suppliers of a market (only suppliers in the watchlist or borrower index)
+    #[command(after_help = "Examples:
+  risk-engine-cli top-suppliers --limit 10")]
+    TopSuppliers(TopArgs),
+
+    /// List the markets the engine monitors, and optionally Comets on the chain it does not
+    #[command(after_help = "Examples:
+  risk-engine-cli list-markets
+  risk-engine-cli list-markets --discover --output json")]
+    ListMarkets {
+        /// Also look for Comet deployments that are not monitored (best effort: scans the
+        /// Configurator's events from `scanner.start_block`)
+        #[arg(long)]
+        discover: bool,
+    },
+
+    /// Show the subgraph's indexed block and how far it trails the chain head
+    SubgraphStatus,
+
+    /// Check the RPC endpoint, block lag, cache, storage, alert routes and configuration,
+    /// exiting non-zero if any check fails
+    #[command(after_help = "Examples:
+  risk-engine-cli doctor
+  risk-engine-cli doctor --output json")]
+    Doctor,
+
+    /// Replay the known-incident fixtures compiled into the binary through every check,
+    /// exiting non-zero if an incident is no longer detected; reads no configuration or network
+    #[command(after_help = "Examples:
+  risk-engine-cli selftest
+  risk-engine-cli selftest --output json")]
+    Selftest,
+
+    /// Send a synthetic Critical finding through every configured alert route
+    #[command(after_help = "Examples:
+  risk-engine-cli alert-test
+  risk-engine-cli alert-test --explain-routing --severity High --category OracleReliability
+  risk-engine-cli alert-test --explain-routing --account 0x1234567890abcdef1234567890abcdef12345678")]
+    AlertTest {
+        /// Show which routing rules the synthetic finding would hit instead of sending it
+        #[arg(long)]
+        explain_routing: bool,
+
+        /// Severity of the synthetic finding
+        #[arg(long, default_value = "Critical", value_parser = parse_variant::<RiskSeverity>)]
+        severity: RiskSeverity,
+
+        /// Category of the synthetic finding
+        #[arg(long, default_value = "HighUtilization", value_parser = parse_variant::<RiskCategory>)]
+        category: RiskCategory,
+
+        /// Comet proxy the synthetic finding is about (default: `compound.comet_proxy_address`)
+        #[arg(short, long, value_parser = parse_market)]
+        market: Option<Address>,
+
+        /// Account the synthetic finding is about, as for watchlist findings
+        #[arg(long, value_parser = parse_address)]
+        account: Option<Address>,
+    },
+
+    /// Stop alerting on a finding until it escalates past its current severity
+    #[command(after_help = "Examples:
+  risk-engine-cli ack 3f2a9c0d8e7b6a51")]
+    Ack {
+        /// Fingerprint of the finding, as in alerts and `--output json assess`
+        fingerprint: String,
+    },
+
+    /// Attach a note to a finding in the history database, shown under it in later reports
+    #[command(after_help = "Examples:
+  risk-engine-cli annotate 3f2a9c0d8e7b6a51 --note \"confirmed false positive, feed migrated\" --author alice")]
+    Annotate {
+        /// Fingerprint of the finding, as in alerts, `history --output json` and `--output json assess`
+        fingerprint: String,
+
+        /// The note; it never changes the finding's score or alerts
+        #[arg(long)]
+        note: String,
+
+        /// Who made the note
+        #[arg(long)]
+        author: String,
+    },
+
+    /// Plot risk score and utilization stored in the history database, with its findings
+    #[command(after_help = "Examples:
+  risk-engine-cli history --market USDC --days 7
+  risk-engine-cli history --category HighUtilization --min-severity High
+  risk-engine-cli --output csv history --market 0xc3d688B66703497DAA19211EEdff47f25384cdc3 --days 30 > usdc.csv")]
+    History {
+        /// Market name or Comet proxy address (all stored markets when omitted)
         #[arg(short, long)]
         market: Option<String>,
+
+        /// Number of days to look back
+        #[arg(long, default_value_t = 30)]
+        days: i64,
+
+        /// Only show findings in this category (e.g. `HighUtilization`)
+        #[arg(long, value_parser = parse_variant::<RiskCategory>)]
+        category: Option<RiskCategory>,
+
+        /// Only show findings at or above this severity (e.g. `High`)
+        #[arg(long, value_parser = parse_variant::<RiskSeverity>)]
+        min_severity: Option<RiskSeverity>,
+    },
+
+    /// Diff two saved assessments or two points in time; exits with code 2 if TO is riskier than FROM
+    #[command(after_help = "Examples:
+  risk-engine-cli compare 7d now
+  risk-engine-cli compare monday.json today.json  # offline, from files saved with `assess --out`")]
+    Compare {
+        /// Older side: an `--output json assess` file, `now`, a time (`2024-05-01`, RFC 3339) or an age (`7d`)
+        from: String,
+
+        /// Newer side, in the same forms as FROM
+        to: String,
+    },
+
+    /// Export findings or positions at risk as flat CSV (columns are listed in `risk_engine::export`)
+    #[command(after_help = "Examples:
+  risk-engine-cli export --out findings.csv --days 7
+  risk-engine-cli export --data positions --max-hf 1.2 --out at-risk.csv")]
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// File to write
+        #[arg(long)]
+        out: PathBuf,
+
+        /// What to export
+        #[arg(long, value_enum, default_value_t = ExportData::Findings)]
+        data: ExportData,
+
+        /// Export findings stored over this many days instead of assessing now (needs a `storage` backend)
+        #[arg(long)]
+        days: Option<i64>,
+
+        /// Only export positions below this health factor (default: 1 + `risk.liquidation_threshold_buffer`)
+        #[arg(long)]
+        max_hf: Option<f64>,
+    },
+
+    /// Create, check or print the configuration file given by `--config`
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Inspect audit log files
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+
+    /// Interactive terminal dashboard, refreshed as scheduled assessments complete
+    #[cfg(feature = "tui")]
+    Dashboard {
+        /// Time between runs (e.g. `30s`, `5m30s`, `1h`)
+        #[arg(long, default_value = "60s", value_parser = utils::parse_duration)]
+        interval: Duration,
+
+        /// Append log lines to this file (they are discarded otherwise, as the dashboard owns the terminal)
+        #[arg(long, value_name = "PATH")]
+        log_file: Option<PathBuf>,
+    },
+
+    /// Serve the JSON API while assessing all markets on a schedule
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on (defaults to `server.bind_address`)
+        #[arg(long)]
+        bind: Option<String>,
+
+        /// Seconds between scheduled assessments
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+    },
+
+    /// Print a shell completion script
+    #[command(after_help = "Examples:
+  risk-engine-cli completions bash > /etc/bash_completion.d/risk-engine-cli
+  risk-engine-cli completions zsh > \"${fpath[1]}/_risk-engine-cli\"
+  risk-engine-cli completions fish > ~/.config/fish/completions/risk-engine-cli.fish")]
+    Completions {
+        /// Shell to complete for
+        shell: Shell,
+    },
+}
+
+/// Format of command results on stdout; logs and notices always go to stderr
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable report
+    Text,
+    /// The serde form of the result (one JSON object per line for `assess --stream`)
+    Json,
+    /// Comma-separated rows with a header (`history` only)
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportData {
+    /// One row per finding, metadata flattened into columns
+    Findings,
+    /// One row per tracked borrower at risk of liquidation
+    Positions,
+}
+
+#[derive(Clone, Subcommand)]
+enum ConfigCommand {
+    /// Write a commented default configuration; prompts for the network, market and RPC URL on a terminal
+    Init {
+        /// Network of the market (`mainnet`, `base`, `arbitrum`, `polygon`, `optimism` or `scroll`)
+        #[arg(long, value_parser = parse_variant::<Network>)]
+        network: Option<Network>,
+
+        /// Base asset symbol of the network's market to assess, from the built-in registry (default: USDC)
+        #[arg(long)]
+        market: Option<String>,
+
+        /// RPC endpoint of the network (required for networks other than mainnet without a terminal)
+        #[arg(long)]
+        rpc_url: Option<String>,
+
+        /// File format (`json`, `toml` or `yaml`; default: by the `--config` extension)
+        #[arg(long, value_parser = parse_variant::<ConfigFormat>)]
+        format: Option<ConfigFormat>,
+
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Report every problem in the file at once: invalid values, unknown settings and optionally the RPC endpoint
+    Validate {
+        /// Also check that `compound.rpc_url` answers, for the configured chain
+        #[arg(long)]
+        check_rpc: bool,
+    },
+    /// Print the configuration in effect after defaults, the file and command-line flags, secrets redacted
+    ShowEffective,
+}
+
+#[derive(Clone, Subcommand)]
+enum AuditCommand {
+    /// Check that every line is a well-formed entry and timestamps never go backwards
+    Verify {
+        /// Audit file to check (e.g. `audit/audit-2024-05-01.jsonl`)
+        path: PathBuf,
     },
 }
 
+/// Name the completion scripts complete, i.e. the binary's
+const BIN_NAME: &str = "risk-engine-cli";
+
+/// Set from `--lenient-addresses` before the command line is parsed, as the address value
+/// parsers below run while it is
+static LENIENT_ADDRESSES: AtomicBool = AtomicBool::new(false);
+
+/// Parse `--market` as a Comet proxy address
+fn parse_market(input: &str) -> std::result::Result<Address, String> {
+    checked_address(input, "expected the address of a Comet proxy: 0x followed by 40 hex digits")
+}
+
+fn parse_address(input: &str) -> std::result::Result<Address, String> {
+    checked_address(input, "expected 0x followed by 40 hex digits")
+}
+
+/// Check that `--user` is an address or an ENS name; names are resolved once the engine runs
+fn parse_account(input: &str) -> std::result::Result<String, String> {
+    let input = input.trim();
+    if ens::is_ens_name(input) {
+        return Ok(input.to_string());
+    }
+    checked_address(input, "expected an address (0x followed by 40 hex digits) or an ENS name such as vitalik.eth")?;
+    Ok(input.to_string())
+}
+
+/// `input` as an address, its checksum checked unless `--lenient-addresses` is given; fails
+/// with `expected` when it is no address at all
+fn checked_address(input: &str, expected: &str) -> std::result::Result<Address, String> {
+    let input = input.trim();
+    let address = utils::parse_address(input).map_err(|_| expected.to_string())?;
+    if LENIENT_ADDRESSES.load(Ordering::Relaxed) {
+        return Ok(address);
+    }
+    utils::parse_address_strict(input).map_err(|e| match e {
+        RiskEngineError::Parse { message, .. } => format!("{} (--lenient-addresses accepts it)", message),
+        e => e.to_string(),
+    })
+}
+
+/// Parse a finding category or severity by its variant name
+fn parse_variant<T: serde::de::DeserializeOwned>(input: &str) -> std::result::Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(input.to_string())).map_err(|e| e.to_string())
+}
+
+/// Exit code of an `assess` run that found something at or above the `--fail-on` severity
+const EXIT_FINDINGS: u8 = 2;
+
+/// Exit code used when the engine could not do its job (bad arguments or config, RPC or storage failure)
+const EXIT_ERROR: u8 = 3;
+
+/// Exit code used when the command was interrupted with ctrl-c
+const EXIT_CANCELLED: u8 = 130;
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Parse command line arguments
-    let cli = Cli::parse();
-    
-    // Initialize logger
-    init_logger(&cli.log_level)?;
-    
-    // Load configuration
-    let config = if cli.config.exists() {
-        info!("Loading configuration from {:?}", cli.config);
-        Config::from_file(&cli.config)?
+async fn main() -> ExitCode {
+    // Parse command line arguments; usage errors exit with EXIT_ERROR rather than clap's 2
+    LENIENT_ADDRESSES.store(std::env::args().any(|arg| arg == "--lenient-addresses"), Ordering::Relaxed);
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { EXIT_ERROR.into() } else { 0 })
+    });
+
+    let result = execute(cli).await;
+    if result.as_ref().is_err_and(is_cancelled) {
+        eprintln!("Interrupted; partial progress has been saved");
+    } else if let Err(e) = &result {
+        eprintln!("Error: {:?}", e);
+    }
+    ExitCode::from(exit_code(&result))
+}
+
+/// Process exit code for the outcome of `execute` or `run`
+fn exit_code(result: &Result<u8>) -> u8 {
+    match result {
+        Ok(code) => *code,
+        Err(e) if is_cancelled(e) => EXIT_CANCELLED,
+        Err(_) => EXIT_ERROR,
+    }
+}
+
+fn is_cancelled(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<RiskEngineError>().is_some_and(RiskEngineError::is_cancelled)
+}
+
+/// Set up logging and the engine, then run the command; returns the exit code
+async fn execute(cli: Cli) -> Result<u8> {
+    if let Command::Completions { shell } = &cli.command {
+        clap_complete::generate(*shell, &mut Cli::command(), BIN_NAME, &mut std::io::stdout());
+        return Ok(0);
+    }
+
+    if cli.output == OutputFormat::Csv && !matches!(cli.command, Command::History { .. }) {
+        anyhow::bail!("--output csv is only supported by `history`; `export` writes findings and positions as CSV");
+    }
+
+    // These work on the file itself, so they must not fail on a broken one before reporting it
+    if let Command::Config { command } = &cli.command {
+        return config_command(&cli, command).await;
+    }
+
+    // Checks the build rather than a deployment, so the configuration is not even read
+    if let Command::Selftest = &cli.command {
+        return selftest(cli.output).await;
+    }
+
+    // Load configuration; it holds the trace export settings, so logging starts afterwards
+    let config_found = cli.config.exists();
+    let config = load_config(&cli, std::env::vars())?;
+    // Report every problem before trace export or the engine connect to anything
+    config.validate()?;
+
+    // Initialize logging and trace export; the guard flushes exported spans and the log file
+    // writer on exit, so it lives until the end of this function
+    let directives = cli
+        .log_level
+        .clone()
+        .or_else(|| std::env::var(telemetry::LOG_ENV).ok().filter(|value| !value.trim().is_empty()))
+        .unwrap_or_else(|| config.log_level.clone());
+    let telemetry = init_telemetry_to(&directives, &config, log_writer(&cli.command)?)?;
+    if config_found {
+        info!("Loaded configuration from {:?}", cli.config);
     } else {
         warn!("Configuration file not found at {:?}, using default config", cli.config);
-        Config::default()
-    };
+    }
+    for setting in &cli.set {
+        let value = config.setting(&setting.path).unwrap_or_default();
+        debug!("Setting `{}` overridden on the command line: {}", setting.path, value);
+    }
     
+    if cli.dry_run {
+        return dry_run(config, &cli.command, cli.output).await;
+    }
+
     // Create risk engine
-    let engine = RiskEngine::new(config).await?;
+    let engine = Arc::new(RiskEngine::new(config).await?);
     
-    // Execute command
-    match cli.command {
-        Command::Assess { market } => {
-            // Get all markets
-            let markets = engine.assess_risks().await?;
-            
-            // Filter by market address if provided
-            let markets = if let Some(market_addr) = market {
-                let market_addr = Address::from_str(&market_addr)?;
-                markets.into_iter()
-                    .filter(|m| m.market_address == market_addr)
-                    .collect::<Vec<_>>()
-            } else {
-                markets
-            };
-            
-            // Output results
-            println!("\n=== RISK ASSESSMENT REPORT ===");
-            for assessment in &markets {
-                println!("\nMarket: {} ({})", 
-                    assessment.market_name, 
-                    format_address(&assessment.market_address)
-                );
-                println!("Risk Score: {}/100", assessment.risk_score);
-                
-                if assessment.findings.is_empty() {
-                    println!("✅ No risks identified");
-                } else {
-                    println!("\nRisks Identified:");
-                    for (i, finding) in assessment.findings.iter().enumerate() {
-                        println!("{}. [{}] {}", 
-                            i + 1,
-                            format!("{:?}", finding.severity),
-                            finding.description
-                        );
+    if engine.config().metrics.bind_address.is_some() {
+        let addr = engine.start_metrics_exporter()?;
+        info!("Prometheus metrics available at http://{}/metrics", addr);
+    }
+
+    #[cfg(feature = "reload")]
+    if cli.reload_config {
+        start_config_watcher(&cli, &engine)?;
+    }
+    
+    // Cancel in-flight work on ctrl-c; interrupted operations persist partial state first
+    let cancel = engine.cancellation_token();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("Interrupt received, cancelling...");
+            cancel.cancel();
+        }
+    });
+    
+    let progress = reports_progress(&cli.command).then(|| ProgressDisplay::start(&engine));
+    let result = run(cli.command, &engine, cli.output).await;
+    // Clears the bar before the outcome is reported, also after ctrl-c
+    drop(progress);
+    engine.shutdown().await;
+    // Flush exported spans and log file lines before reporting the outcome
+    drop(telemetry);
+    result
+}
+
+/// Whether `command` shows the progress of borrower scans and position fetches; commands that
+/// run on a schedule or own the terminal do not
+fn reports_progress(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::DiscoverBorrowers
+            | Command::ListMarkets { discover: true }
+            | Command::ScanPositions { .. }
+            | Command::TopBorrowers(_)
+            | Command::TopSuppliers(_)
+            | Command::Assess { stream: false, .. }
+            | Command::Compare { .. }
+            | Command::Export { .. }
+    )
+}
+
+/// The engine's progress on stderr: a bar with ETA on a terminal, otherwise a log line
+/// every 10%
+struct ProgressDisplay {
+    bar: Arc<Mutex<Option<ProgressBar>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ProgressDisplay {
+    fn start(engine: &RiskEngine) -> Self {
+        let terminal = std::io::stderr().is_terminal();
+        let mut updates = engine.progress();
+        let bar = Arc::new(Mutex::new(None::<ProgressBar>));
+        let shown = bar.clone();
+        let task = tokio::spawn(async move {
+            let mut logged = None;
+            while updates.changed().await.is_ok() {
+                let Some(progress) = *updates.borrow_and_update() else {
+                    if let Some(bar) = shown.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                        bar.finish_and_clear();
+                    }
+                    logged = None;
+                    continue;
+                };
+                if !terminal {
+                    let step = (progress.fraction() * 10.0).floor() as u64 * 10;
+                    if logged != Some((progress.stage, step)) {
+                        logged = Some((progress.stage, step));
+                        let rate = progress.per_second.map(|rate| format!(", {} {}/sec", rate, progress.stage.unit())).unwrap_or_default();
+                        info!("{}: {}% ({}/{}{})", progress.stage.label(), step, progress.done, progress.total, rate);
                     }
+                    continue;
                 }
+                let mut current = shown.lock().unwrap_or_else(|e| e.into_inner());
+                let bar = current.get_or_insert_with(|| {
+                    let style = ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} ({per_sec}, ETA {eta})")
+                        .expect("valid progress template")
+                        .progress_chars("=> ");
+                    ProgressBar::new(progress.total).with_style(style)
+                });
+                if bar.length() != Some(progress.total) || bar.message() != progress.stage.label() {
+                    bar.reset();
+                    bar.set_length(progress.total);
+                    bar.set_message(progress.stage.label());
+                }
+                bar.set_position(progress.done);
             }
-        },
-        
-        Command::CheckUser { market, user } => {
-            // Parse user address
-            let user_address = Address::from_str(&user)?;
-            
-            // Get markets
-            let markets = engine.assess_risks().await?;
-            
-            // Filter by market address if provided
-            let markets = if let Some(market_addr) = market {
-                let market_addr = Address::from_str(&market_addr)?;
-                markets.into_iter()
-                    .filter(|m| m.market_address == market_addr)
-                    .collect::<Vec<_>>()
-            } else {
-                markets
+        });
+        Self { bar, task }
+    }
+}
+
+impl Drop for ProgressDisplay {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Some(bar) = self.bar.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// Configuration in effect: defaults, the `--config` file with its selected profile,
+/// `COMETGUARD__…` variables in `vars`, command-line flags, then `--set` overrides, each taking
+/// precedence over the previous
+fn load_config(cli: &Cli, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config> {
+    let mut config = if cli.config.exists() {
+        Config::load_profile(&cli.config, cli.profile.as_deref(), vars)?
+    } else if let Some(profile) = &cli.profile {
+        anyhow::bail!("profile `{}` needs a configuration file, and {} does not exist", profile, cli.config.display());
+    } else {
+        Config::with_env(None, vars)?
+    };
+    apply_flags(cli, &mut config);
+    Ok(config.with_overrides(&cli.set)?)
+}
+
+/// Reload the `--config` file into `engine` whenever it changes, with the environment, flags and `--set` on top
+#[cfg(feature = "reload")]
+fn start_config_watcher(cli: &Cli, engine: &Arc<RiskEngine>) -> Result<()> {
+    if !cli.config.exists() {
+        anyhow::bail!("--reload-config needs a configuration file, and {} does not exist", cli.config.display());
+    }
+    let flags = cli.clone();
+    engine.start_config_watcher(&cli.config, move || {
+        let mut config = Config::load_profile(&flags.config, flags.profile.as_deref(), std::env::vars())?;
+        apply_flags(&flags, &mut config);
+        config.with_overrides(&flags.set)
+    })?;
+    info!("Reloading {:?} when it changes", cli.config);
+    Ok(())
+}
+
+/// Apply the command-line flags that override the config file
+fn apply_flags(cli: &Cli, config: &mut Config) {
+    if cli.mock {
+        config.data_source = DataSource::Mock;
+    }
+
+    // Command-line record/replay flags take precedence over the config file
+    if let Some(path) = &cli.record {
+        config.rpc.mode = RpcMode::Record;
+        config.rpc.session_path = Some(path.clone());
+    } else if let Some(path) = &cli.replay {
+        config.rpc.mode = RpcMode::Replay;
+        config.rpc.session_path = Some(path.clone());
+    }
+
+    if let Some(min) = cli.min_borrow {
+        config.risk.min_position_usd = Some(min);
+    }
+    if cli.exhaustive {
+        config.risk.sampling.enabled = false;
+    }
+    if cli.lenient_addresses {
+        config.lenient_addresses = true;
+    }
+    if cli.skip_reconcile {
+        config.reconcile.enabled = false;
+    }
+}
+
+/// `config init`, `config validate` and `config show-effective` on the `--config` file
+async fn config_command(cli: &Cli, command: &ConfigCommand) -> Result<u8> {
+    let path = &cli.config;
+    let text = cli.output == OutputFormat::Text;
+    match command {
+        ConfigCommand::Init { network, market, rpc_url, format, force } => {
+            // `--format` picks the extension, so `--format toml` writes `config.toml` by default
+            let (format, path) = match format {
+                Some(format) if *format != ConfigFormat::from_path(path) => (*format, path.with_extension(format.extension())),
+                _ => (ConfigFormat::from_path(path), path.clone()),
             };
-            
-            if markets.is_empty() {
-                println!("No matching markets found");
-                return Ok(());
+            let path = &path;
+            if path.exists() && !force {
+                anyhow::bail!("{} already exists; pass --force to overwrite it", path.display());
             }
-            
-            // For milestone 1, we'll just use the first market
-            let market = &markets[0];
-            println!("\n=== USER POSITION CHECK ===");
-            println!("Market: {} ({})", 
-                market.market_name, 
-                format_address(&market.market_address)
-            );
-            println!("User: {}", format_address(&user_address));
-            
-            // This part would connect to the market and get the user's position
-            // For milestone 1, we'll just show a mock user position
-            println!("\nMock User Position (for Milestone 1):");
-            println!("Base Balance: 1,000.00 USDC");
-            println!("Collateral: 0.5 ETH (worth approximately $1,000)");
-            println!("Health Factor: 2.0");
-            println!("\nPosition Status: ✅ Healthy");
-        },
-        
-        Command::Simulate { market } => {
-            // Get all markets
-            let markets = engine.assess_risks().await?;
-            
-            // Filter by market address if provided
-            let markets = if let Some(market_addr) = market {
-                let market_addr = Address::from_str(&market_addr)?;
-                markets.into_iter()
-                    .filter(|m| m.market_address == market_addr)
-                    .collect::<Vec<_>>()
-            } else {
-                markets
+            let interactive = std::io::stdin().is_terminal();
+            let network = match network {
+                Some(network) => *network,
+                None if interactive => {
+                    let names: Vec<&str> = Network::ALL.iter().map(|n| n.name()).collect();
+                    let answer = prompt(&format!("Network ({})", names.join(", ")), "mainnet")?;
+                    parse_variant(&answer).map_err(|_| anyhow::anyhow!("unknown network `{}`", answer))?
+                }
+                None => Network::Mainnet,
             };
-            
-            if markets.is_empty() {
-                println!("No matching markets found");
-                return Ok(());
+            let symbols: Vec<&str> = registry::known_markets(network.chain_id()).map(|known| known.base_symbol).collect();
+            let market = match market {
+                Some(symbol) => symbol.clone(),
+                None if interactive && symbols.len() > 1 => prompt(&format!("Market ({})", symbols.join(", ")), symbols[0])?,
+                None => symbols[0].to_string(),
+            };
+            let known = registry::resolve(&format!("{}:{}", network.name(), market))?;
+            let default_rpc_url = (network == Network::Mainnet).then(|| Config::default().compound.rpc_url);
+            let rpc_url = match (rpc_url, default_rpc_url) {
+                (Some(url), _) => url.clone(),
+                (None, default) if interactive => prompt("RPC URL", default.as_deref().unwrap_or(""))?,
+                (None, Some(default)) => default,
+                (None, None) => anyhow::bail!("pass --rpc-url with an endpoint for {:?}", network),
+            };
+
+            let config = Config { compound: known.compound_config(rpc_url), ..Config::default() };
+            config.validate()?;
+            std::fs::write(path, config.to_commented(format)?).map_err(|e| RiskEngineError::io(path, e))?;
+            if text {
+                println!("✅ Wrote a {:?} {} configuration to {}", network, known.name, path.display());
+                println!("Check it after editing with `risk-engine-cli --config {} config validate`", path.display());
+            } else {
+                print_json(&json!({ "path": path, "network": network, "market": known.shorthand() }))?;
             }
-            
-            // For milestone 1, we'll just use the first market
-            println!("\n=== MARKET SIMULATION ===");
-            println!("Market: {} ({})", 
-                markets[0].market_name, 
-                format_address(&markets[0].market_address)
-            );
-            
-            // This would run a real simulation in later milestones
-            // For milestone 1, we'll just show some basic information
-            println!("\nSimulation Results (for Milestone 1):");
-            println!("- If utilization increases by 10%, risk score would increase by 15 points");
-            println!("- If largest collateral price drops by 20%, 5% of positions would be liquidated");
-            println!("- Stress test shows current market can handle up to 25% price drop before cascade");
-        },
+        }
+
+        ConfigCommand::Validate { check_rpc } => {
+            let (config, unknown) = Config::load_with_unknown(path, cli.profile.as_deref(), std::env::vars())?;
+            let mut problems = config.problems();
+            problems.extend(unknown);
+            match config.resolve_env() {
+                Ok(resolved) if *check_rpc => problems.extend(rpc_problem(&resolved).await),
+                Ok(_) => {}
+                Err(RiskEngineError::InvalidConfig { problems: unset }) => problems.extend(unset),
+                Err(e) => return Err(e.into()),
+            }
+
+            if text {
+                for problem in &problems {
+                    println!("❌ {}", problem);
+                }
+            } else {
+                print_json(&json!({ "path": path, "problems": problems }))?;
+            }
+            if !problems.is_empty() {
+                anyhow::bail!("{} problem(s) in {}", problems.len(), path.display());
+            }
+            if text {
+                println!("✅ {} is valid", path.display());
+            }
+        }
+
+        ConfigCommand::ShowEffective => {
+            if !path.exists() {
+                eprintln!("{} not found; showing the defaults", path.display());
+            }
+            let config = load_config(cli, std::env::vars())?;
+            print_json(&config.redacted())?;
+        }
     }
-    
-    println!("\n");
-    Ok(())
-} 
\ No newline at end of file
+    Ok(0)
+}
+
+/// Ask on stderr and read one line from stdin; an empty answer picks `default`
+fn prompt(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        eprint!("{}: ", question);
+    } else {
+        eprint!("{} [{}]: ", question, default);
+    }
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+}
+
+/// Problem with `compound.rpc_url` if it does not answer, or answers for another chain
+async fn rpc_problem(config: &Config) -> Option<ConfigProblem> {
+    let rpc_url = &config.compound.rpc_url;
+    let host = rpc_host(rpc_url);
+    let problem = |message: String| Some(ConfigProblem { field: "compound.rpc_url".to_string(), message });
+    let client = match RecordingClient::live(rpc_url) {
+        Ok(client) => client,
+        Err(e) => return problem(e.to_string()),
+    };
+    let timeout = Duration::from_secs(config.performance.timeout_seconds);
+    match tokio::time::timeout(timeout, Provider::new(client).get_chainid()).await {
+        Err(_) => problem(format!("{} did not answer within {}s", host, timeout.as_secs())),
+        // Transport errors may quote the full URL, which often embeds an API key
+        Ok(Err(e)) => problem(format!("{} is unreachable: {}", host, e.to_string().replace(rpc_url.as_str(), &host))),
+        Ok(Ok(chain_id)) if chain_id.as_u64() != config.compound.chain_id => problem(format!(
+            "{} serves chain {}, not the configured chain {}",
+            host, chain_id, config.compound.chain_id
+        )),
+        Ok(Ok(_)) => None,
+    }
+}
+
+/// Where log lines go: stderr, except while the dashboard owns the terminal
+fn log_writer(command: &Command) -> Result<BoxMakeWriter> {
+    #[cfg(feature = "tui")]
+    if let Command::Dashboard { log_file, .. } = command {
+        return Ok(match log_file {
+            Some(path) => {
+                let file = std::fs::OpenOptions::new().create(true).append(true).open(path)
+                    .map_err(|e| RiskEngineError::io(path, e))?;
+                BoxMakeWriter::new(std::sync::Mutex::new(file))
+            }
+            None => BoxMakeWriter::new(std::io::sink),
+        });
+    }
+    let _ = command;
+    Ok(BoxMakeWriter::new(std::io::stderr))
+}
+
+/// Print the plan of the assessment run `command` would start, from an engine that opens
+/// nothing over the network
+async fn dry_run(config: Config, command: &Command, output: OutputFormat) -> Result<u8> {
+    let market = match command {
+        Command::Assess { market, .. } => *market,
+        Command::Watch { .. } => None,
+        _ => anyhow::bail!("--dry-run plans assessment runs; use it with `assess` or `watch`"),
+    };
+    let engine = RiskEngine::builder().config(config).offline().build().await?;
+    let mut plan = engine.plan().await?;
+    if let Some(market) = market {
+        plan.markets.retain(|planned| planned.market_address == market);
+    }
+    match output {
+        OutputFormat::Text => println!("{}", plan),
+        _ => print_json(&plan)?,
+    }
+    Ok(0)
+}
+
+/// Replay the bundled incidents, printing each with whether its findings still appear
+async fn selftest(output: OutputFormat) -> Result<u8> {
+    let report = SelfTest::run().await?;
+    let code = if report.passed() { 0 } else { EXIT_ERROR };
+    if output != OutputFormat::Text {
+        print_json(&report)?;
+        return Ok(code);
+    }
+    println!("\n=== SELF-TEST ({}) ===", report.engine_version);
+    for outcome in &report.incidents {
+        let icon = if outcome.passed() { "✅" } else { "❌" };
+        println!("{} {}", icon, outcome);
+    }
+    match report.failures().count() {
+        0 => println!("\nAll {} incidents detected", report.incidents.len()),
+        failed => println!("\n{} of {} incident(s) not detected", failed, report.incidents.len()),
+    }
+    Ok(code)
+}
+
+/// Banner printed above every report built from mock data
+const MOCK_BANNER: &str = "*** MOCK DATA: demo dataset, not on-chain state ***";
+
+/// Execute a single command against the engine, printing its result in `output` format
+///
+/// Returns the exit code: 0, `EXIT_FINDINGS` when `assess` hits its `--fail-on` severity, or
+/// `EXIT_ERROR` when a `doctor` check fails.
+async fn run(command: Command, engine: &Arc<RiskEngine>, output: OutputFormat) -> Result<u8> {
+    let text = output == OutputFormat::Text;
+    if engine.is_mock_data().await {
+        // JSON documents carry `mock_data` instead
+        if text {
+            println!("\n{}", MOCK_BANNER);
+        } else {
+            eprintln!("{}", MOCK_BANNER);
+        }
+    }
+
+    let mut code = 0;
+    match command {
+        Command::Assess { market, stream: true, fail_on, fail_on_partial, out, .. } => {
+            let wanted = |addr: &Address| market.is_none_or(|m| m == *addr);
+
+            if text {
+                println!("\n=== RISK ASSESSMENT REPORT (streaming) ===");
+            }
+            let mut completed = Vec::new();
+            let mut failed = 0;
+            let mut events = Box::pin(engine.assess_risks_stream());
+            while let Some(event) = events.next().await {
+                let relevant = match &event {
+                    AssessmentEvent::MarketStarted { market_address, .. }
+                    | AssessmentEvent::Finding { market_address, .. } => wanted(market_address),
+                    AssessmentEvent::MarketCompleted(assessment) => wanted(&assessment.market_address),
+                    AssessmentEvent::Error(error) => wanted(&error.market_address) || error.market_address.is_zero(),
+                };
+                if !relevant {
+                    continue;
+                }
+                match &event {
+                    AssessmentEvent::MarketCompleted(assessment) => completed.push(assessment.as_ref().clone()),
+                    AssessmentEvent::Error(_) => failed += 1,
+                    _ => {}
+                }
+                if !text {
+                    println!("{}", serde_json::to_string(&event)?);
+                    continue;
+                }
+                match event {
+                    AssessmentEvent::MarketStarted { market_name, market_address } => {
+                        println!("\nAssessing {}...", format_named_address(&market_name, &market_address));
+                    }
+                    AssessmentEvent::Finding { finding, .. } => {
+                        println!("  {}", finding);
+                    }
+                    AssessmentEvent::MarketCompleted(assessment) => {
+                        println!("Market: {}", assessment);
+                        if let Some(partial) = assessment.rpc.as_ref().and_then(|rpc| rpc.degradation()) {
+                            println!("  Partial within the RPC budget: {}", partial);
+                        }
+                        if let Some(timed_out) = assessment.timed_out() {
+                            println!("  Partial, timed out: {}", timed_out);
+                        }
+                        if let Some(degraded) = assessment.data_degradation() {
+                            println!("  Degraded data, findings at low confidence: {}", degraded);
+                        }
+                        if let Some(baseline) = assessment.baseline.as_ref().and_then(|baseline| baseline.summary()) {
+                            println!("  Against its history: {}", baseline);
+                        }
+                    }
+                    AssessmentEvent::Error(error) => {
+                        println!("❌ Failed to assess {}: {}", error.market_name, error.error);
+                    }
+                }
+            }
+            log_slowest_checks(&completed);
+            if failed > 0 {
+                anyhow::bail!("{} market assessment(s) failed", failed);
+            }
+            if text {
+                print!("\n{}", report::summary_section(&engine.summarize(&completed), &engine.units()));
+            }
+            if let Some(path) = &out {
+                save_results(engine, path, ContentKind::Assessments, &completed).await?;
+            }
+            let config = engine.config();
+            code = fail_on_exit_code(&completed, fail_on.or(config.risk.fail_on), fail_on_partial || config.timeouts.fail_on_partial);
+        },
+
+        Command::Assess { market, stream: false, fail_on, fail_on_partial, no_color, out } => {
+            let markets = assessments(engine, market).await?;
+            let config = engine.config();
+            code = fail_on_exit_code(&markets, fail_on.or(config.risk.fail_on), fail_on_partial || config.timeouts.fail_on_partial);
+            if let Some(path) = &out {
+                save_results(engine, path, ContentKind::Assessments, &markets).await?;
+            }
+            if !text {
+                print_json(&markets)?;
+                return Ok(code);
+            }
+
+            let summary = engine.summarize(&markets);
+            // Utilization and TVL for the summary table, from the provider's market data
+            let state = engine.provider().get_markets().await?;
+            let units = Units::new(&config, &state);
+            let points: Vec<AssessmentPoint> = markets
+                .into_iter()
+                .map(|assessment| {
+                    let metrics = state.iter().find(|m| m.comet_address == assessment.market_address).map(MarketMetrics::from);
+                    AssessmentPoint { assessment, metrics }
+                })
+                .collect();
+            println!("\n=== RISK ASSESSMENT REPORT ===\n");
+            println!("{}", report::summary_section(&summary, &units));
+            print!("{}", render::assessment_report(&points, RenderOptions::detect(no_color).with_numbers(config.display.number_style).with_units(&units)));
+        },
+        
+        Command::Watch { interval, max_iterations, fail_on, redraw } => {
+            if text {
+                println!("\n=== WATCH (every {:?}, ctrl-c to stop) ===", interval);
+            }
+            let mut view = WatchView::new(fail_on.or(engine.config().risk.fail_on)).with_timezone(engine.config().display.tz());
+            let mut show = |event: RiskEvent| -> Result<()> {
+                if !text {
+                    println!("{}", serde_json::to_string(&event)?);
+                } else if let Some(block) = view.observe(&event) {
+                    if redraw {
+                        println!("{}{}", CLEAR_SCREEN, view.summary_table());
+                    }
+                    print!("\n{}", block);
+                }
+                std::io::stdout().flush()?;
+                Ok(())
+            };
+
+            // Subscribe before the first run so none of its events are missed
+            let mut events = engine.subscribe();
+            let scheduler = engine.run_scheduled_times(interval, max_iterations, engine.cancellation_token());
+            tokio::pin!(scheduler);
+            loop {
+                tokio::select! {
+                    result = &mut scheduler => {
+                        result?;
+                        break;
+                    }
+                    received = events.recv() => match received {
+                        Ok(event) => show(event)?,
+                        Err(RecvError::Lagged(skipped)) => warn!("Watch output fell behind and skipped {} event(s)", skipped),
+                        Err(RecvError::Closed) => break,
+                    },
+                }
+            }
+            // Events of the last run may still be queued
+            loop {
+                match events.try_recv() {
+                    Ok(event) => show(event)?,
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+        },
+
+        Command::CheckUser { market: None, user } => {
+            let user_address = engine.resolve_account(&user).await?;
+            let exposure = engine.get_account_exposure(user_address).await?;
+            if !text {
+                print_json(&exposure)?;
+                return Ok(0);
+            }
+
+            println!("\n=== USER EXPOSURE ===");
+            match &exposure.account_name {
+                Some(name) => println!("User: {} ({})", sanitize_inline(name), format_address(&user_address)),
+                None => println!("User: {}", format_address_labeled(&user_address)),
+            }
+            if exposure.markets.is_empty() {
+                println!("\nNo positions in any market");
+            } else {
+                println!("\nPositions:");
+                for market in &exposure.markets {
+                    let position = &market.position;
+                    let health = if position.total_borrow_value > 0.0 { format!("{:.2}", position.health_factor) } else { "-".to_string() };
+                    println!("  {}: borrow ${:.2}, collateral ${:.2}, health factor {}, borrow capacity ${:.2}, available liquidity {:.2}",
+                        format_named_address(&market.market_name, &market.market_address),
+                        position.total_borrow_value,
+                        position.total_collateral_value,
+                        health,
+                        position.borrow_capacity_usd,
+                        market.available_liquidity
+                    );
+                }
+            }
+            for failed in &exposure.failed {
+                println!("  ❌ {}: {}", format_named_address(&failed.market_name, &failed.market_address), failed.error);
+            }
+
+            println!("\nTotal Collateral Value: ${:.2}", exposure.total_collateral_usd);
+            println!("Total Borrow Value: ${:.2} ({} of all markets' borrow)",
+                exposure.total_borrow_usd,
+                utils::format_percentage(exposure.borrow_share)
+            );
+            if let Some(health_factor) = exposure.min_health_factor {
+                println!("Lowest Health Factor: {:.2}", health_factor);
+            }
+            if exposure.is_concentrated() {
+                println!("\n⚠️ Borrows more than {} of all markets' borrow", utils::format_percentage(exposure.max_borrow_share));
+            }
+        },
+
+        Command::CheckUser { market: Some(market), user } => {
+            let user_address = engine.resolve_account(&user).await?;
+            let Some((market, position)) = user_position(engine, Some(market), user_address).await? else {
+                eprintln!("No matching markets found");
+                if !text {
+                    print_json(&serde_json::Value::Null)?;
+                }
+                return Ok(0);
+            };
+            let projection = projection::project(&position, &market, &engine.config().risk.projection);
+            let managers = manager_permissions(engine, &market, user_address).await;
+            if !text {
+                let available_liquidity = market.available_liquidity();
+                print_json(&UserCheck { position, available_liquidity, time_to_liquidation: projection, managers })?;
+                return Ok(0);
+            }
+
+            println!("\n=== USER POSITION CHECK ===");
+            println!("Market: {}{}",
+                format_named_address(&market.name, &market.comet_address),
+                mock_tag(engine.is_mock_data().await)
+            );
+            match engine.account_name(user_address).await {
+                Some(name) => println!("User: {} ({})", sanitize_inline(&name), format_address(&user_address)),
+                None => println!("User: {}", format_address_labeled(&user_address)),
+            }
+            
+            let numbers = engine.config().display.number_style;
+            let unit = Units::new(&engine.config(), std::slice::from_ref(&market)).of(&market.comet_address);
+            // Separators localized before the symbol is added, as symbols such as USDC.e have dots
+            let base_balance = utils::format_token_amount(position.base_balance, "", market.base_asset.decimals);
+            println!("\nBase Balance: {} {}", numbers.localize(base_balance.trim_end()), market.base_asset.symbol);
+            println!("Collateral Value: {}", unit.format_exact(position.total_collateral_value, numbers));
+            println!("Borrow Value: {}", unit.format_exact(position.total_borrow_value, numbers));
+            println!("Health Factor: {}", numbers.localize(&format!("{:.2}", position.health_factor)));
+            let capacity = match (position.remaining_borrow_headroom_pct, position.borrow_overage(&market)) {
+                (_, Some(overage)) => format!("none, {} over the borrow limit", utils::format_money_with(overage, "$", numbers)),
+                (None, None) => "none, no collateral to borrow against".to_string(),
+                (Some(headroom), None) => format!(
+                    "{} ({}% of the borrow limit left)",
+                    utils::format_money_with(position.borrow_capacity_usd, "$", numbers),
+                    numbers.localize(&format!("{:.2}", headroom))
+                ),
+            };
+            println!("Borrow Capacity: {}", capacity);
+            let liquidity = utils::format_token_amount(market.available_liquidity(), "", market.base_asset.decimals);
+            println!("Available Liquidity: {} {}", numbers.localize(liquidity.trim_end()), market.base_asset.symbol);
+
+            let buffer = 1.0 + engine.config().risk.liquidation_threshold_buffer;
+            let status = if position.health_factor < 1.0 {
+                "❌ Liquidatable"
+            } else if position.health_factor < buffer {
+                "⚠️ At risk"
+            } else {
+                "✅ Healthy"
+            };
+            println!("\nPosition Status: {}", status);
+
+            let days = |days: Option<u64>| match days {
+                Some(0) => "now".to_string(),
+                Some(days) => format!("{} days ({})", days, (chrono::Utc::now() + chrono::Duration::days(days as i64)).format("%Y-%m-%d")),
+                None => "never".to_string(),
+            };
+            println!("Time to liquidation at current rates ({} APR): {}",
+                utils::format_percentage(projection.borrow_apr),
+                days(projection.days_at_static_prices)
+            );
+            println!("  with collateral prices {:+.0}% a year: {}",
+                projection.collateral_price_drift * 100.0,
+                days(projection.days_with_price_drift)
+            );
+
+            if let Some(permissions) = managers {
+                let trusted = engine.config().trusted_manager_addresses();
+                let partial = if permissions.complete { "" } else { " (candidates only: Approval logs unavailable)" };
+                if permissions.managers.is_empty() {
+                    println!("\nAllowed Managers: none{}", partial);
+                } else {
+                    println!("\nAllowed Managers{}:", partial);
+                    for manager in &permissions.managers {
+                        let status = if trusted.contains(manager) { "trusted" } else { "⚠️ not trusted" };
+                        println!("  {} ({})", format_address_labeled(manager), status);
+                    }
+                }
+            }
+        },
+        
+        Command::Simulate { market, absorb, absorb_largest, out } => {
+            let target = match absorb {
+                Some(account) => Some(AbsorbTarget::Account(engine.resolve_account(&account).await?)),
+                None => absorb_largest.then_some(AbsorbTarget::Largest),
+            };
+            let Some(simulation) = simulation(engine, market, target).await? else {
+                eprintln!("No matching markets found");
+                if !text {
+                    print_json(&serde_json::Value::Null)?;
+                }
+                return Ok(0);
+            };
+            if let Some(path) = &out {
+                save_results(engine, path, ContentKind::Simulation, &simulation).await?;
+            }
+            if !text {
+                print_json(&simulation)?;
+                return Ok(0);
+            }
+
+            println!("\n=== MARKET SIMULATION ===");
+            println!("Market: {}{}",
+                format_named_address(&simulation.market_name, &simulation.market_address),
+                mock_tag(simulation.mock_data)
+            );
+            
+            // This would run a real simulation in later milestones
+            // For milestone 1, we'll just show some basic information
+            println!("\nSimulation Results (for Milestone 1):");
+            println!("- If utilization increases by 10%, risk score would increase by 15 points");
+            println!("- If largest collateral price drops by 20%, 5% of positions would be liquidated");
+            println!("- Stress test shows current market can handle up to 25% price drop before cascade");
+            for finding in &simulation.findings {
+                println!("- {}", finding);
+            }
+            if let Some(absorption) = &simulation.absorption {
+                print_absorption(absorption);
+            }
+        },
+        
+        Command::DiscoverBorrowers => {
+            let index = engine.discover_borrowers().await?;
+            if !text {
+                print_json(&index)?;
+                return Ok(0);
+            }
+            println!("\n=== BORROWER DISCOVERY ===");
+            println!("Market: {}", format_address(&index.comet_address));
+            if let Some(block) = index.last_scanned_block {
+                println!("Scanned through block: {}", block);
+            }
+            if let Some(finalized) = index.finalized {
+                println!("Final through block: {}", finalized.number);
+            }
+            println!("Known borrowers: {}", index.borrowers.len());
+            if !index.flows.is_empty() {
+                println!("Base flows kept: {} in {} block(s)", index.flows.len(), index.net_flows().len());
+            }
+        },
+
+        Command::ScanPositions { file, market, out } => {
+            let list = AddressList::from_file(&file)?;
+            let Some(market) = select_market(engine.provider().get_markets().await?, market) else {
+                eprintln!("No matching markets found");
+                if !text {
+                    print_json(&serde_json::Value::Null)?;
+                }
+                return Ok(0);
+            };
+            let scan = engine.scan_positions(market.comet_address, &list).await?;
+            if let Some(path) = &out {
+                save_results(engine, path, ContentKind::PositionScan, &scan).await?;
+            }
+            if !text {
+                print_json(&scan)?;
+                return Ok(0);
+            }
+
+            let config = engine.config();
+            let units = Units::new(&config, std::slice::from_ref(&market));
+            let unit = units.of(&market.comet_address);
+            println!("\n=== POSITION SCAN ===");
+            println!("Market: {}{}", format_named_address(&scan.market_name, &scan.market_address), mock_tag(scan.mock_data));
+            if scan.positions.is_empty() {
+                println!("No positions read");
+            } else {
+                println!("{}", render::position_scan_table(&scan, RenderOptions::detect(false).with_numbers(config.display.number_style).with_units(&units)));
+            }
+            let summary = &scan.summary;
+            println!("\nAccounts: {}", summary.accounts);
+            println!("Total borrow: {}", unit.format(summary.total_borrow_value, config.display.number_style));
+            println!("Total collateral: {}", unit.format(summary.total_collateral_value, config.display.number_style));
+            println!("At risk: {}", summary.at_risk);
+            for failed in &scan.failed {
+                println!("⚠️  {}: position not read: {}", format_address(&failed.address), failed.error);
+            }
+            for invalid in &scan.invalid_lines {
+                println!("⚠️  {} line {}: {} (`{}`)", file.display(), invalid.line, invalid.reason, sanitize_inline(&invalid.text));
+            }
+        },
+
+        Command::TopBorrowers(args) => print_top_positions(engine, args, PositionSort::Borrow, text).await?,
+
+        Command::TopSuppliers(args) => print_top_positions(engine, args, PositionSort::Supply, text).await?,
+
+        Command::ListMarkets { discover } => {
+            let markets = engine.list_markets().await?;
+            let discovery = if discover { Some(engine.discover_markets().await) } else { None };
+            if !text {
+                print_json(&json!({ "markets": markets, "discovery": discovery }))?;
+                return Ok(0);
+            }
+            let units = display_units(engine).await?;
+            println!("\n=== MARKETS ===");
+            println!("{}", render::markets_table(&markets, RenderOptions::detect(false).with_numbers(engine.config().display.number_style).with_units(&units)));
+            if let Some(discovery) = discovery {
+                println!("\n=== NOT MONITORED (best effort) ===");
+                if discovery.unmonitored.is_empty() {
+                    println!("No other deployments found");
+                }
+                for deployment in &discovery.unmonitored {
+                    let source = match deployment.source {
+                        DeploymentSource::Registry => "known deployment",
+                        DeploymentSource::ConfiguratorEvents => "Configurator events",
+                    };
+                    let name = deployment.base_symbol.as_deref().map(|symbol| format!("Comet {}", symbol)).unwrap_or_else(|| "Comet".to_string());
+                    println!("- {} [{}]", format_named_address(&name, &deployment.comet_address), source);
+                }
+                if let Some(block) = discovery.scanned_through {
+                    println!("Configurator events scanned through block {}", block);
+                }
+                for error in &discovery.errors {
+                    println!("⚠️  Incomplete: {}", error);
+                }
+            }
+        },
+
+        Command::AlertTest { explain_routing, severity, category, market, account } => {
+            let dispatcher = AlertDispatcher::from_config(&engine.config())?;
+            if dispatcher.is_empty() {
+                anyhow::bail!("no alert routes configured; add webhooks under `alerts` in the config file");
+            }
+
+            let alert = Alert::synthetic_for(&engine.config(), severity, category, market, account);
+            if explain_routing {
+                let outcomes = dispatcher.explain(&alert);
+                if !text {
+                    print_json(&outcomes)?;
+                    return Ok(0);
+                }
+                println!("\n=== ALERT ROUTING ===");
+                println!("{}", alert.headline());
+                for outcome in &outcomes {
+                    let status = match (&outcome.mismatch, outcome.evaluated) {
+                        (_, false) => "⏭️  not reached".to_string(),
+                        (None, true) => "✅ sends".to_string(),
+                        (Some(reason), true) => format!("❌ skips: {}", reason),
+                    };
+                    println!("{} → {}: {}", outcome.rule, outcome.sink, status);
+                    if let Some(message) = &outcome.message {
+                        println!("    {}", message);
+                    }
+                }
+                let sinks: Vec<&str> = outcomes.iter().filter(|o| o.sends()).map(|o| o.sink.as_str()).collect();
+                if sinks.is_empty() {
+                    println!("No sink would receive this finding");
+                }
+                return Ok(0);
+            }
+
+            let outcomes = dispatcher.send_test(&alert).await;
+            let failed = outcomes.iter().filter(|(_, status)| matches!(status, DeliveryStatus::Failed(_))).count();
+            if text {
+                println!("\n=== ALERT TEST ===");
+                for (route, status) in &outcomes {
+                    match status {
+                        DeliveryStatus::Failed(error) => println!("❌ {}: {}", route, error),
+                        _ => println!("✅ {}: delivered", route),
+                    }
+                }
+            } else {
+                let routes: Vec<_> = outcomes.iter().map(|(route, status)| json!({ "route": route, "status": status })).collect();
+                print_json(&routes)?;
+            }
+            if failed > 0 {
+                anyhow::bail!("{} of {} alert route(s) failed", failed, outcomes.len());
+            }
+        },
+
+        Command::Ack { fingerprint } => {
+            if engine.config().alerts.state_path.is_none() {
+                anyhow::bail!("acknowledgements are kept in the alert state file; set `alerts.state_path` in the config file");
+            }
+            let record = engine.acknowledge_finding(&fingerprint)?;
+            if !text {
+                print_json(&record)?;
+                return Ok(0);
+            }
+            println!(
+                "✅ Acknowledged {}: {:?} {:?} finding in {}",
+                fingerprint, record.severity, record.category, record.market_name
+            );
+            println!("It alerts again only if it escalates above {:?} or resolves and recurs", record.severity);
+        },
+
+        Command::Annotate { fingerprint, note, author } => {
+            if engine.storage().is_none() {
+                anyhow::bail!(
+                    "annotations are kept in the history database; set `storage.database_path` (a SQLite file) or \
+                     `storage.postgres` in the config file"
+                );
+            }
+            let annotation = engine.annotate_finding(&fingerprint, &note, &author).await?;
+            if !text {
+                print_json(&annotation)?;
+                return Ok(0);
+            }
+            println!("📝 Annotated {}: {}", annotation.fingerprint, annotation);
+        },
+
+        Command::SubgraphStatus => {
+            let Some(subgraph) = engine.subgraph() else {
+                anyhow::bail!("no data is read from the subgraph; set a `subgraph` source to `subgraph` in the config file");
+            };
+            let lag = subgraph.lag().await?;
+            if !text {
+                print_json(&lag)?;
+                return Ok(0);
+            }
+            println!("\n=== SUBGRAPH STATUS ===");
+            println!("Indexed block: {}", lag.indexed_block);
+            println!("Chain head:    {}", lag.chain_head);
+            if lag.lagging {
+                println!("⚠️  {} blocks behind (more than `subgraph.max_lag_blocks`)", lag.lag_blocks);
+            } else {
+                println!("✅ {} blocks behind", lag.lag_blocks);
+            }
+        },
+
+        Command::Doctor => {
+            let report = engine.diagnostics().await;
+            if report.is_failing() {
+                code = EXIT_ERROR;
+            }
+            if !text {
+                print_json(&report)?;
+                return Ok(code);
+            }
+            println!("\n=== DIAGNOSTICS ===");
+            for check in &report.checks {
+                let icon = match check.status {
+                    CheckStatus::Pass => "✅",
+                    CheckStatus::Warn => "⚠️ ",
+                    CheckStatus::Fail => "❌",
+                };
+                println!("{} {}", icon, check);
+            }
+            let count = |status| report.checks.iter().filter(|c| c.status == status).count();
+            match report.status {
+                CheckStatus::Pass => println!("\nAll {} checks passed", report.checks.len()),
+                CheckStatus::Warn => println!("\n{} warning(s), no failures", count(CheckStatus::Warn)),
+                CheckStatus::Fail => println!("\n{} check(s) failed", count(CheckStatus::Fail)),
+            }
+        },
+
+        Command::History { market, days, category, min_severity } => {
+            let Some(storage) = engine.storage() else {
+                anyhow::bail!(
+                    "history reads the assessments the engine stores, and no history database is configured. \
+                     Set `storage.database_path` (a SQLite file) or `storage.postgres` in the config file, or pass \
+                     `--set storage.database_path=history.db`; `watch`, `serve` and `assess` then record every assessment"
+                );
+            };
+            let markets = match market {
+                Some(market) => match storage.find_market(&market).await? {
+                    Some(found) => vec![found],
+                    None => anyhow::bail!("no stored assessments for market `{}`", market),
+                },
+                None => storage.markets().await?,
+            };
+
+            let until = chrono::Utc::now();
+            let since = until - chrono::Duration::days(days);
+            let threshold = engine.config().risk.max_utilization_threshold;
+            let mut trends = Vec::new();
+            for market in &markets {
+                trends.push(Trend::load(storage.as_ref(), market, since, until, threshold).await?);
+            }
+            if output == OutputFormat::Csv {
+                write_trend_csv(std::io::stdout().lock(), &trends)?;
+                return Ok(0);
+            }
+            let query = |market: Address| FindingQuery {
+                market: Some(market),
+                category: category.clone(),
+                min_severity,
+                since: Some(since),
+                until: Some(until),
+            };
+            if !text {
+                let mut history = Vec::new();
+                for trend in trends {
+                    let spans = storage.fingerprint_spans(&query(trend.market.address)).await?;
+                    let fingerprints: Vec<String> = spans.iter().map(|span| span.fingerprint.clone()).collect();
+                    history.push(json!({
+                        "market": trend.market,
+                        "scores": storage.score_series(trend.market.address, since, until).await?,
+                        "findings": spans,
+                        "annotations": storage.latest_annotations(&fingerprints).await?,
+                        "trend": trend,
+                    }));
+                }
+                print_json(&history)?;
+                return Ok(0);
+            }
+
+            println!("\n=== RISK HISTORY (last {} days) ===", days);
+            for trend in &trends {
+                println!("\nMarket: {}", format_named_address(&trend.market.name, &trend.market.address));
+                print_trend(trend, engine.config().display.tz());
+
+                let spans = storage.fingerprint_spans(&query(trend.market.address)).await?;
+                if spans.is_empty() {
+                    println!("\n✅ No matching findings");
+                    continue;
+                }
+                let fingerprints: Vec<String> = spans.iter().map(|span| span.fingerprint.clone()).collect();
+                let annotations = storage.latest_annotations(&fingerprints).await?;
+                println!("\nFindings:");
+                for span in &spans {
+                    println!("- [{:?}] {}\n  first seen {}, last seen {}, in {} assessment(s)",
+                        span.max_severity,
+                        span.last_description,
+                        utils::format_timestamp(&span.first_seen, engine.config().display.tz()),
+                        utils::format_timestamp(&span.last_seen, engine.config().display.tz()),
+                        span.occurrences
+                    );
+                    if let Some(annotation) = annotations.get(&span.fingerprint) {
+                        println!("  Note: {}", annotation);
+                    }
+                }
+            }
+        },
+
+        Command::Compare { from, to } => {
+            let older = compare_side(engine, &from).await?;
+            let newer = compare_side(engine, &to).await?;
+            let diff = compare::diff_assessments(&older, &newer);
+            code = if diff.is_riskier() { EXIT_FINDINGS } else { 0 };
+            for warning in diff.version_warnings() {
+                eprintln!("⚠️  {}; scores may not compare", warning);
+            }
+            if !text {
+                print_json(&diff)?;
+                return Ok(code);
+            }
+
+            println!("\n=== COMPARISON {} → {} ===", from, to);
+            for market in &diff.markets {
+                print!("\nMarket: {}", format_named_address(&market.market_name, &market.market_address));
+                if market.is_unchanged() {
+                    println!(" - no changes");
+                    continue;
+                }
+                println!();
+                let score = |s: Option<u8>| s.map_or("-".to_string(), |s| s.to_string());
+                println!("  Risk Score: {} → {} ({:+})", score(market.score_before), score(market.score_after), market.score_delta());
+                let metrics = [
+                    ("Utilization", market.utilization_rate, utils::format_percentage as fn(f64) -> String),
+                    ("TVL", market.tvl_usd, |v| utils::format_money(v, "$")),
+                    ("Reserves", market.reserves, |v| utils::format_money(v, "")),
+                ];
+                for (label, delta, format) in metrics {
+                    if let Some(delta) = delta {
+                        let change = format(delta.change());
+                        let sign = if delta.change() >= 0.0 { "+" } else { "" };
+                        println!("  {}: {} → {} ({}{})", label, format(delta.before), format(delta.after), sign, change);
+                    }
+                }
+                for change in &market.findings {
+                    let points = change.score_delta();
+                    match change {
+                        FindingChange::Added { finding } => println!("  + {} ({:+})", finding, points),
+                        FindingChange::Removed { finding } => println!("  - {} ({:+})", finding, points),
+                        FindingChange::SeverityChanged { previous, finding, .. } => {
+                            let arrow = if finding.severity > *previous { "↑" } else { "↓" };
+                            println!("  {} [{:?} → {:?}] {} ({:+})", arrow, previous, finding.severity, finding.description, points);
+                        }
+                    }
+                }
+                let unattributed = market.unattributed_score_delta();
+                if unattributed != 0 {
+                    println!("  {:+} not attributed to a finding change", unattributed);
+                }
+            }
+            if code != 0 {
+                eprintln!("❌ {} is riskier than {}", to, from);
+            }
+        },
+
+        Command::Export { format: ExportFormat::Csv, out, data, days, max_hf } => {
+            // Created once the data is in hand, so a failed run leaves no empty file behind
+            let create = || std::fs::File::create(&out).map_err(|e| RiskEngineError::io(&out, e));
+            let rows = match (data, days) {
+                (ExportData::Findings, None) => {
+                    let assessments = engine.assess_risks().await?;
+                    export::write_findings_csv(create()?, &export::assessment_findings(&assessments))?
+                }
+                (ExportData::Findings, Some(days)) => {
+                    let Some(storage) = engine.storage() else {
+                        anyhow::bail!("no history database configured; set `storage.database_path` or `storage.postgres` in the config file");
+                    };
+                    let query = FindingQuery {
+                        since: Some(chrono::Utc::now() - chrono::Duration::days(days)),
+                        ..FindingQuery::default()
+                    };
+                    export::write_findings_csv(create()?, &storage.findings(&query).await?)?
+                }
+                (ExportData::Positions, None) => {
+                    let max_hf = max_hf.unwrap_or(1.0 + engine.config().risk.liquidation_threshold_buffer);
+                    let mut at_risk = Vec::new();
+                    for market in engine.provider().get_markets().await? {
+                        for position in engine.tracked_positions(&market).await? {
+                            if position.total_borrow_value > 0.0 && position.health_factor < max_hf {
+                                at_risk.push((market.clone(), position));
+                            }
+                        }
+                    }
+                    export::write_positions_csv(create()?, at_risk.iter().map(|(market, position)| (market, position)))?
+                }
+                (ExportData::Positions, Some(_)) => {
+                    anyhow::bail!("position history is not stored; export current positions by omitting `--days`");
+                }
+            };
+            if text {
+                println!("\nWrote {} row(s) to {}", rows, out.display());
+            } else {
+                print_json(&json!({ "path": out, "rows": rows }))?;
+            }
+        },
+
+        Command::Config { .. } | Command::Completions { .. } | Command::Selftest => {
+            unreachable!("config, completions and selftest commands run without an engine")
+        }
+
+        Command::Audit { command: AuditCommand::Verify { path } } => {
+            let report = audit::verify(&path)?;
+            if !text {
+                print_json(&report)?;
+                if !report.is_ok() {
+                    anyhow::bail!("{} problem(s) in {}", report.problems.len(), path.display());
+                }
+                return Ok(0);
+            }
+            println!("\n=== AUDIT LOG {} ===", path.display());
+            println!("{} well-formed entr{}", report.entries, if report.entries == 1 { "y" } else { "ies" });
+            if let (Some(first), Some(last)) = (report.first, report.last) {
+                let timezone = engine.config().display.tz();
+                println!("Recorded from {} to {}", utils::format_timestamp(&first, timezone), utils::format_timestamp(&last, timezone));
+            }
+            for (version, entries) in &report.engine_versions {
+                println!("  {} entr{} by engine {}", entries, if *entries == 1 { "y" } else { "ies" }, version);
+            }
+            for problem in &report.problems {
+                println!("❌ line {}: {}", problem.line, problem.message);
+            }
+            if !report.is_ok() {
+                anyhow::bail!("{} problem(s) in {}", report.problems.len(), path.display());
+            }
+            println!("✅ Audit log is intact");
+        },
+
+        #[cfg(feature = "tui")]
+        Command::Dashboard { interval, .. } => {
+            risk_engine::dashboard::run(engine.clone(), interval).await?;
+            return Ok(0);
+        },
+
+        #[cfg(feature = "server")]
+        Command::Serve { bind, interval } => {
+            let Some(bind) = bind.or_else(|| engine.config().server.bind_address.clone()) else {
+                anyhow::bail!("no listen address; pass `--bind` or set `server.bind_address` in the config file");
+            };
+            let addr = bind.parse().map_err(|e| anyhow::anyhow!("`{}` is not a socket address: {}", bind, e))?;
+
+            // Both stop on ctrl-c through the engine's cancellation token
+            let schedule = Duration::from_secs(interval);
+            tokio::try_join!(engine.serve(addr), engine.run_scheduled(schedule, engine.cancellation_token()))?;
+        },
+    }
+    
+    if text {
+        println!("\n");
+    }
+    Ok(code)
+}
+
+/// `EXIT_FINDINGS` if any finding is at or above `threshold`, or with `fail_on_partial` if
+/// any assessment is partial, otherwise 0
+fn fail_on_exit_code(assessments: &[RiskAssessment], threshold: Option<RiskSeverity>, fail_on_partial: bool) -> u8 {
+    let mut code = 0;
+    if let Some(threshold) = threshold {
+        let hits = assessments.iter().flat_map(|a| &a.findings).filter(|f| f.severity >= threshold).count();
+        if hits > 0 {
+            eprintln!("❌ {} finding(s) at or above {:?}", hits, threshold);
+            code = EXIT_FINDINGS;
+        }
+    }
+    let partial = assessments.iter().filter(|a| !a.complete).count();
+    if fail_on_partial && partial > 0 {
+        eprintln!("❌ {} partial assessment(s), timed out", partial);
+        code = EXIT_FINDINGS;
+    }
+    code
+}
+
+/// One side of `compare`: a saved assessment file, `now`, or the history store at a point in time
+async fn compare_side(engine: &RiskEngine, side: &str) -> Result<Vec<AssessmentPoint>> {
+    let path = std::path::Path::new(side);
+    if path.is_file() {
+        return Ok(compare::load(path)?);
+    }
+    if side == "now" {
+        return Ok(compare::current(engine).await?);
+    }
+    if side.starts_with("block:") {
+        anyhow::bail!("block-pinned assessments are not supported; compare saved assessment files or times");
+    }
+    let at = parse_point_in_time(side)?;
+    let Some(storage) = engine.storage() else {
+        anyhow::bail!("no history database configured; set `storage.database_path` or `storage.postgres` in the config file");
+    };
+    let points = compare::stored_at(storage.as_ref(), at).await?;
+    if points.is_empty() {
+        anyhow::bail!("no stored assessments at or before {}", at.to_rfc3339());
+    }
+    Ok(points)
+}
+
+/// An RFC 3339 time, a date (midnight UTC) or an age such as `7d`
+fn parse_point_in_time(input: &str) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&chrono::Utc));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc());
+    }
+    match utils::parse_duration(input) {
+        Ok(age) => Ok(chrono::Utc::now() - chrono::Duration::from_std(age)?),
+        Err(_) => anyhow::bail!("`{}` is not a file, `now`, a time or an age such as `7d`", input),
+    }
+}
+
+/// Save `content` to `path` in the `archive` envelope, with the chain head it was computed at
+async fn save_results<T: Serialize>(engine: &RiskEngine, path: &Path, kind: ContentKind, content: &T) -> Result<()> {
+    let envelope = Envelope::new(kind, &engine.config(), engine.head_block().await, content);
+    archive::write(path, &envelope)?;
+    info!("Saved {:?} results to {}", kind, path.display());
+    Ok(())
+}
+
+/// Assessments of every market, or of `market` only
+async fn assessments(engine: &RiskEngine, market: Option<Address>) -> Result<Vec<RiskAssessment>> {
+    let mut assessments = engine.assess_risks().await?;
+    if let Some(market) = market {
+        assessments.retain(|a| a.market_address == market);
+    }
+    log_slowest_checks(&assessments);
+    Ok(assessments)
+}
+
+/// Log the three slowest checks of `assessments` at debug level
+fn log_slowest_checks(assessments: &[RiskAssessment]) {
+    let slowest: Vec<String> = risk::slowest_checks(assessments, 3).iter().map(ToString::to_string).collect();
+    if !slowest.is_empty() {
+        debug!("Slowest checks: {}", slowest.join(", "));
+    }
+}
+
+/// `user`'s position in `market`, or in the first market when unset; `None` if no market matches
+async fn user_position(engine: &RiskEngine, market: Option<Address>, user: Address) -> Result<Option<(Market, UserPosition)>> {
+    let provider = engine.provider();
+    let Some(market) = select_market(provider.get_markets().await?, market) else { return Ok(None) };
+    let position = provider.get_user_position(&market, user).await?;
+    Ok(Some((market, position)))
+}
+
+/// Managers `user` allows in `market`, `None` (logged) when the data source cannot read them
+async fn manager_permissions(engine: &RiskEngine, market: &Market, user: Address) -> Option<ManagerPermissions> {
+    let trusted = engine.config().trusted_manager_addresses();
+    match engine.provider().get_manager_permissions(market, user, &trusted).await {
+        Ok(permissions) => Some(permissions),
+        Err(e) => {
+            debug!("Not showing the managers of {}: {}", utils::to_checksum_address(&user), e);
+            None
+        }
+    }
+}
+
+/// Simulation of `market`, or of the first market when unset, with the absorption of
+/// `absorb`; `None` if no market matches
+async fn simulation(engine: &RiskEngine, market: Option<Address>, absorb: Option<AbsorbTarget>) -> Result<Option<SimulationResult>> {
+    let Some(market) = select_market(engine.provider().get_markets().await?, market) else {
+        return Ok(None);
+    };
+    let mut simulation = engine.simulate_market(&market).await?;
+    if let Some(target) = absorb {
+        simulation.absorption = Some(engine.simulate_absorption(&market, target).await?);
+    }
+    Ok(Some(simulation))
+}
+
+/// What absorbing one position does, ending with whether reserves absorb the shortfall
+fn print_absorption(absorption: &AbsorptionSimulation) {
+    let money = |value: f64| utils::format_money(value, "$");
+    println!("\n=== ABSORPTION OF {} ===", format_address_labeled(&absorption.account));
+    println!("Debt cleared: {} (health factor {:.2})", money(absorption.debt_usd), absorption.health_factor);
+    for sale in &absorption.collateral {
+        let depth = match sale.dex_depth_usd {
+            Some(depth) if sale.exceeds_depth() => format!(", {} DEX depth ⚠️ exceeded", money(depth)),
+            Some(depth) => format!(", {} DEX depth", money(depth)),
+            None => String::new(),
+        };
+        let impact = match sale.price_impact {
+            Some(impact) if sale.unprofitable() => format!(", {} price impact ⚠️ exceeds the discount", utils::format_percentage(impact)),
+            Some(impact) => format!(", {} price impact", utils::format_percentage(impact)),
+            None => ", price impact unknown".to_string(),
+        };
+        println!("  {}: {} sold for {}{}{}", sale.symbol, money(sale.value_usd), money(sale.proceeds_usd), depth, impact);
+    }
+    if absorption.surplus_usd > 0.0 {
+        println!("Kept by the account as base supply: {}", money(absorption.surplus_usd));
+    }
+    println!("Reserves: {} -> {} ({})",
+        money(absorption.reserves_before_usd),
+        money(absorption.reserves_after_usd),
+        money(absorption.reserves_change_usd())
+    );
+    println!("Utilization: {} -> {}",
+        utils::format_percentage(absorption.utilization_before),
+        utils::format_percentage(absorption.utilization_after)
+    );
+    let knock_on = &absorption.knock_on;
+    if knock_on.newly_liquidatable.is_empty() {
+        println!("Knock-on: none of {} other borrowing positions become liquidatable", knock_on.positions_checked);
+    } else {
+        println!("Knock-on: {} of {} other borrowing positions become liquidatable ({} borrowed)",
+            knock_on.newly_liquidatable.len(),
+            knock_on.positions_checked,
+            money(knock_on.borrow_usd)
+        );
+        for account in &knock_on.newly_liquidatable {
+            println!("  {}", format_address_labeled(account));
+        }
+    }
+    println!("\n{}", absorption.verdict());
+}
+
+/// Width of the `history` sparklines, in cells
+const TREND_WIDTH: usize = 60;
+
+/// Sparklines of score and utilization with the window's summary, times in `timezone`; blank
+/// cells are downtime
+fn print_trend(trend: &Trend, timezone: Tz) {
+    let summary = &trend.summary;
+    let (Some(min), Some(avg), Some(max)) = (summary.min_score, summary.avg_score, summary.max_score) else {
+        println!("No assessments in this period");
+        return;
+    };
+    let score = trend.sparkline(TREND_WIDTH, 0.0, 100.0, |p| Some(f64::from(p.risk_score)));
+    let utilization = trend.sparkline(TREND_WIDTH, 0.0, 1.0, |p| p.utilization_rate);
+    println!("Score        {}  0-100", score);
+    println!("Utilization  {}  0-100%", utilization);
+    let date = |at: &chrono::DateTime<chrono::Utc>| at.with_timezone(&timezone).format("%Y-%m-%d").to_string();
+    let (from, to) = (date(&trend.since), date(&trend.until));
+    println!("             {}{:>width$}", from, to, width = score.chars().count().saturating_sub(from.len()));
+
+    println!("\nAssessments: {} (score min {}, avg {:.0}, max {})", summary.assessments, min, avg, max);
+    println!("High+ findings: {} (reported in {} assessment(s))", summary.high_findings, summary.high_reports);
+    let threshold = utils::format_percentage(summary.utilization_threshold);
+    match &summary.longest_above_threshold {
+        Some(stretch) => println!(
+            "Longest above {} utilization: {}, {} to {} ({} assessment(s))",
+            threshold,
+            utils::format_duration(stretch.duration().to_std().unwrap_or_default()),
+            utils::format_timestamp(&stretch.from, timezone),
+            utils::format_timestamp(&stretch.to, timezone),
+            stretch.assessments
+        ),
+        None => println!("Never above {} utilization", threshold),
+    }
+    if !trend.gaps.is_empty() {
+        println!("No assessments (engine down?):");
+        for gap in &trend.gaps {
+            println!("- {} to {} ({})",
+                utils::format_timestamp(&gap.from, timezone),
+                utils::format_timestamp(&gap.to, timezone),
+                utils::format_duration((gap.to - gap.from).to_std().unwrap_or_default())
+            );
+        }
+    }
+}
+
+/// Units of every market in `display.denomination`; in USD without asking the provider for
+/// prices
+async fn display_units(engine: &RiskEngine) -> Result<Units> {
+    let config = engine.config();
+    if config.display.denomination == Denomination::Usd {
+        return Ok(Units::default());
+    }
+    Ok(Units::new(&config, &engine.provider().get_markets().await?))
+}
+
+/// Print the largest positions of the market `args` selects by `sort`
+async fn print_top_positions(engine: &RiskEngine, args: TopArgs, sort: PositionSort, text: bool) -> Result<()> {
+    let Some(market) = select_market(engine.provider().get_markets().await?, args.market) else {
+        eprintln!("No matching markets found");
+        if !text {
+            print_json(&serde_json::Value::Null)?;
+        }
+        return Ok(());
+    };
+    let filter = HealthFactorFilter { min_hf: args.min_hf, max_hf: args.max_hf };
+    let top = engine.top_positions(market.comet_address, sort, args.limit, filter).await?;
+    if !text {
+        return print_json(&top);
+    }
+    let title = match sort {
+        PositionSort::Borrow => "TOP BORROWERS",
+        PositionSort::Supply => "TOP SUPPLIERS",
+    };
+    println!("\n=== {} ===", title);
+    println!("Market: {}{}", format_named_address(&top.market_name, &top.market_address), mock_tag(top.mock_data));
+    let config = engine.config();
+    let units = Units::new(&config, std::slice::from_ref(&market));
+    println!("Market total: {}", units.of(&market.comet_address).format(top.market_total_usd, config.display.number_style));
+    if top.positions.is_empty() {
+        println!("No tracked positions match");
+    } else {
+        println!("{}", render::top_positions_table(&top, RenderOptions::detect(false).with_numbers(config.display.number_style).with_units(&units)));
+        println!("Showing {} of {} matching tracked position(s)", top.positions.len(), top.matching);
+    }
+    Ok(())
+}
+
+/// The market with Comet proxy `address`, or the first market when unset
+fn select_market(markets: Vec<Market>, address: Option<Address>) -> Option<Market> {
+    match address {
+        Some(address) => markets.into_iter().find(|m| m.comet_address == address),
+        None => markets.into_iter().next(),
+    }
+}
+
+/// Print `value` on stdout as one pretty-printed JSON document
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    write_json(&mut std::io::stdout().lock(), value)?;
+    Ok(())
+}
+
+fn write_json<T: Serialize + ?Sized>(out: &mut impl Write, value: &T) -> std::io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, value)?;
+    writeln!(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risk_engine::exposure::AccountExposure;
+    use risk_engine::models::{PriceHistory, ProtocolMetrics};
+    use risk_engine::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+    use risk_engine::scanner::LogSource;
+
+    const RISKY_USER: &str = "0x2222222222222222222222222222222222222222";
+
+    fn fixture_engine() -> RiskEngine {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        RiskEngine::with_provider(Config::default(), Arc::new(fixture))
+    }
+
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+        let mut out = Vec::new();
+        write_json(&mut out, value).unwrap();
+        serde_json::from_slice(&out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_assess_json_parses_back() {
+        let engine = fixture_engine();
+        let markets = assessments(&engine, None).await.unwrap();
+        let parsed: Vec<RiskAssessment> = round_trip(&markets);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].risk_score, markets[0].risk_score);
+        assert_eq!(parsed[0].findings.len(), markets[0].findings.len());
+        assert!(parsed[0].mock_data);
+
+        let none = assessments(&engine, Some(Address::zero())).await.unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_user_and_simulate_json_parse_back() {
+        let engine = fixture_engine();
+        let user = utils::parse_address(RISKY_USER).unwrap();
+        let (market, position) = user_position(&engine, None, user).await.unwrap().unwrap();
+        let parsed: UserPosition = round_trip(&position);
+        assert_eq!(parsed.address, user);
+        assert_eq!(parsed.health_factor, position.health_factor);
+        // Fixture accounts allow no managers
+        let managers = manager_permissions(&engine, &market, user).await.unwrap();
+        assert!(managers.managers.is_empty() && managers.complete);
+
+        let exposure = engine.get_account_exposure(user).await.unwrap();
+        let parsed: AccountExposure = round_trip(&exposure);
+        assert_eq!(parsed.markets.len(), 1);
+        assert_eq!(parsed.markets[0].position.health_factor, position.health_factor);
+        assert_eq!(parsed.total_borrow_usd, position.total_borrow_value);
+
+        let simulation = simulation(&engine, None, None).await.unwrap().unwrap();
+        let parsed: SimulationResult = round_trip(&simulation);
+        assert_eq!(parsed.market_address, simulation.market_address);
+        assert_eq!(parsed.findings.len(), simulation.findings.len());
+    }
+
+    fn assess(fail_on: Option<RiskSeverity>, stream: bool) -> Command {
+        Command::Assess { market: None, stream, fail_on, fail_on_partial: false, no_color: true, out: None }
+    }
+
+    /// Provider whose every read fails, like an unreachable RPC endpoint
+    struct BrokenProvider;
+
+    #[async_trait::async_trait]
+    impl MarketDataProvider for BrokenProvider {
+        async fn get_markets(&self) -> risk_engine::Result<Vec<Market>> {
+            Err(RiskEngineError::Unavailable { what: "market data", reason: "connection refused".to_string() })
+        }
+        async fn get_user_position(&self, _: &Market, _: Address) -> risk_engine::Result<UserPosition> {
+            unreachable!()
+        }
+        async fn get_price_history(&self, _: &Market, _: Address) -> risk_engine::Result<PriceHistory> {
+            unreachable!()
+        }
+        async fn get_protocol_metrics(&self, _: &Market) -> risk_engine::Result<ProtocolMetrics> {
+            unreachable!()
+        }
+        fn log_source(&self) -> Option<Arc<dyn LogSource>> {
+            Some(Arc::new(BrokenProvider))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LogSource for BrokenProvider {
+        async fn head_block(&self) -> risk_engine::Result<u64> {
+            Err(RiskEngineError::Unavailable { what: "chain head", reason: "connection refused".to_string() })
+        }
+        async fn borrowers_in_range(&self, _: Address, _: u64, _: u64) -> risk_engine::Result<Vec<Address>> {
+            unreachable!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_doctor_exits_non_zero_when_a_check_fails() {
+        let healthy = Arc::new(fixture_engine());
+        assert_eq!(exit_code(&run(Command::Doctor, &healthy, OutputFormat::Json).await), 0);
+        let offline = Arc::new(RiskEngine::with_provider(Config::default(), Arc::new(BrokenProvider)));
+        let report = offline.diagnostics().await;
+        assert_eq!(report.check("rpc (mainnet)").map(|c| c.status), Some(CheckStatus::Fail));
+        assert_eq!(exit_code(&run(Command::Doctor, &offline, OutputFormat::Text).await), EXIT_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_selftest_passes_on_this_build() {
+        assert_eq!(exit_code(&selftest(OutputFormat::Json).await), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fail_on_exit_codes() {
+        // The fixture market has a High utilization finding and nothing Critical
+        let engine = Arc::new(fixture_engine());
+        for stream in [false, true] {
+            let high = run(assess(Some(RiskSeverity::High), stream), &engine, OutputFormat::Json).await;
+            assert_eq!(exit_code(&high), EXIT_FINDINGS);
+            let critical = run(assess(Some(RiskSeverity::Critical), stream), &engine, OutputFormat::Json).await;
+            assert_eq!(exit_code(&critical), 0);
+            let unset = run(assess(None, stream), &engine, OutputFormat::Json).await;
+            assert_eq!(exit_code(&unset), 0);
+        }
+
+        // The config threshold applies unless the flag overrides it
+        let mut config = Config::default();
+        config.risk.fail_on = Some(RiskSeverity::Medium);
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = Arc::new(RiskEngine::with_provider(config, Arc::new(fixture)));
+        assert_eq!(exit_code(&run(assess(None, false), &engine, OutputFormat::Json).await), EXIT_FINDINGS);
+        let overridden = run(assess(Some(RiskSeverity::Critical), false), &engine, OutputFormat::Json).await;
+        assert_eq!(exit_code(&overridden), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fail_on_partial_exit_code() {
+        let mut assessments = fixture_engine().assess_risks().await.unwrap();
+        assert_eq!(fail_on_exit_code(&assessments, None, true), 0);
+        assessments[0].complete = false;
+        assert_eq!(fail_on_exit_code(&assessments, None, false), 0);
+        assert_eq!(fail_on_exit_code(&assessments, None, true), EXIT_FINDINGS);
+        assert_eq!(fail_on_exit_code(&assessments, Some(RiskSeverity::Critical), true), EXIT_FINDINGS);
+    }
+
+    #[tokio::test]
+    async fn test_watch_stops_after_max_iterations_without_failing() {
+        let engine = Arc::new(fixture_engine());
+        let watch = Command::Watch {
+            interval: Duration::from_millis(10),
+            max_iterations: Some(2),
+            fail_on: Some(RiskSeverity::High),
+            redraw: true,
+        };
+        let result = tokio::time::timeout(Duration::from_secs(10), run(watch, &engine, OutputFormat::Text)).await;
+        assert_eq!(exit_code(&result.expect("watch did not stop")), 0);
+    }
+
+    #[tokio::test]
+    async fn test_operational_errors_have_their_own_exit_code() {
+        let mut config = Config::default();
+        config.risk.fail_on = Some(RiskSeverity::Low);
+        let engine = Arc::new(RiskEngine::with_provider(config, Arc::new(BrokenProvider)));
+        for stream in [false, true] {
+            let result = run(assess(None, stream), &engine, OutputFormat::Json).await;
+            assert!(result.is_err());
+            assert_eq!(exit_code(&result), EXIT_ERROR);
+        }
+        assert_eq!(exit_code(&Err(RiskEngineError::Cancelled.into())), EXIT_CANCELLED);
+    }
+
+    #[tokio::test]
+    async fn test_compare_exits_non_zero_when_the_newer_side_is_riskier() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("before.json");
+        std::fs::write(&saved, serde_json::to_string(&assessments(&fixture_engine(), None).await.unwrap()).unwrap()).unwrap();
+        let saved = saved.to_string_lossy().to_string();
+
+        // Watching the risky account adds a liquidation cascade finding
+        let config = Config { watchlist: vec![RISKY_USER.to_string()], ..Config::default() };
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = Arc::new(RiskEngine::with_provider(config, Arc::new(fixture)));
+        let compare = |from: &str, to: &str| Command::Compare { from: from.to_string(), to: to.to_string() };
+        assert_eq!(exit_code(&run(compare(&saved, "now"), &engine, OutputFormat::Json).await), EXIT_FINDINGS);
+        assert_eq!(exit_code(&run(compare("now", &saved), &engine, OutputFormat::Text).await), 0);
+        assert_eq!(exit_code(&run(compare(&saved, &saved), &engine, OutputFormat::Text).await), 0);
+
+        // Points in time need a history database
+        let result = run(compare("7d", "now"), &engine, OutputFormat::Text).await;
+        assert_eq!(exit_code(&result), EXIT_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_compare_saved_files_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        let assess_to = |engine: Arc<RiskEngine>, out: String| async move {
+            let command = Command::Assess { market: None, stream: false, fail_on: None, fail_on_partial: false, no_color: true, out: Some(out.into()) };
+            exit_code(&run(command, &engine, OutputFormat::Json).await)
+        };
+        assert_eq!(assess_to(Arc::new(fixture_engine()), path("before.json")).await, 0);
+        let config = Config { watchlist: vec![RISKY_USER.to_string()], ..Config::default() };
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        assert_eq!(assess_to(Arc::new(RiskEngine::with_provider(config, Arc::new(fixture))), path("after.json")).await, 0);
+
+        // Every read of the engine's provider fails, so only the files are used
+        let offline = Arc::new(RiskEngine::with_provider(Config::default(), Arc::new(BrokenProvider)));
+        let compare = Command::Compare { from: path("before.json"), to: path("after.json") };
+        assert_eq!(exit_code(&run(compare, &offline, OutputFormat::Json).await), EXIT_FINDINGS);
+        let compare = Command::Compare { from: path("after.json"), to: path("before.json") };
+        assert_eq!(exit_code(&run(compare, &offline, OutputFormat::Text).await), 0);
+    }
+
+    #[test]
+    fn test_parse_point_in_time() {
+        assert_eq!(parse_point_in_time("2024-05-01").unwrap().to_rfc3339(), "2024-05-01T00:00:00+00:00");
+        assert_eq!(parse_point_in_time("2024-05-01T12:00:00+02:00").unwrap().to_rfc3339(), "2024-05-01T10:00:00+00:00");
+        let week_ago = parse_point_in_time("7d").unwrap();
+        assert!((chrono::Utc::now() - week_ago - chrono::Duration::days(7)).num_seconds().abs() < 5);
+        assert!(parse_point_in_time("last tuesday").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_config_init_writes_a_valid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let path = path.to_str().unwrap();
+        let config = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["risk-engine-cli", "--output", "json", "--config", path, "config"].iter().chain(args)).unwrap();
+            async move {
+                let Command::Config { command } = &cli.command else { unreachable!() };
+                config_command(&cli, command).await
+            }
+        };
+
+        assert_eq!(exit_code(&config(&["init", "--network", "polygon", "--rpc-url", "https://polygon.example/KEY"]).await), 0);
+        assert_eq!(Config::from_file(&PathBuf::from(path)).unwrap().compound.chain_id, 137);
+        assert_eq!(exit_code(&config(&["validate"]).await), 0);
+        assert_eq!(exit_code(&config(&["show-effective"]).await), 0);
+        // An existing file is only replaced with --force
+        assert_eq!(exit_code(&config(&["init"]).await), EXIT_ERROR);
+        assert_eq!(exit_code(&config(&["init", "--force"]).await), 0);
+
+        std::fs::write(path, r#"{ "compound": { "rpc_url": "x" } }"#).unwrap();
+        assert_eq!(exit_code(&config(&["validate"]).await), EXIT_ERROR);
+
+        // --format names the file by its format
+        assert_eq!(exit_code(&config(&["init", "--format", "toml"]).await), 0);
+        let toml = dir.path().join("config.toml");
+        assert!(std::fs::read_to_string(&toml).unwrap().contains("[compound]"));
+        assert_eq!(Config::from_file(&toml).unwrap().compound.chain_id, 1);
+    }
+
+    #[test]
+    fn test_config_precedence_flag_over_env_over_file_over_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut file = Config::default();
+        file.rpc.session_path = Some("file.jsonl".into());
+        file.scanner.chunk_size = 100;
+        file.scanner.start_block = 7;
+        file.to_file(&path).unwrap();
+
+        let cli = |flags: &[&str]| Cli::try_parse_from(["risk-engine-cli", "--config", path.to_str().unwrap()].iter().chain(flags).chain(&["assess"])).unwrap();
+        let env = vec![
+            ("COMETGUARD__RPC__SESSION_PATH".to_string(), "env.jsonl".to_string()),
+            ("COMETGUARD__SCANNER__CHUNK_SIZE".to_string(), "200".to_string()),
+        ];
+
+        let config = load_config(&cli(&[]), env.clone()).unwrap();
+        assert_eq!(config.performance.max_concurrent_assessments, 4, "default");
+        assert_eq!(config.scanner.start_block, 7, "file over default");
+        assert_eq!(config.scanner.chunk_size, 200, "env over file");
+        assert_eq!(config.rpc.session_path, Some(PathBuf::from("env.jsonl")), "env over file");
+
+        let config = load_config(&cli(&["--replay", "flag.jsonl"]), env).unwrap();
+        assert_eq!(config.rpc.session_path, Some(PathBuf::from("flag.jsonl")), "flag over env");
+        assert_eq!(config.rpc.mode, RpcMode::Replay);
+    }
+
+    #[test]
+    fn test_profile_flag_over_profile_variable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let mut file = serde_json::to_value(Config::default()).unwrap();
+        file["profiles"] = json!({
+            "default": { "scanner": { "chunk_size": 500 } },
+            "sepolia": { "compound": { "chain_id": 11155111 } },
+            "staging": { "compound": { "chain_id": 5 }, "scanner": { "chunk_size": 50 } }
+        });
+        std::fs::write(&path, serde_yaml::to_string(&file).unwrap()).unwrap();
+
+        let cli = |flags: &[&str]| Cli::try_parse_from(["risk-engine-cli", "--config", path.to_str().unwrap(), "config", "show-effective"].iter().chain(flags)).unwrap();
+        let env = vec![("COMETGUARD_PROFILE".to_string(), "sepolia".to_string())];
+
+        let config = load_config(&cli(&[]), Vec::new()).unwrap();
+        assert_eq!((config.compound.chain_id, config.scanner.chunk_size), (1, 500), "default profile");
+        let config = load_config(&cli(&[]), env.clone()).unwrap();
+        assert_eq!((config.compound.chain_id, config.scanner.chunk_size), (11155111, 500), "variable");
+        let config = load_config(&cli(&["--profile", "staging"]), env).unwrap();
+        assert_eq!((config.compound.chain_id, config.scanner.chunk_size), (5, 50), "flag over variable");
+
+        let err = load_config(&cli(&["--profile", "mainnet"]), Vec::new()).unwrap_err().to_string();
+        assert!(err.contains("available: default, sepolia, staging"), "{}", err);
+    }
+
+    #[test]
+    fn test_market_and_user_are_checked_while_parsing() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["risk-engine-cli"].iter().chain(args));
+        assert!(parse(&["check-user", "--user", "vitalik.eth"]).is_ok());
+        assert!(parse(&["check-user", "--user", RISKY_USER, "--market", "0xc3d688B66703497DAA19211EEdff47f25384cdc3"]).is_ok());
+
+        let err = parse(&["check-user", "--user", "vitalik"]).err().unwrap().to_string();
+        assert!(err.contains("or an ENS name such as vitalik.eth"), "{}", err);
+        let err = parse(&["assess", "--market", "USDC"]).err().unwrap().to_string();
+        assert!(err.contains("expected the address of a Comet proxy"), "{}", err);
+        let err = parse(&["assess", "--market", "0xC3d688B66703497DAA19211EEdff47f25384cdc3"]).err().unwrap().to_string();
+        assert!(err.contains("did you mean 0xc3d688B66703497DAA19211EEdff47f25384cdc3?"), "{}", err);
+        assert!(parse(&["assess", "--market", "0xc3d688b66703497daa19211eedff47f25384cdc3"]).is_ok());
+    }
+
+    /// Regenerate with `cargo run --bin risk-engine-cli -- --help > fixtures/cli-help.txt`
+    #[cfg(all(feature = "tui", feature = "server", feature = "reload"))]
+    #[test]
+    fn test_top_level_help_matches_snapshot() {
+        let help = Cli::command().bin_name(BIN_NAME).render_long_help().to_string();
+        assert_eq!(help.trim_end(), include_str!("../../fixtures/cli-help.txt").trim_end());
+    }
+}
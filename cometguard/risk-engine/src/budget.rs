@@ -0,0 +1,208 @@
+//! Fitting assessments into `rpc.budget`
+//!
+//! Reading positions dominates the cost of an assessment: every tracked account costs a few
+//! `eth_call`s, once for each check that reads it. Before assessing a market the engine projects
+//! that cost against the market's share of the per-run budget. Over it, `BudgetPlan` skips the
+//! optional checks and then samples the borrower index, as `rpc.budget` allows, and the
+//! assessment's `RpcReport` records what was left out.
+
+use crate::config::RpcBudgetConfig;
+use crate::models::Market;
+use crate::rpc::{compute_units, RpcUsage};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+/// Checks `RiskProcessor` skips to stay within budget: they refine the assessment, while the
+/// checks left decide its severity
+pub const OPTIONAL_CHECKS: [&str; 2] = ["absorption", "manager_permissions"];
+
+/// Estimated compute units of the manager permissions check for one watchlist account:
+/// the chain head, its `Approval` logs and an `isAllowed` read for a couple of candidates
+const MANAGER_CHECK_UNITS: u64 = 10 + 75 + 2 * 26;
+
+/// Borrower index accounts read when the index was sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSample {
+    pub read: usize,
+    pub tracked: usize,
+}
+
+/// RPC cost of one assessment and what it left out to stay within `rpc.budget`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcReport {
+    #[serde(flatten)]
+    pub usage: RpcUsage,
+    /// Compute units the market could spend; `None` without a per-run budget
+    pub allowance: Option<u64>,
+    /// Compute units projected for the assessment with every check and tracked account
+    pub projected_compute_units: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampled_accounts: Option<AccountSample>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_checks: Vec<String>,
+}
+
+impl RpcReport {
+    /// Whether the assessment read less than it would have without a budget
+    pub fn is_degraded(&self) -> bool {
+        self.sampled_accounts.is_some() || !self.skipped_checks.is_empty()
+    }
+
+    /// What the assessment left out, e.g. "sampled 500 of 5000 indexed accounts; skipped absorption"
+    pub fn degradation(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(sample) = self.sampled_accounts {
+            parts.push(format!("sampled {} of {} indexed accounts", sample.read, sample.tracked));
+        }
+        if !self.skipped_checks.is_empty() {
+            parts.push(format!("skipped {}", self.skipped_checks.join(", ")));
+        }
+        (!parts.is_empty()).then(|| parts.join("; "))
+    }
+}
+
+/// Accounts and checks of one assessment, fitted into its allowance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetPlan {
+    /// Accounts the position checks read: the watchlist and all or a sample of the index
+    pub accounts: Vec<Address>,
+    pub skipped_checks: Vec<&'static str>,
+    pub sampled_accounts: Option<AccountSample>,
+    pub allowance: Option<u64>,
+    pub projected_compute_units: u64,
+}
+
+impl BudgetPlan {
+    /// Plan an assessment of the tracked `accounts`, of which `watchlist` are always read,
+    /// each position costing `position_units`
+    ///
+    /// Without an allowance, or within it, everything is read. A plan that still exceeds the
+    /// allowance after the degradations `settings` allows is returned as it is.
+    pub fn new(
+        settings: &RpcBudgetConfig,
+        allowance: Option<u64>,
+        accounts: Vec<Address>,
+        watchlist: &[Address],
+        position_units: u64,
+    ) -> Self {
+        let watched = accounts.iter().filter(|a| watchlist.contains(a)).count() as u64;
+        // Absorption and the exposure read every account, the watchlist check its own again
+        let cost = |accounts: u64, skip_optional: bool| {
+            if skip_optional {
+                position_units * (accounts + watched)
+            } else {
+                position_units * (2 * accounts + watched) + MANAGER_CHECK_UNITS * watched
+            }
+        };
+        let projected_compute_units = cost(accounts.len() as u64, false);
+        let mut plan = Self {
+            accounts,
+            skipped_checks: Vec::new(),
+            sampled_accounts: None,
+            allowance,
+            projected_compute_units,
+        };
+        let Some(allowance) = allowance else { return plan };
+        if projected_compute_units <= allowance {
+            return plan;
+        }
+
+        let skip_optional = settings.skip_optional_checks;
+        if skip_optional {
+            plan.skipped_checks = OPTIONAL_CHECKS.to_vec();
+        }
+        if cost(plan.accounts.len() as u64, skip_optional) <= allowance || !settings.sample_positions {
+            return plan;
+        }
+
+        let (watched, indexed): (Vec<Address>, Vec<Address>) =
+            plan.accounts.iter().partition(|a| watchlist.contains(a));
+        if indexed.is_empty() {
+            return plan;
+        }
+        let reads_per_account = if skip_optional { 1 } else { 2 };
+        let spare = allowance.saturating_sub(cost(watched.len() as u64, skip_optional));
+        let read = ((spare / (position_units * reads_per_account).max(1)) as usize).min(indexed.len());
+        // Evenly spaced over the index, which is sorted by address and so in no useful order
+        let sample = (0..read).map(|i| indexed[i * indexed.len() / read]);
+        plan.accounts = watched.iter().copied().chain(sample).collect();
+        plan.accounts.sort();
+        plan.sampled_accounts = Some(AccountSample { read, tracked: indexed.len() });
+        plan
+    }
+
+    /// Report of an assessment that ran under this plan and used `usage`
+    pub fn report(&self, usage: RpcUsage) -> RpcReport {
+        RpcReport {
+            usage,
+            allowance: self.allowance,
+            projected_compute_units: self.projected_compute_units,
+            sampled_accounts: self.sampled_accounts,
+            skipped_checks: self.skipped_checks.iter().map(|check| check.to_string()).collect(),
+        }
+    }
+}
+
+/// Estimated compute units of reading one position in `market`: its base balances and one
+/// balance per collateral asset, read one at a time as without Multicall3; positions packed into
+/// `aggregate3` reads cost less, so this is an upper bound
+pub fn position_units(market: &Market) -> u64 {
+    position_units_with(market.collateral_assets.len() as u64)
+}
+
+/// `position_units` of a market with `collateral_assets`
+pub fn position_units_with(collateral_assets: u64) -> u64 {
+    (2 + collateral_assets) * compute_units("eth_call")
+}
+
+/// Requests of reading a market with `collateral_assets` from chain: the chain head, thirteen
+/// Comet reads and the base token's symbol and decimals, then the info, price, totals, symbol
+/// and decimals of every collateral asset
+pub fn market_read_calls(collateral_assets: u64) -> u64 {
+    16 + 5 * collateral_assets
+}
+
+/// Compute units of `market_read_calls`
+pub fn market_read_units(collateral_assets: u64) -> u64 {
+    compute_units("eth_blockNumber") + (market_read_calls(collateral_assets) - 1) * compute_units("eth_call")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts(range: std::ops::Range<u8>) -> Vec<Address> {
+        range.map(Address::repeat_byte).collect()
+    }
+
+    #[test]
+    fn test_plan_skips_optional_checks_then_samples_the_index() {
+        let settings = RpcBudgetConfig::default();
+        let tracked = accounts(1..101);
+        let watchlist = accounts(1..3);
+
+        // Within the allowance, or without one, everything is read
+        let full = BudgetPlan::new(&settings, None, tracked.clone(), &watchlist, 10);
+        assert_eq!(full.projected_compute_units, 10 * (200 + 2) + 2 * MANAGER_CHECK_UNITS);
+        assert_eq!((full.accounts.len(), full.report(RpcUsage::default()).is_degraded()), (100, false));
+
+        // Skipping the optional checks is enough: one read per account
+        let skipped = BudgetPlan::new(&settings, Some(1_500), tracked.clone(), &watchlist, 10);
+        assert_eq!((skipped.accounts.len(), skipped.skipped_checks.as_slice()), (100, OPTIONAL_CHECKS.as_slice()));
+        assert_eq!(skipped.sampled_accounts, None);
+
+        // Sampling keeps the watchlist and spreads the rest over the index
+        let sampled = BudgetPlan::new(&settings, Some(540), tracked.clone(), &watchlist, 10);
+        assert_eq!(sampled.sampled_accounts, Some(AccountSample { read: 50, tracked: 98 }));
+        assert_eq!(sampled.accounts.len(), 52);
+        assert!(watchlist.iter().all(|a| sampled.accounts.contains(a)));
+        assert!(sampled.accounts.contains(&Address::repeat_byte(3)) && sampled.accounts.contains(&Address::repeat_byte(99)));
+        let report = sampled.report(RpcUsage::default());
+        assert_eq!(report.degradation().unwrap(), "sampled 50 of 98 indexed accounts; skipped absorption, manager_permissions");
+
+        // Without degradations allowed the plan runs over budget as it is
+        let strict = RpcBudgetConfig { sample_positions: false, skip_optional_checks: false, ..settings };
+        let over = BudgetPlan::new(&strict, Some(540), tracked, &watchlist, 10);
+        assert_eq!((over.accounts.len(), over.skipped_checks.len(), over.sampled_accounts), (100, 0, None));
+    }
+}
@@ -0,0 +1,290 @@
+//! Assembling a `RiskEngine` from injected components
+//!
+//! `RiskEngine::new` opens everything from the configuration: the Compound client (or the
+//! data source it names), the storage backend and the alert routes. Applications embedding the
+//! crate can bring their own through `RiskEngineBuilder` instead:
+//!
+//! - `provider`: any `MarketDataProvider`, such as a `FixtureProvider`; nothing is opened from
+//!   `compound` and `data_source`
+//! - `scorer`: a `RiskScorer` replacing `RiskScore::of`
+//! - `add_check`: `RiskCheck`s run on every market after the built-in checks
+//! - `storage`: a `Storage` backend replacing the configured one; with
+//!   `storage.postgres.leader_election` enabled it must hold the leases (`Storage::leases`)
+//! - `alert_sink`: `AlertSink`s alerted on besides the configured routes, with the default
+//!   route filter (High and above); routing rules only route configured sinks
+//! - `offline`: open nothing that reaches the network or writes history, for `RiskEngine::plan`
+//!
+//! The configuration is still required, and is resolved and validated before anything is
+//! opened or read over the network.
+
+use crate::alerts::{AlertRoute, AlertSink};
+use crate::config::{Config, RouteFilter};
+use crate::error::{Result, RiskEngineError};
+use crate::leader::Leadership;
+use crate::provider::{MarketDataProvider, SharedProvider};
+use crate::risk::{RiskCheck, RiskScorer};
+use crate::storage::Storage;
+use crate::{audit, labels, metrics, open_provider, progress, resolve_watchlist, storage, RiskEngine};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Builder for a `RiskEngine`; see the module documentation
+#[derive(Default)]
+pub struct RiskEngineBuilder {
+    config: Option<Config>,
+    provider: Option<SharedProvider>,
+    scorer: Option<Arc<dyn RiskScorer>>,
+    checks: Vec<Arc<dyn RiskCheck>>,
+    storage: Option<Arc<dyn Storage>>,
+    alert_routes: Vec<AlertRoute>,
+    offline: bool,
+}
+
+impl RiskEngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configuration of the engine; required
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Read market data from `provider` instead of the one the configuration selects
+    pub fn provider(mut self, provider: impl MarketDataProvider + 'static) -> Self {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Score findings with `scorer` instead of `RiskScore::of`
+    pub fn scorer(mut self, scorer: impl RiskScorer + 'static) -> Self {
+        self.scorer = Some(Arc::new(scorer));
+        self
+    }
+
+    /// Run `check` on every market, after the built-in checks and the checks added before it
+    pub fn add_check(mut self, check: impl RiskCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Write assessments to `storage` instead of the configured backend
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Alert `sink` of findings at or above High, besides the configured routes
+    pub fn alert_sink(mut self, sink: impl AlertSink + 'static) -> Self {
+        self.alert_routes.push(AlertRoute::new(Arc::new(sink), RouteFilter::default()));
+        self
+    }
+
+    /// Leave ENS names in the watchlist unresolved and open neither the configured storage
+    /// backend nor the audit log; the data provider is still built, which sends no request
+    /// by itself
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    /// Validate the configuration, then open what was not injected
+    ///
+    /// Fails without a configuration, or if it does not validate, before anything is opened.
+    pub async fn build(self) -> Result<RiskEngine> {
+        let config = self.config.ok_or_else(|| {
+            RiskEngineError::config("config", "the engine needs a configuration; call `RiskEngineBuilder::config`")
+        })?;
+        let config = config.resolve_env()?;
+        config.validate()?;
+        labels::install(labels::AddressBook::from_config(&config)?);
+        let config = Arc::new(config);
+        let cancel = CancellationToken::new();
+        let metrics = Arc::new(metrics::Metrics::new(config.compound.chain_id));
+        let progress = progress::ProgressReporter::new();
+        let (provider, subgraph) = match self.provider {
+            Some(provider) => (provider, None),
+            None => open_provider(&config, &metrics, &cancel, &progress).await?,
+        };
+        let (config, storage, audit) = if self.offline {
+            (config, self.storage, None)
+        } else {
+            let config = Arc::new(resolve_watchlist(config.as_ref().clone(), &provider).await?);
+            let storage = match self.storage {
+                Some(storage) => Some(storage),
+                None => storage::open(&config.storage).await?,
+            };
+            let audit = audit::AuditLog::from_config(&config)?;
+            (config, storage, audit)
+        };
+        let mut engine = RiskEngine::assemble(config, provider, cancel, metrics);
+        engine.storage = storage;
+        engine.audit = audit;
+        engine.subgraph = Mutex::new(subgraph);
+        engine.progress = progress;
+        engine.checks = self.checks;
+        if let Some(scorer) = self.scorer {
+            engine.scorer = scorer;
+        }
+        engine.alert_routes = self.alert_routes;
+        let election = engine.config().storage.postgres.as_ref().map(|postgres| postgres.leader_election.clone());
+        if let (Some(election), Some(storage)) = (election.filter(|election| election.enabled && !self.offline), &engine.storage) {
+            let leases = storage.clone().leases().ok_or_else(|| {
+                RiskEngineError::config("storage.postgres.leader_election", format!("{} cannot hold leases", storage.name()))
+            })?;
+            let leadership = Leadership::new(leases, election.name.clone(), election.lease(), election.heartbeat());
+            engine = engine.with_leadership(Arc::new(leadership));
+            engine.start_leader_heartbeat().await?;
+        }
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Alert;
+    use crate::annotations::Annotation;
+    use crate::models::Market;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::risk::{finding_fingerprint, RiskAssessment, RiskCategory, RiskFinding, RiskScore, RiskSeverity};
+    use crate::storage::{FindingQuery, FingerprintSpan, MarketSnapshot, ScorePoint, StoredFinding, StoredMarket};
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+    use ethers::types::Address;
+    use std::time::Duration;
+
+    /// Flags every market as Critical
+    struct AlwaysCritical;
+
+    #[async_trait]
+    impl RiskCheck for AlwaysCritical {
+        fn name(&self) -> String {
+            "always_critical".to_string()
+        }
+
+        async fn check(&self, market: &Market, provider: Option<&SharedProvider>, timestamp: DateTime<Utc>) -> Result<Vec<RiskFinding>> {
+            assert!(provider.is_some_and(|p| p.is_mock()));
+            Ok(vec![RiskFinding {
+                category: RiskCategory::HighUtilization,
+                severity: RiskSeverity::Critical,
+                description: format!("{} flagged by the embedding application", market.name),
+                metadata: serde_json::json!({}),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, "always"),
+                score_contribution: 0,
+            }])
+        }
+    }
+
+    /// Keeps assessments in memory
+    #[derive(Clone, Default)]
+    struct RecordingStorage(Arc<Mutex<Vec<RiskAssessment>>>);
+
+    #[async_trait]
+    impl Storage for RecordingStorage {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+
+        async fn record(&self, _chain_id: u64, _market: &Market, assessment: &RiskAssessment) -> Result<()> {
+            self.0.lock().unwrap().push(assessment.clone());
+            Ok(())
+        }
+
+        async fn markets(&self) -> Result<Vec<StoredMarket>> {
+            Ok(Vec::new())
+        }
+
+        async fn score_series(&self, _market: Address, _since: DateTime<Utc>, _until: DateTime<Utc>) -> Result<Vec<ScorePoint>> {
+            Ok(Vec::new())
+        }
+
+        async fn snapshots(&self, _market: Address, _since: DateTime<Utc>, _until: DateTime<Utc>) -> Result<Vec<MarketSnapshot>> {
+            Ok(Vec::new())
+        }
+
+        async fn latest_snapshots(&self, _market: Address, _until: DateTime<Utc>, _count: u32) -> Result<Vec<MarketSnapshot>> {
+            Ok(Vec::new())
+        }
+
+        async fn findings(&self, _query: &FindingQuery) -> Result<Vec<StoredFinding>> {
+            Ok(Vec::new())
+        }
+
+        async fn fingerprint_spans(&self, _query: &FindingQuery) -> Result<Vec<FingerprintSpan>> {
+            Ok(Vec::new())
+        }
+
+        async fn annotate(&self, _annotation: &Annotation) -> Result<()> {
+            Ok(())
+        }
+
+        async fn annotations(&self, _fingerprints: &[String]) -> Result<Vec<Annotation>> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Collects the alerts it is sent
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<Alert>>>);
+
+    #[async_trait]
+    impl AlertSink for RecordingSink {
+        fn name(&self) -> String {
+            "recording".to_string()
+        }
+
+        async fn send(&self, alert: &Alert) -> Result<()> {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fully_injected_engine_assesses_without_network() {
+        let storage = RecordingStorage::default();
+        let sink = RecordingSink::default();
+        let engine = RiskEngine::builder()
+            .config(Config::default())
+            .provider(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap())
+            // Every finding counts 10 points
+            .scorer(|findings: &[RiskFinding]| {
+                let contributions = vec![10; findings.len()];
+                RiskScore { total: contributions.iter().sum::<u8>().min(100), contributions }
+            })
+            .add_check(AlwaysCritical)
+            .storage(storage.clone())
+            .alert_sink(sink.clone())
+            .build()
+            .await
+            .unwrap();
+
+        engine.run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+        let stored = storage.0.lock().unwrap().clone();
+        assert_eq!(stored.len(), 1);
+        let assessment = &stored[0];
+        // Followed only by the note that there is no history to compare with yet
+        let custom = &assessment.findings[assessment.findings.len() - 2];
+        assert_eq!(custom.description, "USDC flagged by the embedding application");
+        assert_eq!(custom.score_contribution, 10);
+        assert_eq!(usize::from(assessment.risk_score), 10 * (assessment.findings.len() - 1));
+        assert_eq!(assessment.check_versions["always_critical"], 1);
+
+        let alerts = sink.0.lock().unwrap();
+        assert!(alerts.iter().any(|alert| alert.finding.fingerprint == custom.fingerprint), "{:?}", alerts.len());
+    }
+
+    #[tokio::test]
+    async fn test_configuration_is_required_and_validated_first() {
+        let missing = RiskEngineBuilder::new().build().await.err().unwrap();
+        assert!(missing.to_string().contains("needs a configuration"), "{}", missing);
+
+        // An invalid RPC URL fails validation before the provider is opened
+        let mut config = Config::default();
+        config.compound.rpc_url = "not a url".to_string();
+        let invalid = RiskEngine::builder().config(config).build().await.err().unwrap();
+        assert!(matches!(invalid, RiskEngineError::InvalidConfig { .. }), "{}", invalid);
+    }
+}
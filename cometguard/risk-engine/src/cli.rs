@@ -0,0 +1,3 @@
+//! Terminal output of `risk-engine-cli` beyond the plain-text sections in `report`
+
+pub mod render;
@@ -0,0 +1,552 @@
+//! Tables for the `assess` report: a summary row per market, then each market's health factor
+//! histogram and findings;
+//! the `list-markets` table of what the engine monitors, the `top-borrowers` and
+//! `top-suppliers` rankings, and the positions of a `scan-positions` list
+//!
+//! Colors mark score bands and severities. They are only used on a terminal, and never when
+//! `NO_COLOR` is set or `--no-color` is given; off a terminal the tables lose their borders
+//! too, leaving plain aligned columns that are easy to grep. Amounts, percentages and health
+//! factors use the separators of `display.number_style`, and amounts are shown in the unit of
+//! `display.denomination`, which the header of their column names.
+
+use crate::compare::AssessmentPoint;
+use crate::denomination::Units;
+use crate::health_factors::HealthFactorDistribution;
+use crate::inventory::{DataOrigin, MarketListing};
+use crate::position_scan::PositionScan;
+use crate::ranking::{PositionSort, TopPositions};
+use crate::report::mock_tag;
+use crate::risk::{RiskAssessment, RiskSeverity};
+use crate::utils::{format_address, format_address_labeled, format_named_address, format_percentage, sanitize_inline, NumberStyle};
+use ethers::types::Address;
+use comfy_table::presets::{NOTHING, UTF8_FULL_CONDENSED};
+use comfy_table::{Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+/// How tables are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions<'a> {
+    /// Color score and severity cells
+    pub color: bool,
+    /// Draw borders and fit the tables to the terminal width
+    pub terminal: bool,
+    /// Separators of the numbers in cells
+    pub numbers: NumberStyle,
+    /// Units of the markets' amounts; USD when unset
+    pub units: Option<&'a Units>,
+}
+
+impl<'a> RenderOptions<'a> {
+    /// Options for stdout: borders on a terminal, colors there too unless `no_color` or `NO_COLOR`
+    pub fn detect(no_color: bool) -> Self {
+        let terminal = std::io::stdout().is_terminal();
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self { color: terminal && !no_color && !no_color_env, terminal, numbers: NumberStyle::default(), units: None }
+    }
+
+    /// Plain aligned columns without colors, as written to a file or pipe
+    pub fn plain() -> Self {
+        Self { color: false, terminal: false, numbers: NumberStyle::default(), units: None }
+    }
+
+    /// These options with `numbers` separators, as `display.number_style` sets
+    pub fn with_numbers(self, numbers: NumberStyle) -> Self {
+        Self { numbers, ..self }
+    }
+
+    /// These options with amounts in `units`, as `display.denomination` sets
+    pub fn with_units(self, units: &'a Units) -> Self {
+        Self { units: Some(units), ..self }
+    }
+
+    /// USD amount of the market at `market`, abbreviated, in its unit
+    fn money(&self, market: &Address, value: f64) -> String {
+        self.units.map(|units| units.of(market)).unwrap_or_default().format(value, self.numbers)
+    }
+
+    /// Unit of the amounts of `markets`, as `Units::label` names it
+    fn unit_label(&self, markets: &[Address]) -> String {
+        self.units.map_or_else(|| "USD".to_string(), |units| units.label(markets))
+    }
+
+    /// Column header `name` with the unit of the amounts of `markets`, e.g. `TVL (USD)`
+    fn amount_header(&self, name: &str, markets: &[Address]) -> String {
+        format!("{} ({})", name, self.unit_label(markets))
+    }
+
+    fn percent(&self, value: f64) -> String {
+        self.numbers.localize(&format_percentage(value))
+    }
+
+    /// Health factor with two decimals
+    fn health_factor(&self, value: f64) -> String {
+        self.numbers.localize(&format!("{:.2}", value))
+    }
+
+    fn table(&self, header: &[&str]) -> Table {
+        let mut table = Table::new();
+        if self.terminal {
+            table.load_preset(UTF8_FULL_CONDENSED).set_content_arrangement(ContentArrangement::Dynamic);
+        } else {
+            table.load_preset(NOTHING).force_no_tty();
+        }
+        if self.color {
+            table.enforce_styling();
+        }
+        table.set_header(header.iter().map(|h| Cell::new(h).add_attribute(Attribute::Bold)));
+        table
+    }
+
+    /// `table` as text; plain tables lose the padding after their last column
+    fn finish(&self, table: Table) -> String {
+        if self.terminal {
+            table.to_string()
+        } else {
+            table.lines().map(|line| line.trim_end().to_string()).collect::<Vec<_>>().join("\n")
+        }
+    }
+
+    fn colored(&self, cell: Cell, color: Color, bold: bool) -> Cell {
+        match (self.color, bold) {
+            (false, _) => cell,
+            (true, false) => cell.fg(color),
+            (true, true) => cell.fg(color).add_attribute(Attribute::Bold),
+        }
+    }
+
+    fn severity_cell(&self, severity: RiskSeverity) -> Cell {
+        let (color, bold) = match severity {
+            RiskSeverity::Low => (Color::Blue, false),
+            RiskSeverity::Medium => (Color::Yellow, false),
+            RiskSeverity::High => (Color::Red, false),
+            RiskSeverity::Critical => (Color::Red, true),
+        };
+        self.colored(Cell::new(format!("{:?}", severity)), color, bold)
+    }
+
+    fn score_cell(&self, assessment: &RiskAssessment) -> Cell {
+        // The bands of the dashboard's score column
+        let color = match assessment.risk_score {
+            0..=29 => Color::Green,
+            30..=59 => Color::Yellow,
+            _ => Color::Red,
+        };
+        self.colored(Cell::new(assessment.score_label()), color, false)
+    }
+}
+
+/// Summary table of `points`, followed by a findings table for each market that has findings
+pub fn assessment_report(points: &[AssessmentPoint], options: RenderOptions) -> String {
+    let mut report = summary_table(points, options);
+    for point in points {
+        let assessment = &point.assessment;
+        write!(report, "\n\n{}\n", market_label(point)).unwrap();
+        if let Some(partial) = assessment.rpc.as_ref().and_then(|rpc| rpc.degradation()) {
+            writeln!(report, "⚠️  Partial within the RPC budget: {}", partial).unwrap();
+        }
+        if let Some(degraded) = assessment.data_degradation() {
+            writeln!(report, "⚠️  Degraded data, findings at low confidence: {}", degraded).unwrap();
+        }
+        if let Some(baseline) = assessment.baseline.as_ref().and_then(|baseline| baseline.summary()) {
+            writeln!(report, "Against its history: {}", baseline).unwrap();
+        }
+        if !assessment.category_scores.is_empty() {
+            report.push_str(&category_table(assessment, options));
+            report.push('\n');
+        }
+        if let Some(distribution) = &assessment.health_factors {
+            report.push_str(&health_factor_histogram(distribution, &assessment.market_address, options));
+        }
+        if assessment.findings.is_empty() {
+            report.push_str("✅ No risks identified");
+            continue;
+        }
+        let mut table = options.table(&["#", "SEVERITY", "POINTS", "CATEGORY", "FINDING"]);
+        for (i, finding) in assessment.findings.iter().enumerate() {
+            table.add_row(vec![
+                Cell::new(i + 1).set_alignment(CellAlignment::Right),
+                options.severity_cell(finding.severity),
+                Cell::new(format!("+{}", finding.score_contribution)).set_alignment(CellAlignment::Right),
+                Cell::new(format!("{:?}", finding.category)),
+                Cell::new(match assessment.annotations.get(&finding.fingerprint) {
+                    Some(annotation) => format!("{}\nNote: {}", sanitize_inline(&finding.description), annotation),
+                    None => sanitize_inline(&finding.description),
+                }),
+            ]);
+        }
+        report.push_str(&options.finish(table));
+    }
+    report.push('\n');
+    report
+}
+
+/// One row per market: score, utilization, TVL, base rates with net rates (COMP rewards
+/// included) in parentheses, and the most severe finding
+pub fn summary_table(points: &[AssessmentPoint], options: RenderOptions) -> String {
+    let markets: Vec<Address> = points.iter().map(|point| point.assessment.market_address).collect();
+    let tvl = options.amount_header("TVL", &markets);
+    let mut table = options.table(&["MARKET", "SCORE", "UTILIZATION", &tvl, "SUPPLY APR (NET)", "BORROW APR (NET)", "WORST"]);
+    for point in points {
+        let assessment = &point.assessment;
+        let worst = match assessment.findings.iter().map(|f| f.severity).max() {
+            Some(severity) => options.severity_cell(severity),
+            None => Cell::new("-"),
+        };
+        let metric = |value: Option<String>| Cell::new(value.unwrap_or_else(|| "-".to_string())).set_alignment(CellAlignment::Right);
+        let rates = point.metrics.as_ref().and_then(|m| m.rates);
+        let rate = |base: f64, net: f64| format!("{} ({})", options.percent(base), options.percent(net));
+        table.add_row(vec![
+            Cell::new(market_label(point)),
+            options.score_cell(assessment).set_alignment(CellAlignment::Right),
+            metric(point.metrics.as_ref().map(|m| options.percent(m.utilization_rate))),
+            metric(point.metrics.as_ref().map(|m| options.money(&assessment.market_address, m.tvl_usd))),
+            metric(rates.map(|r| rate(r.supply_apr, r.net_supply_apr))),
+            metric(rates.map(|r| rate(r.borrow_apr, r.net_borrow_apr))),
+            worst,
+        ]);
+    }
+    options.finish(table)
+}
+
+/// One row per monitored market: where it is, its size, and where the numbers came from
+pub fn markets_table(listings: &[MarketListing], options: RenderOptions) -> String {
+    let markets: Vec<Address> = listings.iter().map(|listing| listing.comet_address).collect();
+    let tvl = options.amount_header("TVL", &markets);
+    let mut table = options.table(&["NETWORK", "MARKET", "BASE", "COLLATERALS", "UTILIZATION", &tvl, "DATA"]);
+    for listing in listings {
+        let network = match listing.network {
+            Some(network) => format!("{:?} ({})", network, listing.chain_id),
+            None => format!("chain {}", listing.chain_id),
+        };
+        let origin = match listing.origin {
+            DataOrigin::Live => "live",
+            DataOrigin::Cache => "cache",
+            DataOrigin::Subgraph => "subgraph",
+            DataOrigin::Mock => "mock",
+        };
+        let right = |text: String| Cell::new(text).set_alignment(CellAlignment::Right);
+        let origin = match listing.origin {
+            DataOrigin::Mock => options.colored(Cell::new(origin), Color::Yellow, false),
+            _ => Cell::new(origin),
+        };
+        table.add_row(vec![
+            Cell::new(network),
+            Cell::new(sanitize_inline(&format_named_address(&listing.name, &listing.comet_address))),
+            Cell::new(sanitize_inline(&listing.base_symbol)),
+            right(listing.collaterals.to_string()),
+            right(options.percent(listing.utilization_rate)),
+            right(options.money(&listing.comet_address, listing.tvl_usd)),
+            origin,
+        ]);
+    }
+    options.finish(table)
+}
+
+/// One row per ranked position, largest first, with its collateral by asset
+pub fn top_positions_table(top: &TopPositions, options: RenderOptions) -> String {
+    let market = top.market_address;
+    let value_header = match top.sort {
+        PositionSort::Borrow => options.amount_header("BORROW", &[market]),
+        PositionSort::Supply => options.amount_header("SUPPLY", &[market]),
+    };
+    let collateral_header = options.amount_header("COLLATERAL", &[market]);
+    // Only when the provider lists other markets the accounts may also borrow in
+    let across_markets = top.positions.iter().any(|ranked| ranked.all_markets_borrow_usd.is_some());
+    // Borrow in other markets is in this market's unit too
+    let across_header = options.amount_header("ALL MARKETS BORROW", &[market]);
+    let mut headers = vec!["#", "ACCOUNT", value_header.as_str(), "SHARE", "HEALTH", collateral_header.as_str()];
+    if across_markets {
+        headers.push(&across_header);
+    }
+    let mut table = options.table(&headers);
+    for (i, ranked) in top.positions.iter().enumerate() {
+        let account = match &ranked.account_name {
+            Some(name) => format!("{} ({})", sanitize_inline(name), format_address(&ranked.address)),
+            None => format_address_labeled(&ranked.address),
+        };
+        let value = match top.sort {
+            PositionSort::Borrow => ranked.borrow_value_usd,
+            PositionSort::Supply => ranked.supply_value_usd,
+        };
+        // Without a borrow the health factor is unbounded
+        let health = if ranked.borrow_value_usd > 0.0 {
+            let cell = Cell::new(options.health_factor(ranked.health_factor));
+            match ranked.health_factor {
+                hf if hf < 1.0 => options.colored(cell, Color::Red, true),
+                hf if hf < 1.1 => options.colored(cell, Color::Yellow, false),
+                _ => cell,
+            }
+        } else {
+            Cell::new("-")
+        };
+        let total_collateral: f64 = ranked.collateral.iter().map(|c| c.value_usd).sum();
+        let collateral = ranked
+            .collateral
+            .iter()
+            .map(|c| format!("{} {} ({:.0}%)", sanitize_inline(&c.symbol), options.money(&market, c.value_usd), c.value_usd / total_collateral * 100.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut row = vec![
+            Cell::new(i + 1).set_alignment(CellAlignment::Right),
+            Cell::new(account),
+            Cell::new(options.money(&market, value)).set_alignment(CellAlignment::Right),
+            Cell::new(options.percent(ranked.market_share)).set_alignment(CellAlignment::Right),
+            health.set_alignment(CellAlignment::Right),
+            Cell::new(if collateral.is_empty() { "-".to_string() } else { collateral }),
+        ];
+        if across_markets {
+            let borrow = match (ranked.all_markets_borrow_usd, ranked.protocol_borrow_share) {
+                (Some(borrow), Some(share)) => format!("{} ({})", options.money(&market, borrow), options.percent(share)),
+                _ => "-".to_string(),
+            };
+            row.push(Cell::new(borrow).set_alignment(CellAlignment::Right));
+        }
+        table.add_row(row);
+    }
+    options.finish(table)
+}
+
+/// One row per scanned position, lowest health factor first, with the severity of its
+/// liquidation finding
+pub fn position_scan_table(scan: &PositionScan, options: RenderOptions) -> String {
+    let market = scan.market_address;
+    let borrow = options.amount_header("BORROW", &[market]);
+    let collateral = options.amount_header("COLLATERAL", &[market]);
+    let mut table = options.table(&["ACCOUNT", "HEALTH", &borrow, &collateral, "RISK"]);
+    for scanned in &scan.positions {
+        let position = &scanned.position;
+        let health = if position.total_borrow_value > 0.0 { options.health_factor(position.health_factor) } else { "-".to_string() };
+        let risk = match &scanned.finding {
+            Some(finding) => options.severity_cell(finding.severity),
+            None => Cell::new("-"),
+        };
+        table.add_row(vec![
+            Cell::new(format_address_labeled(&position.address)),
+            Cell::new(health).set_alignment(CellAlignment::Right),
+            Cell::new(options.money(&market, position.total_borrow_value)).set_alignment(CellAlignment::Right),
+            Cell::new(options.money(&market, position.total_collateral_value)).set_alignment(CellAlignment::Right),
+            risk,
+        ]);
+    }
+    options.finish(table)
+}
+
+/// Widest bar of `health_factor_histogram`, in characters
+const HISTOGRAM_WIDTH: usize = 30;
+
+/// ASCII histogram of `distribution` of the market at `market`, one line per bucket with bars
+/// by borrow
+pub fn health_factor_histogram(distribution: &HealthFactorDistribution, market: &Address, options: RenderOptions) -> String {
+    let label_width = distribution.buckets.iter().map(|b| b.label.chars().count()).max().unwrap_or(0);
+    let largest = distribution.buckets.iter().fold(0.0, |max: f64, b| max.max(b.borrow_usd));
+    let mut histogram = format!("Health factors of tracked positions (borrow in {})\n", options.unit_label(&[*market]));
+    for bucket in &distribution.buckets {
+        let bar = if largest > 0.0 && bucket.borrow_usd > 0.0 {
+            ((bucket.borrow_usd / largest * HISTOGRAM_WIDTH as f64).round() as usize).max(1)
+        } else {
+            0
+        };
+        writeln!(
+            histogram,
+            "  {:<label_width$}  {:<HISTOGRAM_WIDTH$}  {:>6}  {}",
+            options.numbers.localize(&bucket.label),
+            "#".repeat(bar),
+            bucket.positions,
+            options.money(market, bucket.borrow_usd),
+        )
+        .unwrap();
+    }
+    histogram
+}
+
+/// Sub-score of each category with the way its trend is heading; categories no check
+/// evaluated say so rather than show a score
+pub fn category_table(assessment: &RiskAssessment, options: RenderOptions) -> String {
+    let mut table = options.table(&["CATEGORY", "SCORE", "TREND"]);
+    for (category, score) in &assessment.category_scores {
+        let trend = assessment.trend.as_ref().and_then(|trend| trend.categories.get(category));
+        table.add_row(vec![
+            Cell::new(format!("{:?}", category)),
+            Cell::new(score.map_or_else(|| "not evaluated".to_string(), |score| format!("{}/100", score))).set_alignment(CellAlignment::Right),
+            Cell::new(trend.filter(|_| score.is_some()).map_or("", |trend| trend.direction.arrow())),
+        ]);
+    }
+    options.finish(table)
+}
+
+fn market_label(point: &AssessmentPoint) -> String {
+    let assessment = &point.assessment;
+    format!(
+        "{}{}",
+        sanitize_inline(&format_named_address(&assessment.market_name, &assessment.market_address)),
+        mock_tag(assessment.mock_data)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::MarketMetrics;
+    use crate::models::InterestRates;
+    use crate::risk::{RiskAssessment, RiskCategory, RiskFinding, RiskScore};
+    use crate::testing::{assessment, finding};
+    use ethers::types::Address;
+
+    fn point(name: &str, score: u8, findings: Vec<(RiskSeverity, &str)>, tvl_usd: Option<f64>) -> AssessmentPoint {
+        let mut findings: Vec<RiskFinding> = findings
+            .into_iter()
+            .map(|(severity, description)| RiskFinding { description: description.to_string(), ..finding("", severity) })
+            .collect();
+        RiskScore::of(&findings).attribute(&mut findings);
+        AssessmentPoint {
+            assessment: RiskAssessment {
+                market_name: name.to_string(),
+                market_address: Address::repeat_byte(score),
+                ..assessment(score, findings)
+            },
+            metrics: tvl_usd.map(|tvl_usd| MarketMetrics {
+                utilization_rate: 0.85,
+                tvl_usd,
+                reserves: None,
+                rates: Some(InterestRates { supply_apr: 0.048, borrow_apr: 0.067, net_supply_apr: 0.0542, net_borrow_apr: 0.0585 }),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_plain_report_is_aligned_without_colors() {
+        let points = vec![
+            point("USDC", 45, vec![(RiskSeverity::Medium, "Utilization is 85%"), (RiskSeverity::High, "Oracle\nstale")], Some(2_500_000.0)),
+            point("WETH", 5, Vec::new(), None),
+        ];
+        let report = assessment_report(&points, RenderOptions::plain());
+        assert!(!report.contains('\u{1b}'), "{}", report);
+        assert_eq!(
+            report.lines().collect::<Vec<_>>(),
+            [
+                " MARKET                SCORE   UTILIZATION  TVL (USD)  SUPPLY APR (NET)  BORROW APR (NET)  WORST",
+                " USDC (0x2d2d...2D2d)  45/100       85.00%     $2.50M     4.80% (5.42%)     6.70% (5.85%)  High",
+                " WETH (0x0505...0505)   5/100            -          -                 -                 -  -",
+                "",
+                "USDC (0x2d2d...2D2d)",
+                " #  SEVERITY  POINTS  CATEGORY         FINDING",
+                " 1  Medium       +15  HighUtilization  Utilization is 85%",
+                " 2  High         +30  HighUtilization  Oracle stale",
+                "",
+                "WETH (0x0505...0505)",
+                "✅ No risks identified",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_health_factor_histogram_scales_bars_by_borrow() {
+        use crate::models::UserPosition;
+        let position = |borrow: f64, health_factor: f64| UserPosition {
+            address: Address::random(),
+            base_balance: -borrow,
+            collateral_balances: Default::default(),
+            total_collateral_value: borrow * health_factor,
+            total_borrow_value: borrow,
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        let positions = [position(3_000.0, 0.9), position(100.0, 1.2), position(1_500.0, 1.6), position(0.0, f64::INFINITY)];
+        let mut point = point("USDC", 45, Vec::new(), None);
+        point.assessment.health_factors = Some(HealthFactorDistribution::new(&positions, &[1.1, 1.5]));
+        let report = assessment_report(&[point], RenderOptions::plain());
+        let lines: Vec<&str> = report.lines().skip_while(|line| !line.starts_with("Health factors")).take(6).collect();
+        assert_eq!(
+            lines,
+            [
+                "Health factors of tracked positions (borrow in USD)",
+                "  <1.0       ##############################       1  $3.00K",
+                "  1.0-1.1                                         0  $0.00",
+                "  1.1-1.5    #                                    1  $100",
+                "  1.5+       ###############                      1  $1.50K",
+                "  no borrow                                       1  $0.00",
+            ]
+        );
+
+        let distribution = HealthFactorDistribution::new(&positions, &[1.1, 1.5]);
+        let decimal_comma = health_factor_histogram(&distribution, &Address::zero(), RenderOptions::plain().with_numbers(NumberStyle::DecimalComma));
+        assert!(decimal_comma.contains("\n  1,0-1,1    ") && decimal_comma.contains("  $3,00K\n"), "{}", decimal_comma);
+    }
+
+    #[test]
+    fn test_category_table_tells_unevaluated_from_zero() {
+        use crate::trend::{Direction, ScoreTrend};
+        let mut point = point("USDC", 45, vec![(RiskSeverity::High, "Utilization is 95%")], None);
+        point.assessment.category_scores =
+            [(RiskCategory::HighUtilization, Some(30)), (RiskCategory::PriceVolatility, Some(0)), (RiskCategory::BadDebt, None)].into_iter().collect();
+        let trend = ScoreTrend {
+            smoothed_score: 30.0,
+            previous_smoothed_score: Some(20.0),
+            delta_24h: None,
+            delta_7d: None,
+            slope_per_day: Some(10.0),
+            direction: Direction::Deteriorating,
+            categories: Default::default(),
+        };
+        point.assessment.trend = Some(ScoreTrend { categories: [(RiskCategory::HighUtilization, trend.clone())].into_iter().collect(), ..trend });
+        let report = assessment_report(&[point], RenderOptions::plain());
+        let lines: Vec<&str> = report.lines().skip_while(|line| !line.starts_with(" CATEGORY  ")).take(4).collect();
+        assert_eq!(
+            lines,
+            [
+                " CATEGORY         SCORE          TREND",
+                " HighUtilization         30/100  ↑",
+                " PriceVolatility          0/100",
+                " BadDebt          not evaluated",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colors_only_when_enabled() {
+        let points = vec![point("USDC", 75, vec![(RiskSeverity::Critical, "Underwater")], Some(1000.0))];
+        let bordered = assessment_report(&points, RenderOptions { color: false, terminal: true, ..RenderOptions::plain() });
+        assert!(bordered.contains('│') && !bordered.contains('\u{1b}'), "{}", bordered);
+        let colored = assessment_report(&points, RenderOptions { color: true, terminal: true, ..RenderOptions::plain() });
+        assert!(colored.contains('\u{1b}'), "{}", colored);
+    }
+
+    #[test]
+    fn test_markets_table_names_network_and_origin() {
+        let listing = |chain_id: u64, origin: DataOrigin| MarketListing {
+            chain_id,
+            network: crate::config::Network::from_chain_id(chain_id),
+            name: "USDC".to_string(),
+            comet_address: Address::repeat_byte(0x2d),
+            base_symbol: "USDC".to_string(),
+            collaterals: 5,
+            utilization_rate: 0.9,
+            tvl_usd: 2_500_000.0,
+            origin,
+        };
+        let table = markets_table(&[listing(8453, DataOrigin::Cache), listing(77, DataOrigin::Mock)], RenderOptions::plain());
+        assert_eq!(
+            table.lines().collect::<Vec<_>>(),
+            [
+                " NETWORK      MARKET                BASE  COLLATERALS  UTILIZATION  TVL (USD)  DATA",
+                " Base (8453)  USDC (0x2d2d...2D2d)  USDC            5       90.00%     $2.50M  cache",
+                " chain 77     USDC (0x2d2d...2D2d)  USDC            5       90.00%     $2.50M  mock",
+            ]
+        );
+
+        // In `base`, a market whose base asset is WETH shows its TVL in WETH
+        let provider = crate::provider::FixtureProvider::from_file(&crate::provider::bundled_fixture("basic.json")).unwrap();
+        let mut weth = provider.data().markets[0].clone();
+        weth.comet_address = Address::repeat_byte(0x2d);
+        weth.base_asset = weth.collateral_assets.values().find(|asset| asset.symbol == "WETH").unwrap().clone();
+        let mut config = crate::config::Config::default();
+        config.display.denomination = crate::denomination::Denomination::Base;
+        let units = Units::new(&config, &[weth.clone()]);
+        let listing = MarketListing { tvl_usd: 2_500.0 * weth.base_asset.price, ..listing(8453, DataOrigin::Cache) };
+        let table = markets_table(&[listing], RenderOptions::plain().with_units(&units));
+        assert!(table.lines().next().unwrap().ends_with("TVL (WETH)  DATA") && table.contains("2.50K WETH  cache"), "{}", table);
+    }
+}
@@ -0,0 +1,238 @@
+//! Collateral parameters governance lowered since the previous assessment
+//!
+//! Setting an asset's collateral factor to 0 is how Compound delists collateral: positions
+//! holding it can no longer borrow against it and are pushed to repay or be liquidated. A
+//! lower borrow or liquidate collateral factor does the same by degrees, a higher liquidation
+//! penalty (a lower `liquidationFactor` in Comet) takes more of what liquidated positions
+//! hold, and a supply cap dropped below the amount supplied stops anyone adding that
+//! collateral to defend a position. Each
+//! stored market snapshot records the parameters of every collateral asset;
+//! `CollateralChange::between` compares the previous snapshot's with the market as assessed
+//! now, and the `collateral_changes` check reports each change with the value of the
+//! collateral it affects and the tracked positions holding most of it.
+
+use crate::models::Market;
+use crate::utils::{format_compact, format_token_amount, u256_to_f64};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Parameters of one collateral asset as a market snapshot stores them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollateralParameters {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub asset: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    /// Borrow collateral factor
+    pub collateral_factor: f64,
+    /// Liquidate collateral factor, `Asset::liquidation_factor`; snapshots stored before the
+    /// rename call it `liquidation_factor`
+    #[serde(alias = "liquidation_factor")]
+    pub liquidate_collateral_factor: f64,
+    /// Share of the collateral value an absorption takes, 1 minus Comet's `liquidationFactor`;
+    /// `None` in snapshots stored before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub liquidation_penalty: Option<f64>,
+    /// Supply cap in asset units
+    pub supply_cap: f64,
+    /// Amount supplied in asset units
+    pub total_supplied: f64,
+    /// Amount the protocol holds from absorptions, in asset units; `None` where the source did
+    /// not report it, and in snapshots stored before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_held: Option<f64>,
+}
+
+impl CollateralParameters {
+    /// Parameters of every collateral asset of `market`, by address
+    pub fn of(market: &Market) -> Vec<Self> {
+        let mut parameters: Vec<Self> = market
+            .collateral_assets
+            .values()
+            .map(|asset| Self {
+                asset: asset.address,
+                symbol: asset.symbol.clone(),
+                decimals: asset.decimals,
+                collateral_factor: asset.collateral_factor,
+                liquidate_collateral_factor: asset.liquidation_factor,
+                liquidation_penalty: Some(asset.liquidation_penalty),
+                supply_cap: u256_to_f64(asset.supply_cap, asset.decimals),
+                total_supplied: asset.total_supplied,
+                protocol_held: asset.protocol_held,
+            })
+            .collect();
+        parameters.sort_by_key(|p| p.asset);
+        parameters
+    }
+}
+
+/// What governance changed about a collateral asset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CollateralChangeKind {
+    /// Collateral factor set to 0: the asset no longer backs any borrow
+    Delisted { previous_factor: f64 },
+    /// Collateral factor lowered, but not to 0
+    CollateralFactorReduced { previous: f64, current: f64 },
+    /// Liquidate collateral factor lowered: positions holding the asset become liquidatable
+    /// sooner
+    LiquidateCollateralFactorReduced { previous: f64, current: f64 },
+    /// Liquidation penalty raised (Comet's `liquidationFactor` lowered): absorbed positions are
+    /// credited less of their collateral
+    LiquidationPenaltyRaised { previous: f64, current: f64 },
+    /// Supply cap lowered below the amount supplied, so no more of the asset can be added
+    SupplyCapBelowUsage { previous: f64, current: f64, supplied: f64 },
+}
+
+impl CollateralChangeKind {
+    /// Short name, used in fingerprints
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Delisted { .. } => "delisted",
+            Self::CollateralFactorReduced { .. } => "collateral_factor_reduced",
+            Self::LiquidateCollateralFactorReduced { .. } => "liquidate_collateral_factor_reduced",
+            Self::LiquidationPenaltyRaised { .. } => "liquidation_penalty_raised",
+            Self::SupplyCapBelowUsage { .. } => "supply_cap_below_usage",
+        }
+    }
+}
+
+/// A change to one collateral asset since the previous snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollateralChange {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub asset: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    #[serde(flatten)]
+    pub kind: CollateralChangeKind,
+    /// Value of the asset supplied to the market, in USD at the current price
+    pub collateral_usd: f64,
+}
+
+impl CollateralChange {
+    /// Changes from the collateral parameters `previous` to those of `market` now
+    ///
+    /// Assets listed only on one side are not compared, nor penalties a snapshot did not record.
+    /// An asset whose collateral factor went to 0 is `Delisted` and not also
+    /// `CollateralFactorReduced`.
+    pub fn between(previous: &[CollateralParameters], market: &Market) -> Vec<Self> {
+        let mut changes = Vec::new();
+        for current in CollateralParameters::of(market) {
+            let Some(before) = previous.iter().find(|p| p.asset == current.asset) else { continue };
+            let price = market.collateral_assets.get(&current.asset).map_or(0.0, |asset| asset.price);
+            let mut change = |kind| {
+                changes.push(Self {
+                    asset: current.asset,
+                    symbol: current.symbol.clone(),
+                    decimals: current.decimals,
+                    kind,
+                    collateral_usd: current.total_supplied * price,
+                })
+            };
+            if current.collateral_factor <= 0.0 && before.collateral_factor > 0.0 {
+                change(CollateralChangeKind::Delisted { previous_factor: before.collateral_factor });
+            } else if current.collateral_factor < before.collateral_factor {
+                change(CollateralChangeKind::CollateralFactorReduced { previous: before.collateral_factor, current: current.collateral_factor });
+            }
+            if current.liquidate_collateral_factor < before.liquidate_collateral_factor {
+                change(CollateralChangeKind::LiquidateCollateralFactorReduced {
+                    previous: before.liquidate_collateral_factor,
+                    current: current.liquidate_collateral_factor,
+                });
+            }
+            if let (Some(previous), Some(penalty)) = (before.liquidation_penalty, current.liquidation_penalty) {
+                if penalty > previous {
+                    change(CollateralChangeKind::LiquidationPenaltyRaised { previous, current: penalty });
+                }
+            }
+            if current.supply_cap < before.supply_cap && current.supply_cap < current.total_supplied {
+                change(CollateralChangeKind::SupplyCapBelowUsage {
+                    previous: before.supply_cap,
+                    current: current.supply_cap,
+                    supplied: current.total_supplied,
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// `WETH delisted (collateral factor 0.825 → 0), $120M supplied`
+impl fmt::Display for CollateralChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CollateralChangeKind::Delisted { previous_factor } => {
+                write!(f, "{} delisted (collateral factor {:.3} → 0)", self.symbol, previous_factor)?
+            }
+            CollateralChangeKind::CollateralFactorReduced { previous, current } => {
+                write!(f, "{} collateral factor lowered {:.3} → {:.3}", self.symbol, previous, current)?
+            }
+            CollateralChangeKind::LiquidateCollateralFactorReduced { previous, current } => {
+                write!(f, "{} liquidate collateral factor lowered {:.3} → {:.3}", self.symbol, previous, current)?
+            }
+            CollateralChangeKind::LiquidationPenaltyRaised { previous, current } => {
+                write!(f, "{} liquidation penalty raised {:.1}% → {:.1}%", self.symbol, previous * 100.0, current * 100.0)?
+            }
+            CollateralChangeKind::SupplyCapBelowUsage { previous, current, supplied } => {
+                let amount = |value: f64| format_token_amount(value, &self.symbol, self.decimals);
+                write!(f, "{} supply cap lowered {} → {}, below the {} supplied", self.symbol, amount(*previous), amount(*current), amount(*supplied))?
+            }
+        }
+        write!(f, ", {} supplied", format_compact(self.collateral_usd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use ethers::types::U256;
+
+    #[test]
+    fn test_lowered_parameters_are_changes_and_raised_ones_are_not() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let before = provider.data().markets[0].clone();
+        let weth = *before.collateral_assets.keys().next().unwrap();
+        let previous = CollateralParameters::of(&before);
+
+        // Raising a factor or a cap is not a risk
+        let mut raised = before.clone();
+        let asset = raised.collateral_assets.get_mut(&weth).unwrap();
+        asset.collateral_factor += 0.05;
+        asset.liquidation_penalty -= 0.01;
+        asset.supply_cap *= 2;
+        assert!(CollateralChange::between(&previous, &raised).is_empty());
+
+        // Delisted, liquidating sooner at a higher penalty and capped below what is supplied
+        let mut lowered = before.clone();
+        let asset = lowered.collateral_assets.get_mut(&weth).unwrap();
+        asset.collateral_factor = 0.0;
+        asset.liquidation_factor -= 0.1;
+        asset.liquidation_penalty = 0.08;
+        asset.total_supplied = 100.0;
+        asset.supply_cap = U256::from(50) * U256::exp10(asset.decimals as usize);
+        let changes = CollateralChange::between(&previous, &lowered);
+        let kinds: Vec<&str> = changes.iter().map(|change| change.kind.name()).collect();
+        assert_eq!(kinds, ["delisted", "liquidate_collateral_factor_reduced", "liquidation_penalty_raised", "supply_cap_below_usage"]);
+        let price = lowered.collateral_assets[&weth].price;
+        assert!(changes.iter().all(|change| change.collateral_usd == 100.0 * price));
+        assert!(changes[0].to_string().starts_with("WETH delisted (collateral factor 0.825 → 0), $"), "{}", changes[0]);
+        assert!(changes[1].to_string().starts_with("WETH liquidate collateral factor lowered 0.910 → 0.810, $"), "{}", changes[1]);
+        assert!(changes[2].to_string().starts_with("WETH liquidation penalty raised 5.0% → 8.0%, $"), "{}", changes[2]);
+        assert!(changes[3].to_string().starts_with("WETH supply cap lowered 10,000.00 WETH → 50.00 WETH, below the 100.00 WETH supplied, $"), "{}", changes[3]);
+
+        // Snapshots stored before the penalty was recorded name the liquidate collateral
+        // factor `liquidation_factor` and are not compared on the penalty
+        let mut stored = serde_json::to_value(&previous).unwrap();
+        let entry = stored[0].as_object_mut().unwrap();
+        let factor = entry.remove("liquidate_collateral_factor").unwrap();
+        entry.insert("liquidation_factor".to_string(), factor);
+        entry.remove("liquidation_penalty");
+        let stored: Vec<CollateralParameters> = serde_json::from_value(stored).unwrap();
+        assert_eq!((stored[0].liquidate_collateral_factor, stored[0].liquidation_penalty), (0.91, None));
+        let kinds: Vec<&str> = CollateralChange::between(&stored, &lowered).iter().map(|change| change.kind.name()).collect();
+        assert_eq!(kinds, ["delisted", "liquidate_collateral_factor_reduced", "supply_cap_below_usage"]);
+    }
+}
@@ -0,0 +1,491 @@
+//! Differences between two sets of assessments
+//!
+//! Findings are matched by fingerprint, markets by Comet proxy address. The same finding
+//! diff drives the scheduler's change events (`events::diff_findings`) and so the alerts;
+//! `diff_assessments` adds score and market metric deltas for `risk-engine-cli compare`, with
+//! the score delta attributed to the finding changes by their `score_contribution`.
+
+use crate::error::Result;
+use crate::models::{InterestRates, Market};
+use crate::risk::{RiskAssessment, RiskFinding, RiskScore, RiskSeverity};
+use crate::storage::{FindingQuery, Storage};
+use crate::RiskEngine;
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Market state compared alongside the findings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketMetrics {
+    pub utilization_rate: f64,
+    /// Total base supplied, in USD
+    pub tvl_usd: f64,
+    /// Protocol reserves in base asset units; the history store does not keep them
+    pub reserves: Option<f64>,
+    /// Base and net interest rates; the history store does not keep net rates
+    #[serde(default)]
+    pub rates: Option<InterestRates>,
+}
+
+impl From<&Market> for MarketMetrics {
+    /// Utilization and TVL of `market`; reserves need a separate read and are left unknown
+    fn from(market: &Market) -> Self {
+        Self {
+            utilization_rate: market.utilization(),
+            tvl_usd: market.total_supply * market.base_asset.price,
+            reserves: None,
+            rates: Some(market.rates()),
+        }
+    }
+}
+
+/// An assessment and, when known, the market state it was computed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssessmentPoint {
+    pub assessment: RiskAssessment,
+    pub metrics: Option<MarketMetrics>,
+}
+
+impl From<RiskAssessment> for AssessmentPoint {
+    fn from(assessment: RiskAssessment) -> Self {
+        Self { assessment, metrics: None }
+    }
+}
+
+/// How one finding differs between two assessments of a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum FindingChange {
+    /// Present only in the newer assessment
+    Added { finding: RiskFinding },
+    /// Present only in the older assessment
+    Removed { finding: RiskFinding },
+    /// Present in both with a different severity; `finding` is the newer report
+    SeverityChanged {
+        previous: RiskSeverity,
+        #[serde(default)]
+        previous_score_contribution: u8,
+        finding: RiskFinding,
+    },
+}
+
+impl FindingChange {
+    /// Whether the change makes the market riskier (added or escalated)
+    pub fn is_riskier(&self) -> bool {
+        match self {
+            Self::Added { .. } => true,
+            Self::Removed { .. } => false,
+            Self::SeverityChanged { previous, finding, .. } => finding.severity > *previous,
+        }
+    }
+
+    /// Points the change moved the risk score by
+    pub fn score_delta(&self) -> i16 {
+        match self {
+            Self::Added { finding } => i16::from(finding.score_contribution),
+            Self::Removed { finding } => -i16::from(finding.score_contribution),
+            Self::SeverityChanged { previous_score_contribution, finding, .. } => {
+                i16::from(finding.score_contribution) - i16::from(*previous_score_contribution)
+            }
+        }
+    }
+}
+
+/// Old and new value of a metric
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricDelta {
+    pub before: f64,
+    pub after: f64,
+}
+
+impl MetricDelta {
+    pub fn change(&self) -> f64 {
+        self.after - self.before
+    }
+}
+
+/// Engines that produced the two sides of a market's diff, when they differ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineChange {
+    pub before: String,
+    pub after: String,
+    /// Checks whose version differs, or that only one side ran
+    #[serde(default)]
+    pub checks: Vec<String>,
+}
+
+/// Differences for one market; a market missing on one side has no score there
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketDiff {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub score_before: Option<u8>,
+    pub score_after: Option<u8>,
+    pub findings: Vec<FindingChange>,
+    pub utilization_rate: Option<MetricDelta>,
+    pub tvl_usd: Option<MetricDelta>,
+    pub reserves: Option<MetricDelta>,
+    /// Set when different engine versions produced the two sides, whose scores may then
+    /// not mean the same; unknown versions (older files) are not compared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<EngineChange>,
+}
+
+impl MarketDiff {
+    /// Change in risk score, counting a missing side as 0
+    pub fn score_delta(&self) -> i16 {
+        i16::from(self.score_after.unwrap_or(0)) - i16::from(self.score_before.unwrap_or(0))
+    }
+
+    /// Part of `score_delta` the finding changes do not account for: the points of unchanged
+    /// findings that moved as other findings took up or freed the score's cap
+    pub fn unattributed_score_delta(&self) -> i16 {
+        self.score_delta() - self.findings.iter().map(FindingChange::score_delta).sum::<i16>()
+    }
+
+    /// Whether the score went up or a finding was added or escalated
+    pub fn is_riskier(&self) -> bool {
+        self.score_delta() > 0 || self.findings.iter().any(FindingChange::is_riskier)
+    }
+
+    /// Whether nothing compared differs
+    pub fn is_unchanged(&self) -> bool {
+        self.score_before == self.score_after
+            && self.findings.is_empty()
+            && [self.utilization_rate, self.tvl_usd, self.reserves]
+                .iter()
+                .all(|delta| delta.is_none_or(|d| d.change() == 0.0))
+    }
+}
+
+/// Differences between two sets of assessments, by market name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssessmentDiff {
+    pub markets: Vec<MarketDiff>,
+}
+
+impl AssessmentDiff {
+    /// Whether the newer side is strictly riskier: some market's score went up or gained
+    /// or escalated a finding
+    pub fn is_riskier(&self) -> bool {
+        self.markets.iter().any(MarketDiff::is_riskier)
+    }
+
+    /// One warning per market whose sides different engine versions produced, e.g. "USDC was
+    /// assessed by engine 0.1.0+3f2a9c1d0b7e, then by 0.2.0+8e55104a1c2f (changed checks:
+    /// utilization)"
+    pub fn version_warnings(&self) -> Vec<String> {
+        self.markets
+            .iter()
+            .filter_map(|market| {
+                let engine = market.engine.as_ref()?;
+                let checks = if engine.checks.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (changed checks: {})", engine.checks.join(", "))
+                };
+                Some(format!("{} was assessed by engine {}, then by {}{}", market.market_name, engine.before, engine.after, checks))
+            })
+            .collect()
+    }
+}
+
+/// Changes from `previous` to `current` findings: added and re-rated findings in `current`
+/// order, then removed findings in `previous` order
+pub fn diff_findings(previous: &[RiskFinding], current: &[RiskFinding]) -> Vec<FindingChange> {
+    let before: HashMap<&str, &RiskFinding> = previous.iter().map(|f| (f.fingerprint.as_str(), f)).collect();
+    let after: BTreeSet<&str> = current.iter().map(|f| f.fingerprint.as_str()).collect();
+
+    let mut changes = Vec::new();
+    for finding in current {
+        match before.get(finding.fingerprint.as_str()) {
+            None => changes.push(FindingChange::Added { finding: finding.clone() }),
+            Some(old) if old.severity != finding.severity => changes.push(FindingChange::SeverityChanged {
+                previous: old.severity,
+                previous_score_contribution: old.score_contribution,
+                finding: finding.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for finding in previous {
+        if !after.contains(finding.fingerprint.as_str()) {
+            changes.push(FindingChange::Removed { finding: finding.clone() });
+        }
+    }
+    changes
+}
+
+/// Compare `older` with `newer`, market by market
+pub fn diff_assessments(older: &[AssessmentPoint], newer: &[AssessmentPoint]) -> AssessmentDiff {
+    let before: HashMap<Address, &AssessmentPoint> = older.iter().map(|p| (p.assessment.market_address, p)).collect();
+    let after: HashMap<Address, &AssessmentPoint> = newer.iter().map(|p| (p.assessment.market_address, p)).collect();
+    let mut addresses: Vec<Address> = before.keys().chain(after.keys()).copied().collect();
+    addresses.sort();
+    addresses.dedup();
+
+    let mut markets: Vec<MarketDiff> = addresses
+        .into_iter()
+        .map(|address| {
+            let (old, new) = (before.get(&address).copied(), after.get(&address).copied());
+            let named = new.or(old).expect("address comes from one side");
+            let metric = |get: fn(&MarketMetrics) -> Option<f64>| {
+                let value = |point: Option<&AssessmentPoint>| point.and_then(|p| p.metrics.as_ref()).and_then(get);
+                Some(MetricDelta { before: value(old)?, after: value(new)? })
+            };
+            MarketDiff {
+                market_name: named.assessment.market_name.clone(),
+                market_address: address,
+                score_before: old.map(|p| p.assessment.risk_score),
+                score_after: new.map(|p| p.assessment.risk_score),
+                findings: diff_findings(findings_of(old), findings_of(new)),
+                utilization_rate: metric(|m| Some(m.utilization_rate)),
+                tvl_usd: metric(|m| Some(m.tvl_usd)),
+                reserves: metric(|m| m.reserves),
+                engine: engine_change(old.map(|p| &p.assessment), new.map(|p| &p.assessment)),
+            }
+        })
+        .collect();
+    markets.sort_by(|a, b| a.market_name.cmp(&b.market_name));
+    AssessmentDiff { markets }
+}
+
+fn engine_change(old: Option<&RiskAssessment>, new: Option<&RiskAssessment>) -> Option<EngineChange> {
+    let (old, new) = (old?, new?);
+    if old.engine_version.is_empty() || new.engine_version.is_empty() || old.engine_version == new.engine_version {
+        return None;
+    }
+    let names: BTreeSet<&String> = old.check_versions.keys().chain(new.check_versions.keys()).collect();
+    let checks = names
+        .into_iter()
+        .filter(|name| old.check_versions.get(*name) != new.check_versions.get(*name))
+        .cloned()
+        .collect();
+    Some(EngineChange { before: old.engine_version.clone(), after: new.engine_version.clone(), checks })
+}
+
+fn findings_of(point: Option<&AssessmentPoint>) -> &[RiskFinding] {
+    point.map_or(&[], |p| &p.assessment.findings)
+}
+
+/// Assessments saved by `risk-engine-cli assess --out`, or printed by `--output json assess`
+///
+/// Files written before findings recorded their score contributions get them recomputed.
+pub fn load(path: &Path) -> Result<Vec<AssessmentPoint>> {
+    Ok(RiskAssessment::load_many(path)?
+        .into_iter()
+        .map(|mut assessment| {
+            if assessment.findings.iter().all(|f| f.score_contribution == 0) {
+                RiskScore::of(&assessment.findings).attribute(&mut assessment.findings);
+            }
+            AssessmentPoint::from(assessment)
+        })
+        .collect())
+}
+
+/// Assess every market now, with live market metrics
+pub async fn current(engine: &RiskEngine) -> Result<Vec<AssessmentPoint>> {
+    let assessments = engine.assess_risks().await?;
+    let provider = engine.provider();
+    let markets = provider.get_markets().await?;
+    let mut points = Vec::new();
+    for assessment in assessments {
+        let metrics = match markets.iter().find(|m| m.comet_address == assessment.market_address) {
+            Some(market) => {
+                let protocol = provider.get_protocol_metrics(market).await?;
+                Some(MarketMetrics { reserves: Some(protocol.reserves), ..MarketMetrics::from(market) })
+            }
+            None => None,
+        };
+        points.push(AssessmentPoint { assessment, metrics });
+    }
+    Ok(points)
+}
+
+/// The newest stored assessment of each market at or before `at`
+///
+/// Markets first assessed after `at` are left out.
+pub async fn stored_at(storage: &dyn Storage, at: DateTime<Utc>) -> Result<Vec<AssessmentPoint>> {
+    // Stored timestamps have millisecond precision
+    let until = at + Duration::milliseconds(1);
+    let mut points = Vec::new();
+    for market in storage.markets().await? {
+        let series = storage.score_series(market.address, DateTime::UNIX_EPOCH, until).await?;
+        let Some(point) = series.last() else { continue };
+        let assessed = point.timestamp..point.timestamp + Duration::milliseconds(1);
+
+        let query = FindingQuery {
+            market: Some(market.address),
+            since: Some(assessed.start),
+            until: Some(assessed.end),
+            ..FindingQuery::default()
+        };
+        let mut findings: Vec<RiskFinding> = storage.findings(&query).await?.into_iter().map(|stored| stored.finding).collect();
+        // The store keeps no contributions; they follow from the findings
+        RiskScore::of(&findings).attribute(&mut findings);
+        let snapshot = storage.snapshots(market.address, assessed.start, assessed.end).await?.pop();
+        points.push(AssessmentPoint {
+            assessment: RiskAssessment {
+                risk_score: point.risk_score,
+                timestamp: point.timestamp,
+                engine_version: point.engine_version.clone().unwrap_or_default(),
+                ..RiskAssessment::new(market.name.clone(), market.address, findings)
+            },
+            metrics: snapshot.map(|s| MarketMetrics {
+                utilization_rate: Market::utilization_of(s.total_supply, s.total_borrow),
+                tvl_usd: s.total_supply * s.base_price,
+                reserves: None,
+                rates: None,
+            }),
+        });
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::finding;
+
+    fn point(address: u64, score: u8, findings: Vec<RiskFinding>, utilization: Option<f64>) -> AssessmentPoint {
+        AssessmentPoint {
+            assessment: RiskAssessment {
+                risk_score: score,
+                ..RiskAssessment::new(format!("M{}", address), Address::from_low_u64_be(address), findings)
+            },
+            metrics: utilization.map(|u| MarketMetrics { utilization_rate: u, tvl_usd: 1000.0, reserves: None, rates: None }),
+        }
+    }
+
+    #[test]
+    fn test_diff_matches_findings_by_fingerprint() {
+        let older = [point(1, 45, vec![finding("a", RiskSeverity::High), finding("b", RiskSeverity::Low)], Some(0.90))];
+        let newer = [
+            point(1, 40, vec![finding("a", RiskSeverity::Medium), finding("c", RiskSeverity::Low)], Some(0.92)),
+            point(2, 15, vec![finding("d", RiskSeverity::Medium)], None),
+        ];
+        let diff = diff_assessments(&older, &newer);
+        assert_eq!(diff.markets.len(), 2);
+
+        let m1 = &diff.markets[0];
+        assert_eq!((m1.score_before, m1.score_after, m1.score_delta()), (Some(45), Some(40), -5));
+        let kinds: Vec<String> = m1.findings.iter().map(|c| serde_json::to_value(c).unwrap()["change"].to_string()).collect();
+        assert_eq!(kinds, ["\"severity_changed\"", "\"added\"", "\"removed\""]);
+        assert!((m1.utilization_rate.unwrap().change() - 0.02).abs() < 1e-9);
+        assert_eq!(m1.tvl_usd.unwrap().change(), 0.0);
+        assert!(m1.reserves.is_none());
+        // A new Low finding makes it riskier despite the lower score
+        assert!(m1.is_riskier());
+
+        let m2 = &diff.markets[1];
+        assert_eq!((m2.score_before, m2.score_after), (None, Some(15)));
+        assert!(m2.utilization_rate.is_none());
+        assert!(diff.is_riskier());
+
+        // A de-escalation and a removal at a lower score
+        let calmer = [point(1, 30, vec![finding("a", RiskSeverity::Medium)], Some(0.90))];
+        assert!(!diff_assessments(&older, &calmer).is_riskier());
+        assert!(diff_assessments(&older, &older).markets.iter().all(MarketDiff::is_unchanged));
+    }
+
+    #[test]
+    fn test_diff_flags_different_engine_versions() {
+        let stamped = |version: &str, utilization_version: u32| {
+            let mut point = point(1, 45, vec![], None);
+            point.assessment.engine_version = version.to_string();
+            point.assessment.check_versions =
+                [("utilization".to_string(), utilization_version), ("watchlist".to_string(), 1)].into_iter().collect();
+            point
+        };
+        let diff = diff_assessments(&[stamped("0.1.0+aaa", 1)], &[stamped("0.2.0+bbb", 2)]);
+        assert_eq!(diff.markets[0].engine.as_ref().unwrap().checks, ["utilization"]);
+        assert_eq!(diff.version_warnings(), ["M1 was assessed by engine 0.1.0+aaa, then by 0.2.0+bbb (changed checks: utilization)"]);
+
+        // The same engine, or a side saved before versions were recorded: nothing to warn about
+        assert!(diff_assessments(&[stamped("0.1.0+aaa", 1)], &[stamped("0.1.0+aaa", 1)]).version_warnings().is_empty());
+        assert!(diff_assessments(&[stamped("", 1)], &[stamped("0.2.0+bbb", 2)]).version_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_score_deltas_are_attributed_to_finding_changes() {
+        let scored = |findings: Vec<RiskFinding>| {
+            let mut point = point(1, 0, findings, None);
+            let score = RiskScore::of(&point.assessment.findings);
+            score.attribute(&mut point.assessment.findings);
+            point.assessment.risk_score = score.total;
+            point
+        };
+        let before = [scored(vec![finding("a", RiskSeverity::Critical), finding("b", RiskSeverity::Critical)])];
+        let after = [scored(vec![
+            finding("a", RiskSeverity::Critical),
+            finding("b", RiskSeverity::High),
+            finding("c", RiskSeverity::Medium),
+        ])];
+        let market = &diff_assessments(&before, &after).markets[0];
+        let deltas: Vec<i16> = market.findings.iter().map(FindingChange::score_delta).collect();
+        assert_eq!((market.score_delta(), deltas.as_slice()), (-5, [-20, 15].as_slice()));
+        assert_eq!(market.unattributed_score_delta(), 0);
+
+        // A finding past the cap gains points without changing when another de-escalates
+        let capped = [scored(vec![
+            finding("a", RiskSeverity::Critical),
+            finding("b", RiskSeverity::Critical),
+            finding("x", RiskSeverity::High),
+        ])];
+        let freed = [scored(vec![
+            finding("a", RiskSeverity::Critical),
+            finding("b", RiskSeverity::High),
+            finding("x", RiskSeverity::High),
+        ])];
+        let market = &diff_assessments(&capped, &freed).markets[0];
+        assert_eq!((market.score_delta(), market.findings[0].score_delta()), (0, -20));
+        assert_eq!(market.unattributed_score_delta(), 20);
+    }
+
+    #[test]
+    fn test_load_accepts_a_list_or_a_single_assessment() {
+        let dir = tempfile::tempdir().unwrap();
+        let assessment = point(1, 45, vec![finding("a", RiskSeverity::High)], None).assessment;
+        let (many, one) = (dir.path().join("many.json"), dir.path().join("one.json"));
+        std::fs::write(&many, serde_json::to_string(&[&assessment, &assessment]).unwrap()).unwrap();
+        std::fs::write(&one, serde_json::to_string(&assessment).unwrap()).unwrap();
+
+        assert_eq!(load(&many).unwrap().len(), 2);
+        let loaded = load(&one).unwrap();
+        assert_eq!(loaded[0].assessment.findings[0].fingerprint, "a");
+        assert!(loaded[0].metrics.is_none());
+        std::fs::write(&one, "{}").unwrap();
+        assert!(load(&one).is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_stored_assessments_round_trip_through_compare() {
+        use crate::config::Config;
+        use crate::provider::{bundled_fixture, FixtureProvider};
+        use std::sync::Arc;
+
+        let storage = Arc::new(crate::storage::SqliteStorage::open_in_memory().unwrap());
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = RiskEngine::with_provider(Config::default(), Arc::new(fixture)).with_storage(storage.clone());
+
+        let live = current(&engine).await.unwrap();
+        storage.flush().await.unwrap();
+        let stored = stored_at(storage.as_ref(), Utc::now()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].assessment.findings.len(), live[0].assessment.findings.len());
+        assert!(stored[0].metrics.is_some());
+        assert_eq!(stored[0].assessment.engine_version, crate::version::ENGINE_VERSION);
+
+        let diff = diff_assessments(&stored, &live);
+        assert!(!diff.is_riskier());
+        assert!(diff.version_warnings().is_empty());
+        assert!(diff.markets[0].findings.is_empty());
+        assert_eq!(diff.markets[0].utilization_rate.unwrap().change(), 0.0);
+        // Nothing was stored before the first assessment
+        assert!(stored_at(storage.as_ref(), Utc::now() - Duration::days(1)).await.unwrap().is_empty());
+    }
+}
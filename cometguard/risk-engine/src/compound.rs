@@ -1,33 +1,111 @@
-use crate::config::Config;
-use crate::models::{Asset, AssetType, Market, UserPosition, ProtocolMetrics};
-use anyhow::{Result, Context};
+use crate::config::{Config, Network};
+use crate::ens::EnsResolver;
+use crate::gas::{GasQuote, GasSource, ABSORPTION_CALLDATA_BYTES};
+use crate::scanner::{BorrowerScanner, LogSource};
+use crate::models::{
+    Asset, AssetConfiguration, AssetType, ConfigurationPair, ManagerPermissions, Market, MarketConfiguration, Provenance, PriceHistory,
+    RewardsFunding, SequencerStatus, UserPosition, ProtocolMetrics, RATE_SECONDS_PER_YEAR,
+};
+use crate::error::{Result, RiskEngineError};
+use crate::metrics::Metrics;
+use crate::provider::CacheStats;
+use crate::progress::{ProgressReporter, Stage};
+use crate::rpc::{RecordingClient, RpcBudget, RpcProvider};
 use ethers::{
-    core::types::{Address, U256},
-    providers::{Provider, Http},
-    contract::abigen,
+    core::types::{Address, BlockId, BlockNumber, Bytes, U256},
+    providers::{Middleware, Provider, ProviderError},
+    abi::{AbiDecode, Detokenize, InvalidOutputType, Tokenizable},
+    contract::{abigen, ContractCall},
 };
+use futures::stream::{self, Stream, StreamExt};
 use std::{sync::Arc, collections::HashMap, str::FromStr};
-use tracing::info;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::{OnceCell, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
 use moka::future::Cache;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 // Generate contracts with inline ABI definitions
 abigen!(
     Comet,
     r#"[
         function balanceOf(address) view returns (uint256)
+        function borrowBalanceOf(address) view returns (uint256)
         function baseToken() view returns (address)
         function baseTokenPriceFeed() view returns (address)
         function collateralBalanceOf(address, address) view returns (uint256)
         function totalSupply() view returns (uint256)
         function totalBorrow() view returns (uint256)
+        function getUtilization() view returns (uint256)
+        function getSupplyRate(uint256) view returns (uint64)
+        function getBorrowRate(uint256) view returns (uint64)
+        function getPrice(address) view returns (uint256)
+        function getReserves() view returns (int256)
+        function numAssets() view returns (uint8)
+        function getAssetInfo(uint8) view returns (tuple(uint8,address,address,uint64,uint64,uint64,uint64,uint128))
+        function baseTrackingSupplySpeed() view returns (uint256)
+        function baseTrackingBorrowSpeed() view returns (uint256)
+        function trackingIndexScale() view returns (uint64)
+        function baseBorrowMin() view returns (uint104)
+        function storeFrontPriceFactor() view returns (uint64)
+        function totalsCollateral(address) view returns (uint128, uint128)
+        function getCollateralReserves(address) view returns (uint256)
+        function isAllowed(address, address) view returns (bool)
+        function hasPermission(address, address) view returns (bool)
+        function userNonce(address) view returns (uint256)
+        function governor() view returns (address)
+        function pauseGuardian() view returns (address)
+        function extensionDelegate() view returns (address)
+        function supplyKink() view returns (uint64)
+        function supplyPerSecondInterestRateSlopeLow() view returns (uint64)
+        function supplyPerSecondInterestRateSlopeHigh() view returns (uint64)
+        function supplyPerSecondInterestRateBase() view returns (uint64)
+        function borrowKink() view returns (uint64)
+        function borrowPerSecondInterestRateSlopeLow() view returns (uint64)
+        function borrowPerSecondInterestRateSlopeHigh() view returns (uint64)
+        function borrowPerSecondInterestRateBase() view returns (uint64)
+        function baseMinForRewards() view returns (uint104)
+        function targetReserves() view returns (uint104)
+    ]"#
+);
+
+abigen!(
+    CometRewards,
+    r#"[
+        function rewardConfig(address) view returns (address, uint64, bool)
     ]"#
 );
 
 abigen!(
     CometConfigurator,
     r#"[
-        function getConfiguration(address) view returns (tuple(address,address,address,address,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256,uint256))
+        struct AssetConfig { address asset; address priceFeed; uint8 decimals; uint64 borrowCollateralFactor; uint64 liquidateCollateralFactor; uint64 liquidationFactor; uint128 supplyCap; }
+        struct Configuration { address governor; address pauseGuardian; address baseToken; address baseTokenPriceFeed; address extensionDelegate; uint64 supplyKink; uint64 supplyPerYearInterestRateSlopeLow; uint64 supplyPerYearInterestRateSlopeHigh; uint64 supplyPerYearInterestRateBase; uint64 borrowKink; uint64 borrowPerYearInterestRateSlopeLow; uint64 borrowPerYearInterestRateSlopeHigh; uint64 borrowPerYearInterestRateBase; uint64 storeFrontPriceFactor; uint64 trackingIndexScale; uint64 baseTrackingSupplySpeed; uint64 baseTrackingBorrowSpeed; uint104 baseMinForRewards; uint104 baseBorrowMin; uint104 targetReserves; AssetConfig[] assetConfigs; }
+        function getConfiguration(address) view returns (Configuration)
+    ]"#
+);
+
+abigen!(
+    AggregatorV3,
+    r#"[
+        function latestRoundData() view returns (uint80, int256, uint256, uint256, uint80)
+    ]"#
+);
+
+abigen!(
+    L1FeeOracle,
+    r#"[
+        function getL1Fee(bytes) view returns (uint256)
+    ]"#
+);
+
+abigen!(
+    Multicall3,
+    r#"[
+        struct Call3 { address target; bool allowFailure; bytes callData; }
+        struct Call3Result { bool success; bytes returnData; }
+        function aggregate3(Call3[] calls) view returns (Call3Result[])
     ]"#
 );
 
@@ -41,188 +119,1017 @@ abigen!(
     ]"#
 );
 
+/// Number of accounts packed into a single batched read
+pub const POSITION_BATCH_SIZE: usize = 25;
+
+/// Multicall3, deployed at the same address on every chain the engine supports
+pub const MULTICALL3_ADDRESS: Address = ethers::types::H160([
+    0xca, 0x11, 0xbd, 0xe0, 0x59, 0x77, 0xb3, 0x63, 0x11, 0x67, 0x02, 0x88, 0x62, 0xbe, 0x2a, 0x17, 0x3a, 0x97, 0x6c, 0xa1,
+]);
+
+/// Scale of Comet's factors and utilization (1e18 = 100%)
+const FACTOR_SCALE: f64 = 1e18;
+
+/// Decimals of prices returned by `Comet.getPrice`
+const PRICE_DECIMALS: u8 = 8;
+
+/// Seconds per year used by Comet to annualize per-second rates
+const SECONDS_PER_YEAR: f64 = 31_536_000.0;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Recent blocks whose priority fees `get_gas_quote` takes the percentile of
+const FEE_HISTORY_BLOCKS: u64 = 20;
+
+/// Outcome of fetching one account's position in a batch
+pub type PositionResult = (Address, Result<UserPosition>);
+
+/// Rewards of a market as read by `CompoundClient::fetch_rewards`
+struct Rewards {
+    /// Reward tokens per second for one unit of tracking speed
+    per_speed_unit: f64,
+    funding: RewardsFunding,
+}
+
 /// Convert a U256 value to f64, accounting for decimals
+///
+/// Any `decimals` is accepted, even a garbled 255, so that `Market::input_issues` can report it.
 pub fn u256_to_f64(value: U256, decimals: u8) -> f64 {
-    let decimals_factor = 10u64.pow(decimals as u32) as f64;
+    let decimals_factor = 10f64.powi(decimals as i32);
     let value_u128 = value.as_u128() as f64;
     value_u128 / decimals_factor
 }
 
+/// Decode the return data of `CometConfigurator.getConfiguration`
+pub fn decode_configuration(data: &[u8]) -> Result<MarketConfiguration> {
+    let parse_error = |message: String| RiskEngineError::Parse {
+        what: "Configurator configuration",
+        input: format!("{} bytes", data.len()),
+        message,
+    };
+    let raw = GetConfigurationReturn::decode(data).map_err(|e| parse_error(e.to_string()))?;
+    configuration_of(raw.0).map_err(|e| parse_error(e.to_string()))
+}
+
+/// `getConfiguration`'s struct, which calls return as a tuple of the same tokens, as a
+/// `MarketConfiguration`
+fn configuration_of(raw: impl Tokenizable) -> std::result::Result<MarketConfiguration, InvalidOutputType> {
+    let raw = Configuration::from_token(raw.into_token())?;
+    Ok(MarketConfiguration {
+        governor: raw.governor,
+        pause_guardian: raw.pause_guardian,
+        base_token: raw.base_token,
+        base_token_price_feed: raw.base_token_price_feed,
+        extension_delegate: raw.extension_delegate,
+        supply_kink: raw.supply_kink,
+        supply_per_year_interest_rate_slope_low: raw.supply_per_year_interest_rate_slope_low,
+        supply_per_year_interest_rate_slope_high: raw.supply_per_year_interest_rate_slope_high,
+        supply_per_year_interest_rate_base: raw.supply_per_year_interest_rate_base,
+        borrow_kink: raw.borrow_kink,
+        borrow_per_year_interest_rate_slope_low: raw.borrow_per_year_interest_rate_slope_low,
+        borrow_per_year_interest_rate_slope_high: raw.borrow_per_year_interest_rate_slope_high,
+        borrow_per_year_interest_rate_base: raw.borrow_per_year_interest_rate_base,
+        store_front_price_factor: raw.store_front_price_factor,
+        tracking_index_scale: raw.tracking_index_scale,
+        base_tracking_supply_speed: raw.base_tracking_supply_speed,
+        base_tracking_borrow_speed: raw.base_tracking_borrow_speed,
+        base_min_for_rewards: raw.base_min_for_rewards,
+        base_borrow_min: raw.base_borrow_min,
+        target_reserves: raw.target_reserves,
+        asset_configs: raw
+            .asset_configs
+            .into_iter()
+            .map(|config| AssetConfiguration {
+                asset: config.asset,
+                price_feed: config.price_feed,
+                decimals: config.decimals,
+                borrow_collateral_factor: config.borrow_collateral_factor,
+                liquidate_collateral_factor: config.liquidate_collateral_factor,
+                liquidation_factor: config.liquidation_factor,
+                supply_cap: config.supply_cap,
+            })
+            .collect(),
+    })
+}
+
+/// Execute a read-only contract call, tagging failures with the contract and method
+async fn read<D: Detokenize>(contract: Address, method: &str, call: ContractCall<RpcProvider, D>) -> Result<D> {
+    call.call()
+        .await
+        .map_err(|e| RiskEngineError::contract_call(contract, method, e))
+}
+
+/// `read` against the state at `block`, so that reads of one operation see the same state
+async fn read_at<D: Detokenize>(
+    block: impl Into<BlockId>,
+    contract: Address,
+    method: &str,
+    call: ContractCall<RpcProvider, D>,
+) -> Result<D> {
+    read(contract, method, call.block(block)).await
+}
+
+/// Value of one `aggregate3` read of `method` on `contract`, failing if it reverted
+fn decode_call3(contract: Address, method: &str, (success, data): &(bool, Bytes)) -> Result<U256> {
+    if !success {
+        return Err(RiskEngineError::contract_call(
+            contract,
+            method,
+            ethers::contract::ContractError::<RpcProvider>::Revert(data.clone()),
+        ));
+    }
+    U256::decode(data).map_err(|e| RiskEngineError::contract_call(contract, method, e))
+}
+
+/// Collateral assets of `market` in address order
+fn sorted_collateral(market: &Market) -> Vec<&Asset> {
+    let mut assets: Vec<&Asset> = market.collateral_assets.values().collect();
+    assets.sort_by_key(|a| a.address);
+    assets
+}
+
 /// Client for interacting with Compound V3 contracts
 pub struct CompoundClient {
-    #[allow(dead_code)]
-    provider: Arc<Provider<Http>>,
-    #[allow(dead_code)]
+    provider: Arc<RpcProvider>,
     config: Arc<Config>,
-    comet_address: Address,
+    /// Comet proxies assessed, the primary one first
+    comets: Vec<Address>,
     cache: Cache<String, Arc<Market>>,
+    /// Market lookups served from `cache`, and ones read from chain
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    ens: Arc<EnsResolver>,
+    budget: Arc<RpcBudget>,
+    cancel: CancellationToken,
+    progress: ProgressReporter,
 }
 
 impl CompoundClient {
     /// Create a new CompoundClient instance
     pub async fn new(config: Arc<Config>) -> Result<Self> {
-        let provider = Provider::<Http>::try_from(&config.compound.rpc_url)
-            .context("Failed to create Ethereum provider")?;
-        let provider = Arc::new(provider);
+        Self::build(config, None)
+    }
+
+    /// Create a CompoundClient whose RPC requests and retries are counted in `metrics`
+    pub async fn new_with_metrics(config: Arc<Config>, metrics: Arc<Metrics>) -> Result<Self> {
+        Self::build(config, Some(metrics))
+    }
+
+    fn build(config: Arc<Config>, metrics: Option<Arc<Metrics>>) -> Result<Self> {
+        let budget = Arc::new(RpcBudget::new(config.rpc.budget.clone()));
+        let mut transport = RecordingClient::from_config(&config)?.with_budget(budget.clone());
+        if let Some(metrics) = metrics {
+            transport = transport.with_metrics(metrics);
+        }
+        let provider = Arc::new(Provider::new(transport));
         
-        let comet_address = Address::from_str(&config.compound.comet_proxy_address)
-            .context("Invalid Comet proxy address")?;
+        let comets = config.market_addresses()?;
         
         // Initialize cache with 60 second TTL
         let cache = Cache::builder()
             .time_to_live(Duration::from_secs(60))
             .build();
             
+        let ens = Arc::new(EnsResolver::new(provider.clone(), Duration::from_secs(config.ens.cache_ttl_seconds)));
+
         Ok(Self {
             provider,
             config,
-            comet_address,
+            comets,
             cache,
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            ens,
+            budget,
+            cancel: CancellationToken::new(),
+            progress: ProgressReporter::new(),
         })
     }
+
+    /// Use `cancel` to interrupt batch operations started by this client
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Report batch fetches and borrower scans started by this client to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Borrower scanner reading logs through this client's provider
+    pub fn borrower_scanner(&self) -> BorrowerScanner {
+        BorrowerScanner::new(
+            self.provider.clone(),
+            self.config.scanner.chunk_size,
+            self.config.scanner.index_path.clone(),
+        )
+        .with_confirmations(self.config.scanner.confirmations)
+        .with_concurrency(self.config.scanner.max_concurrent_chunks)
+        .with_progress(self.progress.clone())
+    }
+
+    /// Compute-unit accounting of this client's requests
+    pub fn rpc_budget(&self) -> Arc<RpcBudget> {
+        self.budget.clone()
+    }
+
+    /// Address of the primary Comet proxy
+    pub fn comet_address(&self) -> Address {
+        self.comets[0]
+    }
+
+    /// Configuration this client was built with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Underlying Ethereum provider
+    pub fn provider(&self) -> Arc<RpcProvider> {
+        self.provider.clone()
+    }
+
+    /// ENS resolver reading through this client's provider, with its own cache
+    pub fn name_resolver(&self) -> Arc<EnsResolver> {
+        self.ens.clone()
+    }
+
+    /// Market lookups served from the cache so far, and ones read from chain
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Host of the RPC endpoint, safe to log
+    fn rpc_host(&self) -> &str {
+        (*self.provider).as_ref().host()
+    }
     
-    /// Get information about all markets (for milestone 1, only one market is supported)
+    /// Get information about all markets, served from cache when fresh
     pub async fn get_markets(&self) -> Result<Vec<Market>> {
         info!("Fetching market data from Compound V3");
-        
-        // Check cache first
-        let cache_key = format!("markets:{}", self.comet_address);
-        if let Some(cached) = self.cache.get(&cache_key) {
-            info!("Using cached market data");
-            return Ok(vec![cached.as_ref().clone()]);
+        futures::future::try_join_all(self.comets.iter().map(|&comet| self.get_market(comet))).await
+    }
+
+    /// Fetch market data bypassing the cache, then store it for subsequent `get_markets` calls
+    pub async fn refresh_markets(&self) -> Result<Vec<Market>> {
+        futures::future::try_join_all(self.comets.iter().map(|&comet| self.refresh_market(comet))).await
+    }
+
+    /// Market of `comet` from cache, preferring the latest block cached, or read from chain
+    async fn get_market(&self, comet: Address) -> Result<Market> {
+        let prefix = Self::markets_cache_key(comet, None);
+        let cached = self
+            .cache
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .max_by_key(|(_, market)| market.block_number);
+        if let Some((_, cached)) = cached {
+            info!("Using cached market data from block {:?}", cached.block_number);
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(cached.as_ref().clone());
         }
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        self.refresh_market(comet).await
+    }
+
+    async fn refresh_market(&self, comet: Address) -> Result<Market> {
+        let market = self.fetch_market(comet).await?;
         
-        // Use mock data for milestone 1
-        let market = self.create_mock_market().await?;
-        
-        // Store in cache
-        let _ = self.cache.insert(cache_key, Arc::new(market.clone()));
+        // Store in cache under the block read, so snapshots of different blocks stay apart
+        self.cache.insert(Self::markets_cache_key(comet, market.block_number), Arc::new(market.clone())).await;
         
-        Ok(vec![market])
+        Ok(market)
     }
-    
-    /// Create a mock market for testing
-    async fn create_mock_market(&self) -> Result<Market> {
-        // Mocked USDC market
-        let base_asset = Asset {
-            address: Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(), // USDC
-            symbol: "USDC".to_string(),
-            decimals: 6,
-            price: 1.0,
+
+    /// Cache key of the market of `comet` read at `block`, or the prefix of all its keys without one
+    fn markets_cache_key(comet: Address, block: Option<u64>) -> String {
+        match block {
+            Some(block) => format!("markets:{}@{}", comet, block),
+            None => format!("markets:{}@", comet),
+        }
+    }
+
+    /// Read the base asset, totals, rates and collateral assets of the Comet at `address` from chain
+    ///
+    /// The chain head is resolved first and every read is pinned to it, so that totals,
+    /// utilization and prices all describe the same block.
+    #[instrument(level = "debug", skip(self), fields(comet = ?address, rpc_host = %self.rpc_host(), block), err(level = "debug"))]
+    async fn fetch_market(&self, address: Address) -> Result<Market> {
+        let comet = Comet::new(address, self.provider.clone());
+        let block = self.provider.head_block().await?;
+        tracing::Span::current().record("block", block);
+
+        let (base_token, base_feed, total_supply, total_borrow, utilization, num_assets, supply_speed, borrow_speed) = futures::try_join!(
+            read_at(block, address, "baseToken", comet.base_token()),
+            read_at(block, address, "baseTokenPriceFeed", comet.base_token_price_feed()),
+            read_at(block, address, "totalSupply", comet.total_supply()),
+            read_at(block, address, "totalBorrow", comet.total_borrow()),
+            read_at(block, address, "getUtilization", comet.get_utilization()),
+            read_at(block, address, "numAssets", comet.num_assets()),
+            read_at(block, address, "baseTrackingSupplySpeed", comet.base_tracking_supply_speed()),
+            read_at(block, address, "baseTrackingBorrowSpeed", comet.base_tracking_borrow_speed()),
+        )?;
+        let (supply_rate, borrow_rate, base_price, base_borrow_min, store_front_price_factor, (base_symbol, base_decimals)) = futures::try_join!(
+            read_at(block, address, "getSupplyRate", comet.get_supply_rate(utilization)),
+            read_at(block, address, "getBorrowRate", comet.get_borrow_rate(utilization)),
+            read_at(block, address, "getPrice", comet.get_price(base_feed)),
+            read_at(block, address, "baseBorrowMin", comet.base_borrow_min()),
+            read_at(block, address, "storeFrontPriceFactor", comet.store_front_price_factor()),
+            self.token_metadata(base_token, block),
+        )?;
+
+        let mut base_asset = Asset {
+            address: base_token,
+            symbol: base_symbol.clone(),
+            decimals: base_decimals,
+            price: u256_to_f64(base_price, PRICE_DECIMALS),
             asset_type: AssetType::Base,
             collateral_factor: 0.0,
             liquidation_factor: 0.0,
             liquidation_penalty: 0.0,
-            supply_cap: U256::from(0),
-            borrow_cap: U256::from(0),
+            supply_cap: U256::zero(),
+            borrow_cap: U256::zero(),
+            provenance: Provenance::of_symbol(&base_symbol),
+            total_supplied: 0.0,
+            protocol_held: None,
         };
-        
-        // Add WETH as collateral
-        let mut collateral_assets = HashMap::new();
-        let weth_address = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(); // WETH
-        
-        collateral_assets.insert(weth_address, Asset {
-            address: weth_address,
-            symbol: "WETH".to_string(),
-            decimals: 18,
-            price: 2000.0, // Approximate price
-            asset_type: AssetType::Collateral,
-            collateral_factor: 0.825,
-            liquidation_factor: 0.91,
-            liquidation_penalty: 0.05,
-            supply_cap: U256::from(10_000_000_000_000_000_000_000u128), // 10,000 ETH
-            borrow_cap: U256::from(0),
-        });
-        
-        // Create market with mock data
-        let market = Market {
-            name: "USDC".to_string(),
-            comet_address: self.comet_address,
+
+        let usd_per_price = self.usd_per_price(&comet, &base_asset, block).await?;
+        base_asset.price *= usd_per_price;
+
+        let collateral = futures::future::try_join_all((0..num_assets).map(|i| self.fetch_collateral_asset(&comet, i, block))).await?;
+        let collateral_assets = collateral
+            .into_iter()
+            .map(|mut asset| {
+                asset.price *= usd_per_price;
+                (asset.address, asset)
+            })
+            .collect();
+
+        let supply_apr = supply_rate as f64 / FACTOR_SCALE * SECONDS_PER_YEAR;
+        let borrow_apr = borrow_rate as f64 / FACTOR_SCALE * SECONDS_PER_YEAR;
+        let mut market = Market {
+            name: base_symbol,
+            comet_address: address,
             base_asset,
             collateral_assets,
-            total_supply: 1_000_000_000.0,
-            total_borrow: 750_000_000.0,
-            utilization_rate: 0.75,
-            supply_apr: 0.0125,
-            borrow_apr: 0.0325,
-            base_tracking_supply_speed: U256::from(0),
-            base_tracking_borrow_speed: U256::from(0),
-            base_min_interest_rate: U256::from(0),
-            base_max_interest_rate: U256::from(0),
+            total_supply: u256_to_f64(total_supply, base_decimals),
+            total_borrow: u256_to_f64(total_borrow, base_decimals),
+            // The rates above take Comet's own utilization; the field is derived from the totals
+            utilization_rate: Market::utilization_of(u256_to_f64(total_supply, base_decimals), u256_to_f64(total_borrow, base_decimals)),
+            supply_apr,
+            borrow_apr,
+            base_tracking_supply_speed: supply_speed,
+            base_tracking_borrow_speed: borrow_speed,
+            // Comet exposes a piecewise rate curve rather than min/max rates
+            base_min_interest_rate: U256::zero(),
+            base_max_interest_rate: U256::zero(),
+            base_borrow_min: u256_to_f64(U256::from(base_borrow_min), base_decimals),
+            store_front_price_factor: Some(store_front_price_factor as f64 / FACTOR_SCALE),
+            supply_reward_apr: 0.0,
+            borrow_reward_apr: 0.0,
+            net_supply_apr: supply_apr,
+            net_borrow_apr: borrow_apr,
+            rewards: None,
+            block_number: Some(block),
+            data_issues: Vec::new(),
         };
-        
+        market.annotate_data_quality();
+        for issue in &market.data_issues {
+            warn!(market = %market.name, block, check = %issue.check, "Degraded market data: {}", issue.message);
+        }
+        if let Some(rewards) = self.fetch_rewards(&comet, &market, block).await? {
+            let base_price = market.base_asset.price;
+            let reward_apr = |speed: U256, total_base: f64| {
+                let total_usd = total_base * base_price;
+                if total_usd > 0.0 {
+                    u256_to_f64(speed, 0) * rewards.per_speed_unit * SECONDS_PER_YEAR * rewards.funding.token_price / total_usd
+                } else {
+                    0.0
+                }
+            };
+            let supply_reward_apr = reward_apr(supply_speed, market.total_supply);
+            let borrow_reward_apr = reward_apr(borrow_speed, market.total_borrow);
+            market.set_reward_aprs(supply_reward_apr, borrow_reward_apr);
+            market.rewards = Some(rewards.funding);
+        }
+        // `assets` settings take precedence over the built-in classification
+        for asset in std::iter::once(&mut market.base_asset).chain(market.collateral_assets.values_mut()) {
+            asset.provenance = self.config.provenance(asset);
+        }
         Ok(market)
     }
+
+    /// USD per unit of the prices `comet` reports: 1 where it prices in USD, ETH/USD from
+    /// `compound.eth_usd_feed` where it prices in ETH
+    ///
+    /// A Comet prices in its base asset when that is priced at exactly 1 without being a USD
+    /// stablecoin. The WETH Comets do, with every collateral feed an ETH rate (wstETH/ETH,
+    /// cbETH/ETH); scaling all prices alike leaves health factors as Comet computes them.
+    /// Other base-priced Comets keep their prices in base units, with a warning.
+    async fn usd_per_price(&self, comet: &Comet<RpcProvider>, base: &Asset, block: u64) -> Result<f64> {
+        if base.price != 1.0 || base.is_usd_stablecoin() {
+            return Ok(1.0);
+        }
+        if !["WETH", "ETH"].iter().any(|s| s.eq_ignore_ascii_case(&base.symbol)) {
+            warn!("The {} Comet prices its assets in {}, not USD; its values are in {} too", base.symbol, base.symbol, base.symbol);
+            return Ok(1.0);
+        }
+        let Some(feed) = &self.config.compound.eth_usd_feed else {
+            return Err(RiskEngineError::config(
+                "compound.eth_usd_feed",
+                format!("the {} Comet prices its assets in ETH; set an ETH/USD feed to convert them to USD", base.symbol),
+            ));
+        };
+        let feed = Address::from_str(feed)
+            .map_err(|e| RiskEngineError::config("compound.eth_usd_feed", format!("`{}` is not a valid address: {}", feed, e)))?;
+        let price = read_at(block, comet.address(), "getPrice", comet.get_price(feed)).await?;
+        Ok(u256_to_f64(price, PRICE_DECIMALS))
+    }
+
+    /// Read the COMP funding of the rewards contract and Comet's tracking scale; `None` when
+    /// `compound.rewards_address` or `compound.comp_price_feed` is not set, or the rewards
+    /// contract pays nothing for this Comet
+    async fn fetch_rewards(&self, comet: &Comet<RpcProvider>, market: &Market, block: u64) -> Result<Option<Rewards>> {
+        let compound = &self.config.compound;
+        let (Some(contract), Some(feed)) = (&compound.rewards_address, &compound.comp_price_feed) else {
+            return Ok(None);
+        };
+        let address = |field: &'static str, value: &str| {
+            Address::from_str(value).map_err(|e| RiskEngineError::config(field, format!("`{}` is not a valid address: {}", value, e)))
+        };
+        let (contract, feed) = (address("compound.rewards_address", contract)?, address("compound.comp_price_feed", feed)?);
+
+        let rewards = CometRewards::new(contract, self.provider.clone());
+        let (token, _rescale_factor, _should_upscale) = read_at(block, contract, "rewardConfig", rewards.reward_config(market.comet_address)).await?;
+        if token.is_zero() {
+            return Ok(None);
+        }
+        let erc20 = ERC20::new(token, self.provider.clone());
+        let (tracking_scale, price, balance, decimals) = futures::try_join!(
+            read_at(block, market.comet_address, "trackingIndexScale", comet.tracking_index_scale()),
+            read_at(block, market.comet_address, "getPrice", comet.get_price(feed)),
+            read_at(block, token, "balanceOf", erc20.balance_of(contract)),
+            read_at(block, token, "decimals", erc20.decimals()),
+        )?;
+
+        // Tracking speeds are reward tokens per second, scaled by `trackingIndexScale`
+        let per_speed_unit = 1.0 / tracking_scale.max(1) as f64;
+        let speeds = u256_to_f64(market.base_tracking_supply_speed + market.base_tracking_borrow_speed, 0);
+        Ok(Some(Rewards {
+            per_speed_unit,
+            funding: RewardsFunding {
+                contract,
+                token,
+                token_price: u256_to_f64(price, PRICE_DECIMALS),
+                balance: u256_to_f64(balance, decimals),
+                daily_emission: speeds * per_speed_unit * SECONDS_PER_DAY,
+            },
+        }))
+    }
+
+    /// Read collateral asset `index` of `comet` with its oracle price at `block`
+    async fn fetch_collateral_asset(&self, comet: &Comet<RpcProvider>, index: u8, block: u64) -> Result<Asset> {
+        let address = comet.address();
+        let (_offset, asset, price_feed, scale, borrow_cf, liquidate_cf, liquidation_factor, supply_cap) =
+            read_at(block, address, "getAssetInfo", comet.get_asset_info(index)).await?;
+        let (price, (total_supplied, _), protocol_held, (symbol, _)) = futures::try_join!(
+            read_at(block, address, "getPrice", comet.get_price(price_feed)),
+            read_at(block, address, "totalsCollateral", comet.totals_collateral(asset)),
+            read_at(block, address, "getCollateralReserves", comet.get_collateral_reserves(asset)),
+            self.token_metadata(asset, block),
+        )?;
+
+        let decimals = (scale as f64).log10().round() as u8;
+        let provenance = Provenance::of_symbol(&symbol);
+        Ok(Asset {
+            address: asset,
+            symbol,
+            decimals,
+            price: u256_to_f64(price, PRICE_DECIMALS),
+            asset_type: AssetType::Collateral,
+            collateral_factor: borrow_cf as f64 / FACTOR_SCALE,
+            liquidation_factor: liquidate_cf as f64 / FACTOR_SCALE,
+            liquidation_penalty: 1.0 - liquidation_factor as f64 / FACTOR_SCALE,
+            supply_cap: U256::from(supply_cap),
+            borrow_cap: U256::zero(),
+            provenance,
+            total_supplied: u256_to_f64(U256::from(total_supplied), decimals),
+            protocol_held: Some(u256_to_f64(protocol_held, decimals)),
+        })
+    }
+
+    /// Symbol and decimals of an ERC-20 token
+    async fn token_metadata(&self, token: Address, block: u64) -> Result<(String, u8)> {
+        let erc20 = ERC20::new(token, self.provider.clone());
+        futures::try_join!(
+            read_at(block, token, "symbol", erc20.symbol()),
+            read_at(block, token, "decimals", erc20.decimals()),
+        )
+    }
     
     /// Get information about a user's position in a market
-    pub async fn get_user_position(&self, _market: &Market, user_address: Address) -> Result<UserPosition> {
-        // For milestone 1, we'll return a mock user position
-        // In a production version, this would make real contract calls
-        
-        // Mock a user with some USDC supplied and WETH as collateral
-        let weth_address = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap();
-        
+    #[instrument(
+        level = "debug",
+        skip(self, market),
+        fields(market = %market.name, rpc_host = %self.rpc_host()),
+        err(level = "debug")
+    )]
+    pub async fn get_user_position(&self, market: &Market, user_address: Address) -> Result<UserPosition> {
+        let block = self.provider.head_block().await?;
+        self.fetch_user_position(market, user_address, block).await
+    }
+
+    /// Read a user's position with every read pinned to `block`
+    async fn fetch_user_position(&self, market: &Market, user_address: Address, block: u64) -> Result<UserPosition> {
+        if user_address.is_zero() {
+            return Err(RiskEngineError::NotFound {
+                kind: "position",
+                id: format!("{:?}", user_address),
+            });
+        }
+
+
+        let address = market.comet_address;
+        let comet = Comet::new(address, self.provider.clone());
+        let (supplied, borrowed) = futures::try_join!(
+            read_at(block, address, "balanceOf", comet.balance_of(user_address)),
+            read_at(block, address, "borrowBalanceOf", comet.borrow_balance_of(user_address)),
+        )?;
+
+        let assets = sorted_collateral(market);
+        let balances = futures::future::try_join_all(assets.iter().map(|asset| {
+            read_at(block, address, "collateralBalanceOf", comet.collateral_balance_of(user_address, asset.address))
+        }))
+        .await?;
+        Ok(self.position_from_balances(market, user_address, supplied, borrowed, assets.into_iter().zip(balances), block))
+    }
+
+    /// Position of `user_address` from its base balances and the balance of each collateral asset
+    fn position_from_balances<'a>(
+        &self,
+        market: &Market,
+        user_address: Address,
+        supplied: U256,
+        borrowed: U256,
+        balances: impl IntoIterator<Item = (&'a Asset, U256)>,
+        block: u64,
+    ) -> UserPosition {
         let mut collateral_balances = HashMap::new();
-        collateral_balances.insert(weth_address, 0.5); // 0.5 ETH collateral
-        
-        let position = UserPosition {
+        let mut total_collateral_value = 0.0;
+        for (asset, balance) in balances {
+            if balance.is_zero() {
+                continue;
+            }
+            let amount = u256_to_f64(balance, asset.decimals);
+            total_collateral_value += amount * asset.price;
+            collateral_balances.insert(asset.address, amount);
+        }
+
+        let decimals = market.base_asset.decimals;
+        let base_balance = u256_to_f64(supplied, decimals) - u256_to_f64(borrowed, decimals);
+        let health_factor = self.calculate_health_factor(base_balance, &collateral_balances, market);
+
+        let mut position = UserPosition {
             address: user_address,
-            base_balance: 1000.0, // 1000 USDC supplied
+            base_balance,
             collateral_balances,
-            total_collateral_value: 0.5 * 2000.0, // 0.5 ETH * $2000/ETH
-            total_borrow_value: 0.0, // No borrowing
-            health_factor: 2.0, // Healthy position
+            total_collateral_value,
+            total_borrow_value: u256_to_f64(borrowed, decimals) * market.base_asset.price,
+            health_factor,
+            block_number: Some(block),
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
         };
-        
-        Ok(position)
+        position.set_borrow_capacity(market);
+        position
     }
     
+    /// Fetch many positions with at most `concurrency` batched reads in flight
+    ///
+    /// Accounts are packed `POSITION_BATCH_SIZE` per read: one Multicall3 `aggregate3` call
+    /// carrying the `balanceOf`, `borrowBalanceOf` and `collateralBalanceOf` reads of every account
+    /// of the group. Where that call fails, e.g. on an endpoint that rejects large calls, the
+    /// group's accounts are read one at a time instead. All reads are pinned to the chain
+    /// head resolved by the first batch. Results are yielded as each
+    /// batch completes (not in input order), and a failing address is reported inline
+    /// instead of failing the whole batch. Once the client's cancellation token fires,
+    /// remaining batches are not fetched and their addresses report `RiskEngineError::Cancelled`.
+    /// Positions received are reported to the client's progress reporter as `Stage::PositionFetch`.
+    pub fn get_positions_batch<'a>(
+        &'a self,
+        market: &'a Market,
+        addresses: Vec<Address>,
+        concurrency: usize,
+    ) -> impl Stream<Item = PositionResult> + 'a {
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let block = Arc::new(OnceCell::new());
+        let fetched = Arc::new(AtomicUsize::new(0));
+        let started = Instant::now();
+        let total = addresses.len();
+
+        let chunks: Vec<Vec<Address>> = addresses
+            .chunks(POSITION_BATCH_SIZE)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+
+        let counter = fetched.clone();
+        let task = self.progress.start(Stage::PositionFetch, total as u64);
+        stream::iter(chunks)
+            .map(move |chunk| {
+                let permits = permits.clone();
+                let block = block.clone();
+                async move {
+                    // The semaphore is never closed, so acquiring cannot fail
+                    let _permit = permits.acquire_owned().await.expect("semaphore closed");
+                    self.fetch_position_chunk(market, chunk, &block).await
+                }
+            })
+            .buffer_unordered(total.div_ceil(POSITION_BATCH_SIZE).max(1))
+            .flat_map(stream::iter)
+            .inspect(move |_| {
+                let done = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                task.set(done as u64);
+            })
+            .map(Some)
+            .chain(stream::once(async move {
+                let elapsed = started.elapsed().as_secs_f64();
+                let done = fetched.load(Ordering::Relaxed);
+                debug!(
+                    "Fetched {}/{} positions in {:.2}s ({:.1} positions/sec)",
+                    done,
+                    total,
+                    elapsed,
+                    done as f64 / elapsed.max(f64::EPSILON),
+                );
+                None
+            }))
+            .filter_map(|item| async move { item })
+    }
+
+    /// Read the positions of one packed group of accounts at `block`, resolving the chain head
+    /// into it first if no other group of the batch has
+    #[instrument(level = "debug", skip_all, fields(market = %market.name, accounts = chunk.len(), rpc_host = %self.rpc_host()))]
+    async fn fetch_position_chunk(&self, market: &Market, chunk: Vec<Address>, block: &OnceCell<u64>) -> Vec<PositionResult> {
+        if self.cancel.is_cancelled() {
+            return chunk.into_iter().map(|address| (address, Err(RiskEngineError::Cancelled))).collect();
+        }
+        let block = match block.get_or_try_init(|| self.provider.head_block()).await {
+            Ok(block) => *block,
+            Err(e) => {
+                let reason = e.to_string();
+                let unavailable = || RiskEngineError::Unavailable { what: "chain head", reason: reason.clone() };
+                return chunk.into_iter().map(|address| (address, Err(unavailable()))).collect();
+            }
+        };
+
+        match self.read_position_chunk(market, &chunk, block).await {
+            Ok(results) => results,
+            Err(e) => {
+                debug!("Packed read of {} positions failed, reading them one at a time: {}", chunk.len(), e);
+                let mut results = Vec::with_capacity(chunk.len());
+                for address in chunk {
+                    let position = self.fetch_user_position(market, address, block).await;
+                    results.push((address, position));
+                }
+                results
+            }
+        }
+    }
+
+    /// Read the positions of `chunk` at `block` in one Multicall3 `aggregate3` call; an account
+    /// with a reverted read is reported inline, a failure of the call itself fails the group
+    async fn read_position_chunk(&self, market: &Market, chunk: &[Address], block: u64) -> Result<Vec<PositionResult>> {
+        let address = market.comet_address;
+        let comet = Comet::new(address, self.provider.clone());
+        let assets = sorted_collateral(market);
+        let accounts: Vec<Address> = chunk.iter().copied().filter(|account| !account.is_zero()).collect();
+        // Empty calldata would come back as garbage decoded as positions, so it fails the group
+        let call = |call: ContractCall<RpcProvider, U256>| -> Result<Call3> {
+            let call_data = call.calldata().ok_or_else(|| RiskEngineError::Unavailable {
+                what: "packed position reads",
+                reason: format!("{} could not be encoded", call.function.name),
+            })?;
+            Ok(Call3 { target: address, allow_failure: true, call_data })
+        };
+        let calls: Vec<Call3> = accounts
+            .iter()
+            .flat_map(|&account| {
+                [call(comet.balance_of(account)), call(comet.borrow_balance_of(account))]
+                    .into_iter()
+                    .chain(assets.iter().map(|asset| call(comet.collateral_balance_of(account, asset.address))))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Result<_>>()?;
+        let reads_per_account = 2 + assets.len();
+        let multicall = Multicall3::new(MULTICALL3_ADDRESS, self.provider.clone());
+        let returned = read_at(block, MULTICALL3_ADDRESS, "aggregate3", multicall.aggregate_3(calls)).await?;
+        if returned.len() != accounts.len() * reads_per_account {
+            return Err(RiskEngineError::Unavailable {
+                what: "packed position reads",
+                reason: format!("aggregate3 returned {} results for {} calls", returned.len(), accounts.len() * reads_per_account),
+            });
+        }
+
+        let mut returned = returned.chunks(reads_per_account);
+        let results = chunk
+            .iter()
+            .map(|&account| {
+                if account.is_zero() {
+                    return (account, Err(RiskEngineError::NotFound { kind: "position", id: format!("{:?}", account) }));
+                }
+                let reads = returned.next().expect("one group of results per account");
+                let decoded = reads
+                    .iter()
+                    .zip(["balanceOf", "borrowBalanceOf"].into_iter().chain(std::iter::repeat("collateralBalanceOf")))
+                    .map(|(read, method)| decode_call3(address, method, read))
+                    .collect::<Result<Vec<U256>>>();
+                let position = decoded.map(|values| {
+                    let balances = assets.iter().copied().zip(values[2..].iter().copied());
+                    self.position_from_balances(market, account, values[0], values[1], balances, block)
+                });
+                (account, position)
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Borrow of each of `accounts` in `market`, in USD, from one `borrowBalanceOf` each with at
+    /// most `concurrency` in flight, all at the chain head resolved first
+    ///
+    /// Cheaper than their positions when only the size of each borrow is needed, as for
+    /// sampling positions by borrow size.
+    #[instrument(level = "debug", skip_all, fields(market = %market.name, accounts = accounts.len(), rpc_host = %self.rpc_host()), err(level = "debug"))]
+    pub async fn get_borrows(&self, market: &Market, accounts: &[Address], concurrency: usize) -> Result<Vec<(Address, Result<f64>)>> {
+        if self.cancel.is_cancelled() {
+            return Err(RiskEngineError::Cancelled);
+        }
+        let block = self.provider.head_block().await?;
+        let address = market.comet_address;
+        let comet = Comet::new(address, self.provider.clone());
+        let (decimals, price) = (market.base_asset.decimals, market.base_asset.price);
+        let borrows = stream::iter(accounts.to_vec())
+            .map(|account| {
+                let call = comet.borrow_balance_of(account);
+                async move {
+                    let borrowed = read_at(block, address, "borrowBalanceOf", call).await;
+                    (account, borrowed.map(|borrowed| u256_to_f64(borrowed, decimals) * price))
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        Ok(borrows)
+    }
+
+    /// Managers `account` currently allows in `market`
+    ///
+    /// Candidates are the spenders of the account's `Approval` logs since `scanner.start_block`
+    /// and `candidates`; each is checked with `isAllowed`, since a later `allow` may have revoked
+    /// it. When the logs cannot be read (e.g. the endpoint limits the block range) only
+    /// `candidates` are checked and the result is marked incomplete.
+    #[instrument(level = "debug", skip(self, market, candidates), fields(market = %market.name), err(level = "debug"))]
+    pub async fn get_manager_permissions(&self, market: &Market, account: Address, candidates: &[Address]) -> Result<ManagerPermissions> {
+        let address = market.comet_address;
+        let comet = Comet::new(address, self.provider.clone());
+        let mut candidates: std::collections::BTreeSet<Address> = candidates.iter().copied().collect();
+        let from = self.config.scanner.start_block;
+        let approvals = match self.provider.head_block().await {
+            Ok(head) => self.provider.approvals_in_range(address, account, from, head).await,
+            Err(e) => Err(e),
+        };
+        let complete = match approvals {
+            Ok(spenders) => {
+                candidates.extend(spenders);
+                true
+            }
+            Err(e) => {
+                debug!("Checking only candidate managers of {:?} in {}: {}", account, market.name, e);
+                false
+            }
+        };
+        candidates.remove(&account);
+
+        let nonce = read(address, "userNonce", comet.user_nonce(account)).await?;
+        let allowed = futures::future::try_join_all(
+            candidates.iter().map(|&manager| read(address, "isAllowed", comet.is_allowed(account, manager))),
+        )
+        .await?;
+        Ok(ManagerPermissions {
+            account,
+            nonce: nonce.low_u64(),
+            managers: candidates.into_iter().zip(allowed).filter(|(_, allowed)| *allowed).map(|(manager, _)| manager).collect(),
+            complete,
+        })
+    }
+
+    /// State of the L2 sequencer from `compound.sequencer_uptime_feed`, `None` without one
+    ///
+    /// The feed answers 0 while the sequencer is up and 1 while it is down; the round's
+    /// `startedAt` is when that last changed.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn get_sequencer_status(&self) -> Result<Option<SequencerStatus>> {
+        let Some(feed) = &self.config.compound.sequencer_uptime_feed else { return Ok(None) };
+        let feed = Address::from_str(feed)
+            .map_err(|e| RiskEngineError::config("compound.sequencer_uptime_feed", e.to_string()))?;
+        let (_, answer, started_at, _, _) = read(feed, "latestRoundData", AggregatorV3::new(feed, self.provider.clone()).latest_round_data()).await?;
+        let since = chrono::DateTime::from_timestamp(started_at.low_u64() as i64, 0).unwrap_or_default();
+        Ok(Some(SequencerStatus { up: answer.is_zero(), since }))
+    }
+
+    /// Current fees of the network: the next block's base fee and the `fee_percentile`
+    /// priority fee of the last `FEE_HISTORY_BLOCKS` blocks, or `eth_gasPrice` where fee
+    /// history is not served, plus the L1 data fee of an absorption on rollups with an L1 fee
+    /// oracle
+    #[instrument(level = "debug", skip(self), fields(rpc_host = %self.rpc_host()), err(level = "debug"))]
+    pub async fn get_gas_quote(&self, fee_percentile: f64) -> Result<GasQuote> {
+        let rpc_error = |method: &str, e: ProviderError| RiskEngineError::Unavailable {
+            what: "gas price",
+            reason: format!("{} failed: {}", method, e),
+        };
+        let gwei = |wei: U256| u256_to_f64(wei, 9);
+        let mut quote = match self.provider.fee_history(FEE_HISTORY_BLOCKS, BlockNumber::Latest, &[fee_percentile]).await {
+            Ok(history) if !history.base_fee_per_gas.is_empty() => {
+                // The last base fee is the next block's
+                let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+                let mut tips: Vec<U256> = history.reward.iter().filter_map(|rewards| rewards.first().copied()).collect();
+                tips.sort();
+                let tip = tips.get(tips.len() / 2).copied().unwrap_or_default();
+                GasQuote { base_fee_gwei: gwei(base_fee), priority_fee_gwei: gwei(tip), l1_data_fee: None, source: GasSource::FeeHistory }
+            }
+            history => {
+                if let Err(e) = history {
+                    debug!("No fee history, reading eth_gasPrice: {}", e);
+                }
+                let price = self.provider.get_gas_price().await.map_err(|e| rpc_error("eth_gasPrice", e))?;
+                GasQuote { base_fee_gwei: 0.0, priority_fee_gwei: gwei(price), l1_data_fee: None, source: GasSource::GasPrice }
+            }
+        };
+        if let Some(oracle) = Network::from_chain_id(self.config.compound.chain_id).and_then(Network::l1_fee_oracle) {
+            let oracle = Address::from_str(oracle).expect("bundled L1 fee oracle address is valid");
+            let calldata = Bytes::from(vec![0xff; ABSORPTION_CALLDATA_BYTES]);
+            match read(oracle, "getL1Fee", L1FeeOracle::new(oracle, self.provider.clone()).get_l1_fee(calldata)).await {
+                Ok(fee) => quote.l1_data_fee = Some(u256_to_f64(fee, 18)),
+                Err(e) => debug!("No L1 data fee: {}", e),
+            }
+        }
+        Ok(quote)
+    }
+
+    /// Get the price history of an asset in a market
+    #[instrument(level = "debug", skip(self, market), fields(market = %market.name), err(level = "debug"))]
+    pub async fn get_price_history(&self, market: &Market, asset_address: Address) -> Result<PriceHistory> {
+        let asset = if market.base_asset.address == asset_address {
+            &market.base_asset
+        } else {
+            market.collateral_assets.get(&asset_address).ok_or_else(|| RiskEngineError::NotFound {
+                kind: "asset",
+                id: format!("{:?} in {}", asset_address, market.name),
+            })?
+        };
+
+        // Comet only exposes the current oracle price; history needs an external source
+        Err(RiskEngineError::Unavailable {
+            what: "price history",
+            reason: format!(
+                "{} has no on-chain price history; configure a historical price source",
+                asset.symbol
+            ),
+        })
+    }
+
     /// Get protocol metrics for a market
+    ///
+    /// Comet does not track account counts on chain, so `suppliers_count` and
+    /// `borrowers_count` are 0 here.
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(market = %market.name, rpc_host = %self.rpc_host()),
+        err(level = "debug")
+    )]
     pub async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics> {
-        // For milestone 1, return mock metrics
-        let metrics = ProtocolMetrics {
+        let address = market.comet_address;
+        let comet = Comet::new(address, self.provider.clone());
+        let reserves = read(address, "getReserves", comet.get_reserves()).await?;
+        let reserves_units = u256_to_f64(reserves.unsigned_abs(), market.base_asset.decimals);
+        let reserves_units = if reserves.is_negative() { -reserves_units } else { reserves_units };
+
+        Ok(ProtocolMetrics {
             tvl: market.total_supply * market.base_asset.price,
             total_borrow: market.total_borrow * market.base_asset.price,
-            utilization_rate: market.utilization_rate,
-            suppliers_count: 1250,
-            borrowers_count: 750,
-            reserves: 25000000.0,
-        };
-        
-        Ok(metrics)
+            utilization_rate: market.utilization(),
+            suppliers_count: 0,
+            borrowers_count: 0,
+            reserves: reserves_units * market.base_asset.price,
+            rates: Some(market.rates()),
+        })
     }
     
+    /// Configuration of `market` in `compound.configurator_address` and the parameters its
+    /// deployed Comet runs, both read at the same block
+    #[instrument(level = "debug", skip_all, fields(market = %market.name, rpc_host = %self.rpc_host()), err(level = "debug"))]
+    pub async fn get_market_configurations(&self, market: &Market) -> Result<ConfigurationPair> {
+        let configurator = Address::from_str(&self.config.compound.configurator_address)
+            .map_err(|e| RiskEngineError::config("compound.configurator_address", e.to_string()))?;
+        let block = self.provider.head_block().await?;
+        let (configured, live) = futures::try_join!(
+            read_at(block, configurator, "getConfiguration", CometConfigurator::new(configurator, self.provider.clone()).get_configuration(market.comet_address)),
+            self.fetch_live_configuration(market.comet_address, block),
+        )?;
+        let configured = configuration_of(configured).map_err(|e| RiskEngineError::contract_call(configurator, "getConfiguration", e))?;
+        Ok(ConfigurationPair { configurator, configured, live })
+    }
+
+    /// Parameters of the Comet at `address`, with its per-second rates made yearly
+    async fn fetch_live_configuration(&self, address: Address, block: u64) -> Result<MarketConfiguration> {
+        let comet = Comet::new(address, self.provider.clone());
+        let (governor, pause_guardian, base_token, base_token_price_feed, extension_delegate, num_assets) = futures::try_join!(
+            read_at(block, address, "governor", comet.governor()),
+            read_at(block, address, "pauseGuardian", comet.pause_guardian()),
+            read_at(block, address, "baseToken", comet.base_token()),
+            read_at(block, address, "baseTokenPriceFeed", comet.base_token_price_feed()),
+            read_at(block, address, "extensionDelegate", comet.extension_delegate()),
+            read_at(block, address, "numAssets", comet.num_assets()),
+        )?;
+        let (supply_kink, supply_slope_low, supply_slope_high, supply_base, borrow_kink, borrow_slope_low, borrow_slope_high, borrow_base) = futures::try_join!(
+            read_at(block, address, "supplyKink", comet.supply_kink()),
+            read_at(block, address, "supplyPerSecondInterestRateSlopeLow", comet.supply_per_second_interest_rate_slope_low()),
+            read_at(block, address, "supplyPerSecondInterestRateSlopeHigh", comet.supply_per_second_interest_rate_slope_high()),
+            read_at(block, address, "supplyPerSecondInterestRateBase", comet.supply_per_second_interest_rate_base()),
+            read_at(block, address, "borrowKink", comet.borrow_kink()),
+            read_at(block, address, "borrowPerSecondInterestRateSlopeLow", comet.borrow_per_second_interest_rate_slope_low()),
+            read_at(block, address, "borrowPerSecondInterestRateSlopeHigh", comet.borrow_per_second_interest_rate_slope_high()),
+            read_at(block, address, "borrowPerSecondInterestRateBase", comet.borrow_per_second_interest_rate_base()),
+        )?;
+        let (store_front_price_factor, tracking_index_scale, supply_speed, borrow_speed, base_min_for_rewards, base_borrow_min, target_reserves) = futures::try_join!(
+            read_at(block, address, "storeFrontPriceFactor", comet.store_front_price_factor()),
+            read_at(block, address, "trackingIndexScale", comet.tracking_index_scale()),
+            read_at(block, address, "baseTrackingSupplySpeed", comet.base_tracking_supply_speed()),
+            read_at(block, address, "baseTrackingBorrowSpeed", comet.base_tracking_borrow_speed()),
+            read_at(block, address, "baseMinForRewards", comet.base_min_for_rewards()),
+            read_at(block, address, "baseBorrowMin", comet.base_borrow_min()),
+            read_at(block, address, "targetReserves", comet.target_reserves()),
+        )?;
+        let assets = futures::future::try_join_all((0..num_assets).map(|i| read_at(block, address, "getAssetInfo", comet.get_asset_info(i)))).await?;
+
+        let yearly = |per_second: u64| per_second.saturating_mul(RATE_SECONDS_PER_YEAR);
+        Ok(MarketConfiguration {
+            governor,
+            pause_guardian,
+            base_token,
+            base_token_price_feed,
+            extension_delegate,
+            supply_kink,
+            supply_per_year_interest_rate_slope_low: yearly(supply_slope_low),
+            supply_per_year_interest_rate_slope_high: yearly(supply_slope_high),
+            supply_per_year_interest_rate_base: yearly(supply_base),
+            borrow_kink,
+            borrow_per_year_interest_rate_slope_low: yearly(borrow_slope_low),
+            borrow_per_year_interest_rate_slope_high: yearly(borrow_slope_high),
+            borrow_per_year_interest_rate_base: yearly(borrow_base),
+            store_front_price_factor,
+            tracking_index_scale,
+            base_tracking_supply_speed: supply_speed.low_u64(),
+            base_tracking_borrow_speed: borrow_speed.low_u64(),
+            base_min_for_rewards,
+            base_borrow_min,
+            target_reserves,
+            asset_configs: assets
+                .into_iter()
+                .map(|(_offset, asset, price_feed, scale, borrow_collateral_factor, liquidate_collateral_factor, liquidation_factor, supply_cap)| {
+                    AssetConfiguration {
+                        asset,
+                        price_feed,
+                        decimals: (scale as f64).log10().round() as u8,
+                        borrow_collateral_factor,
+                        liquidate_collateral_factor,
+                        liquidation_factor,
+                        supply_cap,
+                    }
+                })
+                .collect(),
+        })
+    }
+
     /// Calculate health factor for a user position
     pub fn calculate_health_factor(&self, base_balance: f64, collateral_balances: &HashMap<Address, f64>, market: &Market) -> f64 {
-        // If no borrow, health factor is high
-        if base_balance >= 0.0 {
-            return 100.0;
-        }
-        
-        // Calculate total collateral value
-        let mut total_collateral_value = 0.0;
-        for (address, &amount) in collateral_balances {
-            if let Some(asset) = market.collateral_assets.get(address) {
-                // Apply collateral factor
-                total_collateral_value += amount * asset.price * asset.collateral_factor;
-            }
-        }
-        
-        // Calculate borrow value
-        let borrow_value = -base_balance * market.base_asset.price;
-        
-        // Health factor is collateral value / borrow value
-        if borrow_value > 0.0 {
-            total_collateral_value / borrow_value
-        } else {
-            100.0
-        }
+        market.health_factor(base_balance, collateral_balances)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::testing::FakeChain;
+    use crate::config::MAINNET_ETH_USD_FEED;
+    use ethers::abi::AbiEncode;
+    use ethers::types::{H256, I256};
     
     #[test]
     fn test_u256_to_f64() {
@@ -232,20 +1139,475 @@ mod tests {
     }
     
     #[tokio::test]
-    async fn test_create_mock_market() {
-        let config = Arc::new(Config::default());
-        let client = CompoundClient::new(config).await.unwrap();
-        let market = client.create_mock_market().await.unwrap();
-        
+    async fn test_bad_rpc_url_is_config_error() {
+        let mut config = Config::default();
+        config.compound.rpc_url = "not a url".to_string();
+        let err = CompoundClient::new(Arc::new(config)).await.err().unwrap();
+        assert!(matches!(err, RiskEngineError::Config { ref field, .. } if field == "compound.rpc_url"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_comet_address_is_config_error() {
+        let mut config = Config::default();
+        config.compound.comet_proxy_address = "0x1234".to_string();
+        let err = CompoundClient::new(Arc::new(config)).await.err().unwrap();
+        assert!(matches!(
+            err,
+            RiskEngineError::Config { ref field, .. } if field == "compound.comet_proxy_address"
+        ));
+    }
+
+    const USDC: u64 = 0xa0;
+    const WETH: u64 = 0xe0;
+    const USDC_FEED: u64 = 0xfa;
+    const WETH_FEED: u64 = 0xfe;
+    const COMP: u64 = 0xc0;
+
+    fn addr(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn e18(value: f64) -> u64 {
+        (value * 1e18) as u64
+    }
+
+    /// Fake chain holding a USDC Comet with WETH collateral at 75% utilization, emitting 0.01
+    /// COMP a second to suppliers and 0.02 to borrowers from 25,920 COMP (10 days)
+    fn fake_comet(comet: Address) -> FakeChain {
+        let chain = FakeChain::new();
+        let utilization = U256::from(e18(0.75));
+        let config = Config::default();
+        let rewards = Address::from_str(config.compound.rewards_address.as_deref().unwrap()).unwrap();
+        let comp_feed = Address::from_str(config.compound.comp_price_feed.as_deref().unwrap()).unwrap();
+        chain
+            .on_call(comet, comet::BaseTokenCall, addr(USDC))
+            .on_call(comet, comet::BaseTokenPriceFeedCall, addr(USDC_FEED))
+            .on_call(comet, comet::TotalSupplyCall, U256::from(1_000_000_000_000_000u64))
+            .on_call(comet, comet::TotalBorrowCall, U256::from(750_000_000_000_000u64))
+            .on_call(comet, comet::GetUtilizationCall, utilization)
+            .on_call(comet, comet::GetSupplyRateCall(utilization), 1_000_000_000u64)
+            .on_call(comet, comet::GetBorrowRateCall(utilization), 2_000_000_000u64)
+            .on_call(comet, comet::GetPriceCall(addr(USDC_FEED)), U256::from(100_000_000u64))
+            .on_call(comet, comet::GetPriceCall(addr(WETH_FEED)), U256::from(200_000_000_000u64))
+            .on_call(comet, comet::GetReservesCall, I256::from(-5_000_000i64))
+            .on_call(comet, comet::NumAssetsCall, 1u8)
+            .on_call(
+                comet,
+                comet::GetAssetInfoCall(0),
+                (0u8, addr(WETH), addr(WETH_FEED), e18(1.0), e18(0.825), e18(0.895), e18(0.95), 10_000u128 * 10u128.pow(18)),
+            )
+            .on_call(comet, comet::TotalsCollateralCall(addr(WETH)), (2_500u128 * 10u128.pow(18), 0u128))
+            .on_call(comet, comet::GetCollateralReservesCall(addr(WETH)), U256::from(40u64) * U256::exp10(18))
+            .on_call(comet, comet::BaseTrackingSupplySpeedCall, U256::from(10_000_000_000_000u64))
+            .on_call(comet, comet::BaseTrackingBorrowSpeedCall, U256::from(20_000_000_000_000u64))
+            .on_call(comet, comet::TrackingIndexScaleCall, 1_000_000_000_000_000u64)
+            .on_call(comet, comet::BaseBorrowMinCall, 100_000_000u128)
+            .on_call(comet, comet::StoreFrontPriceFactorCall, e18(0.6))
+            .on_call(comet, comet::GetPriceCall(comp_feed), U256::from(5_000_000_000u64))
+            .on_call(rewards, comet_rewards::RewardConfigCall(comet), (addr(COMP), 1_000_000_000_000u64, true))
+            .on_call(addr(COMP), erc20::BalanceOfCall(rewards), U256::from(25_920u64) * U256::exp10(18))
+            .on_call(addr(COMP), erc20::DecimalsCall, 18u8)
+            .on_call(addr(USDC), erc20::SymbolCall, "USDC".to_string())
+            .on_call(addr(USDC), erc20::DecimalsCall, 6u8)
+            .on_call(addr(WETH), erc20::SymbolCall, "WETH".to_string())
+            .on_call(addr(WETH), erc20::DecimalsCall, 18u8);
+        chain
+    }
+
+    /// Register a borrower of `borrow` USDC against `weth` WETH
+    fn add_borrower(chain: &FakeChain, comet: Address, user: Address, borrow: u64, weth: u64) {
+        chain
+            .on_call(comet, comet::BalanceOfCall(user), U256::zero())
+            .on_call(comet, comet::BorrowBalanceOfCall(user), U256::from(borrow) * 1_000_000)
+            .on_call(
+                comet,
+                comet::CollateralBalanceOfCall(user, addr(WETH)),
+                U256::from(weth) * U256::exp10(18),
+            );
+    }
+
+    async fn live_client(chain: &FakeChain) -> CompoundClient {
+        let mut config = Config::default();
+        config.compound.rpc_url = chain.serve().await;
+        CompoundClient::new(Arc::new(config)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_markets_replays_a_recorded_session() {
+        // Recorded with --record from the fake USDC Comet above; unrecorded requests fail, so
+        // this also pins the calls a market read makes
+        let mut config = Config::default();
+        config.rpc.mode = crate::config::RpcMode::Replay;
+        config.rpc.session_path = Some(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/sessions/usdc.jsonl").into());
+        let client = CompoundClient::new(Arc::new(config)).await.unwrap();
+        let market = client.get_markets().await.unwrap().remove(0);
+
+        assert_eq!((market.name.as_str(), market.total_supply, market.block_number), ("USDC", 1_000_000_000.0, Some(100)));
+        assert!((market.utilization_rate - 0.75).abs() < 1e-9);
+        let weth = &market.collateral_assets[&addr(WETH)];
+        assert_eq!((weth.symbol.as_str(), weth.price), ("WETH", 2000.0));
+        assert!((weth.collateral_factor - 0.825).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_markets_reads_comet() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let client = live_client(&fake_comet(comet)).await;
+        let market = client.get_markets().await.unwrap().remove(0);
+
         assert_eq!(market.name, "USDC");
-        assert_eq!(market.utilization_rate, 0.75);
+        assert_eq!(market.base_asset.decimals, 6);
+        assert_eq!(market.total_supply, 1_000_000_000.0);
+        assert!((market.utilization_rate - 0.75).abs() < 1e-9);
+        assert!(market.data_issues.is_empty());
+        assert!((market.borrow_apr - 2e-9 * SECONDS_PER_YEAR).abs() < 1e-9);
+        assert_eq!(market.base_borrow_min, 100.0);
+        assert_eq!(market.store_front_price_factor, Some(0.6));
+
+        let weth = &market.collateral_assets[&addr(WETH)];
+        assert_eq!((weth.symbol.as_str(), weth.decimals, weth.price), ("WETH", 18, 2000.0));
+        assert!((weth.collateral_factor - 0.825).abs() < 1e-9);
+        assert!((weth.liquidation_penalty - 0.05).abs() < 1e-9);
+        assert_eq!((weth.total_supplied, weth.protocol_held, weth.provenance), (2_500.0, Some(40.0), Provenance::Native));
+
+        // 315,360 COMP a year at $50 on $1bn supplied and $750m borrowed
+        assert!((market.supply_reward_apr - 0.015768).abs() < 1e-9, "{}", market.supply_reward_apr);
+        assert!((market.borrow_reward_apr - 0.042048).abs() < 1e-9, "{}", market.borrow_reward_apr);
+        assert!((market.net_borrow_apr - (market.borrow_apr - 0.042048)).abs() < 1e-9);
+        let rewards = market.rewards.as_ref().unwrap();
+        assert!((rewards.runway_days().unwrap() - 10.0).abs() < 1e-9);
+
+        let metrics = client.get_protocol_metrics(&market).await.unwrap();
+        assert_eq!(metrics.reserves, -5.0);
+        assert_eq!(metrics.rates, Some(market.rates()));
+    }
+
+    #[tokio::test]
+    async fn test_weth_comet_prices_convert_to_usd() {
+        const WSTETH: u64 = 0xe1;
+        const WSTETH_FEED: u64 = 0xf1;
+        let comet = addr(0xc0e7);
+        let user = addr(0x11);
+        let eth_usd = Address::from_str(MAINNET_ETH_USD_FEED).unwrap();
+        // Every price is in ETH: WETH's feed is a constant 1, wstETH's the wstETH/ETH rate
+        let chain = FakeChain::new();
+        let utilization = U256::from(e18(0.8));
+        chain
+            .on_call(comet, comet::BaseTokenCall, addr(WETH))
+            .on_call(comet, comet::BaseTokenPriceFeedCall, addr(WETH_FEED))
+            .on_call(comet, comet::TotalSupplyCall, U256::from(1_000u64) * U256::exp10(18))
+            .on_call(comet, comet::TotalBorrowCall, U256::from(800u64) * U256::exp10(18))
+            .on_call(comet, comet::GetUtilizationCall, utilization)
+            .on_call(comet, comet::GetSupplyRateCall(utilization), 500_000_000u64)
+            .on_call(comet, comet::GetBorrowRateCall(utilization), 900_000_000u64)
+            .on_call(comet, comet::GetPriceCall(addr(WETH_FEED)), U256::from(100_000_000u64))
+            .on_call(comet, comet::GetPriceCall(addr(WSTETH_FEED)), U256::from(117_000_000u64))
+            .on_call(comet, comet::GetPriceCall(eth_usd), U256::from(300_000_000_000u64))
+            .on_call(comet, comet::NumAssetsCall, 1u8)
+            .on_call(
+                comet,
+                comet::GetAssetInfoCall(0),
+                (0u8, addr(WSTETH), addr(WSTETH_FEED), e18(1.0), e18(0.9), e18(0.93), e18(0.97), 10_000u128 * 10u128.pow(18)),
+            )
+            .on_call(comet, comet::TotalsCollateralCall(addr(WSTETH)), (500u128 * 10u128.pow(18), 0u128))
+            .on_call(comet, comet::GetCollateralReservesCall(addr(WSTETH)), U256::zero())
+            .on_call(comet, comet::BaseTrackingSupplySpeedCall, U256::zero())
+            .on_call(comet, comet::BaseTrackingBorrowSpeedCall, U256::zero())
+            .on_call(comet, comet::BaseBorrowMinCall, 100_000_000_000_000_000u128)
+            .on_call(comet, comet::StoreFrontPriceFactorCall, e18(0.6))
+            .on_call(addr(WETH), erc20::SymbolCall, "WETH".to_string())
+            .on_call(addr(WETH), erc20::DecimalsCall, 18u8)
+            .on_call(addr(WSTETH), erc20::SymbolCall, "wstETH".to_string())
+            .on_call(addr(WSTETH), erc20::DecimalsCall, 18u8)
+            // 10 WETH borrowed against 10 wstETH
+            .on_call(comet, comet::BalanceOfCall(user), U256::zero())
+            .on_call(comet, comet::BorrowBalanceOfCall(user), U256::from(10u64) * U256::exp10(18))
+            .on_call(comet, comet::CollateralBalanceOfCall(user, addr(WSTETH)), U256::from(10u64) * U256::exp10(18));
+
+        let mut config = Config::default();
+        config.compound.rpc_url = chain.serve().await;
+        config.compound.comet_proxy_address = format!("{:?}", comet);
+        config.compound.rewards_address = None;
+        let client = CompoundClient::new(Arc::new(config.clone())).await.unwrap();
+        let market = client.get_markets().await.unwrap().remove(0);
+        assert_eq!(market.base_asset.price, 3_000.0);
+        assert!((market.collateral_assets[&addr(WSTETH)].price - 3_510.0).abs() < 1e-6);
+        assert!(market.data_issues.is_empty());
+
+        // 10 wstETH at 1.17 ETH, 93% of it counted at liquidation, against 10 WETH: the ETH price
+        // cancels out
+        let position = client.get_user_position(&market, user).await.unwrap();
+        assert!((position.health_factor - 1.0881).abs() < 1e-9, "{}", position.health_factor);
+        assert!((position.total_borrow_value - 30_000.0).abs() < 1e-6);
+        assert!((position.total_collateral_value - 35_100.0).abs() < 1e-6);
+
+        // Without a feed the ETH prices are not passed off as USD
+        config.compound.eth_usd_feed = None;
+        let client = CompoundClient::new(Arc::new(config)).await.unwrap();
+        let err = client.get_markets().await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Config { ref field, .. } if field == "compound.eth_usd_feed"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_more_borrowed_than_supplied_is_a_data_quality_finding() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet);
+        chain.on_call(comet, comet::TotalBorrowCall, U256::from(1_200_000_000_000_000u64));
+        let market = live_client(&chain).await.get_markets().await.unwrap().remove(0);
+
+        // Derived from the totals, not taken from Comet's getUtilization
+        assert!((market.utilization_rate - 1.2).abs() < 1e-9);
+        assert_eq!(market.utilization_rate, market.utilization());
+        assert_eq!(market.data_issues.len(), 1);
+        assert!(market.data_issues[0].message.contains("exceeds total supply"), "{}", market.data_issues[0].message);
+
+        let processor = crate::risk::RiskProcessor::new(Arc::new(Config::default()));
+        let assessment = processor.assess_market(&market).await.unwrap();
+        let finding = assessment.findings.iter().find(|f| f.category == crate::risk::RiskCategory::DataQuality).unwrap();
+        assert_eq!(finding.severity, crate::risk::RiskSeverity::Low);
+        assert_eq!(finding.metadata["block_number"], market.block_number.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_gas_quote_reads_fee_history_and_l1_fee() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet);
+        chain.on_fees(30_000_000_000, 2_000_000_000);
+        let quote = live_client(&chain).await.get_gas_quote(50.0).await.unwrap();
+        assert_eq!(quote, GasQuote { base_fee_gwei: 30.0, priority_fee_gwei: 2.0, l1_data_fee: None, source: GasSource::FeeHistory });
+
+        // On Base the L1 data fee of an absorption comes from the GasPriceOracle predeploy
+        let oracle = Address::from_str(crate::config::OP_STACK_GAS_PRICE_ORACLE).unwrap();
+        let calldata = Bytes::from(vec![0xff; ABSORPTION_CALLDATA_BYTES]);
+        chain.on_call(oracle, l1_fee_oracle::GetL1FeeCall(calldata), U256::exp10(14));
+        let mut config = Config::default();
+        config.compound.rpc_url = chain.serve().await;
+        config.compound.chain_id = 8453;
+        let client = CompoundClient::new(Arc::new(config)).await.unwrap();
+        assert_eq!(client.get_gas_quote(50.0).await.unwrap().l1_data_fee, Some(0.0001));
+
+        // Neither method served: no quote
+        let err = live_client(&fake_comet(comet)).await.get_gas_quote(50.0).await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Unavailable { what: "gas price", .. }), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_manager_permissions_follow_approvals_and_revocations() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let (owner, bulker, revoked, trusted) = (addr(0x11), addr(0xb0), addr(0xb1), addr(0xb2));
+        let approval = |spender: Address| {
+            let topic0 = H256::from(ethers::utils::keccak256(crate::scanner::APPROVAL_EVENT));
+            vec![topic0, H256::from(owner), H256::from(spender)]
+        };
+        let permissions = |chain: FakeChain| async move {
+            chain
+                .on_call(comet, comet::UserNonceCall(owner), U256::from(2))
+                .on_call(comet, comet::IsAllowedCall(owner, bulker), true)
+                .on_call(comet, comet::IsAllowedCall(owner, revoked), false)
+                .on_call(comet, comet::IsAllowedCall(owner, trusted), true);
+            let client = live_client(&chain).await;
+            let market = client.get_markets().await.unwrap().remove(0);
+            client.get_manager_permissions(&market, owner, &[trusted]).await.unwrap()
+        };
+
+        let chain = fake_comet(comet);
+        chain
+            .on_log(comet, approval(bulker), U256::MAX.encode().into())
+            .on_log(comet, approval(revoked), U256::zero().encode().into())
+            .on_log(addr(USDC), approval(addr(0xbad)), U256::MAX.encode().into());
+        let found = permissions(chain).await;
+        assert_eq!(found.managers, [bulker, trusted]);
+        assert_eq!((found.nonce, found.complete), (2, true));
+
+        // An endpoint that refuses the log query still answers for the candidates
+        let found = permissions(fake_comet(comet)).await;
+        assert_eq!(found.managers, [trusted]);
+        assert!(!found.complete);
+    }
+
+    #[tokio::test]
+    async fn test_sequencer_status_reads_uptime_feed() {
+        let client = live_client(&FakeChain::new()).await;
+        assert_eq!(client.get_sequencer_status().await.unwrap(), None);
+
+        let feed = addr(0x5e);
+        for (answer, up) in [(0i64, true), (1, false)] {
+            let chain = FakeChain::new();
+            chain.on_call(feed, aggregator_v3::LatestRoundDataCall, (7u128, I256::from(answer), U256::from(1_700_000_000u64), U256::from(1_700_000_100u64), 7u128));
+            let mut config = Config::default();
+            config.compound.rpc_url = chain.serve().await;
+            config.compound.sequencer_uptime_feed = Some(format!("{:?}", feed));
+            let client = CompoundClient::new(Arc::new(config)).await.unwrap();
+            let status = client.get_sequencer_status().await.unwrap().unwrap();
+            assert_eq!((status.up, status.since.timestamp()), (up, 1_700_000_000));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assessment_over_rpc_budget_samples_the_index() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet);
+        let mut index = crate::scanner::BorrowerIndex::new(comet);
+        for n in 0..20 {
+            add_borrower(&chain, comet, addr(0x100 + n), 1_000, 1);
+            index.borrowers.insert(addr(0x100 + n));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("borrowers.json");
+        index.save(&index_path).unwrap();
+
+        let mut config = Config::default();
+        config.compound.rpc_url = chain.serve().await;
+        config.scanner.index_path = Some(index_path);
+        let assess = |config: Config| async move {
+            let client = CompoundClient::new(Arc::new(config.clone())).await.unwrap();
+            let engine = crate::RiskEngine::with_provider(config, Arc::new(client));
+            engine.assess_risks().await.unwrap().remove(0).rpc.unwrap()
+        };
+
+        let unlimited = assess(config.clone()).await;
+        assert!(!unlimited.is_degraded());
+        // 20 accounts, each read by the absorption check and the exposure, projected as if read
+        // one at a time but packed into one `aggregate3` call per read
+        assert!(unlimited.usage.by_method["eth_call"].calls < 2 * 20 * 3, "{:?}", unlimited.usage);
+        assert_eq!(unlimited.projected_compute_units, 2 * 20 * 3 * 26);
+
+        config.rpc.budget.per_run_compute_units = Some(2_000);
+        let limited = assess(config).await;
+        let sample = limited.sampled_accounts.unwrap();
+        assert!(sample.read > 0 && sample.read < 20, "{:?}", sample);
+        assert_eq!(limited.skipped_checks, crate::budget::OPTIONAL_CHECKS);
+        assert!(limited.usage.compute_units < unlimited.usage.compute_units);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_comet_fails_loudly() {
+        let client = live_client(&FakeChain::new()).await;
+        let err = client.get_markets().await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::ContractCall { .. }), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_live_price_history_is_unavailable() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let client = live_client(&fake_comet(comet)).await;
+        let market = client.get_markets().await.unwrap().remove(0);
+        let err = client.get_price_history(&market, addr(WETH)).await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Unavailable { .. }));
     }
     
+    #[tokio::test]
+    async fn test_get_positions_batch_reports_failures_inline() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet);
+        let mut addresses: Vec<Address> = (1..=60u64).map(Address::from_low_u64_be).collect();
+        for &user in &addresses {
+            add_borrower(&chain, comet, user, 1_000, 1);
+        }
+        let client = live_client(&chain).await;
+        let market = client.get_markets().await.unwrap().remove(0);
+        addresses.push(Address::zero());
+        // Registered without its collateral read, which then reverts inside the packed call
+        let partial = Address::from_low_u64_be(99);
+        chain.on_call(comet, comet::BalanceOfCall(partial), U256::zero());
+        chain.on_call(comet, comet::BorrowBalanceOfCall(partial), U256::zero());
+        addresses.push(partial);
+        chain.take_call_blocks();
+
+        let results: Vec<PositionResult> = client
+            .get_positions_batch(&market, addresses.clone(), 2)
+            .collect()
+            .await;
+
+        // 62 accounts in three packed reads
+        assert_eq!(chain.take_call_blocks().len(), 3);
+        assert_eq!(results.len(), addresses.len());
+        let mut failures: Vec<Address> = results.iter().filter(|(_, r)| r.is_err()).map(|(a, _)| *a).collect();
+        failures.sort();
+        assert_eq!(failures, [Address::zero(), partial]);
+
+        let (_, position) = results.iter().find(|(a, _)| *a == addr(1)).unwrap();
+        let position = position.as_ref().unwrap();
+        assert_eq!(position.base_balance, -1_000.0);
+        assert!((position.health_factor - 1.79).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_get_positions_batch_reads_accounts_one_by_one_without_multicall() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet).without_multicall();
+        let users: Vec<Address> = (1..=3u64).map(Address::from_low_u64_be).collect();
+        for &user in &users {
+            add_borrower(&chain, comet, user, 1_000, 1);
+        }
+        let client = live_client(&chain).await;
+        let market = client.get_markets().await.unwrap().remove(0);
+        chain.take_call_blocks();
+
+        let results: Vec<PositionResult> = client.get_positions_batch(&market, users, 4).collect().await;
+        assert!(results.iter().all(|(_, position)| position.as_ref().unwrap().base_balance == -1_000.0));
+        // The rejected packed read, then balanceOf, borrowBalanceOf and one collateral read each
+        assert_eq!(chain.take_call_blocks().len(), 1 + 3 * 3);
+    }
+
+    #[tokio::test]
+    async fn test_reads_of_one_operation_are_pinned_to_one_block() {
+        let comet = Address::from_str(&Config::default().compound.comet_proxy_address).unwrap();
+        let chain = fake_comet(comet).advancing_blocks();
+        let users: Vec<Address> = (1..=30u64).map(Address::from_low_u64_be).collect();
+        for &user in &users {
+            add_borrower(&chain, comet, user, 1_000, 1);
+        }
+        let client = live_client(&chain).await;
+        let tag = |block: u64| serde_json::json!(format!("{:#x}", block));
+
+        // Every request mines a block, yet all the market's reads see the head it started at
+        let market = client.refresh_markets().await.unwrap().remove(0);
+        let block = market.block_number.unwrap();
+        let tags = chain.take_call_blocks();
+        assert!(tags.len() > 20, "{:?}", tags);
+        assert!(tags.iter().all(|t| *t == tag(block)), "{:?}", tags);
+
+        // A batch over several packed reads resolves the head once
+        let results: Vec<PositionResult> = client.get_positions_batch(&market, users, 4).collect().await;
+        let blocks: std::collections::BTreeSet<Option<u64>> =
+            results.iter().map(|(_, position)| position.as_ref().unwrap().block_number).collect();
+        assert_eq!(blocks.len(), 1);
+        let position_block = blocks.into_iter().next().flatten().unwrap();
+        assert!(position_block > block);
+        assert!(chain.take_call_blocks().iter().all(|t| *t == tag(position_block)));
+
+        // Snapshots are cached per block and the latest one is served
+        let refreshed = client.refresh_markets().await.unwrap().remove(0);
+        assert!(refreshed.block_number > market.block_number);
+        assert_eq!(client.get_markets().await.unwrap()[0].block_number, refreshed.block_number);
+    }
+
+    #[tokio::test]
+    async fn test_get_positions_batch_cancelled() {
+        let cancel = CancellationToken::new();
+        let config = Arc::new(Config::default());
+        let client = CompoundClient::new(config).await.unwrap().with_cancellation(cancel.clone());
+        let market = fixture_market();
+
+        cancel.cancel();
+        let addresses: Vec<Address> = (1..=30u64).map(Address::from_low_u64_be).collect();
+        let results: Vec<PositionResult> = client.get_positions_batch(&market, addresses, 4).collect().await;
+
+        assert_eq!(results.len(), 30);
+        assert!(results.iter().all(|(_, r)| r.as_ref().unwrap_err().is_cancelled()));
+    }
+
+    fn fixture_market() -> Market {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        fixture.data().markets[0].clone()
+    }
+
     #[tokio::test]
     async fn test_calculate_health_factor() {
         let config = Arc::new(Config::default());
         let client = CompoundClient::new(config).await.unwrap();
-        let market = client.create_mock_market().await.unwrap();
+        let market = fixture_market();
         
         let mut collateral_balances = HashMap::new();
         let weth_address = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap();
@@ -254,8 +1616,119 @@ mod tests {
         // 1000 USDC borrow
         let health_factor = client.calculate_health_factor(-1000.0, &collateral_balances, &market);
         
-        // 1 ETH at $2000 with 0.825 collateral factor = $1650
-        // $1650 / $1000 = 1.65
-        assert!(health_factor > 1.6 && health_factor < 1.7);
+        // 1 ETH at $2000 with a 0.91 liquidation factor = $1820, over $1000 = 1.82; the 0.825
+        // borrow collateral factor does not enter it
+        assert!((health_factor - 1.82).abs() < 1e-9, "{}", health_factor);
     }
-} 
\ No newline at end of file
+
+    /// `getConfiguration` response for cUSDCv3 with mainnet's addresses and WETH and WBTC as
+    /// collateral
+    fn captured_configuration() -> Bytes {
+        Bytes::from_str(std::fs::read_to_string(bundled_fixture("configurator-usdc.hex")).unwrap().trim()).unwrap()
+    }
+
+    #[test]
+    fn test_configurator_configuration_decodes_by_field() {
+        let address = |value: &str| Address::from_str(value).unwrap();
+        let configuration = decode_configuration(&captured_configuration()).unwrap();
+        assert_eq!(configuration.governor, address("0x6d903f6003cca6255D85CcA4D3B5E5146dC33925"));
+        assert_eq!(configuration.pause_guardian, address("0xbbf3f1421D886E9b2c5D716B5192aC998af2012c"));
+        assert_eq!(configuration.base_token, address("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"));
+        assert_eq!(configuration.base_token_price_feed, address("0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6"));
+        assert_eq!(configuration.extension_delegate, address("0x285617313887d43256F852cAE0Ee4de4b68D45B0"));
+        assert_eq!(
+            (configuration.supply_kink, configuration.supply_per_year_interest_rate_slope_low, configuration.supply_per_year_interest_rate_slope_high, configuration.supply_per_year_interest_rate_base),
+            (800_000_000_000_000_000, 32_500_000_000_000_000, 400_000_000_000_000_000, 0)
+        );
+        assert_eq!(
+            (configuration.borrow_kink, configuration.borrow_per_year_interest_rate_slope_low, configuration.borrow_per_year_interest_rate_slope_high, configuration.borrow_per_year_interest_rate_base),
+            (800_000_000_000_000_000, 35_000_000_000_000_000, 250_000_000_000_000_000, 15_000_000_000_000_000)
+        );
+        assert_eq!((configuration.store_front_price_factor, configuration.tracking_index_scale), (500_000_000_000_000_000, 1_000_000_000_000_000));
+        assert_eq!((configuration.base_tracking_supply_speed, configuration.base_tracking_borrow_speed), (11_574_074_074, 3_472_222_222));
+        assert_eq!(
+            (configuration.base_min_for_rewards, configuration.base_borrow_min, configuration.target_reserves),
+            (1_000_000_000_000, 100_000_000, 5_000_000_000_000)
+        );
+
+        let assets: Vec<Address> = configuration.asset_configs.iter().map(|c| c.asset).collect();
+        assert_eq!(assets, [address("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"), address("0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599")]);
+        let wbtc = &configuration.asset_configs[1];
+        assert_eq!(wbtc.price_feed, address("0xF4030086522a5bEEa4988F8cA5B36dbC97BeE88c"));
+        assert_eq!(
+            (wbtc.decimals, wbtc.borrow_collateral_factor, wbtc.liquidate_collateral_factor, wbtc.liquidation_factor, wbtc.supply_cap),
+            (8, 700_000_000_000_000_000, 770_000_000_000_000_000, 950_000_000_000_000_000, 12_000 * 10u128.pow(8))
+        );
+
+        let truncated = decode_configuration(&captured_configuration()[..640]).unwrap_err();
+        assert!(matches!(truncated, RiskEngineError::Parse { what: "Configurator configuration", .. }), "{}", truncated);
+    }
+
+    #[tokio::test]
+    async fn test_configuration_drift_compares_configurator_with_comet() {
+        let config = Config::default();
+        let comet = Address::from_str(&config.compound.comet_proxy_address).unwrap();
+        let configurator = Address::from_str(&config.compound.configurator_address).unwrap();
+        let configured = decode_configuration(&captured_configuration()).unwrap();
+
+        // The deployed Comet runs the captured configuration, except for a borrow kink the
+        // Configurator has since raised from 80% and a WBTC cap not yet deployed
+        let chain = FakeChain::new();
+        let per_second = |rate: u64| rate / RATE_SECONDS_PER_YEAR;
+        chain
+            .on_raw_call(configurator, comet_configurator::GetConfigurationCall(comet).encode().into(), captured_configuration())
+            .on_call(comet, comet::GovernorCall, configured.governor)
+            .on_call(comet, comet::PauseGuardianCall, configured.pause_guardian)
+            .on_call(comet, comet::BaseTokenCall, configured.base_token)
+            .on_call(comet, comet::BaseTokenPriceFeedCall, configured.base_token_price_feed)
+            .on_call(comet, comet::ExtensionDelegateCall, configured.extension_delegate)
+            .on_call(comet, comet::SupplyKinkCall, configured.supply_kink)
+            .on_call(comet, comet::SupplyPerSecondInterestRateSlopeLowCall, per_second(configured.supply_per_year_interest_rate_slope_low))
+            .on_call(comet, comet::SupplyPerSecondInterestRateSlopeHighCall, per_second(configured.supply_per_year_interest_rate_slope_high))
+            .on_call(comet, comet::SupplyPerSecondInterestRateBaseCall, 0u64)
+            .on_call(comet, comet::BorrowKinkCall, 750_000_000_000_000_000u64)
+            .on_call(comet, comet::BorrowPerSecondInterestRateSlopeLowCall, per_second(configured.borrow_per_year_interest_rate_slope_low))
+            .on_call(comet, comet::BorrowPerSecondInterestRateSlopeHighCall, per_second(configured.borrow_per_year_interest_rate_slope_high))
+            .on_call(comet, comet::BorrowPerSecondInterestRateBaseCall, per_second(configured.borrow_per_year_interest_rate_base))
+            .on_call(comet, comet::StoreFrontPriceFactorCall, configured.store_front_price_factor)
+            .on_call(comet, comet::TrackingIndexScaleCall, configured.tracking_index_scale)
+            .on_call(comet, comet::BaseTrackingSupplySpeedCall, U256::from(configured.base_tracking_supply_speed))
+            .on_call(comet, comet::BaseTrackingBorrowSpeedCall, U256::from(configured.base_tracking_borrow_speed))
+            .on_call(comet, comet::BaseMinForRewardsCall, configured.base_min_for_rewards)
+            .on_call(comet, comet::BaseBorrowMinCall, configured.base_borrow_min)
+            .on_call(comet, comet::TargetReservesCall, configured.target_reserves)
+            .on_call(comet, comet::NumAssetsCall, 2u8);
+        for (i, asset) in configured.asset_configs.iter().enumerate() {
+            let supply_cap = if asset.decimals == 8 { 10_000 * 10u128.pow(8) } else { asset.supply_cap };
+            chain.on_call(
+                comet,
+                comet::GetAssetInfoCall(i as u8),
+                (
+                    i as u8,
+                    asset.asset,
+                    asset.price_feed,
+                    10u64.pow(asset.decimals.into()),
+                    asset.borrow_collateral_factor,
+                    asset.liquidate_collateral_factor,
+                    asset.liquidation_factor,
+                    supply_cap,
+                ),
+            );
+        }
+        let client = live_client(&chain).await;
+
+        let pair = client.get_market_configurations(&fixture_market()).await.unwrap();
+        assert_eq!(pair.configured, configured);
+        let drift = pair.configured.drift(&pair.live);
+        let drift: Vec<(&str, Option<Address>, &str, &str)> =
+            drift.iter().map(|d| (d.field.as_str(), d.asset, d.configured.as_str(), d.live.as_str())).collect();
+        let wbtc = configured.asset_configs[1].asset;
+        assert_eq!(
+            drift,
+            [
+                ("borrow_kink", None, "800000000000000000", "750000000000000000"),
+                ("supply_cap", Some(wbtc), "1200000000000", "1000000000000"),
+            ]
+        );
+    }
+}
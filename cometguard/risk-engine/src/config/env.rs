@@ -0,0 +1,315 @@
+//! Environment layers of the configuration: `COMETGUARD__…` overrides and `${NAME}` references
+
+use super::{profile, Config, ConfigFormat, ConfigProblem};
+use crate::error::{Result, RiskEngineError};
+use crate::utils::expand_env;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Prefix of variables overriding a setting; `__` separates the path, e.g. `COMETGUARD__COMPOUND__RPC_URL`
+pub const ENV_PREFIX: &str = "COMETGUARD__";
+
+impl Config {
+    /// Load `path`, then apply `COMETGUARD__…` overrides from the process environment
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with_env(path, std::env::vars())
+    }
+
+    /// Load `path`, then apply the `COMETGUARD__…` overrides in `vars`
+    ///
+    /// The profile named by `COMETGUARD_PROFILE` in `vars`, if any, is selected.
+    pub fn load_with_env(path: &Path, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        Self::load_profile(path, None, vars)
+    }
+
+    /// Load the settings of `profile` from `path`, then apply the `COMETGUARD__…` overrides in `vars`
+    ///
+    /// Without `profile`, the one named by `COMETGUARD_PROFILE` in `vars` is selected, and
+    /// without either the `default` one. Unknown settings fail TOML and YAML files (see
+    /// `ConfigFormat::is_strict`).
+    pub fn load_profile(
+        path: &Path,
+        profile: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let format = ConfigFormat::from_path(path);
+        let (config, unknown) = Self::load_with_unknown(path, profile, vars)?;
+        if format.is_strict() && !unknown.is_empty() {
+            return Err(RiskEngineError::InvalidConfig { problems: unknown });
+        }
+        Ok(config)
+    }
+
+    /// Load like `load_profile`, returning the unknown settings instead of failing on them
+    ///
+    /// Settings are checked in every profile, not only the selected one.
+    pub fn load_with_unknown(
+        path: &Path,
+        profile: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(Self, Vec<ConfigProblem>)> {
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+        let profile = profile.or_else(|| {
+            vars.iter().find(|(name, _)| name == profile::PROFILE_ENV).map(|(_, value)| value.as_str())
+        });
+        let format = ConfigFormat::from_path(path);
+        let content = std::fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        let raw = format.parse(path, &content)?;
+        let mut unknown = profile::unknown_fields(&raw);
+        let has_profiles = profile::has_profiles(&raw);
+        let file = profile::flatten(raw, profile)?;
+        let config = match Self::with_env(Some(file.clone()), vars.iter().cloned()) {
+            Ok(config) => config,
+            Err(e @ RiskEngineError::Serialization { .. }) => {
+                // A misspelled key usually leaves a required one missing; name the typo instead
+                unknown.extend(Config::default().unknown_fields(&file));
+                if format.is_strict() && !unknown.is_empty() {
+                    return Err(RiskEngineError::InvalidConfig { problems: unknown });
+                }
+                // Type errors in the JSON form carry no position; the typed parse of the file has one,
+                // but it cannot see settings merged in from profiles
+                if has_profiles {
+                    return Err(e);
+                }
+                return Err(format.locate_error(path, &content).unwrap_or(e));
+            }
+            Err(e) => return Err(e),
+        };
+        unknown.extend(config.unknown_fields(&file));
+        Ok((config, unknown))
+    }
+
+    /// Defaults with `COMETGUARD__…` overrides from the process environment, when there is no file
+    pub fn from_env() -> Result<Self> {
+        Self::with_env(None, std::env::vars())
+    }
+
+    /// `file` (or the defaults) with the `COMETGUARD__…` overrides in `vars` on top
+    ///
+    /// Each remaining path segment names a key, lowercased, or an array index. A value is
+    /// taken as a string where the setting is a string, and as JSON (falling back to a
+    /// string) elsewhere, so `COMETGUARD__COMPOUND__CHAIN_ID=8453` sets a number.
+    pub fn with_env(file: Option<Value>, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        // Every setting with its default; only used to reject misspelled variables
+        let schema = serde_json::to_value(Config::default()).map_err(|e| RiskEngineError::serialization("config", e))?;
+        let mut merged = file.unwrap_or_else(|| schema.clone());
+        let mut overrides: Vec<(String, String, String)> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let path = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase().replace("__", ".");
+                Some((path, name, value))
+            })
+            .collect();
+        // Applied in path order, so a variable for a whole section comes before its fields
+        overrides.sort();
+
+        let mut sources = BTreeMap::new();
+        for (path, name, value) in overrides {
+            match set_path(&mut merged, &schema, &path, &value) {
+                Some(true) => {
+                    sources.insert(path, format!("${{{}}}", name));
+                }
+                Some(false) => {}
+                None => return Err(RiskEngineError::config(path, format!("`{}` does not name a setting", name))),
+            }
+        }
+
+        let mut config: Config = serde_json::from_value(merged)
+            .map_err(|e| RiskEngineError::serialization("config with environment overrides", e))?;
+        config.env_sources = sources;
+        config.fill_from_markets();
+        Ok(config)
+    }
+
+    /// Copy with every `${NAME}` reference in a string setting replaced by the variable's value
+    ///
+    /// Fails listing every setting that references an unset variable. The copy remembers the
+    /// references in `env_sources`, so `to_file` still writes them rather than the secrets.
+    pub fn resolve_env(&self) -> Result<Config> {
+        let mut value = serde_json::to_value(self).map_err(|e| RiskEngineError::serialization("config", e))?;
+        let mut problems = Vec::new();
+        let mut sources = self.env_sources.clone();
+        expand_strings(&mut Vec::new(), &mut value, &mut sources, &mut problems);
+        if !problems.is_empty() {
+            return Err(RiskEngineError::InvalidConfig { problems });
+        }
+        let mut resolved: Config =
+            serde_json::from_value(value).map_err(|e| RiskEngineError::serialization("resolved config", e))?;
+        resolved.env_sources = sources;
+        Ok(resolved)
+    }
+
+    /// Serialized form for `to_file`, with the `env_sources` text in place of those settings
+    pub(super) fn to_file_value(&self) -> Result<Value> {
+        let mut value = serde_json::to_value(self).map_err(|e| RiskEngineError::serialization("config", e))?;
+        for (path, source) in &self.env_sources {
+            if let Some(setting @ Value::String(_)) = lookup_mut(&mut value, path) {
+                *setting = Value::String(source.clone());
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Set the setting at dotted `path`, creating objects along the way
+///
+/// Returns whether the setting was set as a string, or `None` if `path` is not a setting:
+/// a key missing from a section of `schema`, or a step into something other than an
+/// object or array. Keys of unset sections and of maps (empty in `schema`) are not checked.
+fn set_path(root: &mut Value, schema: &Value, path: &str, raw: &str) -> Option<bool> {
+    let mut target = root;
+    let mut schema = Some(schema);
+    for segment in path.split('.') {
+        if let Some(Value::Object(section)) = schema {
+            if !section.is_empty() && !section.contains_key(segment) {
+                return None;
+            }
+        }
+        schema = schema.and_then(|s| s.get(segment));
+        if target.is_null() {
+            *target = Value::Object(Default::default());
+        }
+        target = match target {
+            Value::Object(map) => map.entry(segment).or_insert(Value::Null),
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    *target = match target {
+        Value::String(_) => Value::String(raw.to_string()),
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    };
+    Some(target.is_string())
+}
+
+fn lookup_mut<'a>(root: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(root, |value, segment| match value {
+        Value::Object(map) => map.get_mut(segment),
+        Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Expand references below `path`, recording the original text of every expanded setting
+fn expand_strings(
+    path: &mut Vec<String>,
+    value: &mut Value,
+    sources: &mut BTreeMap<String, String>,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    match value {
+        Value::String(text) if text.contains("${") => {
+            // Shown like the fields `Config::validate` reports, e.g. `alerts.webhooks[0].url`
+            let field = path.iter().fold(String::new(), |field, segment| match segment.parse::<usize>() {
+                Ok(_) => format!("{}[{}]", field, segment),
+                Err(_) if field.is_empty() => segment.clone(),
+                Err(_) => format!("{}.{}", field, segment),
+            });
+            match expand_env(&field, text) {
+                Ok(expanded) => {
+                    sources.entry(path.join(".")).or_insert_with(|| text.clone());
+                    *text = expanded;
+                }
+                Err(RiskEngineError::Config { field, message }) => problems.push(ConfigProblem { field, message }),
+                Err(e) => problems.push(ConfigProblem { field, message: e.to_string() }),
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                path.push(key.clone());
+                expand_strings(path, value, sources, problems);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push(i.to_string());
+                expand_strings(path, item, sources, problems);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RpcMode;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_take_precedence_over_the_file() {
+        let mut file = serde_json::to_value(Config::default()).unwrap();
+        file["compound"]["chain_id"] = 10.into();
+        file["rpc"]["mode"] = "record".into();
+
+        let env = vars(&[
+            ("COMETGUARD__COMPOUND__CHAIN_ID", "8453"),
+            ("COMETGUARD__COMPOUND__RPC_URL", "https://base.example/v2/KEY"),
+            ("COMETGUARD__STORAGE__POSTGRES__URL", "postgres://db/risk"),
+            ("COMETGUARD__COMPOUND__RPC_ULR", "https://typo.example"),
+            ("PATH", "/usr/bin"),
+        ]);
+        let err = Config::with_env(Some(file.clone()), env.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration `compound.rpc_ulr`: `COMETGUARD__COMPOUND__RPC_ULR` does not name a setting"
+        );
+
+        let config = Config::with_env(Some(file.clone()), env.into_iter().filter(|(k, _)| !k.ends_with("ULR"))).unwrap();
+        assert_eq!(config.compound.chain_id, 8453);
+        assert_eq!(config.compound.rpc_url, "https://base.example/v2/KEY");
+        assert_eq!(config.storage.postgres.as_ref().unwrap().url, "postgres://db/risk");
+        // Untouched settings come from the file, then the defaults
+        assert_eq!(config.rpc.mode, RpcMode::Record);
+        assert_eq!(config.performance.max_concurrent_assessments, 4);
+
+        let defaults = Config::with_env(None, vars(&[("COMETGUARD__RISK__FAIL_ON", "High")])).unwrap();
+        assert_eq!(defaults.risk.fail_on, Some(crate::risk::RiskSeverity::High));
+        assert_eq!(defaults.compound.chain_id, 1);
+    }
+
+    #[test]
+    fn test_references_resolve_and_name_unset_variables() {
+        let mut config = Config::default();
+        config.compound.rpc_url = "https://eth-mainnet.g.alchemy.com/v2/${COMETGUARD_TEST_ALCHEMY_KEY}".to_string();
+        config.server.auth_token = Some("${COMETGUARD_TEST_UNSET_TOKEN}".to_string());
+
+        std::env::set_var("COMETGUARD_TEST_ALCHEMY_KEY", "abc123");
+        let err = config.resolve_env().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "invalid configuration:\n  1. `server.auth_token`: environment variable `COMETGUARD_TEST_UNSET_TOKEN` is not set"
+        );
+
+        config.server.auth_token = None;
+        let resolved = config.resolve_env().unwrap();
+        assert_eq!(resolved.compound.rpc_url, "https://eth-mainnet.g.alchemy.com/v2/abc123");
+        assert!(config.compound.rpc_url.contains("${COMETGUARD_TEST_ALCHEMY_KEY}"));
+    }
+
+    #[test]
+    fn test_to_file_writes_references_instead_of_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::env::set_var("COMETGUARD_TEST_FILE_KEY", "s3cret");
+        let mut config = Config::with_env(
+            None,
+            vars(&[("COMETGUARD__COMPOUND__RPC_URL", "https://rpc.example/s3cret"), ("COMETGUARD__SCANNER__CHUNK_SIZE", "500")]),
+        )
+        .unwrap();
+        config.server.auth_token = Some("${COMETGUARD_TEST_FILE_KEY}".to_string());
+
+        config.resolve_env().unwrap().to_file(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("s3cret"), "{}", written);
+        assert!(written.contains("${COMETGUARD__COMPOUND__RPC_URL}"));
+        assert!(written.contains("${COMETGUARD_TEST_FILE_KEY}"));
+        assert_eq!(Config::from_file(&path).unwrap().scanner.chunk_size, 500);
+    }
+}
@@ -0,0 +1,227 @@
+//! Config file formats, chosen by file extension
+
+use super::Config;
+use crate::error::{Result, RiskEngineError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// Format of a config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Format for `path` by extension (`.toml`, `.yaml` or `.yml`); JSON otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    /// Extension `config init` gives files of this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        }
+    }
+
+    /// Whether settings no field reads are rejected
+    ///
+    /// JSON files are lenient, as they carry `"//"` comment keys; TOML and YAML have real
+    /// comments, so an unknown key there is a typo.
+    pub fn is_strict(self) -> bool {
+        self != Self::Json
+    }
+
+    /// Parse `content` (read from `path`) into its JSON form, with the parser's line and column on error
+    pub fn parse(self, path: &Path, content: &str) -> Result<Value> {
+        let parse_error = |what: &'static str, message: String| RiskEngineError::Parse {
+            what,
+            input: path.display().to_string(),
+            message,
+        };
+        match self {
+            Self::Json => serde_json::from_str(content)
+                .map_err(|e| RiskEngineError::serialization(format!("config file {}", path.display()), e)),
+            Self::Toml => {
+                let value: toml::Value = toml::from_str(content).map_err(|e| parse_error("TOML config", e.to_string()))?;
+                serde_json::to_value(value).map_err(|e| RiskEngineError::serialization("TOML config", e))
+            }
+            Self::Yaml => serde_yaml::from_str(content).map_err(|e| parse_error("YAML config", e.to_string())),
+        }
+    }
+
+    /// Typed parse of `content`, only used to locate an error the JSON form has no position for
+    pub(super) fn locate_error(self, path: &Path, content: &str) -> Option<RiskEngineError> {
+        let located = |what: &'static str, message: String| RiskEngineError::Parse {
+            what,
+            input: path.display().to_string(),
+            message,
+        };
+        match self {
+            Self::Json => serde_json::from_str::<Config>(content)
+                .err()
+                .map(|e| RiskEngineError::serialization(format!("config file {}", path.display()), e)),
+            Self::Toml => toml::from_str::<Config>(content).err().map(|e| located("TOML config", e.to_string())),
+            Self::Yaml => serde_yaml::from_str::<Config>(content).err().map(|e| located("YAML config", e.to_string())),
+        }
+    }
+
+    /// Render `value`, the JSON form of a config, in this format
+    pub fn render(self, value: &Value) -> Result<String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(value).map_err(|e| RiskEngineError::serialization("config", e)),
+            // TOML has no null; unset settings are left out, as serde omits `None` fields
+            Self::Toml => toml::to_string_pretty(&without_nulls(value))
+                .map_err(|e| RiskEngineError::config("config", format!("cannot be written as TOML: {}", e))),
+            Self::Yaml => serde_yaml::to_string(value)
+                .map_err(|e| RiskEngineError::config("config", format!("cannot be written as YAML: {}", e))),
+        }
+    }
+
+    /// Put `comment` above the line starting `section` (top of the file for `""`); a dotted
+    /// `section` such as `risk.gas` names a table within another
+    pub(super) fn comment(self, text: &mut String, section: &str, comment: &str) {
+        let at_line_start = |i: usize| i == 0 || text.as_bytes()[i - 1] == b'\n';
+        let at = match self {
+            Self::Json => return,
+            _ if section.is_empty() => {
+                text.insert_str(0, &format!("# {}\n\n", comment));
+                return;
+            }
+            Self::Toml => {
+                let header = format!("[{}]\n", section);
+                text.match_indices(&header).map(|(i, _)| i).find(|i| at_line_start(*i))
+            }
+            Self::Yaml => yaml_key_line(text, section),
+        };
+        if let Some(at) = at {
+            let indent = " ".repeat(text[at..].len() - text[at..].trim_start_matches(' ').len());
+            text.insert_str(at, &format!("{}# {}\n", indent, comment));
+        }
+    }
+}
+
+/// Start of the line of the YAML mapping key `path`, each dotted part indented two spaces
+/// below the one before and looked for only within the block of the part before
+fn yaml_key_line(text: &str, path: &str) -> Option<usize> {
+    // Lines of `text[from..to]` with their offsets
+    let lines = |from: usize, to: usize| {
+        text[from..to].split_inclusive('\n').scan(from, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+    };
+    let (mut at, mut end) = (0, text.len());
+    for (depth, key) in path.split('.').enumerate() {
+        let header = format!("{}{}:", " ".repeat(depth * 2), key);
+        at = lines(at, end).find_map(|(start, line)| {
+            line.strip_prefix(header.as_str()).filter(|rest| rest.starts_with(['\n', ' '])).map(|_| start)
+        })?;
+        // The block of the key ends at the next key indented no deeper than it
+        let body = text[at..end].find('\n').map_or(end, |i| at + i + 1);
+        end = lines(body, end)
+            .find(|(_, line)| {
+                let indent = line.len() - line.trim_start_matches(' ').len();
+                !line.trim().is_empty() && indent <= depth * 2 && !line.trim_start().starts_with('-')
+            })
+            .map_or(end, |(start, _)| start);
+    }
+    Some(at)
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k.clone(), without_nulls(v))).collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().filter(|v| !v.is_null()).map(without_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Network, SourceKind};
+
+    fn sample() -> Config {
+        let mut config = Config {
+            compound: Network::Arbitrum.compound_config("https://arb.example/v2/${ARB_KEY}".to_string()),
+            watchlist: vec!["0x2222222222222222222222222222222222222222".to_string()],
+            ..Config::default()
+        };
+        config.risk.fail_on = Some(crate::risk::RiskSeverity::High);
+        config.alerts.webhooks.push(serde_json::from_str(r#"{ "url": "https://hooks.example", "format": "slack" }"#).unwrap());
+        config.alerts.pagerduty = Some(serde_json::from_str(r#"{ "routing_key": "${PD_KEY}" }"#).unwrap());
+        config.subgraph.endpoints.insert(42161, "https://graph.example/arb".to_string());
+        config.subgraph.markets = SourceKind::Subgraph;
+        config
+    }
+
+    #[test]
+    fn test_every_format_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = sample();
+        let expected = serde_json::to_value(&config).unwrap();
+        for name in ["config.json", "config.toml", "config.yaml", "config.yml"] {
+            let path = dir.path().join(name);
+            config.to_file(&path).unwrap();
+            let loaded = Config::from_file(&path).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), expected, "{}", name);
+        }
+        assert!(std::fs::read_to_string(dir.path().join("config.toml")).unwrap().contains("[compound]"));
+        assert_eq!(ConfigFormat::from_path(Path::new("thresholds.conf")), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn test_commented_templates_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let text = Config::default().to_commented(format).unwrap();
+            if format != ConfigFormat::Json {
+                assert!(text.starts_with("# CometGuard risk engine configuration"), "{}", text);
+                assert!(text.contains("# Thresholds are fractions in [0, 1]"), "{}", text);
+                // Subsections get their own comment, indented with them in YAML
+                let gas = match format {
+                    ConfigFormat::Toml => "# Liquidation gas at the chain's base fee",
+                    _ => "\n  # Liquidation gas at the chain's base fee",
+                };
+                assert!(text.contains(gas), "{}", text);
+            }
+            let path = dir.path().join(format!("config.{}", format.extension()));
+            std::fs::write(&path, text).unwrap();
+            assert_eq!(Config::from_file(&path).unwrap().compound.chain_id, 1, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_toml_typos_and_type_errors_are_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let template = Config::default().to_commented(ConfigFormat::Toml).unwrap();
+
+        std::fs::write(&path, template.replace("max_utilization_threshold", "max_utilisation_threshold")).unwrap();
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("`risk.max_utilisation_threshold`: unknown setting; did you mean `max_utilization_threshold`?"), "{}", err);
+
+        std::fs::write(&path, template.replace("chain_id = 1", "chain_id = \"one\"")).unwrap();
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("line") && err.contains("chain_id"), "{}", err);
+
+        std::fs::write(&path, "[compound\nrpc_url = 1").unwrap();
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.starts_with("failed to parse TOML config") && err.contains("line 1"), "{}", err);
+    }
+}
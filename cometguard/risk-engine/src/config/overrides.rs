@@ -0,0 +1,172 @@
+//! Single-setting overrides given on the command line as `--set key=value`
+
+use super::Config;
+use crate::error::{Result, RiskEngineError};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// One `key=value` override of the setting at a dotted path, e.g. `risk.max_utilization_threshold=0.8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingOverride {
+    /// Dotted path of the setting; array items by index (`watchlist.0`)
+    pub path: String,
+    /// Value as typed; parsed according to the type of the setting
+    pub value: String,
+}
+
+impl FromStr for SettingOverride {
+    type Err = RiskEngineError;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.split_once('=') {
+            Some((path, value)) if !path.trim().is_empty() => Ok(Self {
+                path: path.trim().to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(RiskEngineError::Parse {
+                what: "setting override",
+                input: input.to_string(),
+                message: "expected KEY=VALUE, e.g. risk.max_utilization_threshold=0.8".to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SettingOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.path, self.value)
+    }
+}
+
+impl Config {
+    /// Copy with each override applied in order
+    ///
+    /// Works on the serialized form, so every setting can be overridden without naming it
+    /// here. A value must fit the type of the setting it replaces: a number, `true` or `false`,
+    /// any text for strings, and JSON for lists and unset optional settings. Fails naming the
+    /// settings of the section if a path does not name one.
+    pub fn with_overrides(&self, overrides: &[SettingOverride]) -> Result<Config> {
+        let mut value = serde_json::to_value(self).map_err(|e| RiskEngineError::serialization("config", e))?;
+        for setting in overrides {
+            let target = setting_mut(&mut value, &setting.path)?;
+            *target = typed_value(&setting.path, target, &setting.value)?;
+        }
+
+        let mut config: Config = serde_json::from_value(value).map_err(|e| {
+            let paths: Vec<&str> = overrides.iter().map(|o| o.path.as_str()).collect();
+            RiskEngineError::config(paths.join(", "), format!("override does not fit the setting: {}", e))
+        })?;
+        config.env_sources = self.env_sources.clone();
+        // The command line, not the environment, now supplies these
+        for setting in overrides {
+            config.env_sources.remove(&setting.path);
+        }
+        Ok(config)
+    }
+
+    /// Current value of the setting at dotted `path`, as JSON
+    pub fn setting(&self, path: &str) -> Option<Value> {
+        let mut value = serde_json::to_value(self).ok()?;
+        setting_mut(&mut value, path).ok().map(|v| v.take())
+    }
+}
+
+/// The setting at dotted `path` below `root`; sections cannot be replaced as a whole
+fn setting_mut<'a>(root: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    let mut target = root;
+    let mut walked = String::new();
+    for segment in path.split('.') {
+        let at = if walked.is_empty() { "the top level".to_string() } else { format!("`{}`", walked) };
+        target = match target {
+            Value::Object(section) => {
+                if !section.contains_key(segment) {
+                    let known: Vec<&str> = section.keys().map(String::as_str).collect();
+                    return Err(RiskEngineError::config(
+                        path,
+                        format!("no setting `{}` in {}; valid keys: {}", segment, at, known.join(", ")),
+                    ));
+                }
+                section.get_mut(segment).expect("checked above")
+            }
+            Value::Array(items) => {
+                let len = items.len();
+                segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)).ok_or_else(|| {
+                    RiskEngineError::config(path, format!("{} has {} item(s); `{}` is not an index", at, len, segment))
+                })?
+            }
+            _ => return Err(RiskEngineError::config(path, format!("{} is a single setting, not a section", at))),
+        };
+        walked = if walked.is_empty() { segment.to_string() } else { format!("{}.{}", walked, segment) };
+    }
+    if let Value::Object(section) = target {
+        let known: Vec<&str> = section.keys().map(String::as_str).collect();
+        return Err(RiskEngineError::config(path, format!("is a section; set one of: {}", known.join(", "))));
+    }
+    Ok(target)
+}
+
+/// `raw` parsed as the type of `current`
+fn typed_value(path: &str, current: &Value, raw: &str) -> Result<Value> {
+    let mismatch = |expected: &str| RiskEngineError::config(path, format!("expects {}, got `{}`", expected, raw));
+    match current {
+        Value::String(_) => Ok(Value::String(raw.to_string())),
+        Value::Bool(_) => raw.trim().parse::<bool>().map(Value::Bool).map_err(|_| mismatch("true or false")),
+        Value::Number(_) => match serde_json::from_str(raw.trim()) {
+            Ok(number @ Value::Number(_)) => Ok(number),
+            _ => Err(mismatch("a number")),
+        },
+        Value::Array(_) => match serde_json::from_str(raw) {
+            Ok(list @ Value::Array(_)) => Ok(list),
+            _ => Err(mismatch("a JSON list")),
+        },
+        // Unset optional settings have no type to go by; the typed parse of the result checks them
+        _ => Ok(serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RpcMode;
+    use crate::risk::RiskSeverity;
+
+    fn set(pairs: &[&str]) -> Vec<SettingOverride> {
+        pairs.iter().map(|pair| pair.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_overrides_are_typed_by_the_setting() {
+        let config = Config::default()
+            .with_overrides(&set(&[
+                "risk.max_utilization_threshold=0.8",
+                "performance.allow_parallel_requests=false",
+                "rpc.max_retries=5",
+                "rpc.mode=record",
+                "rpc.session_path=session.jsonl",
+                "risk.fail_on=High",
+            ]))
+            .unwrap();
+        assert_eq!(config.risk.max_utilization_threshold, 0.8);
+        assert!(!config.performance.allow_parallel_requests);
+        assert_eq!((config.rpc.max_retries, config.rpc.mode), (5, RpcMode::Record));
+        assert_eq!(config.rpc.session_path.as_deref(), Some(std::path::Path::new("session.jsonl")));
+        assert_eq!(config.risk.fail_on, Some(RiskSeverity::High));
+        assert_eq!(config.setting("rpc.max_retries"), Some(serde_json::json!(5)));
+    }
+
+    #[test]
+    fn test_bad_overrides_name_the_problem() {
+        let err = |pair: &str| Config::default().with_overrides(&set(&[pair])).unwrap_err().to_string();
+
+        let unknown = err("risk.max_utilisation_threshold=0.8");
+        assert!(unknown.contains("no setting `max_utilisation_threshold` in `risk`"), "{}", unknown);
+        assert!(unknown.contains("valid keys: baseline, fail_on, gas, liquidation_threshold_buffer"), "{}", unknown);
+        assert!(err("cache.ttl_seconds=60").contains("no setting `cache` in the top level; valid keys: address_book, alerts, assets, audit"));
+        assert!(err("risk.max_utilization_threshold=high").ends_with("expects a number, got `high`"));
+        assert!(err("performance.allow_parallel_requests=1").ends_with("expects true or false, got `1`"));
+        assert!(err("risk=0.8").contains("is a section; set one of"));
+        assert!(err("rpc.max_retries=-1").contains("override does not fit the setting"));
+        assert!("risk.max_utilization_threshold".parse::<SettingOverride>().is_err());
+    }
+}
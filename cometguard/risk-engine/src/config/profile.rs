@@ -0,0 +1,156 @@
+//! Named profiles: `profiles.<name>` sections layered over the rest of the config file
+
+use super::{Config, ConfigProblem};
+use crate::error::{Result, RiskEngineError};
+use serde_json::Value;
+
+/// Variable selecting the profile when `--profile` is not given
+pub const PROFILE_ENV: &str = "COMETGUARD_PROFILE";
+
+/// Profile every other profile inherits from; also selected when none is named
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Key of the profiles section in the config file
+const PROFILES_KEY: &str = "profiles";
+
+/// `file` without its `profiles` section, with `profiles.default` and then `profiles.<profile>`
+/// merged over the remaining settings
+///
+/// Sections merge key by key; any other value, including an array, replaces the one below it.
+/// Fails listing the available profiles if `profile` is not one of them.
+pub(super) fn flatten(mut file: Value, profile: Option<&str>) -> Result<Value> {
+    let profiles = match file.as_object_mut().and_then(|map| map.remove(PROFILES_KEY)) {
+        None | Some(Value::Null) => serde_json::Map::new(),
+        Some(Value::Object(profiles)) => profiles,
+        Some(_) => return Err(RiskEngineError::config(PROFILES_KEY, "must map profile names to settings")),
+    };
+    let profile = profile.unwrap_or(DEFAULT_PROFILE);
+    if profile != DEFAULT_PROFILE && !profiles.contains_key(profile) {
+        let mut available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+        if !available.contains(&DEFAULT_PROFILE) {
+            available.insert(0, DEFAULT_PROFILE);
+        }
+        return Err(RiskEngineError::config(
+            "profile",
+            format!("no profile `{}`; available: {}", profile, available.join(", ")),
+        ));
+    }
+
+    for name in [DEFAULT_PROFILE, profile] {
+        match profiles.get(name) {
+            Some(overlay @ (Value::Object(_) | Value::Null)) => merge(&mut file, overlay.clone()),
+            Some(_) => {
+                return Err(RiskEngineError::config(format!("{}.{}", PROFILES_KEY, name), "must be a section of settings"));
+            }
+            None => {}
+        }
+        if name == profile {
+            break;
+        }
+    }
+    Ok(file)
+}
+
+/// Misspelled settings in every profile of `file`, whether selected or not
+pub(super) fn unknown_fields(file: &Value) -> Vec<ConfigProblem> {
+    let Some(Value::Object(profiles)) = file.get(PROFILES_KEY) else {
+        return Vec::new();
+    };
+    let schema = Config::default();
+    profiles
+        .iter()
+        .flat_map(|(name, settings)| {
+            schema.unknown_fields(settings).into_iter().map(move |problem| ConfigProblem {
+                field: format!("{}.{}.{}", PROFILES_KEY, name, problem.field),
+                message: problem.message,
+            })
+        })
+        .collect()
+}
+
+/// Whether `file` has a profiles section
+pub(super) fn has_profiles(file: &Value) -> bool {
+    file.get(PROFILES_KEY).is_some()
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        // A `null` profile has no settings of its own
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn file() -> Value {
+        json!({
+            "compound": { "rpc_url": "https://mainnet.example", "chain_id": 1 },
+            "watchlist": ["0x01", "0x02"],
+            "profiles": {
+                "default": { "risk": { "max_utilization_threshold": 0.9 }, "watchlist": ["0x03"] },
+                "sepolia": {
+                    "compound": { "chain_id": 11155111 },
+                    "risk": { "max_price_volatility": 0.5 }
+                },
+                "staging": { "risk": { "max_utilization_threshold": 0.7 }, "watchlist": [] }
+            }
+        })
+    }
+
+    #[test]
+    fn test_named_profile_inherits_from_default() {
+        let sepolia = flatten(file(), Some("sepolia")).unwrap();
+        assert!(sepolia.get("profiles").is_none());
+        // Sections merge key by key: the named profile only replaces what it sets
+        assert_eq!(sepolia["compound"], json!({ "rpc_url": "https://mainnet.example", "chain_id": 11155111 }));
+        assert_eq!(sepolia["risk"], json!({ "max_utilization_threshold": 0.9, "max_price_volatility": 0.5 }));
+        // Arrays are replaced, not appended to
+        assert_eq!(sepolia["watchlist"], json!(["0x03"]));
+    }
+
+    #[test]
+    fn test_named_profile_overrides_default() {
+        let staging = flatten(file(), Some("staging")).unwrap();
+        assert_eq!(staging["risk"]["max_utilization_threshold"], json!(0.7));
+        assert_eq!(staging["watchlist"], json!([]));
+        assert_eq!(staging["compound"]["chain_id"], json!(1));
+
+        let default = flatten(file(), None).unwrap();
+        assert_eq!(default, flatten(file(), Some("default")).unwrap());
+        assert_eq!(default["risk"], json!({ "max_utilization_threshold": 0.9 }));
+    }
+
+    #[test]
+    fn test_unknown_profile_lists_available_ones() {
+        let err = flatten(file(), Some("mainnet")).unwrap_err().to_string();
+        assert_eq!(err, "invalid configuration `profile`: no profile `mainnet`; available: default, sepolia, staging");
+
+        let plain = json!({ "compound": { "chain_id": 1 } });
+        assert_eq!(flatten(plain.clone(), None).unwrap(), plain);
+        let err = flatten(plain, Some("sepolia")).unwrap_err().to_string();
+        assert!(err.ends_with("available: default"), "{}", err);
+    }
+
+    #[test]
+    fn test_typos_are_reported_in_every_profile() {
+        let mut file = file();
+        file["profiles"]["staging"]["risk"]["max_utilisation_threshold"] = json!(0.7);
+        let problems = unknown_fields(&file);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].field, "profiles.staging.risk.max_utilisation_threshold");
+    }
+}
@@ -0,0 +1,728 @@
+//! Whole-config validation for `Config::validate` and `risk-engine-cli config validate`
+
+use super::{market_reference, AuditRotation, Config, EmailMode, RpcMode, COMMENT_KEY};
+use crate::alerts;
+use crate::ens::is_ens_name;
+use crate::registry;
+use crate::telemetry;
+use crate::utils;
+use crate::error::{Result, RiskEngineError};
+use ethers::types::Address;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// One problem with a configuration setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigProblem {
+    /// Dotted path of the setting, e.g. `risk.max_utilization_threshold`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.message)
+    }
+}
+
+impl Config {
+    /// Check every setting, failing with all problems found at once
+    ///
+    /// `RiskEngine::new` runs this before connecting to anything.
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RiskEngineError::InvalidConfig { problems })
+        }
+    }
+
+    /// Every problem `validate` fails with, in file order
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut check = Problems { lenient_addresses: self.lenient_addresses, ..Problems::default() };
+
+        let compound = &self.compound;
+        check.url("compound.rpc_url", &compound.rpc_url, &["http", "https", "ws", "wss"]);
+        let contracts = [
+            ("compound.comet_proxy_address", &compound.comet_proxy_address),
+            ("compound.configurator_address", &compound.configurator_address),
+        ];
+        for (field, value) in contracts {
+            if value.is_empty() {
+                check.push(field, "is not set; set it or name a market in `markets`, e.g. \"mainnet:USDC\"");
+            } else {
+                check.contract(field, value);
+            }
+        }
+        if compound.chain_id == 0 {
+            check.push("compound.chain_id", "must be at least 1");
+        }
+        if let Some(rewards) = &compound.rewards_address {
+            check.contract("compound.rewards_address", rewards);
+        }
+        if let Some(feed) = &compound.comp_price_feed {
+            check.contract("compound.comp_price_feed", feed);
+        }
+        if let Some(feed) = &compound.eth_usd_feed {
+            check.contract("compound.eth_usd_feed", feed);
+        }
+        if let Some(feed) = &compound.sequencer_uptime_feed {
+            check.contract("compound.sequencer_uptime_feed", feed);
+        }
+        for (i, entry) in self.markets.iter().enumerate() {
+            let field = format!("markets[{}]", i);
+            if entry.starts_with("0x") {
+                check.contract(&field, entry);
+                continue;
+            }
+            match registry::resolve(entry) {
+                Ok(known) if known.network.chain_id() != compound.chain_id => check.push(
+                    &field,
+                    format!(
+                        "`{}` is on chain {}, not compound.chain_id {}; assess each chain from its own profile",
+                        entry,
+                        known.network.chain_id(),
+                        compound.chain_id
+                    ),
+                ),
+                Ok(_) => {}
+                Err(e) => check.push(&field, e.to_string()),
+            }
+        }
+
+        let risk = &self.risk;
+        check.fraction("risk.max_utilization_threshold", risk.max_utilization_threshold);
+        check.fraction("risk.liquidation_threshold_buffer", risk.liquidation_threshold_buffer);
+        check.fraction("risk.max_price_volatility", risk.max_price_volatility);
+        check.fraction("risk.max_bridged_collateral_share", risk.max_bridged_collateral_share);
+        check.fraction("risk.max_borrower_share", risk.max_borrower_share);
+        if risk.max_utilization_threshold == 0.0 {
+            check.push("risk.max_utilization_threshold", "0 would flag every market; use a fraction above 0");
+        }
+        if risk.liquidation_threshold_buffer == 1.0 {
+            check.push("risk.liquidation_threshold_buffer", "must be below 1");
+        }
+        if let Some(hf) = risk.watchlist_alert_health_factor {
+            // At or below 1 the position can already be liquidated
+            if !(hf.is_finite() && hf > 1.0) {
+                check.push("risk.watchlist_alert_health_factor", format!("{} is not a health factor above 1", hf));
+            }
+        }
+        if let Some(min) = risk.min_position_usd {
+            if !(min.is_finite() && min >= 0.0) {
+                check.push("risk.min_position_usd", format!("{} is not a USD amount of at least 0", min));
+            }
+        }
+        let projection = &risk.projection;
+        // A rate cut of 100 points or more, or prices losing everything within a year, is no scenario
+        if !(projection.borrow_rate_shock.is_finite() && projection.borrow_rate_shock > -1.0) {
+            check.push("risk.projection.borrow_rate_shock", format!("{} is not an APR change above -1", projection.borrow_rate_shock));
+        }
+        if !(projection.collateral_price_drift.is_finite() && projection.collateral_price_drift > -1.0) {
+            check.push("risk.projection.collateral_price_drift", format!("{} is not a yearly price change above -1", projection.collateral_price_drift));
+        }
+        if !(risk.min_rewards_runway_days.is_finite() && risk.min_rewards_runway_days >= 0.0) {
+            check.push("risk.min_rewards_runway_days", format!("{} is not a number of days of at least 0", risk.min_rewards_runway_days));
+        }
+        let small_positions = &risk.small_positions;
+        if !(small_positions.gas_cost_usd.is_finite() && small_positions.gas_cost_usd >= 0.0) {
+            check.push("risk.small_positions.gas_cost_usd", format!("{} is not a USD amount of at least 0", small_positions.gas_cost_usd));
+        }
+        if !(small_positions.max_reserve_fraction.is_finite() && small_positions.max_reserve_fraction >= 0.0) {
+            check.push("risk.small_positions.max_reserve_fraction", format!("{} is not a fraction of at least 0", small_positions.max_reserve_fraction));
+        }
+        let gas = &risk.gas;
+        if let Some(gwei) = gas.pinned_gwei.filter(|gwei| !(gwei.is_finite() && *gwei >= 0.0)) {
+            check.push("risk.gas.pinned_gwei", format!("{} is not a gas price in gwei of at least 0", gwei));
+        }
+        if !(0.0..=100.0).contains(&gas.fee_percentile) {
+            check.push("risk.gas.fee_percentile", format!("{} is not a percentile in [0, 100]", gas.fee_percentile));
+        }
+        for (i, withdrawal) in risk.planned_withdrawals.iter().enumerate() {
+            let field = |name: &str| format!("risk.planned_withdrawals[{}].{}", i, name);
+            check.address(&field("account"), &withdrawal.account);
+            if let Some(market) = &withdrawal.market {
+                check.contract(&field("market"), market);
+            }
+            if !(withdrawal.amount.is_finite() && withdrawal.amount > 0.0) {
+                check.push(&field("amount"), format!("{} is not an amount above 0", withdrawal.amount));
+            }
+        }
+        let baseline = &risk.baseline;
+        if baseline.windows_days.is_empty() || baseline.windows_days.contains(&0) {
+            check.push("risk.baseline.windows_days", "needs at least one window of 1 day or more");
+        }
+        if !(baseline.percentile > 0.5 && baseline.percentile < 1.0) {
+            check.push("risk.baseline.percentile", format!("{} is not a percentile in (0.5, 1)", baseline.percentile));
+        }
+        check.at_least_one("risk.baseline.min_samples", baseline.min_samples as u64);
+        let changes = &risk.position_changes;
+        if let Some(usd) = changes.min_change_usd.filter(|usd| !(usd.is_finite() && *usd >= 0.0)) {
+            check.push("risk.position_changes.min_change_usd", format!("{} is not a USD amount of at least 0", usd));
+        }
+        if let Some(share) = changes.min_change_share.filter(|share| !(share.is_finite() && *share > 0.0)) {
+            check.push("risk.position_changes.min_change_share", format!("{} is not a share above 0", share));
+        }
+        let outflows = &risk.outflows;
+        if let Some(usd) = outflows.min_outflow_usd.filter(|usd| !(usd.is_finite() && *usd > 0.0)) {
+            check.push("risk.outflows.min_outflow_usd", format!("{} is not a USD amount above 0", usd));
+        }
+        if let Some(share) = outflows.min_outflow_share.filter(|share| !(*share > 0.0 && *share <= 1.0)) {
+            check.push("risk.outflows.min_outflow_share", format!("{} is not a share in (0, 1]", share));
+        }
+        check.at_least_one("risk.outflows.window_seconds", outflows.window_seconds);
+        let percentile = risk.storefront_margin.daily_move_percentile;
+        if !(percentile > 0.0 && percentile <= 1.0) {
+            check.push("risk.storefront_margin.daily_move_percentile", format!("{} is not a percentile in (0, 1]", percentile));
+        }
+        let held = &risk.protocol_collateral;
+        if !(held.max_reserve_fraction.is_finite() && held.max_reserve_fraction > 0.0) {
+            check.push("risk.protocol_collateral.max_reserve_fraction", format!("{} is not a fraction above 0", held.max_reserve_fraction));
+        }
+        if !(held.elevated_usd.is_finite() && held.elevated_usd >= 0.0) {
+            check.push("risk.protocol_collateral.elevated_usd", format!("{} is not a USD amount of at least 0", held.elevated_usd));
+        }
+        check.at_least_one("risk.protocol_collateral.max_elevated_snapshots", held.max_elevated_snapshots as u64);
+        let summary = &risk.summary;
+        let edges = &summary.health_factor_buckets;
+        if let Some(edge) = edges.iter().find(|edge| !(edge.is_finite() && **edge > 1.0)) {
+            check.push("risk.summary.health_factor_buckets", format!("{} is not a health factor above 1", edge));
+        } else if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            check.push("risk.summary.health_factor_buckets", "edges must be in increasing order");
+        } else if summary.near_liquidation_margin > 0.0
+            && !edges.iter().any(|edge| (edge - (1.0 + summary.near_liquidation_margin)).abs() < 1e-9)
+        {
+            check.push(
+                "risk.summary.health_factor_buckets",
+                format!("has no edge at {}, 1 plus risk.summary.near_liquidation_margin", 1.0 + summary.near_liquidation_margin),
+            );
+        }
+        let sampling = &risk.sampling;
+        check.at_least_one("risk.sampling.strata", sampling.strata as u64);
+        if sampling.top_borrowers >= sampling.sample_size {
+            check.push(
+                "risk.sampling.top_borrowers",
+                format!("{} leaves none of the {} sampled positions to the other borrowers", sampling.top_borrowers, sampling.sample_size),
+            );
+        }
+        if let Err(RiskEngineError::Parse { message, .. }) = telemetry::parse_filter(&self.log_level) {
+            check.push("log_level", message);
+        }
+        if self.logging.directory.is_some() {
+            check.at_least_one("logging.max_files", self.logging.max_files as u64);
+            let prefix = &self.logging.file_prefix;
+            if prefix.is_empty() || prefix.contains(['/', '\\']) {
+                check.push("logging.file_prefix", format!("`{}` is not a file name", prefix));
+            }
+        }
+        if let Some(timezone) = &self.display.timezone {
+            if let Err(RiskEngineError::Parse { message, .. }) = utils::parse_timezone(timezone) {
+                check.push("display.timezone", format!("`{}` is not a timezone: {}", timezone, message));
+            }
+        }
+
+        check.at_least_one("performance.timeout_seconds", self.performance.timeout_seconds);
+        check.at_least_one("performance.max_concurrent_assessments", self.performance.max_concurrent_assessments as u64);
+        check.at_least_one("performance.check_budget_ms", self.performance.check_budget_ms);
+        check.at_least_one("timeouts.rpc_call", self.timeouts.rpc_call);
+        check.at_least_one("timeouts.per_market", self.timeouts.per_market);
+        check.at_least_one("timeouts.total", self.timeouts.total);
+        check.at_least_one("scanner.chunk_size", self.scanner.chunk_size);
+        check.at_least_one("scanner.max_concurrent_chunks", self.scanner.max_concurrent_chunks as u64);
+        if self.rpc.mode != RpcMode::Live && self.rpc.session_path.is_none() {
+            check.push("rpc.session_path", "required when rpc.mode is record or replay");
+        }
+        if let Some(units) = self.rpc.budget.per_run_compute_units {
+            check.at_least_one("rpc.budget.per_run_compute_units", units);
+        }
+        if let Some(units) = self.rpc.budget.per_minute_compute_units {
+            check.at_least_one("rpc.budget.per_minute_compute_units", units);
+        }
+        check.socket_addr("metrics.bind_address", self.metrics.bind_address.as_deref());
+        for (i, account) in self.watchlist.iter().enumerate() {
+            // Names are resolved when the engine starts
+            if !is_ens_name(account) {
+                check.address(&format!("watchlist[{}]", i), account);
+            }
+        }
+        for (i, manager) in self.trusted_managers.iter().enumerate() {
+            check.address(&format!("trusted_managers[{}]", i), manager);
+        }
+        for (key, asset) in &self.assets {
+            let field = |name: &str| format!("assets.{}.{}", key, name);
+            if key.starts_with("0x") {
+                check.address(&format!("assets.{}", key), key);
+            }
+            if let Some(threshold) = asset.max_price_volatility {
+                check.fraction(&field("max_price_volatility"), threshold);
+            }
+            if let Some(heartbeat) = asset.oracle_heartbeat_seconds {
+                check.at_least_one(&field("oracle_heartbeat_seconds"), heartbeat);
+            }
+            if let Some(slippage) = asset.dex_slippage {
+                check.fraction(&field("dex_slippage"), slippage);
+            }
+            if let Some(depth) = asset.dex_depth_usd {
+                if !(depth.is_finite() && depth > 0.0) {
+                    check.push(&field("dex_depth_usd"), format!("{} is not an amount above 0", depth));
+                }
+            }
+        }
+
+        let alerts = &self.alerts;
+        for (i, webhook) in alerts.webhooks.iter().enumerate() {
+            check.url(&format!("alerts.webhooks[{}].url", i), &webhook.url, &["http", "https"]);
+        }
+        if let Some(telegram) = &alerts.telegram {
+            if telegram.bot_token.is_empty() {
+                check.push("alerts.telegram.bot_token", "the bot token is empty");
+            }
+            if telegram.chat_id.is_empty() {
+                check.push("alerts.telegram.chat_id", "the chat id is empty");
+            }
+            check.url("alerts.telegram.api_url", &telegram.api_url, &["http", "https"]);
+        }
+        if let Some(email) = &alerts.email {
+            if email.to.is_empty() {
+                check.push("alerts.email.to", "at least one recipient is required");
+            }
+            if email.mode == EmailMode::Digest {
+                check.at_least_one("alerts.email.digest_interval_seconds", email.digest_interval_seconds);
+            }
+        }
+        if let Some(pagerduty) = &alerts.pagerduty {
+            if pagerduty.routing_key.is_empty() {
+                check.push("alerts.pagerduty.routing_key", "the routing key is empty");
+            }
+            check.url("alerts.pagerduty.events_url", &pagerduty.events_url, &["http", "https"]);
+        }
+        let sinks = alerts.sink_names();
+        for (i, webhook) in alerts.webhooks.iter().enumerate() {
+            let name = webhook.sink_name(i);
+            if sinks.iter().filter(|sink| **sink == name).count() > 1 {
+                check.push(&format!("alerts.webhooks[{}].name", i), format!("sink name `{}` is used by another sink", name));
+            }
+        }
+        for (i, rule) in alerts.rules.iter().enumerate() {
+            let field = |key: &str| format!("alerts.rules[{}].{}", i, key);
+            if !sinks.contains(&rule.sink) {
+                let defined = if sinks.is_empty() { "none".to_string() } else { sinks.join(", ") };
+                check.push(&field("sink"), format!("no sink named `{}` (defined: {})", rule.sink, defined));
+            }
+            let criteria = &rule.criteria;
+            if let (Some(min), Some(max)) = (criteria.min_severity, criteria.max_severity) {
+                if min > max {
+                    check.push(&field("match"), format!("min_severity {:?} is above max_severity {:?}", min, max));
+                }
+            }
+            for (j, market) in criteria.markets.iter().enumerate() {
+                check.address(&field(&format!("match.markets[{}]", j)), market);
+            }
+            let unknown = rule.template.as_deref().map(alerts::unknown_placeholders).unwrap_or_default();
+            if !unknown.is_empty() {
+                let unknown: Vec<String> = unknown.iter().map(|name| format!("{{{}}}", name)).collect();
+                check.push(&field("template"), format!("unknown placeholder(s) {}", unknown.join(", ")));
+            }
+        }
+
+        let storage = &self.storage;
+        if storage.database_path.is_some() && storage.postgres.is_some() {
+            check.push("storage", "set either `database_path` or `postgres`, not both");
+        }
+        if let Some(postgres) = &storage.postgres {
+            check.url("storage.postgres.url", &postgres.url, &["postgres", "postgresql"]);
+            check.at_least_one("storage.postgres.write_queue_capacity", postgres.write_queue_capacity as u64);
+            let election = &postgres.leader_election;
+            if election.enabled {
+                check.at_least_one("storage.postgres.leader_election.heartbeat_secs", election.heartbeat_secs);
+                if election.lease_secs < 2 * election.heartbeat_secs {
+                    check.push(
+                        "storage.postgres.leader_election.lease_secs",
+                        format!("must be at least twice heartbeat_secs ({}), so one late renewal does not hand the lease over", election.heartbeat_secs),
+                    );
+                }
+                if election.name.trim().is_empty() {
+                    check.push("storage.postgres.leader_election.name", "the lease name is empty");
+                }
+            }
+        }
+
+        if self.reconcile.enabled {
+            check.at_least_one("reconcile.max_data_age_secs", self.reconcile.max_data_age_secs);
+        }
+        let assessed = self.market_addresses().ok();
+        for (key, secs) in &self.schedule.intervals {
+            let field = format!("schedule.intervals.{}", key);
+            check.at_least_one(&field, *secs);
+            match market_reference(key) {
+                Ok(address) if assessed.as_ref().is_some_and(|assessed| !assessed.contains(&address)) => {
+                    check.push(&field, "names a market that is not assessed; add it to `markets`")
+                }
+                Ok(_) => {}
+                Err(e) => check.push(&field, e.to_string()),
+            }
+        }
+
+        check.socket_addr("server.bind_address", self.server.bind_address.as_deref());
+        if self.server.auth_token.as_deref() == Some("") {
+            check.push("server.auth_token", "the token is empty");
+        }
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            check.url("telemetry.otlp_endpoint", endpoint, &["http", "https"]);
+        }
+        check.fraction("telemetry.sample_ratio", self.telemetry.sample_ratio);
+
+        if self.audit.rotation == AuditRotation::Size {
+            check.at_least_one("audit.max_file_bytes", self.audit.max_file_bytes);
+        }
+        check.at_least_one("audit.queue_capacity", self.audit.queue_capacity as u64);
+
+        let subgraph = &self.subgraph;
+        for (chain_id, endpoint) in &subgraph.endpoints {
+            check.url(&format!("subgraph.endpoints.{}", chain_id), endpoint, &["http", "https"]);
+        }
+        if subgraph.in_use() && !subgraph.endpoints.contains_key(&compound.chain_id) {
+            check.push("subgraph.endpoints", format!("no subgraph endpoint for chain {}", compound.chain_id));
+        }
+        check.fraction("subgraph.cross_check_tolerance", subgraph.cross_check_tolerance);
+        check.at_least_one("subgraph.page_size", subgraph.page_size as u64);
+        check.at_least_one("ens.cache_ttl_seconds", self.ens.cache_ttl_seconds);
+
+        check.found
+    }
+
+    /// Keys in the raw config file that no setting reads, such as misspelled names
+    ///
+    /// `self` must have been loaded from `raw`; comment keys (`"//"`) are skipped.
+    pub fn unknown_fields(&self, raw: &Value) -> Vec<ConfigProblem> {
+        let known = serde_json::to_value(self).unwrap_or(Value::Null);
+        let mut problems = Vec::new();
+        collect_unknown("", raw, &known, &mut problems);
+        problems
+    }
+}
+
+fn collect_unknown(path: &str, raw: &Value, known: &Value, problems: &mut Vec<ConfigProblem>) {
+    match (raw, known) {
+        (Value::Object(raw), Value::Object(known)) => {
+            for (key, value) in raw {
+                let field = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known.get(key) {
+                    Some(value_known) => collect_unknown(&field, value, value_known, problems),
+                    None if key != COMMENT_KEY => {
+                        let message = match closest(key, known.keys()) {
+                            Some(similar) => format!("unknown setting; did you mean `{}`?", similar),
+                            None => "unknown setting".to_string(),
+                        };
+                        problems.push(ConfigProblem { field, message });
+                    }
+                    None => {}
+                }
+            }
+        }
+        (Value::Array(raw), Value::Array(known)) => {
+            for (i, (raw, known)) in raw.iter().zip(known).enumerate() {
+                collect_unknown(&format!("{}[{}]", path, i), raw, known, problems);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The candidate at most two edits away from `key`, if any
+fn closest<'a>(key: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[derive(Default)]
+struct Problems {
+    found: Vec<ConfigProblem>,
+    /// Accept addresses failing their checksum, as `lenient_addresses` asks
+    lenient_addresses: bool,
+}
+
+impl Problems {
+    fn push(&mut self, field: &str, message: impl Into<String>) {
+        self.found.push(ConfigProblem {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn address(&mut self, field: &str, value: &str) {
+        self.parse_address(field, value);
+    }
+
+    /// A valid address that can hold a contract, i.e. not the zero address
+    fn contract(&mut self, field: &str, value: &str) {
+        if self.parse_address(field, value).is_some_and(|address| address.is_zero()) {
+            self.push(field, "the zero address is not a contract");
+        }
+    }
+
+    /// `value` as an address, checksum checked unless `lenient_addresses` is set
+    fn parse_address(&mut self, field: &str, value: &str) -> Option<Address> {
+        let parsed = if self.lenient_addresses { utils::parse_address(value) } else { utils::parse_address_strict(value) };
+        match parsed {
+            Ok(address) => Some(address),
+            Err(RiskEngineError::Parse { message, .. }) => {
+                self.push(field, format!("`{}` is not a valid address: {}", value, message));
+                None
+            }
+            Err(e) => {
+                self.push(field, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn fraction(&mut self, field: &str, value: f64) {
+        if !(0.0..=1.0).contains(&value) {
+            self.push(field, format!("{} is outside [0, 1]", value));
+        }
+    }
+
+    fn at_least_one(&mut self, field: &str, value: u64) {
+        if value == 0 {
+            self.push(field, "must be at least 1");
+        }
+    }
+
+    /// URLs with `${NAME}` references are only checked once expanded, by the component using them
+    fn url(&mut self, field: &str, value: &str, schemes: &[&str]) {
+        if value.contains("${") {
+            return;
+        }
+        match reqwest::Url::parse(value) {
+            Ok(url) if schemes.contains(&url.scheme()) => {}
+            Ok(url) => self.push(field, format!("unsupported URL scheme `{}` (expected {})", url.scheme(), schemes.join(", "))),
+            Err(e) => self.push(field, format!("invalid URL: {}", e)),
+        }
+    }
+
+    fn socket_addr(&mut self, field: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            if let Err(e) = SocketAddr::from_str(value) {
+                self.push(field, format!("`{}` is not a socket address: {}", value, e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PlannedWithdrawal, PostgresConfig, SourceKind, WebhookConfig};
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert_eq!(Config::default().problems(), []);
+        assert!(Config::default().validate().is_ok());
+
+        // A bad checksum passes only when asked to
+        let mut config = Config::default();
+        config.compound.comet_proxy_address = "0xC3d688B66703497DAA19211EEdff47f25384cdc3".into();
+        assert_eq!(config.problems().len(), 1);
+        config.lenient_addresses = true;
+        assert_eq!(config.problems(), []);
+    }
+
+    #[test]
+    fn test_every_problem_is_reported_at_once() {
+        let mut config = Config::default();
+        config.compound.comet_proxy_address = "0x123".to_string();
+        config.risk.max_utilization_threshold = 1.5;
+        config.watchlist = vec!["0x2222222222222222222222222222222222222222".to_string(), "alice".to_string()];
+        config.rpc.mode = RpcMode::Replay;
+        config.storage.database_path = Some("history.db".into());
+        config.storage.postgres = Some(serde_json::from_str::<PostgresConfig>(r#"{ "url": "${DATABASE_URL}" }"#).unwrap());
+        config.alerts.webhooks.push(serde_json::from_str::<WebhookConfig>(r#"{ "url": "ftp://hooks.example" }"#).unwrap());
+        config.subgraph.markets = SourceKind::Subgraph;
+
+        let fields: Vec<String> = config.problems().into_iter().map(|p| p.field).collect();
+        assert_eq!(
+            fields,
+            [
+                "compound.comet_proxy_address",
+                "risk.max_utilization_threshold",
+                "rpc.session_path",
+                "watchlist[1]",
+                "alerts.webhooks[0].url",
+                "storage",
+                "subgraph.endpoints",
+            ]
+        );
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.starts_with("invalid configuration:\n  1. `compound.comet_proxy_address`: `0x123` is not a valid address"), "{}", message);
+        assert!(message.contains("\n  2. `risk.max_utilization_threshold`: 1.5 is outside [0, 1]\n  3. "));
+    }
+
+    #[test]
+    fn test_each_rule_reports_its_setting() {
+        type Case = (&'static str, &'static str, fn(&mut Config));
+        let cases: &[Case] = &[
+            ("compound.rpc_url", "unsupported URL scheme `ftp`", |c| c.compound.rpc_url = "ftp://rpc.example".into()),
+            ("compound.rpc_url", "invalid URL", |c| c.compound.rpc_url = "rpc.example/v2".into()),
+            ("compound.comet_proxy_address", "is not a valid address", |c| {
+                c.compound.comet_proxy_address = "0xc3d688B66703497DAA19211EEdff47f25384cd".into()
+            }),
+            ("compound.configurator_address", "zero address", |c| {
+                c.compound.configurator_address = format!("{:?}", Address::zero())
+            }),
+            ("compound.chain_id", "at least 1", |c| c.compound.chain_id = 0),
+            ("trusted_managers[0]", "did you mean 0xc3d688B66703497DAA19211EEdff47f25384cdc3?", |c| {
+                c.trusted_managers = vec!["0xC3d688B66703497DAA19211EEdff47f25384cdc3".into()]
+            }),
+            ("compound.rewards_address", "is not a valid address", |c| c.compound.rewards_address = Some("0x1B0e".into())),
+            ("compound.comet_proxy_address", "name a market in `markets`", |c| c.compound.comet_proxy_address.clear()),
+            ("markets[0]", "unknown network `goerli`", |c| c.markets = vec!["goerli:USDC".into()]),
+            ("markets[1]", "on chain 8453, not compound.chain_id 1", |c| c.markets = vec!["mainnet:WETH".into(), "base:USDbC".into()]),
+            ("markets[0]", "zero address", |c| c.markets = vec![format!("{:?}", Address::zero())]),
+            ("risk.max_utilization_threshold", "outside [0, 1]", |c| c.risk.max_utilization_threshold = 1.01),
+            ("risk.max_utilization_threshold", "flag every market", |c| c.risk.max_utilization_threshold = 0.0),
+            ("risk.liquidation_threshold_buffer", "outside [0, 1]", |c| c.risk.liquidation_threshold_buffer = -0.1),
+            ("risk.liquidation_threshold_buffer", "below 1", |c| c.risk.liquidation_threshold_buffer = 1.0),
+            ("risk.max_price_volatility", "outside [0, 1]", |c| c.risk.max_price_volatility = f64::NAN),
+            ("risk.max_borrower_share", "outside [0, 1]", |c| c.risk.max_borrower_share = 1.5),
+            ("risk.watchlist_alert_health_factor", "above 1", |c| c.risk.watchlist_alert_health_factor = Some(0.9)),
+            ("risk.min_position_usd", "at least 0", |c| c.risk.min_position_usd = Some(-5.0)),
+            ("risk.projection.collateral_price_drift", "above -1", |c| c.risk.projection.collateral_price_drift = -1.5),
+            ("risk.min_rewards_runway_days", "at least 0", |c| c.risk.min_rewards_runway_days = -1.0),
+            ("risk.small_positions.gas_cost_usd", "at least 0", |c| c.risk.small_positions.gas_cost_usd = f64::INFINITY),
+            ("risk.gas.pinned_gwei", "at least 0", |c| c.risk.gas.pinned_gwei = Some(-1.0)),
+            ("risk.gas.fee_percentile", "in [0, 100]", |c| c.risk.gas.fee_percentile = 150.0),
+            ("risk.planned_withdrawals[0].amount", "not an amount above 0", |c| {
+                c.risk.planned_withdrawals = vec![PlannedWithdrawal {
+                    account: "0x1111111111111111111111111111111111111111".into(),
+                    market: None,
+                    amount: -5.0,
+                }];
+            }),
+            ("risk.baseline.windows_days", "at least one window", |c| c.risk.baseline.windows_days = vec![30, 0]),
+            ("risk.baseline.percentile", "in (0.5, 1)", |c| c.risk.baseline.percentile = 95.0),
+            ("risk.position_changes.min_change_usd", "at least 0", |c| c.risk.position_changes.min_change_usd = Some(-1.0)),
+            ("risk.position_changes.min_change_share", "above 0", |c| c.risk.position_changes.min_change_share = Some(0.0)),
+            ("risk.outflows.min_outflow_usd", "above 0", |c| c.risk.outflows.min_outflow_usd = Some(0.0)),
+            ("risk.outflows.min_outflow_share", "in (0, 1]", |c| c.risk.outflows.min_outflow_share = Some(1.5)),
+            ("risk.outflows.window_seconds", "at least 1", |c| c.risk.outflows.window_seconds = 0),
+            ("risk.storefront_margin.daily_move_percentile", "in (0, 1]", |c| c.risk.storefront_margin.daily_move_percentile = 95.0),
+            ("risk.protocol_collateral.max_reserve_fraction", "above 0", |c| c.risk.protocol_collateral.max_reserve_fraction = 0.0),
+            ("risk.protocol_collateral.max_elevated_snapshots", "at least 1", |c| c.risk.protocol_collateral.max_elevated_snapshots = 0),
+            ("risk.summary.health_factor_buckets", "above 1", |c| c.risk.summary.health_factor_buckets = vec![0.9, 1.1]),
+            ("risk.summary.health_factor_buckets", "increasing order", |c| c.risk.summary.health_factor_buckets = vec![1.5, 1.1]),
+            ("risk.summary.health_factor_buckets", "no edge at 1.2", |c| c.risk.summary.near_liquidation_margin = 0.2),
+            ("risk.sampling.strata", "at least 1", |c| c.risk.sampling.strata = 0),
+            ("risk.sampling.top_borrowers", "none of the 2000", |c| c.risk.sampling.top_borrowers = 2_000),
+            ("log_level", "`verbose` is neither a level", |c| c.log_level = "verbose".into()),
+            ("log_level", "neither a level", |c| c.log_level = "risk_engine::compound=debug,inf".into()),
+            ("logging.max_files", "at least 1", |c| {
+                c.logging.directory = Some("logs".into());
+                c.logging.max_files = 0;
+            }),
+            ("logging.file_prefix", "not a file name", |c| {
+                c.logging.directory = Some("logs".into());
+                c.logging.file_prefix = "logs/cometguard".into();
+            }),
+            ("display.timezone", "`CEST` is not a timezone", |c| c.display.timezone = Some("CEST".into())),
+            ("performance.timeout_seconds", "at least 1", |c| c.performance.timeout_seconds = 0),
+            ("performance.check_budget_ms", "at least 1", |c| c.performance.check_budget_ms = 0),
+            ("timeouts.per_market", "at least 1", |c| c.timeouts.per_market = 0),
+            ("scanner.chunk_size", "at least 1", |c| c.scanner.chunk_size = 0),
+            ("scanner.max_concurrent_chunks", "at least 1", |c| c.scanner.max_concurrent_chunks = 0),
+            ("rpc.session_path", "required", |c| c.rpc.mode = RpcMode::Record),
+            ("rpc.budget.per_minute_compute_units", "at least 1", |c| c.rpc.budget.per_minute_compute_units = Some(0)),
+            ("metrics.bind_address", "not a socket address", |c| c.metrics.bind_address = Some("localhost".into())),
+            ("watchlist[0]", "not a valid address", |c| c.watchlist = vec!["bob".into()]),
+            ("trusted_managers[0]", "not a valid address", |c| c.trusted_managers = vec!["bulker.eth".into()]),
+            ("assets.0x12", "not a valid address", |c| {
+                c.assets.insert("0x12".into(), Default::default());
+            }),
+            ("assets.WETH.max_price_volatility", "outside [0, 1]", |c| {
+                c.assets.entry("WETH".into()).or_default().max_price_volatility = Some(1.5);
+            }),
+            ("assets.WETH.dex_depth_usd", "not an amount above 0", |c| {
+                c.assets.entry("WETH".into()).or_default().dex_depth_usd = Some(0.0);
+            }),
+            ("alerts.rules[0].sink", "no sink named `pager` (defined: none)", |c| {
+                c.alerts.rules = serde_json::from_str(r#"[{ "sink": "pager" }]"#).unwrap()
+            }),
+            ("alerts.rules[0].template", "unknown placeholder(s) {sevrity}", |c| {
+                c.alerts.webhooks = serde_json::from_str(r#"[{ "name": "archive", "url": "https://hooks.example" }]"#).unwrap();
+                c.alerts.rules = serde_json::from_str(r#"[{ "sink": "archive", "template": "{sevrity} in {market}" }]"#).unwrap();
+            }),
+            ("alerts.rules[0].match", "above max_severity", |c| {
+                c.alerts.webhooks = serde_json::from_str(r#"[{ "url": "https://hooks.example" }]"#).unwrap();
+                c.alerts.rules = serde_json::from_str(
+                    r#"[{ "sink": "webhooks[0]", "match": { "min_severity": "High", "max_severity": "Low" } }]"#,
+                )
+                .unwrap();
+            }),
+            ("storage.postgres.leader_election.lease_secs", "at least twice heartbeat_secs (10)", |c| {
+                let mut postgres = serde_json::from_str::<PostgresConfig>(r#"{ "url": "postgres://db/history" }"#).unwrap();
+                postgres.leader_election.enabled = true;
+                postgres.leader_election.lease_secs = 15;
+                c.storage.postgres = Some(postgres);
+            }),
+            ("reconcile.max_data_age_secs", "at least 1", |c| c.reconcile.max_data_age_secs = 0),
+            ("schedule.intervals.mainnet:WETH", "not assessed; add it to `markets`", |c| c.schedule.intervals = [("mainnet:WETH".into(), 3600)].into()),
+            ("schedule.intervals.mainnet:DAI", "no known market on mainnet", |c| c.schedule.intervals = [("mainnet:DAI".into(), 60)].into()),
+            ("server.auth_token", "empty", |c| c.server.auth_token = Some(String::new())),
+            ("telemetry.sample_ratio", "outside [0, 1]", |c| c.telemetry.sample_ratio = 2.0),
+            ("subgraph.page_size", "at least 1", |c| c.subgraph.page_size = 0),
+            ("ens.cache_ttl_seconds", "at least 1", |c| c.ens.cache_ttl_seconds = 0),
+        ];
+
+        for (field, message, mutate) in cases {
+            let mut config = Config::default();
+            mutate(&mut config);
+            let problems = config.problems();
+            assert_eq!(problems.len(), 1, "{}: {:?}", field, problems);
+            assert_eq!(problems[0].field, *field);
+            assert!(problems[0].message.contains(message), "{}: {}", field, problems[0].message);
+        }
+    }
+
+    #[test]
+    fn test_unknown_fields_are_listed_with_their_path() {
+        let raw: Value = serde_json::json!({
+            "//": "comment",
+            "compound": {
+                "rpc_url": "http://localhost:8545",
+                "comet_proxy_address": "0xc3d688B66703497DAA19211EEdff47f25384cdc3",
+                "configurator_address": "0x316f9708bB98af7dA9c68C1C3b5e79039cD336E3",
+                "chain_id": 1
+            },
+            "risk": { "max_utilization_threshold": 0.85, "liquidation_threshold_buffer": 0.05, "max_price_volatility": 0.1, "fail_onn": "High" },
+            "log_level": "info",
+            "alerts": { "webhooks": [{ "url": "https://hooks.example", "min_severity": "High", "fromat": "slack" }] },
+            "checks": ["utilization"]
+        });
+        let config: Config = serde_json::from_value(raw.clone()).unwrap();
+        let fields: Vec<String> = config.unknown_fields(&raw).into_iter().map(|p| p.field).collect();
+        assert_eq!(fields, ["alerts.webhooks[0].fromat", "checks", "risk.fail_onn"]);
+    }
+}
@@ -0,0 +1,713 @@
+//! Interactive terminal dashboard (`risk-engine-cli dashboard`)
+//!
+//! The dashboard runs the scheduler and redraws whenever a market's assessment completes.
+//! It reads everything through the engine (`latest_assessment`, the provider's cached
+//! markets, `tracked_positions`, `health`, `last_run`) rather than fetching on its own, so
+//! what it shows matches the API server and the alerts.
+//!
+//! Layout adapts to the terminal: below `WIDE_LAYOUT_WIDTH` columns the watchlist panel and
+//! trend columns are dropped, and below `MIN_WIDTH` x `MIN_HEIGHT` only a notice is shown.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::events::RiskEvent;
+use crate::leader::Role;
+use crate::models::{Market, UserPosition};
+use crate::risk::{RiskAssessment, RiskFinding, RiskSeverity};
+use crate::utils::{format_address, format_relative, sanitize_inline, to_checksum_address};
+use crate::{Health, HealthStatus, RiskEngine, RunSummary};
+use ethers::types::Address;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, List, ListItem, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Smallest terminal the dashboard draws panels in
+pub const MIN_WIDTH: u16 = 40;
+pub const MIN_HEIGHT: u16 = 10;
+
+/// Terminals at least this wide get the watchlist panel and trend columns
+pub const WIDE_LAYOUT_WIDTH: u16 = 100;
+
+/// Assessments kept per market for the trend sparklines
+const HISTORY_LEN: usize = 24;
+
+/// At-risk positions listed in the market view
+const TOP_POSITIONS: usize = 10;
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// What the dashboard shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    /// Every market, findings across markets, the watchlist and the status footer
+    Overview,
+    /// Findings and top at-risk positions of the selected market
+    Market,
+}
+
+/// What the caller should do after a key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+/// Latest state of one market plus its recent history
+#[derive(Debug, Clone)]
+struct MarketPanel {
+    assessment: RiskAssessment,
+    utilization: Option<f64>,
+    scores: VecDeque<u64>,
+    /// Utilization in whole percent
+    utilizations: VecDeque<u64>,
+    /// Borrowing positions, riskiest first
+    positions: Vec<UserPosition>,
+}
+
+/// State and rendering of the dashboard, independent of the terminal it is drawn on
+#[derive(Debug)]
+pub struct Dashboard {
+    watchlist: Vec<Address>,
+    /// Health factor below which a position is highlighted (1 + `risk.liquidation_threshold_buffer`)
+    alert_health_factor: f64,
+    markets: Vec<MarketPanel>,
+    health: Option<Health>,
+    last_run: Option<RunSummary>,
+    selected: usize,
+    view: View,
+}
+
+impl Dashboard {
+    /// Empty dashboard for the watchlist and thresholds in `config`
+    pub fn new(config: &Config) -> Self {
+        Self {
+            watchlist: config.watchlist.iter().filter_map(|a| Address::from_str(a).ok()).collect(),
+            alert_health_factor: 1.0 + config.risk.liquidation_threshold_buffer,
+            markets: Vec::new(),
+            health: None,
+            last_run: None,
+            selected: 0,
+            view: View::Overview,
+        }
+    }
+
+    /// Current view
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    /// Comet proxy of the selected market, if any market has been assessed
+    pub fn selected_market(&self) -> Option<Address> {
+        self.markets.get(self.selected).map(|panel| panel.assessment.market_address)
+    }
+
+    /// Reload every market the engine has assessed, plus the status footer
+    pub async fn refresh(&mut self, engine: &RiskEngine) {
+        for assessment in engine.latest_assessments() {
+            self.refresh_market(engine, assessment.market_address).await;
+        }
+        self.set_status(engine.health().await, engine.last_run());
+    }
+
+    /// Reload one market after its assessment completed, plus the status footer
+    pub async fn refresh_market(&mut self, engine: &RiskEngine, market: Address) {
+        let Some(assessment) = engine.latest_assessment(market) else { return };
+        // Served from the provider's cache
+        let markets = engine.provider().get_markets().await.unwrap_or_default();
+        let market = markets.into_iter().find(|m| m.comet_address == market);
+        let positions = match &market {
+            // Failures are logged by the engine; the previous positions stay on screen
+            Some(market) => engine.tracked_positions(market).await.ok(),
+            None => None,
+        };
+        self.update_market(assessment, market.as_ref(), positions);
+        self.set_status(engine.health().await, engine.last_run());
+    }
+
+    /// Record a market's newest assessment, its market data and (if read) its tracked positions
+    pub fn update_market(&mut self, assessment: RiskAssessment, market: Option<&Market>, positions: Option<Vec<UserPosition>>) {
+        let address = assessment.market_address;
+        let index = match self.markets.iter().position(|p| p.assessment.market_address == address) {
+            Some(index) => index,
+            None => {
+                let index = self
+                    .markets
+                    .partition_point(|p| p.assessment.market_name <= assessment.market_name);
+                self.markets.insert(
+                    index,
+                    MarketPanel {
+                        assessment: assessment.clone(),
+                        utilization: None,
+                        scores: VecDeque::new(),
+                        utilizations: VecDeque::new(),
+                        positions: Vec::new(),
+                    },
+                );
+                if index <= self.selected && self.markets.len() > 1 {
+                    self.selected += 1;
+                }
+                index
+            }
+        };
+
+        let panel = &mut self.markets[index];
+        let is_new_run = panel.scores.is_empty() || panel.assessment.timestamp != assessment.timestamp;
+        if let Some(market) = market {
+            panel.utilization = Some(market.utilization());
+        }
+        if is_new_run {
+            push_bounded(&mut panel.scores, u64::from(assessment.risk_score));
+            if let Some(utilization) = panel.utilization {
+                push_bounded(&mut panel.utilizations, (utilization * 100.0).round() as u64);
+            }
+        }
+        if let Some(mut positions) = positions {
+            positions.retain(|p| p.total_borrow_value > 0.0);
+            positions.sort_by(|a, b| a.health_factor.total_cmp(&b.health_factor));
+            panel.positions = positions;
+        }
+        panel.assessment = assessment;
+    }
+
+    /// Update the footer's data freshness and RPC status
+    pub fn set_status(&mut self, health: Health, last_run: Option<RunSummary>) {
+        self.health = Some(health);
+        self.last_run = last_run;
+    }
+
+    /// Apply a key press: ↑/↓ (or k/j) select a market, Enter opens it, Esc goes back, q quits
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.code == KeyCode::Char('q') || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+            return Action::Quit;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.markets.len().saturating_sub(1));
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') if !self.markets.is_empty() => self.view = View::Market,
+            KeyCode::Esc | KeyCode::Left | KeyCode::Backspace | KeyCode::Char('h') => self.view = View::Overview,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Draw the current view into `frame`
+    pub fn render(&self, frame: &mut Frame) {
+        let area = frame.area();
+        if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+            let notice = format!("Terminal too small: need at least {}x{}", MIN_WIDTH, MIN_HEIGHT);
+            frame.render_widget(Paragraph::new(notice).wrap(Wrap { trim: true }), area);
+            return;
+        }
+        let wide = area.width >= WIDE_LAYOUT_WIDTH;
+        let [body, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        match (self.view, self.markets.get(self.selected)) {
+            (View::Market, Some(panel)) => self.render_market(frame, body, panel, wide),
+            _ => self.render_overview(frame, body, wide),
+        }
+        frame.render_widget(Paragraph::new(self.footer(wide)), footer);
+    }
+
+    fn render_overview(&self, frame: &mut Frame, area: Rect, wide: bool) {
+        let findings: Vec<(&str, &RiskFinding)> = self
+            .markets
+            .iter()
+            .flat_map(|p| p.assessment.findings.iter().map(move |f| (p.assessment.market_name.as_str(), f)))
+            .collect();
+
+        if wide {
+            let [top, watchlist] = Layout::vertical([Constraint::Percentage(60), Constraint::Min(4)]).areas(area);
+            let [markets, findings_area] =
+                Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(top);
+            self.render_markets(frame, markets, true);
+            render_findings(frame, findings_area, "Findings", findings);
+            self.render_watchlist(frame, watchlist);
+        } else {
+            let [markets, findings_area] =
+                Layout::vertical([Constraint::Percentage(50), Constraint::Min(3)]).areas(area);
+            self.render_markets(frame, markets, false);
+            render_findings(frame, findings_area, "Findings", findings);
+        }
+    }
+
+    fn render_markets(&self, frame: &mut Frame, area: Rect, wide: bool) {
+        let block = Block::bordered().title(" Markets ");
+        if self.markets.is_empty() {
+            frame.render_widget(Paragraph::new("Waiting for the first assessment...").block(block), area);
+            return;
+        }
+
+        let rows = self.markets.iter().map(|panel| {
+            let utilization = panel.utilization.map(|u| format!("{:.1}%", u * 100.0)).unwrap_or_else(|| "-".to_string());
+            let mut cells = vec![
+                Cell::from(sanitize_inline(&panel.assessment.market_name)),
+                Cell::from(panel.assessment.risk_score.to_string()).style(score_style(panel.assessment.risk_score)),
+            ];
+            if wide {
+                cells.push(Cell::from(sparkline(&panel.scores, 100)));
+            }
+            cells.push(Cell::from(utilization));
+            if wide {
+                cells.push(Cell::from(sparkline(&panel.utilizations, 100)));
+            }
+            Row::new(cells)
+        });
+        let (header, widths) = if wide {
+            (
+                Row::new(["Market", "Score", "Trend", "Util", "Trend"]),
+                vec![Constraint::Min(8), Constraint::Length(5), Constraint::Length(12), Constraint::Length(7), Constraint::Length(12)],
+            )
+        } else {
+            (
+                Row::new(["Market", "Score", "Util"]),
+                vec![Constraint::Min(8), Constraint::Length(5), Constraint::Length(7)],
+            )
+        };
+        let table = Table::new(rows, widths)
+            .header(header.style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(block)
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn render_watchlist(&self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .markets
+            .iter()
+            .flat_map(|panel| {
+                panel
+                    .positions
+                    .iter()
+                    .filter(|p| self.watchlist.contains(&p.address))
+                    .map(move |p| (panel, p))
+            })
+            .map(|(panel, position)| {
+                Row::new([
+                    Cell::from(to_checksum_address(&position.address)),
+                    Cell::from(sanitize_inline(&panel.assessment.market_name)),
+                    Cell::from(format!("{:.2}", position.health_factor)).style(self.health_factor_style(position.health_factor)),
+                    Cell::from(format!("{:.0}", position.total_borrow_value)),
+                ])
+            })
+            .collect();
+        let block = Block::bordered().title(" Watchlist ");
+        if rows.is_empty() {
+            let text = if self.watchlist.is_empty() { "No watchlist accounts configured" } else { "No borrowing watchlist accounts" };
+            frame.render_widget(Paragraph::new(text).block(block), area);
+            return;
+        }
+        let widths = [Constraint::Length(44), Constraint::Min(8), Constraint::Length(8), Constraint::Length(14)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["Account", "Market", "HF", "Borrow (USD)"]).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(block);
+        frame.render_widget(table, area);
+    }
+
+    fn render_market(&self, frame: &mut Frame, area: Rect, panel: &MarketPanel, wide: bool) {
+        let [header, findings, positions] =
+            Layout::vertical([Constraint::Length(3), Constraint::Fill(1), Constraint::Fill(1)]).areas(area);
+
+        let assessment = &panel.assessment;
+        let mut summary = vec![
+            Span::styled(sanitize_inline(&assessment.market_name), Style::new().add_modifier(Modifier::BOLD)),
+            Span::raw(format!(" {}  ", format_address(&assessment.market_address))),
+            Span::raw("score "),
+            Span::styled(assessment.risk_score.to_string(), score_style(assessment.risk_score)),
+        ];
+        if let Some(trend) = &assessment.trend {
+            summary.push(Span::raw(format!(" {}", trend.direction.arrow())));
+        }
+        if let Some(utilization) = panel.utilization {
+            summary.push(Span::raw(format!("  util {:.1}%", utilization * 100.0)));
+        }
+        frame.render_widget(Paragraph::new(Line::from(summary)).block(Block::bordered().title(" Market ")), header);
+
+        let market_findings = assessment.findings.iter().map(|f| (assessment.market_name.as_str(), f)).collect();
+        render_findings(frame, findings, "Findings", market_findings);
+
+        let rows = panel.positions.iter().take(TOP_POSITIONS).map(|position| {
+            let mut cells = vec![
+                Cell::from(if wide { to_checksum_address(&position.address) } else { format_address(&position.address) }),
+                Cell::from(format!("{:.2}", position.health_factor)).style(self.health_factor_style(position.health_factor)),
+                Cell::from(format!("{:.0}", position.total_borrow_value)),
+            ];
+            if wide {
+                cells.push(Cell::from(format!("{:.0}", position.total_collateral_value)));
+            }
+            Row::new(cells)
+        });
+        let (header, widths) = if wide {
+            (
+                Row::new(vec!["Account", "HF", "Borrow (USD)", "Collateral (USD)"]),
+                vec![Constraint::Length(44), Constraint::Length(8), Constraint::Length(14), Constraint::Length(16)],
+            )
+        } else {
+            (
+                Row::new(vec!["Account", "HF", "Borrow"]),
+                vec![Constraint::Length(14), Constraint::Length(6), Constraint::Min(6)],
+            )
+        };
+        let block = Block::bordered().title(" Positions at risk ");
+        if panel.positions.is_empty() {
+            frame.render_widget(Paragraph::new("No tracked borrowers").block(block), positions);
+            return;
+        }
+        let table = Table::new(rows, widths)
+            .header(header.style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(block);
+        frame.render_widget(table, positions);
+    }
+
+    fn footer(&self, wide: bool) -> Line<'static> {
+        let mut spans = Vec::new();
+        match &self.last_run {
+            None => spans.push(Span::raw("RPC: waiting for first run")),
+            Some(run) if run.errors.is_empty() => {
+                spans.push(Span::styled(format!("RPC: ok ({} markets)", run.assessed), Style::new().fg(Color::Green)));
+            }
+            Some(run) => {
+                let mut text = format!("RPC: {} failed", run.errors.len());
+                if wide {
+                    text.push_str(&format!(" ({})", sanitize_inline(&run.errors[0].error)));
+                }
+                spans.push(Span::styled(text, Style::new().fg(Color::Red)));
+            }
+        }
+        if let (true, Some(run)) = (wide, &self.last_run) {
+            spans.push(Span::raw(format!(" | last run {}", format_relative(&run.finished_at))));
+        }
+        spans.push(Span::raw(" | "));
+        let (text, color) = match &self.health {
+            None => ("data: pending".to_string(), Color::Reset),
+            Some(health) => match (health.status, health.data_age_secs) {
+                (HealthStatus::Pending, _) | (_, None) => ("data: pending".to_string(), Color::Reset),
+                (HealthStatus::Ok, Some(age)) => (format!("data: {}s old", age), Color::Green),
+                (HealthStatus::Stale, Some(age)) => (format!("data: STALE {}s old", age), Color::Red),
+            },
+        };
+        spans.push(Span::styled(text, Style::new().fg(color)));
+        if let Some(progress) = self.health.as_ref().and_then(|h| h.progress) {
+            spans.push(Span::raw(format!(" | {} {:.0}%", progress.stage.label(), progress.fraction() * 100.0)));
+        }
+        if self.health.as_ref().is_some_and(|h| h.mock_data) {
+            spans.push(Span::styled(" | MOCK DATA", Style::new().fg(Color::Yellow)));
+        }
+        if self.health.as_ref().and_then(|h| h.leadership.as_ref()).is_some_and(|l| l.role == Role::Standby) {
+            spans.push(Span::styled(" | STANDBY, not alerting", Style::new().fg(Color::Yellow)));
+        }
+        if wide {
+            let keys = match self.view {
+                View::Overview => " | ↑↓ select  Enter details  q quit",
+                View::Market => " | Esc back  q quit",
+            };
+            spans.push(Span::raw(keys));
+        }
+        Line::from(spans)
+    }
+
+    fn health_factor_style(&self, health_factor: f64) -> Style {
+        if health_factor < 1.0 {
+            Style::new().fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else if health_factor < self.alert_health_factor {
+            Style::new().fg(Color::Yellow)
+        } else {
+            Style::new()
+        }
+    }
+}
+
+/// Run the dashboard on the current terminal until `q`, ctrl-c or the engine's cancellation
+///
+/// Markets are assessed every `interval` by `run_scheduled`, which also drives the
+/// configured alert routes. Log lines written to the terminal would corrupt the display;
+/// send them elsewhere with `telemetry::init_telemetry_to`.
+pub async fn run(engine: Arc<RiskEngine>, interval: Duration) -> Result<()> {
+    let cancel = engine.cancellation_token().child_token();
+    let mut events = engine.subscribe();
+    let scheduler = tokio::spawn({
+        let engine = engine.clone();
+        let cancel = cancel.clone();
+        async move { engine.run_scheduled(interval, cancel).await }
+    });
+
+    let mut dashboard = Dashboard::new(&engine.config());
+    dashboard.refresh(&engine).await;
+    let mut keys = read_keys(cancel.clone());
+    let mut terminal = ratatui::try_init()?;
+
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| dashboard.render(frame)) {
+            break Err(e.into());
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break Ok(()),
+            key = keys.recv() => match key {
+                Some(key) if dashboard.handle_key(key) == Action::Quit => break Ok(()),
+                Some(_) => {}
+                None => break Ok(()),
+            },
+            received = events.recv() => match received {
+                Ok(RiskEvent::AssessmentCompleted(assessment)) => {
+                    dashboard.refresh_market(&engine, assessment.market_address).await;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => dashboard.refresh(&engine).await,
+                Err(RecvError::Closed) => break Ok(()),
+            },
+            // Keeps the data age current and picks up terminal resizes
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                dashboard.set_status(engine.health().await, engine.last_run());
+            }
+        }
+    };
+
+    ratatui::restore();
+    cancel.cancel();
+    let _ = scheduler.await;
+    result
+}
+
+/// Forward key presses from a blocking reader thread until `cancel` fires
+fn read_keys(cancel: CancellationToken) -> mpsc::UnboundedReceiver<KeyEvent> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while !cancel.is_cancelled() && !tx.is_closed() {
+            match event::poll(Duration::from_millis(200)) {
+                Ok(true) => {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        if key.kind == KeyEventKind::Press && tx.send(key).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(false) => {}
+                Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+fn render_findings(frame: &mut Frame, area: Rect, title: &str, mut findings: Vec<(&str, &RiskFinding)>) {
+    // Most severe first; stable, so each market's own order is kept within a severity
+    findings.sort_by_key(|(_, finding)| std::cmp::Reverse(finding.severity));
+    let block = Block::bordered().title(format!(" {} ({}) ", title, findings.len()));
+    if findings.is_empty() {
+        frame.render_widget(Paragraph::new("No risks identified").block(block), area);
+        return;
+    }
+    let items = findings.into_iter().map(|(market, finding)| {
+        ListItem::new(Line::from(vec![
+            Span::styled(format!("[{:?}] ", finding.severity), severity_style(finding.severity)),
+            Span::raw(format!("{}: {}", sanitize_inline(market), sanitize_inline(&finding.description))),
+        ]))
+    });
+    frame.render_widget(List::new(items).block(block), area);
+}
+
+fn severity_style(severity: RiskSeverity) -> Style {
+    match severity {
+        RiskSeverity::Low => Style::new().fg(Color::Blue),
+        RiskSeverity::Medium => Style::new().fg(Color::Yellow),
+        RiskSeverity::High => Style::new().fg(Color::Red),
+        RiskSeverity::Critical => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+    }
+}
+
+fn score_style(score: u8) -> Style {
+    match score {
+        0..=29 => Style::new().fg(Color::Green),
+        30..=59 => Style::new().fg(Color::Yellow),
+        _ => Style::new().fg(Color::Red),
+    }
+}
+
+/// `values` as a line of block characters scaled to `max`
+fn sparkline(values: &VecDeque<u64>, max: u64) -> String {
+    values
+        .iter()
+        .map(|&v| {
+            let level = (v.min(max) * (SPARK_LEVELS.len() as u64 - 1) + max / 2) / max.max(1);
+            SPARK_LEVELS[level as usize]
+        })
+        .collect()
+}
+
+fn push_bounded(values: &mut VecDeque<u64>, value: u64) {
+    if values.len() == HISTORY_LEN {
+        values.pop_front();
+    }
+    values.push_back(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::{Progress, Stage};
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::testing;
+    use chrono::{TimeZone, Utc};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::collections::HashMap;
+
+    const WATCHED: &str = "0x2222222222222222222222222222222222222222";
+
+    /// Text of every cell, one string per row; styles are not compared
+    fn screen(dashboard: &Dashboard, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn assessment(name: &str, address: u64, score: u8, findings: Vec<(RiskSeverity, &str)>) -> RiskAssessment {
+        let timestamp = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let findings = findings
+            .into_iter()
+            .map(|(severity, description)| RiskFinding {
+                description: description.to_string(),
+                timestamp,
+                ..testing::finding(description, severity)
+            })
+            .collect();
+        RiskAssessment {
+            market_name: name.to_string(),
+            market_address: Address::from_low_u64_be(address),
+            timestamp,
+            ..testing::assessment(score, findings)
+        }
+    }
+
+    fn position(address: &str, health_factor: f64) -> UserPosition {
+        UserPosition {
+            address: Address::from_str(address).unwrap(),
+            base_balance: -1000.0,
+            collateral_balances: HashMap::new(),
+            total_collateral_value: 2000.0,
+            total_borrow_value: 1000.0,
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        }
+    }
+
+    fn sample() -> Dashboard {
+        let mut dashboard = Dashboard::new(&Config { watchlist: vec![WATCHED.to_string()], ..Config::default() });
+        dashboard.update_market(
+            assessment("WETH", 2, 15, vec![(RiskSeverity::Medium, "rETH volatility 11%")]),
+            None,
+            Some(Vec::new()),
+        );
+        dashboard.update_market(
+            assessment("USDC", 1, 45, vec![(RiskSeverity::Low, "Reserves low"), (RiskSeverity::High, "Utilization 92%")]),
+            None,
+            Some(vec![position(WATCHED, 1.03), position("0x1111111111111111111111111111111111111111", 1.65)]),
+        );
+        let health = Health {
+            status: HealthStatus::Ok,
+            mock_data: false,
+            last_refresh: None,
+            last_assessment: None,
+            data_age_secs: Some(12),
+            markets_assessed: 2,
+            progress: None,
+            leadership: None,
+        };
+        dashboard.set_status(health, Some(RunSummary { finished_at: Utc::now(), assessed: 2, errors: Vec::new(), duration_ms: 0 }));
+        dashboard
+    }
+
+    #[test]
+    fn test_narrow_overview_snapshot() {
+        // WETH was assessed first and stays selected when USDC is listed above it
+        let expected = [
+            "┌ Markets ───────────────────────────────────────┐",
+            "│  Market                           Score Util   │",
+            "│  USDC                             45    -      │",
+            "│> WETH                             15    -      │",
+            "│                                                │",
+            "└────────────────────────────────────────────────┘",
+            "┌ Findings (3) ──────────────────────────────────┐",
+            "│[High] USDC: Utilization 92%                    │",
+            "│[Medium] WETH: rETH volatility 11%              │",
+            "│[Low] USDC: Reserves low                        │",
+            "└────────────────────────────────────────────────┘",
+            "RPC: ok (2 markets) | data: 12s old",
+        ];
+        assert_eq!(screen(&sample(), 50, 12), expected);
+        assert_eq!(screen(&sample(), 30, 12)[0], "Terminal too small: need at");
+
+        let mut fetching = sample();
+        let mut health = fetching.health.clone().unwrap();
+        health.progress = Some(Progress { stage: Stage::PositionFetch, done: 50, total: 200, per_second: None });
+        fetching.set_status(health, None);
+        let footer = screen(&fetching, 80, 12).pop().unwrap();
+        assert!(footer.contains("data: 12s old | Fetching positions 25%"), "{}", footer);
+        let footer = screen(&sample(), 120, 30).pop().unwrap();
+        assert!(footer.starts_with("RPC: ok (2 markets) | last run just now | data: 12s old"), "{}", footer);
+    }
+
+    #[test]
+    fn test_keys_select_a_market_and_open_its_positions() {
+        let mut dashboard = sample();
+        dashboard.handle_key(key(KeyCode::Up));
+        assert_eq!(dashboard.selected_market(), Some(Address::from_low_u64_be(1)));
+        dashboard.handle_key(key(KeyCode::Enter));
+        assert_eq!(dashboard.view(), View::Market);
+
+        let lines = screen(&dashboard, 50, 16);
+        assert!(lines[1].contains("USDC 0x0000...0001  score 45"), "{:#?}", lines);
+        assert!(lines.iter().any(|l| l.contains("[High] USDC: Utilization 92%")));
+        // Riskiest position first
+        let positions: Vec<&String> = lines.iter().filter(|l| l.contains("1000")).collect();
+        assert!(positions[0].contains("0x2222...2222") && positions[0].contains("1.03"), "{:#?}", lines);
+        assert!(positions[1].contains("1.65"));
+
+        dashboard.handle_key(key(KeyCode::Esc));
+        assert_eq!(dashboard.view(), View::Overview);
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('q'))), Action::Quit);
+        assert_eq!(dashboard.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Action::Quit);
+    }
+
+    #[tokio::test]
+    async fn test_wide_layout_reads_through_the_engine() {
+        let config = Config { watchlist: vec![WATCHED.to_string()], ..Config::default() };
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = RiskEngine::with_provider(config.clone(), Arc::new(fixture));
+        engine.assess_risks().await.unwrap();
+
+        let mut dashboard = Dashboard::new(&config);
+        dashboard.refresh(&engine).await;
+        let lines = screen(&dashboard, 120, 30);
+        let text = lines.join("\n");
+        assert!(text.contains("Trend"), "{}", text);
+        assert!(lines.iter().any(|l| l.contains("USDC") && l.contains("60") && l.contains("90.0%")), "{}", text);
+        assert!(text.contains(" Watchlist "));
+        assert!(lines.iter().any(|l| l.contains(WATCHED) && l.contains("1.04")), "{}", text);
+        // Watchlist findings raise the score from 45
+        // The fixture engine is not scheduled, so no run has finished yet
+        assert!(lines.last().unwrap().starts_with("RPC: waiting for first run | data:"), "{}", text);
+        assert!(text.contains("MOCK DATA"));
+    }
+}
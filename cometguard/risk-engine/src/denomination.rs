@@ -0,0 +1,233 @@
+//! Units amounts are displayed in: USD, each market's base asset or the network's native token
+//!
+//! Models keep amounts in USD and in asset units, and JSON always shows them as stored. Only
+//! text meant for people converts them, at render time: `display.denomination` picks the unit,
+//! `Unit::of` resolves it for one market from the prices the market was read with, and
+//! `Units` holds the unit of every market a table or report covers. Percentages and health
+//! factors have no unit and are left alone. A WETH market in `base` shows WETH, the volatile
+//! asset itself, at the same price its USD amounts were computed with, so converting back
+//! gives the amounts read from chain. Where the price of the unit is unknown, amounts stay in
+//! USD, and the unit says so.
+
+use crate::config::Config;
+use crate::models::Market;
+use crate::utils::{format_compact, format_compact_amount, format_money_with, NumberStyle};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Unit of the amounts of tables, reports and alert templates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Denomination {
+    /// US dollars
+    #[default]
+    Usd,
+    /// The base asset of each market, e.g. WETH for the WETH Comet
+    Base,
+    /// The network's gas token, `risk.gas.native_asset`
+    Native,
+}
+
+/// Unit of one market's amounts and its USD price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    /// What the unit resolved to; `Usd` where the asked-for unit has no price
+    pub denomination: Denomination,
+    /// `USD`, or the asset's symbol
+    pub symbol: String,
+    /// USD per unit; 1 for USD
+    pub price_usd: f64,
+}
+
+impl Default for Unit {
+    fn default() -> Self {
+        Self::usd()
+    }
+}
+
+impl Unit {
+    pub fn usd() -> Self {
+        Self { denomination: Denomination::Usd, symbol: "USD".to_string(), price_usd: 1.0 }
+    }
+
+    /// Unit of `market` in `denomination`, the native token being the asset of `market` whose
+    /// symbol is `native_asset`; USD where that asset is not in the market or has no price
+    pub fn of(denomination: Denomination, market: &Market, native_asset: &str) -> Self {
+        let asset = match denomination {
+            Denomination::Usd => None,
+            Denomination::Base => Some(&market.base_asset),
+            Denomination::Native => std::iter::once(&market.base_asset)
+                .chain(market.collateral_assets.values())
+                .find(|asset| asset.symbol.eq_ignore_ascii_case(native_asset)),
+        };
+        match asset.filter(|asset| asset.price.is_finite() && asset.price > 0.0) {
+            Some(asset) => Self { denomination, symbol: asset.symbol.clone(), price_usd: asset.price },
+            None => Self::usd(),
+        }
+    }
+
+    pub fn is_usd(&self) -> bool {
+        self.denomination == Denomination::Usd
+    }
+
+    /// `usd` in this unit
+    pub fn amount(&self, usd: f64) -> f64 {
+        usd / self.price_usd
+    }
+
+    /// `usd` in this unit, abbreviated with the separators of `numbers`: `$1.20M` in USD,
+    /// `1.20M WETH` otherwise
+    ///
+    /// Separators are localized before the symbol is added, as symbols such as USDC.e have dots.
+    pub fn format(&self, usd: f64, numbers: NumberStyle) -> String {
+        if self.is_usd() {
+            numbers.localize(&format_compact(usd))
+        } else {
+            format!("{} {}", numbers.localize(&format_compact_amount(self.amount(usd))), self.symbol)
+        }
+    }
+
+    /// `usd` in this unit to two decimals with the separators of `numbers`: `$1,200.50` in
+    /// USD, `0.45 WETH` otherwise
+    pub fn format_exact(&self, usd: f64, numbers: NumberStyle) -> String {
+        if self.is_usd() {
+            format_money_with(usd, "$", numbers)
+        } else {
+            format!("{} {}", format_money_with(self.amount(usd), "", numbers), self.symbol)
+        }
+    }
+}
+
+/// Units of a set of markets, by Comet address; markets not in it are shown in USD
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Units {
+    by_market: HashMap<Address, Unit>,
+}
+
+impl Units {
+    /// Units of `markets` in `config.display.denomination`
+    pub fn new(config: &Config, markets: &[Market]) -> Self {
+        let mut units = Self::default();
+        for market in markets {
+            units.insert(config, market);
+        }
+        units
+    }
+
+    /// Add or replace the unit of `market`
+    pub fn insert(&mut self, config: &Config, market: &Market) {
+        let unit = Unit::of(config.display.denomination, market, &config.risk.gas.native_asset);
+        self.by_market.insert(market.comet_address, unit);
+    }
+
+    /// Unit of the market at `market`
+    pub fn of(&self, market: &Address) -> Unit {
+        self.by_market.get(market).cloned().unwrap_or_default()
+    }
+
+    /// The unit every market of `markets` shares, or of every market when `None`; USD when
+    /// they differ, as amounts of several markets are then only comparable in USD
+    pub fn common(&self, markets: Option<&[Address]>) -> Unit {
+        let mut units = match markets {
+            Some(markets) => markets.iter().map(|market| self.of(market)).collect::<Vec<_>>(),
+            None => self.by_market.values().cloned().collect(),
+        }
+        .into_iter();
+        let Some(first) = units.next() else { return Unit::usd() };
+        if units.all(|unit| unit.symbol == first.symbol) {
+            first
+        } else {
+            Unit::usd()
+        }
+    }
+
+    /// Unit of the amounts of `markets`: `USD` or `WETH` when they share one, `BASE ASSET` or
+    /// `NATIVE TOKEN` when each is in its own
+    pub fn label(&self, markets: &[Address]) -> String {
+        let units: Vec<Unit> = markets.iter().map(|market| self.of(market)).collect();
+        match units.split_first() {
+            None => "USD".to_string(),
+            Some((first, rest)) if rest.iter().all(|unit| unit.symbol == first.symbol) => first.symbol.clone(),
+            Some(_) if units.iter().all(|unit| unit.denomination == Denomination::Base) => "BASE ASSET".to_string(),
+            Some(_) if units.iter().all(|unit| unit.denomination == Denomination::Native) => "NATIVE TOKEN".to_string(),
+            Some(_) => "MIXED UNITS".to_string(),
+        }
+    }
+}
+
+/// Units of the markets an engine assessed, kept up to date by each assessment and read by
+/// reports and by the alert dispatcher for `{amount.KEY}` in templates
+#[derive(Debug, Default)]
+pub struct MarketUnits {
+    units: Mutex<Units>,
+}
+
+impl MarketUnits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the unit of `market` as assessed with `config`
+    pub fn observe(&self, config: &Config, market: &Market) {
+        self.units.lock().unwrap_or_else(|e| e.into_inner()).insert(config, market);
+    }
+
+    /// Unit of the market at `market`; USD before its first assessment
+    pub fn of(&self, market: &Address) -> Unit {
+        self.units.lock().unwrap_or_else(|e| e.into_inner()).of(market)
+    }
+
+    /// Units of every market assessed so far
+    pub fn snapshot(&self) -> Units {
+        self.units.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+
+    #[test]
+    fn test_amounts_convert_at_render_time_and_fall_back_to_usd() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let usdc = provider.data().markets[0].clone();
+        // A WETH market: its base asset is the volatile asset, and the native token too
+        let mut weth = usdc.clone();
+        weth.comet_address = Address::repeat_byte(0xee);
+        weth.base_asset = weth.collateral_assets.values().find(|asset| asset.symbol == "WETH").unwrap().clone();
+        weth.collateral_assets.clear();
+        let price = weth.base_asset.price;
+
+        let mut config = Config::default();
+        let units = Units::new(&config, &[usdc.clone(), weth.clone()]);
+        assert_eq!(units.of(&weth.comet_address).format(4_000_000.0, NumberStyle::Us), "$4.00M");
+        assert_eq!(units.label(&[usdc.comet_address, weth.comet_address]), "USD");
+
+        config.display.denomination = Denomination::Base;
+        let units = Units::new(&config, &[usdc.clone(), weth.clone()]);
+        let in_weth = units.of(&weth.comet_address);
+        assert_eq!(in_weth.amount(1_000.0 * price), 1_000.0);
+        assert_eq!(in_weth.format(2_500.0 * price, NumberStyle::DecimalComma), "2,50K WETH");
+        assert_eq!(in_weth.format_exact(1_234.5 * price, NumberStyle::Us), "1,234.50 WETH");
+        assert_eq!(units.label(&[weth.comet_address]), "WETH");
+        assert_eq!(units.label(&[usdc.comet_address, weth.comet_address]), "BASE ASSET");
+        // Amounts of markets in different units only add up in USD
+        assert!(units.common(None).is_usd());
+        assert_eq!(units.common(Some(&[weth.comet_address])).symbol, "WETH");
+
+        // WETH is the native token of both markets
+        config.display.denomination = Denomination::Native;
+        let units = Units::new(&config, &[usdc.clone(), weth.clone()]);
+        assert_eq!(units.of(&usdc.comet_address).format(price * 10.0, NumberStyle::Us), "10.0 WETH");
+        assert_eq!(units.label(&[usdc.comet_address, weth.comet_address]), "WETH");
+
+        // Without a price for the native token the amounts stay in USD
+        config.risk.gas.native_asset = "MATIC".to_string();
+        let unit = Unit::of(config.display.denomination, &usdc, &config.risk.gas.native_asset);
+        assert_eq!((unit.is_usd(), unit.format(1_500.0, NumberStyle::Us)), (true, "$1.50K".to_string()));
+        assert!(Units::default().of(&usdc.comet_address).is_usd());
+    }
+}
@@ -0,0 +1,141 @@
+//! Self-diagnostics of the engine and what it depends on
+//!
+//! `RiskEngine::diagnostics` checks the RPC endpoint, how far the newest assessment trails
+//! the chain head, the market cache, the storage backend, the alert routes, the configuration
+//! and the freshness of the data, and rates each pass, warn or fail. The probes run
+//! concurrently, each under its own timeout, so a hung dependency fails its own item instead
+//! of holding up the report. `risk-engine-cli doctor` prints the report; the API serves it
+//! on `GET /readyz` and alongside the freshness on `GET /healthz`.
+
+use crate::error::{Result, RiskEngineError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Longest `RiskEngine::diagnostics` waits for any one probe
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Blocks the newest assessment may trail the chain head before the lag is a warning
+pub const MAX_BLOCK_LAG: u64 = 100;
+
+/// Rating of one diagnostic check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    /// Working, but worth a look
+    Warn,
+    /// Broken; the engine cannot fully do its job
+    Fail,
+}
+
+impl CheckStatus {
+    /// Upper-case label, as printed by `doctor`
+    pub fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+/// Outcome of one diagnostic check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    /// What was checked, e.g. "rpc (mainnet)" or "alerts (telegram)"
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    /// How long the probe took; `None` for checks that read no external dependency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl DiagnosticCheck {
+    pub fn new(name: impl Into<String>, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self { name: name.into(), status, message: message.into(), latency_ms: None }
+    }
+
+    /// Record that the probe took `latency`
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = Some(latency.as_millis() as u64);
+        self
+    }
+}
+
+impl fmt::Display for DiagnosticCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.status.label(), self.name, self.message)?;
+        if let Some(latency) = self.latency_ms {
+            write!(f, " ({} ms)", latency)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report of `RiskEngine::diagnostics`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+    /// Worst status among the checks
+    pub status: CheckStatus,
+    pub checked_at: DateTime<Utc>,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl Diagnostics {
+    pub fn new(checks: Vec<DiagnosticCheck>) -> Self {
+        let status = checks.iter().map(|c| c.status).max().unwrap_or(CheckStatus::Pass);
+        Self { status, checked_at: Utc::now(), checks }
+    }
+
+    /// Whether any check failed
+    pub fn is_failing(&self) -> bool {
+        self.status == CheckStatus::Fail
+    }
+
+    /// Check named `name`, if the report has one
+    pub fn check(&self, name: &str) -> Option<&DiagnosticCheck> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+}
+
+/// Await `probe` for at most `timeout`, returning its outcome and how long it took
+///
+/// A probe still running at the timeout is dropped and fails with `RiskEngineError::Unavailable`.
+pub async fn timed<T>(timeout: Duration, probe: impl Future<Output = Result<T>>) -> (Result<T>, Duration) {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, probe).await {
+        Ok(result) => result,
+        Err(_) => Err(RiskEngineError::Unavailable {
+            what: "diagnostic probe",
+            reason: format!("no answer within {}", crate::utils::format_duration(timeout)),
+        }),
+    };
+    (result, started.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_probes_time_out_on_their_own() {
+        let hung = timed(Duration::from_millis(20), std::future::pending::<Result<()>>());
+        let quick = timed(Duration::from_secs(5), async { Ok(7) });
+        let ((hung, waited), (quick, _)) = tokio::join!(hung, quick);
+        assert!(hung.unwrap_err().to_string().contains("no answer within"));
+        assert!(waited < Duration::from_secs(1));
+        assert_eq!(quick.unwrap(), 7);
+
+        let report = Diagnostics::new(vec![
+            DiagnosticCheck::new("config", CheckStatus::Pass, "valid"),
+            DiagnosticCheck::new("storage", CheckStatus::Warn, "slow").with_latency(Duration::from_millis(1500)),
+        ]);
+        assert_eq!((report.status, report.is_failing()), (CheckStatus::Warn, false));
+        assert_eq!(report.check("storage").unwrap().to_string(), "[WARN] storage: slow (1500 ms)");
+        assert_eq!(serde_json::to_value(&report.checks[0]).unwrap()["status"], "pass");
+    }
+}
@@ -0,0 +1,125 @@
+//! ENS names for accounts: forward resolution of inputs, reverse lookup for display
+//!
+//! Both directions are cached, so a name costs RPC calls once per `ens.cache_ttl_seconds`.
+//! Nothing here runs during an assessment; watchlist names are resolved when the engine starts.
+
+use crate::error::{Result, RiskEngineError};
+use crate::rpc::RpcProvider;
+use crate::utils;
+use ethers::providers::Middleware;
+use ethers::types::Address;
+use moka::future::Cache;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Whether `input` looks like an ENS name (`vitalik.eth`) rather than a hex address
+pub fn is_ens_name(input: &str) -> bool {
+    let input = input.trim();
+    !input.starts_with("0x")
+        && input.contains('.')
+        && !input.starts_with('.')
+        && !input.ends_with('.')
+        && !input.chars().any(char::is_whitespace)
+}
+
+/// Cached ENS resolution through a chain provider
+pub struct EnsResolver {
+    provider: Arc<RpcProvider>,
+    addresses: Cache<String, Address>,
+    names: Cache<Address, Option<String>>,
+}
+
+impl EnsResolver {
+    /// Resolver reading the ENS registry through `provider`, reusing results for `ttl`
+    pub fn new(provider: Arc<RpcProvider>, ttl: Duration) -> Self {
+        Self {
+            provider,
+            addresses: Cache::builder().time_to_live(ttl).build(),
+            names: Cache::builder().time_to_live(ttl).build(),
+        }
+    }
+
+    /// `input` as an address: hex as given, anything shaped like a name resolved through ENS
+    ///
+    /// Fails naming the input if it is neither a valid address nor a name that resolves.
+    pub async fn resolve(&self, input: &str) -> Result<Address> {
+        if let Ok(address) = utils::parse_address(input) {
+            return Ok(address);
+        }
+        if !is_ens_name(input) {
+            return Err(not_an_account(input, "not a hex address or an ENS name".to_string()));
+        }
+
+        let name = input.trim().to_lowercase();
+        if let Some(address) = self.addresses.get(&name) {
+            return Ok(address);
+        }
+        let address = self
+            .provider
+            .resolve_name(&name)
+            .await
+            .map_err(|e| not_an_account(input, format!("not a hex address, and the ENS name did not resolve: {}", e)))?;
+        self.addresses.insert(name, address).await;
+        Ok(address)
+    }
+
+    /// Primary ENS name of `address`; lookup failures count as having none
+    pub async fn lookup(&self, address: Address) -> Option<String> {
+        if let Some(name) = self.names.get(&address) {
+            return name;
+        }
+        let name = match self.provider.lookup_address(address).await {
+            Ok(name) => Some(name),
+            Err(e) => {
+                debug!("No ENS name for {}: {}", utils::to_checksum_address(&address), e);
+                None
+            }
+        };
+        self.names.insert(address, name.clone()).await;
+        name
+    }
+}
+
+fn not_an_account(input: &str, message: String) -> RiskEngineError {
+    RiskEngineError::Parse {
+        what: "account",
+        input: input.to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rpc::RecordingClient;
+    use ethers::providers::Provider;
+
+    #[test]
+    fn test_names_are_told_apart_from_addresses() {
+        assert!(is_ens_name("vitalik.eth"));
+        assert!(is_ens_name("treasury.compound-finance.eth"));
+        assert!(!is_ens_name("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045"));
+        assert!(!is_ens_name("vitalik"));
+        assert!(!is_ens_name("vitalik.eth."));
+        assert!(!is_ens_name("vitalik .eth"));
+    }
+
+    #[tokio::test]
+    async fn test_hex_input_needs_no_lookup() {
+        // Unreachable endpoint: only inputs that need a lookup touch it
+        let mut config = Config::default();
+        config.compound.rpc_url = "http://127.0.0.1:9".to_string();
+        let provider = Arc::new(Provider::new(RecordingClient::from_config(&config).unwrap()));
+        let resolver = EnsResolver::new(provider, Duration::from_secs(60));
+
+        let address = "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045";
+        assert_eq!(resolver.resolve(address).await.unwrap(), address.parse().unwrap());
+
+        let err = resolver.resolve("bob").await.unwrap_err().to_string();
+        assert!(err.contains("not a hex address or an ENS name"), "{}", err);
+        let err = resolver.resolve("bob.eth").await.unwrap_err().to_string();
+        assert!(err.contains("the ENS name did not resolve"), "{}", err);
+    }
+}
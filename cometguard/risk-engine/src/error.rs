@@ -0,0 +1,213 @@
+use ethers::providers::ProviderError;
+use ethers::types::Address;
+use std::path::PathBuf;
+
+/// Boxed underlying cause for errors from heterogeneous sources (e.g. contract bindings)
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Result type used throughout the library
+pub type Result<T, E = RiskEngineError> = std::result::Result<T, E>;
+
+/// Error returned by the public risk-engine APIs
+///
+/// Variants carry enough context to act on programmatically. RPC errors only ever
+/// record the endpoint host, never the full URL, since URLs commonly embed API keys.
+#[derive(Debug, thiserror::Error)]
+pub enum RiskEngineError {
+    /// Configuration is missing or invalid
+    #[error("invalid configuration `{field}`: {message}")]
+    Config { field: String, message: String },
+
+    /// `Config::validate` found one or more problems, listed one per line
+    #[error("invalid configuration:{}", numbered(.problems))]
+    InvalidConfig { problems: Vec<crate::config::ConfigProblem> },
+
+    /// The RPC provider or its transport failed
+    #[error("RPC request to {host} failed: {source}")]
+    Provider {
+        host: String,
+        #[source]
+        source: ProviderError,
+    },
+
+    /// A contract call reverted or could not be decoded
+    #[error("call to {method} on {contract:?} failed: {source}")]
+    ContractCall {
+        contract: Address,
+        method: String,
+        #[source]
+        source: BoxError,
+    },
+
+    /// User or chain supplied input could not be parsed
+    #[error("failed to parse {what} `{input}`: {message}")]
+    Parse {
+        what: &'static str,
+        input: String,
+        message: String,
+    },
+
+    /// (De)serialization of a file or payload failed
+    #[error("failed to (de)serialize {context}: {source}")]
+    Serialization {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Reading or writing a file failed
+    #[error("I/O error{}: {source}", .path.as_ref().map(|p| format!(" on {}", p.display())).unwrap_or_default())]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The cache could not be used
+    #[error("cache error for `{key}`: {message}")]
+    Cache { key: String, message: String },
+
+    /// The history database could not be opened, migrated, written or queried
+    #[error("storage {operation} failed: {message}")]
+    Storage { operation: &'static str, message: String },
+
+    /// A requested entity (market, asset, account...) does not exist
+    #[error("{kind} not found: {id}")]
+    NotFound { kind: &'static str, id: String },
+
+    /// The data source cannot supply this kind of data
+    #[error("{what} is unavailable: {reason}")]
+    Unavailable { what: &'static str, reason: String },
+
+    /// An alert could not be delivered to a sink
+    #[error("alert delivery via {sink} failed: {message}")]
+    Delivery {
+        sink: String,
+        message: String,
+        /// Whether sending again may succeed (timeouts, 5xx, rate limiting)
+        retryable: bool,
+    },
+
+    /// One or more market assessments failed under `ErrorPolicy::Strict`
+    #[error(transparent)]
+    Assessment(#[from] crate::AggregateAssessmentError),
+
+    /// The operation was interrupted through its cancellation token
+    #[error("operation cancelled")]
+    Cancelled,
+}
+
+impl RiskEngineError {
+    /// Configuration error for `field`
+    pub fn config(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::Config {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Provider error tagged with the host of `rpc_url`
+    pub fn provider(rpc_url: &str, source: ProviderError) -> Self {
+        Self::Provider {
+            host: rpc_host(rpc_url),
+            source,
+        }
+    }
+
+    /// Contract call error
+    pub fn contract_call(
+        contract: Address,
+        method: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Self::ContractCall {
+            contract,
+            method: method.into(),
+            source: Box::new(source),
+        }
+    }
+
+    /// I/O error on `path`
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    /// Serialization error with a description of what was being (de)serialized
+    pub fn serialization(context: impl Into<String>, source: serde_json::Error) -> Self {
+        Self::Serialization {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Whether this error means the operation was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
+}
+
+impl From<ProviderError> for RiskEngineError {
+    fn from(source: ProviderError) -> Self {
+        Self::Provider {
+            host: "unknown".to_string(),
+            source,
+        }
+    }
+}
+
+impl From<serde_json::Error> for RiskEngineError {
+    fn from(source: serde_json::Error) -> Self {
+        Self::serialization("JSON", source)
+    }
+}
+
+impl From<std::io::Error> for RiskEngineError {
+    fn from(source: std::io::Error) -> Self {
+        Self::Io { path: None, source }
+    }
+}
+
+/// `problems` as a numbered list, one per line
+fn numbered(problems: &[crate::config::ConfigProblem]) -> String {
+    problems
+        .iter()
+        .enumerate()
+        .map(|(i, problem)| format!("\n  {}. {}", i + 1, problem))
+        .collect()
+}
+
+/// Host part of an RPC URL, safe to log (paths and query strings often hold API keys)
+pub fn rpc_host(rpc_url: &str) -> String {
+    reqwest::Url::parse(rpc_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "<invalid url>".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_host_strips_secrets() {
+        assert_eq!(
+            rpc_host("https://eth-mainnet.g.alchemy.com/v2/SECRET_KEY"),
+            "eth-mainnet.g.alchemy.com"
+        );
+        assert_eq!(rpc_host("not a url"), "<invalid url>");
+    }
+
+    #[test]
+    fn test_provider_error_display_has_host_only() {
+        let err = RiskEngineError::provider(
+            "https://rpc.example.org/key123",
+            ProviderError::CustomError("connection refused".to_string()),
+        );
+        let rendered = err.to_string();
+        assert!(rendered.contains("rpc.example.org"));
+        assert!(!rendered.contains("key123"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::compare::{self, FindingChange};
+use crate::position_changes::PositionDelta;
+use crate::risk::{RiskAssessment, RiskFinding, RiskSeverity};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Capacity of the engine's event broadcast channel
+///
+/// A subscriber that falls more than this many events behind skips the oldest ones and
+/// receives `RecvError::Lagged` instead of blocking the scheduler.
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Event published to `RiskEngine::subscribe` receivers
+///
+/// Only changes are published: a finding that persists across runs at the same (or a
+/// lower) severity does not produce a new event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RiskEvent {
+    /// An assessment of a market finished
+    AssessmentCompleted(Box<RiskAssessment>),
+    /// A finding appeared that was not present in the previous run
+    NewFinding {
+        market_name: String,
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+        finding: RiskFinding,
+    },
+    /// A finding from the previous run is no longer present
+    FindingResolved {
+        market_name: String,
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+        finding: RiskFinding,
+    },
+    /// A finding persisted but its severity increased
+    SeverityEscalated {
+        market_name: String,
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+        previous: RiskSeverity,
+        finding: RiskFinding,
+    },
+    /// A watchlist position changed by `risk.position_changes` since the previous assessment
+    PositionChanged {
+        market_name: String,
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+        change: PositionDelta,
+    },
+    /// A new configuration took effect; `changed` lists its top-level sections that differ
+    ConfigReloaded { changed: Vec<String> },
+}
+
+impl RiskEvent {
+    /// Comet proxy of the market the event is about (`None` for `ConfigReloaded`)
+    pub fn market_address(&self) -> Option<Address> {
+        match self {
+            Self::AssessmentCompleted(assessment) => Some(assessment.market_address),
+            Self::NewFinding { market_address, .. }
+            | Self::FindingResolved { market_address, .. }
+            | Self::SeverityEscalated { market_address, .. }
+            | Self::PositionChanged { market_address, .. } => Some(*market_address),
+            Self::ConfigReloaded { .. } => None,
+        }
+    }
+
+    /// The finding the event is about (`None` for `AssessmentCompleted`, `PositionChanged`
+    /// and `ConfigReloaded`)
+    pub fn finding(&self) -> Option<&RiskFinding> {
+        match self {
+            Self::AssessmentCompleted(_) | Self::PositionChanged { .. } | Self::ConfigReloaded { .. } => None,
+            Self::NewFinding { finding, .. }
+            | Self::FindingResolved { finding, .. }
+            | Self::SeverityEscalated { finding, .. } => Some(finding),
+        }
+    }
+
+    /// Variant name, e.g. `NewFinding`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::AssessmentCompleted(_) => "AssessmentCompleted",
+            Self::NewFinding { .. } => "NewFinding",
+            Self::FindingResolved { .. } => "FindingResolved",
+            Self::SeverityEscalated { .. } => "SeverityEscalated",
+            Self::PositionChanged { .. } => "PositionChanged",
+            Self::ConfigReloaded { .. } => "ConfigReloaded",
+        }
+    }
+}
+
+/// Remembers the previous assessment per market and turns new ones into change events
+#[derive(Debug, Default)]
+pub struct FindingTracker {
+    previous: HashMap<Address, RiskAssessment>,
+}
+
+impl FindingTracker {
+    /// Create an empty tracker; every finding in the first run is reported as new
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `assessment` and return the events describing how it differs from the last one,
+    /// with its watchlist position changes
+    pub fn observe(&mut self, assessment: RiskAssessment) -> Vec<RiskEvent> {
+        let market_address = assessment.market_address;
+        let market_name = &assessment.market_name;
+        let mut events = match self.previous.get(&market_address) {
+            Some(previous) => diff_findings(market_name, market_address, &previous.findings, &assessment.findings),
+            None => assessment
+                .findings
+                .iter()
+                .map(|finding| RiskEvent::NewFinding {
+                    market_name: market_name.clone(),
+                    market_address,
+                    finding: finding.clone(),
+                })
+                .collect(),
+        };
+
+        events.extend(assessment.position_changes.iter().map(|change| RiskEvent::PositionChanged {
+            market_name: market_name.clone(),
+            market_address,
+            change: change.clone(),
+        }));
+
+        self.previous.insert(market_address, assessment.clone());
+        events.push(RiskEvent::AssessmentCompleted(Box::new(assessment)));
+        events
+    }
+}
+
+/// Compare two finding sets for one market, matching findings by fingerprint
+pub fn diff_findings(
+    market_name: &str,
+    market_address: Address,
+    previous: &[RiskFinding],
+    current: &[RiskFinding],
+) -> Vec<RiskEvent> {
+    compare::diff_findings(previous, current)
+        .into_iter()
+        .filter_map(|change| match change {
+            FindingChange::Added { finding } => Some(RiskEvent::NewFinding {
+                market_name: market_name.to_string(),
+                market_address,
+                finding,
+            }),
+            FindingChange::SeverityChanged { previous, finding, .. } if finding.severity > previous => {
+                Some(RiskEvent::SeverityEscalated {
+                    market_name: market_name.to_string(),
+                    market_address,
+                    previous,
+                    finding,
+                })
+            }
+            FindingChange::SeverityChanged { .. } => None,
+            FindingChange::Removed { finding } => Some(RiskEvent::FindingResolved {
+                market_name: market_name.to_string(),
+                market_address,
+                finding,
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assessment, finding};
+
+    #[test]
+    fn test_tracker_emits_only_changes() {
+        let mut tracker = FindingTracker::new();
+
+        let events = tracker.observe(assessment(0, vec![finding("a", RiskSeverity::Medium)]));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], RiskEvent::NewFinding { .. }));
+        assert!(matches!(events[1], RiskEvent::AssessmentCompleted(_)));
+
+        // Same finding again: nothing but the completion event
+        let events = tracker.observe(assessment(0, vec![finding("a", RiskSeverity::Medium)]));
+        assert_eq!(events.len(), 1);
+
+        // Escalation plus a new finding
+        let events = tracker.observe(assessment(0, vec![
+            finding("a", RiskSeverity::Critical),
+            finding("b", RiskSeverity::Low),
+        ]));
+        assert!(matches!(
+            events[0],
+            RiskEvent::SeverityEscalated { previous: RiskSeverity::Medium, .. }
+        ));
+        assert!(matches!(&events[1], RiskEvent::NewFinding { finding, .. } if finding.fingerprint == "b"));
+
+        // De-escalation is not an event; disappearance is
+        let events = tracker.observe(assessment(0, vec![finding("a", RiskSeverity::High)]));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], RiskEvent::FindingResolved { finding, .. } if finding.fingerprint == "b"));
+    }
+}
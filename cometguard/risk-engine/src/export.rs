@@ -0,0 +1,266 @@
+//! Flat CSV exports of findings and positions for spreadsheets and Dune
+//!
+//! Column sets are fixed so exports from different runs line up. Finding metadata is
+//! flattened into one column per known key; any other keys are kept together as JSON in
+//! `extra_json` rather than adding columns.
+
+use crate::error::Result;
+use crate::models::{Market, UserPosition};
+use crate::risk::RiskAssessment;
+use crate::storage::StoredFinding;
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+/// Leading columns of the findings CSV, followed by `METADATA_COLUMNS` and `extra_json`
+///
+/// - `assessed_at`: RFC 3339 time of the assessment that reported the finding
+/// - `market_name`, `market_address`: market the finding is about
+/// - `category`, `severity`: variant names (e.g. `HighUtilization`, `High`)
+/// - `score_contribution`: points the finding added to the market's risk score (capped at 100 in total)
+/// - `fingerprint`: stable identifier of the underlying condition
+/// - `description`: human-readable summary
+pub const FINDING_COLUMNS: [&str; 8] = [
+    "assessed_at",
+    "market_name",
+    "market_address",
+    "category",
+    "severity",
+    "score_contribution",
+    "fingerprint",
+    "description",
+];
+
+/// Metadata keys with a column of their own, empty when a finding does not set them
+///
+/// Utilization: `current_utilization`, `simulated_utilization`, `threshold`, `base_asset`,
+/// `total_supply`, `total_borrow`. Price volatility: `asset`, `asset_address`,
+/// `volatility_30d`, `price_change_24h`, `price_change_7d`. Liquidation risk: `account`,
+/// `health_factor`, `alert_health_factor`, `buffer`, `collateral_value`, `borrow_value`,
+/// `dominant_collateral`, `liquidation_price`.
+pub const METADATA_COLUMNS: [&str; 19] = [
+    "account",
+    "asset",
+    "asset_address",
+    "base_asset",
+    "current_utilization",
+    "simulated_utilization",
+    "threshold",
+    "total_supply",
+    "total_borrow",
+    "volatility_30d",
+    "price_change_24h",
+    "price_change_7d",
+    "health_factor",
+    "alert_health_factor",
+    "buffer",
+    "collateral_value",
+    "borrow_value",
+    "dominant_collateral",
+    "liquidation_price",
+];
+
+/// Metadata keys that repeat another column (`market` is the market name) and are left out
+const REDUNDANT_METADATA: [&str; 1] = ["market"];
+
+/// Columns of the positions CSV
+///
+/// - `market_name`, `market_address`: market the position is in
+/// - `account`: position owner
+/// - `health_factor`: below 1 the position can be liquidated
+/// - `borrow_value`, `collateral_value`: USD values
+/// - `dominant_collateral`: symbol of the collateral asset with the largest USD value
+/// - `dominant_collateral_value`: USD value of that collateral
+/// - `liquidation_price`: price of the dominant collateral at which the health factor
+///   reaches 1, other prices unchanged; empty if that collateral alone cannot cause it
+pub const POSITION_COLUMNS: [&str; 9] = [
+    "market_name",
+    "market_address",
+    "account",
+    "health_factor",
+    "borrow_value",
+    "collateral_value",
+    "dominant_collateral",
+    "dominant_collateral_value",
+    "liquidation_price",
+];
+
+/// Findings of `assessments`, in the shape the history store returns them
+pub fn assessment_findings(assessments: &[RiskAssessment]) -> Vec<StoredFinding> {
+    assessments
+        .iter()
+        .flat_map(|assessment| {
+            assessment.findings.iter().map(|finding| StoredFinding {
+                market_name: assessment.market_name.clone(),
+                market_address: assessment.market_address,
+                assessed_at: assessment.timestamp,
+                finding: finding.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Write `findings` as CSV with a header row; returns the number of rows written
+pub fn write_findings_csv<W: Write>(writer: W, findings: &[StoredFinding]) -> Result<usize> {
+    let mut csv = csv::Writer::from_writer(writer);
+    let header = FINDING_COLUMNS.iter().chain(&METADATA_COLUMNS).chain(&["extra_json"]);
+    csv.write_record(header).map_err(io::Error::from)?;
+
+    for stored in findings {
+        let finding = &stored.finding;
+        let mut record = vec![
+            stored.assessed_at.to_rfc3339(),
+            stored.market_name.clone(),
+            crate::utils::to_checksum_address(&stored.market_address),
+            format!("{:?}", finding.category),
+            format!("{:?}", finding.severity),
+            finding.severity.score_weight().to_string(),
+            finding.fingerprint.clone(),
+            finding.description.clone(),
+        ];
+
+        let empty = Map::new();
+        let metadata = finding.metadata.as_object().unwrap_or(&empty);
+        record.extend(METADATA_COLUMNS.iter().map(|key| metadata.get(*key).map(cell).unwrap_or_default()));
+
+        let extra: Map<String, Value> = metadata
+            .iter()
+            .filter(|(key, _)| !METADATA_COLUMNS.contains(&key.as_str()) && !REDUNDANT_METADATA.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        record.push(if extra.is_empty() { String::new() } else { Value::Object(extra).to_string() });
+
+        csv.write_record(&record).map_err(io::Error::from)?;
+    }
+    csv.flush()?;
+    Ok(findings.len())
+}
+
+/// Write `positions` as CSV with a header row; returns the number of rows written
+pub fn write_positions_csv<'a, W: Write>(
+    writer: W,
+    positions: impl IntoIterator<Item = (&'a Market, &'a UserPosition)>,
+) -> Result<usize> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(POSITION_COLUMNS).map_err(io::Error::from)?;
+
+    let mut rows = 0;
+    for (market, position) in positions {
+        let dominant = position.dominant_collateral(market);
+        let record = [
+            market.name.clone(),
+            crate::utils::to_checksum_address(&market.comet_address),
+            crate::utils::to_checksum_address(&position.address),
+            position.health_factor.to_string(),
+            position.total_borrow_value.to_string(),
+            position.total_collateral_value.to_string(),
+            dominant.map(|(asset, _)| asset.symbol.clone()).unwrap_or_default(),
+            dominant.map(|(asset, balance)| (balance * asset.price).to_string()).unwrap_or_default(),
+            dominant
+                .and_then(|(asset, _)| position.liquidation_price(asset))
+                .map(|price| price.to_string())
+                .unwrap_or_default(),
+        ];
+        csv.write_record(&record).map_err(io::Error::from)?;
+        rows += 1;
+    }
+    csv.flush()?;
+    Ok(rows)
+}
+
+/// Metadata value as a CSV cell: strings unquoted, numbers and booleans as written, null empty
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::risk::{RiskCategory, RiskFinding, RiskSeverity};
+    use crate::testing::finding;
+    use crate::RiskEngine;
+    use chrono::Utc;
+    use ethers::types::Address;
+    use std::sync::Arc;
+
+    fn rows(csv: &[u8]) -> Vec<Vec<String>> {
+        csv::Reader::from_reader(csv)
+            .records()
+            .map(|r| r.unwrap().iter().map(str::to_string).collect())
+            .collect()
+    }
+
+    fn column(name: &str) -> usize {
+        FINDING_COLUMNS.iter().chain(&METADATA_COLUMNS).chain(&["extra_json"]).position(|c| *c == name).unwrap()
+    }
+
+    #[test]
+    fn test_finding_metadata_is_flattened() {
+        let finding = StoredFinding {
+            market_name: "USDC".to_string(),
+            market_address: Address::from_low_u64_be(1),
+            assessed_at: Utc::now(),
+            finding: RiskFinding {
+                category: RiskCategory::LiquidationCascade,
+                description: "Account near liquidation, \"1.03\"".to_string(),
+                metadata: serde_json::json!({
+                    "account": "0x2222222222222222222222222222222222222222",
+                    "health_factor": 1.03,
+                    "market": "USDC",
+                    "liquidation_price": null,
+                    "oracle": {"source": "chainlink"},
+                }),
+                ..finding("abc", RiskSeverity::High)
+            },
+        };
+
+        let mut out = Vec::new();
+        assert_eq!(write_findings_csv(&mut out, &[finding]).unwrap(), 1);
+        let header = csv::Reader::from_reader(out.as_slice()).headers().unwrap().len();
+        assert_eq!(header, FINDING_COLUMNS.len() + METADATA_COLUMNS.len() + 1);
+
+        let row = &rows(&out)[0];
+        assert_eq!(row[column("severity")], "High");
+        assert_eq!(row[column("score_contribution")], "30");
+        assert_eq!(row[column("description")], "Account near liquidation, \"1.03\"");
+        assert_eq!(row[column("account")], "0x2222222222222222222222222222222222222222");
+        assert_eq!(row[column("health_factor")], "1.03");
+        assert_eq!(row[column("liquidation_price")], "");
+        assert_eq!(row[column("extra_json")], r#"{"oracle":{"source":"chainlink"}}"#);
+    }
+
+    #[tokio::test]
+    async fn test_export_fixture_findings_and_positions() {
+        let config = Config {
+            watchlist: vec![
+                "0x1111111111111111111111111111111111111111".to_string(),
+                "0x2222222222222222222222222222222222222222".to_string(),
+            ],
+            ..Config::default()
+        };
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = RiskEngine::with_provider(config, Arc::new(fixture));
+        let assessments = engine.assess_risks().await.unwrap();
+
+        let mut out = Vec::new();
+        let findings = assessment_findings(&assessments);
+        assert_eq!(write_findings_csv(&mut out, &findings).unwrap(), 3);
+        let categories: Vec<String> = rows(&out).iter().map(|r| r[column("category")].clone()).collect();
+        assert_eq!(categories, ["HighUtilization", "PriceVolatility", "LiquidationCascade"]);
+
+        let market = engine.provider().get_markets().await.unwrap().remove(0);
+        let positions = engine.tracked_positions(&market).await.unwrap();
+        let mut out = Vec::new();
+        assert_eq!(write_positions_csv(&mut out, positions.iter().map(|p| (&market, p))).unwrap(), 2);
+        let rows = rows(&out);
+        // Riskiest first
+        assert_eq!(rows[0][2], "0x2222222222222222222222222222222222222222");
+        assert_eq!(rows[0][3], "1.04");
+        assert!(!rows[0][6].is_empty(), "dominant collateral");
+    }
+}
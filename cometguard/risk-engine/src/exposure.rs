@@ -0,0 +1,140 @@
+//! One account's positions across every market, for `check-user` and concentration reviews
+//!
+//! Large accounts borrow in several Comets at once. Per market each position may look small;
+//! summed over the markets the engine reads, the account can hold a large share of all borrow.
+//! Only the markets of the configured deployment are read, so the totals cover that chain.
+
+use crate::models::{Market, UserPosition};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+/// An account's position in one market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPosition {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub chain_id: u64,
+    pub position: UserPosition,
+    /// `Market::available_liquidity`, in the market's base asset
+    #[serde(default)]
+    pub available_liquidity: f64,
+}
+
+/// A market whose position could not be read
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedMarket {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub error: String,
+}
+
+/// An account's positions over all markets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountExposure {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub address: Address,
+    /// Primary ENS name, filled in by `RiskEngine::get_account_exposure` when reverse lookups are on
+    pub account_name: Option<String>,
+    /// Markets the account has a position in, largest borrow first
+    pub markets: Vec<MarketPosition>,
+    /// Markets whose position could not be read; the totals leave them out
+    pub failed: Vec<FailedMarket>,
+    pub total_borrow_usd: f64,
+    pub total_collateral_usd: f64,
+    /// Lowest health factor among markets the account borrows in
+    pub min_health_factor: Option<f64>,
+    /// Borrow of every market read, in USD
+    pub protocol_borrow_usd: f64,
+    /// `total_borrow_usd` over `protocol_borrow_usd`, from 0 to 1
+    pub borrow_share: f64,
+    /// `risk.max_account_borrow_share` in effect
+    pub max_borrow_share: f64,
+    pub mock_data: bool,
+}
+
+impl AccountExposure {
+    /// Exposure of `address` from its `positions`, against the borrow of all `markets`
+    pub fn new(
+        address: Address,
+        markets: &[Market],
+        mut positions: Vec<MarketPosition>,
+        failed: Vec<FailedMarket>,
+        max_borrow_share: f64,
+    ) -> Self {
+        positions.sort_by(|a, b| b.position.total_borrow_value.total_cmp(&a.position.total_borrow_value));
+        let total_borrow_usd = positions.iter().fold(0.0, |sum, p| sum + p.position.total_borrow_value);
+        let protocol_borrow_usd = markets.iter().fold(0.0, |sum, m| sum + m.total_borrow * m.base_asset.price);
+        Self {
+            address,
+            account_name: None,
+            total_collateral_usd: positions.iter().fold(0.0, |sum, p| sum + p.position.total_collateral_value),
+            min_health_factor: positions
+                .iter()
+                .filter(|p| p.position.total_borrow_value > 0.0)
+                .map(|p| p.position.health_factor)
+                .min_by(f64::total_cmp),
+            borrow_share: if protocol_borrow_usd > 0.0 { total_borrow_usd / protocol_borrow_usd } else { 0.0 },
+            total_borrow_usd,
+            protocol_borrow_usd,
+            markets: positions,
+            failed,
+            max_borrow_share,
+            mock_data: false,
+        }
+    }
+
+    /// Whether the account holds more than `max_borrow_share` of all borrow
+    pub fn is_concentrated(&self) -> bool {
+        self.borrow_share > self.max_borrow_share
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+    #[tokio::test]
+    async fn test_exposure_sums_markets_and_flags_large_shares() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let usdc = fixture.get_markets().await.unwrap().remove(0);
+        let mut weth = usdc.clone();
+        weth.name = "WETH".to_string();
+        weth.comet_address = Address::repeat_byte(0xa1);
+        let account = Address::repeat_byte(0xbe);
+        let position = |borrow: f64, collateral: f64, health_factor: f64| UserPosition {
+            address: account,
+            base_balance: -borrow,
+            collateral_balances: Default::default(),
+            total_collateral_value: collateral,
+            total_borrow_value: borrow,
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        let at = |market: &Market, position: UserPosition| MarketPosition {
+            market_name: market.name.clone(),
+            market_address: market.comet_address,
+            chain_id: 1,
+            position,
+            available_liquidity: market.available_liquidity(),
+        };
+
+        // Two borrows each under 5% of all borrow, together above it
+        let protocol_borrow = 2.0 * usdc.total_borrow * usdc.base_asset.price;
+        let (small, large) = (protocol_borrow * 0.025, protocol_borrow * 0.035);
+        let positions = vec![at(&usdc, position(small, 2.0 * small, 1.6)), at(&weth, position(large, 2.0 * large, 1.2))];
+        let exposure = AccountExposure::new(account, &[usdc.clone(), weth.clone()], positions, Vec::new(), 0.05);
+        assert_eq!(exposure.markets[0].market_name, "WETH");
+        assert!((exposure.total_collateral_usd - protocol_borrow * 0.12).abs() < 1e-6);
+        assert!((exposure.borrow_share - 0.06).abs() < 1e-9, "{}", exposure.borrow_share);
+        assert_eq!(exposure.min_health_factor, Some(1.2));
+        assert!(exposure.is_concentrated());
+
+        let supplier = AccountExposure::new(account, &[usdc], Vec::new(), Vec::new(), 0.05);
+        assert_eq!((supplier.total_borrow_usd, supplier.min_health_factor, supplier.is_concentrated()), (0.0, None, false));
+    }
+}
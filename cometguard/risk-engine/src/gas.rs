@@ -0,0 +1,142 @@
+//! What liquidating costs in gas
+//!
+//! Liquidation economics swing with gas: absorbing a position at 200 gwei costs twenty times
+//! what it does at 10. `MarketDataProvider::get_gas_quote` reads the chain's fees, the next
+//! block's base fee plus a percentile of recent priority fees from `eth_feeHistory`, or
+//! `eth_gasPrice` where fee history is not served. On rollups with an L1 fee oracle the quote
+//! includes the L1 data fee of an absorption transaction. `GasEstimate::of` prices the quote
+//! in USD through the market's `risk.gas.native_asset`, falling back to
+//! `risk.small_positions.gas_cost_usd` when either price is unknown. `risk.gas.pinned_gwei`
+//! replaces the chain's fees with a fixed price, so simulations come out the same every time.
+
+use crate::config::Config;
+use crate::models::Market;
+use serde::{Deserialize, Serialize};
+
+/// Calldata of an absorption and the purchase of its collateral, in bytes, as priced by L1
+/// fee oracles
+pub const ABSORPTION_CALLDATA_BYTES: usize = 264;
+
+/// Where a gas price came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasSource {
+    /// Base fee and a percentile of priority fees from `eth_feeHistory`
+    FeeHistory,
+    /// `eth_gasPrice`, with no split between base and priority fee
+    GasPrice,
+    /// `risk.gas.pinned_gwei`
+    Pinned,
+    /// Fixture data
+    Fixture,
+    /// `risk.small_positions.gas_cost_usd`, for want of a gas or native token price
+    Configured,
+}
+
+/// Fees of the chain, as read by `MarketDataProvider::get_gas_quote`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GasQuote {
+    /// Base fee of the next block, in gwei; 0 where only a gas price is known
+    pub base_fee_gwei: f64,
+    /// Priority fee paid on top of the base fee, in gwei
+    pub priority_fee_gwei: f64,
+    /// L1 data fee of an absorption transaction, in the native token, on rollups whose
+    /// provider exposes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1_data_fee: Option<f64>,
+    pub source: GasSource,
+}
+
+impl GasQuote {
+    /// `risk.gas.pinned_gwei` as a quote with no priority or L1 fee
+    pub fn pinned(gwei: f64) -> Self {
+        Self { base_fee_gwei: gwei, priority_fee_gwei: 0.0, l1_data_fee: None, source: GasSource::Pinned }
+    }
+
+    /// Price per unit of gas, in gwei
+    pub fn gas_price_gwei(&self) -> f64 {
+        self.base_fee_gwei + self.priority_fee_gwei
+    }
+
+    /// Native token paid for `gas_units` of execution plus the L1 data fee
+    pub fn cost(&self, gas_units: u64) -> f64 {
+        gas_units as f64 * self.gas_price_gwei() * 1e-9 + self.l1_data_fee.unwrap_or(0.0)
+    }
+}
+
+/// Gas context of liquidation findings: what absorbing a position costs in USD
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GasEstimate {
+    /// Absorbing a position and buying its collateral, in USD
+    pub absorption_cost_usd: f64,
+    /// `None` when the cost is `risk.small_positions.gas_cost_usd`
+    pub base_fee_gwei: Option<f64>,
+    pub gas_price_gwei: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1_data_fee_usd: Option<f64>,
+    /// Price of the gas token, from the market's `risk.gas.native_asset`
+    pub native_price_usd: Option<f64>,
+    pub source: GasSource,
+}
+
+impl GasEstimate {
+    /// Cost of an absorption in `market` at `quote`, or at the configured USD cost when the
+    /// quote or the native token's price is missing
+    pub fn of(quote: Option<&GasQuote>, market: &Market, config: &Config) -> Self {
+        let settings = &config.risk.gas;
+        let native_price_usd = std::iter::once(&market.base_asset)
+            .chain(market.collateral_assets.values())
+            .find(|asset| asset.symbol.eq_ignore_ascii_case(&settings.native_asset))
+            .map(|asset| asset.price)
+            .filter(|price| price.is_finite() && *price > 0.0);
+        match (quote, native_price_usd) {
+            (Some(quote), Some(price)) => Self {
+                absorption_cost_usd: quote.cost(settings.absorption_gas_units) * price,
+                base_fee_gwei: Some(quote.base_fee_gwei),
+                gas_price_gwei: Some(quote.gas_price_gwei()),
+                l1_data_fee_usd: quote.l1_data_fee.map(|fee| fee * price),
+                native_price_usd,
+                source: quote.source,
+            },
+            _ => Self {
+                absorption_cost_usd: config.risk.small_positions.gas_cost_usd,
+                base_fee_gwei: quote.map(|quote| quote.base_fee_gwei),
+                gas_price_gwei: quote.map(GasQuote::gas_price_gwei),
+                l1_data_fee_usd: None,
+                native_price_usd,
+                source: GasSource::Configured,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+
+    #[test]
+    fn test_absorption_cost_follows_the_gas_price() {
+        let market = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().markets[0].clone();
+        let config = Config::default();
+
+        // 300,000 gas at 10 and at 200 gwei, with WETH at $2000
+        let calm = GasEstimate::of(Some(&GasQuote::pinned(10.0)), &market, &config);
+        let spike = GasEstimate::of(Some(&GasQuote::pinned(200.0)), &market, &config);
+        assert!((calm.absorption_cost_usd - 6.0).abs() < 1e-9, "{}", calm.absorption_cost_usd);
+        assert!((spike.absorption_cost_usd - 120.0).abs() < 1e-9, "{}", spike.absorption_cost_usd);
+        assert_eq!(spike.source, GasSource::Pinned);
+
+        let rollup = GasQuote { base_fee_gwei: 0.01, priority_fee_gwei: 0.001, l1_data_fee: Some(0.0005), source: GasSource::FeeHistory };
+        let estimate = GasEstimate::of(Some(&rollup), &market, &config);
+        assert_eq!(estimate.l1_data_fee_usd, Some(1.0));
+        assert!((estimate.absorption_cost_usd - (300_000.0 * 0.011e-9 * 2000.0 + 1.0)).abs() < 1e-9);
+
+        // No gas token among the market's assets: the configured cost
+        let mut config = Config::default();
+        config.risk.gas.native_asset = "MATIC".to_string();
+        let fallback = GasEstimate::of(Some(&GasQuote::pinned(10.0)), &market, &config);
+        assert_eq!((fallback.absorption_cost_usd, fallback.source), (25.0, GasSource::Configured));
+        assert_eq!(fallback.gas_price_gwei, Some(10.0));
+    }
+}
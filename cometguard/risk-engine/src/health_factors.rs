@@ -0,0 +1,193 @@
+//! Distribution of the tracked positions of a market over health factor buckets
+//!
+//! Buckets run from an explicit "<1.0" bucket of liquidatable positions through the ranges
+//! between `risk.summary.health_factor_buckets`, and an open one above the last edge, to a
+//! "no borrow" bucket for positions whose health factor is unbounded. Each has the positions
+//! in it and their borrow in USD. The distribution is computed with the market's exposure and
+//! attached to its assessment; the exposure's value near liquidation is read from it.
+//!
+//! Dashboards consume the serialized form: bucket labels and field names are stable, and
+//! buckets are always listed in the order above.
+
+use crate::models::UserPosition;
+use crate::sampling::{self, PositionSample};
+use serde::{Deserialize, Serialize};
+
+/// Label of the bucket of liquidatable positions
+pub const LIQUIDATABLE: &str = "<1.0";
+
+/// Label of the bucket of positions without a borrow
+pub const NO_BORROW: &str = "no borrow";
+
+/// Tracked positions with a health factor in one range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthFactorBucket {
+    /// "<1.0", "1.0-1.05", …, "2.0+" or "no borrow"
+    pub label: String,
+    /// Lowest health factor in the bucket; `None` for "<1.0" and "no borrow"
+    pub min_health_factor: Option<f64>,
+    /// Health factor the bucket stops below; `None` for the last range and "no borrow"
+    pub max_health_factor: Option<f64>,
+    pub positions: usize,
+    /// Borrow of those positions, in USD
+    pub borrow_usd: f64,
+}
+
+/// Tracked positions of a market by health factor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthFactorDistribution {
+    pub buckets: Vec<HealthFactorBucket>,
+}
+
+impl HealthFactorDistribution {
+    /// Distribution of `positions` over buckets split at 1 and at each of `edges`; edges at or
+    /// below 1 are left out
+    pub fn new(positions: &[UserPosition], edges: &[f64]) -> Self {
+        Self::estimated(positions, edges, None)
+    }
+
+    /// Distribution as in `new`, extrapolated to all borrowers when `positions` were read from
+    /// `sample`
+    ///
+    /// Accounts without a borrow are not sampled, so in sampled markets the "no borrow" bucket
+    /// only counts the ones read.
+    pub fn estimated(positions: &[UserPosition], edges: &[f64], sample: Option<&PositionSample>) -> Self {
+        let edges: Vec<f64> = edges.iter().copied().filter(|&edge| edge > 1.0).collect();
+        let lower = std::iter::once(None).chain(std::iter::once(1.0).chain(edges.iter().copied()).map(Some));
+        let upper = std::iter::once(1.0).chain(edges.iter().copied()).map(Some).chain(std::iter::once(None));
+        let mut buckets: Vec<HealthFactorBucket> = lower
+            .zip(upper)
+            .map(|(min_health_factor, max_health_factor)| HealthFactorBucket {
+                label: label(min_health_factor, max_health_factor),
+                min_health_factor,
+                max_health_factor,
+                positions: 0,
+                borrow_usd: 0.0,
+            })
+            .collect();
+        buckets.push(HealthFactorBucket {
+            label: NO_BORROW.to_string(),
+            min_health_factor: None,
+            max_health_factor: None,
+            positions: 0,
+            borrow_usd: 0.0,
+        });
+
+        let no_borrow = buckets.len() - 1;
+        let bucket_of = |position: &UserPosition| {
+            if position.total_borrow_value <= 0.0 {
+                no_borrow
+            } else if position.health_factor < 1.0 {
+                0
+            } else {
+                1 + edges.iter().take_while(|&&edge| position.health_factor >= edge).count()
+            }
+        };
+        for (index, bucket) in buckets.iter_mut().enumerate() {
+            let count = sampling::total(sample, positions, |p| if bucket_of(p) == index { 1.0 } else { 0.0 });
+            let borrow = sampling::total(sample, positions, |p| if bucket_of(p) == index { p.total_borrow_value } else { 0.0 });
+            bucket.positions = count.value.round() as usize;
+            bucket.borrow_usd = borrow.value;
+        }
+        Self { buckets }
+    }
+
+    /// Borrowing positions, over every bucket but "no borrow"
+    pub fn borrowing_positions(&self) -> usize {
+        self.borrowing().map(|bucket| bucket.positions).sum()
+    }
+
+    /// Borrow of the buckets wholly below `health_factor`: all borrow below it when it is 1
+    /// or one of the edges
+    pub fn borrow_below(&self, health_factor: f64) -> f64 {
+        self.borrowing()
+            .filter(|bucket| bucket.max_health_factor.is_some_and(|max| max <= health_factor + 1e-9))
+            .fold(0.0, |sum, bucket| sum + bucket.borrow_usd)
+    }
+
+    fn borrowing(&self) -> impl Iterator<Item = &HealthFactorBucket> {
+        self.buckets.iter().filter(|bucket| bucket.label != NO_BORROW)
+    }
+}
+
+/// "<1.0", "1.0-1.05" or "2.0+"
+fn label(min: Option<f64>, max: Option<f64>) -> String {
+    match (min, max) {
+        (None, _) => LIQUIDATABLE.to_string(),
+        (Some(min), Some(max)) => format!("{}-{}", edge(min), edge(max)),
+        (Some(min), None) => format!("{}+", edge(min)),
+    }
+}
+
+/// `value` with at least one decimal, e.g. "1.0" and "1.05"
+fn edge(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.1}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+    use std::collections::HashMap;
+
+    fn position(borrow: f64, health_factor: f64) -> UserPosition {
+        UserPosition {
+            address: Address::random(),
+            base_balance: -borrow,
+            collateral_balances: HashMap::new(),
+            total_collateral_value: borrow * health_factor,
+            total_borrow_value: borrow,
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        }
+    }
+
+    #[test]
+    fn test_positions_fall_into_labeled_buckets() {
+        let positions = [
+            position(500.0, 0.9),
+            position(1_000.0, 1.0),
+            position(2_000.0, 1.08),
+            position(4_000.0, 1.1),
+            position(8_000.0, 3.5),
+            position(0.0, f64::INFINITY),
+        ];
+        let distribution = HealthFactorDistribution::new(&positions, &[1.1, 1.5]);
+        let buckets: Vec<(&str, usize, f64)> =
+            distribution.buckets.iter().map(|b| (b.label.as_str(), b.positions, b.borrow_usd)).collect();
+        assert_eq!(
+            buckets,
+            [
+                ("<1.0", 1, 500.0),
+                ("1.0-1.1", 2, 3_000.0),
+                ("1.1-1.5", 1, 4_000.0),
+                ("1.5+", 1, 8_000.0),
+                ("no borrow", 1, 0.0),
+            ]
+        );
+        assert_eq!(distribution.borrowing_positions(), 5);
+        assert_eq!(distribution.borrow_below(1.0), 500.0);
+        assert_eq!(distribution.borrow_below(1.0 + 0.1), 3_500.0);
+
+        // Field names and bucket shape are what dashboards read
+        assert_eq!(
+            serde_json::to_value(&distribution.buckets[1]).unwrap(),
+            serde_json::json!({
+                "label": "1.0-1.1",
+                "min_health_factor": 1.0,
+                "max_health_factor": 1.1,
+                "positions": 2,
+                "borrow_usd": 3000.0,
+            })
+        );
+        let empty = HealthFactorDistribution::new(&[], &[0.5, 2.0]);
+        let labels: Vec<&str> = empty.buckets.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, ["<1.0", "1.0-2.0", "2.0+", "no borrow"]);
+    }
+}
@@ -0,0 +1,276 @@
+//! Known incidents the checks must keep detecting
+//!
+//! Each file of `fixtures/incidents/` is a fixture, loadable by `FixtureProvider::from_file`
+//! like any other, of a market in a canonical bad state: utilization past the kink, an oracle
+//! gone stale, a whale borrower, an underwater account, a depegged stablecoin, a watchlist
+//! account borrowing more against less, a whale draining a busy market, collateral delisted
+//! under its holders, absorbed collateral nobody buys back. Next to the fixture data it names
+//! the checks it exercises and the findings those must produce, by category and lowest
+//! severity; an incident about a change between assessments also has the snapshot `before` it,
+//! and one about base flows the flows as the borrower index keeps them. `Incident::replay` runs
+//! `RiskProcessor::assess_market` on it with the default configuration, so a change to a check
+//! that stops it detecting a known state fails the tests. The incidents are compiled into the
+//! binary, where `risk-engine-cli selftest` replays them on a deployed build. Every built-in
+//! check ships at least one incident, apart from those listed in `WITHOUT_INCIDENT`.
+
+use crate::collateral_changes::CollateralParameters;
+use crate::config::{Config, SettingOverride};
+use crate::error::{Result, RiskEngineError};
+use crate::provider::{FixtureData, FixtureProvider, MarketDataProvider};
+use crate::position_changes::PositionHistory;
+use crate::risk::{RiskAssessment, RiskCategory, RiskProcessor, RiskSeverity};
+use crate::scanner::BaseFlow;
+use crate::version;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Incident fixtures compiled into the crate, by file name
+pub const BUNDLED_INCIDENTS: &[(&str, &str)] = &[
+    ("near-kink.json", include_str!("../fixtures/incidents/near-kink.json")),
+    ("stale-oracle.json", include_str!("../fixtures/incidents/stale-oracle.json")),
+    ("whale-borrower.json", include_str!("../fixtures/incidents/whale-borrower.json")),
+    ("underwater-account.json", include_str!("../fixtures/incidents/underwater-account.json")),
+    ("depegged-stable.json", include_str!("../fixtures/incidents/depegged-stable.json")),
+    ("watchlist-position-change.json", include_str!("../fixtures/incidents/watchlist-position-change.json")),
+    ("whale-outflow.json", include_str!("../fixtures/incidents/whale-outflow.json")),
+    ("collateral-delisting.json", include_str!("../fixtures/incidents/collateral-delisting.json")),
+    ("unsold-collateral.json", include_str!("../fixtures/incidents/unsold-collateral.json")),
+];
+
+/// Built-in checks that predate the incident suite and have no incident yet; new checks are
+/// not to be added here but to ship an incident of their own
+pub const WITHOUT_INCIDENT: &[&str] = &[
+    "sequencer",
+    "planned_withdrawals",
+    "asset_settings",
+    "rewards_runway",
+    "absorption",
+    "liquidator_margin",
+    "collateral_provenance",
+    "configuration_drift",
+    "manager_permissions",
+];
+
+/// Finding an incident must produce
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Expectation {
+    pub category: RiskCategory,
+    /// Lowest severity that satisfies the expectation
+    pub min_severity: RiskSeverity,
+}
+
+/// `HighUtilization at High or above`
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at {:?} or above", self.category, self.min_severity)
+    }
+}
+
+/// A market in a known bad state, with the findings assessing it must produce
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Incident {
+    pub name: String,
+    pub description: String,
+    /// Built-in checks, by the names of `version::CHECK_VERSIONS`, the incident exercises
+    pub checks: Vec<String>,
+    /// Accounts assessed as the `watchlist`; every position of the fixture is tracked as if
+    /// in the borrower index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watchlist: Vec<String>,
+    /// Settings over the default configuration, as `--set` takes them
+    /// (`risk.position_changes.findings=true`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub settings: Vec<String>,
+    /// Base flows of the market, as if in the borrower index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub base_flows: Vec<BaseFlow>,
+    pub expect: Vec<Expectation>,
+    /// Snapshot assessed first, for checks comparing an assessment with the previous one;
+    /// its findings are not counted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<FixtureData>,
+    /// The snapshot itself, at the top level so the file loads as a plain fixture
+    #[serde(flatten)]
+    pub fixture: FixtureData,
+}
+
+impl Incident {
+    /// Parse the incident in `json`; `source` names it in errors
+    pub fn from_json(source: &str, json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| RiskEngineError::serialization(format!("incident {}", source), e))
+    }
+
+    /// Assess every market of the fixture, after those of `before`, and compare the findings
+    /// with `expect`
+    pub async fn replay(&self) -> Result<IncidentOutcome> {
+        let overrides = self.settings.iter().map(|setting| setting.parse()).collect::<Result<Vec<SettingOverride>>>()?;
+        let config = Arc::new(Config { watchlist: self.watchlist.clone(), ..Config::default() }.with_overrides(&overrides)?);
+        let history = Arc::new(PositionHistory::new());
+        if let Some(before) = &self.before {
+            assess(&config, &history, before, &[], None).await?;
+        }
+        let found = assess(&config, &history, &self.fixture, &self.base_flows, self.before.as_ref())
+            .await?
+            .iter()
+            .flat_map(|assessment| assessment.findings.iter().map(|finding| (finding.category.clone(), finding.severity)))
+            .collect::<Vec<_>>();
+        let missing = self
+            .expect
+            .iter()
+            .filter(|expected| !found.iter().any(|(category, severity)| *category == expected.category && *severity >= expected.min_severity))
+            .cloned()
+            .collect();
+        Ok(IncidentOutcome { name: self.name.clone(), description: self.description.clone(), expected: self.expect.clone(), found, missing })
+    }
+}
+
+/// Assessments of every market of `fixture`, tracking each position and any `flows` as if
+/// indexed, with the collateral parameters of the same market in `before` as if stored
+async fn assess(
+    config: &Arc<Config>,
+    history: &Arc<PositionHistory>,
+    fixture: &FixtureData,
+    flows: &[BaseFlow],
+    before: Option<&FixtureData>,
+) -> Result<Vec<RiskAssessment>> {
+    let accounts: Vec<_> = fixture.positions.iter().map(|entry| entry.position.address).collect();
+    let provider = Arc::new(FixtureProvider::new(fixture.clone()));
+    let mut assessments = Vec::new();
+    for market in provider.get_markets().await? {
+        let mut processor = RiskProcessor::with_provider(config.clone(), provider.clone())
+            .with_accounts(accounts.clone())
+            .with_position_history(history.clone());
+        if !flows.is_empty() {
+            processor = processor.with_base_flows(flows.to_vec());
+        }
+        let previous = before.and_then(|before| before.markets.iter().find(|m| m.comet_address == market.comet_address));
+        if let Some(previous) = previous {
+            processor = processor.with_previous_collateral(CollateralParameters::of(previous));
+        }
+        assessments.push(processor.assess_market(&market).await?);
+    }
+    Ok(assessments)
+}
+
+/// The incidents of `BUNDLED_INCIDENTS`
+pub fn bundled() -> Vec<Incident> {
+    BUNDLED_INCIDENTS
+        .iter()
+        .map(|(file, json)| Incident::from_json(file, json).expect("bundled incident is valid JSON"))
+        .collect()
+}
+
+/// Findings an incident produced against those it must
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncidentOutcome {
+    pub name: String,
+    pub description: String,
+    pub expected: Vec<Expectation>,
+    /// Category and severity of every finding of the assessment
+    pub found: Vec<(RiskCategory, RiskSeverity)>,
+    /// Expectations no finding met
+    pub missing: Vec<Expectation>,
+}
+
+impl IncidentOutcome {
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// `near-kink: HighUtilization at High or above`, then what is missing if it failed
+impl fmt::Display for IncidentOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected: Vec<String> = self.expected.iter().map(ToString::to_string).collect();
+        write!(f, "{}: {}", self.name, expected.join(", "))?;
+        if !self.passed() {
+            let missing: Vec<String> = self.missing.iter().map(ToString::to_string).collect();
+            let found: Vec<String> = self.found.iter().map(|(category, severity)| format!("{:?} {:?}", severity, category)).collect();
+            let found = if found.is_empty() { "nothing".to_string() } else { found.join(", ") };
+            write!(f, "; missing {} (found {})", missing.join(", "), found)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of replaying every bundled incident on this build
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelfTest {
+    pub engine_version: String,
+    pub incidents: Vec<IncidentOutcome>,
+}
+
+impl SelfTest {
+    /// Replay the bundled incidents; an incident that fails to assess is an error
+    pub async fn run() -> Result<Self> {
+        let mut incidents = Vec::new();
+        for incident in bundled() {
+            incidents.push(incident.replay().await?);
+        }
+        Ok(Self { engine_version: version::ENGINE_VERSION.to_string(), incidents })
+    }
+
+    pub fn passed(&self) -> bool {
+        self.incidents.iter().all(IncidentOutcome::passed)
+    }
+
+    /// Incidents whose expectations were not met
+    pub fn failures(&self) -> impl Iterator<Item = &IncidentOutcome> {
+        self.incidents.iter().filter(|outcome| !outcome.passed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::bundled_fixture;
+
+    #[tokio::test]
+    async fn test_every_incident_is_still_detected() {
+        let report = SelfTest::run().await.unwrap();
+        assert_eq!(report.incidents.len(), BUNDLED_INCIDENTS.len());
+        let failures: Vec<String> = report.failures().map(ToString::to_string).collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+
+        // Each file is also a plain fixture
+        for (file, _) in BUNDLED_INCIDENTS {
+            let provider = FixtureProvider::from_file(&bundled_fixture(&format!("incidents/{}", file))).unwrap();
+            assert!(!provider.data().markets.is_empty(), "{}", file);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unmet_expectations_are_reported() {
+        let (file, json) = BUNDLED_INCIDENTS[0];
+        let mut incident = Incident::from_json(file, json).unwrap();
+        let unmet = Expectation { category: RiskCategory::BadDebt, min_severity: RiskSeverity::Critical };
+        incident.expect.push(unmet.clone());
+
+        let outcome = incident.replay().await.unwrap();
+        assert!(!outcome.passed());
+        assert_eq!(outcome.missing, [unmet]);
+        let shown = outcome.to_string();
+        assert!(shown.contains("; missing BadDebt at Critical or above (found High HighUtilization"), "{}", shown);
+    }
+
+    #[test]
+    fn test_every_check_ships_an_incident() {
+        let incidents = bundled();
+        for incident in &incidents {
+            for check in &incident.checks {
+                assert_ne!(version::check_version(check), 0, "{} names unknown check {}", incident.name, check);
+            }
+        }
+        for (check, _) in version::CHECK_VERSIONS {
+            if version::HISTORY_CHECKS.contains(check) || WITHOUT_INCIDENT.contains(check) {
+                continue;
+            }
+            assert!(
+                incidents.iter().any(|incident| incident.checks.iter().any(|c| c == check)),
+                "check {} has no incident in fixtures/incidents",
+                check
+            );
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! What the engine monitors: the markets it reads, and Comet deployments it does not
+//!
+//! `discover` is best effort. It combines the Comets `registry` knows for the chain with those
+//! the Configurator set up since `scanner.start_block`, and records what it could not read
+//! instead of failing.
+
+use crate::config::{Config, Network};
+use crate::error::{Result, RiskEngineError};
+use crate::models::Market;
+use crate::progress::{ProgressReporter, Stage};
+use crate::registry;
+use crate::scanner::LogSource;
+use crate::utils::parse_address;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Where the market data shown came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataOrigin {
+    /// Read from chain for this listing
+    Live,
+    /// Served from the background refresh's cache
+    Cache,
+    Subgraph,
+    /// The bundled demo dataset or a fixture file
+    Mock,
+}
+
+/// A market the engine assesses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketListing {
+    pub chain_id: u64,
+    /// The chain's name, if it is a `Network`
+    pub network: Option<Network>,
+    pub name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub comet_address: Address,
+    pub base_symbol: String,
+    pub collaterals: usize,
+    pub utilization_rate: f64,
+    /// Total base supplied, in USD
+    pub tvl_usd: f64,
+    pub origin: DataOrigin,
+}
+
+impl MarketListing {
+    pub fn new(config: &Config, market: &Market, origin: DataOrigin) -> Self {
+        Self {
+            chain_id: config.compound.chain_id,
+            network: Network::from_chain_id(config.compound.chain_id),
+            name: market.name.clone(),
+            comet_address: market.comet_address,
+            base_symbol: market.base_asset.symbol.clone(),
+            collaterals: market.collateral_assets.len(),
+            utilization_rate: market.utilization(),
+            tvl_usd: market.total_supply * market.base_asset.price,
+            origin,
+        }
+    }
+}
+
+/// How a deployment was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentSource {
+    /// Listed by `registry::known_markets`
+    Registry,
+    /// Set up by the configured Configurator (`SetFactory` events)
+    ConfiguratorEvents,
+}
+
+/// A Comet deployment on the configured chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deployment {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub comet_address: Address,
+    /// Base asset, when the registry knows it
+    pub base_symbol: Option<String>,
+    pub source: DeploymentSource,
+}
+
+/// Outcome of `discover`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Discovery {
+    /// Deployments the engine does not assess, by address
+    pub unmonitored: Vec<Deployment>,
+    /// Last block of Configurator events read, if any were
+    pub scanned_through: Option<u64>,
+    /// What could not be read; the deployments found before are still listed
+    pub errors: Vec<String>,
+}
+
+/// Comet deployments on the chain of `config` that are not in `monitored`
+///
+/// Configurator events are read through `source` in `scanner.chunk_size` blocks, reporting to
+/// `progress`; a failed read or `cancel` ends the scan with an entry in `errors`.
+pub async fn discover(
+    config: &Config,
+    source: Option<Arc<dyn LogSource>>,
+    monitored: &[Address],
+    cancel: &CancellationToken,
+    progress: &ProgressReporter,
+) -> Discovery {
+    let mut found = BTreeMap::new();
+    let mut discovery = Discovery::default();
+    for known in registry::known_markets(config.compound.chain_id) {
+        let address = parse_address(known.comet).expect("registry addresses are valid");
+        found.insert(address, Deployment { comet_address: address, base_symbol: Some(known.base_symbol.to_string()), source: DeploymentSource::Registry });
+    }
+    if found.is_empty() {
+        discovery.errors.push(format!("registry: no known deployments on chain {}", config.compound.chain_id));
+    }
+
+    match scan_configurator(config, source, cancel, progress, &mut discovery).await {
+        Ok(comets) => {
+            for address in comets {
+                found.entry(address).or_insert(Deployment { comet_address: address, base_symbol: None, source: DeploymentSource::ConfiguratorEvents });
+            }
+        }
+        Err(e) => discovery.errors.push(format!("Configurator events: {}", e)),
+    }
+
+    discovery.unmonitored = found.into_values().filter(|d| !monitored.contains(&d.comet_address)).collect();
+    discovery
+}
+
+/// Comets set up by the configured Configurator from `scanner.start_block`; a scan cut short
+/// records its error and returns what it found until then
+async fn scan_configurator(
+    config: &Config,
+    source: Option<Arc<dyn LogSource>>,
+    cancel: &CancellationToken,
+    progress: &ProgressReporter,
+    discovery: &mut Discovery,
+) -> Result<Vec<Address>> {
+    let source = source.ok_or_else(|| RiskEngineError::Unavailable {
+        what: "event scan",
+        reason: "the data source cannot read chain logs".to_string(),
+    })?;
+    let configurator = parse_address(&config.compound.configurator_address)?;
+    let head = source.head_block().await?;
+    let start = config.scanner.start_block;
+    let chunk_size = config.scanner.chunk_size.max(1);
+    let task = progress.start(Stage::MarketDiscovery, (head + 1).saturating_sub(start));
+
+    let mut comets = Vec::new();
+    let mut from = start;
+    while from <= head {
+        let to = (from + chunk_size - 1).min(head);
+        let result = tokio::select! {
+            _ = cancel.cancelled() => Err(RiskEngineError::Cancelled),
+            result = source.comets_configured_in_range(configurator, from, to) => result,
+        };
+        match result {
+            Ok(found) => comets.extend(found),
+            Err(e) => {
+                discovery.errors.push(format!("Configurator events: blocks {}-{}: {}", from, to, e));
+                break;
+            }
+        }
+        discovery.scanned_through = Some(to);
+        task.set(to + 1 - start);
+        from = to + 1;
+    }
+    Ok(comets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Configurator that set up one Comet at block 150, failing reads past `fail_from`
+    struct Configurator {
+        fail_from: u64,
+    }
+
+    #[async_trait]
+    impl LogSource for Configurator {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(299)
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, _from: u64, _to: u64) -> Result<Vec<Address>> {
+            Ok(Vec::new())
+        }
+
+        async fn comets_configured_in_range(&self, _configurator: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+            if to >= self.fail_from {
+                return Err(RiskEngineError::Unavailable { what: "logs", reason: "rate limited".to_string() });
+            }
+            Ok(if (from..=to).contains(&150) { vec![Address::repeat_byte(0x15)] } else { Vec::new() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_discovery_lists_unmonitored_deployments() {
+        let mut config = Config::default();
+        config.scanner.chunk_size = 100;
+        let usdc = parse_address(&config.compound.comet_proxy_address).unwrap();
+        let discover_with = |fail_from: u64| {
+            let config = config.clone();
+            async move {
+                let source: Arc<dyn LogSource> = Arc::new(Configurator { fail_from });
+                discover(&config, Some(source), &[usdc], &CancellationToken::new(), &ProgressReporter::new()).await
+            }
+        };
+
+        let discovery = discover_with(u64::MAX).await;
+        let listed: Vec<(Option<&str>, DeploymentSource)> =
+            discovery.unmonitored.iter().map(|d| (d.base_symbol.as_deref(), d.source)).collect();
+        assert_eq!(
+            listed,
+            [
+                (None, DeploymentSource::ConfiguratorEvents),
+                (Some("USDT"), DeploymentSource::Registry),
+                (Some("wstETH"), DeploymentSource::Registry),
+                (Some("WETH"), DeploymentSource::Registry),
+            ]
+        );
+        assert_eq!((discovery.scanned_through, discovery.errors.len()), (Some(299), 0));
+
+        // A failed read keeps what was found before it
+        let discovery = discover_with(200).await;
+        assert_eq!(discovery.unmonitored.len(), 4);
+        assert_eq!(discovery.scanned_through, Some(199));
+        assert!(discovery.errors[0].contains("blocks 200-299: logs is unavailable: rate limited"), "{:?}", discovery.errors);
+
+        // Without chain logs only the registry is consulted
+        let discovery = discover(&config, None, &[usdc], &CancellationToken::new(), &ProgressReporter::new()).await;
+        assert_eq!(discovery.unmonitored.len(), 3);
+        assert!(discovery.errors[0].contains("cannot read chain logs"), "{:?}", discovery.errors);
+    }
+}
@@ -0,0 +1,177 @@
+//! Address book: human-readable labels for addresses in reports
+//!
+//! The Compound deployments in `registry` are labeled out of the box; the file named by
+//! `address_book` adds entries and overrides built-in ones. The engine installs the merged
+//! book when it starts, so `label_for` gives the CLI, reports and API consumers the same names.
+
+use crate::config::{Config, Network};
+use crate::error::{Result, RiskEngineError};
+use crate::registry::{self, KnownMarket};
+use crate::utils::parse_address;
+use ethers::types::Address;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Book installed by `install`; the built-in one until then
+static INSTALLED: RwLock<Option<Arc<AddressBook>>> = RwLock::new(None);
+
+/// Labels by address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressBook {
+    labels: HashMap<Address, String>,
+}
+
+impl AddressBook {
+    /// Labels of the Comets, Configurators and rewards contracts in the registry
+    pub fn builtin() -> Self {
+        Self::builtin_on(Network::Mainnet.chain_id())
+    }
+
+    /// Built-in labels, preferring the deployments on `chain_id` where chains reuse an address
+    ///
+    /// Comets deployed by the same account share addresses across chains: Base's USDbC Comet
+    /// is at the address of Arbitrum's USDC one.
+    pub fn builtin_on(chain_id: u64) -> Self {
+        let mut book = Self::default();
+        let (local, others): (Vec<&KnownMarket>, Vec<&KnownMarket>) =
+            registry::all().iter().partition(|known| known.network.chain_id() == chain_id);
+        for known in local.into_iter().chain(others) {
+            let suffix = if known.network == Network::Mainnet { String::new() } else { format!(" {:?}", known.network) };
+            book.insert_new(known.comet, format!("Comet {}{}", known.base_symbol, suffix));
+            book.insert_new(known.configurator, format!("Configurator{}", suffix));
+            book.insert_new(known.rewards, format!("Comet Rewards{}", suffix));
+        }
+        book
+    }
+
+    /// The built-in labels of the configured chain with the entries of `config.address_book`,
+    /// if set, taking precedence
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut book = Self::builtin_on(config.compound.chain_id);
+        if let Some(path) = &config.address_book {
+            book.extend(Self::from_file(path)?);
+        }
+        Ok(book)
+    }
+
+    /// Entries of a CSV file of `address,label` rows (a header row is optional) or, for any
+    /// other extension, a JSON object of labels by address
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        let invalid = |message: String| RiskEngineError::Parse {
+            what: "address book",
+            input: path.display().to_string(),
+            message,
+        };
+
+        let entries: Vec<(String, String)> = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+            let mut reader = csv::ReaderBuilder::new().has_headers(false).trim(csv::Trim::All).from_reader(text.as_bytes());
+            let mut entries = Vec::new();
+            for (i, record) in reader.records().enumerate() {
+                let record = record.map_err(|e| invalid(e.to_string()))?;
+                match (record.get(0), record.get(1)) {
+                    (Some(address), _) if i == 0 && address.eq_ignore_ascii_case("address") => {}
+                    (Some(address), Some(label)) => entries.push((address.to_string(), label.to_string())),
+                    _ => return Err(invalid(format!("line {}: expected `address,label`", i + 1))),
+                }
+            }
+            entries
+        } else {
+            let labels: HashMap<String, String> = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+            labels.into_iter().collect()
+        };
+
+        let mut book = Self::default();
+        for (address, label) in entries {
+            let address = parse_address(&address).map_err(|e| invalid(e.to_string()))?;
+            book.labels.insert(address, label);
+        }
+        Ok(book)
+    }
+
+    /// Add the entries of `other`, replacing labels of addresses already in the book
+    pub fn extend(&mut self, other: AddressBook) {
+        self.labels.extend(other.labels);
+    }
+
+    /// Label of `address`, if it has one
+    pub fn label(&self, address: &Address) -> Option<&str> {
+        self.labels.get(address).map(String::as_str)
+    }
+
+    /// Number of labeled addresses
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Whether no address is labeled
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Label a built-in address unless it already is
+    fn insert_new(&mut self, address: &str, label: String) {
+        let address = parse_address(address).expect("built-in addresses are valid");
+        self.labels.entry(address).or_insert(label);
+    }
+}
+
+/// Make `book` the one `label_for` reads, for the rest of the process
+pub fn install(book: AddressBook) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(book));
+}
+
+/// Label of `address` in the installed address book
+pub fn label_for(address: &Address) -> Option<String> {
+    static BUILTIN_BOOK: OnceLock<AddressBook> = OnceLock::new();
+    let installed = INSTALLED.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match installed {
+        Some(book) => book.label(address).map(str::to_string),
+        None => BUILTIN_BOOK.get_or_init(AddressBook::builtin).label(address).map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const COMET_USDC: &str = "0xc3d688B66703497DAA19211EEdff47f25384cdc3";
+
+    #[test]
+    fn test_builtin_book_labels_known_deployments() {
+        let comet = parse_address(COMET_USDC).unwrap();
+        assert_eq!(label_for(&comet).as_deref(), Some("Comet USDC"));
+        let base = parse_address("0xb125E6687d4313864e53df431d5425969c15Eb2F").unwrap();
+        assert_eq!(AddressBook::builtin().label(&base), Some("Comet USDC Base"));
+        let shared = parse_address("0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf").unwrap();
+        assert_eq!(AddressBook::builtin_on(8453).label(&shared), Some("Comet USDbC Base"));
+        assert_eq!(AddressBook::builtin_on(42161).label(&shared), Some("Comet USDC Arbitrum"));
+        assert_eq!(label_for(&Address::repeat_byte(0x42)), None);
+    }
+
+    #[test]
+    fn test_user_entries_override_builtin_ones() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("book.csv");
+        fs::write(&csv, format!("address,label\n{}, Main USDC market\n0x{}, Treasury\n", COMET_USDC, "42".repeat(20))).unwrap();
+        let json = dir.path().join("book.json");
+        fs::write(&json, format!(r#"{{ "0x{}": "Ops multisig" }}"#, "43".repeat(20))).unwrap();
+
+        let config = Config { address_book: Some(csv), ..Config::default() };
+        let book = AddressBook::from_config(&config).unwrap();
+        let comet = parse_address(COMET_USDC).unwrap();
+        assert_eq!(book.label(&comet), Some("Main USDC market"));
+        assert_eq!(book.label(&Address::repeat_byte(0x42)), Some("Treasury"));
+        assert_eq!(book.len(), AddressBook::builtin().len() + 1);
+
+        let book = AddressBook::from_file(&json).unwrap();
+        assert_eq!(book.label(&Address::repeat_byte(0x43)), Some("Ops multisig"));
+
+        fs::write(&json, r#"{ "treasury": "Treasury" }"#).unwrap();
+        let err = AddressBook::from_file(&json).unwrap_err().to_string();
+        assert!(err.starts_with("failed to parse address book"), "{}", err);
+    }
+}
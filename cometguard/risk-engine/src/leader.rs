@@ -0,0 +1,209 @@
+//! Leader election between engines sharing one PostgreSQL history database
+//!
+//! Engines run in pairs for redundancy would otherwise both alert on every finding and both
+//! write it to the shared history. With `storage.postgres.leader_election` enabled they take
+//! turns holding a named lease in the database: the holder sends alerts and writes history,
+//! the others keep assessing and serving the API as standbys, and `/healthz` says which is
+//! which. The holder renews the lease every `heartbeat_secs`; when it stops, the lease
+//! expires `lease_secs` after its last renewal and the next standby heartbeat takes it over.
+//!
+//! Expiry is decided by the database's clock alone, so the engines' clocks may disagree. An
+//! engine counts its lease from before it asked for it, on its own monotonic clock, and stops
+//! acting as leader one heartbeat before that count runs out: by the time a standby can take
+//! the lease, the old leader has already stepped down unless it stalled mid-heartbeat. A
+//! brief overlap is still possible after a stall; alert dedup keys make the double sends
+//! harmless. A failed heartbeat keeps the current role until the lease runs out locally.
+
+use crate::error::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Whether an engine holds the lease
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Sends alerts and writes history
+    Leader,
+    /// Assesses and serves the API only
+    Standby,
+}
+
+/// Role of an engine as shown by `/healthz`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderStatus {
+    pub role: Role,
+    /// This engine's name in the lease table
+    pub holder: String,
+    /// Term of the lease this engine holds; terms count up with every change of leader
+    pub term: Option<u64>,
+}
+
+/// Leases engines elect a leader with, kept where all of them can reach
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Name of this engine in the store, unique among the engines sharing it
+    fn holder(&self) -> &str;
+
+    /// Take the lease `name` if it is free or expired, or renew it if this engine holds it,
+    /// for `lease` as measured by the store's clock; `Some(term)` if this engine holds it now
+    async fn acquire(&self, name: &str, lease: Duration) -> Result<Option<u64>>;
+
+    /// Give up the lease `name` if this engine holds it, so a standby can take over at once
+    async fn release(&self, name: &str) -> Result<()>;
+}
+
+/// Lease held, and until when this engine may act on it
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    term: u64,
+    until: Instant,
+}
+
+/// This engine's side of the election: its role, renewed by `heartbeat`
+pub struct Leadership {
+    leases: Arc<dyn LeaseStore>,
+    name: String,
+    lease: Duration,
+    heartbeat: Duration,
+    held: StdMutex<Option<Held>>,
+}
+
+impl Leadership {
+    /// Election for the lease `name` in `leases`, renewed every `heartbeat` for `lease`;
+    /// standby until the first heartbeat
+    pub fn new(leases: Arc<dyn LeaseStore>, name: impl Into<String>, lease: Duration, heartbeat: Duration) -> Self {
+        Self { leases, name: name.into(), lease, heartbeat, held: StdMutex::new(None) }
+    }
+
+    pub fn role(&self) -> Role {
+        if self.current().is_some() {
+            Role::Leader
+        } else {
+            Role::Standby
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role() == Role::Leader
+    }
+
+    pub fn status(&self) -> LeaderStatus {
+        let held = self.current();
+        LeaderStatus {
+            role: if held.is_some() { Role::Leader } else { Role::Standby },
+            holder: self.leases.holder().to_string(),
+            term: held.map(|held| held.term),
+        }
+    }
+
+    /// The lease, while this engine may still act on it
+    fn current(&self) -> Option<Held> {
+        self.held.lock().unwrap_or_else(|e| e.into_inner()).filter(|held| held.until > Instant::now())
+    }
+
+    /// Take or renew the lease, returning the role that follows
+    pub async fn heartbeat(&self) -> Role {
+        // Counted from before the request, so the lease never outlasts the store's
+        let asked = Instant::now();
+        let before = self.role();
+        match self.leases.acquire(&self.name, self.lease).await {
+            Ok(Some(term)) => {
+                let until = asked + self.lease.saturating_sub(self.heartbeat);
+                *self.held.lock().unwrap_or_else(|e| e.into_inner()) = Some(Held { term, until });
+            }
+            Ok(None) => *self.held.lock().unwrap_or_else(|e| e.into_inner()) = None,
+            Err(e) => warn!("Could not renew the `{}` lease as {}: {}", self.name, self.leases.holder(), e),
+        }
+        let role = self.role();
+        match (before, role) {
+            (Role::Standby, Role::Leader) => info!("{} now leads `{}`: sending alerts and writing history", self.leases.holder(), self.name),
+            (Role::Leader, Role::Standby) => info!("{} is now a standby of `{}`", self.leases.holder(), self.name),
+            _ => debug!("{} remains {:?} of `{}`", self.leases.holder(), role, self.name),
+        }
+        role
+    }
+
+    /// Give up the lease, so a standby takes over without waiting for it to expire
+    pub async fn step_down(&self) {
+        let held = self.held.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if held.is_some() {
+            if let Err(e) = self.leases.release(&self.name).await {
+                warn!("Could not release the `{}` lease; it expires in at most {:?}: {}", self.name, self.lease, e);
+            }
+        }
+    }
+
+    /// Renew the lease every `heartbeat` in the background, until the handle is shut down or
+    /// the engine dropped; a dropped engine leaves the lease to expire, like a crashed one
+    pub fn spawn(self: &Arc<Self>) -> LeaderHeartbeat {
+        let (stop, mut stop_rx) = watch::channel(false);
+        let leadership = Arc::downgrade(self);
+        let interval = self.heartbeat;
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = stop_rx.changed() => break,
+                    _ = tokio::time::sleep(interval) => {}
+                }
+                let Some(leadership) = leadership.upgrade() else { break };
+                leadership.heartbeat().await;
+            }
+        });
+        LeaderHeartbeat { stop, handle }
+    }
+}
+
+/// Handle to a running heartbeat task
+pub struct LeaderHeartbeat {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl LeaderHeartbeat {
+    /// Signal the task to stop and wait for it to exit
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(e) = self.handle.await {
+            warn!("Leader heartbeat task ended abnormally: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryLeases;
+
+    #[tokio::test]
+    async fn test_standby_takes_over_once_the_leader_stops_renewing() {
+        let store = MemoryLeases::default();
+        let lease = Duration::from_millis(300);
+        let heartbeat = Duration::from_millis(100);
+        let a = Leadership::new(store.handle("a"), "cometguard", lease, heartbeat);
+        let b = Leadership::new(store.handle("b"), "cometguard", lease, heartbeat);
+        assert_eq!(a.role(), Role::Standby);
+
+        assert_eq!((a.heartbeat().await, b.heartbeat().await), (Role::Leader, Role::Standby));
+        assert_eq!(a.status().term, Some(1));
+        assert_eq!(b.status(), LeaderStatus { role: Role::Standby, holder: "b".to_string(), term: None });
+
+        // `a` stops renewing: it steps down a heartbeat before its lease expires in the
+        // store, so the two never lead at once
+        tokio::time::sleep(lease - heartbeat + Duration::from_millis(20)).await;
+        assert_eq!(a.role(), Role::Standby);
+        assert_eq!(b.heartbeat().await, Role::Standby);
+        tokio::time::sleep(heartbeat).await;
+        assert_eq!(b.heartbeat().await, Role::Leader);
+        assert_eq!(b.status().term, Some(2));
+        assert_eq!(a.heartbeat().await, Role::Standby);
+
+        // Stepping down hands the lease over without waiting for it to expire
+        b.step_down().await;
+        assert_eq!((b.role(), a.heartbeat().await), (Role::Standby, Role::Leader));
+    }
+}
@@ -1,60 +1,3044 @@
+pub mod absorb;
+pub mod absorption;
+pub mod alerts;
+pub mod annotations;
+pub mod archive;
+pub mod audit;
+pub mod baseline;
+pub mod budget;
+pub mod builder;
+pub mod cli;
+pub mod collateral_changes;
+pub mod compare;
 pub mod compound;
 pub mod config;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod denomination;
+pub mod diagnostics;
+pub mod ens;
+pub mod error;
+pub mod events;
+pub mod exposure;
+pub mod gas;
+pub mod health_factors;
+pub mod incidents;
+pub mod export;
+pub mod inventory;
+pub mod labels;
+pub mod leader;
+pub mod metrics;
 pub mod models;
+pub mod plan;
+pub mod position_changes;
+pub mod position_scan;
+pub mod progress;
+pub mod projection;
+pub mod protocol_collateral;
+pub mod provider;
+pub mod ranking;
+pub mod reconcile;
+pub mod refresh;
+pub mod registry;
+#[cfg(feature = "reload")]
+pub mod reload;
+pub mod report;
 pub mod risk;
+pub mod rpc;
+pub mod sampling;
+pub mod scanner;
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod storage;
+pub mod subgraph;
+pub mod summary;
+pub mod telemetry;
+pub mod trend;
+#[cfg(test)]
+mod testing;
 pub mod utils;
+pub mod version;
+pub mod watch;
+
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock as StdRwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+pub use builder::RiskEngineBuilder;
+pub use error::{Result, RiskEngineError};
+pub use events::RiskEvent;
+pub use provider::{FixtureProvider, MarketDataProvider, SharedProvider};
+pub use risk::AssessmentEvent;
+
+/// How long `shutdown` waits for queued history writes
+pub const STORAGE_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest to wait for the head block recorded in audit entries and saved results
+const HEAD_BLOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Share of the scheduler's interval a run may take before `run_scheduled` warns that runs
+/// are falling behind it
+pub const INTERVAL_WARNING_SHARE: f64 = 0.8;
+
+/// Configuration sections the data provider is built from; `reload_config` rebuilds it when one changes
+const PROVIDER_SECTIONS: &[&str] = &["compound", "data_source", "ens", "performance", "rpc", "scanner", "subgraph"];
+
+/// Configuration sections read once at startup; `reload_config` only warns about changes to them
+const RESTART_SECTIONS: &[&str] = &["alerts", "audit", "log_format", "log_level", "logging", "metrics", "server", "storage", "telemetry"];
+
+/// How `assess_risks_with` treats markets whose assessment failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Return the successful assessments alongside per-market error records
+    Partial,
+    /// Fail with an aggregate error if any market failed
+    Strict,
+}
+
+/// Record of a market whose assessment failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketError {
+    /// Market name
+    pub market_name: String,
+    /// Market address
+    pub market_address: Address,
+    /// Rendered error chain
+    pub error: String,
+}
+
+/// Result of assessing every market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssessmentRun {
+    /// Successful assessments, in market order
+    pub assessments: Vec<risk::RiskAssessment>,
+    /// Markets that could not be assessed, in market order
+    pub errors: Vec<MarketError>,
+    /// Rollup of the successful assessments
+    pub summary: summary::ProtocolRiskSummary,
+}
+
+/// Whether the engine's assessment data is recent enough to rely on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Data is younger than `server.max_data_age_secs`
+    Ok,
+    /// Nothing has been assessed or refreshed yet
+    Pending,
+    /// The newest data is older than `server.max_data_age_secs`
+    Stale,
+}
 
-use anyhow::Result;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+/// Freshness of the engine's data, as served by `GET /healthz` and shown by the dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub status: HealthStatus,
+    pub mock_data: bool,
+    /// Last successful background refresh
+    pub last_refresh: Option<DateTime<Utc>>,
+    /// Newest cached assessment
+    pub last_assessment: Option<DateTime<Utc>>,
+    /// Seconds since the newer of the two
+    pub data_age_secs: Option<i64>,
+    /// Markets with a cached assessment
+    pub markets_assessed: usize,
+    /// Borrower scan or position fetch in flight, if any
+    #[serde(default)]
+    pub progress: Option<progress::Progress>,
+    /// Whether this engine leads or stands by, with leader election enabled
+    #[serde(default)]
+    pub leadership: Option<leader::LeaderStatus>,
+}
+
+/// Outcome of the newest run of `run_scheduled`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub finished_at: DateTime<Utc>,
+    /// Markets assessed successfully
+    pub assessed: usize,
+    /// Markets that could not be assessed; a failure to list markets is recorded against the zero address
+    pub errors: Vec<MarketError>,
+    /// How long the run took, in milliseconds
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+impl RunSummary {
+    /// Whether the run took at least `INTERVAL_WARNING_SHARE` of `interval`, so that runs are
+    /// about to fall behind it
+    ///
+    /// Never true of back-to-back runs, with a zero interval.
+    pub fn nears_interval(&self, interval: Duration) -> bool {
+        !interval.is_zero() && self.duration_ms as f64 >= interval.as_millis() as f64 * INTERVAL_WARNING_SHARE
+    }
+}
+
+/// Aggregate error returned under `ErrorPolicy::Strict`
+#[derive(Debug, thiserror::Error)]
+#[error("{} of {} market assessments failed: {}", .errors.len(), .errors.len() + .partial.len(), summarize_errors(.errors))]
+pub struct AggregateAssessmentError {
+    /// The failed markets
+    pub errors: Vec<MarketError>,
+    /// Assessments that did succeed, so callers can still inspect them
+    pub partial: Vec<risk::RiskAssessment>,
+}
+
+fn summarize_errors(errors: &[MarketError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{} ({})", e.market_name, e.error))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
 
 /// Main RiskEngine type that orchestrates all risk assessment operations
 pub struct RiskEngine {
-    config: Arc<config::Config>,
-    compound: Arc<RwLock<compound::CompoundClient>>,
+    config: StdRwLock<Arc<config::Config>>,
+    /// Replaced by `reload_provider` and `reload_config`; the lock is only held to clone or
+    /// swap the `Arc`, never across an await
+    provider: refresh::ProviderSlot,
+    background_refresh: Mutex<Option<refresh::BackgroundRefresh>>,
+    last_refresh: refresh::LastRefresh,
+    events: broadcast::Sender<RiskEvent>,
+    cancel: CancellationToken,
+    metrics: Arc<metrics::Metrics>,
+    metrics_exporter: Mutex<Option<metrics::MetricsExporter>>,
+    storage: Option<Arc<dyn storage::Storage>>,
+    audit: Option<audit::AuditLog>,
+    subgraph: Mutex<Option<Arc<subgraph::SubgraphProvider>>>,
+    #[cfg(feature = "reload")]
+    config_watcher: Mutex<Option<reload::ConfigWatcher>>,
+    latest: Mutex<HashMap<Address, risk::RiskAssessment>>,
+    last_run: Mutex<Option<RunSummary>>,
+    progress: progress::ProgressReporter,
+    alert_state: Arc<alerts::AlertStateStore>,
+    /// Checks run after the built-in ones
+    checks: Vec<Arc<dyn risk::RiskCheck>>,
+    scorer: Arc<dyn risk::RiskScorer>,
+    /// Routes alerted on besides the configured ones
+    alert_routes: Vec<alerts::AlertRoute>,
+    /// Outcome of the latest deliveries per alert route, for `diagnostics`
+    deliveries: Arc<alerts::DeliveryLog>,
+    /// Watchlist positions as last assessed, for `RiskAssessment::position_changes`
+    position_history: Arc<position_changes::PositionHistory>,
+    /// Unit of each assessed market's amounts in `display.denomination`
+    units: Arc<denomination::MarketUnits>,
+    /// Election among engines sharing the history database; without it this engine leads
+    leadership: Option<Arc<leader::Leadership>>,
+    leader_heartbeat: Mutex<Option<leader::LeaderHeartbeat>>,
 }
 
 impl RiskEngine {
     /// Create a new RiskEngine instance with the provided configuration
+    ///
+    /// With `data_source = "mock"` the engine serves the bundled demo dataset instead of
+    /// reading from chain, and every assessment it produces is marked `mock_data`.
+    /// With a `storage` backend configured, every assessment is also written to it, and
+    /// with `audit.directory` set, to the audit log. `${NAME}` references in the configuration
+    /// are resolved and the result checked with `Config::validate` before anything is opened;
+    /// ENS names in the watchlist are resolved once the data provider is open. The address book
+    /// (`address_book` over the built-in labels) is installed for `labels::label_for`.
+    ///
+    /// Shorthand for `RiskEngine::builder().config(config).build()`; use the builder to bring
+    /// a provider, scorer, checks, storage or alert sinks of your own.
     pub async fn new(config: config::Config) -> Result<Self> {
-        let config = Arc::new(config);
-        let compound = Arc::new(RwLock::new(
-            compound::CompoundClient::new(config.clone()).await?,
+        Self::builder().config(config).build().await
+    }
+
+    /// Builder for an engine with injected components
+    pub fn builder() -> RiskEngineBuilder {
+        RiskEngineBuilder::new()
+    }
+
+    /// Create a RiskEngine reading from a custom data provider (e.g. `FixtureProvider`)
+    ///
+    /// The configured `storage` backend and audit log are not opened; attach them with
+    /// `with_storage` and `with_audit_log`.
+    pub fn with_provider(config: config::Config, provider: SharedProvider) -> Self {
+        let metrics = Arc::new(metrics::Metrics::new(config.compound.chain_id));
+        Self::assemble(Arc::new(config), provider, CancellationToken::new(), metrics)
+    }
+
+    fn assemble(
+        config: Arc<config::Config>,
+        provider: SharedProvider,
+        cancel: CancellationToken,
+        metrics: Arc<metrics::Metrics>,
+    ) -> Self {
+        Self {
+            provider: Arc::new(StdRwLock::new(provider)),
+            background_refresh: Mutex::new(None),
+            last_refresh: Default::default(),
+            events: broadcast::channel(events::EVENT_CHANNEL_CAPACITY).0,
+            cancel,
+            metrics,
+            metrics_exporter: Mutex::new(None),
+            storage: None,
+            audit: None,
+            subgraph: Mutex::new(None),
+            #[cfg(feature = "reload")]
+            config_watcher: Mutex::new(None),
+            latest: Mutex::new(HashMap::new()),
+            last_run: Mutex::new(None),
+            progress: progress::ProgressReporter::new(),
+            alert_state: Arc::new(alerts::AlertStateStore::new(config.alerts.state_path.clone())),
+            checks: Vec::new(),
+            scorer: Arc::new(risk::RiskScore::of),
+            alert_routes: Vec::new(),
+            deliveries: Arc::new(alerts::DeliveryLog::new()),
+            position_history: Arc::new(position_changes::PositionHistory::new()),
+            units: Arc::new(denomination::MarketUnits::new()),
+            leadership: None,
+            leader_heartbeat: Mutex::new(None),
+            config: StdRwLock::new(config),
+        }
+    }
+
+    /// Write every assessment to `storage`
+    pub fn with_storage(mut self, storage: Arc<dyn storage::Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Send alerts and write history only while `leadership` says this engine leads
+    ///
+    /// Renew the lease with `start_leader_heartbeat`; until the first renewal the engine is a
+    /// standby.
+    pub fn with_leadership(mut self, leadership: Arc<leader::Leadership>) -> Self {
+        self.leadership = Some(leadership);
+        self
+    }
+
+    /// Whether this engine sends alerts and writes history: it holds the lease, or takes part
+    /// in no election
+    pub fn is_leader(&self) -> bool {
+        self.leadership.as_ref().is_none_or(|leadership| leadership.is_leader())
+    }
+
+    /// Role of this engine in the leader election, if it takes part in one
+    pub fn leader_status(&self) -> Option<leader::LeaderStatus> {
+        self.leadership.as_ref().map(|leadership| leadership.status())
+    }
+
+    /// Renew the leader lease every `heartbeat_secs` in the background, after taking or
+    /// renewing it once
+    ///
+    /// Does nothing without leader election, and fails if the heartbeat is already running.
+    pub async fn start_leader_heartbeat(&self) -> Result<()> {
+        let Some(leadership) = &self.leadership else { return Ok(()) };
+        if self.leader_heartbeat.lock().unwrap_or_else(|e| e.into_inner()).is_some() {
+            return Err(RiskEngineError::config("storage.postgres.leader_election", "the leader heartbeat is already running"));
+        }
+        leadership.heartbeat().await;
+        let mut slot = self.leader_heartbeat.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            *slot = Some(leadership.spawn());
+        }
+        Ok(())
+    }
+
+    /// Append every assessment to `audit`
+    pub fn with_audit_log(mut self, audit: audit::AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Audit log, if one is attached
+    pub fn audit_log(&self) -> Option<&audit::AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// Subgraph data source, if the configuration reads anything from it
+    pub fn subgraph(&self) -> Option<Arc<subgraph::SubgraphProvider>> {
+        self.subgraph.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Assessment history backend, if one is attached
+    pub fn storage(&self) -> Option<&Arc<dyn storage::Storage>> {
+        self.storage.as_ref()
+    }
+
+    /// The data provider currently in use
+    ///
+    /// Each assessment run takes the provider once when it starts, so a run in progress keeps
+    /// reading from the provider it started with when `reload_provider` replaces it.
+    pub fn provider(&self) -> SharedProvider {
+        self.provider.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Rebuild the data provider from the configuration in effect, dropping its cache
+    ///
+    /// Assessments started afterwards read through the new provider. A provider injected with
+    /// `RiskEngineBuilder::provider` is replaced by the one the configuration selects.
+    pub async fn reload_provider(&self) -> Result<()> {
+        let (provider, subgraph) = open_provider(&self.config(), &self.metrics, &self.cancel, &self.progress).await?;
+        self.replace_provider(provider, subgraph);
+        info!("Rebuilt the data provider");
+        Ok(())
+    }
+
+    fn replace_provider(&self, provider: SharedProvider, subgraph: Option<Arc<subgraph::SubgraphProvider>>) {
+        *self.provider.write().unwrap_or_else(|e| e.into_inner()) = provider;
+        *self.subgraph.lock().unwrap_or_else(|e| e.into_inner()) = subgraph;
+    }
+
+    /// Configuration in effect; replaced by `reload_config`
+    pub fn config(&self) -> Arc<config::Config> {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the configuration, returning the top-level sections that changed
+    ///
+    /// `config` is resolved and validated like in `new`; if that fails the current configuration
+    /// stays in effect. Assessments started afterwards use the new one, and the data provider is
+    /// rebuilt when a setting it was built from changed (its cache starts empty). Storage, the
+    /// audit log, alert routes, metrics, telemetry and the API server keep their settings until
+    /// a restart. Publishes `RiskEvent::ConfigReloaded` unless nothing changed.
+    pub async fn reload_config(&self, config: config::Config) -> Result<Vec<String>> {
+        let config = config.resolve_env()?;
+        config.validate()?;
+        let config = Arc::new(resolve_watchlist(config, &self.provider()).await?);
+        let changed = changed_sections(&self.config(), &config)?;
+        if changed.is_empty() {
+            debug!("Reloaded configuration is unchanged");
+            return Ok(changed);
+        }
+
+        if changed.iter().any(|section| section == "address_book") {
+            labels::install(labels::AddressBook::from_config(&config)?);
+        }
+        if changed.iter().any(|section| PROVIDER_SECTIONS.contains(&section.as_str())) {
+            let (provider, subgraph) = open_provider(&config, &self.metrics, &self.cancel, &self.progress).await?;
+            self.replace_provider(provider, subgraph);
+            info!("Rebuilt the data provider for the new configuration");
+        }
+        let pending: Vec<&str> = changed
+            .iter()
+            .map(String::as_str)
+            .filter(|section| RESTART_SECTIONS.contains(section))
+            .collect();
+        if !pending.is_empty() {
+            warn!("Changes to {} take effect after a restart", pending.join(", "));
+        }
+
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+        info!("Configuration reloaded; changed: {}", changed.join(", "));
+        // No subscribers is not an error
+        let _ = self.events.send(RiskEvent::ConfigReloaded { changed: changed.clone() });
+        Ok(changed)
+    }
+
+    /// Reload the configuration with `load` whenever the file at `path` changes
+    ///
+    /// A configuration that fails to load or validate is logged and ignored. Fails if a
+    /// watcher is already running; `shutdown` stops it.
+    #[cfg(feature = "reload")]
+    pub fn start_config_watcher<F>(self: &Arc<Self>, path: &std::path::Path, load: F) -> Result<()>
+    where
+        F: Fn() -> Result<config::Config> + Send + 'static,
+    {
+        let mut slot = self.config_watcher.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_some() {
+            return Err(RiskEngineError::config("reload", "the configuration watcher is already running"));
+        }
+        *slot = Some(reload::ConfigWatcher::spawn(Arc::downgrade(self), path, load)?);
+        Ok(())
+    }
+
+    /// Address of an account given as hex or as an ENS name
+    ///
+    /// Names need a provider that reads from chain; results are cached by the provider.
+    pub async fn resolve_account(&self, input: &str) -> Result<Address> {
+        if let Ok(address) = utils::parse_address(input) {
+            return Ok(address);
+        }
+        match self.provider().name_resolver() {
+            Some(resolver) => resolver.resolve(input).await,
+            None => Err(RiskEngineError::Unavailable {
+                what: "ENS resolution",
+                reason: format!("`{}` is not a hex address and the data source has no ENS registry", input),
+            }),
+        }
+    }
+
+    /// Primary ENS name of `address` for display, with `ens.reverse_lookup` on and a chain provider
+    pub async fn account_name(&self, address: Address) -> Option<String> {
+        if !self.config().ens.reverse_lookup {
+            return None;
+        }
+        self.provider().name_resolver()?.lookup(address).await
+    }
+
+    /// Whether the current provider serves mock or fixture data instead of chain state
+    pub async fn is_mock_data(&self) -> bool {
+        self.provider().is_mock()
+    }
+
+    /// Token that interrupts the engine's long-running operations when cancelled
+    ///
+    /// Interrupted operations flush any partial persistent state and return
+    /// `RiskEngineError::Cancelled`.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Progress of the borrower scan or position fetch in flight, updated as it advances
+    ///
+    /// Only the chain provider reports position fetches; fixture data is read at once.
+    pub fn progress(&self) -> tokio::sync::watch::Receiver<Option<progress::Progress>> {
+        self.progress.subscribe()
+    }
+
+    /// Discover borrowers of the primary market, resuming from the persisted index
+    ///
+    /// Borrowers are found by scanning logs, or with `subgraph.accounts = "subgraph"`, listed
+    /// by the subgraph.
+    pub async fn discover_borrowers(&self) -> Result<scanner::BorrowerIndex> {
+        let config = self.config();
+        if config.subgraph.accounts == config::SourceKind::Subgraph {
+            return self.list_subgraph_borrowers().await;
+        }
+        let source = self.provider().log_source().ok_or_else(|| {
+            RiskEngineError::config("scanner", "the configured data provider cannot read chain logs")
+        })?;
+        let comet_address = utils::parse_address(&config.compound.comet_proxy_address)?;
+        let scanner_config = &config.scanner;
+        let mut index = match &scanner_config.index_path {
+            Some(path) => scanner::BorrowerIndex::load_or_new(path, comet_address)?,
+            None => scanner::BorrowerIndex::new(comet_address),
+        };
+
+        let scanner = scanner::BorrowerScanner::new(source, scanner_config.chunk_size, scanner_config.index_path.clone())
+            .with_confirmations(scanner_config.confirmations)
+            .with_flow_blocks(scanner_config.flow_blocks)
+            .with_concurrency(scanner_config.max_concurrent_chunks)
+            .with_progress(self.progress.clone());
+        let summary = scanner.scan(&mut index, scanner_config.start_block, &self.cancel).await?;
+        if let Some(block) = summary.reorg_from_block {
+            warn!(
+                "A reorg replaced blocks from {}: rolled back {} borrower(s) found in them and rescanned",
+                block, summary.rolled_back_borrowers
+            );
+        }
+        if summary.range_splits > 0 {
+            info!(
+                "The provider refused {} block range(s) as too large; set scanner.chunk_size to at most {} to avoid the retries",
+                summary.range_splits, summary.chunk_size
+            );
+        }
+        info!(
+            "Borrower scan covered blocks {}-{}, found {} new borrower(s)",
+            summary.from_block, summary.to_block, summary.new_borrowers
+        );
+        Ok(index)
+    }
+
+    /// Markets the engine assesses, with their current state and where it came from
+    pub async fn list_markets(&self) -> Result<Vec<inventory::MarketListing>> {
+        let config = self.config();
+        let provider = self.provider();
+        let origin = if provider.is_mock() {
+            inventory::DataOrigin::Mock
+        } else if config.subgraph.markets == config::SourceKind::Subgraph {
+            inventory::DataOrigin::Subgraph
+        } else if self.last_refresh().is_some() {
+            inventory::DataOrigin::Cache
+        } else {
+            inventory::DataOrigin::Live
+        };
+        let markets = provider.get_markets().await?;
+        Ok(markets.iter().map(|market| inventory::MarketListing::new(&config, market, origin)).collect())
+    }
+
+    /// Comet deployments on the configured chain that the engine does not assess
+    ///
+    /// Best effort, see `inventory::discover`: failures are listed in `Discovery::errors`.
+    pub async fn discover_markets(&self) -> inventory::Discovery {
+        let config = self.config();
+        let provider = self.provider();
+        let mut errors = Vec::new();
+        let mut monitored: Vec<Address> = config.market_addresses().unwrap_or_default();
+        match provider.get_markets().await {
+            Ok(markets) => monitored.extend(markets.iter().map(|m| m.comet_address)),
+            Err(e) => errors.push(format!("monitored markets: {}", e)),
+        }
+        let mut discovery = inventory::discover(&config, provider.log_source(), &monitored, &self.cancel, &self.progress).await;
+        errors.append(&mut discovery.errors);
+        discovery.errors = errors;
+        discovery
+    }
+
+    /// Add the borrowers listed by the subgraph to the persisted index
+    async fn list_subgraph_borrowers(&self) -> Result<scanner::BorrowerIndex> {
+        let subgraph = self.subgraph().ok_or_else(|| {
+            RiskEngineError::config("subgraph.accounts", "the subgraph is only used with `data_source = \"live\"`")
+        })?;
+        let config = self.config();
+        let comet_address = utils::parse_address(&config.compound.comet_proxy_address)?;
+        let mut index = match &config.scanner.index_path {
+            Some(path) => scanner::BorrowerIndex::load_or_new(path, comet_address)?,
+            None => scanner::BorrowerIndex::new(comet_address),
+        };
+
+        let (borrowers, indexed_block) = subgraph.borrowers(comet_address).await?;
+        let known = index.borrowers.len();
+        index.borrowers.extend(borrowers);
+        // A later log scan only needs to cover what the subgraph has not indexed yet
+        index.last_scanned_block = index.last_scanned_block.max(indexed_block);
+        if let Some(path) = &config.scanner.index_path {
+            index.save(path)?;
+        }
+        info!(
+            "Subgraph lists borrowers up to block {}, found {} new borrower(s)",
+            indexed_block.map_or("unknown".to_string(), |b| b.to_string()),
+            index.borrowers.len() - known
+        );
+        Ok(index)
+    }
+
+    /// Watchlist accounts plus, for the configured Comet, the borrowers in the scanner's index
+    pub fn tracked_accounts(&self, market: &models::Market) -> Result<Vec<Address>> {
+        self.tracked_accounts_of(market.comet_address)
+    }
+
+    /// `tracked_accounts` of the Comet at `comet`
+    fn tracked_accounts_of(&self, comet: Address) -> Result<Vec<Address>> {
+        let config = self.config();
+        let mut accounts: Vec<Address> = config
+            .watchlist
+            .iter()
+            .filter_map(|entry| utils::parse_address(entry).ok())
+            .collect();
+
+        let primary = utils::parse_address(&config.compound.comet_proxy_address).ok();
+        if let (Some(path), true) = (&config.scanner.index_path, primary == Some(comet)) {
+            accounts.extend(scanner::BorrowerIndex::load_or_new(path, comet)?.borrowers);
+        }
+        accounts.sort();
+        accounts.dedup();
+        Ok(accounts)
+    }
+
+    /// Base flows of the last `risk.outflows.window_seconds` in the scanner's index, for the
+    /// configured Comet when the index keeps flows; `None` otherwise
+    fn recent_base_flows(&self, market: &models::Market) -> Result<Option<Vec<scanner::BaseFlow>>> {
+        let config = self.config();
+        let primary = utils::parse_address(&config.compound.comet_proxy_address).ok();
+        let Some(path) = config.scanner.index_path.as_ref().filter(|_| primary == Some(market.comet_address) && config.scanner.flow_blocks > 0) else {
+            return Ok(None);
+        };
+        let since = chrono::Utc::now() - chrono::Duration::seconds(config.risk.outflows.window_seconds as i64);
+        let mut flows = scanner::BorrowerIndex::load_or_new(path, market.comet_address)?.flows;
+        flows.retain(|flow| flow.timestamp >= since);
+        Ok(Some(flows))
+    }
+
+    /// Positions of the tracked accounts in `market`, lowest health factor first
+    ///
+    /// Accounts whose position cannot be read are logged and left out, as are positions
+    /// borrowing less than `risk.min_position_usd`.
+    pub async fn tracked_positions(&self, market: &models::Market) -> Result<Vec<models::UserPosition>> {
+        let risk = &self.config().risk;
+        let mut positions = self.fetch_tracked_positions(market).await?;
+        positions.retain(|position| !risk.below_min_position(position));
+        positions.sort_by(|a, b| a.health_factor.total_cmp(&b.health_factor));
+        Ok(positions)
+    }
+
+    /// The `limit` largest tracked positions in the market with Comet proxy `market`
+    ///
+    /// Suppliers are only ranked if they are tracked; see `ranking` for what that covers.
+    /// `risk.min_position_usd` does not apply. Fails with `RiskEngineError::NotFound` if the
+    /// provider does not list the market.
+    pub async fn top_positions(
+        &self,
+        market: Address,
+        sort: ranking::PositionSort,
+        limit: usize,
+        filter: ranking::HealthFactorFilter,
+    ) -> Result<ranking::TopPositions> {
+        let provider = self.provider();
+        let found = find_market(provider.as_ref(), market).await?;
+        let positions = self.fetch_tracked_positions(&found).await?;
+        let mut top = ranking::rank(&found, &positions, sort, limit, filter);
+        for ranked in &mut top.positions {
+            ranked.account_name = self.account_name(ranked.address).await;
+        }
+        self.add_borrow_across_markets(&provider, &found, &mut top.positions).await?;
+        top.mock_data = provider.is_mock();
+        Ok(top)
+    }
+
+    /// Positions of `address` in every market the provider lists, with their totals against
+    /// the borrow of all those markets
+    ///
+    /// Markets where the account holds nothing are left out; those whose position cannot be
+    /// read are listed in `AccountExposure::failed`.
+    pub async fn get_account_exposure(&self, address: Address) -> Result<exposure::AccountExposure> {
+        let provider = self.provider();
+        let markets = provider.get_markets().await?;
+        let chain_id = self.config().compound.chain_id;
+        let mut positions = Vec::new();
+        let mut failed = Vec::new();
+        for market in &markets {
+            let result = tokio::select! {
+                _ = self.cancel.cancelled() => return Err(RiskEngineError::Cancelled),
+                result = provider.get_user_position(market, address) => result,
+            };
+            match result {
+                Ok(position) if position.base_balance != 0.0 || position.total_collateral_value > 0.0 => {
+                    positions.push(exposure::MarketPosition {
+                        market_name: market.name.clone(),
+                        market_address: market.comet_address,
+                        chain_id,
+                        position,
+                        available_liquidity: market.available_liquidity(),
+                    })
+                }
+                Ok(_) | Err(RiskEngineError::NotFound { .. }) => {}
+                Err(e) => failed.push(exposure::FailedMarket {
+                    market_name: market.name.clone(),
+                    market_address: market.comet_address,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        let max_share = self.config().risk.max_account_borrow_share;
+        let mut exposure = exposure::AccountExposure::new(address, &markets, positions, failed, max_share);
+        exposure.account_name = self.account_name(address).await;
+        exposure.mock_data = provider.is_mock();
+        Ok(exposure)
+    }
+
+    /// Positions of `accounts` in the market with Comet proxy `market`, checked for liquidation
+    /// risk like the watchlist is, lowest health factor first
+    ///
+    /// Accounts whose position cannot be read are listed in `PositionScan::failed`; the scan
+    /// goes on without them. Fails with `RiskEngineError::NotFound` if the provider does not
+    /// list the market.
+    pub async fn scan_positions(&self, market: Address, accounts: &position_scan::AddressList) -> Result<position_scan::PositionScan> {
+        let provider = self.provider();
+        let found = find_market(provider.as_ref(), market).await?;
+        let results = tokio::select! {
+            _ = self.cancel.cancelled() => Err(RiskEngineError::Cancelled),
+            results = provider.get_positions(&found, &accounts.addresses) => results,
+        }?;
+
+        let processor = risk::RiskProcessor::new(self.config());
+        let mut positions = Vec::new();
+        let mut failed = Vec::new();
+        for (address, result) in results {
+            match result {
+                Ok(position) => positions.push(position_scan::ScannedPosition {
+                    finding: processor.check_user_liquidation_risk(found.comet_address, &position),
+                    position,
+                }),
+                Err(e) => failed.push(position_scan::FailedAccount { address, error: e.to_string() }),
+            }
+        }
+        positions.sort_by(|a, b| a.position.health_factor.total_cmp(&b.position.health_factor));
+        Ok(position_scan::PositionScan {
+            market_name: found.name,
+            market_address: found.comet_address,
+            summary: position_scan::ScanSummary::of(&positions),
+            positions,
+            failed,
+            invalid_lines: accounts.invalid.clone(),
+            mock_data: provider.is_mock(),
+        })
+    }
+
+    /// Fill in each ranked account's borrow over every market and its share of their borrow,
+    /// so an account borrowing in several markets is not ranked as several small borrowers;
+    /// left unset when the provider lists only `market`
+    async fn add_borrow_across_markets(
+        &self,
+        provider: &SharedProvider,
+        market: &models::Market,
+        ranked: &mut [ranking::RankedPosition],
+    ) -> Result<()> {
+        let markets = provider.get_markets().await?;
+        if markets.len() < 2 || ranked.is_empty() {
+            return Ok(());
+        }
+        let accounts: Vec<Address> = ranked.iter().map(|r| r.address).collect();
+        let mut borrow: HashMap<Address, f64> = ranked.iter().map(|r| (r.address, r.borrow_value_usd)).collect();
+        for other in markets.iter().filter(|m| m.comet_address != market.comet_address) {
+            for (account, result) in provider.get_positions(other, &accounts).await? {
+                match result {
+                    Ok(position) => *borrow.entry(account).or_default() += position.total_borrow_value,
+                    Err(RiskEngineError::NotFound { .. }) => {}
+                    Err(e) => warn!("Failed to fetch position {} in {}: {}", utils::to_checksum_address(&account), other.name, e),
+                }
+            }
+        }
+        let protocol_borrow_usd: f64 = markets.iter().map(|m| m.total_borrow * m.base_asset.price).sum();
+        for ranked in ranked {
+            let total = borrow[&ranked.address];
+            ranked.all_markets_borrow_usd = Some(total);
+            ranked.protocol_borrow_share = Some(if protocol_borrow_usd > 0.0 { total / protocol_borrow_usd } else { 0.0 });
+        }
+        Ok(())
+    }
+
+    /// Positions of the tracked accounts in `market`; those that cannot be read are logged and left out
+    async fn fetch_tracked_positions(&self, market: &models::Market) -> Result<Vec<models::UserPosition>> {
+        let accounts = self.tracked_accounts(market)?;
+        let results = self.provider().get_positions(market, &accounts).await?;
+        Ok(results
+            .into_iter()
+            .filter_map(|(account, result)| match result {
+                Ok(position) => Some(position),
+                Err(e) => {
+                    warn!("Failed to fetch position {} in {}: {}", utils::to_checksum_address(&account), market.name, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Subscribe to events published by `run_scheduled`
+    ///
+    /// Every subscriber receives every event. A subscriber that falls more than
+    /// `EVENT_CHANNEL_CAPACITY` events behind gets `RecvError::Lagged` and skips ahead;
+    /// it never slows the scheduler down.
+    pub fn subscribe(&self) -> broadcast::Receiver<RiskEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stop alerting on the finding `fingerprint` until it escalates past its current severity
+    ///
+    /// Fails with `NotFound` for a finding no scheduler run has alerted on. Without
+    /// `alerts.state_path` the acknowledgement only reaches a scheduler in this process.
+    pub fn acknowledge_finding(&self, fingerprint: &str) -> Result<alerts::AlertRecord> {
+        let record = self.alert_state.acknowledge(fingerprint, Utc::now())?;
+        info!("Acknowledged finding {} ({:?} in {})", fingerprint, record.category, record.market_name);
+        Ok(record)
+    }
+
+    /// Attach `note` by `author` to the finding `fingerprint` in the history store
+    ///
+    /// Fails with `Unavailable` without a history store, and with `NotFound` for a finding
+    /// neither stored nor in a latest assessment. The note shows on the latest assessment of
+    /// its market right away and on every later one; it never changes a score or an alert.
+    pub async fn annotate_finding(&self, fingerprint: &str, note: &str, author: &str) -> Result<annotations::Annotation> {
+        let annotation = annotations::Annotation::new(fingerprint, note, author)?;
+        let storage = self.annotation_store()?;
+        let fingerprint = annotation.fingerprint.as_str();
+        let current = self.latest_assessments().iter().any(|a| a.findings.iter().any(|f| f.fingerprint == fingerprint));
+        if !current && storage.fingerprint_span(fingerprint).await?.is_none() {
+            return Err(RiskEngineError::NotFound { kind: "finding", id: fingerprint.to_string() });
+        }
+        storage.annotate(&annotation).await?;
+        for assessment in self.latest.lock().unwrap_or_else(|e| e.into_inner()).values_mut() {
+            if assessment.findings.iter().any(|f| f.fingerprint == fingerprint) {
+                assessment.annotations.insert(fingerprint.to_string(), annotation.clone());
+            }
+        }
+        info!("Annotated finding {} as {}", fingerprint, annotation.author);
+        Ok(annotation)
+    }
+
+    /// Every annotation of the finding `fingerprint`, oldest first; fails with `Unavailable`
+    /// without a history store
+    pub async fn finding_annotations(&self, fingerprint: &str) -> Result<Vec<annotations::Annotation>> {
+        let storage = self.annotation_store()?;
+        storage.annotations(&[fingerprint.trim().to_string()]).await
+    }
+
+    /// Reconcile the persisted borrower index, alert state and history with the chain
+    ///
+    /// See `reconcile` for what is checked. Only open findings of the alert state whose data is
+    /// older than `reconcile.max_data_age_secs` are changed; they are resolved as stale. The
+    /// index is read, not written. Failing to read the head, positions or history leaves that
+    /// part out with a warning; failing to update the alert state is an error.
+    pub async fn reconcile(&self) -> Result<reconcile::Reconciliation> {
+        let started = Instant::now();
+        let config = self.config();
+        let now = Utc::now();
+        let cutoff = now - chrono::Duration::seconds(config.reconcile.max_data_age_secs as i64);
+
+        let comet = utils::parse_address(&config.compound.comet_proxy_address).ok();
+        let index = match (&config.scanner.index_path, comet) {
+            (Some(path), Some(comet)) if path.exists() => Some(scanner::BorrowerIndex::load_or_new(path, comet)?),
+            _ => None,
+        };
+        let mut lag = None;
+        let mut positions = None;
+        if let Some(index) = &index {
+            match self.head_block().await {
+                Some(head) => {
+                    lag = Some(reconcile::IndexLag::of(index, config.scanner.start_block, head, config.scanner.chunk_size))
+                }
+                None => warn!("Reconciliation could not read the chain head; the borrower index lag is unknown"),
+            }
+            let sample = reconcile::sample(&index.borrowers, config.reconcile.sample_size);
+            if !sample.is_empty() {
+                match self.recheck_positions(index.comet_address, &sample).await {
+                    Ok(check) => positions = Some(check),
+                    Err(e) => warn!("Reconciliation could not re-read indexed positions: {}", e),
+                }
+            }
+        }
+
+        let mut stale_markets = Vec::new();
+        if let Some(storage) = &self.storage {
+            match storage.markets().await {
+                Ok(markets) => stale_markets.extend(markets.into_iter().filter(|market| market.last_assessed < cutoff).map(|market| {
+                    reconcile::StaleMarket {
+                        market_name: market.name,
+                        market_address: market.address,
+                        last_assessed: market.last_assessed,
+                    }
+                })),
+                Err(e) => warn!("Reconciliation could not read the stored markets: {}", e),
+            }
+        }
+        let stale_findings = self.alert_state.resolve_stale(cutoff, now)?;
+
+        let reconciliation = reconcile::Reconciliation {
+            finished_at: Utc::now(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            index: lag,
+            positions,
+            stale_markets,
+            stale_findings,
+        };
+        info!("Reconciled persisted state in {}ms: {}", reconciliation.duration_ms, reconciliation);
+        Ok(reconciliation)
+    }
+
+    /// Which of `accounts` still borrow in the market with Comet proxy `comet`
+    async fn recheck_positions(&self, comet: Address, accounts: &[Address]) -> Result<reconcile::PositionCheck> {
+        let provider = self.provider();
+        let market = find_market(provider.as_ref(), comet).await?;
+        let mut check = reconcile::PositionCheck { sampled: accounts.len(), ..Default::default() };
+        for (account, borrow) in provider.get_borrows(&market, accounts).await? {
+            match borrow {
+                Ok(borrow) if borrow > 0.0 => check.borrowing += 1,
+                Ok(_) => check.closed += 1,
+                Err(e) => {
+                    debug!("Could not re-read {:?} in reconciliation: {}", account, e);
+                    check.unreadable += 1;
+                }
+            }
+        }
+        Ok(check)
+    }
+
+    /// Assess all markets every `interval` until `cancel` fires, publishing change events
+    ///
+    /// Markets listed in `schedule.intervals` are assessed at their own interval instead; each
+    /// run assesses the markets then due (see the `schedule` module) and records their
+    /// `cadence`. Findings are diffed against the market's previous assessment by fingerprint,
+    /// so subscribers see new, escalated and resolved findings plus one `AssessmentCompleted`
+    /// per market assessed.
+    /// New and escalated findings are also sent to the configured alert routes. With
+    /// `reconcile.enabled`, the persisted state is reconciled first and nothing is alerted
+    /// until that is done; if it fails, nothing is run and the error is returned.
+    pub async fn run_scheduled(&self, interval: Duration, cancel: CancellationToken) -> Result<()> {
+        self.run_scheduled_times(interval, None, cancel).await
+    }
+
+    /// Like `run_scheduled`, but also stops after `max_runs` runs (batches of due markets) when set
+    pub async fn run_scheduled_times(
+        &self,
+        interval: Duration,
+        max_runs: Option<u64>,
+        cancel: CancellationToken,
+    ) -> Result<()> {
+        if self.config().reconcile.enabled {
+            // Nothing is alerted on state that could not be reconciled
+            self.reconcile().await.map_err(|e| RiskEngineError::Unavailable {
+                what: "alerting",
+                reason: format!("reconciliation of the persisted state failed ({}); pass --skip-reconcile to start anyway", e),
+            })?;
+        }
+        let mut tracker = events::FindingTracker::new();
+        let dispatcher = self
+            .alert_dispatcher(&self.config())?
+            .with_state(self.alert_state.clone())
+            .with_delivery_log(self.deliveries.clone());
+        // Stops the dispatcher (after it drains queued events) however the loop ends
+        let stop = cancel.child_token();
+        let alerting = (!dispatcher.is_empty())
+            .then(|| tokio::spawn(dispatcher.run(self.subscribe(), stop.clone())));
+
+        let mut schedule = schedule::MarketSchedule::new(interval, HashMap::new());
+        let mut runs = 0;
+        loop {
+            // Re-read every run, so a reloaded configuration applies from the next due time on
+            match self.config().market_intervals() {
+                Ok(intervals) => schedule.set_intervals(intervals),
+                Err(e) => warn!("Keeping the previous market intervals: {}", e),
+            }
+            let started = tokio::time::Instant::now();
+            let summary = match self.assess_due(&mut schedule).await {
+                Ok(run) => {
+                    for error in &run.errors {
+                        warn!("Scheduled assessment of {} failed: {}", error.market_name, error.error);
+                    }
+                    let summary = RunSummary {
+                        finished_at: Utc::now(),
+                        assessed: run.assessments.len(),
+                        errors: run.errors,
+                        duration_ms: started.elapsed().as_millis() as u64,
+                    };
+                    let shortest = schedule.shortest_interval(run.assessments.iter().map(|a| a.market_address));
+                    if summary.nears_interval(shortest) {
+                        let slowest: Vec<String> = risk::slowest_checks(&run.assessments, 3).iter().map(ToString::to_string).collect();
+                        warn!(
+                            "Scheduled assessment took {}ms of its {}ms interval and is falling behind; slowest checks: {}",
+                            summary.duration_ms,
+                            shortest.as_millis(),
+                            slowest.join(", ")
+                        );
+                    }
+                    for assessment in run.assessments {
+                        if let Some(warning) = schedule.interval_warning(&assessment) {
+                            warn!("{}", warning);
+                        }
+                        for event in tracker.observe(assessment) {
+                            // No subscribers is not an error
+                            let _ = self.events.send(event);
+                        }
+                    }
+                    summary
+                }
+                Err(e) => {
+                    warn!("Scheduled assessment failed: {:#}", e);
+                    let error = MarketError {
+                        market_name: "all markets".to_string(),
+                        market_address: Address::zero(),
+                        error: format!("{:#}", e),
+                    };
+                    RunSummary { finished_at: Utc::now(), assessed: 0, errors: vec![error], duration_ms: started.elapsed().as_millis() as u64 }
+                }
+            };
+            let failed = summary.assessed == 0 && !summary.errors.is_empty();
+            *self.last_run.lock().unwrap_or_else(|e| e.into_inner()) = Some(summary);
+
+            runs += 1;
+            if max_runs.is_some_and(|max| runs >= max) {
+                break;
+            }
+            // After a failed run, retry at the default interval rather than at once
+            let now = tokio::time::Instant::now();
+            let wake = match schedule.next_due() {
+                Some(due) if !failed || due > now => due,
+                _ => now + interval,
+            };
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep_until(wake) => {}
+            }
+        }
+
+        stop.cancel();
+        if let Some(alerting) = alerting {
+            let _ = alerting.await;
+        }
+        info!("Scheduled assessments stopped");
+        Ok(())
+    }
+
+    /// Start re-fetching market data into the cache every `interval`
+    ///
+    /// Fails if a background refresh is already running. While the RPC is failing the
+    /// task backs off exponentially instead of retrying every `interval`.
+    pub fn start_background_refresh(&self, interval: Duration) -> Result<()> {
+        let mut slot = self.background_refresh.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_some() {
+            return Err(RiskEngineError::config("background_refresh", "background refresh is already running"));
+        }
+
+        *slot = Some(refresh::BackgroundRefresh::spawn(
+            self.provider.clone(),
+            interval,
+            self.last_refresh.clone(),
         ));
+        Ok(())
+    }
+
+    /// Metrics updated by every completed assessment
+    pub fn metrics(&self) -> &metrics::Metrics {
+        &self.metrics
+    }
+
+    /// Serve Prometheus metrics on `metrics.bind_address`, returning the bound address
+    ///
+    /// Fails if the exporter is not configured or already running.
+    pub fn start_metrics_exporter(&self) -> Result<std::net::SocketAddr> {
+        let config = self.config();
+        let bind_address = config.metrics.bind_address.as_deref().ok_or_else(|| {
+            RiskEngineError::config("metrics.bind_address", "the metrics exporter is not configured")
+        })?;
+        let addr = bind_address.parse().map_err(|e| {
+            RiskEngineError::config("metrics.bind_address", format!("`{}` is not a socket address: {}", bind_address, e))
+        })?;
+
+        let mut slot = self.metrics_exporter.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_some() {
+            return Err(RiskEngineError::config("metrics.bind_address", "the metrics exporter is already running"));
+        }
+        let exporter = metrics::MetricsExporter::spawn(self.metrics.clone(), addr)?;
+        let local_addr = exporter.local_addr();
+        *slot = Some(exporter);
+        Ok(local_addr)
+    }
+
+    fn metrics_exporter_running(&self) -> bool {
+        self.metrics_exporter.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    /// Stop the background refresh task, metrics exporter and configuration watcher, if running,
+    /// and wait for them to exit
+    ///
+    /// Queued history and audit writes get up to `STORAGE_FLUSH_TIMEOUT` to reach the storage
+    /// backend and audit log.
+    ///
+    /// This does not cancel in-flight operations; use `cancellation_token` for that.
+    pub async fn shutdown(&self) {
+        #[cfg(feature = "reload")]
+        {
+            let watcher = self.config_watcher.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(watcher) = watcher {
+                watcher.shutdown().await;
+            }
+        }
+        let task = self.background_refresh.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(task) = task {
+            task.shutdown().await;
+        }
+        let exporter = self.metrics_exporter.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(exporter) = exporter {
+            exporter.shutdown().await;
+        }
+        let heartbeat = self.leader_heartbeat.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.shutdown().await;
+        }
+        if let Some(storage) = &self.storage {
+            match tokio::time::timeout(STORAGE_FLUSH_TIMEOUT, storage.flush()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Failed to flush {}: {}", storage.name(), e),
+                Err(_) => warn!("Gave up waiting for {} to store queued assessments", storage.name()),
+            }
+        }
+        // After the queued history is written, so the next leader's writes follow it
+        if let Some(leadership) = &self.leadership {
+            leadership.step_down().await;
+        }
+        if let Some(audit) = &self.audit {
+            if tokio::time::timeout(STORAGE_FLUSH_TIMEOUT, audit.flush()).await.is_err() {
+                warn!("Gave up waiting for queued audit entries to be written");
+            }
+        }
+    }
+
+    /// Time of the last successful background refresh (`None` if none has completed)
+    pub fn last_refresh(&self) -> Option<DateTime<Utc>> {
+        *self.last_refresh.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Outcome of the newest scheduled run (`None` before the first one finishes)
+    pub fn last_run(&self) -> Option<RunSummary> {
+        self.last_run.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// How fresh the engine's data is, judged against `server.max_data_age_secs`
+    pub async fn health(&self) -> Health {
+        let last_refresh = self.last_refresh();
+        let latest = self.latest_assessments();
+        let last_assessment = latest.iter().map(|a| a.timestamp).max();
+        let data_age_secs = last_refresh.max(last_assessment).map(|t| (Utc::now() - t).num_seconds());
+
+        let max_age = self.config().server.max_data_age_secs as i64;
+        let status = match data_age_secs {
+            None => HealthStatus::Pending,
+            Some(age) if age > max_age => HealthStatus::Stale,
+            Some(_) => HealthStatus::Ok,
+        };
+        Health {
+            status,
+            mock_data: self.is_mock_data().await,
+            last_refresh,
+            last_assessment,
+            data_age_secs,
+            markets_assessed: latest.len(),
+            progress: self.progress.current(),
+            leadership: self.leader_status(),
+        }
+    }
+
+    /// Check the engine's dependencies and configuration, giving each probe
+    /// `diagnostics::PROBE_TIMEOUT`; see the `diagnostics` module
+    pub async fn diagnostics(&self) -> diagnostics::Diagnostics {
+        self.diagnostics_within(diagnostics::PROBE_TIMEOUT).await
+    }
+
+    /// Like `diagnostics`, giving each probe at most `timeout`
+    pub async fn diagnostics_within(&self, timeout: Duration) -> diagnostics::Diagnostics {
+        use diagnostics::{CheckStatus, DiagnosticCheck};
+        let config = self.config();
+        let provider = self.provider();
+        let (chain, subgraph, storage, health) = tokio::join!(
+            self.probe_chain(&provider, timeout),
+            self.probe_subgraph(timeout),
+            self.probe_storage(timeout),
+            self.health(),
+        );
+
+        let mut checks = vec![match config.validate() {
+            Ok(()) => DiagnosticCheck::new("config", CheckStatus::Pass, "valid"),
+            Err(e) => DiagnosticCheck::new("config", CheckStatus::Fail, e.to_string()),
+        }];
+        checks.extend(chain);
+        checks.extend(subgraph);
+        checks.push(match provider.cache_stats().map(|stats| (stats, stats.hit_rate())) {
+            None => DiagnosticCheck::new("cache", CheckStatus::Pass, "the data source keeps no market cache"),
+            Some((_, None)) => DiagnosticCheck::new("cache", CheckStatus::Pass, "no market lookups yet"),
+            Some((stats, Some(rate))) => DiagnosticCheck::new(
+                "cache",
+                CheckStatus::Pass,
+                format!("{:.0}% hit rate over {} market lookup(s)", rate * 100.0, stats.hits + stats.misses),
+            ),
+        });
+        checks.push(storage);
+        checks.extend(self.alert_checks(&config));
+        let age = health.data_age_secs.map(|secs| utils::format_duration(Duration::from_secs(secs.max(0) as u64)));
+        checks.push(match health.status {
+            HealthStatus::Pending => DiagnosticCheck::new("freshness", CheckStatus::Pass, "nothing assessed yet"),
+            HealthStatus::Ok => DiagnosticCheck::new("freshness", CheckStatus::Pass, format!("newest data {} old", age.unwrap_or_default())),
+            HealthStatus::Stale => DiagnosticCheck::new(
+                "freshness",
+                CheckStatus::Fail,
+                format!("newest data {} old, over `server.max_data_age_secs`", age.unwrap_or_default()),
+            ),
+        });
+        checks.push(self.check_timings(&config));
+        diagnostics::Diagnostics::new(checks)
+    }
 
-        Ok(Self { config, compound })
+    /// The three slowest checks of the newest assessments, a warning when one took longer than
+    /// `performance.check_budget_ms`
+    fn check_timings(&self, config: &config::Config) -> diagnostics::DiagnosticCheck {
+        use diagnostics::{CheckStatus, DiagnosticCheck};
+        let assessments = self.latest_assessments();
+        let slowest = risk::slowest_checks(&assessments, 3);
+        let Some(slowest_ms) = slowest.first().map(|check| check.timing.ms) else {
+            return DiagnosticCheck::new("check timings", CheckStatus::Pass, "no checks timed yet");
+        };
+        let listed: Vec<String> = slowest.iter().map(ToString::to_string).collect();
+        let message = format!("slowest: {}", listed.join(", "));
+        let budget = config.performance.check_budget_ms;
+        if slowest_ms > budget {
+            DiagnosticCheck::new("check timings", CheckStatus::Warn, format!("{}; over `performance.check_budget_ms` ({}ms)", message, budget))
+        } else {
+            DiagnosticCheck::new("check timings", CheckStatus::Pass, message)
+        }
+    }
+
+    /// Reachability of the RPC endpoint, and how far the newest assessment trails its head
+    async fn probe_chain(&self, provider: &SharedProvider, timeout: Duration) -> Vec<diagnostics::DiagnosticCheck> {
+        use diagnostics::{CheckStatus, DiagnosticCheck, MAX_BLOCK_LAG};
+        let chain_id = self.config().compound.chain_id;
+        let network = config::Network::from_chain_id(chain_id).map_or_else(|| format!("chain {}", chain_id), |n| n.name().to_string());
+        let name = format!("rpc ({})", network);
+        let Some(source) = provider.log_source() else {
+            return vec![DiagnosticCheck::new(name, CheckStatus::Pass, "the data source does not read from a chain")];
+        };
+        let (head, latency) = diagnostics::timed(timeout, source.head_block()).await;
+        let head = match head {
+            Ok(head) => head,
+            Err(e) => {
+                return vec![
+                    DiagnosticCheck::new(name, CheckStatus::Fail, e.to_string()).with_latency(latency),
+                    DiagnosticCheck::new("block lag", CheckStatus::Warn, "chain head unknown"),
+                ]
+            }
+        };
+        let rpc = DiagnosticCheck::new(name, CheckStatus::Pass, format!("head block {}", head)).with_latency(latency);
+        let lag = match self.latest_assessments().iter().filter_map(|a| a.block_number).max() {
+            None => DiagnosticCheck::new("block lag", CheckStatus::Pass, "no assessment read at a known block yet"),
+            Some(block) => {
+                let lag = head.saturating_sub(block);
+                let status = if lag > MAX_BLOCK_LAG { CheckStatus::Warn } else { CheckStatus::Pass };
+                DiagnosticCheck::new("block lag", status, format!("newest assessment read block {}, {} behind the head", block, lag))
+            }
+        };
+        vec![rpc, lag]
+    }
+
+    /// How far the subgraph trails the chain, if anything is read from it
+    async fn probe_subgraph(&self, timeout: Duration) -> Option<diagnostics::DiagnosticCheck> {
+        use diagnostics::{CheckStatus, DiagnosticCheck};
+        let subgraph = self.subgraph()?;
+        let (lag, latency) = diagnostics::timed(timeout, subgraph.lag()).await;
+        let check = match lag {
+            Ok(lag) => DiagnosticCheck::new(
+                "subgraph",
+                if lag.lagging { CheckStatus::Warn } else { CheckStatus::Pass },
+                format!("indexed block {}, {} behind the head", lag.indexed_block, lag.lag_blocks),
+            ),
+            Err(e) => DiagnosticCheck::new("subgraph", CheckStatus::Fail, e.to_string()),
+        };
+        Some(check.with_latency(latency))
+    }
+
+    /// Whether the history backend answers a read
+    async fn probe_storage(&self, timeout: Duration) -> diagnostics::DiagnosticCheck {
+        use diagnostics::{CheckStatus, DiagnosticCheck};
+        let Some(storage) = &self.storage else {
+            return DiagnosticCheck::new("storage", CheckStatus::Pass, "no history backend configured");
+        };
+        let (markets, latency) = diagnostics::timed(timeout, storage.markets()).await;
+        let check = match markets {
+            Ok(markets) => DiagnosticCheck::new(
+                "storage",
+                CheckStatus::Pass,
+                format!("{} answers, {} market(s) stored", storage.name(), markets.len()),
+            ),
+            Err(e) => DiagnosticCheck::new("storage", CheckStatus::Fail, format!("{}: {}", storage.name(), e)),
+        };
+        check.with_latency(latency)
+    }
+
+    /// Latest delivery through every alert route; a route whose latest delivery failed is a warning
+    fn alert_checks(&self, config: &config::Config) -> Vec<diagnostics::DiagnosticCheck> {
+        use diagnostics::{CheckStatus, DiagnosticCheck};
+        let routes = match self.alert_dispatcher(config) {
+            Ok(dispatcher) => dispatcher.route_names(),
+            Err(e) => return vec![DiagnosticCheck::new("alerts", CheckStatus::Fail, e.to_string())],
+        };
+        if routes.is_empty() {
+            return vec![DiagnosticCheck::new("alerts", CheckStatus::Pass, "no alert routes configured")];
+        }
+        let last_success = |deliveries: &alerts::SinkDeliveries| match &deliveries.last_success {
+            Some(at) => format!("last delivered {}", utils::format_relative(at)),
+            None => "never delivered".to_string(),
+        };
+        routes
+            .into_iter()
+            .map(|route| {
+                let name = format!("alerts ({})", route);
+                match self.deliveries.get(&route) {
+                    None => DiagnosticCheck::new(name, CheckStatus::Pass, "nothing sent yet"),
+                    Some(deliveries) if deliveries.failing() => DiagnosticCheck::new(
+                        name,
+                        CheckStatus::Warn,
+                        format!(
+                            "latest delivery failed: {}; {}",
+                            deliveries.last_error.as_deref().unwrap_or("unknown error"),
+                            last_success(&deliveries)
+                        ),
+                    ),
+                    Some(deliveries) => DiagnosticCheck::new(name, CheckStatus::Pass, last_success(&deliveries)),
+                }
+            })
+            .collect()
+    }
+
+    /// Dispatcher for the routes `config` configures and the ones injected with the builder
+    fn alert_dispatcher(&self, config: &config::Config) -> Result<alerts::AlertDispatcher> {
+        let mut dispatcher = alerts::AlertDispatcher::from_config(config)?.with_units(self.units.clone());
+        if let Some(leadership) = &self.leadership {
+            dispatcher = dispatcher.with_leadership(leadership.clone());
+        }
+        Ok(self.alert_routes.iter().cloned().fold(dispatcher, alerts::AlertDispatcher::with_route))
+    }
+
+    /// Units of the amounts of every market assessed since startup, in `display.denomination`
+    /// as it was at each market's latest assessment
+    pub fn units(&self) -> denomination::Units {
+        self.units.snapshot()
+    }
+
+    /// Most recent assessment of the market with Comet proxy `market`, if it was assessed since startup
+    pub fn latest_assessment(&self, market: Address) -> Option<risk::RiskAssessment> {
+        self.latest.lock().unwrap_or_else(|e| e.into_inner()).get(&market).cloned()
+    }
+
+    /// Most recent assessment of every market assessed since startup, in no particular order
+    pub fn latest_assessments(&self) -> Vec<risk::RiskAssessment> {
+        self.latest.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect()
+    }
+
+    /// What `assess_risks` would do, worked out without reading anything over the network
+    ///
+    /// Markets, tracked accounts, the budget plan and the checks are resolved by the code the
+    /// run itself uses; see `plan` for what is estimated. Fixture data is read for the markets,
+    /// live markets are planned from the configuration alone.
+    pub async fn plan(&self) -> Result<plan::RunPlan> {
+        let config = self.config();
+        let provider = self.provider();
+        let chain_id = config.compound.chain_id;
+        let markets: Vec<(Address, String, Option<usize>)> = if provider.is_mock() {
+            let markets = provider.get_markets().await?;
+            markets.into_iter().map(|market| (market.comet_address, market.name, Some(market.collateral_assets.len()))).collect()
+        } else {
+            let addresses = config.market_addresses()?;
+            addresses.into_iter().map(|address| (address, plan::market_name(chain_id, address), None)).collect()
+        };
+
+        // Reading the markets comes out of the run budget before any of them is planned
+        let reads_chain = provider.rpc_budget().is_some();
+        let collateral_of = |collateral: Option<usize>| collateral.map_or(plan::ASSUMED_COLLATERAL_ASSETS, |count| count as u64);
+        let market_units: u64 = markets.iter().map(|(_, _, collateral)| budget::market_read_units(collateral_of(*collateral))).sum();
+        let run_budget = config.rpc.budget.per_run_compute_units;
+        let allowance = run_budget
+            .filter(|_| reads_chain)
+            .map(|units| units.saturating_sub(market_units) / markets.len().max(1) as u64);
+
+        let mut planned = Vec::new();
+        for (address, name, collateral) in markets {
+            let accounts = self.tracked_accounts_of(address).unwrap_or_else(|e| {
+                warn!("No borrower index for {}: {}", name, e);
+                Vec::new()
+            });
+            let tracked_accounts = accounts.len();
+            let assets = collateral_of(collateral);
+            let budget = self.fit_budget(&name, allowance, accounts, budget::position_units_with(assets));
+            let processor = risk::RiskProcessor::with_provider(config.clone(), provider.clone())
+                .with_accounts(budget.accounts.clone())
+                .with_skipped_checks(budget.skipped_checks.clone())
+                .with_checks(self.checks.clone());
+            let (rpc_calls, compute_units) = if reads_chain {
+                (
+                    budget::market_read_calls(assets) + plan::position_calls(budget.projected_compute_units),
+                    budget::market_read_units(assets) + budget.projected_compute_units,
+                )
+            } else {
+                (0, 0)
+            };
+            planned.push(plan::PlannedMarket {
+                market_name: name,
+                market_address: address,
+                collateral_assets: collateral,
+                tracked_accounts,
+                checks: processor.check_versions().into_keys().collect(),
+                skipped_checks: budget.skipped_checks.iter().map(|check| check.to_string()).collect(),
+                sampled_accounts: budget.sampled_accounts,
+                allowance: budget.allowance,
+                rpc_calls,
+                compute_units,
+            });
+        }
+
+        Ok(plan::RunPlan {
+            chain_id,
+            markets: planned,
+            data_sources: plan::PlannedSources::of(&config),
+            alert_routes: self.alert_dispatcher(&config)?.route_names(),
+            unresolved_watchlist: config.watchlist.iter().filter(|entry| ens::is_ens_name(entry)).cloned().collect(),
+            run_budget,
+            mock_data: provider.is_mock(),
+        })
+    }
+
+    /// Assess only the market with Comet proxy `market`
+    ///
+    /// Fails with `RiskEngineError::NotFound` if the provider does not list the market.
+    pub async fn assess_market_by_address(&self, market: Address) -> Result<risk::RiskAssessment> {
+        let provider = self.provider();
+        start_rpc_run(&provider);
+        let deadline = self.run_deadline();
+        let found = find_market(provider.as_ref(), market).await?;
+        expect_markets(&provider, 1);
+        tokio::select! {
+            _ = self.cancel.cancelled() => Err(RiskEngineError::Cancelled),
+            result = self.assess_market(provider.clone(), &found, None, deadline) => result,
+        }
+    }
+
+    /// Simulate stressed conditions in `market`
+    pub async fn simulate_market(&self, market: &models::Market) -> Result<risk::SimulationResult> {
+        let provider = self.provider();
+        let processor = risk::RiskProcessor::with_provider(self.config(), provider.clone());
+        Ok(risk::SimulationResult {
+            market_name: market.name.clone(),
+            market_address: market.comet_address,
+            findings: processor.simulate_market_conditions(market).await?,
+            absorption: None,
+            timestamp: Utc::now(),
+            mock_data: provider.is_mock(),
+        })
+    }
+
+    /// Absorb `target` in `market` at current prices and report what it does to reserves,
+    /// utilization and the other tracked positions
+    ///
+    /// `AbsorbTarget::Largest` picks among the tracked accounts; an account given by address
+    /// need not be tracked. `NotFound` when the account or market has no borrowing position.
+    pub async fn simulate_absorption(&self, market: &models::Market, target: absorb::AbsorbTarget) -> Result<absorb::AbsorptionSimulation> {
+        let provider = self.provider();
+        let mut positions = self.fetch_tracked_positions(market).await?;
+        if let absorb::AbsorbTarget::Account(account) = target {
+            if !positions.iter().any(|p| p.address == account) {
+                positions.push(provider.get_user_position(market, account).await?);
+            }
+        }
+        let position = absorb::select(&positions, target)
+            .filter(|position| position.base_balance < 0.0)
+            .ok_or_else(|| RiskEngineError::NotFound {
+                kind: "borrowing position",
+                id: match target {
+                    absorb::AbsorbTarget::Account(account) => utils::to_checksum_address(&account),
+                    absorb::AbsorbTarget::Largest => format!("no watchlist or indexed account borrows in {}", market.name),
+                },
+            })?;
+        let reserves = provider.get_protocol_metrics(market).await?.reserves;
+        Ok(absorb::AbsorptionSimulation {
+            mock_data: provider.is_mock(),
+            ..absorb::simulate(market, position, &positions, reserves, &self.config())
+        })
+    }
+
+    /// Serve the JSON API on `addr` until the engine's cancellation token fires
+    #[cfg(feature = "server")]
+    pub async fn serve(self: &Arc<Self>, addr: std::net::SocketAddr) -> Result<()> {
+        server::ApiServer::spawn(self.clone(), addr)?.wait().await;
+        Ok(())
     }
 
     /// Run a risk assessment for the specified Compound deployment
+    ///
+    /// Any market failure fails the whole call; use `assess_risks_with` to keep partial results.
     pub async fn assess_risks(&self) -> Result<Vec<risk::RiskAssessment>> {
-        let compound = self.compound.read().await;
-        let markets = compound.get_markets().await?;
-        
+        let run = self.assess_risks_with(ErrorPolicy::Strict).await?;
+        Ok(run.assessments)
+    }
+
+    /// Assess all markets concurrently (bounded by `performance.max_concurrent_assessments`)
+    #[tracing::instrument(level = "debug", name = "assess_risks", skip(self))]
+    pub async fn assess_risks_with(&self, policy: ErrorPolicy) -> Result<AssessmentRun> {
+        let provider = self.provider();
+        start_rpc_run(&provider);
+        let deadline = self.run_deadline();
+        let markets = self.markets_until(&provider, deadline).await?;
+        let (assessments, errors) = self.assess_markets(&provider, markets, deadline).await?;
+        let summary = self.summarize(&assessments);
+        self.metrics.observe_summary(&summary);
+        let run = AssessmentRun { assessments, errors, summary };
+
+        if policy == ErrorPolicy::Strict && !run.errors.is_empty() {
+            return Err(AggregateAssessmentError {
+                errors: run.errors,
+                partial: run.assessments,
+            }
+            .into());
+        }
+
+        Ok(run)
+    }
+
+    /// Assess the markets `schedule` has due now as one run, recording their cadence
+    ///
+    /// The summary covers the newest assessment of every market, not only those of the batch.
+    async fn assess_due(&self, schedule: &mut schedule::MarketSchedule) -> Result<AssessmentRun> {
+        let provider = self.provider();
+        start_rpc_run(&provider);
+        let deadline = self.run_deadline();
+        let markets = self.markets_until(&provider, deadline).await?;
+        let due = schedule.due(markets, tokio::time::Instant::now());
+        let (mut assessments, errors) = self.assess_markets(&provider, due, deadline).await?;
+
+        let finished = tokio::time::Instant::now();
+        for assessment in &mut assessments {
+            assessment.cadence = Some(schedule.assessed(assessment, finished));
+            self.metrics.observe_cadence(assessment);
+        }
+        for error in &errors {
+            schedule.failed(error.market_address, finished);
+        }
+        let summary = self.summarize(&self.latest_assessments());
+        self.metrics.observe_summary(&summary);
+        Ok(AssessmentRun { assessments, errors, summary })
+    }
+
+    /// Assess `markets` concurrently (bounded by `performance.max_concurrent_assessments`),
+    /// sharing the run's `rpc.budget`, returning the assessments and the failures
+    async fn assess_markets(
+        &self,
+        provider: &SharedProvider,
+        markets: Vec<models::Market>,
+        deadline: tokio::time::Instant,
+    ) -> Result<(Vec<risk::RiskAssessment>, Vec<MarketError>)> {
+        expect_markets(provider, markets.len());
+
+        let limit = self.config().performance.concurrency_limit();
+        let results = run_bounded(markets, limit, |market| async move {
+            let result = tokio::select! {
+                _ = self.cancel.cancelled() => Err(RiskEngineError::Cancelled),
+                result = self.assess_market(provider.clone(), &market, None, deadline) => result,
+            };
+            (market, result)
+        })
+        .await;
+
+        if self.cancel.is_cancelled() {
+            return Err(RiskEngineError::Cancelled);
+        }
+
         let mut assessments = Vec::new();
-        for market in markets {
-            let assessment = self.assess_market(&market).await?;
-            assessments.push(assessment);
+        let mut errors = Vec::new();
+        for (market, result) in results {
+            match result {
+                Ok(assessment) => assessments.push(assessment),
+                Err(e) => errors.push(MarketError {
+                    market_name: market.name.clone(),
+                    market_address: market.comet_address,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        if let Some(budget) = provider.rpc_budget() {
+            let usage = budget.run_usage();
+            debug!(calls = usage.calls, compute_units = usage.compute_units, throttled_ms = usage.throttled_ms, "RPC usage of the run");
         }
-        
-        Ok(assessments)
+        Ok((assessments, errors))
     }
-    
-    /// Assess a specific market for risks
-    async fn assess_market(&self, market: &models::Market) -> Result<risk::RiskAssessment> {
-        // For milestone 1, we'll implement a simplified risk assessment
-        let risk_processor = risk::RiskProcessor::new(self.config.clone());
-        risk_processor.assess_market(market).await
+
+    /// When a run starting now must end, by `timeouts.total`
+    fn run_deadline(&self) -> tokio::time::Instant {
+        tokio::time::Instant::now() + self.config().timeouts.total()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Markets of `provider`, failing if they are not read by `deadline`
+    async fn markets_until(&self, provider: &SharedProvider, deadline: tokio::time::Instant) -> Result<Vec<models::Market>> {
+        tokio::time::timeout_at(deadline, provider.get_markets()).await.map_err(|_| RiskEngineError::Unavailable {
+            what: "markets",
+            reason: format!("not read within timeouts.total ({}s)", self.config().timeouts.total),
+        })?
+    }
 
-    #[tokio::test]
-    async fn test_risk_engine_creation() {
-        let config = config::Config::default();
-        let engine = RiskEngine::new(config).await;
-        assert!(engine.is_ok());
+    /// Rollup of `assessments` under `risk.summary.weighting`
+    pub fn summarize(&self, assessments: &[risk::RiskAssessment]) -> summary::ProtocolRiskSummary {
+        summary::ProtocolRiskSummary::new(assessments, self.config().risk.summary.weighting)
+    }
+
+    /// Assess all markets, yielding each market's findings as soon as its checks complete
+    ///
+    /// Every market produces `MarketStarted`, zero or more `Finding`s and then either
+    /// `MarketCompleted` or `Error`. Events from different markets may interleave.
+    pub fn assess_risks_stream(&self) -> impl Stream<Item = AssessmentEvent> + '_ {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let driver = async move {
+            let provider = self.provider();
+            start_rpc_run(&provider);
+            let deadline = self.run_deadline();
+            let markets = self.markets_until(&provider, deadline).await;
+            let markets = match markets {
+                Ok(markets) => markets,
+                Err(e) => {
+                    let _ = tx.send(AssessmentEvent::Error(MarketError {
+                        market_name: "*".to_string(),
+                        market_address: Address::zero(),
+                        error: e.to_string(),
+                    }));
+                    return;
+                }
+            };
+            expect_markets(&provider, markets.len());
+
+            let limit = self.config().performance.concurrency_limit();
+            let tx = &tx;
+            let provider = &provider;
+            run_bounded(markets, limit, |market| async move {
+                let _ = tx.send(AssessmentEvent::MarketStarted {
+                    market_name: market.name.clone(),
+                    market_address: market.comet_address,
+                });
+                let event = match self.assess_market(provider.clone(), &market, Some(tx), deadline).await {
+                    Ok(assessment) => AssessmentEvent::MarketCompleted(Box::new(assessment)),
+                    Err(e) => AssessmentEvent::Error(MarketError {
+                        market_name: market.name.clone(),
+                        market_address: market.comet_address,
+                        error: e.to_string(),
+                    }),
+                };
+                let _ = tx.send(event);
+            })
+            .await;
+        };
+
+        // Drive the assessments alongside the receiver; the channel closes once the driver drops `tx`
+        let events = stream::poll_fn(move |cx| rx.poll_recv(cx));
+        let driver = stream::once(driver).filter_map(|()| async { None });
+        stream::select(events, driver)
+    }
+
+    /// Assess a specific market for risks and record the outcome in the engine's metrics
+    #[tracing::instrument(
+        level = "info",
+        skip_all,
+        fields(market = %market.name, market_address = ?market.comet_address, risk_score),
+        err(level = "debug")
+    )]
+    async fn assess_market(
+        &self,
+        provider: SharedProvider,
+        market: &models::Market,
+        events: Option<&mpsc::UnboundedSender<AssessmentEvent>>,
+        run_deadline: tokio::time::Instant,
+    ) -> Result<risk::RiskAssessment> {
+        let started = Instant::now();
+        self.units.observe(&self.config(), market);
+        // Checks still running at the deadline are cut off, leaving a partial assessment
+        let deadline = run_deadline.min(tokio::time::Instant::now() + self.config().timeouts.per_market());
+        let accounts = self.tracked_accounts(market).unwrap_or_else(|e| {
+            warn!("No borrower index for {}: {}", market.name, e);
+            Vec::new()
+        });
+        let plan = self.budget_plan(&provider, market, accounts);
+        let mut risk_processor = risk::RiskProcessor::with_provider(self.config(), provider.clone())
+            .with_accounts(plan.accounts.clone())
+            .with_skipped_checks(plan.skipped_checks.clone())
+            .with_checks(self.checks.clone())
+            .with_scorer(self.scorer.clone())
+            .with_position_history(self.position_history.clone())
+            .with_deadline(deadline);
+        match self.recent_base_flows(market) {
+            Ok(Some(flows)) => risk_processor = risk_processor.with_base_flows(flows),
+            Ok(None) => {}
+            Err(e) => warn!("No base flows for {}: {}", market.name, e),
+        }
+        let history = self.collateral_history(market, deadline).await;
+        if let Some((_, previous)) = history.last().filter(|(_, collateral)| !collateral.is_empty()) {
+            risk_processor = risk_processor.with_previous_collateral(previous.clone());
+        }
+        risk_processor = risk_processor.with_collateral_history(history);
+        let ((assessment, head_block), usage) = rpc::metered(async {
+            tokio::join!(risk_processor.assess_market_reporting(market, events), self.audit_head_block(&provider))
+        })
+        .await;
+        let mut assessment = assessment?;
+        if let Some(timed_out) = assessment.timed_out() {
+            warn!("Assessment of {} is partial, timed out: {}", market.name, timed_out);
+        }
+        if provider.rpc_budget().is_some() {
+            debug!(calls = usage.calls, compute_units = usage.compute_units, throttled_ms = usage.throttled_ms, "RPC usage of {}", market.name);
+            assessment.rpc = Some(Box::new(plan.report(usage)));
+        }
+        let mut assessment = self.with_trend(assessment, events).await;
+
+        // Reserves cost an extra read, so only fetch them while someone can scrape them or a
+        // baseline keeps them
+        let baseline = self.storage.is_some() && self.config().risk.baseline.enabled;
+        let exporting = self.metrics_exporter_running();
+        if exporting || baseline {
+            match tokio::time::timeout_at(deadline, provider.get_protocol_metrics(market)).await {
+                Err(_) => debug!("No protocol metrics for {}: timed out", market.name),
+                Ok(Err(e)) => debug!("No protocol metrics for {}: {}", market.name, e),
+                Ok(Ok(protocol)) => {
+                    if exporting {
+                        self.metrics.observe_reserves(market, protocol.reserves);
+                    }
+                    if baseline {
+                        assessment.reserves_usd = Some(protocol.reserves);
+                    }
+                }
+            }
+        }
+        let assessment = self.with_baseline(assessment, market, events).await;
+        let assessment = self.with_annotations(assessment, deadline).await;
+        self.metrics.observe_assessment(market, &assessment, started.elapsed());
+        tracing::Span::current().record("risk_score", assessment.risk_score);
+
+        // A failed write loses history but must not fail the assessment. Standbys leave the
+        // shared history to the leader.
+        if let Some(storage) = self.storage.as_ref().filter(|_| self.is_leader()) {
+            if let Err(e) = storage.record(self.config().compound.chain_id, market, &assessment).await {
+                warn!("Failed to store assessment of {} in {}: {}", assessment.market_name, storage.name(), e);
+            }
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(&assessment, head_block, self.last_refresh());
+        }
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(market.comet_address, assessment.clone());
+        Ok(assessment)
+    }
+
+    /// Collateral parameters of `market` in its latest stored snapshots, enough of them for
+    /// `risk.protocol_collateral.max_elevated_snapshots`, oldest first; empty without a store
+    /// or if they cannot be read by `deadline`, and none in snapshots stored before they were
+    async fn collateral_history(
+        &self,
+        market: &models::Market,
+        deadline: tokio::time::Instant,
+    ) -> Vec<(DateTime<Utc>, Vec<collateral_changes::CollateralParameters>)> {
+        let Some(storage) = self.storage.as_ref() else { return Vec::new() };
+        let count = self.config().risk.protocol_collateral.max_elevated_snapshots.saturating_sub(1).max(1) as u32;
+        match tokio::time::timeout_at(deadline, storage.latest_snapshots(market.comet_address, Utc::now(), count)).await {
+            Err(_) => debug!("No previous snapshots of {}: timed out", market.name),
+            Ok(Err(e)) => warn!("No previous snapshots of {} in {}: {}", market.name, storage.name(), e),
+            Ok(Ok(snapshots)) => return snapshots.into_iter().map(|snapshot| (snapshot.timestamp, snapshot.collateral)).collect(),
+        }
+        Vec::new()
+    }
+
+    /// Tracked `accounts` and checks of `market` fitted into its share of `rpc.budget`; every
+    /// account and check for providers not backed by a chain
+    fn budget_plan(&self, provider: &SharedProvider, market: &models::Market, accounts: Vec<Address>) -> budget::BudgetPlan {
+        let allowance = provider.rpc_budget().and_then(|budget| budget.market_allowance());
+        self.fit_budget(&market.name, allowance, accounts, budget::position_units(market))
+    }
+
+    /// `BudgetPlan` of the market `name` reading `accounts` within `allowance`, warning when
+    /// the market is projected over it
+    fn fit_budget(&self, name: &str, allowance: Option<u64>, accounts: Vec<Address>, position_units: u64) -> budget::BudgetPlan {
+        let config = self.config();
+        let watchlist: Vec<Address> = config.watchlist.iter().filter_map(|entry| utils::parse_address(entry).ok()).collect();
+        let plan = budget::BudgetPlan::new(&config.rpc.budget, allowance, accounts, &watchlist, position_units);
+        if let Some(allowance) = plan.allowance.filter(|allowance| plan.projected_compute_units > *allowance) {
+            let degradation = plan.report(Default::default()).degradation().unwrap_or_else(|| "reading everything".to_string());
+            warn!(
+                "{} is projected to use {} compute units, over its allowance of {}: {}",
+                name, plan.projected_compute_units, allowance, degradation,
+            );
+        }
+        plan
+    }
+
+    /// `assessment` with its score trend from the history store, plus a `RiskTrend` finding if
+    /// the smoothed score rose into a higher band; unchanged without a store or if it fails
+    ///
+    /// The finding leaves the risk score alone, since the trend is computed from that score.
+    async fn with_trend(
+        &self,
+        mut assessment: risk::RiskAssessment,
+        events: Option<&mpsc::UnboundedSender<AssessmentEvent>>,
+    ) -> risk::RiskAssessment {
+        let Some(storage) = &self.storage else { return assessment };
+        let config = &self.config().risk.trend;
+        let trend = match trend::ScoreTrend::load(storage.as_ref(), &assessment, config).await {
+            Ok(trend) => trend,
+            Err(e) => {
+                warn!("No score history for {} in {}: {}", assessment.market_name, storage.name(), e);
+                return assessment;
+            }
+        };
+        if let Some(finding) = trend.band_finding(&assessment, &config.bands) {
+            if let Some(events) = events {
+                let _ = events.send(AssessmentEvent::Finding {
+                    market_address: assessment.market_address,
+                    finding: finding.clone(),
+                });
+            }
+            assessment.findings.push(finding);
+        }
+        assessment.trend = Some(trend);
+        assessment.check_versions.insert("trend".to_string(), version::check_version("trend"));
+        assessment
+    }
+
+    /// `assessment` with its key metrics against the market's stored history, plus a finding
+    /// for each beyond its baseline (`baseline::check_deviation_from_baseline`); unchanged
+    /// without a store, with `risk.baseline.enabled` off, or if reading the history fails
+    ///
+    /// Like the trend's, the findings leave the risk score alone.
+    async fn with_baseline(
+        &self,
+        mut assessment: risk::RiskAssessment,
+        market: &models::Market,
+        events: Option<&mpsc::UnboundedSender<AssessmentEvent>>,
+    ) -> risk::RiskAssessment {
+        let Some(storage) = &self.storage else { return assessment };
+        let config = &self.config().risk.baseline;
+        if !config.enabled {
+            return assessment;
+        }
+        let history = match baseline::MarketBaseline::load(storage.as_ref(), &assessment, config).await {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("No baseline history for {} in {}: {}", assessment.market_name, storage.name(), e);
+                return assessment;
+            }
+        };
+        let comparison = baseline::BaselineComparison::compare(history, &assessment, market.utilization(), config);
+        for finding in baseline::check_deviation_from_baseline(&assessment, &comparison, config) {
+            if let Some(events) = events {
+                let _ = events.send(AssessmentEvent::Finding {
+                    market_address: assessment.market_address,
+                    finding: finding.clone(),
+                });
+            }
+            assessment.findings.push(finding);
+        }
+        assessment.baseline = Some(comparison);
+        assessment.check_versions.insert("baseline".to_string(), version::check_version("baseline"));
+        assessment
+    }
+
+    /// History store annotations are kept in; `Unavailable` without one
+    fn annotation_store(&self) -> Result<&Arc<dyn storage::Storage>> {
+        self.storage.as_ref().ok_or_else(|| RiskEngineError::Unavailable {
+            what: "annotations",
+            reason: "they are kept in the history store; set `storage.database_path` or `storage.postgres`".to_string(),
+        })
+    }
+
+    /// `assessment` with the latest annotation of each of its findings from the history
+    /// store; unchanged without a store or if it cannot be read by `deadline`
+    async fn with_annotations(&self, mut assessment: risk::RiskAssessment, deadline: tokio::time::Instant) -> risk::RiskAssessment {
+        let Some(storage) = &self.storage else { return assessment };
+        let mut fingerprints: Vec<String> = assessment.findings.iter().map(|f| f.fingerprint.clone()).collect();
+        fingerprints.sort();
+        fingerprints.dedup();
+        if fingerprints.is_empty() {
+            return assessment;
+        }
+        match tokio::time::timeout_at(deadline, storage.latest_annotations(&fingerprints)).await {
+            Err(_) => debug!("No annotations of {}: timed out", assessment.market_name),
+            Ok(Err(e)) => warn!("No annotations of {} in {}: {}", assessment.market_name, storage.name(), e),
+            Ok(Ok(annotations)) => assessment.annotations = annotations,
+        }
+        assessment
+    }
+
+    /// Chain head for the audit entry, read alongside the market data; `None` without an
+    /// audit log, for providers not backed by a chain, or if the read is slow or fails
+    async fn audit_head_block(&self, provider: &SharedProvider) -> Option<u64> {
+        self.audit.as_ref()?;
+        read_head_block(provider).await
+    }
+
+    /// Chain head, as recorded with results saved through `archive`; `None` for providers not
+    /// backed by a chain, or if the read is slow or fails
+    pub async fn head_block(&self) -> Option<u64> {
+        read_head_block(&self.provider()).await
+    }
+}
+
+/// Start counting the RPC usage of a run against `rpc.budget`
+fn start_rpc_run(provider: &SharedProvider) {
+    if let Some(budget) = provider.rpc_budget() {
+        budget.start_run();
+    }
+}
+
+/// Share what is left of the run's `rpc.budget` among `markets` markets
+fn expect_markets(provider: &SharedProvider, markets: usize) {
+    if let Some(budget) = provider.rpc_budget() {
+        budget.expect_markets(markets);
+    }
+}
+
+async fn read_head_block(provider: &SharedProvider) -> Option<u64> {
+    let source = provider.log_source()?;
+    match tokio::time::timeout(HEAD_BLOCK_TIMEOUT, source.head_block()).await {
+        Ok(Ok(block)) => Some(block),
+        Ok(Err(e)) => {
+            debug!("No head block: {}", e);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Data provider for `config`, plus the subgraph source if it reads from one
+async fn open_provider(
+    config: &Arc<config::Config>,
+    metrics: &Arc<metrics::Metrics>,
+    cancel: &CancellationToken,
+    progress: &progress::ProgressReporter,
+) -> Result<(SharedProvider, Option<Arc<subgraph::SubgraphProvider>>)> {
+    match config.data_source {
+        config::DataSource::Live => {
+            let rpc: SharedProvider = Arc::new(
+                compound::CompoundClient::new_with_metrics(config.clone(), metrics.clone())
+                    .await?
+                    .with_cancellation(cancel.child_token())
+                    .with_progress(progress.clone()),
+            );
+            if !config.subgraph.in_use() {
+                return Ok((rpc, None));
+            }
+            let source = Arc::new(subgraph::SubgraphProvider::from_config(config)?.with_chain_head(rpc.log_source()));
+            Ok((Arc::new(subgraph::RoutedProvider::new(rpc, source.clone(), &config.subgraph)), Some(source)))
+        }
+        config::DataSource::Mock => {
+            warn!("Using the bundled MOCK dataset; results do not reflect on-chain state");
+            Ok((Arc::new(FixtureProvider::demo()), None))
+        }
+    }
+}
+
+/// `config` with the ENS names in its watchlist replaced by the addresses they resolve to
+///
+/// Without an ENS registry (mock data) names are dropped with a warning.
+async fn resolve_watchlist(mut config: config::Config, provider: &SharedProvider) -> Result<config::Config> {
+    if !config.watchlist.iter().any(|entry| ens::is_ens_name(entry)) {
+        return Ok(config);
+    }
+    let Some(resolver) = provider.name_resolver() else {
+        warn!("Ignoring ENS names in the watchlist: the data source has no ENS registry");
+        config.watchlist.retain(|entry| !ens::is_ens_name(entry));
+        return Ok(config);
+    };
+    for (i, entry) in config.watchlist.iter_mut().enumerate() {
+        if !ens::is_ens_name(entry) {
+            continue;
+        }
+        let address = resolver
+            .resolve(entry)
+            .await
+            .map_err(|e| RiskEngineError::config(format!("watchlist[{}]", i), e.to_string()))?;
+        info!("Watchlist entry {} resolves to {}", entry, utils::to_checksum_address(&address));
+        *entry = utils::to_checksum_address(&address);
+    }
+    Ok(config)
+}
+
+/// Top-level sections whose settings differ between `old` and `new`, in name order
+fn changed_sections(old: &config::Config, new: &config::Config) -> Result<Vec<String>> {
+    let old = serde_json::to_value(old).map_err(|e| RiskEngineError::serialization("config", e))?;
+    let new = serde_json::to_value(new).map_err(|e| RiskEngineError::serialization("config", e))?;
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Ok(Vec::new());
+    };
+    let mut changed: Vec<String> = new
+        .iter()
+        .filter(|(section, value)| old.get(*section) != Some(*value))
+        .map(|(section, _)| section.clone())
+        .collect();
+    changed.sort();
+    Ok(changed)
+}
+
+/// Run `f` over `items` with at most `limit` futures in flight, returning outputs in input order
+async fn run_bounded<T, F, Fut, R>(items: Vec<T>, limit: usize, f: F) -> Vec<R>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = R>,
+{
+    let mut indexed: Vec<(usize, R)> = stream::iter(items.into_iter().enumerate())
+        .map(|(i, item)| {
+            let fut = f(item);
+            async move { (i, fut.await) }
+        })
+        .buffer_unordered(limit.max(1))
+        .collect()
+        .await;
+
+    indexed.sort_by_key(|(i, _)| *i);
+    indexed.into_iter().map(|(_, r)| r).collect()
+}
+
+/// The market with Comet proxy `market`, or `RiskEngineError::NotFound` if `provider` does not list it
+async fn find_market(provider: &dyn provider::MarketDataProvider, market: Address) -> Result<models::Market> {
+    provider
+        .get_markets()
+        .await?
+        .into_iter()
+        .find(|m| m.comet_address == market)
+        .ok_or_else(|| RiskEngineError::NotFound { kind: "market", id: utils::to_checksum_address(&market) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Engine over the single-market `basic.json` fixture
+    fn fixture_engine(config: config::Config) -> RiskEngine {
+        let fixture = FixtureProvider::from_file(&provider::bundled_fixture("basic.json")).unwrap();
+        RiskEngine::with_provider(config, Arc::new(fixture))
+    }
+
+    #[tokio::test]
+    async fn test_risk_engine_creation() {
+        let config = config::Config::default();
+        let engine = RiskEngine::new(config).await.unwrap();
+        assert!(!engine.is_mock_data().await);
+    }
+
+    #[tokio::test]
+    async fn test_mock_mode_marks_every_assessment() {
+        let mut config = config::Config::default();
+        config.data_source = config::DataSource::Mock;
+        let engine = RiskEngine::new(config).await.unwrap();
+        assert!(engine.is_mock_data().await);
+
+        let assessments = engine.assess_risks().await.unwrap();
+        assert!(assessments.len() >= 3);
+        assert!(assessments.iter().all(|a| a.mock_data));
+        assert!(assessments.iter().any(|a| !a.findings.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_assess_risks_stream_matches_batch() {
+        let engine = fixture_engine(config::Config::default());
+
+        let batch = engine.assess_risks().await.unwrap();
+        let events: Vec<AssessmentEvent> = engine.assess_risks_stream().collect().await;
+
+        assert!(matches!(events.first(), Some(AssessmentEvent::MarketStarted { .. })));
+        let streamed_findings = events
+            .iter()
+            .filter(|e| matches!(e, AssessmentEvent::Finding { .. }))
+            .count();
+        let completed: Vec<&risk::RiskAssessment> = events
+            .iter()
+            .filter_map(|e| match e {
+                AssessmentEvent::MarketCompleted(a) => Some(a.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(completed.len(), batch.len());
+        assert_eq!(streamed_findings, batch[0].findings.len());
+        assert_eq!(completed[0].risk_score, batch[0].risk_score);
+        assert_eq!(completed[0].findings[0].description, batch[0].findings[0].description);
+    }
+
+    #[tokio::test]
+    async fn test_scan_positions_keeps_going_past_unreadable_accounts() {
+        let engine = fixture_engine(config::Config::default());
+        let usdc = utils::parse_address("0xc3d688B66703497DAA19211EEdff47f25384cdc3").unwrap();
+        let list = position_scan::AddressList::parse(&format!("0x{}\nnot-an-address\n0x{}\n0x{}\n", "11".repeat(20), "33".repeat(20), "22".repeat(20)));
+
+        let scan = engine.scan_positions(usdc, &list).await.unwrap();
+        let health: Vec<f64> = scan.positions.iter().map(|p| p.position.health_factor).collect();
+        assert_eq!(health, [1.04, 1.82]);
+        assert!(scan.positions[0].finding.is_some() && scan.positions[1].finding.is_none());
+        assert_eq!((scan.summary.total_borrow_value, scan.summary.at_risk), (2750.0, 1));
+        assert_eq!(scan.failed[0].address, Address::repeat_byte(0x33));
+        assert_eq!(scan.invalid_lines[0].line, 2);
+
+        let missing = engine.scan_positions(Address::repeat_byte(0x99), &list).await.unwrap_err();
+        assert!(matches!(missing, RiskEngineError::NotFound { .. }), "{}", missing);
+    }
+
+    #[tokio::test]
+    async fn test_background_refresh_lifecycle() {
+        let engine = fixture_engine(config::Config::default());
+        assert!(engine.last_refresh().is_none());
+
+        engine.start_background_refresh(Duration::from_secs(3600)).unwrap();
+        assert!(engine.start_background_refresh(Duration::from_secs(3600)).is_err());
+
+        // The first refresh runs immediately
+        for _ in 0..50 {
+            if engine.last_refresh().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(engine.last_refresh().is_some());
+
+        engine.shutdown().await;
+        // Once stopped it can be started again
+        engine.start_background_refresh(Duration::from_secs(3600)).unwrap();
+        engine.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_run_scheduled_broadcasts_to_all_subscribers() {
+        let mut config = config::Config::default();
+        // Leave utilization as the only finding
+        config.risk.max_price_volatility = 1.0;
+        let engine = fixture_engine(config);
+
+        let mut first = engine.subscribe();
+        let mut second = engine.subscribe();
+        let cancel = CancellationToken::new();
+
+        let scheduler = engine.run_scheduled(Duration::from_millis(20), cancel.clone());
+        let listener = async {
+            let mut received = Vec::new();
+            // Two runs: the second produces only a completion event
+            while received.len() < 3 {
+                received.push(first.recv().await.unwrap());
+            }
+            cancel.cancel();
+            received
+        };
+        let (result, received) = tokio::join!(scheduler, listener);
+        result.unwrap();
+
+        assert!(matches!(received[0], RiskEvent::NewFinding { .. }));
+        assert!(matches!(received[1], RiskEvent::AssessmentCompleted(_)));
+        assert!(matches!(received[2], RiskEvent::AssessmentCompleted(_)));
+        assert!(matches!(second.recv().await.unwrap(), RiskEvent::NewFinding { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_run_scheduled_times_stops_and_drains_alerts() {
+        let server = crate::testing::CaptureServer::new();
+        let mut config = config::Config::default();
+        config.risk.max_price_volatility = 1.0;
+        config.alerts.webhooks.push(config::WebhookConfig {
+            name: None,
+            url: server.serve().await,
+            filter: Default::default(),
+            format: Default::default(),
+        });
+        let engine = fixture_engine(config);
+        let mut events = engine.subscribe();
+
+        let scheduler = engine.run_scheduled_times(Duration::from_millis(10), Some(2), CancellationToken::new());
+        tokio::time::timeout(Duration::from_secs(10), scheduler).await.unwrap().unwrap();
+
+        let mut completed = 0;
+        while let Ok(event) = events.try_recv() {
+            completed += matches!(event, RiskEvent::AssessmentCompleted(_)) as usize;
+        }
+        assert_eq!(completed, 2);
+        let last_run = engine.last_run().unwrap();
+        assert_eq!((last_run.assessed, last_run.errors.len()), (1, 0));
+        // The High utilization finding was alerted once, before the scheduler returned
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_markets_are_assessed_at_their_own_interval() {
+        let weth = "0xA17581A9E3356d9A858b789D68B4d866e593aE94";
+        let mut config = config::Config::default();
+        config.schedule.intervals.insert(weth.to_string(), 30);
+        let engine = RiskEngine::with_provider(config, Arc::new(FixtureProvider::demo()));
+        let mut events = engine.subscribe();
+
+        // At 0s all three markets, at 30s WETH alone, at 60s all three again
+        let started = tokio::time::Instant::now();
+        engine.run_scheduled_times(Duration::from_secs(60), Some(3), CancellationToken::new()).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+        assert_eq!(engine.last_run().unwrap().assessed, 3);
+
+        let mut completed = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let RiskEvent::AssessmentCompleted(assessment) = event {
+                completed.push(*assessment);
+            }
+        }
+        let names: Vec<&str> = completed.iter().map(|a| a.market_name.as_str()).collect();
+        assert_eq!(names, ["USDC", "WETH", "USDT", "WETH", "USDC", "WETH", "USDT"]);
+
+        let cadence = |i: usize| completed[i].cadence.clone().unwrap();
+        assert_eq!((cadence(0).interval_ms, cadence(1).interval_ms), (60_000, 30_000));
+        assert!(cadence(1).previous_assessed_at.is_none());
+        assert_eq!(cadence(3).previous_assessed_at, Some(completed[1].timestamp));
+        assert_eq!(cadence(4).previous_assessed_at, Some(completed[0].timestamp));
+        let text = engine.metrics().render();
+        assert!(text.contains(r#"cometguard_market_assessment_interval_seconds{chain_id="1",market="WETH"} 30"#), "{}", text);
+        assert!(text.contains(r#"cometguard_market_assessment_interval_seconds{chain_id="1",market="USDT"} 60"#));
+        assert!(text.contains(r#"cometguard_market_last_assessed_timestamp_seconds{chain_id="1",market="USDT"}"#));
+    }
+
+    /// The `basic.json` fixture on a chain whose head block is the second field
+    struct FixtureAtHead(FixtureProvider, u64);
+
+    struct Head(u64);
+
+    #[async_trait::async_trait]
+    impl scanner::LogSource for Head {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(self.0)
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, _from: u64, _to: u64) -> Result<Vec<Address>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl provider::MarketDataProvider for FixtureAtHead {
+        async fn get_markets(&self) -> Result<Vec<models::Market>> {
+            self.0.get_markets().await
+        }
+
+        async fn get_user_position(&self, market: &models::Market, user: Address) -> Result<models::UserPosition> {
+            self.0.get_user_position(market, user).await
+        }
+
+        async fn get_price_history(&self, market: &models::Market, asset: Address) -> Result<models::PriceHistory> {
+            self.0.get_price_history(market, asset).await
+        }
+
+        async fn get_protocol_metrics(&self, market: &models::Market) -> Result<models::ProtocolMetrics> {
+            self.0.get_protocol_metrics(market).await
+        }
+
+        fn log_source(&self) -> Option<Arc<dyn scanner::LogSource>> {
+            Some(Arc::new(Head(self.1)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_week_old_state_is_reconciled_before_alerting() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = crate::testing::CaptureServer::new();
+        let mut config = config::Config::default();
+        config.risk.max_price_volatility = 1.0;
+        config.alerts.state_path = Some(dir.path().join("alert-state.json"));
+        config.scanner.index_path = Some(dir.path().join("borrowers.json"));
+        config.scanner.chunk_size = 1_000;
+        config.alerts.webhooks.push(config::WebhookConfig {
+            name: None,
+            url: server.serve().await,
+            filter: Default::default(),
+            format: Default::default(),
+        });
+        let fixture = || FixtureAtHead(FixtureProvider::from_file(&provider::bundled_fixture("basic.json")).unwrap(), 19_050_400);
+        let utilization = RiskEngine::with_provider(config.clone(), Arc::new(fixture())).assess_risks().await.unwrap()[0]
+            .findings
+            .iter()
+            .find(|f| f.category == risk::RiskCategory::HighUtilization)
+            .unwrap()
+            .clone();
+        let market = utils::parse_address(&config.compound.comet_proxy_address).unwrap();
+
+        // The state a week-old engine left behind: an index 50400 blocks behind and two findings
+        // notified back then, one the market no longer has
+        let mut index = scanner::BorrowerIndex::new(market);
+        index.last_scanned_block = Some(19_000_000);
+        index.borrowers = ["0x1111111111111111111111111111111111111111", "0x2222222222222222222222222222222222222222", "0x9999999999999999999999999999999999999999"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        index.save(config.scanner.index_path.as_ref().unwrap()).unwrap();
+        let saved_index = std::fs::read_to_string(config.scanner.index_path.as_ref().unwrap()).unwrap();
+        let week_ago = Utc::now() - chrono::Duration::days(7);
+        let state = alerts::AlertStateStore::new(config.alerts.state_path.clone());
+        let gone = risk::RiskFinding { fingerprint: "0123456789abcdef".to_string(), ..utilization.clone() };
+        for finding in [&utilization, &gone] {
+            let event = RiskEvent::NewFinding { market_name: "USDC".to_string(), market_address: market, finding: finding.clone() };
+            let alert = alerts::Alert::from_event(&event, 1).unwrap();
+            state.observe(&alert, week_ago).unwrap();
+            state.notified(&alert, week_ago).unwrap();
+        }
+        state.resolve_missing(market, &[utilization.fingerprint.as_str(), "0123456789abcdef"].into(), week_ago).unwrap();
+
+        let engine = RiskEngine::with_provider(config.clone(), Arc::new(fixture()));
+        engine.run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+
+        // The week-old notification neither suppressed the finding still there nor resolved
+        // the one that is gone on any route: the only alert is the fresh assessment's
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1, "{:?}", requests);
+        assert!(requests[0].to_string().contains(&utilization.fingerprint));
+        let gone = state.get("0123456789abcdef").unwrap().unwrap();
+        assert!(gone.stale && gone.resolved_at.is_some());
+        let current = state.get(&utilization.fingerprint).unwrap().unwrap();
+        assert!(!current.stale && current.first_seen > week_ago && current.last_notified > Some(week_ago));
+
+        // The index is kept as it was for the next scan to bring up to date
+        assert_eq!(std::fs::read_to_string(config.scanner.index_path.as_ref().unwrap()).unwrap(), saved_index);
+        let reconciliation = engine.reconcile().await.unwrap();
+        let lag = reconciliation.index.unwrap();
+        assert_eq!((lag.blocks_behind, lag.catch_up_requests, lag.borrowers), (50_400, 51, 3));
+        assert_eq!(reconciliation.positions, Some(reconcile::PositionCheck { sampled: 3, borrowing: 2, closed: 0, unreadable: 1 }));
+        assert!(reconciliation.stale_findings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_reconciliation_alerts_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = crate::testing::CaptureServer::new();
+        let mut config = config::Config::default();
+        config.scanner.index_path = Some(dir.path().join("borrowers.json"));
+        config.alerts.webhooks.push(config::WebhookConfig {
+            name: None,
+            url: server.serve().await,
+            filter: Default::default(),
+            format: Default::default(),
+        });
+        std::fs::write(config.scanner.index_path.as_ref().unwrap(), "{ not an index").unwrap();
+
+        let err = fixture_engine(config).run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap_err();
+        assert!(err.to_string().contains("--skip-reconcile"), "{}", err);
+        assert!(server.requests().is_empty(), "{:?}", server.requests());
+    }
+
+    #[tokio::test]
+    async fn test_skipped_reconciliation_leaves_the_state_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config::Config::default();
+        config.reconcile.enabled = false;
+        config.alerts.state_path = Some(dir.path().join("alert-state.json"));
+        let state = alerts::AlertStateStore::new(config.alerts.state_path.clone());
+        let finding = risk::RiskFinding {
+            description: "Utilization at 95%".to_string(),
+            ..testing::finding("0123456789abcdef", risk::RiskSeverity::High)
+        };
+        // Of a market the engine does not assess, so only reconciliation could resolve it
+        let event = RiskEvent::NewFinding { market_name: "Other".to_string(), market_address: Address::repeat_byte(0x46), finding };
+        let alert = alerts::Alert::from_event(&event, 1).unwrap();
+        state.notified(&alert, Utc::now() - chrono::Duration::days(7)).unwrap();
+
+        fixture_engine(config).run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+        assert_eq!(state.get("0123456789abcdef").unwrap().unwrap().resolved_at, None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_only_the_leader_alerts_and_writes_history() {
+        use storage::Storage;
+
+        // Two engines sharing one history, as they would share a database
+        let history = Arc::new(storage::SqliteStorage::open_in_memory().unwrap());
+        let leases = crate::testing::MemoryLeases::default();
+        let mut engines = Vec::new();
+        for holder in ["a", "b"] {
+            let server = crate::testing::CaptureServer::new();
+            let mut config = config::Config::default();
+            config.risk.max_price_volatility = 1.0;
+            config.alerts.webhooks.push(config::WebhookConfig {
+                name: None,
+                url: server.serve().await,
+                filter: Default::default(),
+                format: Default::default(),
+            });
+            let leadership = leader::Leadership::new(leases.handle(holder), "cometguard", Duration::from_millis(400), Duration::from_millis(100));
+            let engine = fixture_engine(config).with_storage(history.clone()).with_leadership(Arc::new(leadership));
+            engine.start_leader_heartbeat().await.unwrap();
+            engines.push((engine, server));
+        }
+        let [(a, a_alerts), (b, b_alerts)] = <[_; 2]>::try_from(engines).ok().unwrap();
+        let role = |engine: &RiskEngine| engine.leader_status().unwrap().role;
+        assert_eq!((role(&a), role(&b)), (leader::Role::Leader, leader::Role::Standby));
+        assert_eq!(b.health().await.leadership.unwrap().holder, "b");
+
+        for engine in [&a, &b] {
+            engine.run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+        }
+        // The standby assessed too, but only the leader alerted and wrote history
+        assert!(b.latest_assessments().len() == 1 && b_alerts.requests().is_empty());
+        assert_eq!(a_alerts.requests().len(), 1);
+        assert_eq!(history.markets().await.unwrap()[0].assessments, 1);
+
+        // Shutting down hands the lease over at the standby's next heartbeat
+        a.shutdown().await;
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(role(&b), leader::Role::Leader);
+        b.run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+        assert_eq!(b_alerts.requests().len(), 1);
+        assert_eq!(history.markets().await.unwrap()[0].assessments, 2);
+        b.shutdown().await;
+    }
+
+    /// Storage whose reads never answer, like a database behind a dropped connection
+    struct HungStorage;
+
+    #[async_trait::async_trait]
+    impl storage::Storage for HungStorage {
+        fn name(&self) -> String {
+            "hung".to_string()
+        }
+
+        async fn record(&self, _chain_id: u64, _market: &models::Market, _assessment: &risk::RiskAssessment) -> Result<()> {
+            Ok(())
+        }
+
+        async fn markets(&self) -> Result<Vec<storage::StoredMarket>> {
+            std::future::pending().await
+        }
+
+        async fn score_series(&self, _market: Address, _since: DateTime<Utc>, _until: DateTime<Utc>) -> Result<Vec<storage::ScorePoint>> {
+            std::future::pending().await
+        }
+
+        async fn snapshots(&self, _market: Address, _since: DateTime<Utc>, _until: DateTime<Utc>) -> Result<Vec<storage::MarketSnapshot>> {
+            std::future::pending().await
+        }
+
+        async fn latest_snapshots(&self, _market: Address, _until: DateTime<Utc>, _count: u32) -> Result<Vec<storage::MarketSnapshot>> {
+            std::future::pending().await
+        }
+
+        async fn findings(&self, _query: &storage::FindingQuery) -> Result<Vec<storage::StoredFinding>> {
+            std::future::pending().await
+        }
+
+        async fn fingerprint_spans(&self, _query: &storage::FindingQuery) -> Result<Vec<storage::FingerprintSpan>> {
+            std::future::pending().await
+        }
+
+        async fn annotate(&self, _annotation: &annotations::Annotation) -> Result<()> {
+            std::future::pending().await
+        }
+
+        async fn annotations(&self, _fingerprints: &[String]) -> Result<Vec<annotations::Annotation>> {
+            std::future::pending().await
+        }
+    }
+
+    /// The `basic.json` fixture with price history, read by the volatility and liquidator margin
+    /// checks, that takes an hour to read
+    struct SlowProvider(FixtureProvider);
+
+    #[async_trait::async_trait]
+    impl provider::MarketDataProvider for SlowProvider {
+        async fn get_markets(&self) -> Result<Vec<models::Market>> {
+            self.0.get_markets().await
+        }
+
+        async fn get_user_position(&self, market: &models::Market, user: Address) -> Result<models::UserPosition> {
+            self.0.get_user_position(market, user).await
+        }
+
+        async fn get_price_history(&self, market: &models::Market, asset: Address) -> Result<models::PriceHistory> {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            self.0.get_price_history(market, asset).await
+        }
+
+        async fn get_protocol_metrics(&self, market: &models::Market) -> Result<models::ProtocolMetrics> {
+            self.0.get_protocol_metrics(market).await
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timed_out_checks_leave_a_partial_assessment() {
+        // Cut off by the market's time limit, then by the run's
+        for (per_market, total, cut_off_after) in [(60, 600, 60), (120, 30, 30)] {
+            let mut config = config::Config::default();
+            config.timeouts.per_market = per_market;
+            config.timeouts.total = total;
+            let fixture = FixtureProvider::from_file(&provider::bundled_fixture("basic.json")).unwrap();
+            let engine = RiskEngine::with_provider(config, Arc::new(SlowProvider(fixture)));
+
+            let started = tokio::time::Instant::now();
+            let assessments = engine.assess_risks().await.unwrap();
+            assert_eq!(started.elapsed(), Duration::from_secs(cut_off_after));
+            let assessment = &assessments[0];
+            assert!(!assessment.complete);
+            assert_eq!(assessment.timed_out().as_deref(), Some("price_volatility, liquidator_margin"));
+            let timed_out = assessment.findings.iter().find(|f| f.category == risk::RiskCategory::DataQuality).unwrap();
+            assert_eq!(timed_out.severity, risk::RiskSeverity::Medium);
+            assert_eq!(timed_out.metadata["kind"], "timed_out");
+            // What finished in time is still there
+            assert!(assessment.findings.iter().any(|f| f.category == risk::RiskCategory::HighUtilization));
+            assert!(assessment.exposure.is_some());
+        }
+
+        // The streaming run ends the same way
+        let fixture = FixtureProvider::from_file(&provider::bundled_fixture("basic.json")).unwrap();
+        let engine = RiskEngine::with_provider(config::Config::default(), Arc::new(SlowProvider(fixture)));
+        let events: Vec<_> = engine.assess_risks_stream().collect().await;
+        let completed = events.iter().find_map(|event| match event {
+            AssessmentEvent::MarketCompleted(assessment) => Some(assessment),
+            _ => None,
+        });
+        assert!(!completed.unwrap().complete);
+
+        // Without slow reads the assessment is complete
+        let assessments = fixture_engine(config::Config::default()).assess_risks().await.unwrap();
+        assert!(assessments[0].complete && assessments[0].timed_out().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_plan_resolves_the_run_without_reading_the_chain() {
+        let mut config = config::Config::default();
+        // Nothing listens here: any request would fail the build or the plan
+        config.compound.rpc_url = "http://127.0.0.1:9".to_string();
+        config.markets = vec!["mainnet:WETH".to_string()];
+        config.watchlist = vec![
+            "0x1111111111111111111111111111111111111111".to_string(),
+            "0x2222222222222222222222222222222222222222".to_string(),
+            "0x3333333333333333333333333333333333333333".to_string(),
+            "vitalik.eth".to_string(),
+        ];
+        config.rpc.budget.per_run_compute_units = Some(4_000);
+        let engine = RiskEngine::builder().config(config).offline().build().await.unwrap();
+
+        let plan = engine.plan().await.unwrap();
+        let names: Vec<&str> = plan.markets.iter().map(|market| market.market_name.as_str()).collect();
+        assert_eq!(names, ["USDC", "WETH"]);
+        assert_eq!(plan.unresolved_watchlist, ["vitalik.eth"]);
+        assert!(!plan.mock_data);
+        assert_eq!(plan.data_sources.positions, "rpc 127.0.0.1 (live)");
+
+        // Two market reads of 1050 units leave 950 per market, short of the 2049 the three
+        // watchlist positions cost with every check: the optional checks go
+        let usdc = &plan.markets[0];
+        assert_eq!((usdc.collateral_assets, usdc.tracked_accounts, usdc.allowance), (None, 3, Some(950)));
+        assert_eq!(usdc.skipped_checks, budget::OPTIONAL_CHECKS);
+        assert!(usdc.checks.contains(&"watchlist".to_string()) && !usdc.checks.contains(&"absorption".to_string()));
+        assert_eq!((usdc.rpc_calls, usdc.compute_units), (41 + 79, 1_050 + 2_049));
+        assert_eq!(plan.compute_units(), 2 * 3_099);
+
+        // Fixture data is read for the markets and costs no RPC
+        let plan = fixture_engine(config::Config::default()).plan().await.unwrap();
+        let market = &plan.markets[0];
+        assert_eq!((market.collateral_assets, market.rpc_calls, market.allowance), (Some(1), 0, None));
+        assert!(plan.mock_data && plan.to_string().starts_with("Dry run [MOCK DATA]"));
+    }
+
+    /// Check that takes `.0` and finds nothing
+    struct Sleeping(Duration);
+
+    #[async_trait::async_trait]
+    impl risk::RiskCheck for Sleeping {
+        fn name(&self) -> String {
+            "sleeping".to_string()
+        }
+
+        async fn check(&self, _market: &models::Market, _provider: Option<&SharedProvider>, _timestamp: DateTime<Utc>) -> Result<Vec<risk::RiskFinding>> {
+            tokio::time::sleep(self.0).await;
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_checks_show_in_the_run_and_in_diagnostics() {
+        use diagnostics::CheckStatus;
+
+        let engine = RiskEngine::builder()
+            .config(config::Config::default())
+            .provider(FixtureProvider::from_file(&provider::bundled_fixture("basic.json")).unwrap())
+            .add_check(Sleeping(Duration::from_secs(50)))
+            .build()
+            .await
+            .unwrap();
+        let report = engine.diagnostics().await;
+        assert_eq!(report.check("check timings").unwrap().message, "no checks timed yet");
+
+        engine.run_scheduled_times(Duration::from_secs(60), Some(1), CancellationToken::new()).await.unwrap();
+        // 50s of a 60s interval is within the last fifth of it
+        let run = engine.last_run().unwrap();
+        assert_eq!(run.duration_ms, 50_000);
+        assert!(run.nears_interval(Duration::from_secs(60)));
+        assert!(!run.nears_interval(Duration::from_secs(120)));
+        assert!(!run.nears_interval(Duration::ZERO));
+
+        let report = engine.diagnostics().await;
+        let timings = report.check("check timings").unwrap();
+        assert_eq!(timings.status, CheckStatus::Warn);
+        assert!(
+            timings.message.starts_with("slowest: sleeping in USDC (50000ms, 0 RPC call(s)), ")
+                && timings.message.ends_with("; over `performance.check_budget_ms` (5000ms)"),
+            "{}",
+            timings.message
+        );
+        assert!(!report.is_failing());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_fail_hung_probes_on_their_own() {
+        use diagnostics::CheckStatus;
+
+        let server = crate::testing::CaptureServer::new();
+        let mut config = config::Config::default();
+        config.risk.max_price_volatility = 1.0;
+        config.alerts.webhooks.push(config::WebhookConfig {
+            name: None,
+            url: server.serve().await,
+            filter: Default::default(),
+            format: Default::default(),
+        });
+        let engine = fixture_engine(config);
+        engine.run_scheduled_times(Duration::ZERO, Some(1), CancellationToken::new()).await.unwrap();
+        let report = engine.diagnostics().await;
+        assert_eq!(report.status, CheckStatus::Pass);
+        assert_eq!(report.check("rpc (mainnet)").unwrap().message, "the data source does not read from a chain");
+        let route = report.checks.iter().find(|c| c.name.starts_with("alerts (")).unwrap();
+        assert_eq!(route.message, "last delivered just now");
+
+        let hung = fixture_engine(config::Config::default()).with_storage(Arc::new(HungStorage));
+        let started = Instant::now();
+        let report = hung.diagnostics_within(Duration::from_millis(50)).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(report.is_failing());
+        let storage = report.check("storage").unwrap();
+        assert_eq!(storage.status, CheckStatus::Fail);
+        assert!(storage.message.contains("no answer within"), "{}", storage.message);
+        // Every other probe still reported
+        assert!(report.checks.iter().filter(|c| c.name != "storage").all(|c| c.status == CheckStatus::Pass));
+    }
+
+    #[tokio::test]
+    async fn test_restart_does_not_alert_on_ongoing_findings_again() {
+        let server = crate::testing::CaptureServer::new();
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config::Config::default();
+        config.risk.max_price_volatility = 1.0;
+        config.alerts.state_path = Some(dir.path().join("alert-state.json"));
+        config.alerts.webhooks.push(config::WebhookConfig {
+            name: None,
+            url: server.serve().await,
+            filter: Default::default(),
+            format: Default::default(),
+        });
+        async fn run_once(engine: &RiskEngine) {
+            let scheduler = engine.run_scheduled_times(Duration::from_millis(10), Some(1), CancellationToken::new());
+            tokio::time::timeout(Duration::from_secs(10), scheduler).await.unwrap().unwrap();
+        }
+
+        run_once(&fixture_engine(config.clone())).await;
+        assert_eq!(server.requests().len(), 1);
+        let fingerprint = server.requests()[0]["finding"]["fingerprint"].as_str().unwrap().to_string();
+
+        // A new engine starts with an empty finding tracker, as after a restart
+        let restarted = fixture_engine(config.clone());
+        run_once(&restarted).await;
+        assert_eq!(server.requests().len(), 1);
+
+        let record = restarted.acknowledge_finding(&fingerprint).unwrap();
+        assert_eq!(record.acknowledged.map(|ack| ack.severity), Some(risk::RiskSeverity::High));
+        assert!(matches!(restarted.acknowledge_finding("unknown"), Err(RiskEngineError::NotFound { .. })));
+        // Another process reads the acknowledgement from the state file
+        let state = alerts::AlertStateStore::new(config.alerts.state_path.clone());
+        assert!(state.get(&fingerprint).unwrap().unwrap().acknowledged.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_swaps_valid_configs_and_rebuilds_the_provider() {
+        let engine = fixture_engine(config::Config::default());
+        let mut events = engine.subscribe();
+
+        let mut invalid = config::Config::default();
+        invalid.risk.max_utilization_threshold = -1.0;
+        assert!(engine.reload_config(invalid).await.is_err());
+        assert_eq!(engine.config().risk.max_utilization_threshold, 0.85);
+        assert!(engine.reload_config(config::Config::default()).await.unwrap().is_empty());
+        assert!(events.try_recv().is_err());
+
+        let mut config = config::Config::default();
+        config.risk.max_utilization_threshold = 0.95;
+        config.data_source = config::DataSource::Mock;
+        let changed = engine.reload_config(config).await.unwrap();
+        assert_eq!(changed, ["data_source", "risk"]);
+        assert!(matches!(events.try_recv().unwrap(), RiskEvent::ConfigReloaded { changed: c } if c == changed));
+        assert_eq!(engine.config().risk.max_utilization_threshold, 0.95);
+        // The single-market fixture was replaced by the demo dataset
+        assert!(engine.assess_risks().await.unwrap().len() >= 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_assessments_survive_provider_reloads() {
+        let config = config::Config { data_source: config::DataSource::Mock, ..config::Config::default() };
+        let engine = Arc::new(fixture_engine(config));
+        let demo_markets = FixtureProvider::demo().get_markets().await.unwrap().len();
+
+        let runs: Vec<_> = (0..8)
+            .map(|_| {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    let mut sizes = Vec::new();
+                    for _ in 0..3 {
+                        sizes.push(engine.assess_risks().await.unwrap().len());
+                        tokio::task::yield_now().await;
+                    }
+                    sizes
+                })
+            })
+            .collect();
+        let reloads = {
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                for _ in 0..5 {
+                    engine.reload_provider().await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        reloads.await.unwrap();
+        for run in runs {
+            // Every run read all its markets from one provider: the fixture's or the demo set
+            for size in run.await.unwrap() {
+                assert!(size == 1 || size == demo_markets, "{} markets", size);
+            }
+        }
+        assert!(engine.is_mock_data().await);
+        assert_eq!(engine.assess_risks().await.unwrap().len(), demo_markets);
+    }
+
+    #[tokio::test]
+    async fn test_assess_risks_cancelled() {
+        let engine = fixture_engine(config::Config::default());
+        engine.cancellation_token().cancel();
+
+        let err = engine.assess_risks().await.unwrap_err();
+        assert!(err.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_assessment_updates_metrics() {
+        let mut config = config::Config::default();
+        config.metrics.bind_address = Some("127.0.0.1:0".to_string());
+        let engine = fixture_engine(config);
+        let addr = engine.start_metrics_exporter().unwrap();
+        assert!(engine.start_metrics_exporter().is_err());
+
+        engine.assess_risks().await.unwrap();
+        let body = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap().text().await.unwrap();
+        assert!(body.contains(r#"cometguard_market_risk_score{chain_id="1",market="USDC"} 45"#), "{}", body);
+        assert!(body.contains(r#"cometguard_market_reserves_usd{chain_id="1",market="USDC"} 25000000"#));
+        assert!(body.contains(r#"cometguard_protocol_risk_score{chain_id="all"} 45"#), "{}", body);
+        engine.shutdown().await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_assessments_are_stored() {
+        use storage::Storage;
+
+        let storage = Arc::new(storage::SqliteStorage::open_in_memory().unwrap());
+        let engine = fixture_engine(config::Config::default()).with_storage(storage.clone());
+
+        engine.assess_risks().await.unwrap();
+        engine.assess_risks().await.unwrap();
+        let markets = storage.markets().await.unwrap();
+        assert_eq!((markets[0].name.as_str(), markets[0].assessments), ("USDC", 2));
+        let spans = storage.fingerprint_spans(&storage::FindingQuery::default()).await.unwrap();
+        // Two risks, and the note that the history is too short for a baseline
+        assert_eq!(spans.len(), 3);
+        assert!(spans.iter().all(|s| s.occurrences == 2));
+        assert!(spans.iter().any(|s| s.category == risk::RiskCategory::DataQuality && s.last_description.starts_with("Not compared with its history")));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_annotations_follow_findings_into_later_assessments() {
+        let unstored = fixture_engine(config::Config::default());
+        let err = unstored.annotate_finding("3f2a9c0d8e7b6a51", "feed migrated", "alice").await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Unavailable { what: "annotations", .. }), "{}", err);
+
+        let storage = Arc::new(storage::SqliteStorage::open_in_memory().unwrap());
+        let engine = fixture_engine(config::Config::default()).with_storage(storage);
+        let err = engine.annotate_finding("3f2a9c0d8e7b6a51", "feed migrated", "alice").await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::NotFound { kind: "finding", .. }), "{}", err);
+
+        let first = engine.assess_risks().await.unwrap().remove(0);
+        let fingerprint = first.findings[0].fingerprint.clone();
+        let annotation = engine.annotate_finding(&fingerprint, "confirmed false positive", "alice").await.unwrap();
+        // The cached assessment shows the note at once, later assessments read it from the store
+        assert_eq!(engine.latest_assessment(first.market_address).unwrap().annotations[&fingerprint], annotation);
+        let second = engine.assess_risks().await.unwrap().remove(0);
+        assert_eq!(second.annotations.len(), 1);
+        assert_eq!(second.annotations[&fingerprint], annotation);
+        assert_eq!(second.risk_score, first.risk_score);
+        assert!(report::market_section(&second).contains("   Note: confirmed false positive (alice, "));
+        assert_eq!(engine.finding_annotations(&fingerprint).await.unwrap(), [annotation]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_rising_score_history_adds_a_trend_finding() {
+        use storage::Storage;
+
+        let storage = Arc::new(storage::SqliteStorage::open_in_memory().unwrap());
+        let mut config = config::Config::default();
+        config.risk.trend.smoothing = 0.7;
+        let engine = fixture_engine(config).with_storage(storage.clone());
+        let market = engine.provider().get_markets().await.unwrap().remove(0);
+        let mut assessment = engine.assess_market_by_address(market.comet_address).await.unwrap();
+        assert_eq!(assessment.trend.as_ref().map(|t| t.previous_smoothed_score), Some(None));
+
+        // Two days of scores around 30 before the fixture's 45: the average goes from 29.55 to 40.365
+        let now = Utc::now();
+        let storage = storage::SqliteStorage::open_in_memory().unwrap();
+        for (hours, score) in [(48, 25), (24, 30), (12, 30)] {
+            assessment.risk_score = score;
+            assessment.timestamp = now - chrono::Duration::hours(hours);
+            storage.record(1, &market, &assessment).await.unwrap();
+        }
+        let engine = engine.with_storage(Arc::new(storage));
+        let assessment = engine.assess_market_by_address(market.comet_address).await.unwrap();
+        let trend = assessment.trend.as_ref().unwrap();
+        assert_eq!((trend.delta_24h, trend.direction), (Some(15), trend::Direction::Deteriorating));
+        let finding = assessment.findings.iter().find(|f| f.category == risk::RiskCategory::RiskTrend).unwrap();
+        assert_eq!((finding.severity, finding.metadata["band"].as_u64()), (risk::RiskSeverity::Low, Some(40)));
+        assert_eq!(assessment.risk_score, 45);
+    }
+
+    #[tokio::test]
+    async fn test_account_exposure_spans_markets() {
+        let whale = "0x00000000000000000000000000000000beef0001";
+        let mut config = config::Config::default();
+        config.data_source = config::DataSource::Mock;
+        config.watchlist = vec![whale.to_string()];
+        config.risk.max_account_borrow_share = 0.00001;
+        let engine = RiskEngine::new(config).await.unwrap();
+        let whale = utils::parse_address(whale).unwrap();
+
+        // The demo account borrows in the USDC and USDT markets
+        let exposure = engine.get_account_exposure(whale).await.unwrap();
+        let markets: Vec<&str> = exposure.markets.iter().map(|m| m.market_name.as_str()).collect();
+        assert_eq!(markets, ["USDC", "USDT"]);
+        assert!((exposure.total_borrow_usd - 14_473.15).abs() < 1e-6);
+        assert_eq!(exposure.min_health_factor, Some(0.92));
+        assert!(exposure.is_concentrated() && exposure.mock_data);
+
+        let usdc = exposure.markets[0].market_address;
+        let top = engine
+            .top_positions(usdc, ranking::PositionSort::Borrow, 5, ranking::HealthFactorFilter::default())
+            .await
+            .unwrap();
+        assert!((top.positions[0].borrow_value_usd - 9_048.91).abs() < 1e-6);
+        assert_eq!(top.positions[0].all_markets_borrow_usd, Some(exposure.total_borrow_usd));
+        assert_eq!(top.positions[0].protocol_borrow_share, Some(exposure.borrow_share));
+    }
+
+    #[tokio::test]
+    async fn test_latest_assessment_is_cached_per_market() {
+        let engine = fixture_engine(config::Config::default());
+        let market = engine.provider().get_markets().await.unwrap()[0].comet_address;
+        assert!(engine.latest_assessment(market).is_none());
+
+        let assessment = engine.assess_market_by_address(market).await.unwrap();
+        assert_eq!(engine.latest_assessment(market).unwrap().timestamp, assessment.timestamp);
+        assert_eq!(engine.latest_assessments().len(), 1);
+
+        let err = engine.assess_market_by_address(Address::repeat_byte(0x11)).await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::NotFound { kind: "market", .. }));
+    }
+
+    #[tokio::test]
+    async fn test_assessments_are_appended_to_the_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = config::AuditConfig { rotation: config::AuditRotation::Size, ..Default::default() };
+        let audit = audit::AuditLog::open(dir.path(), &settings, "hash".to_string(), 1).unwrap();
+        let engine = fixture_engine(config::Config::default()).with_audit_log(audit);
+
+        engine.assess_risks().await.unwrap();
+        engine.assess_risks().await.unwrap();
+        engine.shutdown().await;
+
+        let report = audit::verify(&dir.path().join("audit-000001.jsonl")).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_assess_pipeline_against_fixture() {
+        let engine = fixture_engine(config::Config::default());
+
+        let assessments = engine.assess_risks().await.unwrap();
+        assert_eq!(assessments.len(), 1);
+
+        let categories: Vec<_> = assessments[0].findings.iter().map(|f| f.category.clone()).collect();
+        assert_eq!(
+            categories,
+            vec![risk::RiskCategory::HighUtilization, risk::RiskCategory::PriceVolatility]
+        );
+        assert_eq!(assessments[0].risk_score, 45);
+
+        // Fixture providers cannot scan logs
+        assert!(engine.discover_borrowers().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_min_position_filter_keeps_aggregates() {
+        let run = |min_position_usd: Option<f64>| async move {
+            let mut config = config::Config {
+                watchlist: vec![
+                    "0x1111111111111111111111111111111111111111".to_string(),
+                    "0x2222222222222222222222222222222222222222".to_string(),
+                ],
+                ..config::Config::default()
+            };
+            config.risk.watchlist_alert_health_factor = Some(2.0);
+            config.risk.min_position_usd = min_position_usd;
+            let engine = fixture_engine(config);
+            let assessment = engine.assess_risks().await.unwrap().remove(0);
+            let market = engine.provider().get_markets().await.unwrap().remove(0);
+            let positions = engine.tracked_positions(&market).await.unwrap();
+            (assessment, market, positions)
+        };
+        let per_account = |assessment: &risk::RiskAssessment| {
+            assessment.findings.iter().filter(|f| f.category == risk::RiskCategory::LiquidationCascade).count()
+        };
+
+        let (all, market, positions) = run(None).await;
+        assert_eq!((per_account(&all), positions.len()), (2, 2));
+
+        // The fixture's positions borrow $1,000 and $1,600
+        let (filtered, filtered_market, filtered_positions) = run(Some(1_200.0)).await;
+        assert_eq!((per_account(&filtered), filtered_positions.len()), (1, 1));
+        assert_eq!(filtered.min_position_usd, Some(1_200.0));
+        assert_eq!(all.min_position_usd, None);
+
+        let (none_left, _, no_positions) = run(Some(2_000.0)).await;
+        assert_eq!((per_account(&none_left), no_positions.len()), (0, 0));
+        // Totals and the lowest watchlist health factor still count the filtered positions
+        assert_eq!(none_left.watchlist_min_health_factor, all.watchlist_min_health_factor);
+        assert_eq!(filtered_market.total_borrow, market.total_borrow);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_absorption_of_tracked_and_untracked_accounts() {
+        let config = config::Config { watchlist: vec!["0x1111111111111111111111111111111111111111".to_string()], ..config::Config::default() };
+        let engine = fixture_engine(config);
+        let market = engine.provider().get_markets().await.unwrap().remove(0);
+
+        let largest = engine.simulate_absorption(&market, absorb::AbsorbTarget::Largest).await.unwrap();
+        assert_eq!(largest.account, Address::repeat_byte(0x11));
+        assert_eq!((largest.reserves_before_usd, largest.knock_on.positions_checked), (25_000_000.0, 0));
+        assert!(largest.mock_data);
+
+        // Not on the watchlist, read on its own; the watchlist account is still checked for knock-on
+        let named = engine.simulate_absorption(&market, absorb::AbsorbTarget::Account(Address::repeat_byte(0x22))).await.unwrap();
+        assert_eq!((named.debt_usd, named.knock_on.positions_checked), (1_750.0, 1));
+
+        let missing = engine.simulate_absorption(&market, absorb::AbsorbTarget::Account(Address::repeat_byte(0x33))).await.unwrap_err();
+        assert!(matches!(missing, RiskEngineError::NotFound { .. }), "{}", missing);
+    }
+
+    #[tokio::test]
+    async fn test_ens_names_need_a_chain_provider() {
+        let mut config = config::Config::default();
+        config.data_source = config::DataSource::Mock;
+        config.watchlist = vec!["vitalik.eth".to_string(), "0x1111111111111111111111111111111111111111".to_string()];
+        let engine = RiskEngine::new(config).await.unwrap();
+        // Dropped at startup rather than warned about on every assessment
+        assert_eq!(engine.config().watchlist, ["0x1111111111111111111111111111111111111111"]);
+
+        let hex = "0x2222222222222222222222222222222222222222";
+        assert_eq!(engine.resolve_account(hex).await.unwrap(), hex.parse::<Address>().unwrap());
+        let err = engine.resolve_account("vitalik.eth").await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Unavailable { what: "ENS resolution", .. }), "{}", err);
+        assert_eq!(engine.account_name(hex.parse().unwrap()).await, None);
+    }
+
+    /// Stand-in for a market client whose per-market fetch takes `delay`
+    async fn slow_fetch(market: usize, delay: Duration) -> Result<usize> {
+        tokio::time::sleep(delay).await;
+        if market == 3 {
+            return Err(RiskEngineError::provider(
+                "https://rpc.example.org",
+                ethers::providers::ProviderError::CustomError("rpc unavailable".to_string()),
+            ));
+        }
+        Ok(market)
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_is_concurrent_and_ordered() {
+        let delay = Duration::from_millis(100);
+        let start = Instant::now();
+        let results = run_bounded((0..5).collect(), 5, |m| slow_fetch(m, delay)).await;
+        let elapsed = start.elapsed();
+
+        // Serial execution would take 5 * 100ms
+        assert!(elapsed < Duration::from_millis(300), "took {:?}", elapsed);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap(), &0);
+        assert_eq!(results[4].as_ref().unwrap(), &4);
+        // One failure does not abort the others
+        assert!(results[3].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_respects_limit() {
+        let delay = Duration::from_millis(50);
+        let start = Instant::now();
+        let results = run_bounded((0..4).collect(), 1, |m| slow_fetch(m, delay)).await;
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(results.len(), 4);
     }
 }
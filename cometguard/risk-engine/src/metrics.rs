@@ -0,0 +1,470 @@
+use crate::error::{Result, RiskEngineError};
+use crate::models::Market;
+use crate::risk::{RiskAssessment, RiskCategory, RiskSeverity};
+use crate::summary::{ProtocolRiskSummary, RiskTotals};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server, StatusCode};
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Labels identifying a market; deliberately no per-account labels so cardinality stays bounded
+const MARKET_LABELS: &[&str] = &["market", "chain_id"];
+
+/// `chain_id` label of protocol gauges summed over every chain
+const ALL_CHAINS: &str = "all";
+
+/// Prometheus metrics maintained by the engine
+///
+/// Market gauges are updated whenever an assessment completes, whether it was started by
+/// the scheduler or an explicit call. Serve them with `MetricsExporter`.
+pub struct Metrics {
+    registry: Registry,
+    chain_id: String,
+    risk_score: GaugeVec,
+    category_score: GaugeVec,
+    utilization: GaugeVec,
+    total_supply: GaugeVec,
+    total_borrow: GaugeVec,
+    reserves: GaugeVec,
+    supply_apr: GaugeVec,
+    borrow_apr: GaugeVec,
+    net_supply_apr: GaugeVec,
+    net_borrow_apr: GaugeVec,
+    findings: IntGaugeVec,
+    watchlist_min_health_factor: GaugeVec,
+    last_assessed: GaugeVec,
+    assessment_interval: GaugeVec,
+    health_factor_positions: GaugeVec,
+    health_factor_borrow: GaugeVec,
+    protocol_risk_score: GaugeVec,
+    protocol_tvl: GaugeVec,
+    protocol_bad_debt: GaugeVec,
+    protocol_near_liquidation: GaugeVec,
+    protocol_findings: IntGaugeVec,
+    assessment_duration: HistogramVec,
+    rpc_calls: IntCounterVec,
+    rpc_retries: IntCounterVec,
+}
+
+impl Metrics {
+    /// Create a registry with all engine metrics, labelled with `chain_id`
+    pub fn new(chain_id: u64) -> Self {
+        let registry = Registry::new();
+        let gauge = |name: &str, help: &str| {
+            let gauge = GaugeVec::new(Opts::new(name, help), MARKET_LABELS).expect("valid metric definition");
+            registry.register(Box::new(gauge.clone())).expect("metric names are unique");
+            gauge
+        };
+
+        let risk_score = gauge("cometguard_market_risk_score", "Overall risk score of the market (0-100)");
+        let utilization = gauge("cometguard_market_utilization", "Market utilization (0-1)");
+        let total_supply = gauge("cometguard_market_total_supply", "Total base supplied, in base units");
+        let total_borrow = gauge("cometguard_market_total_borrow", "Total base borrowed, in base units");
+        let reserves = gauge("cometguard_market_reserves_usd", "Protocol reserves of the market in USD");
+        let supply_apr = gauge("cometguard_market_supply_apr", "Supply APR from the rate model (0-1)");
+        let borrow_apr = gauge("cometguard_market_borrow_apr", "Borrow APR from the rate model (0-1)");
+        let net_supply_apr = gauge("cometguard_market_net_supply_apr", "Supply APR plus COMP rewards (0-1)");
+        let net_borrow_apr = gauge("cometguard_market_net_borrow_apr", "Borrow APR minus COMP rewards (0-1)");
+        let watchlist_min_health_factor = gauge(
+            "cometguard_watchlist_min_health_factor",
+            "Lowest health factor among watchlist accounts with a borrow in the market",
+        );
+        let last_assessed = gauge(
+            "cometguard_market_last_assessed_timestamp_seconds",
+            "Unix time of the newest assessment of the market",
+        );
+        let assessment_interval = gauge(
+            "cometguard_market_assessment_interval_seconds",
+            "Interval the scheduler assesses the market at",
+        );
+
+        let protocol_gauge = |name: &str, help: &str| {
+            let gauge = GaugeVec::new(Opts::new(name, help), &["chain_id"]).expect("valid metric definition");
+            registry.register(Box::new(gauge.clone())).expect("metric names are unique");
+            gauge
+        };
+        let protocol_risk_score = protocol_gauge(
+            "cometguard_protocol_risk_score",
+            "Weighted risk score over the markets of a chain, or of all chains (0-100)",
+        );
+        let protocol_tvl = protocol_gauge("cometguard_protocol_tvl_usd", "Total base supplied over the markets, in USD");
+        let protocol_bad_debt = protocol_gauge(
+            "cometguard_protocol_bad_debt_usd",
+            "Borrow of tracked positions in excess of their collateral, in USD",
+        );
+        let protocol_near_liquidation = protocol_gauge(
+            "cometguard_protocol_near_liquidation_usd",
+            "Borrow of tracked positions near or past liquidation, in USD",
+        );
+        let protocol_findings = IntGaugeVec::new(
+            Opts::new("cometguard_protocol_findings", "Findings in the latest run by severity, over the markets"),
+            &["chain_id", "severity"],
+        )
+        .expect("valid metric definition");
+        registry.register(Box::new(protocol_findings.clone())).expect("metric names are unique");
+
+        let findings = IntGaugeVec::new(
+            Opts::new("cometguard_findings", "Findings in the latest assessment by severity and category"),
+            &["market", "chain_id", "severity", "category"],
+        )
+        .expect("valid metric definition");
+        registry.register(Box::new(findings.clone())).expect("metric names are unique");
+
+        let category_score = GaugeVec::new(
+            Opts::new("cometguard_market_category_score", "Risk score of the market from one category's findings alone (0-100)"),
+            &["market", "chain_id", "category"],
+        )
+        .expect("valid metric definition");
+        registry.register(Box::new(category_score.clone())).expect("metric names are unique");
+
+        // One series per bucket of `risk.summary.health_factor_buckets`, so still bounded
+        let bucket_gauge = |name: &str, help: &str| {
+            let gauge = GaugeVec::new(Opts::new(name, help), &["market", "chain_id", "bucket"]).expect("valid metric definition");
+            registry.register(Box::new(gauge.clone())).expect("metric names are unique");
+            gauge
+        };
+        let health_factor_positions = bucket_gauge(
+            "cometguard_market_health_factor_positions",
+            "Tracked positions by health factor bucket (\"<1.0\" liquidatable, \"no borrow\" unbounded)",
+        );
+        let health_factor_borrow = bucket_gauge(
+            "cometguard_market_health_factor_borrow_usd",
+            "Borrow of tracked positions by health factor bucket, in USD",
+        );
+
+        let assessment_duration = HistogramVec::new(
+            HistogramOpts::new("cometguard_assessment_duration_seconds", "Time taken to assess a market")
+                .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+            MARKET_LABELS,
+        )
+        .expect("valid metric definition");
+        registry.register(Box::new(assessment_duration.clone())).expect("metric names are unique");
+
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["method", "chain_id"]).expect("valid metric definition");
+            registry.register(Box::new(counter.clone())).expect("metric names are unique");
+            counter
+        };
+        let rpc_calls = counter("cometguard_rpc_calls_total", "JSON-RPC requests sent, including retries");
+        let rpc_retries = counter("cometguard_rpc_retries_total", "JSON-RPC requests retried after a transport error");
+
+        Self {
+            registry,
+            chain_id: chain_id.to_string(),
+            risk_score,
+            category_score,
+            utilization,
+            total_supply,
+            total_borrow,
+            reserves,
+            supply_apr,
+            borrow_apr,
+            net_supply_apr,
+            net_borrow_apr,
+            findings,
+            watchlist_min_health_factor,
+            last_assessed,
+            assessment_interval,
+            health_factor_positions,
+            health_factor_borrow,
+            protocol_risk_score,
+            protocol_tvl,
+            protocol_bad_debt,
+            protocol_near_liquidation,
+            protocol_findings,
+            assessment_duration,
+            rpc_calls,
+            rpc_retries,
+        }
+    }
+
+    /// Record a completed assessment of `market` that took `elapsed`
+    pub fn observe_assessment(&self, market: &Market, assessment: &RiskAssessment, elapsed: Duration) {
+        let labels = [market.name.as_str(), self.chain_id.as_str()];
+        self.risk_score.with_label_values(&labels).set(assessment.risk_score as f64);
+        self.utilization.with_label_values(&labels).set(market.utilization());
+        self.total_supply.with_label_values(&labels).set(market.total_supply);
+        self.total_borrow.with_label_values(&labels).set(market.total_borrow);
+        self.supply_apr.with_label_values(&labels).set(market.supply_apr);
+        self.borrow_apr.with_label_values(&labels).set(market.borrow_apr);
+        self.net_supply_apr.with_label_values(&labels).set(market.net_supply_apr);
+        self.net_borrow_apr.with_label_values(&labels).set(market.net_borrow_apr);
+        self.assessment_duration.with_label_values(&labels).observe(elapsed.as_secs_f64());
+        self.last_assessed.with_label_values(&labels).set(assessment.timestamp.timestamp_millis() as f64 / 1000.0);
+        if let Some(health_factor) = assessment.watchlist_min_health_factor {
+            self.watchlist_min_health_factor.with_label_values(&labels).set(health_factor);
+        }
+        if let Some(distribution) = &assessment.health_factors {
+            for bucket in &distribution.buckets {
+                let labels = [labels[0], labels[1], bucket.label.as_str()];
+                self.health_factor_positions.with_label_values(&labels).set(bucket.positions as f64);
+                self.health_factor_borrow.with_label_values(&labels).set(bucket.borrow_usd);
+            }
+        }
+
+        // Categories not evaluated have no score, rather than a score of zero
+        for (category, score) in &assessment.category_scores {
+            let category = format!("{:?}", category);
+            let labels = [labels[0], labels[1], category.as_str()];
+            match score {
+                Some(score) => self.category_score.with_label_values(&labels).set(f64::from(*score)),
+                None => {
+                    let _ = self.category_score.remove_label_values(&labels);
+                }
+            }
+        }
+
+        // Every combination is written so that resolved findings drop back to zero
+        for severity in RiskSeverity::ALL {
+            for category in RiskCategory::ALL {
+                let count = assessment
+                    .findings
+                    .iter()
+                    .filter(|f| f.severity == severity && f.category == category)
+                    .count();
+                let severity = format!("{:?}", severity);
+                let category = format!("{:?}", category);
+                self.findings
+                    .with_label_values(&[labels[0], labels[1], severity.as_str(), category.as_str()])
+                    .set(count as i64);
+            }
+        }
+    }
+
+    /// Record the rollup of a run, in total (`chain_id="all"`) and per chain
+    pub fn observe_summary(&self, summary: &ProtocolRiskSummary) {
+        self.observe_totals(ALL_CHAINS, &summary.totals);
+        for chain in &summary.chains {
+            let chain_id = chain.chain_id.map_or_else(|| "unknown".to_string(), |id| id.to_string());
+            self.observe_totals(&chain_id, &chain.totals);
+        }
+    }
+
+    fn observe_totals(&self, chain_id: &str, totals: &RiskTotals) {
+        self.protocol_risk_score.with_label_values(&[chain_id]).set(totals.risk_score);
+        self.protocol_tvl.with_label_values(&[chain_id]).set(totals.tvl_usd);
+        self.protocol_bad_debt.with_label_values(&[chain_id]).set(totals.bad_debt_usd);
+        self.protocol_near_liquidation.with_label_values(&[chain_id]).set(totals.near_liquidation_usd);
+        for (severity, count) in &totals.findings {
+            let severity = format!("{:?}", severity);
+            self.protocol_findings.with_label_values(&[chain_id, severity.as_str()]).set(*count as i64);
+        }
+    }
+
+    /// Record the interval the scheduler assesses the market of `assessment` at, from its cadence
+    pub fn observe_cadence(&self, assessment: &RiskAssessment) {
+        if let Some(cadence) = &assessment.cadence {
+            self.assessment_interval
+                .with_label_values(&[assessment.market_name.as_str(), self.chain_id.as_str()])
+                .set(cadence.interval_ms as f64 / 1000.0);
+        }
+    }
+
+    /// Record the protocol reserves of `market`, in USD
+    pub fn observe_reserves(&self, market: &Market, reserves_usd: f64) {
+        self.reserves
+            .with_label_values(&[market.name.as_str(), self.chain_id.as_str()])
+            .set(reserves_usd);
+    }
+
+    /// Count a JSON-RPC request for `method`
+    pub fn record_rpc_call(&self, method: &str) {
+        self.rpc_calls.with_label_values(&[method, self.chain_id.as_str()]).inc();
+    }
+
+    /// Count a retried JSON-RPC request for `method`
+    pub fn record_rpc_retry(&self, method: &str) {
+        self.rpc_retries.with_label_values(&[method, self.chain_id.as_str()]).inc();
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding into a Vec cannot fail");
+        String::from_utf8(buffer).expect("Prometheus text format is UTF-8")
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").field("chain_id", &self.chain_id).finish_non_exhaustive()
+    }
+}
+
+/// HTTP server exposing `Metrics` at `/metrics`
+pub struct MetricsExporter {
+    local_addr: SocketAddr,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl MetricsExporter {
+    /// Bind `addr` and start serving `metrics`
+    pub fn spawn(metrics: Arc<Metrics>, addr: SocketAddr) -> Result<Self> {
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let metrics = metrics.clone();
+                    async move {
+                        let response = if request.uri().path() == "/metrics" {
+                            Response::builder()
+                                .header("Content-Type", TextEncoder::new().format_type())
+                                .body(Body::from(metrics.render()))
+                        } else {
+                            Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty())
+                        };
+                        Ok::<_, Infallible>(response.expect("static response parts are valid"))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)
+            .map_err(|e| RiskEngineError::config("metrics.bind_address", format!("cannot bind {}: {}", addr, e)))?
+            .serve(make_service);
+        let local_addr = server.local_addr();
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            if let Err(e) = server
+                .with_graceful_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+            {
+                warn!("Metrics exporter failed: {}", e);
+            }
+        });
+
+        info!("Serving Prometheus metrics on http://{}/metrics", local_addr);
+        Ok(Self { local_addr, stop, task })
+    }
+
+    /// Address the exporter is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop serving and wait for the server to exit
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health_factors::HealthFactorDistribution;
+    use crate::models::UserPosition;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::testing::{assessment, finding};
+    use ethers::types::Address;
+    use chrono::Utc;
+
+    fn fixture_market() -> Market {
+        FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().markets[0].clone()
+    }
+
+    #[test]
+    fn test_observe_assessment_sets_gauges_and_clears_resolved_findings() {
+        let metrics = Metrics::new(1);
+        let market = fixture_market();
+
+        let watched = RiskAssessment { watchlist_min_health_factor: Some(1.03), ..assessment(30, vec![finding("f", RiskSeverity::High)]) };
+        metrics.observe_assessment(&market, &watched, Duration::from_millis(20));
+        let text = metrics.render();
+        assert!(text.contains(r#"cometguard_market_risk_score{chain_id="1",market="USDC"} 30"#), "{}", text);
+        assert!(text.contains(r#"cometguard_market_utilization{chain_id="1",market="USDC"} 0.9"#));
+        assert!(text.contains(r#"cometguard_watchlist_min_health_factor{chain_id="1",market="USDC"} 1.03"#));
+        assert!(text.contains(
+            r#"cometguard_findings{category="HighUtilization",chain_id="1",market="USDC",severity="High"} 1"#
+        ));
+        assert!(text.contains(r#"cometguard_assessment_duration_seconds_count{chain_id="1",market="USDC"} 1"#));
+        assert!(text.contains(r#"cometguard_market_last_assessed_timestamp_seconds{chain_id="1",market="USDC"} 1"#));
+
+        let mut scheduled = assessment(30, vec![]);
+        metrics.observe_cadence(&scheduled);
+        assert!(!metrics.render().contains("cometguard_market_assessment_interval_seconds{"));
+        scheduled.cadence = Some(crate::schedule::Cadence { interval_ms: 90_000, previous_assessed_at: None, next_due_at: Utc::now() });
+        metrics.observe_cadence(&scheduled);
+        assert!(metrics.render().contains(r#"cometguard_market_assessment_interval_seconds{chain_id="1",market="USDC"} 90"#));
+
+        let mut distributed = assessment(30, vec![]);
+        let positions = [UserPosition {
+            address: Address::repeat_byte(1),
+            base_balance: -2_000.0,
+            collateral_balances: Default::default(),
+            total_collateral_value: 1_900.0,
+            total_borrow_value: 2_000.0,
+            health_factor: 0.95,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        }];
+        distributed.health_factors = Some(HealthFactorDistribution::new(&positions, &[1.1]));
+        metrics.observe_assessment(&market, &distributed, Duration::from_millis(20));
+        let text = metrics.render();
+        assert!(text.contains(r#"cometguard_market_health_factor_positions{bucket="<1.0",chain_id="1",market="USDC"} 1"#), "{}", text);
+        assert!(text.contains(r#"cometguard_market_health_factor_borrow_usd{bucket="<1.0",chain_id="1",market="USDC"} 2000"#));
+        assert!(text.contains(r#"cometguard_market_health_factor_positions{bucket="no borrow",chain_id="1",market="USDC"} 0"#));
+
+        metrics.observe_assessment(&market, &assessment(30, vec![]), Duration::from_millis(20));
+        assert!(metrics.render().contains(
+            r#"cometguard_findings{category="HighUtilization",chain_id="1",market="USDC",severity="High"} 0"#
+        ));
+
+        // A category no longer evaluated drops its series instead of reading zero
+        let mut scored = assessment(30, vec![]);
+        scored.category_scores = [(RiskCategory::HighUtilization, Some(30)), (RiskCategory::BadDebt, Some(0))].into_iter().collect();
+        metrics.observe_assessment(&market, &scored, Duration::from_millis(20));
+        let text = metrics.render();
+        assert!(text.contains(r#"cometguard_market_category_score{category="HighUtilization",chain_id="1",market="USDC"} 30"#), "{}", text);
+        assert!(text.contains(r#"cometguard_market_category_score{category="BadDebt",chain_id="1",market="USDC"} 0"#));
+        scored.category_scores.insert(RiskCategory::BadDebt, None);
+        metrics.observe_assessment(&market, &scored, Duration::from_millis(20));
+        assert!(!metrics.render().contains(r#"category_score{category="BadDebt""#));
+    }
+
+    #[test]
+    fn test_observe_summary_sets_totals_and_chain_subtotals() {
+        let metrics = Metrics::new(1);
+        let market = fixture_market();
+        let mut assessment = assessment(30, vec![finding("f", RiskSeverity::High)]);
+        assessment.exposure = Some(crate::summary::MarketExposure::new(1, &market, &[], 0.1));
+
+        let summary = ProtocolRiskSummary::new(&[assessment], crate::summary::ScoreWeighting::Tvl);
+        metrics.observe_summary(&summary);
+        let text = metrics.render();
+        assert!(text.contains(r#"cometguard_protocol_risk_score{chain_id="all"} 30"#), "{}", text);
+        assert!(text.contains(r#"cometguard_protocol_risk_score{chain_id="1"} 30"#));
+        assert!(text.contains(r#"cometguard_protocol_findings{chain_id="all",severity="High"} 1"#));
+        assert!(text.contains(r#"cometguard_protocol_findings{chain_id="1",severity="Critical"} 0"#));
+        assert!(text.contains(r#"cometguard_protocol_bad_debt_usd{chain_id="all"} 0"#));
+    }
+
+    #[tokio::test]
+    async fn test_exporter_serves_metrics() {
+        let metrics = Arc::new(Metrics::new(1));
+        metrics.record_rpc_call("eth_call");
+        let exporter = MetricsExporter::spawn(metrics, "127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let url = format!("http://{}/metrics", exporter.local_addr());
+        let body = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(body.contains(r#"cometguard_rpc_calls_total{chain_id="1",method="eth_call"} 1"#), "{}", body);
+
+        let missing = reqwest::get(format!("http://{}/other", exporter.local_addr())).await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+        exporter.shutdown().await;
+    }
+}
@@ -1,6 +1,8 @@
 use ethers::types::{Address, U256};
 use serde::{Deserialize, Serialize};
+use crate::utils::{self, format_named_address, sanitize_inline, to_checksum_address};
 use std::collections::HashMap;
+use std::fmt;
 use chrono::{DateTime, Utc};
 
 /// Asset type in Compound V3
@@ -16,6 +18,7 @@ pub enum AssetType {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Asset {
     /// Asset address
+    #[serde(serialize_with = "crate::utils::checksummed")]
     pub address: Address,
     /// Asset symbol (e.g., "WETH", "USDC")
     pub symbol: String,
@@ -25,9 +28,11 @@ pub struct Asset {
     pub price: f64,
     /// Asset type (base or collateral)
     pub asset_type: AssetType,
-    /// Collateral factor (0 for base assets)
+    /// Collateral factor, Comet's borrow collateral factor: the share of the value that may be
+    /// borrowed against (0 for base assets)
     pub collateral_factor: f64,
-    /// Liquidation factor (0 for base assets)
+    /// Liquidation factor, Comet's liquidate collateral factor: the share of the value that
+    /// counts toward the health factor (0 for base assets)
     pub liquidation_factor: f64,
     /// Liquidation penalty (0 for base assets)
     pub liquidation_penalty: f64,
@@ -35,6 +40,76 @@ pub struct Asset {
     pub supply_cap: U256,
     /// Borrow cap in asset units (for base assets)
     pub borrow_cap: U256,
+    /// Whether the token is native, bridged or custodial
+    #[serde(default)]
+    pub provenance: Provenance,
+    /// Collateral supplied to the market, in asset units; 0 for base assets and where the
+    /// source does not report it
+    #[serde(default)]
+    pub total_supplied: f64,
+    /// Collateral the protocol owns from absorptions and has not yet sold through
+    /// `buyCollateral`, in asset units; `None` for base assets and where the source does not
+    /// report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_held: Option<f64>,
+}
+
+impl Asset {
+    /// Whether the asset is a USD stablecoin, by symbol (case-insensitive), so that prices in
+    /// USD are prices in it
+    pub fn is_usd_stablecoin(&self) -> bool {
+        const USD_STABLECOINS: &[&str] = &["USDC", "USDT", "DAI", "USDS", "USDC.e", "USDbC", "axlUSDC"];
+        USD_STABLECOINS.iter().any(|s| s.eq_ignore_ascii_case(&self.symbol))
+    }
+}
+
+/// Where a token's value comes from, for the bridge and custodian risk price checks miss
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+    /// Issued on the chain or wrapped without a custodian (WETH, wstETH, natively issued USDC)
+    Native,
+    /// Moved from another chain by a bridge (USDC.e, USDbC, tBTC)
+    Bridged,
+    /// Backed by assets a custodian holds (WBTC, cbBTC, cbETH)
+    WrappedCustodial,
+    /// Not classified; counted with bridged assets
+    #[default]
+    Unclassified,
+}
+
+impl Provenance {
+    /// Built-in classification of well-known tokens, by symbol (case-insensitive)
+    ///
+    /// Symbols are classified the same on every network: on an L2, tokens bridged from
+    /// Ethereum under their mainnet symbol need an `assets.<key>.provenance` setting.
+    pub fn of_symbol(symbol: &str) -> Provenance {
+        const NATIVE: &[&str] = &[
+            "WETH", "ETH", "wstETH", "stETH", "rETH", "USDC", "USDT", "DAI", "USDS", "COMP", "LINK", "UNI", "AAVE", "ARB", "OP", "WMATIC", "WPOL",
+        ];
+        const BRIDGED: &[&str] = &["USDC.e", "USDbC", "tBTC", "axlUSDC", "WBTC.e"];
+        const CUSTODIAL: &[&str] = &["WBTC", "cbBTC", "cbETH"];
+        let listed = |symbols: &[&str]| symbols.iter().any(|s| s.eq_ignore_ascii_case(symbol));
+        if listed(NATIVE) {
+            Provenance::Native
+        } else if listed(BRIDGED) {
+            Provenance::Bridged
+        } else if listed(CUSTODIAL) {
+            Provenance::WrappedCustodial
+        } else {
+            Provenance::Unclassified
+        }
+    }
+
+    /// Name as written in settings and reports, e.g. `wrapped_custodial`
+    pub fn label(self) -> &'static str {
+        match self {
+            Provenance::Native => "native",
+            Provenance::Bridged => "bridged",
+            Provenance::WrappedCustodial => "wrapped_custodial",
+            Provenance::Unclassified => "unclassified",
+        }
+    }
 }
 
 /// Market information for a Compound V3 deployment
@@ -43,6 +118,7 @@ pub struct Market {
     /// Market name (e.g., "USDC.e")
     pub name: String,
     /// Comet proxy address
+    #[serde(serialize_with = "crate::utils::checksummed")]
     pub comet_address: Address,
     /// Base asset info
     pub base_asset: Asset,
@@ -52,7 +128,7 @@ pub struct Market {
     pub total_supply: f64,
     /// Total borrow of the base asset
     pub total_borrow: f64,
-    /// Utilization rate (total_borrow / total_supply)
+    /// Utilization rate, as computed by `Market::utilization` when the market was read
     pub utilization_rate: f64,
     /// Supply APR
     pub supply_apr: f64,
@@ -66,12 +142,277 @@ pub struct Market {
     pub base_min_interest_rate: U256,
     /// Base max interest rate
     pub base_max_interest_rate: U256,
+    /// Smallest borrow Comet allows, in base asset units
+    #[serde(default)]
+    pub base_borrow_min: f64,
+    /// Fraction of an asset's liquidation penalty Comet passes on as a discount when selling
+    /// absorbed collateral; `None` where the source does not report it
+    #[serde(default)]
+    pub store_front_price_factor: Option<f64>,
+    /// COMP rewards to suppliers as an APR on the supplied value; 0 where rewards are not read
+    #[serde(default)]
+    pub supply_reward_apr: f64,
+    /// COMP rewards to borrowers as an APR on the borrowed value; 0 where rewards are not read
+    #[serde(default)]
+    pub borrow_reward_apr: f64,
+    /// Supply APR plus supply rewards
+    #[serde(default)]
+    pub net_supply_apr: f64,
+    /// Borrow APR minus borrow rewards; negative when rewards pay more than interest costs
+    #[serde(default)]
+    pub net_borrow_apr: f64,
+    /// Funding of the rewards contract; `None` where rewards are not read
+    #[serde(default)]
+    pub rewards: Option<RewardsFunding>,
+    /// Block every read of the market was pinned to; `None` where the source does not pin reads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Inconsistencies found in the data read, each reported as a Low `DataQuality` finding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_issues: Vec<DataIssue>,
+}
+
+/// Supply below which a market counts as empty, so its utilization is 0
+pub const MIN_UTILIZATION_SUPPLY: f64 = 1e-9;
+
+/// Most decimals a token plausibly has; more is taken for a decoding anomaly
+pub const MAX_TOKEN_DECIMALS: u8 = 36;
+
+/// What is wrong with the data behind a `DataIssue`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataIssueKind {
+    /// Values that cannot all be right, such as more borrowed than supplied
+    #[default]
+    Inconsistent,
+    /// An asset priced at zero or at no finite value
+    MissingPrice,
+    /// A source too far behind the chain to be current
+    StaleSource,
+    /// A value no contract would return, such as 255 decimals
+    DecodingAnomaly,
+    /// Some reads of a batch failed, so figures over it are lower bounds
+    PartialBatch,
+    /// Stored history too short to compare the market with, e.g. for `baseline`
+    ShortHistory,
+    /// A check cut off by `timeouts.per_market` or `timeouts.total`
+    TimedOut,
+}
+
+/// Problem with the data read for a market, as opposed to a risk of the market itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataIssue {
+    #[serde(default)]
+    pub kind: DataIssueKind,
+    /// What is affected, e.g. "utilization" or "price:WETH"; identifies the finding across
+    /// assessments
+    pub check: String,
+    pub message: String,
+}
+
+impl DataIssue {
+    pub fn new(kind: DataIssueKind, check: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { kind, check: check.into(), message: message.into() }
+    }
+}
+
+/// `USDC (0xc3d6...cdc3): supply 1.00B USDC, borrow 500M USDC, utilization 50.00%`, on one line
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = &self.base_asset;
+        let symbol = sanitize_inline(&base.symbol);
+        write!(
+            f,
+            "{}: supply {}, borrow {}, utilization {}",
+            sanitize_inline(&format_named_address(&self.name, &self.comet_address)),
+            utils::format_token_amount(self.total_supply, &symbol, base.decimals),
+            utils::format_token_amount(self.total_borrow, &symbol, base.decimals),
+            utils::format_percentage(self.utilization())
+        )
+    }
+}
+
+/// Reward token held by the rewards contract against the market's emissions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardsFunding {
+    /// Rewards contract
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub contract: Address,
+    /// Reward token, COMP
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub token: Address,
+    /// Reward token price in USD
+    pub token_price: f64,
+    /// Reward tokens held by the rewards contract
+    pub balance: f64,
+    /// Reward tokens the market emits a day, suppliers and borrowers together
+    pub daily_emission: f64,
+}
+
+impl RewardsFunding {
+    /// Days of the market's emissions the balance covers; `None` when nothing is emitted
+    ///
+    /// Every Comet on the network draws on the same balance, so this is an upper bound.
+    pub fn runway_days(&self) -> Option<f64> {
+        (self.daily_emission > 0.0).then(|| self.balance / self.daily_emission)
+    }
+}
+
+/// Base and net (rewards included) interest rates of a market
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InterestRates {
+    pub supply_apr: f64,
+    pub borrow_apr: f64,
+    pub net_supply_apr: f64,
+    pub net_borrow_apr: f64,
+}
+
+impl Market {
+    /// Share of the supply that is borrowed, `total_borrow / total_supply`; see `utilization_of`
+    pub fn utilization(&self) -> f64 {
+        Self::utilization_of(self.total_supply, self.total_borrow)
+    }
+
+    /// Utilization of a market with these totals
+    ///
+    /// 0 when the supply is below `MIN_UTILIZATION_SUPPLY` (an empty market has nothing to
+    /// lend out) or either total is not finite, so the result is never NaN or infinite. It is
+    /// not clamped: above 1 or below 0 the totals are inconsistent, which `utilization_issue`
+    /// reports.
+    pub fn utilization_of(total_supply: f64, total_borrow: f64) -> f64 {
+        if !total_supply.is_finite() || !total_borrow.is_finite() || total_supply.abs() < MIN_UTILIZATION_SUPPLY {
+            return 0.0;
+        }
+        total_borrow / total_supply
+    }
+
+    /// Why the totals cannot both be right: not finite, negative, or more borrowed than supplied
+    pub fn utilization_issue(&self) -> Option<DataIssue> {
+        let (supply, borrow) = (self.total_supply, self.total_borrow);
+        let message = if !supply.is_finite() || !borrow.is_finite() {
+            format!("totals are not finite numbers (supply {}, borrow {})", supply, borrow)
+        } else if supply < 0.0 || borrow < 0.0 {
+            format!("negative totals (supply {}, borrow {})", supply, borrow)
+        } else if borrow > supply {
+            format!(
+                "total borrow {} exceeds total supply {} (utilization {})",
+                borrow,
+                supply,
+                utils::format_percentage(self.utilization())
+            )
+        } else {
+            return None;
+        };
+        Some(DataIssue::new(DataIssueKind::Inconsistent, "utilization", message))
+    }
+
+    /// Every problem the market's own figures show: inconsistent totals, assets without a
+    /// price and implausible decimals
+    pub fn input_issues(&self) -> Vec<DataIssue> {
+        let mut issues: Vec<DataIssue> = self.utilization_issue().into_iter().collect();
+        for asset in std::iter::once(&self.base_asset).chain(self.collateral_assets.values()) {
+            if !(asset.price.is_finite() && asset.price > 0.0) {
+                issues.push(DataIssue::new(
+                    DataIssueKind::MissingPrice,
+                    format!("price:{}", asset.symbol),
+                    format!("{} has no usable price ({}); values in it are unknown", asset.symbol, asset.price),
+                ));
+            }
+            if asset.decimals > MAX_TOKEN_DECIMALS {
+                issues.push(DataIssue::new(
+                    DataIssueKind::DecodingAnomaly,
+                    format!("decimals:{}", asset.symbol),
+                    format!("{} reports {} decimals, more than any token has", asset.symbol, asset.decimals),
+                ));
+            }
+        }
+        issues
+    }
+
+    /// Add the `input_issues` not already in `data_issues`, returning how many were new
+    pub fn annotate_data_quality(&mut self) -> usize {
+        let before = self.data_issues.len();
+        for issue in self.input_issues() {
+            if !self.data_issues.iter().any(|known| known.check == issue.check) {
+                self.data_issues.push(issue);
+            }
+        }
+        self.data_issues.len() - before
+    }
+
+    /// Set the reward APRs and the net rates that follow from them and the base rates
+    pub fn set_reward_aprs(&mut self, supply_reward_apr: f64, borrow_reward_apr: f64) {
+        self.supply_reward_apr = supply_reward_apr;
+        self.borrow_reward_apr = borrow_reward_apr;
+        self.net_supply_apr = self.supply_apr + supply_reward_apr;
+        self.net_borrow_apr = self.borrow_apr - borrow_reward_apr;
+    }
+
+    /// Base and net rates
+    pub fn rates(&self) -> InterestRates {
+        InterestRates {
+            supply_apr: self.supply_apr,
+            borrow_apr: self.borrow_apr,
+            net_supply_apr: self.net_supply_apr,
+            net_borrow_apr: self.net_borrow_apr,
+        }
+    }
+
+    /// Health factor of a position with `base_balance` (negative when borrowing) and `collateral_balances`
+    ///
+    /// Collateral is weighted by its liquidation factor (Comet's liquidate collateral factor), so
+    /// the position is liquidatable below 1; positions without a borrow get 100. The borrow
+    /// collateral factor only bounds new borrows, see `borrow_limit`.
+    pub fn health_factor(&self, base_balance: f64, collateral_balances: &HashMap<Address, f64>) -> f64 {
+        if base_balance >= 0.0 {
+            return 100.0;
+        }
+
+        let collateral_value: f64 = collateral_balances
+            .iter()
+            .filter_map(|(address, &amount)| {
+                self.collateral_assets.get(address).map(|asset| amount * asset.price * asset.liquidation_factor)
+            })
+            .sum();
+        let borrow_value = -base_balance * self.base_asset.price;
+        if borrow_value > 0.0 {
+            collateral_value / borrow_value
+        } else {
+            100.0
+        }
+    }
+
+    /// Most a position with `collateral_balances` may borrow, in USD: its collateral weighted
+    /// by the borrow collateral factors
+    pub fn borrow_limit(&self, collateral_balances: &HashMap<Address, f64>) -> f64 {
+        collateral_balances
+            .iter()
+            .filter_map(|(address, &amount)| {
+                self.collateral_assets.get(address).map(|asset| amount * asset.price * asset.collateral_factor)
+            })
+            .sum()
+    }
+
+    /// Base asset the market can pay out to withdrawals, `total_supply - total_borrow` in base
+    /// asset units
+    ///
+    /// 0 rather than negative when more is borrowed than supplied (`utilization_issue` reports
+    /// that) or either total is not finite.
+    pub fn available_liquidity(&self) -> f64 {
+        let available = self.total_supply - self.total_borrow;
+        if available.is_finite() {
+            available.max(0.0)
+        } else {
+            0.0
+        }
+    }
 }
 
 /// User account position in a Compound V3 market
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserPosition {
     /// User address
+    #[serde(serialize_with = "crate::utils::checksummed")]
     pub address: Address,
     /// Base asset balance (positive for supply, negative for borrow)
     pub base_balance: f64,
@@ -83,12 +424,285 @@ pub struct UserPosition {
     pub total_borrow_value: f64,
     /// Health factor (>1 is healthy, <1 is liquidatable)
     pub health_factor: f64,
+    /// Block every read of the position was pinned to; `None` where the source does not pin reads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// How much more the position may borrow, in USD, before reaching its borrow limit; 0 at
+    /// or over the limit. Filled in by `set_borrow_capacity`
+    #[serde(default)]
+    pub borrow_capacity_usd: f64,
+    /// Share of the borrow limit still unused, in percent: 100 without a borrow, 0 at or over
+    /// the limit, `None` without collateral to borrow against
+    #[serde(default)]
+    pub remaining_borrow_headroom_pct: Option<f64>,
+}
+
+impl UserPosition {
+    /// Fill in `borrow_capacity_usd` and `remaining_borrow_headroom_pct` from the collateral
+    /// and borrow at `market`'s prices and collateral factors
+    pub fn set_borrow_capacity(&mut self, market: &Market) {
+        let limit = market.borrow_limit(&self.collateral_balances);
+        let borrow = self.total_borrow_value.max(0.0);
+        if !(limit.is_finite() && borrow.is_finite()) || limit <= 0.0 {
+            // No collateral, or none with a usable price: nothing more can be borrowed
+            self.borrow_capacity_usd = 0.0;
+            self.remaining_borrow_headroom_pct = None;
+            return;
+        }
+        self.borrow_capacity_usd = (limit - borrow).max(0.0);
+        self.remaining_borrow_headroom_pct = Some(self.borrow_capacity_usd / limit * 100.0);
+    }
+
+    /// Borrow beyond the borrow limit, in USD; `None` within it
+    pub fn borrow_overage(&self, market: &Market) -> Option<f64> {
+        let overage = self.total_borrow_value - market.borrow_limit(&self.collateral_balances);
+        (overage > 0.0).then_some(overage)
+    }
+
+    /// Collateral asset with the largest USD value in the position, with its balance
+    pub fn dominant_collateral<'a>(&self, market: &'a Market) -> Option<(&'a Asset, f64)> {
+        self.collateral_balances
+            .iter()
+            .filter(|(_, &balance)| balance > 0.0)
+            .filter_map(|(address, &balance)| market.collateral_assets.get(address).map(|asset| (asset, balance)))
+            .max_by(|(a, x), (b, y)| (x * a.price).total_cmp(&(y * b.price)))
+    }
+
+    /// Price of `asset` at which the health factor falls to 1, other prices unchanged
+    ///
+    /// The asset counts at its liquidation factor, as in `Market::health_factor`. `None`
+    /// without a borrow, or when the rest of the collateral covers the borrow on its own.
+    pub fn liquidation_price(&self, asset: &Asset) -> Option<f64> {
+        let balance = *self.collateral_balances.get(&asset.address)?;
+        let weighted = balance * asset.price * asset.liquidation_factor;
+        if self.total_borrow_value <= 0.0 || weighted <= 0.0 {
+            return None;
+        }
+        let weighted_others = self.health_factor * self.total_borrow_value - weighted;
+        let price = asset.price * (self.total_borrow_value - weighted_others) / weighted;
+        (price > 0.0).then_some(price)
+    }
+}
+
+/// `0x…: base -1,500.00, collateral $2,000.00, borrow $1,500.00, health factor 1.21`, on one
+/// line with the full checksummed address; positions without a borrow end in `no borrow`
+impl fmt::Display for UserPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: base {}, collateral {}",
+            to_checksum_address(&self.address),
+            utils::format_money(self.base_balance, ""),
+            utils::format_money(self.total_collateral_value, "$")
+        )?;
+        if self.total_borrow_value > 0.0 {
+            write!(
+                f,
+                ", borrow {}, health factor {:.2}",
+                utils::format_money(self.total_borrow_value, "$"),
+                self.health_factor
+            )
+        } else {
+            write!(f, ", no borrow")
+        }
+    }
+}
+
+/// Managers an account has allowed to act for it in a Comet, via `allow` or `allowBySig`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManagerPermissions {
+    /// The account
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub account: Address,
+    /// `userNonce`: signatures the account has used, e.g. for `allowBySig`
+    #[serde(default)]
+    pub nonce: u64,
+    /// Managers currently allowed, in address order
+    #[serde(default)]
+    #[serde(serialize_with = "crate::utils::checksummed_list")]
+    pub managers: Vec<Address>,
+    /// Whether the account's `Approval` logs were read; otherwise only candidate managers
+    /// were checked and others may be allowed too
+    #[serde(default = "default_true")]
+    pub complete: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// State of an L2 sequencer, from its Chainlink uptime feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequencerStatus {
+    /// Whether the sequencer is up
+    pub up: bool,
+    /// When it last went up or down (the feed round's `startedAt`)
+    pub since: DateTime<Utc>,
+}
+
+impl SequencerStatus {
+    /// Whether the sequencer is down, or came back up less than `grace_period` before `now`
+    pub fn degraded(&self, now: DateTime<Utc>, grace_period: chrono::Duration) -> bool {
+        !self.up || now - self.since < grace_period
+    }
+}
+
+/// Seconds per year by which Comet divides the Configurator's yearly rates when deployed
+pub const RATE_SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// Parameters of a Comet, as its Configurator stores them for the next `deployAndUpgrade` or
+/// as the deployed Comet runs them
+///
+/// Values are the raw on-chain ones: factors and kinks scaled by 1e18, interest rates per
+/// year scaled by 1e18 (the deployed Comet keeps them per second, so live rates are the
+/// per-second rate times `RATE_SECONDS_PER_YEAR`), amounts in token units.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketConfiguration {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub governor: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub pause_guardian: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub base_token: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub base_token_price_feed: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub extension_delegate: Address,
+    pub supply_kink: u64,
+    pub supply_per_year_interest_rate_slope_low: u64,
+    pub supply_per_year_interest_rate_slope_high: u64,
+    pub supply_per_year_interest_rate_base: u64,
+    pub borrow_kink: u64,
+    pub borrow_per_year_interest_rate_slope_low: u64,
+    pub borrow_per_year_interest_rate_slope_high: u64,
+    pub borrow_per_year_interest_rate_base: u64,
+    pub store_front_price_factor: u64,
+    pub tracking_index_scale: u64,
+    pub base_tracking_supply_speed: u64,
+    pub base_tracking_borrow_speed: u64,
+    pub base_min_for_rewards: u128,
+    pub base_borrow_min: u128,
+    pub target_reserves: u128,
+    pub asset_configs: Vec<AssetConfiguration>,
+}
+
+/// Parameters of one collateral asset in a `MarketConfiguration`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetConfiguration {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub asset: Address,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub price_feed: Address,
+    pub decimals: u8,
+    pub borrow_collateral_factor: u64,
+    pub liquidate_collateral_factor: u64,
+    pub liquidation_factor: u64,
+    pub supply_cap: u128,
+}
+
+/// A market's configuration in its Configurator and in the deployed Comet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigurationPair {
+    /// Address of the Configurator
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub configurator: Address,
+    /// `getConfiguration` of the Configurator
+    pub configured: MarketConfiguration,
+    /// The deployed Comet's parameters
+    pub live: MarketConfiguration,
+}
+
+/// A parameter whose configured and live values differ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigurationDrift {
+    /// Field of `MarketConfiguration` or `AssetConfiguration`
+    pub field: String,
+    /// Collateral asset of an `AssetConfiguration` field
+    #[serde(default, serialize_with = "crate::utils::checksummed_option")]
+    pub asset: Option<Address>,
+    pub configured: String,
+    pub live: String,
+}
+
+impl MarketConfiguration {
+    /// Parameters that differ between `self`, as configured, and `live`
+    ///
+    /// Rates compare at the per-second precision the deployed Comet keeps. Assets are matched
+    /// by address; one listed on a single side is reported as `listed`.
+    pub fn drift(&self, live: &MarketConfiguration) -> Vec<ConfigurationDrift> {
+        let mut drift = Vec::new();
+        let mut differs = |field: &str, asset: Option<Address>, configured: String, live: String| {
+            if configured != live {
+                drift.push(ConfigurationDrift { field: field.to_string(), asset, configured, live });
+            }
+        };
+        let address = |a: &Address| to_checksum_address(a);
+        let per_second = |rate: u64| (rate / RATE_SECONDS_PER_YEAR).to_string();
+        differs("governor", None, address(&self.governor), address(&live.governor));
+        differs("pause_guardian", None, address(&self.pause_guardian), address(&live.pause_guardian));
+        differs("base_token", None, address(&self.base_token), address(&live.base_token));
+        differs("base_token_price_feed", None, address(&self.base_token_price_feed), address(&live.base_token_price_feed));
+        differs("extension_delegate", None, address(&self.extension_delegate), address(&live.extension_delegate));
+        for (field, configured, deployed) in [
+            ("supply_kink", self.supply_kink, live.supply_kink),
+            ("borrow_kink", self.borrow_kink, live.borrow_kink),
+            ("store_front_price_factor", self.store_front_price_factor, live.store_front_price_factor),
+            ("tracking_index_scale", self.tracking_index_scale, live.tracking_index_scale),
+            ("base_tracking_supply_speed", self.base_tracking_supply_speed, live.base_tracking_supply_speed),
+            ("base_tracking_borrow_speed", self.base_tracking_borrow_speed, live.base_tracking_borrow_speed),
+        ] {
+            differs(field, None, configured.to_string(), deployed.to_string());
+        }
+        for (field, configured, deployed) in [
+            ("supply_per_year_interest_rate_slope_low", self.supply_per_year_interest_rate_slope_low, live.supply_per_year_interest_rate_slope_low),
+            ("supply_per_year_interest_rate_slope_high", self.supply_per_year_interest_rate_slope_high, live.supply_per_year_interest_rate_slope_high),
+            ("supply_per_year_interest_rate_base", self.supply_per_year_interest_rate_base, live.supply_per_year_interest_rate_base),
+            ("borrow_per_year_interest_rate_slope_low", self.borrow_per_year_interest_rate_slope_low, live.borrow_per_year_interest_rate_slope_low),
+            ("borrow_per_year_interest_rate_slope_high", self.borrow_per_year_interest_rate_slope_high, live.borrow_per_year_interest_rate_slope_high),
+            ("borrow_per_year_interest_rate_base", self.borrow_per_year_interest_rate_base, live.borrow_per_year_interest_rate_base),
+        ] {
+            if per_second(configured) != per_second(deployed) {
+                differs(field, None, configured.to_string(), deployed.to_string());
+            }
+        }
+        for (field, configured, deployed) in [
+            ("base_min_for_rewards", self.base_min_for_rewards, live.base_min_for_rewards),
+            ("base_borrow_min", self.base_borrow_min, live.base_borrow_min),
+            ("target_reserves", self.target_reserves, live.target_reserves),
+        ] {
+            differs(field, None, configured.to_string(), deployed.to_string());
+        }
+
+        let listed = |configs: &[AssetConfiguration], asset: Address| configs.iter().any(|c| c.asset == asset);
+        for configured in &self.asset_configs {
+            let Some(deployed) = live.asset_configs.iter().find(|c| c.asset == configured.asset) else {
+                differs("listed", Some(configured.asset), "true".to_string(), "false".to_string());
+                continue;
+            };
+            let asset = Some(configured.asset);
+            differs("price_feed", asset, address(&configured.price_feed), address(&deployed.price_feed));
+            for (field, configured, deployed) in [
+                ("decimals", u128::from(configured.decimals), u128::from(deployed.decimals)),
+                ("borrow_collateral_factor", configured.borrow_collateral_factor.into(), deployed.borrow_collateral_factor.into()),
+                ("liquidate_collateral_factor", configured.liquidate_collateral_factor.into(), deployed.liquidate_collateral_factor.into()),
+                ("liquidation_factor", configured.liquidation_factor.into(), deployed.liquidation_factor.into()),
+                ("supply_cap", configured.supply_cap, deployed.supply_cap),
+            ] {
+                differs(field, asset, configured.to_string(), deployed.to_string());
+            }
+        }
+        for deployed in live.asset_configs.iter().filter(|c| !listed(&self.asset_configs, c.asset)) {
+            differs("listed", Some(deployed.asset), "false".to_string(), "true".to_string());
+        }
+        drift
+    }
 }
 
 /// Price change over time for an asset
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceHistory {
     /// Asset address
+    #[serde(serialize_with = "crate::utils::checksummed")]
     pub asset_address: Address,
     /// Asset symbol
     pub symbol: String,
@@ -102,6 +716,67 @@ pub struct PriceHistory {
     pub volatility_30d: f64,
 }
 
+impl PriceHistory {
+    /// Price changes and volatility from daily `points`, oldest first; `None` with fewer than
+    /// two positive prices
+    pub fn from_points(asset_address: Address, symbol: impl Into<String>, points: Vec<(DateTime<Utc>, f64)>) -> Option<Self> {
+        let prices: Vec<f64> = points.iter().map(|(_, price)| *price).filter(|p| *p > 0.0).collect();
+        let &latest = prices.last().filter(|_| prices.len() >= 2)?;
+        let change_over = |days: usize| latest / prices[prices.len().saturating_sub(days + 1)] - 1.0;
+
+        let returns: Vec<f64> = prices.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+
+        Some(Self {
+            asset_address,
+            symbol: symbol.into(),
+            price_points: points,
+            price_change_24h: change_over(1),
+            price_change_7d: change_over(7),
+            volatility_30d: variance.sqrt(),
+        })
+    }
+
+    /// History of this asset's price in units of `base`, from the points both have at the
+    /// same time; `None` with fewer than two of them
+    pub fn relative_to(&self, base: &PriceHistory) -> Option<Self> {
+        let points = self
+            .price_points
+            .iter()
+            .filter_map(|(at, price)| {
+                let (_, base_price) = base.price_points.iter().find(|(base_at, _)| base_at == at)?;
+                (*base_price > 0.0).then(|| (*at, price / base_price))
+            })
+            .collect();
+        Self::from_points(self.asset_address, self.symbol.clone(), points)
+    }
+
+    /// Absolute daily price move at `percentile` (e.g. 0.95) of the moves between consecutive
+    /// price points; `None` with fewer than two usable points
+    ///
+    /// Moves between points further apart than a day are scaled down by the square root of the
+    /// days between them, as for a random walk.
+    pub fn daily_move_percentile(&self, percentile: f64) -> Option<f64> {
+        let mut moves: Vec<f64> = self
+            .price_points
+            .windows(2)
+            .filter(|w| w[0].1 > 0.0 && w[1].1 > 0.0 && w[1].0 > w[0].0)
+            .map(|w| {
+                let days = (w[1].0 - w[0].0).num_seconds() as f64 / 86_400.0;
+                (w[1].1 / w[0].1 - 1.0).abs() / days.max(1.0).sqrt()
+            })
+            .collect();
+        if moves.is_empty() {
+            return None;
+        }
+        moves.sort_by(f64::total_cmp);
+        // Nearest rank
+        let rank = (percentile * moves.len() as f64).ceil() as usize;
+        Some(moves[rank.clamp(1, moves.len()) - 1])
+    }
+}
+
 /// Protocol-level metrics for a Compound V3 deployment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtocolMetrics {
@@ -117,6 +792,9 @@ pub struct ProtocolMetrics {
     pub borrowers_count: u64,
     /// Reserves amount in base asset
     pub reserves: f64,
+    /// Base and net interest rates; `None` where the source does not report them
+    #[serde(default)]
+    pub rates: Option<InterestRates>,
 }
 
 #[cfg(test)]
@@ -137,6 +815,9 @@ mod tests {
             liquidation_penalty: 0.0,
             supply_cap: U256::from(0),
             borrow_cap: U256::from(0),
+            provenance: Provenance::Native,
+            total_supplied: 0.0,
+            protocol_held: None,
         };
 
         let weth_address = Address::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
@@ -151,6 +832,9 @@ mod tests {
             liquidation_penalty: 0.05,
             supply_cap: U256::from(10_000_000_000_000_000_000_000u128), // 10,000 ETH
             borrow_cap: U256::from(0),
+            provenance: Provenance::Native,
+            total_supplied: 5_000.0,
+            protocol_held: None,
         };
 
         let mut collateral_assets = HashMap::new();
@@ -170,10 +854,90 @@ mod tests {
             base_tracking_borrow_speed: U256::from(0),
             base_min_interest_rate: U256::from(0),
             base_max_interest_rate: U256::from(0),
+            base_borrow_min: 100.0,
+            store_front_price_factor: Some(0.5),
+            supply_reward_apr: 0.0,
+            borrow_reward_apr: 0.0,
+            net_supply_apr: 0.0,
+            net_borrow_apr: 0.0,
+            rewards: None,
+            block_number: None,
+            data_issues: Vec::new(),
         };
 
         assert_eq!(market.name, "USDC");
-        assert_eq!(market.utilization_rate, 0.5);
+        assert_eq!((market.utilization_rate, market.utilization()), (0.5, 0.5));
+        assert_eq!(market.utilization_issue(), None);
+
+        // Degenerate totals never yield NaN; inconsistent ones are reported, not clamped
+        assert_eq!(Market::utilization_of(0.0, 0.0), 0.0);
+        assert_eq!(Market::utilization_of(1e-12, 5.0), 0.0);
+        assert_eq!(Market::utilization_of(f64::NAN, 5.0), 0.0);
+        assert_eq!(Market::utilization_of(100.0, f64::INFINITY), 0.0);
+        let overborrowed = Market { total_borrow: 1_200_000_000.0, ..market.clone() };
+        assert!((overborrowed.utilization() - 1.2).abs() < 1e-9);
+        assert!(overborrowed.utilization_issue().unwrap().message.contains("exceeds total supply"));
+        let negative = Market { total_supply: -1.0, ..market.clone() };
+        assert!(negative.utilization_issue().unwrap().message.starts_with("negative totals"));
+        let empty = Market { total_supply: 0.0, total_borrow: 0.0, ..market.clone() };
+        assert_eq!((empty.utilization(), empty.utilization_issue()), (0.0, None));
         assert_eq!(market.collateral_assets.len(), 1);
+
+        let mut rewarded = market.clone();
+        rewarded.set_reward_aprs(0.01, 0.04);
+        assert_eq!((rewarded.net_supply_apr, rewarded.net_borrow_apr), (0.0125 + 0.01, 0.0325 - 0.04));
+
+        // 1 WETH at $2000 with a 0.91 liquidation factor against a $1500 borrow
+        let weth = &market.collateral_assets[&weth_address];
+        let position = UserPosition {
+            address: Address::zero(),
+            base_balance: -1500.0,
+            collateral_balances: HashMap::from([(weth_address, 1.0)]),
+            total_collateral_value: 2000.0,
+            total_borrow_value: 1500.0,
+            health_factor: 2000.0 * 0.91 / 1500.0,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        assert_eq!(position.dominant_collateral(&market).map(|(a, b)| (a.symbol.as_str(), b)), Some(("WETH", 1.0)));
+        let liquidation_price = position.liquidation_price(weth).unwrap();
+        assert!((liquidation_price - 1500.0 / 0.91).abs() < 1e-6, "{}", liquidation_price);
+        assert!((market.health_factor(position.base_balance, &position.collateral_balances) - position.health_factor).abs() < 1e-9);
+
+        // $1650 borrow limit at the 0.825 borrow collateral factor: $150 more, 9.09% of the limit; never negative past the limit
+        let mut position = position;
+        position.set_borrow_capacity(&market);
+        assert!((position.borrow_capacity_usd - 150.0).abs() < 1e-6, "{}", position.borrow_capacity_usd);
+        assert!((position.remaining_borrow_headroom_pct.unwrap() - 150.0 / 1650.0 * 100.0).abs() < 1e-9);
+        assert_eq!(position.borrow_overage(&market), None);
+        let mut over = UserPosition { total_borrow_value: 1800.0, ..position.clone() };
+        over.set_borrow_capacity(&market);
+        assert_eq!((over.borrow_capacity_usd, over.remaining_borrow_headroom_pct), (0.0, Some(0.0)));
+        assert!((over.borrow_overage(&market).unwrap() - 150.0).abs() < 1e-6);
+        let mut unborrowed = UserPosition { total_borrow_value: 0.0, ..position.clone() };
+        unborrowed.set_borrow_capacity(&market);
+        assert_eq!(unborrowed.remaining_borrow_headroom_pct, Some(100.0));
+        let mut bare = UserPosition { collateral_balances: HashMap::new(), ..position.clone() };
+        bare.set_borrow_capacity(&market);
+        assert_eq!((bare.borrow_capacity_usd, bare.remaining_borrow_headroom_pct), (0.0, None));
+
+        assert_eq!(market.available_liquidity(), 500_000_000.0);
+        assert_eq!(overborrowed.available_liquidity(), 0.0);
+
+        // One-line forms people grep logs for; known markets go by their address book label
+        assert_eq!(
+            market.to_string(),
+            "Comet USDC (0xc3d6...cdc3): supply 1.00B USDC, borrow 500M USDC, utilization 50.00%"
+        );
+        assert_eq!(
+            position.to_string(),
+            "0x0000000000000000000000000000000000000000: base -1,500.00, collateral $2,000.00, borrow $1,500.00, health factor 1.21"
+        );
+        let supplier = UserPosition { base_balance: 1_000.0, total_borrow_value: 0.0, health_factor: 100.0, ..position };
+        assert_eq!(
+            supplier.to_string(),
+            "0x0000000000000000000000000000000000000000: base 1,000.00, collateral $2,000.00, no borrow"
+        );
     }
 } 
\ No newline at end of file
@@ -0,0 +1,175 @@
+//! What an assessment run would do, without doing it
+//!
+//! `RiskEngine::plan` walks the configured work the way `assess_risks` does: the markets of
+//! `Config::market_addresses`, the accounts of `tracked_accounts`, the checks
+//! `RiskProcessor` runs once `BudgetPlan` has fitted the market into `rpc.budget`, and the alert
+//! routes of the dispatcher. It reads nothing over the network, so live markets are only known
+//! by address and registry name until read: their collateral count, which the cost of reading
+//! positions depends on, is taken as `ASSUMED_COLLATERAL_ASSETS`. `--dry-run` prints the plan.
+
+use crate::budget::AccountSample;
+use crate::config::{Config, DataSource, SourceKind};
+use crate::error::rpc_host;
+use crate::registry;
+use crate::report::mock_tag;
+use crate::rpc::compute_units;
+use crate::utils::{format_named_address, to_checksum_address};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Collateral assets taken for a live market that has not been read yet
+pub const ASSUMED_COLLATERAL_ASSETS: u64 = 5;
+
+/// One market of a planned run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedMarket {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    /// Collateral assets of the market; `None` for a live market not read yet, planned with
+    /// `ASSUMED_COLLATERAL_ASSETS`
+    pub collateral_assets: Option<usize>,
+    /// Watchlist and borrower index accounts whose positions the checks read
+    pub tracked_accounts: usize,
+    /// Checks that would run, by name, built-in and added by the embedding application
+    pub checks: Vec<String>,
+    /// Optional checks left out to stay within `rpc.budget`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_checks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampled_accounts: Option<AccountSample>,
+    /// Compute units the market may spend; `None` without a per-run budget
+    pub allowance: Option<u64>,
+    /// Estimated RPC requests of reading the market and every tracked position; 0 for data not
+    /// read from a chain
+    pub rpc_calls: u64,
+    /// Estimated compute units of `rpc_calls`, after the weight table of `rpc::compute_units`
+    pub compute_units: u64,
+}
+
+/// Where each kind of data would be read from, e.g. "rpc eth-mainnet.g.alchemy.com (live)"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedSources {
+    pub markets: String,
+    pub positions: String,
+    pub accounts: String,
+    pub price_history: String,
+    /// Market state is also read from the other source and compared
+    pub cross_check: bool,
+}
+
+impl PlannedSources {
+    /// Sources `config` selects; URLs are reduced to their host, which keeps API keys out
+    pub fn of(config: &Config) -> Self {
+        let source = |kind: SourceKind| match (config.data_source, kind) {
+            (DataSource::Mock, _) => "bundled mock dataset".to_string(),
+            (DataSource::Live, SourceKind::Rpc) => {
+                let mode = format!("{:?}", config.rpc.mode).to_lowercase();
+                format!("rpc {} ({})", rpc_host(&config.compound.rpc_url), mode)
+            }
+            (DataSource::Live, SourceKind::Subgraph) => match config.subgraph.endpoints.get(&config.compound.chain_id) {
+                Some(url) => format!("subgraph {}", rpc_host(url)),
+                None => format!("subgraph (no endpoint for chain {})", config.compound.chain_id),
+            },
+        };
+        let subgraph = &config.subgraph;
+        Self {
+            markets: source(subgraph.markets),
+            positions: source(subgraph.positions),
+            accounts: source(subgraph.accounts),
+            price_history: source(subgraph.price_history),
+            cross_check: config.data_source == DataSource::Live && subgraph.cross_check,
+        }
+    }
+}
+
+/// Work of one assessment run over every configured market, as `RiskEngine::plan` finds it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunPlan {
+    pub chain_id: u64,
+    pub markets: Vec<PlannedMarket>,
+    pub data_sources: PlannedSources,
+    /// Alert and digest routes that findings of the run could be sent to
+    pub alert_routes: Vec<String>,
+    /// Watchlist ENS names, resolved when the engine starts for real; their positions are not
+    /// counted in `tracked_accounts`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unresolved_watchlist: Vec<String>,
+    /// `rpc.budget.per_run_compute_units`
+    pub run_budget: Option<u64>,
+    /// Whether the run would assess mock or fixture data rather than chain state
+    pub mock_data: bool,
+}
+
+impl RunPlan {
+    /// Estimated RPC requests of the whole run
+    pub fn rpc_calls(&self) -> u64 {
+        self.markets.iter().map(|market| market.rpc_calls).sum()
+    }
+
+    /// Estimated compute units of the whole run
+    pub fn compute_units(&self) -> u64 {
+        self.markets.iter().map(|market| market.compute_units).sum()
+    }
+}
+
+impl fmt::Display for RunPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Dry run{}: nothing was read over the network", mock_tag(self.mock_data))?;
+        writeln!(f, "\nMarkets on chain {}:", self.chain_id)?;
+        for market in &self.markets {
+            writeln!(f, "  {}", format_named_address(&market.market_name, &market.market_address))?;
+            let collateral = match market.collateral_assets {
+                Some(count) => count.to_string(),
+                None => format!("{} (assumed, not read yet)", ASSUMED_COLLATERAL_ASSETS),
+            };
+            writeln!(f, "    Collateral assets: {}, tracked accounts: {}", collateral, market.tracked_accounts)?;
+            writeln!(f, "    Checks: {}", market.checks.join(", "))?;
+            writeln!(f, "    Estimated RPC: {} call(s), {} compute units", market.rpc_calls, market.compute_units)?;
+            if let Some(allowance) = market.allowance {
+                writeln!(f, "    Allowance: {} compute units", allowance)?;
+            }
+            if let Some(sample) = market.sampled_accounts {
+                writeln!(f, "    Over budget: would sample {} of {} indexed accounts", sample.read, sample.tracked)?;
+            }
+            if !market.skipped_checks.is_empty() {
+                writeln!(f, "    Over budget: would skip {}", market.skipped_checks.join(", "))?;
+            }
+        }
+        let budget = self.run_budget.map(|units| format!(" of the {} allowed", units)).unwrap_or_default();
+        writeln!(f, "\nEstimated RPC: {} call(s), {} compute units{}", self.rpc_calls(), self.compute_units(), budget)?;
+        if !self.unresolved_watchlist.is_empty() {
+            writeln!(f, "Not counted: ENS names resolved at startup ({})", self.unresolved_watchlist.join(", "))?;
+        }
+
+        let sources = &self.data_sources;
+        writeln!(f, "\nData sources:")?;
+        writeln!(f, "  Markets: {}", sources.markets)?;
+        writeln!(f, "  Positions: {}", sources.positions)?;
+        writeln!(f, "  Accounts: {}", sources.accounts)?;
+        writeln!(f, "  Price history: {}", sources.price_history)?;
+        if sources.cross_check {
+            writeln!(f, "  Market state cross-checked against the other source")?;
+        }
+
+        if self.alert_routes.is_empty() {
+            write!(f, "\nAlert routes: none configured")
+        } else {
+            write!(f, "\nAlert routes: {}", self.alert_routes.join(", "))
+        }
+    }
+}
+
+/// Name of the Comet at `address`: the base symbol the registry lists it under on `chain_id`,
+/// as a market read from chain would be named, or its address
+pub fn market_name(chain_id: u64, address: Address) -> String {
+    registry::known_markets(chain_id)
+        .find(|known| known.comet.eq_ignore_ascii_case(&to_checksum_address(&address)))
+        .map_or_else(|| to_checksum_address(&address), |known| known.base_symbol.to_string())
+}
+
+/// Estimated requests of `units` spent on position reads, each an `eth_call`
+pub fn position_calls(units: u64) -> u64 {
+    units.div_ceil(compute_units("eth_call"))
+}
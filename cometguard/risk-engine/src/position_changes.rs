@@ -0,0 +1,204 @@
+//! How watchlist positions change between assessments
+//!
+//! A health factor says how close an account is to liquidation now, not that it just withdrew
+//! half its collateral or doubled its borrow. `PositionHistory` keeps the last position read
+//! of each watchlist account in each market, with the managers it allowed, for as long as the
+//! engine runs. Each assessment compares its reads with it: a `PositionDelta` whose added
+//! borrow or withdrawn collateral reaches `risk.position_changes`, or that allowed a new
+//! manager, is kept in the assessment and published as `RiskEvent::PositionChanged`. The
+//! first read of an account, after a start or once it joins the watchlist, only records it.
+
+use crate::config::PositionChangeConfig;
+use crate::models::{Market, UserPosition};
+use crate::utils::{format_address_labeled, format_compact};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+
+/// How one watchlist position changed since the previous assessment of its market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionDelta {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub account: Address,
+    pub previous_borrow_usd: f64,
+    pub borrow_usd: f64,
+    pub previous_collateral_usd: f64,
+    pub collateral_usd: f64,
+    pub previous_health_factor: f64,
+    pub health_factor: f64,
+    /// Collateral whose balance fell, by symbol, valued in USD at current prices
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub collateral_withdrawn: BTreeMap<String, f64>,
+    /// Managers allowed since the previous read; empty when either read has no managers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "crate::utils::checksummed_list")]
+    pub managers_added: Vec<Address>,
+}
+
+impl PositionDelta {
+    /// Change from `previous` to `current`, both positions of one account in `market`
+    pub fn between(market: &Market, previous: &UserPosition, current: &UserPosition) -> Self {
+        let mut collateral_withdrawn = BTreeMap::new();
+        for (address, before) in &previous.collateral_balances {
+            let after = current.collateral_balances.get(address).copied().unwrap_or(0.0);
+            if after >= *before {
+                continue;
+            }
+            if let Some(asset) = market.collateral_assets.get(address) {
+                *collateral_withdrawn.entry(asset.symbol.clone()).or_insert(0.0) += (before - after) * asset.price;
+            }
+        }
+        Self {
+            account: current.address,
+            previous_borrow_usd: previous.total_borrow_value,
+            borrow_usd: current.total_borrow_value,
+            previous_collateral_usd: previous.total_collateral_value,
+            collateral_usd: current.total_collateral_value,
+            previous_health_factor: previous.health_factor,
+            health_factor: current.health_factor,
+            collateral_withdrawn,
+            managers_added: Vec::new(),
+        }
+    }
+
+    /// Borrow added since the previous read, in USD; 0 if it fell
+    pub fn borrow_added_usd(&self) -> f64 {
+        (self.borrow_usd - self.previous_borrow_usd).max(0.0)
+    }
+
+    /// Collateral withdrawn since the previous read, in USD at current prices
+    pub fn collateral_withdrawn_usd(&self) -> f64 {
+        self.collateral_withdrawn.values().sum()
+    }
+
+    /// Whether the borrow added or the collateral withdrawn reaches either threshold of
+    /// `settings`, or a manager was allowed
+    pub fn is_material(&self, settings: &PositionChangeConfig) -> bool {
+        let reaches = |amount: f64, before: f64| {
+            let share = if before > 0.0 { amount / before } else { f64::INFINITY };
+            amount > 0.0
+                && (settings.min_change_usd.is_some_and(|usd| amount >= usd)
+                    || settings.min_change_share.is_some_and(|min| share >= min))
+        };
+        !self.managers_added.is_empty()
+            || reaches(self.borrow_added_usd(), self.previous_borrow_usd)
+            || reaches(self.collateral_withdrawn_usd(), self.previous_collateral_usd)
+    }
+}
+
+/// `0x1234...abcd: borrow $1.20M → $1.80M (+50.0%), withdrew $400K WETH, health factor 1.45 → 1.12`
+impl fmt::Display for PositionDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", format_address_labeled(&self.account))?;
+        let mut parts = Vec::new();
+        if self.borrow_usd != self.previous_borrow_usd {
+            let change = if self.previous_borrow_usd > 0.0 {
+                format!(" ({:+.1}%)", (self.borrow_usd / self.previous_borrow_usd - 1.0) * 100.0)
+            } else {
+                String::new()
+            };
+            parts.push(format!("borrow {} → {}{}", format_compact(self.previous_borrow_usd), format_compact(self.borrow_usd), change));
+        }
+        for (symbol, value) in &self.collateral_withdrawn {
+            parts.push(format!("withdrew {} {}", format_compact(*value), symbol));
+        }
+        for manager in &self.managers_added {
+            parts.push(format!("allowed manager {}", format_address_labeled(manager)));
+        }
+        parts.push(format!("health factor {:.2} → {:.2}", self.previous_health_factor, self.health_factor));
+        write!(f, " {}", parts.join(", "))
+    }
+}
+
+/// A position as last read, with the managers its account allowed if those were read
+#[derive(Debug, Clone)]
+struct Snapshot {
+    position: UserPosition,
+    managers: Option<Vec<Address>>,
+}
+
+/// Last position read of each watchlist account, by market, shared by the assessments of
+/// one engine
+#[derive(Debug, Default)]
+pub struct PositionHistory {
+    snapshots: Mutex<HashMap<(Address, Address), Snapshot>>,
+}
+
+impl PositionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `positions` read in `market` and the `managers` read of their accounts,
+    /// returning how each account seen before changed
+    ///
+    /// Accounts whose managers were not read this time keep those of the previous read.
+    pub fn observe(&self, market: &Market, positions: &[UserPosition], managers: &HashMap<Address, Vec<Address>>) -> Vec<PositionDelta> {
+        let mut snapshots = self.snapshots.lock().unwrap_or_else(|e| e.into_inner());
+        let mut deltas = Vec::new();
+        for position in positions {
+            let key = (market.comet_address, position.address);
+            let previous = snapshots.get(&key);
+            let current = Snapshot {
+                position: position.clone(),
+                managers: managers.get(&position.address).cloned().or_else(|| previous.and_then(|p| p.managers.clone())),
+            };
+            if let Some(previous) = previous {
+                let mut delta = PositionDelta::between(market, &previous.position, position);
+                if let (Some(before), Some(after)) = (&previous.managers, &current.managers) {
+                    delta.managers_added = after.iter().copied().filter(|m| !before.contains(m)).collect();
+                }
+                deltas.push(delta);
+            }
+            snapshots.insert(key, current);
+        }
+        deltas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+
+    #[test]
+    fn test_changes_are_reported_from_the_second_read() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = provider.data().markets[0].clone();
+        let weth = *market.collateral_assets.keys().next().unwrap();
+        let before = provider.positions_in(&market)[0].clone();
+        let manager = Address::from_low_u64_be(0xbeef);
+        let history = PositionHistory::new();
+
+        // First read: a baseline, not a change from nothing
+        assert!(history.observe(&market, std::slice::from_ref(&before), &HashMap::new()).is_empty());
+
+        // Half the WETH withdrawn and the borrow up 30%, managers read for the first time
+        let mut after = before.clone();
+        after.collateral_balances.insert(weth, 0.5);
+        after.total_collateral_value = 1000.0;
+        after.total_borrow_value = 1300.0;
+        after.health_factor = 0.7;
+        let deltas = history.observe(&market, std::slice::from_ref(&after), &HashMap::from([(before.address, vec![])]));
+        let delta = &deltas[0];
+        assert_eq!((delta.borrow_added_usd(), delta.collateral_withdrawn_usd()), (300.0, 1000.0));
+        assert!(delta.managers_added.is_empty());
+        let settings = PositionChangeConfig::default();
+        assert!(delta.is_material(&settings));
+        assert_eq!(
+            delta.to_string(),
+            "0x1111...1111: borrow $1.00K → $1.30K (+30.0%), withdrew $1.00K WETH, health factor 1.82 → 0.70"
+        );
+        // $300 more on $1,000 is 30%; in USD alone it is not enough
+        let usd_only = PositionChangeConfig { min_change_share: None, ..settings.clone() };
+        assert!(!delta.is_material(&usd_only));
+
+        // Managers not read this time are carried forward, so a later read can add to them
+        assert!(!history.observe(&market, std::slice::from_ref(&after), &HashMap::new())[0].is_material(&settings));
+        let deltas = history.observe(&market, &[after], &HashMap::from([(before.address, vec![manager])]));
+        assert_eq!(deltas[0].managers_added, [manager]);
+        assert!(deltas[0].is_material(&usd_only));
+    }
+}
@@ -0,0 +1,152 @@
+//! Liquidation risk of an externally supplied list of accounts, such as a fund's wallets
+//!
+//! `AddressList` reads one address per line; CSV rows are accepted too, with the address in
+//! the first column. Lines that are not addresses are kept with their line number instead of
+//! failing the list, and `RiskEngine::scan_positions` reports them with the scan.
+
+use crate::error::{Result, RiskEngineError};
+use crate::models::UserPosition;
+use crate::risk::RiskFinding;
+use crate::utils::parse_address;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// A line of an address list that holds no address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidLine {
+    /// 1-based
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+/// Accounts to scan, in the order first listed, and the lines that could not be read
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressList {
+    #[serde(serialize_with = "crate::utils::checksummed_list")]
+    pub addresses: Vec<Address>,
+    pub invalid: Vec<InvalidLine>,
+}
+
+impl AddressList {
+    /// Addresses in `text`, one per line or in the first column of CSV rows
+    ///
+    /// Blank lines, `#` comments and an `address` header row are skipped; repeated addresses
+    /// are kept once.
+    pub fn parse(text: &str) -> Self {
+        let mut list = Self::default();
+        let mut seen = HashSet::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let cell = line.split(',').next().unwrap_or_default().trim().trim_matches('"');
+            if i == 0 && cell.eq_ignore_ascii_case("address") {
+                continue;
+            }
+            match parse_address(cell) {
+                Ok(address) => {
+                    if seen.insert(address) {
+                        list.addresses.push(address);
+                    }
+                }
+                Err(_) => list.invalid.push(InvalidLine {
+                    line: i + 1,
+                    text: line.to_string(),
+                    reason: "not a hex address".to_string(),
+                }),
+            }
+        }
+        list
+    }
+
+    /// `parse` of the file at `path`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        Ok(Self::parse(&text))
+    }
+}
+
+impl FromIterator<Address> for AddressList {
+    fn from_iter<I: IntoIterator<Item = Address>>(iter: I) -> Self {
+        let mut seen = HashSet::new();
+        let addresses = iter.into_iter().filter(|address| seen.insert(*address)).collect();
+        Self { addresses, invalid: Vec::new() }
+    }
+}
+
+/// A scanned account's position and, if it is close to liquidation, the finding about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedPosition {
+    pub position: UserPosition,
+    pub finding: Option<RiskFinding>,
+}
+
+/// An account whose position could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedAccount {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub address: Address,
+    pub error: String,
+}
+
+/// Totals over the positions read
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub accounts: usize,
+    pub total_borrow_value: f64,
+    pub total_collateral_value: f64,
+    /// Positions with a liquidation finding
+    pub at_risk: usize,
+}
+
+/// Outcome of `RiskEngine::scan_positions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionScan {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    /// Lowest health factor first
+    pub positions: Vec<ScannedPosition>,
+    pub failed: Vec<FailedAccount>,
+    /// Lines of the address list that were skipped
+    pub invalid_lines: Vec<InvalidLine>,
+    pub summary: ScanSummary,
+    pub mock_data: bool,
+}
+
+impl ScanSummary {
+    pub fn of(positions: &[ScannedPosition]) -> Self {
+        Self {
+            accounts: positions.len(),
+            total_borrow_value: positions.iter().map(|p| p.position.total_borrow_value).sum(),
+            total_collateral_value: positions.iter().map(|p| p.position.total_collateral_value).sum(),
+            at_risk: positions.iter().filter(|p| p.finding.is_some()).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_lines_are_reported_not_fatal() {
+        let text = format!(
+            "address,owner\n0x{a},treasury\n\n# cold wallets\n\"0x{b}\", \"ops\"\nvitalik.eth\n0x{a}\n0x12,short\n",
+            a = "11".repeat(20),
+            b = "22".repeat(20)
+        );
+        let list = AddressList::parse(&text);
+        assert_eq!(list.addresses, [Address::repeat_byte(0x11), Address::repeat_byte(0x22)]);
+        let invalid: Vec<(usize, &str)> = list.invalid.iter().map(|l| (l.line, l.text.as_str())).collect();
+        assert_eq!(invalid, [(6, "vitalik.eth"), (8, "0x12,short")]);
+
+        let list: AddressList = [Address::repeat_byte(1), Address::repeat_byte(1)].into_iter().collect();
+        assert_eq!(list.addresses.len(), 1);
+    }
+}
@@ -0,0 +1,150 @@
+//! Progress of long-running operations: borrower scans and batched position fetches
+//!
+//! Operations publish through a `ProgressReporter` instead of printing. The engine's reporter
+//! drives the CLI's progress bar and is part of `Health`, so `GET /healthz` and the dashboard
+//! footer show the same numbers.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// Operation being reported on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Scanning Comet logs for borrowers, counted in blocks
+    BorrowerScan,
+    /// Reading account positions in batches, counted in accounts
+    PositionFetch,
+    /// Scanning Configurator logs for Comet deployments, counted in blocks
+    MarketDiscovery,
+}
+
+impl Stage {
+    /// What the operation is doing, for progress bars and log lines
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::BorrowerScan => "Scanning blocks",
+            Stage::PositionFetch => "Fetching positions",
+            Stage::MarketDiscovery => "Scanning Configurator events",
+        }
+    }
+
+    /// What the operation counts, e.g. "blocks" in "blocks/sec"
+    pub fn unit(self) -> &'static str {
+        match self {
+            Stage::BorrowerScan | Stage::MarketDiscovery => "blocks",
+            Stage::PositionFetch => "accounts",
+        }
+    }
+}
+
+/// `done` of `total` units of work finished in `stage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub stage: Stage,
+    pub done: u64,
+    pub total: u64,
+    /// Units finished per second since the operation started; `None` during its first second
+    #[serde(default)]
+    pub per_second: Option<u64>,
+}
+
+impl Progress {
+    /// Share of the work done, from 0 to 1; an operation with nothing to do is complete
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done.min(self.total) as f64 / self.total as f64
+        }
+    }
+}
+
+/// Latest progress of the operation in flight, or `None` when idle
+///
+/// Clones share one channel. Concurrent operations overwrite each other's reports; the
+/// newest one wins.
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    tx: Arc<watch::Sender<Option<Progress>>>,
+}
+
+impl Default for ProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressReporter {
+    /// Reporter nobody listens to yet
+    pub fn new() -> Self {
+        Self { tx: Arc::new(watch::channel(None).0) }
+    }
+
+    /// Report the start of `total` units of `stage`; the operation counts as finished once
+    /// the returned task is dropped, including on errors and cancellation
+    pub fn start(&self, stage: Stage, total: u64) -> ProgressTask {
+        let task = ProgressTask { reporter: self.clone(), stage, total, started: Instant::now() };
+        task.set(0);
+        task
+    }
+
+    /// The latest report, or `None` when no operation is in flight
+    pub fn current(&self) -> Option<Progress> {
+        *self.tx.borrow()
+    }
+
+    /// Receiver notified of every report
+    pub fn subscribe(&self) -> watch::Receiver<Option<Progress>> {
+        self.tx.subscribe()
+    }
+}
+
+/// Handle of one reported operation, returned by `ProgressReporter::start`
+#[derive(Debug)]
+pub struct ProgressTask {
+    reporter: ProgressReporter,
+    stage: Stage,
+    total: u64,
+    started: Instant,
+}
+
+impl ProgressTask {
+    /// Record that `done` units are finished
+    pub fn set(&self, done: u64) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let per_second = (elapsed >= 1.0).then(|| (done as f64 / elapsed).round() as u64);
+        self.reporter.tx.send_replace(Some(Progress { stage: self.stage, done, total: self.total, per_second }));
+    }
+}
+
+impl Drop for ProgressTask {
+    fn drop(&mut self) {
+        self.reporter.tx.send_replace(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_reports_until_dropped() {
+        let reporter = ProgressReporter::new();
+        let mut rx = reporter.subscribe();
+        assert_eq!(reporter.current(), None);
+
+        let task = reporter.start(Stage::PositionFetch, 4);
+        task.set(3);
+        let progress = rx.borrow_and_update().unwrap();
+        assert_eq!(progress, Progress { stage: Stage::PositionFetch, done: 3, total: 4, per_second: None });
+        assert_eq!(progress.fraction(), 0.75);
+
+        drop(task);
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), None);
+        assert_eq!(Progress { stage: Stage::BorrowerScan, done: 0, total: 0, per_second: None }.fraction(), 1.0);
+    }
+}
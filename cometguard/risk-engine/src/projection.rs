@@ -0,0 +1,105 @@
+//! Time until interest alone makes a position liquidatable
+//!
+//! The borrow balance compounds daily at the market's current borrow APR, which Comet's rate
+//! model gives for the current utilization, plus `risk.projection.borrow_rate_shock`. The user
+//! takes no action and utilization stays where it is. Collateral prices either stay put or
+//! change by `risk.projection.collateral_price_drift` a year, also compounded daily; the base
+//! asset keeps its price. The health factor then evolves as
+//! `hf(n) = hf(0) * ((1 + drift / 365) / (1 + apr / 365))^n` after `n` days.
+
+use crate::config::ProjectionConfig;
+use crate::models::{Market, UserPosition};
+use serde::{Deserialize, Serialize};
+
+/// Compounding periods per year
+pub const DAYS_PER_YEAR: f64 = 365.0;
+
+/// Days until a position's health factor falls below 1; `None` where it never does
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationProjection {
+    /// Borrow APR the projection compounds at, shock included
+    pub borrow_apr: f64,
+    /// At the current collateral prices
+    pub days_at_static_prices: Option<u64>,
+    /// Yearly collateral price change of the drift scenario
+    pub collateral_price_drift: f64,
+    /// With collateral prices changing by `collateral_price_drift` a year
+    pub days_with_price_drift: Option<u64>,
+}
+
+/// Projection for `position` in `market` under `assumptions`
+///
+/// Positions supplying base or without a borrow are never liquidated by interest; positions
+/// already below a health factor of 1 get 0 days.
+pub fn project(position: &UserPosition, market: &Market, assumptions: &ProjectionConfig) -> LiquidationProjection {
+    let borrow_apr = market.borrow_apr + assumptions.borrow_rate_shock;
+    let borrowing = position.base_balance < 0.0 && position.total_borrow_value > 0.0;
+    let days = |drift: f64| borrowing.then(|| days_until_liquidation(position.health_factor, borrow_apr, drift)).flatten();
+    LiquidationProjection {
+        borrow_apr,
+        days_at_static_prices: days(0.0),
+        collateral_price_drift: assumptions.collateral_price_drift,
+        days_with_price_drift: days(assumptions.collateral_price_drift),
+    }
+}
+
+/// First whole day on which `health_factor`, compounded as described in the module docs, is below 1
+pub fn days_until_liquidation(health_factor: f64, borrow_apr: f64, collateral_price_drift: f64) -> Option<u64> {
+    if health_factor < 1.0 {
+        return Some(0);
+    }
+    // Daily factor by which the health factor shrinks
+    let decay = (1.0 + borrow_apr / DAYS_PER_YEAR) / (1.0 + collateral_price_drift / DAYS_PER_YEAR);
+    if !(decay.is_finite() && decay > 1.0) {
+        return None;
+    }
+    let days = health_factor.ln() / decay.ln();
+    // At exactly `days` the health factor is 1, which is not yet liquidatable
+    let first = days.floor() + 1.0;
+    (first.is_finite() && first < u64::MAX as f64).then_some(first as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+    #[test]
+    fn test_days_match_hand_computed_compounding() {
+        // 36.5% APR is 0.1% a day: 1.05 / 1.001^n < 1 from n = ln 1.05 / ln 1.001 = 48.8 on
+        assert_eq!(days_until_liquidation(1.05, 0.365, 0.0), Some(49));
+        // 1.001^48 = 1.04917 still leaves it above 1, 1.001^49 = 1.05022 does not
+        assert!(1.05 / 1.001f64.powi(48) > 1.0 && 1.05 / 1.001f64.powi(49) < 1.0);
+        // Collateral losing 36.5% a year as well: (1.001 / 0.999)^n, n > 24.4
+        assert_eq!(days_until_liquidation(1.05, 0.365, -0.365), Some(25));
+        // Collateral gaining as fast as interest accrues never catches up
+        assert_eq!(days_until_liquidation(1.05, 0.365, 0.365), None);
+        assert_eq!(days_until_liquidation(1.05, 0.0, 0.0), None);
+        assert_eq!(days_until_liquidation(0.98, 0.365, 0.0), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_suppliers_and_debt_free_positions_are_never_liquidated() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let mut position = fixture.get_user_position(&market, "0x2222222222222222222222222222222222222222".parse().unwrap()).await.unwrap();
+
+        // 1 WETH at $2000 counted at the 0.91 liquidation factor against $1750: 1.04, not the
+        // 0.94 the 0.825 borrow collateral factor would give
+        assert!((position.health_factor - 2000.0 * 0.91 / 1750.0).abs() < 1e-9, "{}", position.health_factor);
+        assert!((market.health_factor(position.base_balance, &position.collateral_balances) - position.health_factor).abs() < 1e-9);
+
+        // The fixture borrows at 7.1%: ln 1.04 / ln(1 + 0.071 / 365) = 201.65 days
+        let projection = project(&position, &market, &ProjectionConfig { borrow_rate_shock: 0.0, collateral_price_drift: -0.2 });
+        assert_eq!(projection.days_at_static_prices, Some(202));
+        assert!(projection.days_with_price_drift.unwrap() < 202);
+        let shocked = project(&position, &market, &ProjectionConfig { borrow_rate_shock: 0.1, ..ProjectionConfig::default() });
+        assert_eq!(shocked.borrow_apr, 0.071 + 0.1);
+        assert!(shocked.days_at_static_prices.unwrap() < 202);
+
+        position.base_balance = 500.0;
+        position.total_borrow_value = 0.0;
+        let projection = project(&position, &market, &ProjectionConfig::default());
+        assert_eq!((projection.days_at_static_prices, projection.days_with_price_drift), (None, None));
+    }
+}
@@ -0,0 +1,123 @@
+//! Collateral absorbed into the protocol and not yet bought back
+//!
+//! Absorbing an underwater position moves its collateral to the protocol, which sells it to
+//! anyone calling `buyCollateral` at the storefront discount. While buyers stay away the
+//! protocol holds that volatile collateral against the reserves that covered the absorbed
+//! borrow: the liquidations happened, yet their risk is still there, and no position shows it.
+//! Comet reports what it holds of each asset with `getCollateralReserves`, read with the
+//! market as `Asset::protocol_held`. Stored market snapshots keep it, so `ProtocolHolding::of`
+//! can tell for how many assessments in a row an asset has been held in size.
+
+use crate::collateral_changes::CollateralParameters;
+use crate::models::Market;
+use crate::utils::{format_compact, format_token_amount};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Collateral of one asset the protocol holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolHolding {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub asset: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    /// Amount held, in asset units
+    pub amount: f64,
+    /// Value held, in USD at the current price
+    pub value_usd: f64,
+    /// Consecutive assessments, this one included, in which the holding was elevated; 0 when
+    /// it is not elevated now
+    pub elevated_snapshots: usize,
+    /// Time of the first of those assessments; `None` when not elevated now
+    pub elevated_since: Option<DateTime<Utc>>,
+}
+
+impl ProtocolHolding {
+    /// Holdings of each collateral asset of `market` assessed at `now`, with how long each has
+    /// been worth at least `elevated_usd` over `history`, the collateral parameters of earlier
+    /// snapshots (oldest first)
+    ///
+    /// Earlier amounts are valued at the current price, so a streak follows the amount held
+    /// rather than its price. A snapshot without the amount ends the streak, as do assets whose
+    /// holdings were not read now, which are left out.
+    pub fn of(market: &Market, history: &[(DateTime<Utc>, Vec<CollateralParameters>)], now: DateTime<Utc>, elevated_usd: f64) -> Vec<Self> {
+        let mut assets: Vec<_> = market.collateral_assets.values().collect();
+        assets.sort_by_key(|asset| asset.address);
+        let mut holdings = Vec::new();
+        for asset in assets {
+            let Some(amount) = asset.protocol_held else { continue };
+            let elevated = |amount: f64| amount > 0.0 && amount * asset.price >= elevated_usd;
+            let (mut elevated_snapshots, mut elevated_since) = (0, None);
+            if elevated(amount) {
+                (elevated_snapshots, elevated_since) = (1, Some(now));
+                for (timestamp, collateral) in history.iter().rev() {
+                    let held = collateral.iter().find(|p| p.asset == asset.address).and_then(|p| p.protocol_held);
+                    if !held.is_some_and(elevated) {
+                        break;
+                    }
+                    elevated_snapshots += 1;
+                    elevated_since = Some(*timestamp);
+                }
+            }
+            holdings.push(Self {
+                asset: asset.address,
+                symbol: asset.symbol.clone(),
+                decimals: asset.decimals,
+                amount,
+                value_usd: amount * asset.price,
+                elevated_snapshots,
+                elevated_since,
+            });
+        }
+        holdings
+    }
+}
+
+/// `120.00 wstETH ($432K), held since 2024-05-01 12:00 UTC`
+impl fmt::Display for ProtocolHolding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", format_token_amount(self.amount, &self.symbol, self.decimals), format_compact(self.value_usd))?;
+        if let Some(since) = self.elevated_since {
+            write!(f, ", held since {}", since.format("%Y-%m-%d %H:%M UTC"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use chrono::Duration;
+
+    #[test]
+    fn test_elevated_holdings_count_back_to_the_first_snapshot_holding_them() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut market = provider.data().markets[0].clone();
+        let weth = *market.collateral_assets.keys().next().unwrap();
+        let now = "2024-05-08T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let snapshot = |hours: i64, held: Option<f64>| {
+            let mut collateral = CollateralParameters::of(&market);
+            collateral[0].protocol_held = held;
+            (now - Duration::hours(hours), collateral)
+        };
+        // 100 WETH at $2,000 is elevated at $100K; 10 WETH is not, and neither is a
+        // snapshot stored before holdings were
+        let history = [snapshot(4, Some(100.0)), snapshot(3, None), snapshot(2, Some(10.0)), snapshot(1, Some(150.0))];
+
+        market.collateral_assets.get_mut(&weth).unwrap().protocol_held = Some(120.0);
+        let holdings = ProtocolHolding::of(&market, &history, now, 100_000.0);
+        assert_eq!((holdings[0].amount, holdings[0].value_usd), (120.0, 240_000.0));
+        assert_eq!((holdings[0].elevated_snapshots, holdings[0].elevated_since), (2, Some(now - Duration::hours(1))));
+        assert_eq!(holdings[0].to_string(), "120.00 WETH ($240K), held since 2024-05-07 23:00 UTC");
+
+        market.collateral_assets.get_mut(&weth).unwrap().protocol_held = Some(20.0);
+        let holdings = ProtocolHolding::of(&market, &history, now, 100_000.0);
+        assert_eq!((holdings[0].elevated_snapshots, holdings[0].elevated_since), (0, None));
+
+        market.collateral_assets.get_mut(&weth).unwrap().protocol_held = None;
+        assert!(ProtocolHolding::of(&market, &history, now, 100_000.0).is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+use crate::compound::{CompoundClient, PositionResult};
+use crate::ens::EnsResolver;
+use crate::gas::GasQuote;
+use crate::error::{Result, RiskEngineError};
+use crate::models::{ConfigurationPair, ManagerPermissions, Market, PriceHistory, ProtocolMetrics, SequencerStatus, UserPosition};
+use crate::rpc::RpcBudget;
+use crate::scanner::LogSource;
+use async_trait::async_trait;
+use ethers::types::Address;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Source of market, position and price data consumed by the engine
+///
+/// `CompoundClient` reads from chain; `FixtureProvider` serves canned data for tests and demos.
+#[async_trait]
+pub trait MarketDataProvider: Send + Sync {
+    /// All markets known to this provider, possibly served from cache
+    async fn get_markets(&self) -> Result<Vec<Market>>;
+
+    /// Re-fetch all markets, bypassing any cache
+    async fn refresh_markets(&self) -> Result<Vec<Market>> {
+        self.get_markets().await
+    }
+
+    /// A single account's position in `market`
+    async fn get_user_position(&self, market: &Market, user: Address) -> Result<UserPosition>;
+
+    /// Several accounts' positions; failures are reported per address
+    async fn get_positions(&self, market: &Market, users: &[Address]) -> Result<Vec<PositionResult>> {
+        let mut results = Vec::with_capacity(users.len());
+        for &user in users {
+            results.push((user, self.get_user_position(market, user).await));
+        }
+        Ok(results)
+    }
+
+    /// Borrow of each of `users` in `market`, in USD; failures are reported per address
+    ///
+    /// Providers that can read the borrow alone override this to skip the rest of the position.
+    async fn get_borrows(&self, market: &Market, users: &[Address]) -> Result<Vec<(Address, Result<f64>)>> {
+        let positions = self.get_positions(market, users).await?;
+        Ok(positions.into_iter().map(|(user, position)| (user, position.map(|p| p.total_borrow_value))).collect())
+    }
+
+    /// Price history of an asset listed in `market`
+    async fn get_price_history(&self, market: &Market, asset: Address) -> Result<PriceHistory>;
+
+    /// Protocol-level metrics for `market`
+    async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics>;
+
+    /// Managers `account` has allowed in `market`, checking `candidates` besides those the
+    /// provider discovers itself
+    async fn get_manager_permissions(&self, _market: &Market, _account: Address, _candidates: &[Address]) -> Result<ManagerPermissions> {
+        Err(RiskEngineError::Unavailable {
+            what: "manager permissions",
+            reason: "this provider does not read account permissions".to_string(),
+        })
+    }
+
+    /// State of the network's L2 sequencer; `None` when no uptime feed is configured
+    async fn get_sequencer_status(&self) -> Result<Option<SequencerStatus>> {
+        Ok(None)
+    }
+
+    /// Current fees of the network, with priority fees at `fee_percentile` of recent blocks;
+    /// `None` for providers that cannot read them
+    async fn get_gas_quote(&self, _fee_percentile: f64) -> Result<Option<GasQuote>> {
+        Ok(None)
+    }
+
+    /// Configuration of `market` in its Configurator and in the deployed Comet; `None` for
+    /// providers that cannot read the Configurator
+    async fn get_market_configurations(&self, _market: &Market) -> Result<Option<ConfigurationPair>> {
+        Ok(None)
+    }
+
+    /// Log source for borrower discovery, if this provider can read chain logs
+    fn log_source(&self) -> Option<Arc<dyn LogSource>> {
+        None
+    }
+
+    /// ENS resolver, if this provider reads from a chain
+    fn name_resolver(&self) -> Option<Arc<EnsResolver>> {
+        None
+    }
+
+    /// Compute-unit accounting of the RPC endpoint, if this provider reads from a chain
+    fn rpc_budget(&self) -> Option<Arc<RpcBudget>> {
+        None
+    }
+
+    /// Whether this provider serves mock or fixture data instead of chain state
+    fn is_mock(&self) -> bool {
+        false
+    }
+
+    /// Hits and misses of the market cache, if this provider keeps one
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+/// Market lookups a provider served from its cache, and ones it had to read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups served from the cache; `None` before the first lookup
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+/// Shared handle to a data provider
+pub type SharedProvider = Arc<dyn MarketDataProvider>;
+
+#[async_trait]
+impl MarketDataProvider for CompoundClient {
+    async fn get_markets(&self) -> Result<Vec<Market>> {
+        CompoundClient::get_markets(self).await
+    }
+
+    async fn refresh_markets(&self) -> Result<Vec<Market>> {
+        CompoundClient::refresh_markets(self).await
+    }
+
+    async fn get_user_position(&self, market: &Market, user: Address) -> Result<UserPosition> {
+        CompoundClient::get_user_position(self, market, user).await
+    }
+
+    async fn get_positions(&self, market: &Market, users: &[Address]) -> Result<Vec<PositionResult>> {
+        let concurrency = self.config().performance.concurrency_limit();
+        Ok(self.get_positions_batch(market, users.to_vec(), concurrency).collect().await)
+    }
+
+    async fn get_borrows(&self, market: &Market, users: &[Address]) -> Result<Vec<(Address, Result<f64>)>> {
+        let concurrency = self.config().performance.concurrency_limit();
+        CompoundClient::get_borrows(self, market, users, concurrency).await
+    }
+
+    async fn get_price_history(&self, market: &Market, asset: Address) -> Result<PriceHistory> {
+        CompoundClient::get_price_history(self, market, asset).await
+    }
+
+    async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics> {
+        CompoundClient::get_protocol_metrics(self, market).await
+    }
+
+    async fn get_manager_permissions(&self, market: &Market, account: Address, candidates: &[Address]) -> Result<ManagerPermissions> {
+        CompoundClient::get_manager_permissions(self, market, account, candidates).await
+    }
+
+    async fn get_sequencer_status(&self) -> Result<Option<SequencerStatus>> {
+        CompoundClient::get_sequencer_status(self).await
+    }
+
+    async fn get_gas_quote(&self, fee_percentile: f64) -> Result<Option<GasQuote>> {
+        CompoundClient::get_gas_quote(self, fee_percentile).await.map(Some)
+    }
+
+    async fn get_market_configurations(&self, market: &Market) -> Result<Option<ConfigurationPair>> {
+        CompoundClient::get_market_configurations(self, market).await.map(Some)
+    }
+
+    fn log_source(&self) -> Option<Arc<dyn LogSource>> {
+        Some(self.provider())
+    }
+
+    fn name_resolver(&self) -> Option<Arc<EnsResolver>> {
+        Some(CompoundClient::name_resolver(self))
+    }
+
+    fn rpc_budget(&self) -> Option<Arc<RpcBudget>> {
+        Some(CompoundClient::rpc_budget(self))
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(CompoundClient::cache_stats(self))
+    }
+}
+
+/// Position entry in a fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixturePosition {
+    /// Comet address of the market the position belongs to
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market: Address,
+    /// The position itself
+    pub position: UserPosition,
+}
+
+/// Protocol metrics entry in a fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureMetrics {
+    /// Comet address of the market
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market: Address,
+    /// The metrics
+    pub metrics: ProtocolMetrics,
+}
+
+/// Manager permissions entry in a fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixturePermissions {
+    /// Comet address of the market
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market: Address,
+    /// The account's permissions
+    pub permissions: ManagerPermissions,
+}
+
+/// Configurator and deployed configuration entry in a fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureConfigurations {
+    /// Comet address of the market
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market: Address,
+    pub configurations: ConfigurationPair,
+}
+
+/// Contents of a fixture JSON file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FixtureData {
+    /// Markets, in the order they should be assessed
+    pub markets: Vec<Market>,
+    /// Known positions
+    #[serde(default)]
+    pub positions: Vec<FixturePosition>,
+    /// Price histories by asset
+    #[serde(default)]
+    pub price_history: Vec<PriceHistory>,
+    /// Protocol metrics by market
+    #[serde(default)]
+    pub protocol_metrics: Vec<FixtureMetrics>,
+    /// Manager permissions by market and account; accounts not listed have no managers
+    #[serde(default)]
+    pub permissions: Vec<FixturePermissions>,
+    /// L2 sequencer state; the network has no uptime feed when unset
+    #[serde(default)]
+    pub sequencer: Option<SequencerStatus>,
+    /// Fees of the network; the provider has no gas quote when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<GasQuote>,
+    /// Configurations by market; markets not listed have no Configurator to read
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<FixtureConfigurations>,
+    /// Accounts whose positions fail to read in every market, as if their calls reverted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unreadable_accounts: Vec<Address>,
+}
+
+/// Demo dataset compiled into the crate, so mock mode works regardless of the working directory
+const DEMO_FIXTURE: &str = include_str!("../fixtures/demo.json");
+
+/// Provider serving data loaded from JSON fixtures, with no network access
+#[derive(Debug, Clone)]
+pub struct FixtureProvider {
+    data: FixtureData,
+    positions: HashMap<(Address, Address), UserPosition>,
+}
+
+impl FixtureProvider {
+    /// Build a provider from already-loaded fixture data
+    pub fn new(mut data: FixtureData) -> Self {
+        for market in &mut data.markets {
+            market.utilization_rate = market.utilization();
+            market.annotate_data_quality();
+        }
+        for entry in &mut data.positions {
+            if let Some(market) = data.markets.iter().find(|m| m.comet_address == entry.market) {
+                entry.position.set_borrow_capacity(market);
+            }
+        }
+        let positions = data
+            .positions
+            .iter()
+            .map(|p| ((p.market, p.position.address), p.position.clone()))
+            .collect();
+        Self { data, positions }
+    }
+
+    /// Load fixture data from a JSON file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        let data = serde_json::from_str(&content)
+            .map_err(|e| RiskEngineError::serialization(format!("fixture {}", path.display()), e))?;
+        Ok(Self::new(data))
+    }
+
+    /// The bundled deterministic demo dataset served in `data_source = "mock"` mode
+    pub fn demo() -> Self {
+        let data = serde_json::from_str(DEMO_FIXTURE).expect("bundled demo fixture is valid JSON");
+        Self::new(data)
+    }
+
+    /// The loaded fixture data
+    pub fn data(&self) -> &FixtureData {
+        &self.data
+    }
+
+    /// All fixture positions in `market`
+    pub fn positions_in(&self, market: &Market) -> Vec<UserPosition> {
+        self.data
+            .positions
+            .iter()
+            .filter(|p| p.market == market.comet_address)
+            .map(|p| p.position.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for FixtureProvider {
+    async fn get_markets(&self) -> Result<Vec<Market>> {
+        Ok(self.data.markets.clone())
+    }
+
+    async fn get_user_position(&self, market: &Market, user: Address) -> Result<UserPosition> {
+        if self.data.unreadable_accounts.contains(&user) {
+            return Err(RiskEngineError::Unavailable {
+                what: "position",
+                reason: format!("the fixture makes {} unreadable", crate::utils::to_checksum_address(&user)),
+            });
+        }
+        self.positions
+            .get(&(market.comet_address, user))
+            .cloned()
+            .ok_or_else(|| RiskEngineError::NotFound {
+                kind: "position",
+                id: format!("{} in {}", crate::utils::to_checksum_address(&user), crate::utils::to_checksum_address(&market.comet_address)),
+            })
+    }
+
+    async fn get_price_history(&self, _market: &Market, asset: Address) -> Result<PriceHistory> {
+        self.data
+            .price_history
+            .iter()
+            .find(|h| h.asset_address == asset)
+            .cloned()
+            .ok_or_else(|| RiskEngineError::NotFound {
+                kind: "price history",
+                id: format!("{:?}", asset),
+            })
+    }
+
+    async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics> {
+        self.data
+            .protocol_metrics
+            .iter()
+            .find(|m| m.market == market.comet_address)
+            .map(|m| m.metrics.clone())
+            .ok_or_else(|| RiskEngineError::NotFound {
+                kind: "protocol metrics",
+                id: crate::utils::to_checksum_address(&market.comet_address),
+            })
+    }
+
+    async fn get_manager_permissions(&self, market: &Market, account: Address, _candidates: &[Address]) -> Result<ManagerPermissions> {
+        Ok(self
+            .data
+            .permissions
+            .iter()
+            .find(|p| p.market == market.comet_address && p.permissions.account == account)
+            .map(|p| p.permissions.clone())
+            .unwrap_or(ManagerPermissions { account, nonce: 0, managers: Vec::new(), complete: true }))
+    }
+
+    async fn get_sequencer_status(&self) -> Result<Option<SequencerStatus>> {
+        Ok(self.data.sequencer)
+    }
+
+    async fn get_gas_quote(&self, _fee_percentile: f64) -> Result<Option<GasQuote>> {
+        Ok(self.data.gas)
+    }
+
+    async fn get_market_configurations(&self, market: &Market) -> Result<Option<ConfigurationPair>> {
+        Ok(self.data.configurations.iter().find(|c| c.market == market.comet_address).map(|c| c.configurations.clone()))
+    }
+
+    fn is_mock(&self) -> bool {
+        true
+    }
+}
+
+/// Path of a fixture file shipped with the crate (under `fixtures/`)
+pub fn bundled_fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fixture_provider_loads_basic_fixture() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let markets = provider.get_markets().await.unwrap();
+        assert_eq!(markets.len(), 1);
+
+        let market = &markets[0];
+        assert_eq!(market.collateral_assets.len(), 1);
+
+        let positions = provider.positions_in(market);
+        assert!(!positions.is_empty());
+        let position = provider.get_user_position(market, positions[0].address).await.unwrap();
+        assert_eq!(position.address, positions[0].address);
+
+        let missing = provider.get_user_position(market, Address::from_low_u64_be(42)).await;
+        assert!(matches!(missing, Err(RiskEngineError::NotFound { .. })));
+
+        let results = provider
+            .get_positions(market, &[positions[0].address, Address::from_low_u64_be(42)])
+            .await
+            .unwrap();
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_demo_dataset_covers_varied_positions() {
+        let provider = FixtureProvider::demo();
+        assert!(provider.is_mock());
+
+        let markets = provider.get_markets().await.unwrap();
+        assert!(markets.len() >= 3);
+
+        let positions = &provider.data().positions;
+        assert!(positions.len() >= 30);
+        assert!(positions.iter().any(|p| p.position.health_factor < 1.0));
+        assert!(positions.iter().any(|p| (1.0..1.1).contains(&p.position.health_factor)));
+        assert!(positions.iter().any(|p| p.position.health_factor > 2.0));
+        for market in &markets {
+            assert!(!provider.positions_in(market).is_empty(), "{} has no positions", market.name);
+            assert!(provider.get_protocol_metrics(market).await.is_ok());
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Largest positions in a market, for concentration reviews
+//!
+//! Only tracked accounts are ranked: the watchlist plus the scanner's borrower index. The index
+//! holds accounts that withdrew base, so it covers borrowers but misses suppliers that never
+//! withdrew; supplier rankings are a lower bound on concentration.
+
+use crate::models::{Market, UserPosition};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+/// What positions are ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionSort {
+    /// Borrowed value; positions without a borrow are left out
+    Borrow,
+    /// Supplied base value; positions without a base supply are left out
+    Supply,
+}
+
+/// Health factor bounds, inclusive; positions outside them are not ranked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthFactorFilter {
+    pub min_hf: Option<f64>,
+    pub max_hf: Option<f64>,
+}
+
+impl HealthFactorFilter {
+    pub fn matches(&self, position: &UserPosition) -> bool {
+        self.min_hf.is_none_or(|min| position.health_factor >= min)
+            && self.max_hf.is_none_or(|max| position.health_factor <= max)
+    }
+}
+
+/// One collateral asset of a position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollateralHolding {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub asset_address: Address,
+    pub symbol: String,
+    pub balance: f64,
+    pub value_usd: f64,
+}
+
+/// A position with the values it is ranked by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedPosition {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub address: Address,
+    /// Primary ENS name, filled in by `RiskEngine::top_positions` when reverse lookups are on
+    pub account_name: Option<String>,
+    pub borrow_value_usd: f64,
+    pub supply_value_usd: f64,
+    /// Share of the market's total borrow or supply, depending on the sort, from 0 to 1
+    pub market_share: f64,
+    pub health_factor: f64,
+    /// Collateral by asset, largest value first
+    pub collateral: Vec<CollateralHolding>,
+    /// Borrow over every market the provider lists, filled in by `RiskEngine::top_positions`
+    /// when it lists more than this one
+    #[serde(default)]
+    pub all_markets_borrow_usd: Option<f64>,
+    /// That borrow's share of the borrow of all those markets, from 0 to 1
+    #[serde(default)]
+    pub protocol_borrow_share: Option<f64>,
+}
+
+/// Outcome of a ranking
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopPositions {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub sort: PositionSort,
+    /// Market total borrow or supply, depending on the sort, in USD
+    pub market_total_usd: f64,
+    /// Tracked positions that passed the filter, before `limit` was applied
+    pub matching: usize,
+    pub positions: Vec<RankedPosition>,
+    pub mock_data: bool,
+}
+
+/// The `limit` largest of `positions` in `market` by `sort` that `filter` keeps, largest first
+pub fn rank(
+    market: &Market,
+    positions: &[UserPosition],
+    sort: PositionSort,
+    limit: usize,
+    filter: HealthFactorFilter,
+) -> TopPositions {
+    let base_price = market.base_asset.price;
+    let market_total_usd = match sort {
+        PositionSort::Borrow => market.total_borrow * base_price,
+        PositionSort::Supply => market.total_supply * base_price,
+    };
+
+    let mut ranked: Vec<RankedPosition> = positions
+        .iter()
+        .filter(|position| filter.matches(position))
+        .map(|position| {
+            let supply_value_usd = position.base_balance.max(0.0) * base_price;
+            let value = match sort {
+                PositionSort::Borrow => position.total_borrow_value,
+                PositionSort::Supply => supply_value_usd,
+            };
+            RankedPosition {
+                address: position.address,
+                account_name: None,
+                borrow_value_usd: position.total_borrow_value,
+                supply_value_usd,
+                market_share: if market_total_usd > 0.0 { value / market_total_usd } else { 0.0 },
+                health_factor: position.health_factor,
+                collateral: collateral_holdings(market, position),
+                all_markets_borrow_usd: None,
+                protocol_borrow_share: None,
+            }
+        })
+        .filter(|ranked| match sort {
+            PositionSort::Borrow => ranked.borrow_value_usd > 0.0,
+            PositionSort::Supply => ranked.supply_value_usd > 0.0,
+        })
+        .collect();
+    let value = |ranked: &RankedPosition| match sort {
+        PositionSort::Borrow => ranked.borrow_value_usd,
+        PositionSort::Supply => ranked.supply_value_usd,
+    };
+    ranked.sort_by(|a, b| value(b).total_cmp(&value(a)).then(a.address.cmp(&b.address)));
+    let matching = ranked.len();
+    ranked.truncate(limit);
+
+    TopPositions {
+        market_name: market.name.clone(),
+        market_address: market.comet_address,
+        sort,
+        market_total_usd,
+        matching,
+        positions: ranked,
+        mock_data: false,
+    }
+}
+
+/// Collateral of `position` valued at `market` prices; assets the market does not list are skipped
+fn collateral_holdings(market: &Market, position: &UserPosition) -> Vec<CollateralHolding> {
+    let mut holdings: Vec<CollateralHolding> = position
+        .collateral_balances
+        .iter()
+        .filter(|(_, &balance)| balance > 0.0)
+        .filter_map(|(address, &balance)| {
+            let asset = market.collateral_assets.get(address)?;
+            Some(CollateralHolding {
+                asset_address: *address,
+                symbol: asset.symbol.clone(),
+                balance,
+                value_usd: balance * asset.price,
+            })
+        })
+        .collect();
+    holdings.sort_by(|a, b| b.value_usd.total_cmp(&a.value_usd));
+    holdings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+    #[tokio::test]
+    async fn test_rank_sorts_filters_and_limits() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let weth = *market.collateral_assets.keys().next().unwrap();
+        let position = |byte: u8, base_balance: f64, health_factor: f64| UserPosition {
+            address: Address::repeat_byte(byte),
+            base_balance,
+            collateral_balances: [(weth, 1.0), (Address::repeat_byte(0xee), 5.0)].into_iter().collect(),
+            total_collateral_value: 2000.0,
+            total_borrow_value: (-base_balance).max(0.0),
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        let positions = [
+            position(1, -1000.0, 1.6),
+            position(2, -5000.0, 1.02),
+            position(3, 8000.0, f64::MAX),
+            position(4, -3000.0, 1.2),
+        ];
+
+        let top = rank(&market, &positions, PositionSort::Borrow, 2, HealthFactorFilter::default());
+        let ranked: Vec<u8> = top.positions.iter().map(|p| p.address.0[0]).collect();
+        assert_eq!((ranked, top.matching), (vec![2, 4], 3));
+        assert_eq!(top.positions[0].market_share, 5000.0 / 900_000_000.0);
+        // Collateral the market does not list is left out of the breakdown
+        assert_eq!(top.positions[0].collateral, [CollateralHolding { asset_address: weth, symbol: "WETH".to_string(), balance: 1.0, value_usd: 2000.0 }]);
+
+        let filter = HealthFactorFilter { min_hf: Some(1.1), max_hf: Some(2.0) };
+        let top = rank(&market, &positions, PositionSort::Borrow, 20, filter);
+        assert_eq!(top.positions.iter().map(|p| p.address.0[0]).collect::<Vec<_>>(), [4, 1]);
+
+        let top = rank(&market, &positions, PositionSort::Supply, 20, HealthFactorFilter::default());
+        assert_eq!((top.positions.len(), top.positions[0].supply_value_usd), (1, 8000.0));
+    }
+}
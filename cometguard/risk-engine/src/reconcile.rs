@@ -0,0 +1,204 @@
+//! Startup reconciliation of persisted state against the chain
+//!
+//! After downtime the borrower index, the alert state and the stored snapshots describe the
+//! chain as it was when the engine stopped. Before its first scheduled run,
+//! `RiskEngine::reconcile` measures how far the index is behind the head and what catching up
+//! costs, re-reads the positions of a sample of indexed accounts, lists markets whose newest
+//! stored assessment is older than `reconcile.max_data_age_secs` and resolves the open findings
+//! of the alert state whose data is that old, marking them `stale`. The scheduler starts
+//! alerting only once this is done, so nothing is sent on the strength of week-old data; a stale
+//! finding the first fresh assessment still reports alerts again as new. Reconciliation only
+//! reads the index, which the next scan brings up to date.
+
+use crate::scanner::BorrowerIndex;
+use crate::utils::{format_named_address, sanitize_inline};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// How far the borrower index is behind the chain head
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexLag {
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub comet_address: Address,
+    /// Last block the index has scanned; `None` for an index never scanned
+    pub last_scanned_block: Option<u64>,
+    pub head_block: u64,
+    /// Blocks from the one after `last_scanned_block` (or `scanner.start_block`) to the head
+    pub blocks_behind: u64,
+    /// `eth_getLogs` requests of scanning them at `scanner.chunk_size`
+    pub catch_up_requests: u64,
+    /// Accounts in the index, which stay there while it catches up
+    pub borrowers: usize,
+}
+
+impl IndexLag {
+    /// Lag of `index` behind `head_block` for a scanner starting at `start_block` and reading
+    /// `chunk_size` blocks per request
+    pub fn of(index: &BorrowerIndex, start_block: u64, head_block: u64, chunk_size: u64) -> Self {
+        let next = index.last_scanned_block.map_or(start_block, |block| block + 1);
+        let blocks_behind = (head_block + 1).saturating_sub(next);
+        Self {
+            comet_address: index.comet_address,
+            last_scanned_block: index.last_scanned_block,
+            head_block,
+            blocks_behind,
+            catch_up_requests: blocks_behind.div_ceil(chunk_size.max(1)),
+            borrowers: index.borrowers.len(),
+        }
+    }
+}
+
+/// Outcome of re-reading the positions of a sample of indexed accounts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionCheck {
+    pub sampled: usize,
+    /// Accounts still borrowing
+    pub borrowing: usize,
+    /// Accounts that repaid or were absorbed since they were indexed
+    pub closed: usize,
+    /// Accounts whose position could not be read
+    pub unreadable: usize,
+}
+
+/// Market whose newest stored assessment is older than `reconcile.max_data_age_secs`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleMarket {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub last_assessed: DateTime<Utc>,
+}
+
+/// What `RiskEngine::reconcile` found and changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reconciliation {
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// `None` without a borrower index, or when the head could not be read
+    pub index: Option<IndexLag>,
+    /// `None` without indexed accounts, or when the market could not be read
+    pub positions: Option<PositionCheck>,
+    /// Markets whose stored snapshots are too old to compare a fresh assessment with as if
+    /// nothing happened in between
+    pub stale_markets: Vec<StaleMarket>,
+    /// Fingerprints of the alert state findings resolved as stale
+    pub stale_findings: Vec<String>,
+}
+
+/// `index 50400 block(s) behind (51 request(s) to catch up, 120 borrowers kept), 18/20 sampled
+/// positions still borrowing (2 closed), 1 market(s) last assessed before the cutoff (USDC
+/// (0xc3d6...cdc3) at 2024-05-01 12:00 UTC), 3 stale finding(s) resolved`
+impl fmt::Display for Reconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match &self.index {
+            Some(lag) => parts.push(format!(
+                "index {} block(s) behind ({} request(s) to catch up, {} borrowers kept)",
+                lag.blocks_behind, lag.catch_up_requests, lag.borrowers
+            )),
+            None => parts.push("no borrower index checked".to_string()),
+        }
+        if let Some(check) = &self.positions {
+            let mut line = format!("{}/{} sampled positions still borrowing", check.borrowing, check.sampled);
+            let mut gone = Vec::new();
+            if check.closed > 0 {
+                gone.push(format!("{} closed", check.closed));
+            }
+            if check.unreadable > 0 {
+                gone.push(format!("{} unreadable", check.unreadable));
+            }
+            if !gone.is_empty() {
+                line.push_str(&format!(" ({})", gone.join(", ")));
+            }
+            parts.push(line);
+        }
+        if !self.stale_markets.is_empty() {
+            let markets: Vec<String> = self
+                .stale_markets
+                .iter()
+                .map(|market| {
+                    format!(
+                        "{} at {}",
+                        sanitize_inline(&format_named_address(&market.market_name, &market.market_address)),
+                        market.last_assessed.format("%Y-%m-%d %H:%M UTC")
+                    )
+                })
+                .collect();
+            parts.push(format!("{} market(s) last assessed before the cutoff ({})", markets.len(), markets.join(", ")));
+        }
+        parts.push(format!("{} stale finding(s) resolved", self.stale_findings.len()));
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Up to `size` of `accounts`, spread evenly over the sorted set so repeated starts check the
+/// same accounts
+pub fn sample(accounts: &BTreeSet<Address>, size: usize) -> Vec<Address> {
+    if size == 0 || accounts.is_empty() {
+        return Vec::new();
+    }
+    let len = accounts.len();
+    if size >= len {
+        return accounts.iter().copied().collect();
+    }
+    // The `i * len / size`th accounts: exactly `size` of them, strictly increasing as `size < len`
+    let mut picks = (0..size).map(|i| i * len / size).peekable();
+    accounts.iter().enumerate().filter(|(i, _)| picks.next_if_eq(i).is_some()).map(|(_, account)| *account).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_lag_and_sample() {
+        let comet = Address::repeat_byte(0xc3);
+        let mut index = BorrowerIndex::new(comet);
+        index.borrowers = (1..=45u8).map(Address::repeat_byte).collect();
+
+        // Never scanned: everything from the start block is still to do
+        let lag = IndexLag::of(&index, 1_000, 1_999, 100);
+        assert_eq!((lag.last_scanned_block, lag.blocks_behind, lag.catch_up_requests), (None, 1_000, 10));
+
+        index.last_scanned_block = Some(19_000_000);
+        let lag = IndexLag::of(&index, 1_000, 19_050_400, 1_000);
+        assert_eq!((lag.blocks_behind, lag.catch_up_requests, lag.borrowers), (50_400, 51, 45));
+        assert_eq!(IndexLag::of(&index, 1_000, 19_000_000, 1_000).blocks_behind, 0);
+
+        let sampled = sample(&index.borrowers, 10);
+        assert_eq!(sampled.len(), 10);
+        assert_eq!((sampled[0], sampled[1], sampled[9]), (Address::repeat_byte(1), Address::repeat_byte(5), Address::repeat_byte(41)));
+        assert_eq!(sample(&index.borrowers, 100).len(), 45);
+        assert!(sample(&index.borrowers, 0).is_empty());
+
+        // Lengths that do not divide evenly still give the configured size
+        for (len, size) in [(7u8, 3), (11, 4), (45, 44)] {
+            let accounts: BTreeSet<Address> = (1..=len).map(Address::repeat_byte).collect();
+            let sampled = sample(&accounts, size);
+            assert_eq!(sampled.len(), size, "{} of {}", size, len);
+            assert!(sampled.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", sampled);
+        }
+
+        let reconciliation = Reconciliation {
+            finished_at: Utc::now(),
+            duration_ms: 12,
+            index: Some(lag),
+            positions: Some(PositionCheck { sampled: 20, borrowing: 18, closed: 2, unreadable: 0 }),
+            stale_markets: vec![StaleMarket {
+                market_name: "USDC".to_string(),
+                market_address: comet,
+                last_assessed: "2024-05-01T12:00:00Z".parse().unwrap(),
+            }],
+            stale_findings: vec!["3f2a9c0d8e7b6a51".to_string()],
+        };
+        assert_eq!(
+            reconciliation.to_string(),
+            "index 50400 block(s) behind (51 request(s) to catch up, 45 borrowers kept), 18/20 sampled positions still \
+             borrowing (2 closed), 1 market(s) last assessed before the cutoff (USDC (0xc3c3...c3c3) at 2024-05-01 12:00 \
+             UTC), 1 stale finding(s) resolved"
+        );
+    }
+}
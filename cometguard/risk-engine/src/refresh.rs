@@ -0,0 +1,98 @@
+use crate::provider::SharedProvider;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock as StdRwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Upper bound on the delay between refresh attempts while the RPC is failing
+pub const MAX_REFRESH_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Data provider in use, shared with the background refresh so it follows replacements
+pub type ProviderSlot = Arc<StdRwLock<SharedProvider>>;
+
+/// Shared timestamp of the last successful refresh
+pub type LastRefresh = Arc<StdRwLock<Option<DateTime<Utc>>>>;
+
+/// Handle to a running background refresh task
+pub struct BackgroundRefresh {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl BackgroundRefresh {
+    /// Spawn a task that re-fetches markets into the client cache every `interval`
+    pub fn spawn(
+        provider: ProviderSlot,
+        interval: Duration,
+        last_refresh: LastRefresh,
+    ) -> Self {
+        let (stop, mut stop_rx) = watch::channel(false);
+
+        let handle = tokio::spawn(async move {
+            let mut failures = 0u32;
+            loop {
+                let current = provider.read().unwrap_or_else(|e| e.into_inner()).clone();
+                let result = current.refresh_markets().await;
+
+                let delay = match result {
+                    Ok(markets) => {
+                        failures = 0;
+                        *last_refresh.write().unwrap_or_else(|e| e.into_inner()) = Some(Utc::now());
+                        debug!("Background refresh cached {} market(s)", markets.len());
+                        interval
+                    }
+                    Err(e) => {
+                        failures = failures.saturating_add(1);
+                        let delay = backoff_delay(interval, failures);
+                        warn!("Background refresh failed ({} in a row), retrying in {:?}: {:#}", failures, delay, e);
+                        delay
+                    }
+                };
+
+                tokio::select! {
+                    _ = stop_rx.changed() => break,
+                    _ = tokio::time::sleep(delay) => {}
+                }
+            }
+        });
+
+        Self { stop, handle }
+    }
+
+    /// Signal the task to stop and wait for it to exit
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(e) = self.handle.await {
+            warn!("Background refresh task ended abnormally: {}", e);
+        }
+    }
+}
+
+/// Delay before the next attempt after `failures` consecutive failures (doubling, capped)
+pub fn backoff_delay(interval: Duration, failures: u32) -> Duration {
+    if failures == 0 {
+        return interval;
+    }
+    let factor = 1u32 << failures.min(10);
+    interval.saturating_mul(factor).min(MAX_REFRESH_BACKOFF.max(interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        let interval = Duration::from_secs(30);
+        assert_eq!(backoff_delay(interval, 0), interval);
+        assert_eq!(backoff_delay(interval, 1), Duration::from_secs(60));
+        assert_eq!(backoff_delay(interval, 3), Duration::from_secs(240));
+        assert_eq!(backoff_delay(interval, 30), MAX_REFRESH_BACKOFF);
+
+        // An interval longer than the cap is never shortened
+        let slow = Duration::from_secs(3600);
+        assert_eq!(backoff_delay(slow, 4), slow);
+    }
+}
@@ -0,0 +1,174 @@
+//! Compound V3 deployments known to the engine
+//!
+//! A compiled-in table of the Comets Compound governance deployed on each `Network`, with the
+//! Configurator and CometRewards contracts they share on their chain. It fills in
+//! `Network::compound_config` for `config init`, resolves the `network:SYMBOL` shorthands of the
+//! `markets` setting, lists the known deployments for `list-markets --discover` and labels them
+//! in the built-in address book. Deployments missing here can still be configured by address.
+
+use crate::config::{CompoundConfig, Network, MAINNET_COMP_USD_FEED};
+use crate::error::{Result, RiskEngineError};
+
+/// A Comet deployment listed in the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownMarket {
+    pub network: Network,
+    /// Compound's name for the deployment, e.g. "cUSDCv3"
+    pub name: &'static str,
+    pub base_symbol: &'static str,
+    /// Comet proxy address
+    pub comet: &'static str,
+    pub configurator: &'static str,
+    /// CometRewards contract paying the Comet's COMP rewards
+    pub rewards: &'static str,
+}
+
+impl KnownMarket {
+    /// Shorthand naming the deployment in `markets`, e.g. "base:USDbC"
+    pub fn shorthand(&self) -> String {
+        format!("{}:{}", self.network.name(), self.base_symbol)
+    }
+
+    /// Compound settings of the deployment, read through `rpc_url`
+    ///
+    /// The COMP price feed is only bundled for mainnet; set `compound.comp_price_feed` in the
+    /// file to read reward rates elsewhere.
+    pub fn compound_config(&self, rpc_url: String) -> CompoundConfig {
+        let mainnet = self.network == Network::Mainnet;
+        CompoundConfig {
+            rpc_url,
+            comet_proxy_address: self.comet.to_string(),
+            configurator_address: self.configurator.to_string(),
+            chain_id: self.network.chain_id(),
+            rewards_address: Some(self.rewards.to_string()),
+            comp_price_feed: mainnet.then(|| MAINNET_COMP_USD_FEED.to_string()),
+            eth_usd_feed: self.network.eth_usd_feed().map(str::to_string),
+            sequencer_uptime_feed: self.network.sequencer_uptime_feed().map(str::to_string),
+        }
+    }
+}
+
+/// Every known deployment, grouped by network in `Network::ALL` order, USDC first
+static KNOWN_MARKETS: &[KnownMarket] = &[
+    // Ethereum mainnet
+    market(Network::Mainnet, "cUSDCv3", "USDC", "0xc3d688B66703497DAA19211EEdff47f25384cdc3"),
+    market(Network::Mainnet, "cWETHv3", "WETH", "0xA17581A9E3356d9A858b789D68B4d866e593aE94"),
+    market(Network::Mainnet, "cUSDTv3", "USDT", "0x3Afdc9BCA9213A35503b077a6072F3D0d5AB0840"),
+    market(Network::Mainnet, "cwstETHv3", "wstETH", "0x3D0bb1ccaB520A66e607822fC55BC921738fAFE3"),
+    // Base
+    market(Network::Base, "cUSDCv3", "USDC", "0xb125E6687d4313864e53df431d5425969c15Eb2F"),
+    market(Network::Base, "cUSDbCv3", "USDbC", "0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf"),
+    market(Network::Base, "cWETHv3", "WETH", "0x46e6b214b524310239732D51387075E0e70970bf"),
+    // Arbitrum One
+    market(Network::Arbitrum, "cUSDCv3", "USDC", "0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf"),
+    market(Network::Arbitrum, "cUSDC.ev3", "USDC.e", "0xA5EDBDD9646f8dFF606d7448e414884C7d905dCA"),
+    market(Network::Arbitrum, "cWETHv3", "WETH", "0x6f7D514bbD4aFf3BcD1140B7344b32f063dEe486"),
+    market(Network::Arbitrum, "cUSDTv3", "USDT", "0xd98Be00b5D27fc98112BdE293e487f8D4cA57d07"),
+    // Polygon PoS
+    market(Network::Polygon, "cUSDCv3", "USDC", "0xF25212E676D1F7F89Cd72fFEe66158f541246445"),
+    market(Network::Polygon, "cUSDTv3", "USDT", "0xaeB318360f27748Acb200CE616E389A6C9409a07"),
+    // OP Mainnet
+    market(Network::Optimism, "cUSDCv3", "USDC", "0x2e44e174f7D53F0212823acC11C01A11d58c5bCB"),
+    market(Network::Optimism, "cUSDTv3", "USDT", "0x995E394b8B2437aC8Ce61Ee0bC610D617962B214"),
+    market(Network::Optimism, "cWETHv3", "WETH", "0xE36A30D249f7761327fd973001A32010b521b6Fd"),
+    // Scroll
+    market(Network::Scroll, "cUSDCv3", "USDC", "0xB2f97c1Bd3bf02f5e74d13f02E3e26F93D77CE44"),
+];
+
+/// Configurator and CometRewards contract shared by the Comets of `network`
+const fn network_contracts(network: Network) -> (&'static str, &'static str) {
+    match network {
+        Network::Mainnet => ("0x316f9708bB98af7dA9c68C1C3b5e79039cD336E3", "0x1B0e765F6224C21223AeA2af16c1C46E38885a40"),
+        Network::Base => ("0x45939657d1CA34A8FA39A924B71D28Fe8431e581", "0x123964802e6ABabBE1Bc9547D72Ef1B69B00A6b1"),
+        Network::Arbitrum => ("0xb21b06D71c75973babdE35b49fFDAc3F82Ad3775", "0x88730d254A2f7e6AC8388c3198aFd694bA9f7fae"),
+        Network::Polygon => ("0x83E0F742cAcBE66349E3701B171eE2487a26e738", "0x45939657d1CA34A8FA39A924B71D28Fe8431e581"),
+        Network::Optimism => ("0x84E93EC6170ED630f5ebD89A1AAE72d4F63f2713", "0x443EA0340cb75a160F31A440722dec7b5bc3C2E9"),
+        Network::Scroll => ("0xECAB0bEEa3e5DEa0c35d3E69468EAC20098032D7", "0x70167D30964cbFDc315ECAe02441Af747bE0c5Ee"),
+    }
+}
+
+const fn market(network: Network, name: &'static str, base_symbol: &'static str, comet: &'static str) -> KnownMarket {
+    let (configurator, rewards) = network_contracts(network);
+    KnownMarket { network, name, base_symbol, comet, configurator, rewards }
+}
+
+/// Every deployment in the registry
+pub fn all() -> &'static [KnownMarket] {
+    KNOWN_MARKETS
+}
+
+/// Deployments known on the chain `chain_id`, USDC first; none on chains that are not a `Network`
+pub fn known_markets(chain_id: u64) -> impl Iterator<Item = &'static KnownMarket> {
+    KNOWN_MARKETS.iter().filter(move |market| market.network.chain_id() == chain_id)
+}
+
+/// Deployment named by a `network:SYMBOL` shorthand such as "mainnet:USDC"; the network and
+/// the base symbol are matched ignoring case
+pub fn resolve(shorthand: &str) -> Result<&'static KnownMarket> {
+    let invalid = |message: String| RiskEngineError::Parse {
+        what: "market",
+        input: shorthand.to_string(),
+        message,
+    };
+    let (network, symbol) = shorthand
+        .split_once(':')
+        .ok_or_else(|| invalid("expected `network:SYMBOL`, e.g. `mainnet:USDC`".to_string()))?;
+    let network = Network::ALL
+        .into_iter()
+        .find(|n| n.name().eq_ignore_ascii_case(network.trim()))
+        .ok_or_else(|| {
+            let names: Vec<&str> = Network::ALL.iter().map(|n| n.name()).collect();
+            invalid(format!("unknown network `{}` (known: {})", network, names.join(", ")))
+        })?;
+    let mut markets = known_markets(network.chain_id());
+    markets.find(|market| market.base_symbol.eq_ignore_ascii_case(symbol.trim())).ok_or_else(|| {
+        let symbols: Vec<&str> = known_markets(network.chain_id()).map(|market| market.base_symbol).collect();
+        invalid(format!("no known market on {} (known: {})", network.name(), symbols.join(", ")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::parse_address;
+    use ethers::utils::to_checksum;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry_addresses_are_checksummed() {
+        for market in all() {
+            for (what, address) in [("comet", market.comet), ("configurator", market.configurator), ("rewards", market.rewards)] {
+                let parsed = parse_address(address).unwrap_or_else(|e| panic!("{} {}: {}", market.shorthand(), what, e));
+                assert_eq!(to_checksum(&parsed, None), address, "{} {} is not checksummed", market.shorthand(), what);
+            }
+        }
+
+        // Each network lists its USDC market first, and a symbol once
+        for network in Network::ALL {
+            let markets: Vec<&KnownMarket> = known_markets(network.chain_id()).collect();
+            assert_eq!(markets.first().map(|m| m.base_symbol), Some("USDC"), "{:?}", network);
+            let symbols: HashSet<&str> = markets.iter().map(|m| m.base_symbol).collect();
+            assert_eq!(symbols.len(), markets.len(), "{:?}", network);
+        }
+    }
+
+    #[test]
+    fn test_shorthands_resolve_through_the_registry() {
+        let usdbc = resolve("base:USDbC").unwrap();
+        assert_eq!((usdbc.network, usdbc.comet), (Network::Base, "0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf"));
+        assert_eq!(resolve("Mainnet:usdc").unwrap().name, "cUSDCv3");
+        assert_eq!(usdbc.shorthand(), "base:USDbC");
+        assert_eq!(known_markets(8453).count(), 3);
+        assert_eq!(known_markets(5).count(), 0);
+
+        let errors = [
+            ("USDC", "expected `network:SYMBOL`"),
+            ("goerli:USDC", "unknown network `goerli` (known: mainnet, base, arbitrum, polygon, optimism, scroll)"),
+            ("scroll:WETH", "no known market on scroll (known: USDC)"),
+        ];
+        for (shorthand, message) in errors {
+            let error = resolve(shorthand).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", shorthand, error);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Configuration hot reload for long-running commands
+//!
+//! The watcher follows the config file's directory rather than the file itself, since
+//! editors commonly save by writing a new file and renaming it over the old one.
+
+use crate::config::Config;
+use crate::error::{Result, RiskEngineError};
+use crate::RiskEngine;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Weak;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Quiet period after a change before the file is read, so one save is one reload
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Handle to a running configuration watcher
+pub struct ConfigWatcher {
+    // File notifications stop when this is dropped
+    _watcher: RecommendedWatcher,
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl ConfigWatcher {
+    /// Watch `path` and pass the configuration returned by `load` to `RiskEngine::reload_config`
+    /// after every change, until `shutdown` or until the engine is dropped
+    pub fn spawn<F>(engine: Weak<RiskEngine>, path: &Path, load: F) -> Result<Self>
+    where
+        F: Fn() -> Result<Config> + Send + 'static,
+    {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| RiskEngineError::config("reload", format!("{} does not name a file", path.display())))?
+            .to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (changes, mut changed) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) if !event.kind.is_access() && event.paths.iter().any(|p| p.file_name() == Some(&file_name)) => {
+                let _ = changes.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Configuration watcher error: {}", e),
+        })
+        .map_err(|e| unavailable(path, e))?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(|e| unavailable(path, e))?;
+
+        let (stop, mut stop_rx) = watch::channel(false);
+        let path = path.to_path_buf();
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = stop_rx.changed() => break,
+                    received = changed.recv() => if received.is_none() { break },
+                }
+                tokio::time::sleep(RELOAD_DEBOUNCE).await;
+                while changed.try_recv().is_ok() {}
+
+                let Some(engine) = engine.upgrade() else { break };
+                let result = match load() {
+                    Ok(config) => engine.reload_config(config).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("Ignoring the changed configuration in {}, keeping the current one: {}", path.display(), e);
+                }
+            }
+        });
+
+        Ok(Self { _watcher: watcher, stop, handle })
+    }
+
+    /// Signal the task to stop and wait for it to exit
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(e) = self.handle.await {
+            warn!("Configuration watcher ended abnormally: {}", e);
+        }
+    }
+}
+
+fn unavailable(path: &Path, e: notify::Error) -> RiskEngineError {
+    RiskEngineError::Unavailable {
+        what: "configuration watcher",
+        reason: format!("cannot watch {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::{FixtureProvider, RiskEngine, RiskEvent};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_watcher_reloads_valid_changes_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut config = Config::default();
+        config.to_file(&path).unwrap();
+        let engine = Arc::new(RiskEngine::with_provider(config.clone(), Arc::new(FixtureProvider::demo())));
+        let mut events = engine.subscribe();
+
+        let load = {
+            let path = path.clone();
+            move || Config::from_file(&path)
+        };
+        engine.start_config_watcher(&path, load.clone()).unwrap();
+        assert!(engine.start_config_watcher(&path, load).is_err());
+
+        // Out of range: rejected, the old threshold stays in effect
+        config.risk.max_utilization_threshold = 1.5;
+        config.to_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(engine.config().risk.max_utilization_threshold, 0.85);
+
+        config.risk.max_utilization_threshold = 0.95;
+        config.to_file(&path).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(10), events.recv()).await.unwrap().unwrap();
+        assert!(matches!(&event, RiskEvent::ConfigReloaded { changed } if changed == &["risk"]), "{:?}", event);
+        assert_eq!(engine.config().risk.max_utilization_threshold, 0.95);
+        engine.shutdown().await;
+    }
+}
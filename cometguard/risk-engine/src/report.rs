@@ -0,0 +1,180 @@
+//! Plain-text rendering of assessments, shared by the CLI and email digests
+//!
+//! Strings that come from chain (market names, asset symbols inside descriptions) are
+//! passed through `utils::sanitize_inline`, so they cannot break out of their line. Totals are
+//! in the `display.denomination` unit when every market shares it, and in USD otherwise.
+
+use crate::denomination::{Unit, Units};
+use crate::risk::{RiskAssessment, RiskSeverity};
+use crate::summary::{ProtocolRiskSummary, RiskTotals};
+use crate::utils::{format_named_address, sanitize_inline, NumberStyle};
+use std::fmt::Write;
+
+/// Suffix marking a line of output as derived from mock data
+pub fn mock_tag(mock_data: bool) -> &'static str {
+    if mock_data {
+        " [MOCK DATA]"
+    } else {
+        ""
+    }
+}
+
+/// Report section for one market: header, risk score and numbered findings with the points
+/// each added to the score, and the latest operator note under those that have one
+pub fn market_section(assessment: &RiskAssessment) -> String {
+    let mut section = String::new();
+    writeln!(
+        section,
+        "Market: {}{}",
+        sanitize_inline(&format_named_address(&assessment.market_name, &assessment.market_address)),
+        mock_tag(assessment.mock_data)
+    )
+    .unwrap();
+    writeln!(section, "Risk Score: {}", assessment.score_label()).unwrap();
+    if let Some(partial) = assessment.rpc.as_ref().and_then(|rpc| rpc.degradation()) {
+        writeln!(section, "Partial within the RPC budget: {}", partial).unwrap();
+    }
+    if let Some(timed_out) = assessment.timed_out() {
+        writeln!(section, "Partial, timed out: {}", timed_out).unwrap();
+    }
+    if let Some(degraded) = assessment.data_degradation() {
+        writeln!(section, "Degraded data, findings at low confidence: {}", degraded).unwrap();
+    }
+    if let Some(baseline) = assessment.baseline.as_ref().and_then(|baseline| baseline.summary()) {
+        writeln!(section, "Against its history: {}", baseline).unwrap();
+    }
+    if !assessment.category_scores.is_empty() {
+        writeln!(section, "Sub-scores:").unwrap();
+        for (category, score) in &assessment.category_scores {
+            let score = match score {
+                Some(score) => {
+                    let arrow = assessment.trend.as_ref().and_then(|trend| trend.categories.get(category)).map(|trend| trend.direction.arrow());
+                    format!("{:>3}/100{}", score, arrow.map(|arrow| format!(" {}", arrow)).unwrap_or_default())
+                }
+                None => "not evaluated".to_string(),
+            };
+            writeln!(section, "  {:<20} {}", format!("{:?}", category), score).unwrap();
+        }
+    }
+
+    if assessment.findings.is_empty() {
+        writeln!(section, "✅ No risks identified").unwrap();
+    } else {
+        writeln!(section, "\nRisks Identified:").unwrap();
+        for (i, finding) in assessment.findings.iter().enumerate() {
+            writeln!(
+                section,
+                "{}. {} (+{})",
+                i + 1,
+                finding,
+                finding.score_contribution
+            )
+            .unwrap();
+            if let Some(annotation) = assessment.annotations.get(&finding.fingerprint) {
+                writeln!(section, "   Note: {}", annotation).unwrap();
+            }
+        }
+    }
+    section
+}
+
+/// Report section for the rollup of all markets: totals, findings by severity and one line
+/// per chain, amounts in the unit all markets of `units` share
+pub fn summary_section(summary: &ProtocolRiskSummary, units: &Units) -> String {
+    let totals = &summary.totals;
+    let unit = units.common(None);
+    let mut section = String::new();
+    writeln!(
+        section,
+        "Protocol: {} market(s), Risk Score: {:.1}/100 ({}-weighted)",
+        totals.markets,
+        totals.risk_score,
+        summary.weighting.label()
+    )
+    .unwrap();
+    if let Some(worst) = &totals.worst_market {
+        writeln!(
+            section,
+            "Worst Market: {} {}/100",
+            sanitize_inline(&format_named_address(&worst.market_name, &worst.market_address)),
+            worst.risk_score
+        )
+        .unwrap();
+    }
+    writeln!(section, "{}", exposure_line(totals, &unit)).unwrap();
+    let findings: Vec<String> = RiskSeverity::ALL
+        .iter()
+        .rev()
+        .map(|severity| format!("{} {:?}", totals.findings.get(severity).copied().unwrap_or(0), severity))
+        .collect();
+    writeln!(section, "Findings: {}", findings.join(", ")).unwrap();
+    for chain in &summary.chains {
+        let chain_id = chain.chain_id.map_or_else(|| "unknown".to_string(), |id| id.to_string());
+        writeln!(
+            section,
+            "Chain {}: {} market(s), Risk Score: {:.1}/100, {}",
+            chain_id,
+            chain.totals.markets,
+            chain.totals.risk_score,
+            exposure_line(&chain.totals, &unit)
+        )
+        .unwrap();
+    }
+    section
+}
+
+fn exposure_line(totals: &RiskTotals, unit: &Unit) -> String {
+    let amount = |usd: f64| unit.format(usd, NumberStyle::Us);
+    format!(
+        "TVL {}, Borrow {}, Bad Debt {}, Near Liquidation {}",
+        amount(totals.tvl_usd),
+        amount(totals.borrow_usd),
+        amount(totals.bad_debt_usd),
+        amount(totals.near_liquidation_usd)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::Annotation;
+    use crate::risk::{RiskCategory, RiskFinding, RiskSeverity};
+    use crate::testing::finding;
+    use ethers::types::Address;
+
+    #[test]
+    fn test_market_section_escapes_chain_strings() {
+        let assessment = RiskAssessment {
+            findings: vec![RiskFinding {
+                category: RiskCategory::PriceVolatility,
+                description: "EVIL\n\nClick here volatility is 40%".to_string(),
+                score_contribution: 30,
+                ..finding("3f2a9c0d8e7b6a51", RiskSeverity::High)
+            }],
+            risk_score: 40,
+            mock_data: true,
+            annotations: [(
+                "3f2a9c0d8e7b6a51".to_string(),
+                Annotation {
+                    fingerprint: "3f2a9c0d8e7b6a51".to_string(),
+                    note: "feed migrated\r\nBcc: attacker@example.com".to_string(),
+                    author: "alice".to_string(),
+                    created_at: "2024-05-01T12:00:00Z".parse().unwrap(),
+                },
+            )]
+            .into(),
+            ..RiskAssessment::new("USDC\r\nBcc: attacker@example.com", Address::repeat_byte(0xc3), Vec::new())
+        };
+
+        let section = market_section(&assessment);
+        assert_eq!(
+            section,
+            "Market: USDC  Bcc: attacker@example.com (0xc3c3...c3c3) [MOCK DATA]\n\
+             Risk Score: 40/100\n\
+             \n\
+             Risks Identified:\n\
+             1. [High] EVIL  Click here volatility is 40% (+30)\n   \
+             Note: feed migrated  Bcc: attacker@example.com (alice, 2024-05-01 12:00 UTC)\n"
+        );
+    }
+}
@@ -1,14 +1,44 @@
+use crate::annotations::Annotation;
+use crate::archive::{self, ContentKind};
 use crate::config::Config;
-use crate::models::{Market, UserPosition};
-use anyhow::Result;
+use crate::gas::{GasEstimate, GasQuote};
+use crate::models::{DataIssue, DataIssueKind, Market, PriceHistory, Provenance, UserPosition};
+use crate::absorption;
+use crate::projection;
+use crate::protocol_collateral::ProtocolHolding;
+use crate::sampling::{self, PositionSample};
+use crate::schedule::Cadence;
+use crate::budget::RpcReport;
+use crate::collateral_changes::{CollateralChange, CollateralParameters};
+use crate::health_factors::HealthFactorDistribution;
+use crate::position_changes::{PositionDelta, PositionHistory};
+use crate::summary::MarketExposure;
+use crate::trend::{Direction, ScoreTrend};
+use crate::baseline::BaselineComparison;
+use crate::provider::SharedProvider;
+use crate::scanner::{BaseFlow, FlowDirection};
+use crate::error::RiskEngineError;
+use crate::error::Result;
+use crate::report::mock_tag;
+use crate::utils::{format_address_labeled, format_named_address, sanitize_inline};
+use crate::rpc;
+use crate::version;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tracing::info;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, info, instrument, warn};
 use chrono::{DateTime, Utc};
-use ethers::types::Address;
+use ethers::types::{Address, H256};
+use sha2::{Digest, Sha256};
 
-/// Risk severity level
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// Risk severity level (ordered from least to most severe)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum RiskSeverity {
     /// No significant risk identified
     Low,
@@ -20,8 +50,23 @@ pub enum RiskSeverity {
     Critical,
 }
 
+impl RiskSeverity {
+    /// Every severity, from least to most severe
+    pub const ALL: [RiskSeverity; 4] = [Self::Low, Self::Medium, Self::High, Self::Critical];
+
+    /// Points a finding of this severity adds to its market's risk score
+    pub fn score_weight(self) -> u8 {
+        match self {
+            Self::Low => 5,
+            Self::Medium => 15,
+            Self::High => 30,
+            Self::Critical => 50,
+        }
+    }
+}
+
 /// Risk category
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum RiskCategory {
     /// Market utilization is too high
     HighUtilization,
@@ -35,8 +80,85 @@ pub enum RiskCategory {
     OracleReliability,
     /// Smart contract vulnerability or issue
     SmartContractRisk,
+    /// Settings that do not fit the assessed deployment
+    Configuration,
+    /// Rewards contract funded for too few days of the market's COMP emissions
+    IncentiveRunway,
+    /// Borrow in positions too small to be worth liquidating, which can turn into bad debt
+    BadDebt,
+    /// Watchlist account that allows a manager outside `trusted_managers` to act for it
+    AccountPermissions,
+    /// Risk score smoothed over stored history rising into a higher band
+    RiskTrend,
+    /// Market data that is inconsistent, such as totals read at mismatched blocks
+    DataQuality,
+}
+
+impl RiskCategory {
+    /// Every category
+    pub const ALL: [RiskCategory; 12] = [
+        Self::HighUtilization,
+        Self::PriceVolatility,
+        Self::Concentration,
+        Self::LiquidationCascade,
+        Self::OracleReliability,
+        Self::SmartContractRisk,
+        Self::Configuration,
+        Self::IncentiveRunway,
+        Self::BadDebt,
+        Self::AccountPermissions,
+        Self::RiskTrend,
+        Self::DataQuality,
+    ];
+
+    /// Whether findings in the category add to the risk score, and so whether it has a
+    /// sub-score; `RiskTrend` and `DataQuality` findings add nothing
+    pub fn is_scored(&self) -> bool {
+        !matches!(self, Self::RiskTrend | Self::DataQuality)
+    }
+
+    /// Name of the category in finding fingerprints; fixed, so that renaming a variant does not
+    /// change fingerprints persisted in the alert state or sent as PagerDuty dedup keys
+    pub fn fingerprint_key(&self) -> &'static str {
+        match self {
+            Self::HighUtilization => "high_utilization",
+            Self::PriceVolatility => "price_volatility",
+            Self::Concentration => "concentration",
+            Self::LiquidationCascade => "liquidation_cascade",
+            Self::OracleReliability => "oracle_reliability",
+            Self::SmartContractRisk => "smart_contract_risk",
+            Self::Configuration => "configuration",
+            Self::IncentiveRunway => "incentive_runway",
+            Self::BadDebt => "bad_debt",
+            Self::AccountPermissions => "account_permissions",
+            Self::RiskTrend => "risk_trend",
+            Self::DataQuality => "data_quality",
+        }
+    }
 }
 
+/// Categories each built-in check reports findings in, by its name in `version::CHECK_VERSIONS`;
+/// a category's sub-score is evaluated once one of its checks has run on data
+const CHECK_CATEGORIES: &[(&str, &[RiskCategory])] = &[
+    ("sequencer", &[RiskCategory::OracleReliability]),
+    ("utilization", &[RiskCategory::HighUtilization]),
+    ("planned_withdrawals", &[RiskCategory::HighUtilization]),
+    ("outflows", &[RiskCategory::HighUtilization]),
+    ("price_volatility", &[RiskCategory::PriceVolatility]),
+    ("watchlist", &[RiskCategory::LiquidationCascade]),
+    ("asset_settings", &[RiskCategory::Configuration]),
+    ("rewards_runway", &[RiskCategory::IncentiveRunway]),
+    ("absorption", &[RiskCategory::BadDebt]),
+    ("liquidator_margin", &[RiskCategory::LiquidationCascade]),
+    ("protocol_collateral", &[RiskCategory::BadDebt]),
+    ("collateral_provenance", &[RiskCategory::SmartContractRisk]),
+    ("configuration_drift", &[RiskCategory::SmartContractRisk]),
+    ("manager_permissions", &[RiskCategory::AccountPermissions]),
+    ("position_changes", &[RiskCategory::LiquidationCascade, RiskCategory::AccountPermissions]),
+    ("borrower_concentration", &[RiskCategory::Concentration]),
+    ("collateral_changes", &[RiskCategory::LiquidationCascade]),
+];
+
 /// Individual risk finding
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RiskFinding {
@@ -50,6 +172,203 @@ pub struct RiskFinding {
     pub metadata: serde_json::Value,
     /// Timestamp when the risk was identified
     pub timestamp: DateTime<Utc>,
+    /// Stable identifier of the underlying condition, used to match findings across runs
+    #[serde(default)]
+    pub fingerprint: String,
+    /// Points the finding added to its assessment's `risk_score`, set by `RiskScore` once
+    /// every check has run; findings streamed before then carry 0
+    #[serde(default)]
+    pub score_contribution: u8,
+}
+
+/// `[High] Utilization at 95%`, on one line
+impl fmt::Display for RiskFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:?}] {}", self.severity, sanitize_inline(&self.description))
+    }
+}
+
+/// Risk score of a set of findings and the points each finding added to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiskScore {
+    /// Score out of 100, higher is riskier
+    pub total: u8,
+    /// Points of each finding, in the order the findings were given
+    pub contributions: Vec<u8>,
+}
+
+impl RiskScore {
+    /// Score of `findings`: each adds its severity's `score_weight`, up to 100 in all
+    ///
+    /// The cap is the only diminishing return. Points are credited to the most severe findings
+    /// first, in their order within a severity, so a finding past the cap adds what is left of
+    /// it or nothing and the contributions sum to the total exactly, without rounding.
+    /// `RiskTrend` findings add nothing, since the trend is computed from the score, and
+    /// neither do `DataQuality` findings, which are about the data rather than the market.
+    pub fn of(findings: &[RiskFinding]) -> Self {
+        let mut order: Vec<usize> = (0..findings.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(findings[i].severity));
+        let mut contributions = vec![0; findings.len()];
+        let mut left: u8 = 100;
+        for i in order {
+            if !findings[i].category.is_scored() {
+                continue;
+            }
+            let points = findings[i].severity.score_weight().min(left);
+            contributions[i] = points;
+            left -= points;
+        }
+        Self { total: 100 - left, contributions }
+    }
+
+    /// Record each finding's contribution on it; `findings` must be the ones scored
+    pub fn attribute(&self, findings: &mut [RiskFinding]) {
+        for (finding, &points) in findings.iter_mut().zip(&self.contributions) {
+            finding.score_contribution = points;
+        }
+    }
+}
+
+/// How an assessment's findings are scored; `RiskScore::of` unless replaced with
+/// `RiskEngineBuilder::scorer`
+///
+/// Any `Fn(&[RiskFinding]) -> RiskScore` is a scorer. `contributions` must have one entry per
+/// finding, in the order given.
+pub trait RiskScorer: Send + Sync {
+    fn score(&self, findings: &[RiskFinding]) -> RiskScore;
+}
+
+impl<F> RiskScorer for F
+where
+    F: Fn(&[RiskFinding]) -> RiskScore + Send + Sync,
+{
+    fn score(&self, findings: &[RiskFinding]) -> RiskScore {
+        self(findings)
+    }
+}
+
+/// Check run on every market after the built-in ones, added with `RiskEngineBuilder::add_check`
+///
+/// Its findings are scored and alerted on like any other; give them a `finding_fingerprint`
+/// so they can be followed across runs. A failing check is logged and skipped.
+#[async_trait]
+pub trait RiskCheck: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> String;
+
+    /// Version stamped into assessments under `name`; bump it when what the check's findings
+    /// mean or how they score changes
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Categories the check reports findings in, whose sub-scores it evaluates by running;
+    /// categories it found something in count either way
+    fn categories(&self) -> Vec<RiskCategory> {
+        Vec::new()
+    }
+
+    /// Findings about `market` at `timestamp`; `provider` is the engine's data provider, if it
+    /// has one, for reading further data
+    async fn check(&self, market: &Market, provider: Option<&SharedProvider>, timestamp: DateTime<Utc>) -> Result<Vec<RiskFinding>>;
+}
+
+/// Stable fingerprint for a finding about `subject` (a market, asset or account) in a market
+///
+/// Severity and description are deliberately excluded so that the same condition keeps
+/// its fingerprint while its numbers move. The market's 20 bytes, the category's
+/// `fingerprint_key` and the subject are hashed with `\0` between them, so no two
+/// (category, subject) pairs hash the same input.
+pub fn finding_fingerprint(market: &Address, category: &RiskCategory, subject: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(market.as_bytes());
+    hasher.update(category.fingerprint_key().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(subject.as_bytes());
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// Base flows of one transaction, netted
+#[derive(Debug, Clone)]
+struct TransactionOutflow {
+    tx_hash: H256,
+    timestamp: DateTime<Utc>,
+    /// Withdrawn less supplied, in USD; negative for a net supply
+    outflow_usd: f64,
+    /// Accounts that withdrew
+    accounts: Vec<Address>,
+}
+
+/// Severity of an outflow `multiple` times its threshold from a market left at `utilization`
+///
+/// Low below three quarters of `max_utilization`, where the market can absorb it, Medium
+/// below it and High from it; from twice the threshold, a Medium or High outflow is one level
+/// more severe.
+fn outflow_severity(multiple: f64, utilization: f64, max_utilization: f64) -> RiskSeverity {
+    let severity = if utilization >= max_utilization {
+        RiskSeverity::High
+    } else if utilization >= max_utilization * 0.75 {
+        RiskSeverity::Medium
+    } else {
+        return RiskSeverity::Low;
+    };
+    match (severity, multiple >= 2.0) {
+        (RiskSeverity::High, true) => RiskSeverity::Critical,
+        (RiskSeverity::Medium, true) => RiskSeverity::High,
+        (severity, _) => severity,
+    }
+}
+
+/// Tracked positions listed in the metadata of a collateral change finding
+const LARGEST_AFFECTED_POSITIONS: usize = 5;
+
+/// Severity of a collateral change affecting `share` of a market's collateral value
+///
+/// Critical from a quarter, High from a tenth, Medium from 2% and Low below.
+fn collateral_change_severity(share: f64) -> RiskSeverity {
+    if share >= 0.25 {
+        RiskSeverity::Critical
+    } else if share >= 0.10 {
+        RiskSeverity::High
+    } else if share >= 0.02 {
+        RiskSeverity::Medium
+    } else {
+        RiskSeverity::Low
+    }
+}
+
+/// Lower the confidence of a finding computed from degraded data; `DataQuality` findings are
+/// about the data itself and stay as they are
+fn mark_degraded(finding: &mut RiskFinding) {
+    if finding.category != RiskCategory::DataQuality {
+        finding.metadata["data_degraded"] = serde_json::json!(true);
+        finding.metadata["confidence"] = serde_json::json!("low");
+    }
+}
+
+/// `PartialBatch` issue of the reads of `check`
+fn partial_batch(check: &str, message: String) -> DataIssue {
+    DataIssue::new(DataIssueKind::PartialBatch, check, message)
+}
+
+/// History of `market`'s base asset to measure collateral prices against; `None` where the
+/// base asset is a USD stablecoin, so that USD prices already are prices in it
+///
+/// Borrowers and liquidators of a market lending a volatile asset such as WETH only bear
+/// collateral moves against it: wstETH falling with ETH moves no health factor.
+async fn base_price_history(provider: &SharedProvider, market: &Market) -> Result<Option<PriceHistory>> {
+    if market.base_asset.is_usd_stablecoin() {
+        return Ok(None);
+    }
+    provider.get_price_history(market, market.base_asset.address).await.map(Some)
+}
+
+/// `history` in units of the base asset whose `base_history` it is, as is without one
+fn in_base(history: PriceHistory, base_history: Option<&PriceHistory>) -> Option<PriceHistory> {
+    match base_history {
+        Some(base_history) => history.relative_to(base_history),
+        None => Some(history),
+    }
 }
 
 /// Market risk assessment result
@@ -58,115 +377,1988 @@ pub struct RiskAssessment {
     /// Market name
     pub market_name: String,
     /// Market address
+    #[serde(serialize_with = "crate::utils::checksummed")]
     pub market_address: Address,
     /// List of identified risks
     pub findings: Vec<RiskFinding>,
     /// Overall risk score (0-100, higher is riskier)
     pub risk_score: u8,
+    /// Score of each category the risk score counts, from that category's findings alone
+    /// (0-100); `None` for categories no check evaluated, because it did not run or had no
+    /// data, as opposed to 0 for checks that found nothing. Empty in assessments saved before
+    /// sub-scores were recorded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub category_scores: BTreeMap<RiskCategory, Option<u8>>,
     /// Timestamp of the assessment
     pub timestamp: DateTime<Utc>,
+    /// Whether the assessment was computed from mock or fixture data rather than chain state
+    #[serde(default)]
+    pub mock_data: bool,
+    /// Lowest health factor among borrowing watchlist accounts in this market, if any
+    #[serde(default)]
+    pub watchlist_min_health_factor: Option<f64>,
+    /// `risk.min_position_usd` in effect: smaller positions got no per-account findings
+    #[serde(default)]
+    pub min_position_usd: Option<f64>,
+    /// Score against the market's stored history; `None` without a history store
+    #[serde(default)]
+    pub trend: Option<ScoreTrend>,
+    /// Size of the market and shortfalls of its tracked borrowers, for `ProtocolRiskSummary`
+    #[serde(default)]
+    pub exposure: Option<MarketExposure>,
+    /// Tracked positions by health factor; `None` when no positions were read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_factors: Option<HealthFactorDistribution>,
+    /// RPC calls the assessment made and what it left out to stay within `rpc.budget`;
+    /// `None` for providers not backed by a chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc: Option<Box<RpcReport>>,
+    /// Block the market state was read at; `None` when the provider does not pin reads to a block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Problems with the data the assessment was computed from, each also a `DataQuality`
+    /// finding; see `data_degraded`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_issues: Vec<DataIssue>,
+    /// Highest 30-day price volatility among the collateral assets checked; `None` without
+    /// price history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_volatility: Option<f64>,
+    /// Reserves in USD, read for the market's baseline; `None` when they were not read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserves_usd: Option<f64>,
+    /// Key metrics against the market's stored history; `None` without a history store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BaselineComparison>,
+    /// Whether every check finished in time; the checks cut off are `TimedOut` data issues,
+    /// see `timed_out`
+    #[serde(default = "complete_by_default")]
+    pub complete: bool,
+    /// `version::ENGINE_VERSION` of the engine that produced the assessment; empty in
+    /// assessments saved before it was recorded
+    #[serde(default)]
+    pub engine_version: String,
+    /// Version of each check that ran, by name (see `version::CHECK_VERSIONS`)
+    #[serde(default)]
+    pub check_versions: BTreeMap<String, u32>,
+    /// `version::config_hash` of the configuration the assessment was produced under
+    #[serde(default)]
+    pub config_hash: String,
+    /// Time and RPC calls of each check that ran, by name; checks cut off by the deadline
+    /// count up to the cutoff
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub timings: BTreeMap<String, CheckTiming>,
+    /// Watchlist positions that changed by `risk.position_changes` since the previous
+    /// assessment of the market by the same engine
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub position_changes: Vec<PositionDelta>,
+    /// Latest operator note on each finding that has one, by fingerprint, read from the
+    /// history store; informational only, it adds nothing to any score
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, Annotation>,
+    /// Interval and due times of the market in `run_scheduled`; `None` outside the scheduler
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<Cadence>,
+}
+
+/// What one check of an assessment cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckTiming {
+    /// Wall-clock time, in milliseconds
+    pub ms: u64,
+    /// RPC requests sent, retries included; 0 for providers not backed by a chain
+    pub rpc_calls: u64,
+}
+
+impl fmt::Display for CheckTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}ms, {} RPC call(s)", self.ms, self.rpc_calls)
+    }
+}
+
+/// One check of one market's assessment, as `slowest_checks` ranks them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowCheck<'a> {
+    pub market_name: &'a str,
+    pub check: &'a str,
+    pub timing: CheckTiming,
+}
+
+impl fmt::Display for SlowCheck<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {} ({})", self.check, self.market_name, self.timing)
+    }
+}
+
+/// The `n` checks of `assessments` that took longest, slowest first
+pub fn slowest_checks(assessments: &[RiskAssessment], n: usize) -> Vec<SlowCheck<'_>> {
+    let mut checks: Vec<SlowCheck> = assessments
+        .iter()
+        .flat_map(|assessment| {
+            assessment.timings.iter().map(|(check, timing)| SlowCheck {
+                market_name: &assessment.market_name,
+                check,
+                timing: *timing,
+            })
+        })
+        .collect();
+    checks.sort_by(|a, b| b.timing.ms.cmp(&a.timing.ms).then(b.timing.rpc_calls.cmp(&a.timing.rpc_calls)));
+    checks.truncate(n);
+    checks
+}
+
+fn complete_by_default() -> bool {
+    true
+}
+
+impl RiskAssessment {
+    /// Assessment of `findings` in a market with nothing else known: score 0, complete, no
+    /// engine version and no optional sections, stamped now. Callers set the rest with struct
+    /// update syntax, so a new field needs a default only here
+    pub fn new(market_name: impl Into<String>, market_address: Address, findings: Vec<RiskFinding>) -> Self {
+        Self {
+            market_name: market_name.into(),
+            market_address,
+            findings,
+            risk_score: 0,
+            category_scores: BTreeMap::new(),
+            timestamp: Utc::now(),
+            mock_data: false,
+            watchlist_min_health_factor: None,
+            min_position_usd: None,
+            trend: None,
+            exposure: None,
+            health_factors: None,
+            rpc: None,
+            block_number: None,
+            data_issues: Vec::new(),
+            price_volatility: None,
+            reserves_usd: None,
+            baseline: None,
+            complete: true,
+            engine_version: String::new(),
+            check_versions: BTreeMap::new(),
+            config_hash: String::new(),
+            timings: BTreeMap::new(),
+            position_changes: Vec::new(),
+            annotations: BTreeMap::new(),
+            cadence: None,
+        }
+    }
+
+    /// Whether the engine's view of the market is degraded, as opposed to the market being
+    /// risky: the other findings then carry `"confidence": "low"`
+    pub fn data_degraded(&self) -> bool {
+        !self.data_issues.is_empty()
+    }
+
+    /// What of the data is degraded, e.g. "price:WETH, watchlist"; `None` when nothing is
+    pub fn data_degradation(&self) -> Option<String> {
+        self.data_degraded()
+            .then(|| self.data_issues.iter().map(|issue| issue.check.as_str()).collect::<Vec<_>>().join(", "))
+    }
+
+    /// Checks cut off by a timeout, e.g. "price_volatility, positions"; `None` for complete
+    /// assessments
+    pub fn timed_out(&self) -> Option<String> {
+        let checks: Vec<&str> = self
+            .data_issues
+            .iter()
+            .filter(|issue| issue.kind == DataIssueKind::TimedOut)
+            .map(|issue| issue.check.as_str())
+            .collect();
+        (!checks.is_empty()).then(|| checks.join(", "))
+    }
+
+    /// `risk_score` out of 100, with the direction arrow of its trend if it has one
+    pub fn score_label(&self) -> String {
+        match &self.trend {
+            Some(trend) => format!("{}/100 {}", self.risk_score, trend.direction.arrow()),
+            None => format!("{}/100", self.risk_score),
+        }
+    }
+
+    /// Severity of the worst finding; `None` without findings
+    pub fn worst_severity(&self) -> Option<RiskSeverity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+
+    /// What a notification shows of the assessment, without the findings themselves
+    pub fn summary(&self) -> AssessmentSummary {
+        AssessmentSummary {
+            market_name: self.market_name.clone(),
+            market_address: self.market_address,
+            risk_score: self.risk_score,
+            direction: self.trend.as_ref().map(|trend| trend.direction),
+            worst_severity: self.worst_severity(),
+            findings: self.findings.len(),
+            timestamp: self.timestamp,
+            mock_data: self.mock_data,
+        }
+    }
+
+    /// Assessments saved by `assess --out` (see `archive`), or printed by `--output json assess`
+    pub fn load_many(path: &Path) -> Result<Vec<Self>> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Saved {
+            Many(Vec<RiskAssessment>),
+            One(Box<RiskAssessment>),
+        }
+
+        Ok(match archive::read(path, ContentKind::Assessments)? {
+            Saved::Many(assessments) => assessments,
+            Saved::One(assessment) => vec![*assessment],
+        })
+    }
+}
+
+/// Same line as its `summary()`
+impl fmt::Display for RiskAssessment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.summary().fmt(f)
+    }
+}
+
+/// Score and findings count of an assessment, as a notification shows them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssessmentSummary {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub risk_score: u8,
+    /// Direction of the score's trend; `None` without a history store
+    pub direction: Option<Direction>,
+    /// Severity of the worst finding; `None` without findings
+    pub worst_severity: Option<RiskSeverity>,
+    /// Number of findings
+    pub findings: usize,
+    pub timestamp: DateTime<Utc>,
+    pub mock_data: bool,
+}
+
+/// `USDC (0xc3d6...cdc3): score 45/100 ↑, 3 finding(s), worst High`, on one line
+impl fmt::Display for AssessmentSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}: score {}/100",
+            sanitize_inline(&format_named_address(&self.market_name, &self.market_address)),
+            mock_tag(self.mock_data),
+            self.risk_score
+        )?;
+        if let Some(direction) = self.direction {
+            write!(f, " {}", direction.arrow())?;
+        }
+        match self.worst_severity {
+            Some(worst) => write!(f, ", {} finding(s), worst {:?}", self.findings, worst),
+            None => write!(f, ", no findings"),
+        }
+    }
+}
+
+/// Outcome of simulating stressed conditions in one market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    /// Findings the simulated conditions would produce
+    pub findings: Vec<RiskFinding>,
+    /// Absorption of one position, from `RiskEngine::simulate_absorption`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub absorption: Option<crate::absorb::AbsorptionSimulation>,
+    pub timestamp: DateTime<Utc>,
+    /// Whether the simulation started from mock or fixture data
+    #[serde(default)]
+    pub mock_data: bool,
+}
+
+/// Progress event emitted by the streaming assessment API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AssessmentEvent {
+    /// Assessment of a market has begun
+    MarketStarted {
+        market_name: String,
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+    },
+    /// A check produced a finding for the given market
+    Finding {
+        #[serde(serialize_with = "crate::utils::checksummed")]
+        market_address: Address,
+        finding: RiskFinding,
+    },
+    /// All checks for a market finished; identical to the non-streaming result
+    MarketCompleted(Box<RiskAssessment>),
+    /// A market could not be assessed
+    Error(crate::MarketError),
+}
+
+/// Checks of an assessment cut off by the deadline, and the cost of each check that ran
+#[derive(Default)]
+struct CheckRuns {
+    timed_out: Vec<String>,
+    timings: BTreeMap<String, CheckTiming>,
 }
 
 /// Risk processor for assessing Compound V3 markets
 pub struct RiskProcessor {
     config: Arc<Config>,
+    provider: Option<SharedProvider>,
+    /// Accounts whose positions market-wide position checks read
+    accounts: Vec<Address>,
+    /// Checks from `budget::OPTIONAL_CHECKS` not to run
+    skipped_checks: Vec<&'static str>,
+    /// Checks run after the built-in ones
+    checks: Vec<Arc<dyn RiskCheck>>,
+    scorer: Arc<dyn RiskScorer>,
+    /// Position sample of each market assessed, `None` for markets read in full, drawn once
+    /// so that every check extrapolates from the same accounts
+    samples: Mutex<HashMap<Address, Option<Arc<PositionSample>>>>,
+    /// Fees of the network, read once for every market; `None` inside when unreadable
+    gas_quote: Mutex<Option<Option<GasQuote>>>,
+    /// When checks still running are cut off, leaving the assessment partial
+    deadline: Option<Instant>,
+    /// Watchlist positions of earlier assessments, compared with this one's
+    position_history: Option<Arc<PositionHistory>>,
+    /// Recent base flows of the assessed market from the borrower index
+    base_flows: Option<Vec<BaseFlow>>,
+    /// Collateral parameters of the assessed market in its previous stored snapshot
+    previous_collateral: Option<Vec<CollateralParameters>>,
+    /// Collateral parameters of the assessed market in its latest stored snapshots, oldest
+    /// first, with the time of each
+    collateral_history: Vec<(DateTime<Utc>, Vec<CollateralParameters>)>,
 }
 
 impl RiskProcessor {
     /// Create a new RiskProcessor instance
+    ///
+    /// Without a data provider only checks that need nothing beyond the `Market` itself run.
     pub fn new(config: Arc<Config>) -> Self {
-        Self { config }
+        Self {
+            config,
+            provider: None,
+            accounts: Vec::new(),
+            skipped_checks: Vec::new(),
+            checks: Vec::new(),
+            scorer: Arc::new(RiskScore::of),
+            samples: Mutex::default(),
+            gas_quote: Mutex::default(),
+            deadline: None,
+            position_history: None,
+            base_flows: None,
+            previous_collateral: None,
+            collateral_history: Vec::new(),
+        }
+    }
+
+    /// Create a RiskProcessor whose checks can fetch additional data (price history, positions)
+    pub fn with_provider(config: Arc<Config>, provider: SharedProvider) -> Self {
+        Self {
+            config,
+            provider: Some(provider),
+            accounts: Vec::new(),
+            skipped_checks: Vec::new(),
+            checks: Vec::new(),
+            scorer: Arc::new(RiskScore::of),
+            samples: Mutex::default(),
+            gas_quote: Mutex::default(),
+            deadline: None,
+            position_history: None,
+            base_flows: None,
+            previous_collateral: None,
+            collateral_history: Vec::new(),
+        }
+    }
+
+    /// Read the positions of `accounts` in checks covering all borrowers, such as the
+    /// absorption check; without accounts those checks do not run
+    pub fn with_accounts(mut self, accounts: Vec<Address>) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Leave out `checks`, named as in `budget::OPTIONAL_CHECKS`
+    pub fn with_skipped_checks(mut self, checks: Vec<&'static str>) -> Self {
+        self.skipped_checks = checks;
+        self
+    }
+
+    /// Also run `checks`, after the built-in ones
+    pub fn with_checks(mut self, checks: Vec<Arc<dyn RiskCheck>>) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    /// Score findings with `scorer` instead of `RiskScore::of`
+    pub fn with_scorer(mut self, scorer: Arc<dyn RiskScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    /// Compare watchlist positions with those recorded in `history`, and record them there
+    pub fn with_position_history(mut self, history: Arc<PositionHistory>) -> Self {
+        self.position_history = Some(history);
+        self
+    }
+
+    /// Check `flows`, the indexed base flows of the assessed market, for large outflows; without
+    /// them the outflow check does not run
+    pub fn with_base_flows(mut self, flows: Vec<BaseFlow>) -> Self {
+        self.base_flows = Some(flows);
+        self
+    }
+
+    /// Compare the collateral parameters of the assessed market with `previous`, those of its
+    /// previous snapshot; without them the collateral change check does not run
+    pub fn with_previous_collateral(mut self, previous: Vec<CollateralParameters>) -> Self {
+        self.previous_collateral = Some(previous);
+        self
+    }
+
+    /// Count how long the protocol has held collateral over `history`, the collateral
+    /// parameters of the market's latest snapshots (oldest first); without it every holding
+    /// is as old as the assessment
+    pub fn with_collateral_history(mut self, history: Vec<(DateTime<Utc>, Vec<CollateralParameters>)>) -> Self {
+        self.collateral_history = history;
+        self
+    }
+
+    /// Cut off checks still running at `deadline`: each becomes a `TimedOut` data issue and
+    /// the assessment is returned with what finished, not `complete`
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// `future` of `check`, or `None` with `check` added to `runs.timed_out` if the deadline
+    /// passes first; after the deadline only checks that finish without waiting on a read get
+    /// through. Either way the time and RPC calls of `check` go into `runs.timings`.
+    async fn until_deadline<T>(&self, check: &str, runs: &mut CheckRuns, future: impl Future<Output = T>) -> Option<T> {
+        let started = Instant::now();
+        let (output, usage) = rpc::metered(async {
+            match self.deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+                None => Some(future.await),
+            }
+        })
+        .await;
+        self.record_timing(check, runs, started, usage.calls);
+        if output.is_none() {
+            warn!("Check {} cut off by the assessment deadline", check);
+            runs.timed_out.push(check.to_string());
+        }
+        output
+    }
+
+    /// Run `check`, a check that reads nothing, recording its time in `runs.timings`
+    fn timed<T>(&self, check: &str, runs: &mut CheckRuns, run: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let output = run();
+        self.record_timing(check, runs, started, 0);
+        output
+    }
+
+    /// Record `check`, started at `started`, warning when it took longer than
+    /// `performance.check_budget_ms`
+    fn record_timing(&self, check: &str, runs: &mut CheckRuns, started: Instant, rpc_calls: u64) {
+        let ms = started.elapsed().as_millis() as u64;
+        let budget = self.config.performance.check_budget_ms;
+        if ms > budget {
+            warn!("Check {} took {}ms and {} RPC call(s), over performance.check_budget_ms ({}ms)", check, ms, rpc_calls, budget);
+        }
+        runs.timings.insert(check.to_string(), CheckTiming { ms, rpc_calls });
     }
     
     /// Assess a market for risks
     pub async fn assess_market(&self, market: &Market) -> Result<RiskAssessment> {
+        self.assess_market_reporting(market, None).await
+    }
+
+    /// Assess a market, sending its findings to `events` once every check has run
+    ///
+    /// Whether the data is degraded is only known then, as any check may miss reads or be cut
+    /// off, so no finding goes out without its final `data_degraded` and `confidence`.
+    pub async fn assess_market_reporting(
+        &self,
+        market: &Market,
+        events: Option<&mpsc::UnboundedSender<AssessmentEvent>>,
+    ) -> Result<RiskAssessment> {
         info!("Assessing risks for market: {}", market.name);
         
         let mut findings = Vec::new();
         let now = Utc::now();
+
+        // Check the L2 sequencer first: while it is degraded every other finding is flagged
+        let mut runs = CheckRuns::default();
+        let sequencer = self.until_deadline("sequencer", &mut runs, self.check_sequencer(market, &mut findings, now)).await.flatten();
+        let sequencer_degraded = sequencer.unwrap_or(false);
+        let sequencer_findings = findings.len();
+
+        // Issues the provider found reading the market; the checks add the reads they miss
+        let mut data_issues = market.data_issues.clone();
+        let gas = match self.until_deadline("gas", &mut runs, self.gas_estimate(market)).await {
+            Some(gas) => gas,
+            None => GasEstimate::of(None, market, &self.config),
+        };
+
+        let mut annotated = 0;
+        let mut annotate = |findings: &mut [RiskFinding]| {
+            for (i, finding) in findings.iter_mut().enumerate().skip(annotated) {
+                if sequencer_degraded && i >= sequencer_findings {
+                    finding.metadata["sequencer_degraded"] = serde_json::json!(true);
+                }
+                if finding.category == RiskCategory::LiquidationCascade {
+                    finding.metadata["gas"] = serde_json::json!(gas);
+                }
+            }
+            annotated = findings.len();
+        };
+        annotate(&mut findings);
         
         // Check for high utilization
-        self.check_utilization(market, &mut findings, now);
-        
-        // For milestone 1, we'll focus on utilization risk only
-        // In later milestones, we'll add more risk checks:
-        // - Price volatility
-        // - Concentration
-        // - Liquidation cascade
-        // - Oracle reliability
-        // - Smart contract risks
-        
+        self.timed("utilization", &mut runs, || self.check_utilization(market, &mut findings, now));
+        annotate(&mut findings);
+
+        // Check that the market can pay out the withdrawals watchlist accounts plan
+        self.timed("planned_withdrawals", &mut runs, || self.check_planned_withdrawals(market, &mut findings, now));
+        annotate(&mut findings);
+
+        // Check for large base outflows in the indexed flows
+        if let Some(flows) = &self.base_flows {
+            self.timed("outflows", &mut runs, || self.check_outflows(market, flows, &mut findings, now));
+            annotate(&mut findings);
+        }
+
+        // Check collateral price volatility
+        let price_volatility = self
+            .until_deadline("price_volatility", &mut runs, self.check_price_volatility(market, &mut findings, now))
+            .await
+            .flatten();
+        annotate(&mut findings);
+
+        // Check watchlist accounts against the liquidation buffer
+        let mut watchlist_positions = Vec::new();
+        let watchlist_min_health_factor = self
+            .until_deadline("watchlist", &mut runs, self.check_watchlist(market, &mut findings, &mut data_issues, &mut watchlist_positions))
+            .await
+            .flatten();
+        annotate(&mut findings);
+
+        // Check that every per-asset setting names an asset
+        self.until_deadline("asset_settings", &mut runs, self.check_asset_settings(market, &mut findings, now)).await;
+        annotate(&mut findings);
+
+        // Check that the rewards contract can keep paying the market's emissions
+        self.timed("rewards_runway", &mut runs, || self.check_rewards_runway(market, &mut findings, now));
+        annotate(&mut findings);
+
+        // Check the borrow in positions too small to be worth liquidating against reserves
+        if !self.skipped_checks.contains(&"absorption") {
+            self.until_deadline("absorption", &mut runs, self.check_absorption_economics(market, &gas, &mut findings, now)).await;
+            annotate(&mut findings);
+        }
+
+        // Check that buying absorbed collateral still pays after a bad day
+        self.until_deadline("liquidator_margin", &mut runs, self.check_liquidator_margin(market, &mut findings, now)).await;
+        annotate(&mut findings);
+
+        // Check for absorbed collateral buyers are not taking off the protocol's hands
+        let protocol_collateral = self
+            .until_deadline("protocol_collateral", &mut runs, self.check_protocol_collateral(market, &mut findings, now))
+            .await
+            .unwrap_or(false);
+        annotate(&mut findings);
+
+        // Check how much of the collateral rests on a bridge or custodian
+        self.timed("collateral_provenance", &mut runs, || self.check_collateral_provenance(market, &mut findings, now));
+        annotate(&mut findings);
+
+        // Check for Configurator changes not yet deployed
+        self.until_deadline("configuration_drift", &mut runs, self.check_configuration_drift(market, &mut findings, now)).await;
+        annotate(&mut findings);
+
+        // Check the managers watchlist accounts allow to act for them
+        let mut managers = HashMap::new();
+        if !self.skipped_checks.contains(&"manager_permissions") {
+            managers = self
+                .until_deadline("manager_permissions", &mut runs, self.check_manager_permissions(market, &mut findings, now))
+                .await
+                .unwrap_or_default();
+            annotate(&mut findings);
+        }
+
+        // Compare watchlist positions with those of the previous assessment
+        let mut position_changes = Vec::new();
+        if let Some(history) = &self.position_history {
+            position_changes = self.timed("position_changes", &mut runs, || {
+                self.check_position_changes(history, market, &watchlist_positions, &managers, &mut findings, now)
+            });
+            annotate(&mut findings);
+        }
+
+        // Checks added by the embedding application
+        for check in &self.checks {
+            match self.until_deadline(&check.name(), &mut runs, check.check(market, self.provider.as_ref(), now)).await {
+                Some(Ok(found)) => findings.extend(found),
+                Some(Err(e)) => warn!("Check {} failed for {}: {}", check.name(), market.name, e),
+                None => {}
+            }
+            annotate(&mut findings);
+        }
+
+        let (exposure, health_factors, positions) = match self.until_deadline("positions", &mut runs, self.market_exposure(market)).await {
+            Some((exposure, health_factors, positions_issue, positions)) => {
+                data_issues.extend(positions_issue);
+                (Some(exposure), health_factors, positions)
+            }
+            None => (None, None, Vec::new()),
+        };
+
+        // Check the largest tracked borrower's share of the market's borrow
+        let largest_borrower = positions
+            .iter()
+            .filter(|position| position.total_borrow_value > 0.0)
+            .max_by(|a, b| a.total_borrow_value.total_cmp(&b.total_borrow_value));
+        if let Some(position) = largest_borrower {
+            self.timed("borrower_concentration", &mut runs, || self.check_borrower_concentration(market, position, &mut findings, now));
+            annotate(&mut findings);
+        }
+
+        // Check for collateral delisted or restricted since the previous snapshot
+        if let Some(previous) = &self.previous_collateral {
+            self.timed("collateral_changes", &mut runs, || self.check_collateral_changes(market, previous, &positions, &mut findings, now));
+            annotate(&mut findings);
+        }
+
+        // Checks that ran without data to look at evaluate nothing
+        let idle = [
+            ("sequencer", sequencer.is_none()),
+            ("price_volatility", price_volatility.is_none()),
+            ("watchlist", watchlist_positions.is_empty()),
+            ("rewards_runway", market.rewards.is_none()),
+            ("absorption", self.provider.is_none() || self.accounts.is_empty()),
+            ("configuration_drift", self.provider.is_none()),
+            ("protocol_collateral", !protocol_collateral),
+            ("manager_permissions", managers.is_empty()),
+        ];
+        let mut evaluated: Vec<RiskCategory> = runs
+            .timings
+            .keys()
+            .filter(|check| !runs.timed_out.contains(check) && !idle.contains(&(check.as_str(), true)))
+            .filter_map(|check| CHECK_CATEGORIES.iter().find(|(name, _)| name == check))
+            .flat_map(|(_, categories)| categories.iter().cloned())
+            .collect();
+        for check in &self.checks {
+            if runs.timings.contains_key(&check.name()) && !runs.timed_out.contains(&check.name()) {
+                evaluated.extend(check.categories());
+            }
+        }
+
+        let complete = runs.timed_out.is_empty();
+        data_issues.extend(std::mem::take(&mut runs.timed_out).into_iter().map(|check| {
+            DataIssue::new(DataIssueKind::TimedOut, check, "cut off by the assessment deadline (timeouts.per_market or timeouts.total)")
+        }));
+        if !data_issues.is_empty() {
+            findings.iter_mut().for_each(mark_degraded);
+        }
+
+        // Report what is wrong with the data, apart from the risks of the market
+        self.timed("data_quality", &mut runs, || self.check_data_quality(market, &data_issues, &mut findings, now));
+        annotate(&mut findings);
+        if let Some(tx) = events {
+            for finding in &findings {
+                // A dropped receiver only means nobody is listening any more
+                let _ = tx.send(AssessmentEvent::Finding { market_address: market.comet_address, finding: finding.clone() });
+            }
+        }
+
         // Calculate an overall risk score based on findings
-        let risk_score = self.calculate_risk_score(&findings);
+        let score = self.scorer.score(&findings);
+        score.attribute(&mut findings);
+        let category_scores = self.category_scores(&findings, &evaluated);
         
         let assessment = RiskAssessment {
-            market_name: market.name.clone(),
-            market_address: market.comet_address,
-            findings,
-            risk_score,
+            risk_score: score.total,
+            category_scores,
             timestamp: now,
+            mock_data: self.provider.as_ref().is_some_and(|p| p.is_mock()),
+            watchlist_min_health_factor,
+            min_position_usd: self.config.risk.min_position_usd,
+            exposure,
+            health_factors,
+            block_number: market.block_number,
+            data_issues,
+            price_volatility,
+            complete,
+            engine_version: version::ENGINE_VERSION.to_string(),
+            check_versions: self.check_versions(),
+            config_hash: version::config_hash(&self.config),
+            timings: runs.timings,
+            position_changes,
+            ..RiskAssessment::new(market.name.clone(), market.comet_address, findings)
         };
         
         Ok(assessment)
     }
-    
-    /// Check for high utilization risk
-    fn check_utilization(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
-        let utilization = market.utilization_rate;
-        let threshold = self.config.risk.max_utilization_threshold;
-        
-        if utilization > threshold {
-            // High utilization is a risk
-            let severity = if utilization > threshold + 0.1 {
-                RiskSeverity::Critical
-            } else if utilization > threshold + 0.05 {
+    
+    /// Score of each scored category from its findings alone; `None` for categories neither
+    /// `evaluated` by a check nor found in
+    fn category_scores(&self, findings: &[RiskFinding], evaluated: &[RiskCategory]) -> BTreeMap<RiskCategory, Option<u8>> {
+        RiskCategory::ALL
+            .iter()
+            .filter(|category| category.is_scored())
+            .map(|category| {
+                let found: Vec<RiskFinding> = findings.iter().filter(|f| &f.category == category).cloned().collect();
+                let score = (evaluated.contains(category) || !found.is_empty()).then(|| self.scorer.score(&found).total);
+                (category.clone(), score)
+            })
+            .collect()
+    }
+
+    /// Versions of the checks `assess_market` runs: the built-in ones it does not skip and
+    /// those added by the embedding application
+    pub fn check_versions(&self) -> BTreeMap<String, u32> {
+        let builtin = version::CHECK_VERSIONS
+            .iter()
+            .filter(|(name, _)| !self.skipped_checks.contains(name) && !version::HISTORY_CHECKS.contains(name))
+            .map(|(name, version)| (name.to_string(), *version));
+        builtin.chain(self.checks.iter().map(|check| (check.name(), check.version()))).collect()
+    }
+
+    /// Size of `market` and shortfalls of the tracked borrowers (borrower index and watchlist),
+    /// with their distribution by health factor; position figures are 0, and there is no
+    /// distribution, without a provider or if the positions cannot be read. Positions that
+    /// fail to read make a `PartialBatch` issue. Also returns the positions read.
+    async fn market_exposure(
+        &self,
+        market: &Market,
+    ) -> (MarketExposure, Option<HealthFactorDistribution>, Option<DataIssue>, Vec<UserPosition>) {
+        let settings = &self.config.risk.summary;
+        let sample = self.position_sample(market).await;
+        let accounts = match &sample {
+            Some(sample) => sample.accounts().to_vec(),
+            None => self.tracked_accounts(),
+        };
+        let mut issue = None;
+        let positions: Option<Vec<UserPosition>> = match &self.provider {
+            Some(provider) if !accounts.is_empty() => match provider.get_positions(market, &accounts).await {
+                Ok(results) => {
+                    let read = results.len();
+                    let failed = results.iter().filter(|(_, r)| matches!(r, Err(e) if !matches!(e, RiskEngineError::NotFound { .. }))).count();
+                    if failed > 0 {
+                        issue = Some(partial_batch("positions", format!(
+                            "{} of {} tracked positions could not be read; exposure figures are lower bounds",
+                            failed, read
+                        )));
+                    }
+                    Some(results.into_iter().filter_map(|(_, result)| result.ok()).collect())
+                }
+                Err(e) => {
+                    warn!("Failed to fetch positions for the exposure of {}: {}", market.name, e);
+                    issue = Some(partial_batch("positions", format!("tracked positions could not be read: {}", e)));
+                    None
+                }
+            },
+            _ => None,
+        };
+        let edges = &settings.health_factor_buckets;
+        let distribution = positions
+            .as_ref()
+            .map(|positions| HealthFactorDistribution::estimated(positions, edges, sample.as_deref()));
+        let exposure = MarketExposure::estimated(
+            self.config.compound.chain_id,
+            market,
+            positions.as_deref().unwrap_or_default(),
+            distribution.as_ref().unwrap_or(&HealthFactorDistribution::new(&[], edges)),
+            settings.near_liquidation_margin,
+            sample.as_deref(),
+        );
+        (exposure, distribution, issue, positions.unwrap_or_default())
+    }
+
+    /// The borrower index and the watchlist accounts not in it
+    fn tracked_accounts(&self) -> Vec<Address> {
+        let mut accounts = self.accounts.clone();
+        accounts.extend(self.watchlist_accounts().into_iter().filter(|a| !self.accounts.contains(a)));
+        accounts
+    }
+
+    /// Sample of the tracked positions of `market` by `risk.sampling`, or `None` when every
+    /// position is read: sampling is off, the market tracks few enough accounts, or its
+    /// borrows cannot be read
+    async fn position_sample(&self, market: &Market) -> Option<Arc<PositionSample>> {
+        if let Some(sample) = self.samples.lock().unwrap_or_else(|e| e.into_inner()).get(&market.comet_address) {
+            return sample.clone();
+        }
+        let settings = &self.config.risk.sampling;
+        let accounts = self.tracked_accounts();
+        let sample = match &self.provider {
+            Some(provider) if settings.enabled && accounts.len() > settings.min_accounts => {
+                match provider.get_borrows(market, &accounts).await {
+                    Ok(results) => {
+                        let borrows: Vec<(Address, f64)> =
+                            results.into_iter().filter_map(|(account, borrow)| Some((account, borrow.ok()?))).collect();
+                        let sample = PositionSample::draw(&borrows, &self.watchlist_accounts(), settings);
+                        let report = sample.report();
+                        info!(
+                            "Sampling {} of {} borrowers in {} ({} of {} borrows read)",
+                            report.read,
+                            report.borrowers,
+                            market.name,
+                            borrows.len(),
+                            accounts.len()
+                        );
+                        Some(Arc::new(sample))
+                    }
+                    Err(e) => {
+                        warn!("Failed to read borrows to sample {}, reading every position: {}", market.name, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        self.samples.lock().unwrap_or_else(|e| e.into_inner()).insert(market.comet_address, sample.clone());
+        sample
+    }
+
+    /// What absorbing a position in `market` costs: at `risk.gas.pinned_gwei` when set,
+    /// otherwise at the provider's current fees, read once per processor
+    async fn gas_estimate(&self, market: &Market) -> GasEstimate {
+        let settings = &self.config.risk.gas;
+        let quote = match (settings.pinned_gwei, &self.provider) {
+            (Some(gwei), _) => Some(GasQuote::pinned(gwei)),
+            (None, None) => None,
+            (None, Some(provider)) => {
+                let cached = *self.gas_quote.lock().unwrap_or_else(|e| e.into_inner());
+                match cached {
+                    Some(quote) => quote,
+                    None => {
+                        let quote = provider.get_gas_quote(settings.fee_percentile).await.unwrap_or_else(|e| {
+                            warn!("No gas price, pricing liquidations at risk.small_positions.gas_cost_usd: {}", e);
+                            None
+                        });
+                        *self.gas_quote.lock().unwrap_or_else(|e| e.into_inner()) = Some(quote);
+                        quote
+                    }
+                }
+            }
+        };
+        GasEstimate::of(quote.as_ref(), market, &self.config)
+    }
+
+    /// Check the network's L2 sequencer, returning whether it is down or still within
+    /// `risk.sequencer_grace_period_seconds` of coming back up, or `None` without a feed read
+    ///
+    /// A sequencer outage freezes oracle updates and liquidations at once, a Critical finding.
+    /// Networks without an uptime feed skip the check, as do feeds that cannot be read.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "sequencer", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_sequencer(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) -> Option<bool> {
+        let provider = self.provider.as_ref()?;
+        let status = match provider.get_sequencer_status().await {
+            Ok(Some(status)) => status,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("Failed to read the sequencer uptime feed: {}", e);
+                return None;
+            }
+        };
+        let grace_period = self.config.risk.sequencer_grace_period_seconds;
+        if !status.degraded(timestamp, chrono::Duration::seconds(grace_period as i64)) {
+            return Some(false);
+        }
+
+        let description = if status.up {
+            format!(
+                "The L2 sequencer came back up {} minutes ago, within the {} minute grace period: prices and liquidations may still lag",
+                (timestamp - status.since).num_minutes(),
+                grace_period / 60,
+            )
+        } else {
+            format!(
+                "The L2 sequencer has been down since {}: oracle prices are frozen and liquidations cannot run",
+                status.since.format("%Y-%m-%d %H:%M UTC"),
+            )
+        };
+        findings.push(RiskFinding {
+            category: RiskCategory::OracleReliability,
+            severity: RiskSeverity::Critical,
+            description,
+            metadata: serde_json::json!({
+                "market": market.name,
+                "sequencer_up": status.up,
+                "since": status.since,
+                "grace_period_seconds": grace_period,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::OracleReliability, "sequencer"),
+            score_contribution: 0,
+        });
+        Some(true)
+    }
+
+    /// Check for high utilization risk
+    fn check_utilization(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let _span = tracing::info_span!("risk_check", check = "utilization", market = %market.name, market_address = ?market.comet_address).entered();
+        let utilization = market.utilization();
+        let threshold = self.config.risk.max_utilization_threshold;
+        
+        if utilization > threshold {
+            // High utilization is a risk; band on the excess over the threshold in whole basis
+            // points, so 90% against an 85% threshold is 5 points over however the floats round
+            let excess_bps = ((utilization - threshold) * 10_000.0).round();
+            let severity = if excess_bps >= 1_000.0 {
+                RiskSeverity::Critical
+            } else if excess_bps >= 500.0 {
+                RiskSeverity::High
+            } else {
+                RiskSeverity::Medium
+            };
+            
+            let description = format!(
+                "Market utilization is {:.2}%, which exceeds the recommended threshold of {:.2}%",
+                utilization * 100.0,
+                threshold * 100.0
+            );
+            
+            let metadata = serde_json::json!({
+                "current_utilization": utilization,
+                "threshold": threshold,
+                "base_asset": market.base_asset.symbol,
+                "total_supply": market.total_supply,
+                "total_borrow": market.total_borrow,
+            });
+            
+            findings.push(RiskFinding {
+                category: RiskCategory::HighUtilization,
+                severity,
+                description,
+                metadata,
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, "market"),
+                score_contribution: 0,
+            });
+        }
+    }
+    
+    /// Flag each `risk.planned_withdrawals` entry in `market` larger than its available
+    /// liquidity: a High `HighUtilization` finding, since the withdrawal would revert until
+    /// borrowers repay or others supply
+    fn check_planned_withdrawals(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let available = market.available_liquidity();
+        for withdrawal in &self.config.risk.planned_withdrawals {
+            let in_market = withdrawal
+                .market
+                .as_deref()
+                .is_none_or(|address| crate::utils::parse_address(address).is_ok_and(|a| a == market.comet_address));
+            let Ok(account) = crate::utils::parse_address(&withdrawal.account) else { continue };
+            if !in_market || withdrawal.amount <= available {
+                continue;
+            }
+            let subject = format!("withdrawal:{:?}", account);
+            findings.push(RiskFinding {
+                category: RiskCategory::HighUtilization,
+                severity: RiskSeverity::High,
+                description: format!(
+                    "Planned withdrawal of {} by {} exceeds the {} the market can pay out",
+                    crate::utils::format_token_amount(withdrawal.amount, &market.base_asset.symbol, market.base_asset.decimals),
+                    crate::utils::to_checksum_address(&account),
+                    crate::utils::format_token_amount(available, &market.base_asset.symbol, market.base_asset.decimals)
+                ),
+                metadata: serde_json::json!({
+                    "account": crate::utils::to_checksum_address(&account),
+                    "market": market.name,
+                    "withdrawal": withdrawal.amount,
+                    "available_liquidity": available,
+                    "shortfall": withdrawal.amount - available,
+                    "base_asset": market.base_asset.symbol,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, &subject),
+                score_contribution: 0,
+            });
+        }
+    }
+
+    /// Flag single transactions and rolling `risk.outflows.window_seconds` windows of `flows`
+    /// whose net base outflow reaches `risk.outflows`, more severe the higher the market's
+    /// utilization (`outflow_severity`)
+    ///
+    /// A window is only flagged when more than one transaction withdrew in it; a single one is
+    /// flagged alone.
+    fn check_outflows(&self, market: &Market, flows: &[BaseFlow], findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let _span = tracing::info_span!("risk_check", check = "outflows", market = %market.name, market_address = ?market.comet_address).entered();
+        let settings = &self.config.risk.outflows;
+        let base = &market.base_asset;
+        let supply_usd = market.total_supply * base.price;
+        let utilization = market.utilization();
+        let max_utilization = self.config.risk.max_utilization_threshold;
+        // How many times its threshold an outflow is; flagged from 1
+        let multiple = |usd: f64| {
+            let by_usd = settings.min_outflow_usd.map_or(0.0, |min| usd / min);
+            let by_share = settings.min_outflow_share.filter(|_| supply_usd > 0.0).map_or(0.0, |min| usd / (min * supply_usd));
+            by_usd.max(by_share)
+        };
+        let share = |usd: f64| if supply_usd > 0.0 { usd / supply_usd } else { 0.0 };
+        let labeled = |accounts: &[Address]| -> Vec<serde_json::Value> {
+            accounts
+                .iter()
+                .map(|account| serde_json::json!({
+                    "address": crate::utils::to_checksum_address(account),
+                    "label": crate::labels::label_for(account),
+                }))
+                .collect()
+        };
+        let described = |accounts: &[Address]| accounts.iter().map(format_address_labeled).collect::<Vec<_>>().join(", ");
+
+        // Net outflow of each transaction in USD, in time order
+        let mut transactions: Vec<TransactionOutflow> = Vec::new();
+        for flow in flows {
+            let usd = crate::utils::u256_to_f64(flow.amount, base.decimals) * base.price;
+            let position = transactions.iter().position(|tx| tx.tx_hash == flow.tx_hash);
+            let tx = match position {
+                Some(position) => &mut transactions[position],
+                None => {
+                    transactions.push(TransactionOutflow { tx_hash: flow.tx_hash, timestamp: flow.timestamp, outflow_usd: 0.0, accounts: Vec::new() });
+                    transactions.last_mut().expect("just pushed")
+                }
+            };
+            match flow.direction {
+                FlowDirection::In => tx.outflow_usd -= usd,
+                FlowDirection::Out => {
+                    tx.outflow_usd += usd;
+                    if !tx.accounts.contains(&flow.account) {
+                        tx.accounts.push(flow.account);
+                    }
+                }
+            }
+        }
+        transactions.sort_by_key(|tx| tx.timestamp);
+
+        for tx in &transactions {
+            let times = multiple(tx.outflow_usd);
+            if times < 1.0 {
+                continue;
+            }
+            findings.push(RiskFinding {
+                category: RiskCategory::HighUtilization,
+                severity: outflow_severity(times, utilization, max_utilization),
+                description: format!(
+                    "{} withdrew {} net of {} from {} in one transaction ({:.2}% of supply), leaving utilization at {:.1}%",
+                    described(&tx.accounts),
+                    crate::utils::format_compact(tx.outflow_usd),
+                    base.symbol,
+                    market.name,
+                    share(tx.outflow_usd) * 100.0,
+                    utilization * 100.0
+                ),
+                metadata: serde_json::json!({
+                    "tx_hash": tx.tx_hash,
+                    "accounts": labeled(&tx.accounts),
+                    "outflow_usd": tx.outflow_usd,
+                    "share_of_supply": share(tx.outflow_usd),
+                    "utilization": utilization,
+                    "at": tx.timestamp,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, &format!("outflow:{:?}", tx.tx_hash)),
+                score_contribution: 0,
+            });
+        }
+
+        // The window with the largest net outflow, over transactions less than a window apart
+        let window = chrono::Duration::seconds(settings.window_seconds as i64);
+        let (mut start, mut sum) = (0, 0.0);
+        let mut largest: Option<(f64, usize, usize)> = None;
+        for (end, tx) in transactions.iter().enumerate() {
+            while tx.timestamp - transactions[start].timestamp >= window {
+                sum -= transactions[start].outflow_usd;
+                start += 1;
+            }
+            sum += tx.outflow_usd;
+            if largest.is_none_or(|(most, _, _)| sum > most) {
+                largest = Some((sum, start, end));
+            }
+        }
+        let Some((outflow_usd, start, end)) = largest else { return };
+        let withdrawing: Vec<&TransactionOutflow> = transactions[start..=end].iter().filter(|tx| tx.outflow_usd > 0.0).collect();
+        let times = multiple(outflow_usd);
+        if times < 1.0 || withdrawing.len() < 2 {
+            return;
+        }
+        let mut accounts: Vec<Address> = withdrawing.iter().flat_map(|tx| tx.accounts.iter().copied()).collect();
+        accounts.sort();
+        accounts.dedup();
+        findings.push(RiskFinding {
+            category: RiskCategory::HighUtilization,
+            severity: outflow_severity(times, utilization, max_utilization),
+            description: format!(
+                "{} net of {} left {} within {} in {} transactions ({:.2}% of supply), leaving utilization at {:.1}%",
+                crate::utils::format_compact(outflow_usd),
+                base.symbol,
+                market.name,
+                crate::utils::format_duration(std::time::Duration::from_secs(settings.window_seconds)),
+                withdrawing.len(),
+                share(outflow_usd) * 100.0,
+                utilization * 100.0
+            ),
+            metadata: serde_json::json!({
+                "window_start": transactions[start].timestamp,
+                "window_end": transactions[end].timestamp,
+                "window_seconds": settings.window_seconds,
+                "tx_hashes": withdrawing.iter().map(|tx| tx.tx_hash).collect::<Vec<_>>(),
+                "accounts": labeled(&accounts),
+                "outflow_usd": outflow_usd,
+                "share_of_supply": share(outflow_usd),
+                "utilization": utilization,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, "outflow_window"),
+            score_contribution: 0,
+        });
+    }
+
+    /// Report each data issue as a `DataQuality` finding: Medium when a price, the source's
+    /// freshness or a decoded value is in doubt, since every check reads them, otherwise Low
+    fn check_data_quality(&self, market: &Market, issues: &[DataIssue], findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        for issue in issues {
+            let severity = match issue.kind {
+                DataIssueKind::MissingPrice | DataIssueKind::StaleSource | DataIssueKind::DecodingAnomaly | DataIssueKind::TimedOut => {
+                    RiskSeverity::Medium
+                }
+                DataIssueKind::Inconsistent | DataIssueKind::PartialBatch | DataIssueKind::ShortHistory => RiskSeverity::Low,
+            };
+            findings.push(RiskFinding {
+                category: RiskCategory::DataQuality,
+                severity,
+                description: format!("Degraded {} data for {}: {}", issue.check, market.name, issue.message),
+                metadata: serde_json::json!({
+                    "kind": issue.kind,
+                    "check": issue.check,
+                    "total_supply": market.total_supply,
+                    "total_borrow": market.total_borrow,
+                    "block_number": market.block_number,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::DataQuality, &issue.check),
+                score_contribution: 0,
+            });
+        }
+    }
+
+    /// Check the days of COMP emissions the rewards contract holds against `risk.min_rewards_runway_days`
+    fn check_rewards_runway(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let _span = tracing::info_span!("risk_check", check = "rewards_runway", market = %market.name, market_address = ?market.comet_address).entered();
+        let Some(rewards) = &market.rewards else { return };
+        let Some(runway_days) = rewards.runway_days() else { return };
+        let threshold = self.config.risk.min_rewards_runway_days;
+        if runway_days >= threshold {
+            return;
+        }
+
+        // An empty contract stops rewards now; suppliers and borrowers relying on them will move
+        let severity = if runway_days < threshold / 4.0 { RiskSeverity::High } else { RiskSeverity::Medium };
+        let description = format!(
+            "Rewards contract holds {:.1} days of {} COMP emissions, below the minimum of {:.0} days",
+            runway_days, market.name, threshold
+        );
+        findings.push(RiskFinding {
+            category: RiskCategory::IncentiveRunway,
+            severity,
+            description,
+            metadata: serde_json::json!({
+                "runway_days": runway_days,
+                "threshold_days": threshold,
+                "rewards_contract": rewards.contract,
+                "balance": rewards.balance,
+                "daily_emission": rewards.daily_emission,
+                "supply_reward_apr": market.supply_reward_apr,
+                "borrow_reward_apr": market.borrow_reward_apr,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::IncentiveRunway, &format!("{:?}", rewards.contract)),
+            score_contribution: 0,
+        });
+    }
+
+    /// Check the borrow of tracked positions that cost more gas to absorb than their collateral
+    /// discount pays against `risk.small_positions.max_reserve_fraction` of the market's reserves
+    ///
+    /// In sampled markets the totals are extrapolated and the finding records their margins;
+    /// the histogram covers the positions read.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "absorption", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_absorption_economics(&self, market: &Market, gas: &GasEstimate, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let Some(provider) = &self.provider else { return };
+        if self.accounts.is_empty() {
+            return;
+        }
+        let sample = self.position_sample(market).await;
+        let accounts: Vec<Address> = match &sample {
+            Some(sample) => sample.accounts().iter().copied().filter(|a| self.accounts.contains(a)).collect(),
+            None => self.accounts.clone(),
+        };
+        let positions: Vec<UserPosition> = match provider.get_positions(market, &accounts).await {
+            Ok(results) => results.into_iter().filter_map(|(_, result)| result.ok()).collect(),
+            Err(e) => {
+                warn!("Failed to fetch positions for the absorption check in {}: {}", market.name, e);
+                return;
+            }
+        };
+        let settings = &self.config.risk.small_positions;
+        let gas_cost_usd = gas.absorption_cost_usd;
+        let buckets = absorption::BorrowBucket::histogram(&positions, market, gas_cost_usd);
+        let unprofitable = |p: &UserPosition| {
+            p.total_borrow_value > 0.0 && absorption::estimate(p, market, gas_cost_usd).profit_usd < 0.0
+        };
+        let count = sampling::total(sample.as_deref(), &positions, |p| if unprofitable(p) { 1.0 } else { 0.0 });
+        let borrow = sampling::total(sample.as_deref(), &positions, |p| if unprofitable(p) { p.total_borrow_value } else { 0.0 });
+        let unprofitable = count.value.round() as usize;
+        let unprofitable_borrow_usd = borrow.value;
+        if unprofitable == 0 {
+            return;
+        }
+        let reserves_usd = match provider.get_protocol_metrics(market).await {
+            Ok(protocol) => protocol.reserves,
+            Err(e) => {
+                debug!("No reserves for the absorption check in {}: {}", market.name, e);
+                return;
+            }
+        };
+        let limit = settings.max_reserve_fraction * reserves_usd.max(0.0);
+        if unprofitable_borrow_usd <= limit {
+            return;
+        }
+
+        // Beyond the reserves, the protocol cannot cover the debt if all of it goes bad
+        let severity = if unprofitable_borrow_usd > reserves_usd { RiskSeverity::High } else { RiskSeverity::Medium };
+        let share = if reserves_usd > 0.0 {
+            format!("{:.1}% of reserves", unprofitable_borrow_usd / reserves_usd * 100.0)
+        } else {
+            "with no reserves to cover it".to_string()
+        };
+        let mut description = format!(
+            "{} {} positions cost more gas to liquidate than they pay, borrowing {} ({})",
+            unprofitable,
+            market.name,
+            crate::utils::format_money(unprofitable_borrow_usd, "$"),
+            share,
+        );
+        let mut metadata = serde_json::json!({
+            "unprofitable_positions": unprofitable,
+            "unprofitable_borrow_usd": unprofitable_borrow_usd,
+            "reserves_usd": reserves_usd,
+            "max_reserve_fraction": settings.max_reserve_fraction,
+            "gas_cost_usd": gas_cost_usd,
+            "gas": gas,
+            "base_borrow_min": market.base_borrow_min,
+            "positions_checked": positions.len(),
+            "borrow_buckets": buckets,
+            "confidence": borrow.confidence(),
+        });
+        if let Some(sample) = &sample {
+            description.push_str(&format!(
+                ", estimated from {} of {} borrowers to within {}",
+                sample.report().read,
+                sample.report().borrowers,
+                crate::utils::format_money(borrow.margin, "$"),
+            ));
+            metadata["sampling"] = serde_json::json!(sample.report());
+            metadata["unprofitable_positions_margin"] = serde_json::json!(count.margin);
+            metadata["unprofitable_borrow_margin_usd"] = serde_json::json!(borrow.margin);
+        }
+        findings.push(RiskFinding {
+            category: RiskCategory::BadDebt,
+            severity,
+            description,
+            metadata,
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::BadDebt, "unprofitable-absorption"),
+            score_contribution: 0,
+        });
+    }
+
+    /// Check each collateral's liquidator discount against its daily price move at
+    /// `risk.storefront_margin.daily_move_percentile` plus its `dex_slippage`
+    ///
+    /// Price moves are measured in the base asset, as liquidators pay in it. Missing inputs
+    /// lower the finding's confidence instead of skipping the asset; a Low confidence finding
+    /// is at most Medium. Assets with neither a price history nor a slippage setting have no
+    /// cost to compare against and are skipped.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "liquidator_margin", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_liquidator_margin(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let percentile = self.config.risk.storefront_margin.daily_move_percentile;
+        let base_history = match &self.provider {
+            Some(provider) => base_price_history(provider, market).await.map_err(|e| {
+                debug!("No price history for base asset {}, checking liquidator margins without price moves: {}", market.base_asset.symbol, e);
+            }),
+            None => Ok(None),
+        };
+        let mut assets: Vec<_> = market.collateral_assets.values().collect();
+        assets.sort_by_key(|a| a.address);
+        for asset in assets {
+            let settings = self.config.asset_settings(asset);
+            if settings.ignore {
+                continue;
+            }
+            let daily_move = match (&self.provider, &base_history) {
+                (Some(provider), Ok(base_history)) => match provider.get_price_history(market, asset.address).await {
+                    Ok(history) => in_base(history, base_history.as_ref()).and_then(|history| history.daily_move_percentile(percentile)),
+                    Err(e) => {
+                        debug!("No price history for {}, checking its liquidator margin without it: {}", asset.symbol, e);
+                        None
+                    }
+                },
+                _ => None,
+            };
+            if daily_move.is_none() && settings.dex_slippage.is_none() {
+                continue;
+            }
+
+            let margin = absorption::LiquidatorMargin::new(market, asset, daily_move, settings.dex_slippage);
+            let cost = margin.expected_cost();
+            if margin.margin >= cost {
+                continue;
+            }
+            let confidence = margin.confidence();
+            let severity = if margin.margin < cost / 2.0 && confidence > absorption::Confidence::Low {
+                RiskSeverity::High
+            } else {
+                RiskSeverity::Medium
+            };
+            findings.push(RiskFinding {
+                category: RiskCategory::LiquidationCascade,
+                severity,
+                description: format!(
+                    "Liquidators buy absorbed {} at a {:.2}% discount, less than the {:.2}% a bad day and DEX slippage cost them ({:?} confidence)",
+                    asset.symbol,
+                    margin.margin * 100.0,
+                    cost * 100.0,
+                    confidence,
+                ),
+                metadata: serde_json::json!({
+                    "asset": asset.symbol,
+                    "asset_address": asset.address,
+                    "liquidation_penalty": asset.liquidation_penalty,
+                    "store_front_price_factor": market.store_front_price_factor,
+                    "margin": margin.margin,
+                    "daily_move": margin.daily_move,
+                    "daily_move_percentile": percentile,
+                    "dex_slippage": margin.slippage,
+                    "expected_cost": cost,
+                    "confidence": confidence,
+                    "missing_inputs": margin.missing,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::LiquidationCascade, &format!("liquidator-margin:{:?}", asset.address)),
+                score_contribution: 0,
+            });
+        }
+    }
+
+    /// Check the share of collateral value in assets that are not `Native` (unclassified ones
+    /// included) against `risk.max_bridged_collateral_share`; High beyond twice the share
+    ///
+    /// Ignored assets are left out. Markets whose source reports no collateral totals are skipped.
+    fn check_collateral_provenance(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let _span = tracing::info_span!("risk_check", check = "collateral_provenance", market = %market.name, market_address = ?market.comet_address).entered();
+        let mut assets: Vec<_> = market
+            .collateral_assets
+            .values()
+            .filter(|asset| !self.config.asset_settings(asset).ignore)
+            .map(|asset| (asset, self.config.provenance(asset), asset.total_supplied * asset.price))
+            .collect();
+        let total: f64 = assets.iter().map(|(_, _, value)| value).sum();
+        if total <= 0.0 {
+            return;
+        }
+        assets.retain(|(_, provenance, value)| *provenance != Provenance::Native && *value > 0.0);
+        assets.sort_by(|a, b| b.2.total_cmp(&a.2));
+        let share = assets.iter().map(|(_, _, value)| value).sum::<f64>() / total;
+        let threshold = self.config.risk.max_bridged_collateral_share;
+        if share <= threshold {
+            return;
+        }
+
+        let listed: Vec<String> = assets
+            .iter()
+            .map(|(asset, provenance, value)| format!("{} ({}, {:.1}%)", asset.symbol, provenance.label(), value / total * 100.0))
+            .collect();
+        findings.push(RiskFinding {
+            category: RiskCategory::SmartContractRisk,
+            severity: if share > threshold * 2.0 { RiskSeverity::High } else { RiskSeverity::Medium },
+            description: format!(
+                "{:.1}% of the {} of collateral is bridged, custodial or unclassified, above the {:.1}% limit: {}",
+                share * 100.0,
+                crate::utils::format_compact(total),
+                threshold * 100.0,
+                listed.join(", "),
+            ),
+            metadata: serde_json::json!({
+                "share": share,
+                "threshold": threshold,
+                "total_collateral_usd": total,
+                "assets": assets
+                    .iter()
+                    .map(|(asset, provenance, value)| serde_json::json!({
+                        "asset": asset.symbol,
+                        "address": asset.address,
+                        "provenance": provenance,
+                        "value_usd": value,
+                        "share": value / total,
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::SmartContractRisk, "provenance"),
+            score_contribution: 0,
+        });
+    }
+
+    /// Check the borrow of `position`, the largest tracked borrower, against
+    /// `risk.max_borrower_share` of the market's borrow; High beyond twice the share
+    ///
+    /// One account repaying, or being liquidated, moves that much of the market at once.
+    fn check_borrower_concentration(&self, market: &Market, position: &UserPosition, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let _span = tracing::info_span!("risk_check", check = "borrower_concentration", market = %market.name, market_address = ?market.comet_address).entered();
+        let market_borrow = market.total_borrow * market.base_asset.price;
+        if market_borrow <= 0.0 || !market_borrow.is_finite() {
+            return;
+        }
+        let share = position.total_borrow_value / market_borrow;
+        let threshold = self.config.risk.max_borrower_share;
+        if share <= threshold {
+            return;
+        }
+
+        let severity = if share > threshold * 2.0 { RiskSeverity::High } else { RiskSeverity::Medium };
+        findings.push(RiskFinding {
+            category: RiskCategory::Concentration,
+            severity,
+            description: format!(
+                "{} borrows {:.2}% of {}, above the {:.2}% threshold",
+                format_address_labeled(&position.address),
+                share * 100.0,
+                market.name,
+                threshold * 100.0
+            ),
+            metadata: serde_json::json!({
+                "account": crate::utils::to_checksum_address(&position.address),
+                "borrow_usd": position.total_borrow_value,
+                "market_borrow_usd": market_borrow,
+                "share": share,
+                "threshold": threshold,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::Concentration, "borrower"),
+            score_contribution: 0,
+        });
+    }
+
+    /// Check the collateral the protocol holds from absorptions against
+    /// `risk.protocol_collateral`, returning whether any holdings were read
+    ///
+    /// Holdings worth more than `max_reserve_fraction` of the market's reserves are a High
+    /// finding; holdings of an asset elevated for `max_elevated_snapshots` assessments in a row,
+    /// buyers not taking it, a Medium one. Without reserves only the streaks are checked.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "protocol_collateral", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_protocol_collateral(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) -> bool {
+        let settings = &self.config.risk.protocol_collateral;
+        let holdings = ProtocolHolding::of(market, &self.collateral_history, timestamp, settings.elevated_usd);
+        if holdings.is_empty() {
+            return false;
+        }
+        let held_usd: f64 = holdings.iter().map(|h| h.value_usd).sum();
+        if held_usd <= 0.0 {
+            return true;
+        }
+        let reserves = match &self.provider {
+            Some(provider) => match provider.get_protocol_metrics(market).await {
+                Ok(metrics) => Some(metrics.reserves),
+                Err(e) => {
+                    debug!("No reserves of {}, checking protocol-held collateral without them: {}", market.name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let over_reserves = reserves.is_some_and(|reserves| held_usd > reserves.max(0.0) * settings.max_reserve_fraction);
+        let persistent: Vec<&ProtocolHolding> = holdings.iter().filter(|h| h.elevated_snapshots >= settings.max_elevated_snapshots).collect();
+        let severity = if over_reserves {
+            RiskSeverity::High
+        } else if !persistent.is_empty() {
+            RiskSeverity::Medium
+        } else {
+            return true;
+        };
+
+        let mut description = format!("{} holds {} of absorbed collateral not yet bought back", market.name, crate::utils::format_compact(held_usd));
+        match reserves {
+            Some(reserves) if over_reserves && reserves > 0.0 => {
+                description.push_str(&format!(", {:.0}% of its {} reserves", held_usd / reserves * 100.0, crate::utils::format_compact(reserves)))
+            }
+            Some(reserves) if over_reserves => description.push_str(&format!(", against reserves of {}", crate::utils::format_compact(reserves))),
+            _ => {}
+        }
+        if !persistent.is_empty() {
+            let held: Vec<String> = persistent.iter().map(|h| h.to_string()).collect();
+            description.push_str(&format!("; unsold for {} or more assessments: {}", settings.max_elevated_snapshots, held.join("; ")));
+        }
+        findings.push(RiskFinding {
+            category: RiskCategory::BadDebt,
+            severity,
+            description,
+            metadata: serde_json::json!({
+                "market": market.name,
+                "held_usd": held_usd,
+                "reserves_usd": reserves,
+                "reserve_fraction": reserves.filter(|reserves| *reserves > 0.0).map(|reserves| held_usd / reserves),
+                "max_reserve_fraction": settings.max_reserve_fraction,
+                "elevated_usd": settings.elevated_usd,
+                "max_elevated_snapshots": settings.max_elevated_snapshots,
+                "holdings": holdings,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::BadDebt, "protocol_collateral"),
+            score_contribution: 0,
+        });
+        true
+    }
+
+    /// Check the collateral parameters of `market` against `previous`, those of its previous
+    /// snapshot, for assets delisted, with a lower collateral or liquidation factor, or with a
+    /// supply cap lowered below what is supplied
+    ///
+    /// Severity follows the affected asset's share of the market's collateral value, and the
+    /// metadata lists the tracked `positions` holding most of it.
+    fn check_collateral_changes(
+        &self,
+        market: &Market,
+        previous: &[CollateralParameters],
+        positions: &[UserPosition],
+        findings: &mut Vec<RiskFinding>,
+        timestamp: DateTime<Utc>,
+    ) {
+        let _span = tracing::info_span!("risk_check", check = "collateral_changes", market = %market.name, market_address = ?market.comet_address).entered();
+        let total_collateral_usd: f64 = market.collateral_assets.values().map(|asset| asset.total_supplied * asset.price).sum();
+        for change in CollateralChange::between(previous, market) {
+            let share = if total_collateral_usd > 0.0 { change.collateral_usd / total_collateral_usd } else { 0.0 };
+            let price = market.collateral_assets.get(&change.asset).map_or(0.0, |asset| asset.price);
+            let mut holders: Vec<(&UserPosition, f64)> = positions
+                .iter()
+                .filter_map(|position| position.collateral_balances.get(&change.asset).map(|balance| (position, balance * price)))
+                .filter(|(_, usd)| *usd > 0.0)
+                .collect();
+            holders.sort_by(|a, b| b.1.total_cmp(&a.1));
+            holders.truncate(LARGEST_AFFECTED_POSITIONS);
+            let largest: Vec<serde_json::Value> = holders
+                .iter()
+                .map(|(position, usd)| serde_json::json!({
+                    "address": crate::utils::to_checksum_address(&position.address),
+                    "label": crate::labels::label_for(&position.address),
+                    "collateral_usd": usd,
+                    "borrow_usd": position.total_borrow_value,
+                    "health_factor": position.health_factor,
+                }))
+                .collect();
+
+            let mut metadata = serde_json::to_value(&change).unwrap_or_default();
+            metadata["share_of_collateral"] = serde_json::json!(share);
+            metadata["largest_positions"] = serde_json::json!(largest);
+            findings.push(RiskFinding {
+                category: RiskCategory::LiquidationCascade,
+                severity: collateral_change_severity(share),
+                description: format!("{}: {} ({:.1}% of its collateral value)", market.name, change, share * 100.0),
+                metadata,
+                timestamp,
+                fingerprint: finding_fingerprint(
+                    &market.comet_address,
+                    &RiskCategory::LiquidationCascade,
+                    &format!("collateral_change:{:?}:{}", change.asset, change.kind.name()),
+                ),
+                score_contribution: 0,
+            });
+        }
+    }
+
+    /// Compare the parameters the Configurator stores for `market` with those its deployed
+    /// Comet runs
+    ///
+    /// They differ between a governance change to the Configurator and the `deployAndUpgrade`
+    /// that puts it live, a Medium finding listing every field that differs. Markets whose
+    /// Configurator cannot be read are skipped.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "configuration_drift", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_configuration_drift(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        let Some(provider) = &self.provider else { return };
+        let pair = match provider.get_market_configurations(market).await {
+            Ok(Some(pair)) => pair,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to compare the Configurator's configuration of {}: {}", market.name, e);
+                return;
+            }
+        };
+        let drift = pair.configured.drift(&pair.live);
+        if drift.is_empty() {
+            return;
+        }
+
+        let named = |change: &crate::models::ConfigurationDrift| match change.asset {
+            Some(asset) => {
+                let symbol = market.collateral_assets.get(&asset).map(|a| a.symbol.clone()).unwrap_or_else(|| crate::utils::to_checksum_address(&asset));
+                format!("{}.{}", symbol, change.field)
+            }
+            None => change.field.clone(),
+        };
+        findings.push(RiskFinding {
+            category: RiskCategory::SmartContractRisk,
+            severity: RiskSeverity::Medium,
+            description: format!(
+                "The Configurator holds {} parameter(s) of {} that its deployed Comet does not run yet, pending deployAndUpgrade: {}",
+                drift.len(),
+                market.name,
+                drift.iter().map(|change| format!("{} {} -> {}", named(change), change.live, change.configured)).collect::<Vec<_>>().join(", "),
+            ),
+            metadata: serde_json::json!({
+                "configurator": crate::utils::to_checksum_address(&pair.configurator),
+                "drift": drift,
+            }),
+            timestamp,
+            fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::SmartContractRisk, "configuration_drift"),
+            score_contribution: 0,
+        });
+    }
+
+    /// Addresses of `config.watchlist`, skipping (with a warning) entries that are not addresses
+    fn watchlist_accounts(&self) -> Vec<Address> {
+        self.config
+            .watchlist
+            .iter()
+            .filter_map(|account| match crate::utils::parse_address(account) {
+                Ok(address) => Some(address),
+                Err(e) => {
+                    warn!("Ignoring watchlist entry: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Check the managers each `config.watchlist` account allows in `market` against
+    /// `config.trusted_managers`
+    ///
+    /// A manager can withdraw and transfer the account's assets, so one that is not trusted
+    /// is a Medium finding. Accounts whose permissions cannot be read are skipped.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "manager_permissions", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_manager_permissions(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) -> HashMap<Address, Vec<Address>> {
+        let mut read = HashMap::new();
+        let Some(provider) = &self.provider else { return read };
+        if self.config.watchlist.is_empty() {
+            return read;
+        }
+
+        let trusted = self.config.trusted_manager_addresses();
+        for account in self.watchlist_accounts() {
+            let permissions = match provider.get_manager_permissions(market, account, &trusted).await {
+                Ok(permissions) => permissions,
+                Err(e) => {
+                    warn!("Failed to fetch managers of watchlist account {} in {}: {}", crate::utils::to_checksum_address(&account), market.name, e);
+                    continue;
+                }
+            };
+            read.insert(account, permissions.managers.clone());
+            let untrusted: Vec<Address> = permissions.managers.iter().copied().filter(|m| !trusted.contains(m)).collect();
+            if untrusted.is_empty() {
+                continue;
+            }
+
+            let labeled = |managers: &[Address]| -> Vec<serde_json::Value> {
+                managers
+                    .iter()
+                    .map(|m| serde_json::json!({ "address": crate::utils::to_checksum_address(m), "label": crate::labels::label_for(m) }))
+                    .collect()
+            };
+            findings.push(RiskFinding {
+                category: RiskCategory::AccountPermissions,
+                severity: RiskSeverity::Medium,
+                description: format!(
+                    "Watchlist account {} allows {} manager(s) not on the trusted list to act for it: {}",
+                    format_address_labeled(&account),
+                    untrusted.len(),
+                    untrusted.iter().map(format_address_labeled).collect::<Vec<_>>().join(", "),
+                ),
+                metadata: serde_json::json!({
+                    "account": crate::utils::to_checksum_address(&account),
+                    "market": market.name,
+                    "managers": labeled(&permissions.managers),
+                    "untrusted_managers": labeled(&untrusted),
+                    "nonce": permissions.nonce,
+                    "complete": permissions.complete,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::AccountPermissions, &format!("managers:{:?}", account)),
+                score_contribution: 0,
+            });
+        }
+        read
+    }
+
+    /// Record the watchlist `positions` and `managers` read in `market` in `history`,
+    /// returning the changes since the previous read that reach `risk.position_changes`;
+    /// with `findings` set, each is also a Medium finding
+    fn check_position_changes(
+        &self,
+        history: &PositionHistory,
+        market: &Market,
+        positions: &[UserPosition],
+        managers: &HashMap<Address, Vec<Address>>,
+        findings: &mut Vec<RiskFinding>,
+        timestamp: DateTime<Utc>,
+    ) -> Vec<PositionDelta> {
+        let _span = tracing::info_span!("risk_check", check = "position_changes", market = %market.name, market_address = ?market.comet_address).entered();
+        let settings = &self.config.risk.position_changes;
+        let changes: Vec<PositionDelta> = history
+            .observe(market, positions, managers)
+            .into_iter()
+            .filter(|delta| delta.is_material(settings))
+            .collect();
+        if !settings.findings {
+            return changes;
+        }
+        for delta in &changes {
+            // Allowing a manager alone changes who controls the position, not its risk
+            let category = if delta.borrow_added_usd() > 0.0 || delta.collateral_withdrawn_usd() > 0.0 {
+                RiskCategory::LiquidationCascade
+            } else {
+                RiskCategory::AccountPermissions
+            };
+            findings.push(RiskFinding {
+                category: category.clone(),
+                severity: RiskSeverity::Medium,
+                description: format!("Watchlist position in {} changed since the last assessment: {}", market.name, delta),
+                metadata: serde_json::json!({
+                    "account": crate::utils::to_checksum_address(&delta.account),
+                    "market": market.name,
+                    "change": delta,
+                }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &category, &format!("position_change:{:?}", delta.account)),
+                score_contribution: 0,
+            });
+        }
+        changes
+    }
+
+    /// Check the positions of `config.watchlist` accounts, returning the lowest health factor
+    /// among those that borrow in `market`; positions that fail to read make a `PartialBatch`
+    /// issue. Those read are added to `positions`.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "watchlist", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_watchlist(
+        &self,
+        market: &Market,
+        findings: &mut Vec<RiskFinding>,
+        issues: &mut Vec<DataIssue>,
+        positions: &mut Vec<UserPosition>,
+    ) -> Option<f64> {
+        let provider = self.provider.as_ref()?;
+        if self.config.watchlist.is_empty() {
+            return None;
+        }
+
+        let accounts = self.watchlist_accounts();
+        let results = match provider.get_positions(market, &accounts).await {
+            Ok(results) => results,
+            Err(e) => {
+                warn!("Failed to fetch watchlist positions in {}: {}", market.name, e);
+                issues.push(partial_batch("watchlist", format!("watchlist positions could not be read: {}", e)));
+                return None;
+            }
+        };
+
+        let mut min_health_factor: Option<f64> = None;
+        let (read, mut failed) = (results.len(), 0);
+        for (account, result) in results {
+            let position = match result {
+                Ok(position) => position,
+                Err(RiskEngineError::NotFound { .. }) => continue,
+                Err(e) => {
+                    warn!("Failed to fetch watchlist position {} in {}: {}", crate::utils::to_checksum_address(&account), market.name, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            positions.push(position.clone());
+            if position.total_borrow_value <= 0.0 {
+                continue;
+            }
+            min_health_factor = Some(min_health_factor.map_or(position.health_factor, |m| m.min(position.health_factor)));
+            // Dust still counts towards the lowest health factor, but gets no finding of its own
+            if self.config.risk.below_min_position(&position) {
+                continue;
+            }
+
+            let alert_level = self
+                .config
+                .risk
+                .watchlist_alert_health_factor
+                .filter(|&threshold| position.health_factor < threshold);
+            let finding = match (self.check_user_liquidation_risk(market.comet_address, &position), alert_level) {
+                (Some(mut finding), Some(_)) => {
+                    finding.severity = finding.severity.max(RiskSeverity::High);
+                    Some(finding)
+                }
+                (None, Some(_)) => Some(self.liquidation_finding(market.comet_address, &position, RiskSeverity::High)),
+                (finding, None) => finding,
+            };
+
+            if let Some(mut finding) = finding {
+                let subject = format!("{:?}", account);
+                finding.fingerprint = finding_fingerprint(&market.comet_address, &finding.category, &subject);
+                finding.metadata["account"] = serde_json::json!(crate::utils::to_checksum_address(&account));
+                finding.metadata["market"] = serde_json::json!(market.name);
+                if let Some(threshold) = alert_level {
+                    finding.metadata["alert_health_factor"] = serde_json::json!(threshold);
+                }
+                if let Some((asset, _)) = position.dominant_collateral(market) {
+                    finding.metadata["dominant_collateral"] = serde_json::json!(asset.symbol);
+                    finding.metadata["liquidation_price"] = serde_json::json!(position.liquidation_price(asset));
+                }
+                // Not yet urgent: how long until interest makes it so (`null` for never)
+                if finding.severity == RiskSeverity::Medium {
+                    let projection = projection::project(&position, market, &self.config.risk.projection);
+                    finding.metadata["time_to_liquidation_days"] = serde_json::json!(projection.days_at_static_prices);
+                    finding.metadata["time_to_liquidation_days_with_price_drift"] = serde_json::json!(projection.days_with_price_drift);
+                }
+                findings.push(finding);
+            }
+        }
+        if failed > 0 {
+            issues.push(partial_batch(
+                "watchlist",
+                format!("{} of {} watchlist positions could not be read; the lowest health factor leaves them out", failed, read),
+            ));
+        }
+        min_health_factor
+    }
+
+    /// Check collateral assets whose 30d volatility exceeds their `max_price_volatility`
+    ///
+    /// Needs a data provider; ignored assets and assets without available history are skipped.
+    /// Where the base asset is not a USD stablecoin (the WETH Comets), volatility is measured
+    /// against the base asset's price, so an ETH move alone flags nothing; without a history of
+    /// the base asset the check is skipped. Returns the highest volatility among the assets
+    /// checked, for the market's baseline.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "price_volatility", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_price_volatility(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) -> Option<f64> {
+        let provider = self.provider.as_ref()?;
+        let base = &market.base_asset;
+        let base_history = match base_price_history(provider, market).await {
+            Ok(history) => history,
+            Err(e) => {
+                debug!("No price history for base asset {} to measure collateral against, skipping volatility check: {}", base.symbol, e);
+                return None;
+            }
+        };
+        let mut highest: Option<f64> = None;
+        let mut assets: Vec<_> = market.collateral_assets.values().collect();
+        assets.sort_by_key(|a| a.address);
+        for asset in assets {
+            let settings = self.config.asset_settings(asset);
+            if settings.ignore {
+                continue;
+            }
+            let threshold = settings.max_price_volatility.unwrap_or(self.config.risk.max_price_volatility);
+            let history = match provider.get_price_history(market, asset.address).await {
+                Ok(history) => history,
+                Err(RiskEngineError::NotFound { .. }) => {
+                    debug!("No price history for {}, skipping volatility check", asset.symbol);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to fetch price history for {}: {}", asset.symbol, e);
+                    continue;
+                }
+            };
+            let Some(history) = in_base(history, base_history.as_ref()) else {
+                debug!("Price histories of {} and {} share fewer than two dates, skipping volatility check", asset.symbol, base.symbol);
+                continue;
+            };
+            let against = if base_history.is_some() { format!(" against {}", base.symbol) } else { String::new() };
+
+            let volatility = history.volatility_30d;
+            if volatility.is_finite() {
+                highest = Some(highest.map_or(volatility, |h| h.max(volatility)));
+            }
+            if volatility <= threshold {
+                continue;
+            }
+
+            let severity = if volatility >= threshold * 2.0 {
                 RiskSeverity::High
             } else {
                 RiskSeverity::Medium
             };
-            
-            let description = format!(
-                "Market utilization is {:.2}%, which exceeds the recommended threshold of {:.2}%",
-                utilization * 100.0,
-                threshold * 100.0
-            );
-            
-            let metadata = serde_json::json!({
-                "current_utilization": utilization,
-                "threshold": threshold,
-                "base_asset": market.base_asset.symbol,
-                "total_supply": market.total_supply,
-                "total_borrow": market.total_borrow,
-            });
-            
+
             findings.push(RiskFinding {
-                category: RiskCategory::HighUtilization,
+                category: RiskCategory::PriceVolatility,
                 severity,
-                description,
-                metadata,
+                description: format!(
+                    "{} 30-day volatility{} is {:.2}%, above the {:.2}% threshold",
+                    asset.symbol,
+                    against,
+                    volatility * 100.0,
+                    threshold * 100.0
+                ),
+                metadata: serde_json::json!({
+                    "asset": asset.symbol,
+                    "asset_address": asset.address,
+                    "volatility_30d": volatility,
+                    "threshold": threshold,
+                    "measured_against": if base_history.is_some() { base.symbol.as_str() } else { "USD" },
+                    "price_change_24h": history.price_change_24h,
+                    "price_change_7d": history.price_change_7d,
+                }),
                 timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::PriceVolatility, &format!("{:?}", asset.address)),
+                score_contribution: 0,
             });
         }
+        highest
     }
     
-    /// Calculate risk score from findings (0-100, higher is riskier)
-    fn calculate_risk_score(&self, findings: &[RiskFinding]) -> u8 {
-        if findings.is_empty() {
-            return 0;
+    /// Warn about `assets` entries that match no asset of any market, since they silently do nothing
+    ///
+    /// With a data provider entries are matched against all of its markets, otherwise against `market`.
+    #[instrument(level = "info", name = "risk_check", skip_all, fields(check = "asset_settings", market = %market.name, market_address = ?market.comet_address))]
+    async fn check_asset_settings(&self, market: &Market, findings: &mut Vec<RiskFinding>, timestamp: DateTime<Utc>) {
+        if self.config.assets.is_empty() {
+            return;
+        }
+        let markets = match &self.provider {
+            Some(provider) => match provider.get_markets().await {
+                Ok(markets) => markets,
+                Err(e) => {
+                    warn!("Failed to fetch markets to match asset settings against: {}", e);
+                    return;
+                }
+            },
+            None => vec![market.clone()],
+        };
+
+        for key in self.config.unmatched_assets(&markets) {
+            findings.push(RiskFinding {
+                category: RiskCategory::Configuration,
+                severity: RiskSeverity::Low,
+                description: format!("Asset settings for `{}` match no asset in any market and have no effect", key),
+                metadata: serde_json::json!({ "setting": format!("assets.{}", key) }),
+                timestamp,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::Configuration, &format!("assets.{}", key)),
+                score_contribution: 0,
+            });
         }
-        
-        // Calculate score based on severity and number of findings
-        let base_score = findings.iter().map(|f| match f.severity {
-            RiskSeverity::Low => 5,
-            RiskSeverity::Medium => 15,
-            RiskSeverity::High => 30,
-            RiskSeverity::Critical => 50,
-        }).sum::<u8>();
-        
-        // Cap at 100
-        base_score.min(100)
     }
-    
+
     /// Simulate market conditions with various parameters
     /// This is a placeholder for milestone 1, will be expanded in milestone 2
     pub async fn simulate_market_conditions(&self, market: &Market) -> Result<Vec<RiskFinding>> {
@@ -177,7 +2369,7 @@ impl RiskProcessor {
         let now = Utc::now();
         
         // Simulate increasing utilization by 10%
-        let simulated_utilization = market.utilization_rate + 0.1;
+        let simulated_utilization = market.utilization() + 0.1;
         if simulated_utilization > self.config.risk.max_utilization_threshold {
             let description = format!(
                 "Simulated 10% increase in utilization would result in {:.2}% utilization, exceeding threshold",
@@ -190,18 +2382,20 @@ impl RiskProcessor {
                 description,
                 metadata: serde_json::json!({
                     "simulated_utilization": simulated_utilization,
-                    "current_utilization": market.utilization_rate,
+                    "current_utilization": market.utilization(),
                     "threshold": self.config.risk.max_utilization_threshold,
                 }),
                 timestamp: now,
+                fingerprint: finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, "simulated-utilization"),
+                score_contribution: 0,
             });
         }
         
         Ok(findings)
     }
     
-    /// Check if a user's position is at risk of liquidation
-    pub fn check_user_liquidation_risk(&self, user: &UserPosition) -> Option<RiskFinding> {
+    /// Check if a user's position in the market with Comet proxy `market` is at risk of liquidation
+    pub fn check_user_liquidation_risk(&self, market: Address, user: &UserPosition) -> Option<RiskFinding> {
         // If user has no borrow, they can't be liquidated
         if user.total_borrow_value <= 0.0 {
             return None;
@@ -219,34 +2413,43 @@ impl RiskProcessor {
                 RiskSeverity::Medium
             };
             
-            let description = format!(
-                "User position has a health factor of {:.2}, which is close to or below the liquidation threshold",
-                user.health_factor
-            );
-            
-            return Some(RiskFinding {
-                category: RiskCategory::LiquidationCascade,
-                severity,
-                description,
-                metadata: serde_json::json!({
-                    "health_factor": user.health_factor,
-                    "buffer": buffer,
-                    "collateral_value": user.total_collateral_value,
-                    "borrow_value": user.total_borrow_value,
-                }),
-                timestamp: Utc::now(),
-            });
+            return Some(self.liquidation_finding(market, user, severity));
         }
         
         None
     }
 
+    fn liquidation_finding(&self, market: Address, user: &UserPosition, severity: RiskSeverity) -> RiskFinding {
+        let description = format!(
+            "Position of {} has a health factor of {:.2}, which is close to or below the liquidation threshold",
+            format_address_labeled(&user.address),
+            user.health_factor
+        );
+        
+        RiskFinding {
+            category: RiskCategory::LiquidationCascade,
+            severity,
+            description,
+            metadata: serde_json::json!({
+                "health_factor": user.health_factor,
+                "buffer": self.config.risk.liquidation_threshold_buffer,
+                "collateral_value": user.total_collateral_value,
+                "borrow_value": user.total_borrow_value,
+            }),
+            timestamp: Utc::now(),
+            fingerprint: finding_fingerprint(&market, &RiskCategory::LiquidationCascade, &format!("{:?}", user.address)),
+            score_contribution: 0,
+        }
+    }
+
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{Asset, AssetType};
+    use crate::models::{Asset, AssetType, RewardsFunding};
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::testing::{assessment, finding};
     use ethers::types::U256;
     use std::collections::HashMap;
     use std::str::FromStr;
@@ -263,6 +2466,9 @@ mod tests {
             liquidation_penalty: 0.0,
             supply_cap: U256::from(0),
             borrow_cap: U256::from(0),
+            provenance: Provenance::Native,
+            total_supplied: 0.0,
+            protocol_held: None,
         };
         
         Market {
@@ -279,9 +2485,434 @@ mod tests {
             base_tracking_borrow_speed: U256::from(0),
             base_min_interest_rate: U256::from(0),
             base_max_interest_rate: U256::from(0),
+            base_borrow_min: 100.0,
+            store_front_price_factor: Some(0.5),
+            supply_reward_apr: 0.0,
+            borrow_reward_apr: 0.0,
+            net_supply_apr: 0.05,
+            net_borrow_apr: 0.08,
+            rewards: None,
+            block_number: None,
+            data_issues: Vec::new(),
         }
     }
     
+    #[test]
+    fn test_bridged_and_unclassified_collateral_share_is_flagged() {
+        use crate::config::AssetConfig;
+
+        let collateral = |n: u8, symbol: &str, provenance: Provenance, value: f64| Asset {
+            address: Address::repeat_byte(n),
+            symbol: symbol.to_string(),
+            decimals: 18,
+            price: 1.0,
+            asset_type: AssetType::Collateral,
+            collateral_factor: 0.8,
+            liquidation_factor: 0.85,
+            liquidation_penalty: 0.05,
+            supply_cap: U256::zero(),
+            borrow_cap: U256::zero(),
+            provenance,
+            total_supplied: value,
+            protocol_held: None,
+        };
+        let mut market = create_test_market();
+        for asset in [
+            collateral(1, "WETH", Provenance::Native, 600.0),
+            collateral(2, "WBTC", Provenance::WrappedCustodial, 250.0),
+            collateral(3, "FOO", Provenance::Unclassified, 150.0),
+        ] {
+            market.collateral_assets.insert(asset.address, asset);
+        }
+        let check = |config: Config| {
+            let mut findings = Vec::new();
+            RiskProcessor::new(Arc::new(config)).check_collateral_provenance(&market, &mut findings, Utc::now());
+            findings
+        };
+
+        // 40% of the collateral is not native, over the default 30%
+        let findings = check(Config::default());
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, RiskSeverity::Medium);
+        assert!((findings[0].metadata["share"].as_f64().unwrap() - 0.4).abs() < 1e-9);
+        let listed: Vec<(&str, &str)> = findings[0].metadata["assets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| (a["asset"].as_str().unwrap(), a["provenance"].as_str().unwrap()))
+            .collect();
+        assert_eq!(listed, [("WBTC", "wrapped_custodial"), ("FOO", "unclassified")]);
+        assert!(findings[0].description.contains("WBTC (wrapped_custodial, 25.0%)"), "{}", findings[0].description);
+
+        // Classifying the unknown token as native brings the share down to 25%
+        let mut config = Config::default();
+        config.assets.insert("FOO".to_string(), AssetConfig { provenance: Some(Provenance::Native), ..AssetConfig::default() });
+        assert!(check(config).is_empty());
+
+        let mut config = Config::default();
+        config.risk.max_bridged_collateral_share = 0.15;
+        assert_eq!(check(config)[0].severity, RiskSeverity::High);
+    }
+
+    #[test]
+    fn test_collateral_changes_are_flagged_by_the_value_they_affect() {
+        let collateral = |n: u8, symbol: &str, price: f64, supplied: f64| Asset {
+            address: Address::repeat_byte(n),
+            symbol: symbol.to_string(),
+            decimals: 18,
+            price,
+            asset_type: AssetType::Collateral,
+            collateral_factor: 0.8,
+            liquidation_factor: 0.85,
+            liquidation_penalty: 0.05,
+            supply_cap: U256::from(1_000_000) * U256::exp10(18),
+            borrow_cap: U256::zero(),
+            provenance: Provenance::Native,
+            total_supplied: supplied,
+            protocol_held: None,
+        };
+        let mut market = create_test_market();
+        // $60M of WETH, $38M of WBTC and $2M of UNI
+        for asset in [collateral(1, "WETH", 2_000.0, 30_000.0), collateral(2, "WBTC", 38_000.0, 1_000.0), collateral(3, "UNI", 10.0, 200_000.0)] {
+            market.collateral_assets.insert(asset.address, asset);
+        }
+        let previous = CollateralParameters::of(&market);
+        let holder = |n: u64, weth: f64| UserPosition {
+            address: Address::from_low_u64_be(n),
+            base_balance: -1_000.0,
+            collateral_balances: HashMap::from([(Address::repeat_byte(1), weth)]),
+            total_collateral_value: weth * 2_000.0,
+            total_borrow_value: 1_000.0,
+            health_factor: 1.5,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        let positions: Vec<UserPosition> = (1..=7).map(|n| holder(n, n as f64 * 10.0)).chain([holder(8, 0.0)]).collect();
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let check = |market: &Market| {
+            let mut findings = Vec::new();
+            processor.check_collateral_changes(market, &previous, &positions, &mut findings, Utc::now());
+            findings
+        };
+        assert!(check(&market).is_empty());
+
+        // WETH delisted, and UNI's liquidate collateral factor cut
+        let mut changed = market.clone();
+        changed.collateral_assets.get_mut(&Address::repeat_byte(1)).unwrap().collateral_factor = 0.0;
+        changed.collateral_assets.get_mut(&Address::repeat_byte(3)).unwrap().liquidation_factor = 0.7;
+        let findings = check(&changed);
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        let delisted = findings.iter().find(|f| f.metadata["kind"] == "delisted").unwrap();
+        assert_eq!((delisted.category.clone(), delisted.severity), (RiskCategory::LiquidationCascade, RiskSeverity::Critical));
+        assert_eq!(delisted.description, "USDC: WETH delisted (collateral factor 0.800 → 0), $60.0M supplied (60.0% of its collateral value)");
+        // The five largest holders, largest first
+        let largest: Vec<f64> = delisted.metadata["largest_positions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["collateral_usd"].as_f64().unwrap())
+            .collect();
+        assert_eq!(largest, [140_000.0, 120_000.0, 100_000.0, 80_000.0, 60_000.0]);
+        let uni = findings.iter().find(|f| f.metadata["kind"] == "liquidate_collateral_factor_reduced").unwrap();
+        assert_eq!(uni.severity, RiskSeverity::Medium);
+        assert!(uni.metadata["largest_positions"].as_array().unwrap().is_empty());
+        assert_ne!(uni.fingerprint, delisted.fingerprint);
+
+        // A WBTC cap below what is supplied
+        let mut capped = market.clone();
+        capped.collateral_assets.get_mut(&Address::repeat_byte(2)).unwrap().supply_cap = U256::from(500) * U256::exp10(18);
+        let findings = check(&capped);
+        assert_eq!(findings[0].metadata["kind"], "supply_cap_below_usage");
+        assert_eq!(findings[0].severity, RiskSeverity::Critical);
+        assert_eq!(collateral_change_severity(0.15), RiskSeverity::High);
+        assert_eq!(collateral_change_severity(0.01), RiskSeverity::Low);
+    }
+
+    #[test]
+    fn test_rewards_runway_below_minimum_is_flagged() {
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let mut market = create_test_market();
+        market.rewards = Some(RewardsFunding {
+            contract: Address::repeat_byte(0x1b),
+            token: Address::repeat_byte(0xc0),
+            token_price: 50.0,
+            balance: 2_000.0,
+            daily_emission: 100.0,
+        });
+
+        let mut findings = Vec::new();
+        processor.check_rewards_runway(&market, &mut findings, Utc::now());
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].category.clone(), findings[0].severity), (RiskCategory::IncentiveRunway, RiskSeverity::Medium));
+        assert_eq!(findings[0].metadata["runway_days"], 20.0);
+
+        // A quarter of the minimum or less is High; 60 days is enough
+        for (balance, expected) in [(500.0, Some(RiskSeverity::High)), (6_000.0, None)] {
+            market.rewards.as_mut().unwrap().balance = balance;
+            let mut findings = Vec::new();
+            processor.check_rewards_runway(&market, &mut findings, Utc::now());
+            assert_eq!(findings.first().map(|f| f.severity), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unprofitable_absorptions_are_weighed_against_reserves() {
+        use crate::config::AbsorptionConfig;
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let accounts = vec![Address::repeat_byte(0x11), Address::repeat_byte(0x22)];
+        let provider: SharedProvider = Arc::new(fixture);
+        let processor = |gas_cost_usd: f64, max_reserve_fraction: f64| {
+            let mut config = Config::default();
+            config.risk.small_positions = AbsorptionConfig { gas_cost_usd, max_reserve_fraction };
+            RiskProcessor::with_provider(Arc::new(config), provider.clone()).with_accounts(accounts.clone())
+        };
+        let check = |processor: RiskProcessor, market: Market| async move {
+            let mut findings = Vec::new();
+            let gas = processor.gas_estimate(&market).await;
+            processor.check_absorption_economics(&market, &gas, &mut findings, Utc::now()).await;
+            findings
+        };
+
+        // Both fixture borrowers hold 1 WETH, a $100 discount: not worth $150 of gas
+        let findings = check(processor(150.0, 0.00001), market.clone()).await;
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].category.clone(), findings[0].severity), (RiskCategory::BadDebt, RiskSeverity::Medium));
+        assert_eq!(findings[0].metadata["unprofitable_positions"], 2);
+        assert_eq!(findings[0].metadata["unprofitable_borrow_usd"], 2750.0);
+        assert_eq!(findings[0].metadata["borrow_buckets"][4]["unprofitable"], 2);
+
+        // Within the reserve fraction, or profitable at cheaper gas: no finding
+        for (gas_cost_usd, max_reserve_fraction) in [(150.0, 0.1), (25.0, 0.0)] {
+            let findings = check(processor(gas_cost_usd, max_reserve_fraction), market.clone()).await;
+            assert!(findings.is_empty(), "{:?}", findings);
+        }
+
+        // With the chain's fees the configured cost no longer counts: 300,000 gas at 200 gwei
+        // and WETH at $2000 is $120, more than the discount
+        let mut data = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().clone();
+        data.gas = Some(GasQuote { base_fee_gwei: 190.0, priority_fee_gwei: 10.0, l1_data_fee: None, source: crate::gas::GasSource::Fixture });
+        let spiking: SharedProvider = Arc::new(FixtureProvider::new(data));
+        let mut config = Config::default();
+        config.risk.small_positions.max_reserve_fraction = 0.00001;
+        let live = |config: Config| RiskProcessor::with_provider(Arc::new(config), spiking.clone()).with_accounts(accounts.clone());
+        let findings = check(live(config.clone()), market.clone()).await;
+        assert_eq!(findings.len(), 1);
+        assert!((findings[0].metadata["gas_cost_usd"].as_f64().unwrap() - 120.0).abs() < 1e-9);
+        assert_eq!(findings[0].metadata["gas"]["base_fee_gwei"], 190.0);
+
+        // Pinned at 10 gwei for a reproducible simulation: $6, worth absorbing
+        config.risk.gas.pinned_gwei = Some(10.0);
+        assert!(check(live(config), market).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_protocol_held_collateral_is_flagged_against_reserves_and_over_time() {
+        let provider = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut market = provider.data().markets[0].clone();
+        let weth = *market.collateral_assets.keys().next().unwrap();
+        let processor = RiskProcessor::with_provider(Arc::new(Config::default()), Arc::new(provider));
+        let held = |market: &mut Market, amount: f64| market.collateral_assets.get_mut(&weth).unwrap().protocol_held = Some(amount);
+        async fn check(processor: &RiskProcessor, market: Market) -> (bool, Vec<RiskFinding>) {
+            let mut findings = Vec::new();
+            let read = processor.check_protocol_collateral(&market, &mut findings, Utc::now()).await;
+            (read, findings)
+        }
+
+        // 7,000 WETH at $2,000 is 56% of the $25M reserves, over half of them
+        held(&mut market, 7_000.0);
+        let (read, findings) = check(&processor, market.clone()).await;
+        assert!(read);
+        assert_eq!((findings[0].category.clone(), findings[0].severity), (RiskCategory::BadDebt, RiskSeverity::High));
+        assert!(findings[0].description.contains("holds $14.0M of absorbed collateral not yet bought back, 56% of its $25.0M reserves"), "{}", findings[0].description);
+        assert_eq!(findings[0].metadata["holdings"][0]["elevated_snapshots"], 1);
+
+        // 100 WETH is well within reserves, until it has sat unsold for three assessments
+        held(&mut market, 100.0);
+        assert!(check(&processor, market.clone()).await.1.is_empty());
+        let earlier = |hours: i64, amount: f64| {
+            let mut collateral = CollateralParameters::of(&market);
+            collateral[0].protocol_held = Some(amount);
+            (Utc::now() - chrono::Duration::hours(hours), collateral)
+        };
+        let history = vec![earlier(2, 100.0), earlier(1, 80.0)];
+        let since = history[0].0;
+        let processor = processor.with_collateral_history(history);
+        let (_, findings) = check(&processor, market.clone()).await;
+        assert_eq!(findings[0].severity, RiskSeverity::Medium);
+        assert!(findings[0].description.contains("unsold for 3 or more assessments: 100.00 WETH ($200K), held since"), "{}", findings[0].description);
+        assert_eq!(findings[0].metadata["holdings"][0]["elevated_since"], serde_json::json!(since));
+
+        // Without holdings read there is nothing to evaluate
+        market.collateral_assets.get_mut(&weth).unwrap().protocol_held = None;
+        let (read, findings) = check(&processor, market).await;
+        assert!(!read && findings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_thin_liquidator_margin_is_flagged_with_its_confidence() {
+        use crate::config::AssetConfig;
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut market = fixture.get_markets().await.unwrap().remove(0);
+        let provider: SharedProvider = Arc::new(fixture);
+        let processor = |dex_slippage: Option<f64>| {
+            let mut config = Config::default();
+            config.assets.insert("WETH".to_string(), AssetConfig { dex_slippage, ..AssetConfig::default() });
+            RiskProcessor::with_provider(Arc::new(config), provider.clone())
+        };
+        let check = |processor: RiskProcessor, market: Market| async move {
+            let mut findings = Vec::new();
+            processor.check_liquidator_margin(&market, &mut findings, Utc::now()).await;
+            findings
+        };
+
+        // Weekly WETH moves of up to 4.76%, 1.80% a day; the whole 5% penalty covers that
+        assert!(check(processor(None), market.clone()).await.is_empty());
+
+        // Half of it passed on leaves 2.5% against 1.80% plus 2% slippage
+        market.store_front_price_factor = Some(0.5);
+        let findings = check(processor(Some(0.02)), market.clone()).await;
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, RiskSeverity::Medium);
+        assert_eq!(findings[0].metadata["confidence"], "high");
+        let daily_move = findings[0].metadata["daily_move"].as_f64().unwrap();
+        assert!((daily_move - (1.0 - 2000.0 / 2100.0) / 7f64.sqrt()).abs() < 1e-9, "{}", daily_move);
+
+        // Without the store-front factor the margin is an upper bound: still short, but uncertain
+        market.store_front_price_factor = None;
+        let findings = check(processor(Some(0.12)), market).await;
+        assert_eq!(findings[0].severity, RiskSeverity::High);
+        assert_eq!(findings[0].metadata["missing_inputs"], serde_json::json!(["store_front_price_factor"]));
+    }
+
+    #[tokio::test]
+    async fn test_weth_market_moves_with_eth_without_findings() {
+        use crate::provider::MarketDataProvider;
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("weth.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let borrower = Address::repeat_byte(0x11);
+        let accounts = vec![borrower, Address::repeat_byte(0x22)];
+
+        // 15 wstETH at 1.17 ETH, 93% of it counted at liquidation, against 10 WETH
+        let position = fixture.get_user_position(&market, borrower).await.unwrap();
+        let health_factor = market.health_factor(position.base_balance, &position.collateral_balances);
+        assert!((health_factor - 15.0 * 1.17 * 0.93 / 10.0).abs() < 1e-9, "{}", health_factor);
+        assert!((health_factor - position.health_factor).abs() < 1e-9);
+        // The 0.9 borrow collateral factor only sets how much more it may borrow
+        let borrow_limit_eth = market.borrow_limit(&position.collateral_balances) / market.base_asset.price;
+        assert!((borrow_limit_eth - 15.0 * 1.17 * 0.9).abs() < 1e-9, "{}", borrow_limit_eth);
+
+        // ETH swings 12% a day and every collateral with it: nothing moves against WETH
+        let mut data = fixture.data().clone();
+        let provider: SharedProvider = Arc::new(fixture);
+        let processor = RiskProcessor::with_provider(Arc::new(Config::default()), provider).with_accounts(accounts.clone());
+        let assessment = processor.assess_market(&market).await.unwrap();
+        assert!(assessment.findings.is_empty(), "{:?}", assessment.findings);
+        let volatility = assessment.price_volatility.unwrap();
+        assert!(volatility < 0.001, "{}", volatility);
+
+        // wstETH sliding 3% a day against ETH is flagged, measured against WETH
+        let wsteth = data.price_history.iter_mut().find(|h| h.symbol == "wstETH").unwrap();
+        for (day, (_, price)) in wsteth.price_points.iter_mut().enumerate() {
+            *price *= 0.97f64.powi(day as i32) * if day % 2 == 0 { 1.1 } else { 0.9 };
+        }
+        let provider: SharedProvider = Arc::new(FixtureProvider::new(data));
+        let processor = RiskProcessor::with_provider(Arc::new(Config::default()), provider).with_accounts(accounts);
+        let findings: Vec<RiskFinding> = processor
+            .assess_market(&market)
+            .await
+            .unwrap()
+            .findings
+            .into_iter()
+            .filter(|f| f.category == RiskCategory::PriceVolatility)
+            .collect();
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].description.starts_with("wstETH 30-day volatility against WETH is "), "{}", findings[0].description);
+        assert_eq!(findings[0].metadata["measured_against"], "WETH");
+    }
+
+    #[tokio::test]
+    async fn test_degraded_data_is_reported_apart_from_market_risk() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut data = fixture.data().clone();
+        let weth = Address::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        let unreadable = Address::from_str("0x2222222222222222222222222222222222222222").unwrap();
+        let market = &mut data.markets[0];
+        market.collateral_assets.get_mut(&weth).unwrap().price = 0.0;
+        market.base_asset.decimals = 255;
+        // As the subgraph provider annotates its markets when it lags
+        market.data_issues.push(DataIssue::new(DataIssueKind::StaleSource, "subgraph", "subgraph is 1200 blocks behind"));
+        data.unreadable_accounts.push(unreadable);
+        let provider: SharedProvider = Arc::new(FixtureProvider::new(data));
+        let market = provider.get_markets().await.unwrap().remove(0);
+
+        let config = Config { watchlist: vec![format!("{:?}", unreadable)], ..Config::default() };
+        let processor = RiskProcessor::with_provider(Arc::new(config), provider);
+        let assessment = processor.assess_market(&market).await.unwrap();
+
+        let kinds: Vec<(&str, DataIssueKind)> = assessment.data_issues.iter().map(|i| (i.check.as_str(), i.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("subgraph", DataIssueKind::StaleSource),
+                ("decimals:USDC", DataIssueKind::DecodingAnomaly),
+                ("price:WETH", DataIssueKind::MissingPrice),
+                ("watchlist", DataIssueKind::PartialBatch),
+                ("positions", DataIssueKind::PartialBatch),
+            ]
+        );
+        assert_eq!(assessment.data_degradation().unwrap(), "subgraph, decimals:USDC, price:WETH, watchlist, positions");
+
+        let (quality, risks): (Vec<&RiskFinding>, Vec<&RiskFinding>) =
+            assessment.findings.iter().partition(|f| f.category == RiskCategory::DataQuality);
+        let severities: Vec<RiskSeverity> = quality.iter().map(|f| f.severity).collect();
+        use RiskSeverity::{Low, Medium};
+        assert_eq!(severities, [Medium, Medium, Medium, Low, Low]);
+        assert!(quality.iter().all(|f| f.score_contribution == 0 && f.metadata.get("confidence").is_none()));
+        // The market is still assessed, at lower confidence
+        assert!(risks.iter().any(|f| f.category == RiskCategory::HighUtilization));
+        assert!(risks.iter().all(|f| f.metadata["confidence"] == "low" && f.metadata["data_degraded"] == true));
+        assert_eq!(usize::from(assessment.risk_score), risks.iter().map(|f| usize::from(f.score_contribution)).sum::<usize>());
+    }
+
+    #[test]
+    fn test_fingerprints_separate_category_and_subject_and_markets() {
+        let market = Address::repeat_byte(0xc3);
+        // Pinned: fingerprints are persisted and sent as dedup keys, so they must not drift
+        assert_eq!(finding_fingerprint(&market, &RiskCategory::HighUtilization, "market"), "b9c1eb5ee75471c0");
+        assert_eq!(RiskCategory::LiquidationCascade.fingerprint_key(), "liquidation_cascade");
+        let keys: std::collections::BTreeSet<&str> = RiskCategory::ALL.iter().map(RiskCategory::fingerprint_key).collect();
+        assert_eq!(keys.len(), RiskCategory::ALL.len());
+        // "bad_debt" + "x" and "bad_deb" + "tx" would hash the same bytes without a separator
+        assert_ne!(
+            finding_fingerprint(&market, &RiskCategory::BadDebt, "x"),
+            finding_fingerprint(&market, &RiskCategory::BadDebt, "\0x"),
+        );
+
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let position = UserPosition {
+            address: Address::repeat_byte(0x11),
+            base_balance: -1_000.0,
+            collateral_balances: Default::default(),
+            total_collateral_value: 1_000.0,
+            total_borrow_value: 1_000.0,
+            health_factor: 0.98,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        let usdc = processor.check_user_liquidation_risk(market, &position).unwrap();
+        let weth = processor.check_user_liquidation_risk(Address::repeat_byte(0xa1), &position).unwrap();
+        assert_ne!(usdc.fingerprint, weth.fingerprint);
+        assert_eq!(usdc.fingerprint, finding_fingerprint(&market, &RiskCategory::LiquidationCascade, &format!("{:?}", position.address)));
+    }
+
     #[test]
     fn test_check_utilization() {
         let config = Arc::new(Config::default());
@@ -297,30 +2928,584 @@ mod tests {
         assert_eq!(findings[0].category, RiskCategory::HighUtilization);
         assert_eq!(findings[0].severity, RiskSeverity::High);
     }
+
+    #[test]
+    fn test_utilization_bands_include_their_lower_bound() {
+        // Against the default 85% threshold: Medium above it, High from 90%, Critical from 95%
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let severity = |total_borrow: f64| {
+            let market = Market { total_borrow, ..create_test_market() };
+            let mut findings = Vec::new();
+            processor.check_utilization(&market, &mut findings, Utc::now());
+            findings.first().map(|f| f.severity)
+        };
+        assert_eq!(severity(850_000_000.0), None);
+        assert_eq!(severity(850_100_000.0), Some(RiskSeverity::Medium));
+        assert_eq!(severity(899_900_000.0), Some(RiskSeverity::Medium));
+        assert_eq!(severity(900_000_000.0), Some(RiskSeverity::High));
+        assert_eq!(severity(949_900_000.0), Some(RiskSeverity::High));
+        assert_eq!(severity(950_000_000.0), Some(RiskSeverity::Critical));
+    }
     
     #[test]
-    fn test_calculate_risk_score() {
+    fn test_planned_withdrawals_beyond_available_liquidity_are_flagged() {
+        use crate::config::PlannedWithdrawal;
+
+        // 100M USDC available: the first fits, the second does not, the third is elsewhere
+        let withdrawal = |account: &str, market: Option<&str>, amount: f64| PlannedWithdrawal {
+            account: account.to_string(),
+            market: market.map(str::to_string),
+            amount,
+        };
+        let mut config = Config::default();
+        config.risk.planned_withdrawals = vec![
+            withdrawal("0x1111111111111111111111111111111111111111", None, 50_000_000.0),
+            withdrawal("0x2222222222222222222222222222222222222222", Some("0xc3d688B66703497DAA19211EEdff47f25384cdc3"), 150_000_000.0),
+            withdrawal("0x3333333333333333333333333333333333333333", Some("0xA17581A9E3356d9A858b789D68B4d866e593aE94"), 150_000_000.0),
+        ];
+        let processor = RiskProcessor::new(Arc::new(config));
+        let market = create_test_market();
+
+        let mut findings = Vec::new();
+        processor.check_planned_withdrawals(&market, &mut findings, Utc::now());
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].category, RiskCategory::HighUtilization);
+        assert_eq!(findings[0].severity, RiskSeverity::High);
+        assert_eq!(findings[0].metadata["account"], "0x2222222222222222222222222222222222222222");
+        assert_eq!(findings[0].metadata["shortfall"], 50_000_000.0);
+        assert!(findings[0].description.contains("exceeds the 100M USDC the market can pay out"), "{}", findings[0].description);
+    }
+
+    #[test]
+    fn test_large_outflows_are_flagged_by_transaction_and_window() {
+        let start = Utc::now() - chrono::Duration::hours(3);
+        let flow = |minutes: i64, tx: u64, account: u64, direction: FlowDirection, usd: u64| BaseFlow {
+            block: 20_000_000 + minutes as u64 * 5,
+            timestamp: start + chrono::Duration::minutes(minutes),
+            tx_hash: H256::from_low_u64_be(tx),
+            account: Address::from_low_u64_be(account),
+            direction,
+            amount: U256::from(usd) * U256::exp10(6),
+        };
+        let flows = vec![
+            // Two hours before the rest, so in no window with them
+            flow(0, 1, 0xa, FlowDirection::Out, 4_000_000),
+            flow(120, 2, 0xb, FlowDirection::Out, 25_000_000),
+            // Withdrawn and mostly supplied back in one transaction
+            flow(130, 3, 0xc, FlowDirection::Out, 6_000_000),
+            flow(130, 3, 0xc, FlowDirection::In, 5_000_000),
+            flow(140, 4, 0xd, FlowDirection::Out, 3_000_000),
+            flow(150, 5, 0xe, FlowDirection::In, 1_000_000),
+        ];
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let mut market = create_test_market();
+
+        // $25M is 5 times the $5M threshold, at 90% utilization
+        let mut findings = Vec::new();
+        processor.check_outflows(&market, &flows, &mut findings, Utc::now());
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        let single = &findings[0];
+        assert_eq!((single.category.clone(), single.severity), (RiskCategory::HighUtilization, RiskSeverity::Critical));
+        assert_eq!(single.metadata["tx_hash"], serde_json::json!(H256::from_low_u64_be(2)));
+        assert_eq!(single.metadata["accounts"][0]["address"], crate::utils::to_checksum_address(&Address::from_low_u64_be(0xb)));
+        assert!(single.description.contains("withdrew $25.0M net of USDC from USDC in one transaction (2.50% of supply)"), "{}", single.description);
+        let window = &findings[1];
+        assert_eq!(window.metadata["outflow_usd"], 29_000_000.0);
+        let hashes: Vec<H256> = serde_json::from_value(window.metadata["tx_hashes"].clone()).unwrap();
+        assert_eq!(hashes, [2, 3, 4].map(H256::from_low_u64_be));
+        assert!(window.description.contains("within 1h in 3 transactions"), "{}", window.description);
+
+        // The same flows from a market at 40% utilization are informational, whatever the
+        // stored rate says
+        market.total_borrow = market.total_supply * 0.4;
+        market.utilization_rate = 0.95;
+        let mut findings = Vec::new();
+        processor.check_outflows(&market, &flows, &mut findings, Utc::now());
+        assert!(findings.iter().all(|f| f.severity == RiskSeverity::Low));
+        assert_eq!(outflow_severity(1.5, 0.7, 0.85), RiskSeverity::Medium);
+        assert_eq!(outflow_severity(2.0, 0.7, 0.85), RiskSeverity::High);
+    }
+
+    #[tokio::test]
+    async fn test_assess_market_reporting_matches_findings() {
         let config = Arc::new(Config::default());
         let processor = RiskProcessor::new(config);
-        
-        let findings = vec![
-            RiskFinding {
-                category: RiskCategory::HighUtilization,
-                severity: RiskSeverity::High,
-                description: "Test finding".to_string(),
-                metadata: serde_json::json!({}),
-                timestamp: Utc::now(),
-            },
-            RiskFinding {
-                category: RiskCategory::LiquidationCascade,
-                severity: RiskSeverity::Medium,
-                description: "Test finding 2".to_string(),
-                metadata: serde_json::json!({}),
-                timestamp: Utc::now(),
-            },
+        let market = create_test_market();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let assessment = processor.assess_market_reporting(&market, Some(&tx)).await.unwrap();
+        drop(tx);
+
+        let mut streamed = Vec::new();
+        while let Some(event) = rx.recv().await {
+            match event {
+                AssessmentEvent::Finding { finding, market_address } => {
+                    assert_eq!(market_address, market.comet_address);
+                    streamed.push(finding);
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+
+        assert_eq!(streamed.len(), assessment.findings.len());
+        assert_eq!(streamed[0].description, assessment.findings[0].description);
+    }
+
+    #[tokio::test]
+    async fn test_findings_are_streamed_marked_by_reads_missed_later() {
+        // Only the watchlist read, after the utilization check has run, misses a position
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut data = fixture.data().clone();
+        let unreadable = Address::from_str("0x2222222222222222222222222222222222222222").unwrap();
+        data.unreadable_accounts.push(unreadable);
+        let provider: SharedProvider = Arc::new(FixtureProvider::new(data));
+        let market = provider.get_markets().await.unwrap().remove(0);
+        assert!(market.data_issues.is_empty());
+        let config = Config { watchlist: vec![format!("{:?}", unreadable)], ..Config::default() };
+        let processor = RiskProcessor::with_provider(Arc::new(config), provider);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let assessment = processor.assess_market_reporting(&market, Some(&tx)).await.unwrap();
+        drop(tx);
+        let mut streamed = Vec::new();
+        while let Some(AssessmentEvent::Finding { finding, .. }) = rx.recv().await {
+            streamed.push(finding);
+        }
+
+        assert!(assessment.data_degraded());
+        let marks = |findings: &[RiskFinding]| findings.iter().map(|f| (f.fingerprint.clone(), f.metadata.clone())).collect::<Vec<_>>();
+        assert_eq!(marks(&streamed), marks(&assessment.findings));
+        let risks: Vec<&RiskFinding> = streamed.iter().filter(|f| f.category != RiskCategory::DataQuality).collect();
+        assert!(risks.iter().any(|f| f.category == RiskCategory::HighUtilization));
+        assert!(risks.iter().all(|f| f.metadata["data_degraded"] == true), "{:?}", risks);
+    }
+
+    #[tokio::test]
+    async fn test_category_scores_tell_unevaluated_from_zero() {
+        use crate::models::SequencerStatus;
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        // Without a provider only the checks on the market itself evaluate their categories
+        let market = create_test_market();
+        let assessment = RiskProcessor::new(Arc::new(Config::default())).assess_market(&market).await.unwrap();
+        let scores = &assessment.category_scores;
+        let utilization: Vec<RiskFinding> = assessment.findings.iter().filter(|f| f.category == RiskCategory::HighUtilization).cloned().collect();
+        assert!(!utilization.is_empty());
+        assert_eq!(scores[&RiskCategory::HighUtilization], Some(RiskScore::of(&utilization).total));
+        assert_eq!(scores[&RiskCategory::Configuration], Some(0));
+        assert_eq!((scores[&RiskCategory::PriceVolatility], scores[&RiskCategory::OracleReliability]), (None, None));
+        assert!(!scores.contains_key(&RiskCategory::DataQuality) && !scores.contains_key(&RiskCategory::RiskTrend));
+
+        // A sequencer feed that reads up evaluates oracle reliability at zero
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let mut data = fixture.data().clone();
+        data.sequencer = Some(SequencerStatus { up: true, since: Utc::now() - chrono::Duration::days(1) });
+        let processor = RiskProcessor::with_provider(Arc::new(Config::default()), Arc::new(FixtureProvider::new(data)));
+        let scores = processor.assess_market(&market).await.unwrap().category_scores;
+        assert_eq!(scores[&RiskCategory::OracleReliability], Some(0));
+        assert!(scores[&RiskCategory::PriceVolatility].is_some());
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_managers_of_watchlist_accounts_are_flagged() {
+        use crate::models::ManagerPermissions;
+        use crate::provider::{bundled_fixture, FixturePermissions, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let account = |n: u8| Address::repeat_byte(n);
+        let (bulker, unknown) = (account(0xb0), account(0xee));
+        let mut data = fixture.data().clone();
+        for (owner, managers, complete) in [
+            (account(0x11), vec![bulker], true),
+            (account(0x22), vec![bulker, unknown], true),
+            (account(0x44), vec![unknown], false),
+        ] {
+            data.permissions.push(FixturePermissions {
+                market: market.comet_address,
+                permissions: ManagerPermissions { account: owner, nonce: 1, managers, complete },
+            });
+        }
+        let config = Config {
+            // 0x33.. has no managers at all
+            watchlist: [0x11, 0x22, 0x33, 0x44].map(|n| format!("{:?}", account(n))).to_vec(),
+            trusted_managers: vec![format!("{:?}", bulker)],
+            ..Config::default()
+        };
+        let processor = RiskProcessor::with_provider(Arc::new(config), Arc::new(FixtureProvider::new(data)));
+
+        let mut findings = Vec::new();
+        processor.check_manager_permissions(&market, &mut findings, Utc::now()).await;
+        let flagged: Vec<&serde_json::Value> = findings.iter().map(|f| &f.metadata["account"]).collect();
+        assert_eq!(flagged, [&serde_json::json!(account(0x22)), &serde_json::json!(account(0x44))]);
+        assert!(findings.iter().all(|f| f.severity == RiskSeverity::Medium && f.category == RiskCategory::AccountPermissions));
+        assert_eq!(findings[0].metadata["managers"].as_array().unwrap().len(), 2);
+        assert_eq!(findings[0].metadata["untrusted_managers"], serde_json::json!([{ "address": crate::utils::to_checksum_address(&unknown), "label": null }]));
+        assert_eq!(findings[1].metadata["complete"], false);
+    }
+
+    #[tokio::test]
+    async fn test_configurator_changes_not_yet_deployed_are_flagged() {
+        use crate::models::ConfigurationPair;
+        use crate::provider::{bundled_fixture, FixtureConfigurations, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let blob = std::fs::read_to_string(bundled_fixture("configurator-usdc.hex")).unwrap();
+        let configured = crate::compound::decode_configuration(&ethers::types::Bytes::from_str(blob.trim()).unwrap()).unwrap();
+        let check = |live: crate::models::MarketConfiguration| {
+            let mut data = fixture.data().clone();
+            let configurations = ConfigurationPair { configurator: Address::repeat_byte(0xcf), configured: configured.clone(), live };
+            data.configurations.push(FixtureConfigurations { market: market.comet_address, configurations });
+            let processor = RiskProcessor::with_provider(Arc::new(Config::default()), Arc::new(FixtureProvider::new(data)));
+            let market = market.clone();
+            async move {
+                let mut findings = Vec::new();
+                processor.check_configuration_drift(&market, &mut findings, Utc::now()).await;
+                findings
+            }
+        };
+
+        assert!(check(configured.clone()).await.is_empty());
+
+        // Governance raised WETH's liquidation factor to 0.95 in the Configurator; Comet still runs 0.90
+        let mut live = configured.clone();
+        live.asset_configs[0].liquidation_factor = 900_000_000_000_000_000;
+        let findings = check(live).await;
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].category.clone(), findings[0].severity), (RiskCategory::SmartContractRisk, RiskSeverity::Medium));
+        assert!(
+            findings[0].description.ends_with("pending deployAndUpgrade: WETH.liquidation_factor 900000000000000000 -> 950000000000000000"),
+            "{}",
+            findings[0].description
+        );
+        assert_eq!(findings[0].metadata["drift"][0]["field"], "liquidation_factor");
+    }
+
+    #[tokio::test]
+    async fn test_degraded_sequencer_is_critical_and_flags_other_findings() {
+        use crate::models::SequencerStatus;
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let assess = |sequencer: Option<SequencerStatus>| {
+            let mut data = fixture.data().clone();
+            data.sequencer = sequencer;
+            let processor = RiskProcessor::with_provider(Arc::new(Config::default()), Arc::new(FixtureProvider::new(data)));
+            let market = market.clone();
+            async move { processor.assess_market(&market).await.unwrap().findings }
+        };
+        let status = |up: bool, minutes_ago: i64| Some(SequencerStatus { up, since: Utc::now() - chrono::Duration::minutes(minutes_ago) });
+        let sequencer = |findings: &[RiskFinding]| findings.iter().filter(|f| f.metadata.get("sequencer_up").is_some()).count();
+        let flagged = |findings: &[RiskFinding]| findings.iter().filter(|f| f.metadata.get("sequencer_degraded").is_some()).count();
+
+        // No feed, or up for a day: the usual findings, unflagged
+        let usual = assess(None).await.len();
+        assert!(usual > 0);
+        let up = assess(status(true, 24 * 60)).await;
+        assert_eq!((up.len(), sequencer(&up), flagged(&up)), (usual, 0, 0));
+
+        let down = assess(status(false, 5)).await;
+        assert_eq!(down[0].severity, RiskSeverity::Critical);
+        assert_eq!(down[0].category, RiskCategory::OracleReliability);
+        assert_eq!(down[0].metadata["sequencer_up"], false);
+        assert_eq!((down.len(), sequencer(&down), flagged(&down)), (usual + 1, 1, usual));
+
+        // Back up 10 minutes ago, inside the hour of grace
+        let recovered = assess(status(true, 10)).await;
+        assert_eq!(recovered[0].severity, RiskSeverity::Critical);
+        assert!(recovered[0].description.contains("came back up 10 minutes ago"), "{}", recovered[0].description);
+        assert_eq!((sequencer(&recovered), flagged(&recovered)), (1, usual));
+    }
+
+    #[tokio::test]
+    async fn test_positions_over_their_borrow_limit_are_not_yet_liquidatable() {
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let mut position = fixture.get_user_position(&market, Address::repeat_byte(0x11)).await.unwrap();
+
+        // 1 WETH at $2000 may back $1650 of borrow (0.825) but is liquidated only below $1820
+        // (0.91): $1780 is over the borrow limit, yet 1820 / 1780 = 1.02 above liquidation
+        position.base_balance = -1_780.0;
+        position.total_borrow_value = 1_780.0;
+        position.health_factor = market.health_factor(position.base_balance, &position.collateral_balances);
+        position.set_borrow_capacity(&market);
+        assert!((position.health_factor - 1820.0 / 1780.0).abs() < 1e-9, "{}", position.health_factor);
+        assert!((position.borrow_overage(&market).unwrap() - 130.0).abs() < 1e-9);
+        assert_eq!(position.borrow_capacity_usd, 0.0);
+
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        let finding = processor.check_user_liquidation_risk(market.comet_address, &position).unwrap();
+        assert_eq!(finding.severity, RiskSeverity::High);
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_positions_are_checked() {
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let config = Config {
+            watchlist: vec![
+                "0x1111111111111111111111111111111111111111".to_string(),
+                "0x2222222222222222222222222222222222222222".to_string(),
+                // Not in the fixture: skipped rather than failing the assessment
+                "0x3333333333333333333333333333333333333333".to_string(),
+            ],
+            ..Config::default()
+        };
+        let processor = RiskProcessor::with_provider(Arc::new(config), Arc::new(fixture));
+
+        let assessment = processor.assess_market(&market).await.unwrap();
+        assert_eq!(assessment.watchlist_min_health_factor, Some(1.04));
+        let watchlist: Vec<&RiskFinding> = assessment
+            .findings
+            .iter()
+            .filter(|f| f.category == RiskCategory::LiquidationCascade)
+            .collect();
+        assert_eq!(watchlist.len(), 1);
+        assert_eq!(watchlist[0].severity, RiskSeverity::Medium);
+        assert_eq!(watchlist[0].metadata["account"], "0x2222222222222222222222222222222222222222");
+        assert_eq!(watchlist[0].metadata["dominant_collateral"], "WETH");
+        let liquidation_price = watchlist[0].metadata["liquidation_price"].as_f64().unwrap();
+        // 1 WETH at the 0.91 liquidation factor covers the $1750 borrow down to $1923.08
+        assert!((liquidation_price - 1750.0 / 0.91).abs() < 1e-6, "{}", liquidation_price);
+        assert_eq!(watchlist[0].metadata["time_to_liquidation_days"], 202);
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_alert_health_factor_raises_findings() {
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let mut config = Config {
+            watchlist: vec![
+                "0x1111111111111111111111111111111111111111".to_string(),
+                "0x2222222222222222222222222222222222222222".to_string(),
+            ],
+            ..Config::default()
+        };
+        config.risk.watchlist_alert_health_factor = Some(2.0);
+        let processor = RiskProcessor::with_provider(Arc::new(config), Arc::new(fixture));
+
+        let assessment = processor.assess_market(&market).await.unwrap();
+        let watchlist: Vec<&RiskFinding> = assessment
+            .findings
+            .iter()
+            .filter(|f| f.category == RiskCategory::LiquidationCascade)
+            .collect();
+        assert_eq!(watchlist.len(), 2);
+        assert!(watchlist.iter().all(|f| f.severity == RiskSeverity::High));
+        assert!(watchlist.iter().all(|f| f.metadata["alert_health_factor"] == 2.0));
+    }
+
+    #[tokio::test]
+    async fn test_price_volatility_above_threshold_is_flagged() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.data().markets[0].clone();
+        let check = |volatility: Option<f64>| {
+            let mut data = fixture.data().clone();
+            match volatility {
+                Some(volatility) => data.price_history[0].volatility_30d = volatility,
+                None => data.price_history.clear(),
+            }
+            let provider: SharedProvider = Arc::new(FixtureProvider::new(data));
+            let processor = RiskProcessor::with_provider(Arc::new(Config::default()), provider);
+            let market = market.clone();
+            async move {
+                let mut findings = Vec::new();
+                let highest = processor.check_price_volatility(&market, &mut findings, Utc::now()).await;
+                (highest, findings)
+            }
+        };
+
+        // WETH at 12% against the default 10% threshold
+        let (highest, findings) = check(Some(0.12)).await;
+        assert_eq!(highest, Some(0.12));
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].category.clone(), findings[0].severity), (RiskCategory::PriceVolatility, RiskSeverity::Medium));
+        assert_eq!(findings[0].description, "WETH 30-day volatility is 12.00%, above the 10.00% threshold");
+        assert_eq!(findings[0].metadata["measured_against"], "USD");
+        assert_eq!(findings[0].metadata["threshold"], 0.1);
+
+        // Twice the threshold is High; at the threshold nothing is flagged, but it is still measured
+        assert_eq!(check(Some(0.2)).await.1[0].severity, RiskSeverity::High);
+        let (highest, findings) = check(Some(0.1)).await;
+        assert_eq!((highest, findings.len()), (Some(0.1), 0));
+
+        // Without a price history or a provider there is nothing to measure
+        let (highest, findings) = check(None).await;
+        assert_eq!((highest, findings.len()), (None, 0));
+        let mut findings = Vec::new();
+        let processor = RiskProcessor::new(Arc::new(Config::default()));
+        assert_eq!(processor.check_price_volatility(&market, &mut findings, Utc::now()).await, None);
+        assert!(findings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_asset_settings_override_and_scope_checks() {
+        use crate::config::AssetConfig;
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+
+        let fixture = Arc::new(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap());
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let assess = |assets: Vec<(&str, AssetConfig)>| {
+            let config = Config {
+                assets: assets.into_iter().map(|(key, settings)| (key.to_string(), settings)).collect(),
+                ..Config::default()
+            };
+            let processor = RiskProcessor::with_provider(Arc::new(config), fixture.clone());
+            let market = market.clone();
+            async move { processor.assess_market(&market).await.unwrap().findings }
+        };
+        let volatility = |findings: &[RiskFinding]| {
+            findings.iter().filter(|f| f.category == RiskCategory::PriceVolatility).count()
+        };
+
+        // WETH's 12% volatility is above the default 10% threshold
+        assert_eq!(volatility(&assess(vec![]).await), 1);
+        let looser = AssetConfig { max_price_volatility: Some(0.2), ..AssetConfig::default() };
+        assert_eq!(volatility(&assess(vec![("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", looser)]).await), 0);
+        let ignored = AssetConfig { ignore: true, ..AssetConfig::default() };
+        assert_eq!(volatility(&assess(vec![("weth", ignored)]).await), 0);
+
+        // Entries naming no asset are reported instead of doing nothing
+        let findings = assess(vec![("wstETH", AssetConfig::default()), ("USDC", AssetConfig::default())]).await;
+        let unmatched: Vec<&RiskFinding> = findings.iter().filter(|f| f.category == RiskCategory::Configuration).collect();
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].severity, RiskSeverity::Low);
+        assert_eq!(unmatched[0].metadata["setting"], "assets.wstETH");
+    }
+
+    #[test]
+    fn test_calculate_risk_score() {
+        let mut findings = vec![
+            finding("a", RiskSeverity::High),
+            RiskFinding { category: RiskCategory::LiquidationCascade, ..finding("b", RiskSeverity::Medium) },
         ];
         
-        let score = processor.calculate_risk_score(&findings);
-        assert_eq!(score, 45); // 30 (High) + 15 (Medium) = 45
+        let score = RiskScore::of(&findings);
+        assert_eq!(score.total, 45); // 30 (High) + 15 (Medium) = 45
+        assert_eq!(score.contributions, [30, 15]);
+
+        // Past the cap, the most severe findings keep their points and the rest share what is left
+        findings.splice(0..0, [RiskSeverity::Critical, RiskSeverity::Critical].map(|severity| RiskFinding { severity, ..findings[0].clone() }));
+        findings.push(RiskFinding { category: RiskCategory::RiskTrend, ..findings[3].clone() });
+        let score = RiskScore::of(&findings);
+        assert_eq!((score.total, score.contributions.as_slice()), (100, [50, 50, 0, 0, 0].as_slice()));
+        findings.truncate(4);
+        findings[0].severity = RiskSeverity::Low;
+        let score = RiskScore::of(&findings);
+        assert_eq!(score.contributions, [5, 50, 30, 15]);
+        score.attribute(&mut findings);
+        assert_eq!(findings.iter().map(|f| f.score_contribution as u16).sum::<u16>(), u16::from(score.total));
+    }
+
+    #[test]
+    fn test_one_line_forms_of_assessments_and_findings() {
+        let finding = RiskFinding {
+            description: "Utilization at 95%\nof supply".to_string(),
+            score_contribution: 30,
+            ..finding("", RiskSeverity::High)
+        };
+        assert_eq!(finding.to_string(), "[High] Utilization at 95% of supply");
+
+        let findings = vec![RiskFinding { severity: RiskSeverity::Low, ..finding.clone() }, finding];
+        let mut assessment = assessment(35, findings);
+        assert_eq!(assessment.to_string(), "USDC (0x0000...0001): score 35/100, 2 finding(s), worst High");
+
+        assessment.trend = Some(ScoreTrend {
+            smoothed_score: 30.0,
+            previous_smoothed_score: Some(25.0),
+            delta_24h: Some(10),
+            delta_7d: None,
+            slope_per_day: Some(5.0),
+            direction: Direction::Deteriorating,
+            categories: Default::default(),
+        });
+        assessment.mock_data = true;
+        let summary = assessment.summary();
+        assert_eq!((summary.worst_severity, summary.findings), (Some(RiskSeverity::High), 2));
+        assert_eq!(summary.to_string(), "USDC (0x0000...0001) [MOCK DATA]: score 35/100 ↑, 2 finding(s), worst High");
+
+        assessment.findings.clear();
+        assessment.trend = None;
+        assessment.mock_data = false;
+        assert_eq!(assessment.to_string(), "USDC (0x0000...0001): score 35/100, no findings");
     }
-} 
\ No newline at end of file
+
+    /// Check that waits `delay`, then asks `chain` for the block number `reads` times
+    struct Instrumented {
+        name: &'static str,
+        delay: std::time::Duration,
+        reads: usize,
+        chain: Option<crate::rpc::RpcProvider>,
+    }
+
+    #[async_trait]
+    impl RiskCheck for Instrumented {
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn check(&self, _market: &Market, _provider: Option<&SharedProvider>, _timestamp: DateTime<Utc>) -> Result<Vec<RiskFinding>> {
+            use ethers::providers::Middleware;
+            tokio::time::sleep(self.delay).await;
+            for _ in 0..self.reads {
+                // Charged to the budget whatever the endpoint answers
+                let _ = self.chain.as_ref().unwrap().get_block_number().await;
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_checks_are_timed_and_their_rpc_calls_counted() {
+        use crate::rpc::{metered, RecordingClient, RpcBudget};
+        use std::time::Duration;
+
+        let url = crate::testing::CaptureServer::new().serve().await;
+        let budget = Arc::new(RpcBudget::new(Default::default()));
+        let chain = ethers::providers::Provider::new(RecordingClient::live(&url).unwrap().with_budget(budget));
+
+        let mut config = Config::default();
+        config.performance.check_budget_ms = 5_000;
+        let provider: SharedProvider = Arc::new(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap());
+        let market = provider.get_markets().await.unwrap().remove(0);
+        let checks: Vec<Arc<dyn RiskCheck>> = vec![
+            Arc::new(Instrumented { name: "slow", delay: Duration::from_secs(7), reads: 0, chain: None }),
+            Arc::new(Instrumented { name: "chatty", delay: Duration::from_millis(300), reads: 3, chain: Some(chain) }),
+            Arc::new(Instrumented { name: "quick", delay: Duration::from_millis(20), reads: 0, chain: None }),
+        ];
+        let processor = RiskProcessor::with_provider(Arc::new(config), provider).with_checks(checks);
+
+        let (assessment, usage) = metered(processor.assess_market(&market)).await;
+        let assessment = assessment.unwrap();
+        assert_eq!(assessment.timings["slow"], CheckTiming { ms: 7_000, rpc_calls: 0 });
+        assert_eq!(assessment.timings["chatty"], CheckTiming { ms: 300, rpc_calls: 3 });
+        assert_eq!(assessment.timings["quick"], CheckTiming { ms: 20, rpc_calls: 0 });
+        // Built-in checks are timed too, those that read nothing included
+        assert!(assessment.timings.contains_key("price_volatility"));
+        assert_eq!(assessment.timings["utilization"].rpc_calls, 0);
+        // The per-check counts also reach the assessment's own meter
+        assert_eq!(usage.calls, 3);
+
+        let slowest = slowest_checks(std::slice::from_ref(&assessment), 3);
+        let names: Vec<&str> = slowest.iter().map(|check| check.check).collect();
+        assert_eq!(names, ["slow", "chatty", "quick"]);
+        assert_eq!(slowest[1].to_string(), "chatty in USDC (300ms, 3 RPC call(s))");
+
+        // Cut off by the deadline: counted up to the cutoff
+        let processor = RiskProcessor::with_provider(Arc::new(Config::default()), Arc::new(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap()))
+            .with_checks(vec![Arc::new(Instrumented { name: "hung", delay: Duration::from_secs(3600), reads: 0, chain: None })])
+            .with_deadline(Instant::now() + Duration::from_secs(60));
+        let assessment = processor.assess_market(&market).await.unwrap();
+        assert_eq!(assessment.timed_out().as_deref(), Some("hung"));
+        assert_eq!(assessment.timings["hung"].ms, 60_000);
+    }
+}
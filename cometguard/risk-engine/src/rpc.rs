@@ -0,0 +1,741 @@
+use crate::config::{Config, RpcBudgetConfig, RpcMode};
+use crate::error::{rpc_host, Result, RiskEngineError};
+use crate::metrics::Metrics;
+use async_trait::async_trait;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError, Provider, ProviderError, RpcError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Debug;
+use std::future::Future;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+
+/// Delay before the first retry of a failed request; doubled for every further retry
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Window of `rpc.budget.per_minute_compute_units`
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Provider type used for all chain access
+pub type RpcProvider = Provider<RecordingClient>;
+
+/// One recorded JSON-RPC exchange, stored as a line of a session file
+///
+/// Requests are keyed on method and the exact serialized params, so an `eth_call`
+/// is matched on its call data and block tag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEntry {
+    /// JSON-RPC method
+    pub method: String,
+    /// Request params as sent
+    pub params: Value,
+    /// Successful response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error response (e.g. a revert); replayed as-is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+}
+
+/// JSON-RPC error object as stored in a session file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedError {
+    /// JSON-RPC error code
+    pub code: i64,
+    /// Error message
+    pub message: String,
+    /// Additional data (e.g. revert data)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl From<&JsonRpcError> for RecordedError {
+    fn from(e: &JsonRpcError) -> Self {
+        Self {
+            code: e.code,
+            message: e.message.clone(),
+            data: e.data.clone(),
+        }
+    }
+}
+
+impl From<RecordedError> for JsonRpcError {
+    fn from(e: RecordedError) -> Self {
+        Self {
+            code: e.code,
+            message: e.message,
+            data: e.data,
+        }
+    }
+}
+
+impl SessionEntry {
+    fn key(&self) -> String {
+        request_key(&self.method, &self.params)
+    }
+}
+
+fn request_key(method: &str, params: &Value) -> String {
+    format!("{} {}", method, params)
+}
+
+/// Error from the recording transport
+#[derive(Debug, thiserror::Error)]
+pub enum RpcSessionError {
+    /// The live HTTP transport failed
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+
+    /// A recorded error response, replayed
+    #[error(transparent)]
+    Rpc(JsonRpcError),
+
+    /// Replay mode received a request that is not in the session
+    #[error("no recorded response for {method} {params} in replay session")]
+    Miss { method: String, params: String },
+
+    /// A request or response could not be (de)serialized
+    #[error("malformed RPC payload: {0}")]
+    Serde(#[from] serde_json::Error),
+
+    /// A call did not finish within `timeouts.rpc_call`, retries included
+    #[error("{method} timed out after {}s", .after.as_secs_f64())]
+    Timeout { method: String, after: Duration },
+
+    /// The session file could not be written
+    #[error("session file {}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl RpcError for RpcSessionError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Http(e) => e.as_error_response(),
+            Self::Rpc(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Http(e) => e.as_serde_error(),
+            Self::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<RpcSessionError> for ProviderError {
+    fn from(src: RpcSessionError) -> Self {
+        match src {
+            RpcSessionError::Http(e) => e.into(),
+            other => ProviderError::JsonRpcClientError(Box::new(other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Session {
+    path: PathBuf,
+    /// Open for appending in record mode
+    writer: Option<File>,
+    /// Recorded exchanges by request key, in recording order
+    entries: HashMap<String, Vec<SessionEntry>>,
+    /// Next entry to serve per request key in replay mode
+    cursors: HashMap<String, usize>,
+}
+
+impl Session {
+    fn append(&mut self, entry: SessionEntry) -> std::result::Result<(), RpcSessionError> {
+        let line = serde_json::to_string(&entry)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writeln!(writer, "{}", line).map_err(|source| RpcSessionError::Io {
+                path: self.path.clone(),
+                source,
+            })?;
+        }
+        self.entries.entry(entry.key()).or_default().push(entry);
+        Ok(())
+    }
+
+    /// Next recorded exchange for `key`; repeated requests are served in recording order,
+    /// and the last one is reused once they run out
+    fn next(&mut self, key: &str) -> Option<SessionEntry> {
+        let entries = self.entries.get(key)?;
+        let cursor = self.cursors.entry(key.to_string()).or_insert(0);
+        let entry = entries[(*cursor).min(entries.len() - 1)].clone();
+        *cursor += 1;
+        Some(entry)
+    }
+}
+
+/// Estimated compute units of one request to `method`, after Alchemy's published weights
+///
+/// Methods not listed cost as much as an `eth_call`, which is what nearly all reads are.
+pub fn compute_units(method: &str) -> u64 {
+    match method {
+        "eth_chainId" | "net_version" => 0,
+        "eth_blockNumber" | "eth_feeHistory" => 10,
+        "eth_getTransactionReceipt" => 15,
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => 16,
+        "eth_getStorageAt" => 17,
+        "eth_getBalance" | "eth_gasPrice" => 19,
+        "eth_getLogs" => 75,
+        "eth_estimateGas" => 87,
+        _ => 26,
+    }
+}
+
+/// Requests sent to one method
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodUsage {
+    pub calls: u64,
+    pub compute_units: u64,
+}
+
+/// Requests sent, retries included, and their estimated compute units
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcUsage {
+    pub calls: u64,
+    pub compute_units: u64,
+    /// Time requests waited for the per-minute budget, in milliseconds
+    pub throttled_ms: u64,
+    pub by_method: BTreeMap<String, MethodUsage>,
+}
+
+impl RpcUsage {
+    fn record(&mut self, method: &str, units: u64, throttled: Duration) {
+        self.calls += 1;
+        self.compute_units += units;
+        self.throttled_ms += throttled.as_millis() as u64;
+        let usage = self.by_method.entry(method.to_string()).or_default();
+        usage.calls += 1;
+        usage.compute_units += units;
+    }
+
+    /// Add the requests of `other`
+    fn add(&mut self, other: &RpcUsage) {
+        self.calls += other.calls;
+        self.compute_units += other.compute_units;
+        self.throttled_ms += other.throttled_ms;
+        for (method, usage) in &other.by_method {
+            let total = self.by_method.entry(method.clone()).or_default();
+            total.calls += usage.calls;
+            total.compute_units += usage.compute_units;
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Usage of the innermost `metered` future
+    static METER: Arc<Mutex<RpcUsage>>;
+}
+
+/// Run `future`, returning with its output the RPC usage of the requests it sent
+///
+/// Only requests sent from the future's own task count, not those of tasks it spawns. A
+/// metered future inside another one charges its requests to both.
+pub async fn metered<F: Future>(future: F) -> (F::Output, RpcUsage) {
+    let meter = Arc::new(Mutex::new(RpcUsage::default()));
+    let output = METER.scope(meter.clone(), future).await;
+    let usage = meter.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let _ = METER.try_with(|outer| outer.lock().unwrap_or_else(|e| e.into_inner()).add(&usage));
+    (output, usage)
+}
+
+/// Usage of the current run and how many of its markets have yet to claim a share
+#[derive(Debug, Default)]
+struct RunState {
+    usage: RpcUsage,
+    unplanned_markets: usize,
+}
+
+/// Compute-unit accounting and throttling for `rpc.budget`
+///
+/// The per-minute budget is enforced on every request by waiting until the compute units of
+/// the last minute leave room for it. The per-run budget is not enforced by the transport: the
+/// engine plans each market against `market_allowance` before reading positions.
+#[derive(Debug)]
+pub struct RpcBudget {
+    settings: RpcBudgetConfig,
+    /// Compute units sent within the last `BUDGET_WINDOW`, oldest first
+    window: Mutex<VecDeque<(tokio::time::Instant, u64)>>,
+    run: Mutex<RunState>,
+}
+
+impl RpcBudget {
+    pub fn new(settings: RpcBudgetConfig) -> Self {
+        Self { settings, window: Mutex::new(VecDeque::new()), run: Mutex::new(RunState::default()) }
+    }
+
+    pub fn settings(&self) -> &RpcBudgetConfig {
+        &self.settings
+    }
+
+    /// Start counting a new run
+    pub fn start_run(&self) {
+        *self.lock_run() = RunState::default();
+    }
+
+    /// Split what is left of the run budget over `markets` markets about to be assessed
+    pub fn expect_markets(&self, markets: usize) {
+        self.lock_run().unplanned_markets = markets;
+    }
+
+    /// Usage since the last `start_run`
+    pub fn run_usage(&self) -> RpcUsage {
+        self.lock_run().usage.clone()
+    }
+
+    /// Compute units the next market may plan for: what is left of the run budget, split
+    /// evenly over the markets that have not planned yet; `None` without a per-run budget
+    pub fn market_allowance(&self) -> Option<u64> {
+        let limit = self.settings.per_run_compute_units?;
+        let mut run = self.lock_run();
+        let remaining = limit.saturating_sub(run.usage.compute_units);
+        let markets = run.unplanned_markets.max(1) as u64;
+        run.unplanned_markets = run.unplanned_markets.saturating_sub(1);
+        Some(remaining / markets)
+    }
+
+    /// Wait until a request to `method` fits the per-minute budget, then charge it to the run
+    /// and to the enclosing `metered` future
+    ///
+    /// A single request larger than the whole budget is sent once the window is empty.
+    async fn charge(&self, method: &str) {
+        let units = compute_units(method);
+        let started = tokio::time::Instant::now();
+        if let Some(limit) = self.settings.per_minute_compute_units {
+            while let Some(wait) = self.reserve(units, limit) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        let throttled = started.elapsed();
+        if !throttled.is_zero() {
+            debug!("{} waited {:?} for the per-minute RPC budget", method, throttled);
+        }
+
+        self.lock_run().usage.record(method, units, throttled);
+        let _ = METER.try_with(|meter| meter.lock().unwrap_or_else(|e| e.into_inner()).record(method, units, throttled));
+    }
+
+    /// Take `units` from the rolling window, or return how long until the oldest entry leaves it
+    fn reserve(&self, units: u64, limit: u64) -> Option<Duration> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let now = tokio::time::Instant::now();
+        while window.front().is_some_and(|(at, _)| now.duration_since(*at) >= BUDGET_WINDOW) {
+            window.pop_front();
+        }
+        let used: u64 = window.iter().map(|(_, units)| units).sum();
+        match window.front() {
+            Some((oldest, _)) if used + units > limit => Some(BUDGET_WINDOW - now.duration_since(*oldest)),
+            _ => {
+                window.push_back((now, units));
+                None
+            }
+        }
+    }
+
+    fn lock_run(&self) -> std::sync::MutexGuard<'_, RunState> {
+        self.run.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// JSON-RPC transport that can record traffic to, or replay it from, a session file
+///
+/// Session files are JSON Lines, one `SessionEntry` per line, so a recording that is
+/// interrupted still holds every exchange completed before the interruption.
+#[derive(Debug)]
+pub struct RecordingClient {
+    mode: RpcMode,
+    host: String,
+    http: Option<Http>,
+    session: Option<Mutex<Session>>,
+    max_retries: u32,
+    metrics: Option<Arc<Metrics>>,
+    budget: Option<Arc<RpcBudget>>,
+    call_timeout: Option<Duration>,
+}
+
+impl RecordingClient {
+    /// Plain pass-through to `rpc_url`
+    pub fn live(rpc_url: &str) -> Result<Self> {
+        Ok(Self {
+            mode: RpcMode::Live,
+            host: rpc_host(rpc_url),
+            http: Some(http_transport(rpc_url)?),
+            session: None,
+            max_retries: 0,
+            metrics: None,
+            budget: None,
+            call_timeout: None,
+        })
+    }
+
+    /// Talk to `rpc_url` and record every exchange to `path`, replacing any previous session
+    pub fn record(rpc_url: &str, path: &Path) -> Result<Self> {
+        let writer = File::create(path).map_err(|e| RiskEngineError::io(path, e))?;
+        info!("Recording RPC session to {}", path.display());
+        Ok(Self {
+            mode: RpcMode::Record,
+            host: rpc_host(rpc_url),
+            http: Some(http_transport(rpc_url)?),
+            session: Some(Mutex::new(Session {
+                path: path.to_path_buf(),
+                writer: Some(writer),
+                entries: HashMap::new(),
+                cursors: HashMap::new(),
+            })),
+            max_retries: 0,
+            metrics: None,
+            budget: None,
+            call_timeout: None,
+        })
+    }
+
+    /// Serve requests from the session recorded at `path`, without network access
+    pub fn replay(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        let mut entries: HashMap<String, Vec<SessionEntry>> = HashMap::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let entry: SessionEntry = serde_json::from_str(line)
+                .map_err(|e| RiskEngineError::serialization(format!("RPC session {}", path.display()), e))?;
+            entries.entry(entry.key()).or_default().push(entry);
+        }
+        info!("Replaying {} recorded RPC requests from {}", entries.len(), path.display());
+        Ok(Self {
+            mode: RpcMode::Replay,
+            host: format!("replay:{}", path.display()),
+            http: None,
+            session: Some(Mutex::new(Session {
+                path: path.to_path_buf(),
+                writer: None,
+                entries,
+                cursors: HashMap::new(),
+            })),
+            max_retries: 0,
+            metrics: None,
+            budget: None,
+            call_timeout: None,
+        })
+    }
+
+    /// Build the transport described by `config.rpc`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let rpc_url = config.compound.rpc_url.as_str();
+        let session_path = || {
+            config.rpc.session_path.as_deref().ok_or_else(|| {
+                RiskEngineError::config("rpc.session_path", "required when rpc.mode is record or replay")
+            })
+        };
+        let client = match config.rpc.mode {
+            RpcMode::Live => Self::live(rpc_url)?,
+            RpcMode::Record => Self::record(rpc_url, session_path()?)?,
+            RpcMode::Replay => Self::replay(session_path()?)?,
+        };
+        Ok(client.with_max_retries(config.rpc.max_retries).with_call_timeout(config.timeouts.rpc_call()))
+    }
+
+    /// Retry requests up to `max_retries` times after transport errors
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Fail calls that take longer than `timeout`, retries included
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = Some(timeout);
+        self
+    }
+
+    /// Count requests and retries in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Charge requests to `budget`, waiting while they exceed its per-minute budget
+    pub fn with_budget(mut self, budget: Arc<RpcBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Mode this transport runs in
+    pub fn mode(&self) -> RpcMode {
+        self.mode
+    }
+
+    /// Endpoint host, safe to log
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn session(&self) -> std::sync::MutexGuard<'_, Session> {
+        self.session
+            .as_ref()
+            .expect("record and replay modes always have a session")
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn http(&self) -> &Http {
+        self.http.as_ref().expect("live and record modes always have an HTTP transport")
+    }
+
+    /// `send`, failing with `RpcSessionError::Timeout` past the call timeout
+    async fn send_timed<T, R>(&self, method: &str, params: &T) -> std::result::Result<std::result::Result<R, HttpClientError>, RpcSessionError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let Some(after) = self.call_timeout else { return Ok(self.send(method, params).await) };
+        tokio::time::timeout(after, self.send(method, params)).await.map_err(|_| {
+            warn!("{} to {} timed out after {:?}", method, self.host, after);
+            RpcSessionError::Timeout { method: method.to_string(), after }
+        })
+    }
+
+    /// Send a request over HTTP, retrying transport failures with exponential backoff
+    #[instrument(level = "debug", name = "rpc_request", skip(self, params), fields(rpc_host = %self.host, retries))]
+    async fn send<T, R>(&self, method: &str, params: &T) -> std::result::Result<R, HttpClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let mut attempt = 0;
+        loop {
+            if let Some(budget) = &self.budget {
+                budget.charge(method).await;
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record_rpc_call(method);
+            }
+            match self.http().request(method, params).await {
+                Err(HttpClientError::ReqwestError(e)) if attempt < self.max_retries => {
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    attempt += 1;
+                    warn!("{} to {} failed ({}), retry {} in {:?}", method, self.host, e, attempt, delay);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_rpc_retry(method);
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                outcome => {
+                    if attempt > 0 {
+                        tracing::Span::current().record("retries", attempt);
+                    }
+                    if let Err(e) = &outcome {
+                        debug!(error = %e, "{} to {} failed", method, self.host);
+                    }
+                    return outcome;
+                }
+            }
+        }
+    }
+}
+
+fn http_transport(rpc_url: &str) -> Result<Http> {
+    Http::from_str(rpc_url)
+        .map_err(|e| RiskEngineError::config("compound.rpc_url", format!("not a valid URL: {}", e)))
+}
+
+fn decode<R: DeserializeOwned>(entry: SessionEntry) -> std::result::Result<R, RpcSessionError> {
+    match (entry.error, entry.result) {
+        (Some(error), _) => Err(RpcSessionError::Rpc(error.into())),
+        (None, result) => Ok(serde_json::from_value(result.unwrap_or(Value::Null))?),
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RecordingClient {
+    type Error = RpcSessionError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> std::result::Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self.mode {
+            RpcMode::Live => Ok(self.send_timed(method, &params).await??),
+            RpcMode::Record => {
+                let params = serde_json::to_value(&params)?;
+                // A timed out call has no response to record
+                let outcome: std::result::Result<Value, HttpClientError> = self.send_timed(method, &params).await?;
+                let (result, error) = match outcome {
+                    Ok(result) => (Some(result), None),
+                    Err(e) => match e.as_error_response() {
+                        Some(error) => (None, Some(error.into())),
+                        // Transport failures are not part of the chain state; don't record them
+                        None => return Err(e.into()),
+                    },
+                };
+                let entry = SessionEntry {
+                    method: method.to_string(),
+                    params,
+                    result,
+                    error,
+                };
+                self.session().append(entry.clone())?;
+                decode(entry)
+            }
+            RpcMode::Replay => {
+                let params = serde_json::to_value(&params)?;
+                let key = request_key(method, &params);
+                let entry = self.session().next(&key).ok_or_else(|| RpcSessionError::Miss {
+                    method: method.to_string(),
+                    params: params.to_string(),
+                })?;
+                debug!("Replayed {}", key);
+                decode(entry)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::Middleware;
+    use ethers::types::{transaction::eip2718::TypedTransaction, BlockId, BlockNumber, TransactionRequest, H160};
+    use tempfile::tempdir;
+    use crate::testing::FakeChain;
+
+    fn call(data: &[u8]) -> TypedTransaction {
+        TransactionRequest::new()
+            .to(H160::from_low_u64_be(7))
+            .data(data.to_vec())
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let chain = FakeChain::new();
+        chain.on_raw_call(H160::from_low_u64_be(7), vec![1, 2].into(), vec![0x2a].into());
+        let url = chain.serve().await;
+
+        let recorder = Provider::new(RecordingClient::record(&url, &path).unwrap());
+        let block = Some(BlockId::Number(BlockNumber::Number(100.into())));
+        let live = recorder.call(&call(&[1, 2]), block).await.unwrap();
+        assert_eq!(live.as_ref(), &[0x2a]);
+        drop(recorder);
+
+        let replayer = Provider::new(RecordingClient::replay(&path).unwrap());
+        let replayed = replayer.call(&call(&[1, 2]), block).await.unwrap();
+        assert_eq!(replayed, live);
+
+        // Different call data or block tag is a miss, not a fuzzy match
+        let other_data = replayer.call(&call(&[9]), block).await;
+        assert!(other_data.is_err());
+        let other_block = replayer
+            .call(&call(&[1, 2]), Some(BlockId::Number(BlockNumber::Number(101.into()))))
+            .await;
+        let err = other_block.unwrap_err().to_string();
+        assert!(err.contains("no recorded response"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_replay_serves_repeats_in_order_and_error_responses() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        fs::write(
+            &path,
+            concat!(
+                r#"{"method":"eth_blockNumber","params":null,"result":"0x1"}"#, "\n",
+                r#"{"method":"eth_blockNumber","params":null,"result":"0x2"}"#, "\n",
+                r#"{"method":"eth_chainId","params":null,"error":{"code":-32000,"message":"execution reverted"}}"#, "\n",
+            ),
+        )
+        .unwrap();
+
+        let provider = Provider::new(RecordingClient::replay(&path).unwrap());
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 1);
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 2);
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 2);
+
+        let err = provider.get_chainid().await.unwrap_err();
+        assert_eq!(err.as_error_response().unwrap().message, "execution reverted");
+    }
+
+    #[tokio::test]
+    async fn test_transport_errors_are_retried_and_counted() {
+        // Bind and drop a listener to get a local port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let metrics = Arc::new(Metrics::new(1));
+        let client = RecordingClient::live(&format!("http://127.0.0.1:{}", port))
+            .unwrap()
+            .with_max_retries(2)
+            .with_metrics(metrics.clone());
+
+        let provider = Provider::new(client);
+        assert!(provider.get_block_number().await.is_err());
+
+        let text = metrics.render();
+        assert!(text.contains(r#"cometguard_rpc_calls_total{chain_id="1",method="eth_blockNumber"} 3"#), "{}", text);
+        assert!(text.contains(r#"cometguard_rpc_retries_total{chain_id="1",method="eth_blockNumber"} 2"#));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_budget_throttles_per_minute_and_meters_usage() {
+        let settings = RpcBudgetConfig {
+            per_run_compute_units: Some(1_000),
+            per_minute_compute_units: Some(60),
+            ..RpcBudgetConfig::default()
+        };
+        let budget = RpcBudget::new(settings);
+        budget.start_run();
+        budget.expect_markets(2);
+
+        let started = tokio::time::Instant::now();
+        let ((), usage) = metered(async {
+            budget.charge("eth_call").await;
+            budget.charge("eth_call").await;
+            // 62 units would exceed the minute's 60: waits for the first call to leave the window
+            budget.charge("eth_blockNumber").await;
+        })
+        .await;
+        assert_eq!(started.elapsed(), BUDGET_WINDOW);
+        assert_eq!((usage.calls, usage.compute_units, usage.throttled_ms), (3, 62, 60_000));
+        assert_eq!(usage.by_method["eth_call"], MethodUsage { calls: 2, compute_units: 52 });
+
+        // Requests outside a metered future still count towards the run
+        budget.charge("eth_chainId").await;
+        assert_eq!(budget.run_usage().calls, 4);
+        assert_eq!(budget.market_allowance(), Some((1_000 - 62) / 2));
+        assert_eq!(budget.market_allowance(), Some(1_000 - 62));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_calls_time_out() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let client = RecordingClient::live(&url).unwrap().with_call_timeout(Duration::from_secs(30));
+        let provider = Provider::new(client);
+
+        let started = tokio::time::Instant::now();
+        let err = provider.get_block_number().await.unwrap_err();
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+        assert!(err.to_string().contains("eth_blockNumber timed out after 30s"), "{}", err);
+        drop(listener);
+    }
+
+    #[test]
+    fn test_from_config_requires_session_path() {
+        let mut config = Config::default();
+        config.rpc.mode = RpcMode::Replay;
+        let err = RecordingClient::from_config(&config).unwrap_err();
+        assert!(matches!(err, RiskEngineError::Config { ref field, .. } if field == "rpc.session_path"));
+    }
+}
@@ -0,0 +1,921 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Filter, H256, I256, U256},
+};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+use crate::error::{Result, RiskEngineError};
+use crate::progress::{ProgressReporter, Stage};
+use crate::rpc::RpcProvider;
+
+/// Event signature of Comet's `Withdraw(address indexed src, address indexed to, uint amount)`
+pub const WITHDRAW_EVENT: &str = "Withdraw(address,address,uint256)";
+
+/// Event signature of Comet's `Supply(address indexed from, address indexed dst, uint amount)`
+pub const SUPPLY_EVENT: &str = "Supply(address,address,uint256)";
+
+/// Event signature of the Configurator's `SetFactory(address indexed cometProxy, address indexed
+/// oldFactory, address indexed newFactory)`, emitted when a Comet deployment is set up
+pub const SET_FACTORY_EVENT: &str = "SetFactory(address,address,address)";
+
+/// Event signature of CometExt's `Approval(address indexed owner, address indexed spender, uint
+/// amount)`, emitted by `allow` and `allowBySig` with an amount of 0 or all ones
+pub const APPROVAL_EVENT: &str = "Approval(address,address,uint256)";
+
+/// Number of chunks scanned between flushes of the index to disk
+pub const FLUSH_EVERY_CHUNKS: usize = 10;
+
+/// Lowercase fragments of the errors providers return for an `eth_getLogs` range that is too
+/// large or matches too many logs, e.g. "query returned more than 10000 results" or "block range
+/// is too wide"
+const RANGE_ERROR_HINTS: [&str; 7] = [
+    "block range",
+    "range too large",
+    "range is too",
+    "too many",
+    "more than",
+    "limit exceeded",
+    "response size",
+];
+
+/// Whether `error` is a provider refusing a log query for its size rather than failing it
+///
+/// Such a query succeeds when split into smaller block ranges.
+pub fn is_range_error(error: &RiskEngineError) -> bool {
+    match error {
+        RiskEngineError::Provider { source, .. } => {
+            let message = source.to_string().to_lowercase();
+            RANGE_ERROR_HINTS.iter().any(|hint| message.contains(hint))
+        }
+        _ => false,
+    }
+}
+
+/// Source of borrower activity logs
+#[async_trait]
+pub trait LogSource: Send + Sync {
+    /// Latest block number
+    async fn head_block(&self) -> Result<u64>;
+    /// Accounts that withdrew base (i.e. may have borrowed) from `comet` in `[from, to]`
+    async fn borrowers_in_range(&self, comet: Address, from: u64, to: u64) -> Result<Vec<Address>>;
+    /// Comet proxies `configurator` set a factory for in `[from, to]`
+    async fn comets_configured_in_range(&self, _configurator: Address, _from: u64, _to: u64) -> Result<Vec<Address>> {
+        Err(RiskEngineError::Unavailable {
+            what: "Configurator events",
+            reason: "this log source only reads borrower activity".to_string(),
+        })
+    }
+    /// Hash of block `number`; `None` when the block does not exist (any more) or the source
+    /// cannot tell, in which case scanned blocks near the head are always rescanned
+    async fn block_hash(&self, _number: u64) -> Result<Option<H256>> {
+        Ok(None)
+    }
+    /// Spenders `owner` approved or revoked in `comet` in `[from, to]`
+    async fn approvals_in_range(&self, _comet: Address, _owner: Address, _from: u64, _to: u64) -> Result<Vec<Address>> {
+        Err(RiskEngineError::Unavailable {
+            what: "Approval events",
+            reason: "this log source only reads borrower activity".to_string(),
+        })
+    }
+    /// Base asset supplied to and withdrawn from `comet` in `[from, to]`, in log order
+    async fn base_flows_in_range(&self, _comet: Address, _from: u64, _to: u64) -> Result<Vec<BaseFlow>> {
+        Err(RiskEngineError::Unavailable {
+            what: "Supply and Withdraw amounts",
+            reason: "this log source only reads borrower activity".to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl LogSource for RpcProvider {
+    #[instrument(level = "debug", skip(self), fields(rpc_host = %self.as_ref().host(), block_number), err(level = "debug"))]
+    async fn head_block(&self) -> Result<u64> {
+        let block = self
+            .get_block_number()
+            .await
+            .map_err(|e| RiskEngineError::Provider {
+                host: self.as_ref().host().to_string(),
+                source: e,
+            })?;
+        tracing::Span::current().record("block_number", block.as_u64());
+        Ok(block.as_u64())
+    }
+
+    #[instrument(level = "debug", skip(self), fields(rpc_host = %self.as_ref().host()), err(level = "debug"))]
+    async fn block_hash(&self, number: u64) -> Result<Option<H256>> {
+        let block = self.get_block(number).await.map_err(|e| RiskEngineError::Provider {
+            host: self.as_ref().host().to_string(),
+            source: e,
+        })?;
+        Ok(block.and_then(|block| block.hash))
+    }
+
+    #[instrument(level = "debug", skip(self, comet), fields(rpc_host = %self.as_ref().host()), err(level = "debug"))]
+    async fn borrowers_in_range(&self, comet: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+        let filter = Filter::new()
+            .address(comet)
+            .event(WITHDRAW_EVENT)
+            .from_block(from)
+            .to_block(to);
+        let logs = self
+            .get_logs(&filter)
+            .await
+            .map_err(|e| RiskEngineError::Provider {
+                host: self.as_ref().host().to_string(),
+                source: e,
+            })?;
+
+        Ok(logs
+            .iter()
+            .filter_map(|log| log.topics.get(1))
+            .map(|topic: &H256| Address::from(*topic))
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip(self, configurator), fields(rpc_host = %self.as_ref().host()), err(level = "debug"))]
+    async fn comets_configured_in_range(&self, configurator: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+        let filter = Filter::new()
+            .address(configurator)
+            .event(SET_FACTORY_EVENT)
+            .from_block(from)
+            .to_block(to);
+        let logs = self
+            .get_logs(&filter)
+            .await
+            .map_err(|e| RiskEngineError::Provider {
+                host: self.as_ref().host().to_string(),
+                source: e,
+            })?;
+
+        Ok(logs
+            .iter()
+            .filter_map(|log| log.topics.get(1))
+            .map(|topic: &H256| Address::from(*topic))
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip(self, comet, owner), fields(rpc_host = %self.as_ref().host()), err(level = "debug"))]
+    async fn approvals_in_range(&self, comet: Address, owner: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+        let filter = Filter::new()
+            .address(comet)
+            .event(APPROVAL_EVENT)
+            .topic1(H256::from(owner))
+            .from_block(from)
+            .to_block(to);
+        let logs = self
+            .get_logs(&filter)
+            .await
+            .map_err(|e| RiskEngineError::Provider {
+                host: self.as_ref().host().to_string(),
+                source: e,
+            })?;
+
+        Ok(logs
+            .iter()
+            .filter_map(|log| log.topics.get(2))
+            .map(|topic: &H256| Address::from(*topic))
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip(self, comet), fields(rpc_host = %self.as_ref().host()), err(level = "debug"))]
+    async fn base_flows_in_range(&self, comet: Address, from: u64, to: u64) -> Result<Vec<BaseFlow>> {
+        let provider_error = |e| RiskEngineError::Provider {
+            host: self.as_ref().host().to_string(),
+            source: e,
+        };
+        let filter = Filter::new()
+            .address(comet)
+            .events([SUPPLY_EVENT, WITHDRAW_EVENT])
+            .from_block(from)
+            .to_block(to);
+        let logs = self.get_logs(&filter).await.map_err(provider_error)?;
+
+        let supply = H256::from(ethers::utils::keccak256(SUPPLY_EVENT));
+        let mut block_times = HashMap::new();
+        let mut flows = Vec::new();
+        for log in logs {
+            // Supply credits `dst`, Withdraw debits `src`
+            let (direction, account) = match log.topics.first() {
+                Some(topic) if *topic == supply => (FlowDirection::In, log.topics.get(2)),
+                _ => (FlowDirection::Out, log.topics.get(1)),
+            };
+            let (Some(account), Some(block), Some(tx_hash)) = (account, log.block_number, log.transaction_hash) else {
+                continue;
+            };
+            let block = block.as_u64();
+            let timestamp = match block_times.get(&block) {
+                Some(timestamp) => *timestamp,
+                None => {
+                    let header = self.get_block(block).await.map_err(provider_error)?.ok_or_else(|| RiskEngineError::NotFound {
+                        kind: "block",
+                        id: block.to_string(),
+                    })?;
+                    let timestamp = DateTime::from_timestamp(header.timestamp.low_u64() as i64, 0).unwrap_or_default();
+                    *block_times.entry(block).or_insert(timestamp)
+                }
+            };
+            flows.push(BaseFlow {
+                block,
+                timestamp,
+                tx_hash,
+                account: Address::from(*account),
+                direction,
+                amount: U256::from_big_endian(&log.data),
+            });
+        }
+        Ok(flows)
+    }
+}
+
+/// Whether a base flow moved the asset into or out of the Comet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowDirection {
+    /// Supplied, whether as a deposit or a repayment
+    In,
+    /// Withdrawn, whether from a deposit or as a borrow
+    Out,
+}
+
+/// Base asset moved by one `Supply` or `Withdraw` log of a Comet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaseFlow {
+    pub block: u64,
+    /// Time of the block
+    pub timestamp: DateTime<Utc>,
+    pub tx_hash: H256,
+    /// Account whose base balance changed: `dst` of a supply, `src` of a withdrawal
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub account: Address,
+    pub direction: FlowDirection,
+    /// Amount in the base asset's smallest unit
+    pub amount: U256,
+}
+
+/// A block and its hash as seen by the scanner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub number: u64,
+    /// `None` when the log source could not tell
+    pub hash: Option<H256>,
+}
+
+/// Blocks scanned within `scanner.confirmations` of the head, which a reorg may still replace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TailSegment {
+    pub from_block: u64,
+    /// Last block of the segment, with its hash read before the logs
+    pub to_block: BlockRef,
+    /// Accounts the segment added to the index, removed again if it is rolled back
+    pub added: BTreeSet<Address>,
+}
+
+/// Persisted set of accounts that have interacted with a Comet as possible borrowers
+///
+/// Blocks at least `scanner.confirmations` behind the head when scanned are final. Newer ones
+/// form the tail, whose segments are checked against the chain on the next scan: blocks link
+/// to their parents, so a segment whose last block still has the same hash is unchanged, and
+/// the segments after the newest unchanged one are rolled back and rescanned.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BorrowerIndex {
+    /// Comet proxy the index belongs to
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub comet_address: Address,
+    /// Last block whose logs have been fully scanned, tail included
+    pub last_scanned_block: Option<u64>,
+    /// Last final block and its hash
+    #[serde(default)]
+    pub finalized: Option<BlockRef>,
+    /// Segments scanned past `finalized`, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tail: Vec<TailSegment>,
+    /// Discovered accounts, tail included
+    pub borrowers: BTreeSet<Address>,
+    /// Base flows of the last `scanner.flow_blocks` blocks scanned, tail included, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flows: Vec<BaseFlow>,
+}
+
+impl BorrowerIndex {
+    /// Create an empty index for `comet_address`
+    pub fn new(comet_address: Address) -> Self {
+        Self {
+            comet_address,
+            ..Default::default()
+        }
+    }
+
+    /// Load an index from disk, or start an empty one if the file does not exist
+    pub fn load_or_new(path: &Path, comet_address: Address) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(comet_address));
+        }
+        let content = fs::read_to_string(path).map_err(|e| RiskEngineError::io(path, e))?;
+        let index: Self = serde_json::from_str(&content)
+            .map_err(|e| RiskEngineError::serialization(format!("borrower index {}", path.display()), e))?;
+        if index.comet_address != comet_address {
+            return Err(RiskEngineError::config(
+                "scanner.index_path",
+                format!(
+                    "borrower index {} belongs to {:?}, not {:?}",
+                    path.display(),
+                    index.comet_address,
+                    comet_address
+                ),
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Write the index to disk atomically (temp file + rename)
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| RiskEngineError::serialization("borrower index", e))?;
+        fs::write(&tmp, content).map_err(|e| RiskEngineError::io(&tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| RiskEngineError::io(path, e))?;
+        Ok(())
+    }
+
+    /// Net base flow into the Comet of each block with flows, in the base asset's smallest
+    /// unit; negative for blocks with more withdrawn than supplied
+    pub fn net_flows(&self) -> BTreeMap<u64, I256> {
+        let mut net = BTreeMap::new();
+        for flow in &self.flows {
+            let amount = I256::from_raw(flow.amount);
+            let entry = net.entry(flow.block).or_insert(I256::zero());
+            *entry = match flow.direction {
+                FlowDirection::In => entry.saturating_add(amount),
+                FlowDirection::Out => entry.saturating_sub(amount),
+            };
+        }
+        net
+    }
+}
+
+/// Summary of one `BorrowerScanner::scan` call
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanSummary {
+    /// First block scanned in this call
+    pub from_block: u64,
+    /// Last block scanned in this call
+    pub to_block: u64,
+    /// Accounts added to the index by this call, net of those rolled back
+    pub new_borrowers: usize,
+    /// First block rescanned because a reorg replaced it, if any
+    pub reorg_from_block: Option<u64>,
+    /// Accounts removed with the rolled back blocks
+    pub rolled_back_borrowers: usize,
+    /// Block ranges split in two because the provider refused them
+    pub range_splits: usize,
+    /// Blocks per request at the end of the scan, below the configured size after splits
+    pub chunk_size: u64,
+}
+
+/// Scans Comet logs in block chunks to discover borrowers
+pub struct BorrowerScanner {
+    source: Arc<dyn LogSource>,
+    chunk_size: u64,
+    concurrency: usize,
+    confirmations: u64,
+    flow_blocks: u64,
+    index_path: Option<PathBuf>,
+    progress: ProgressReporter,
+}
+
+impl BorrowerScanner {
+    /// Create a scanner reading up to `chunk_size` blocks per request, one request at a time
+    pub fn new(source: Arc<dyn LogSource>, chunk_size: u64, index_path: Option<PathBuf>) -> Self {
+        Self {
+            source,
+            chunk_size: chunk_size.max(1),
+            concurrency: 1,
+            confirmations: 0,
+            flow_blocks: 0,
+            index_path,
+            progress: ProgressReporter::new(),
+        }
+    }
+
+    /// Treat the last `confirmations` blocks before the head as reorg-able tail
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Also index the base flows of the last `blocks` blocks before the head
+    pub fn with_flow_blocks(mut self, blocks: u64) -> Self {
+        self.flow_blocks = blocks;
+        self
+    }
+
+    /// Keep up to `concurrency` chunk requests in flight
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Report the blocks scanned to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Scan from the block after `index.last_scanned_block` (or `start_block`) up to the chain head
+    ///
+    /// The tail of the previous scan is verified first and rolled back where a reorg replaced
+    /// it. Chunks end at the last confirmed block, so each is either final or a tail segment.
+    /// Up to `concurrency` chunks are requested at once and applied to the index in block
+    /// order, with the base flows of those within `flow_blocks` of the head; a chunk the provider refuses as too large is split, and the chunks after it use
+    /// the smaller size. Cancellation is checked at every chunk boundary; on cancellation the
+    /// progress made so far is flushed to the index file and `RiskEngineError::Cancelled` is
+    /// returned.
+    #[instrument(level = "debug", name = "borrower_scan", skip_all, fields(comet = ?index.comet_address, from_block, to_block))]
+    pub async fn scan(
+        &self,
+        index: &mut BorrowerIndex,
+        start_block: u64,
+        cancel: &CancellationToken,
+    ) -> Result<ScanSummary> {
+        let head = self.source.head_block().await?;
+        let before = index.borrowers.len();
+        let (reorg_from_block, rolled_back_borrowers) = self.verify_tail(index).await?;
+        let confirmed = head.saturating_sub(self.confirmations);
+        self.finalize_tail(index, confirmed);
+        let from_block = index.last_scanned_block.map_or(start_block, |b| b + 1);
+        tracing::Span::current().record("from_block", from_block).record("to_block", head);
+        info!("Scanning blocks {}-{} for borrowers of {}", from_block, head, crate::utils::to_checksum_address(&index.comet_address));
+        let task = self.progress.start(Stage::BorrowerScan, (head + 1).saturating_sub(from_block));
+        let started = Instant::now();
+
+        let comet = index.comet_address;
+        let flows_from = (head + 1).saturating_sub(self.flow_blocks);
+        let (size, splits) = (AtomicU64::new(self.chunk_size), AtomicUsize::new(0));
+        let (size, splits) = (&size, &splits);
+        // Chunks are cut as they are requested, so those after a split use the smaller size
+        let chunks = stream::unfold(from_block, |chunk_start| {
+            let chunk = (chunk_start <= head).then(|| {
+                let limit = if chunk_start > confirmed { head } else { confirmed };
+                let chunk_end = (chunk_start + size.load(Ordering::Relaxed) - 1).min(limit);
+                ((chunk_start, chunk_end), chunk_end + 1)
+            });
+            std::future::ready(chunk)
+        });
+        let mut results = chunks
+            .map(|(chunk_start, chunk_end)| async move {
+                // Read before the logs: a reorg in between then shows as a changed hash next time
+                let tail_end = if chunk_start > confirmed { Some(self.block_ref(chunk_end).await?) } else { None };
+                let found = self
+                    .fetch_range(chunk_start, chunk_end, size, splits, |from, to| self.source.borrowers_in_range(comet, from, to))
+                    .await?;
+                let flows = if self.flow_blocks > 0 && chunk_end >= flows_from {
+                    let from = chunk_start.max(flows_from);
+                    self.fetch_range(from, chunk_end, size, splits, |from, to| self.source.base_flows_in_range(comet, from, to))
+                        .await?
+                } else {
+                    Vec::new()
+                };
+                Ok::<_, RiskEngineError>((chunk_start, chunk_end, tail_end, found, flows))
+            })
+            .buffered(self.concurrency);
+
+        let mut chunks = 0;
+        loop {
+            let next = tokio::select! {
+                _ = cancel.cancelled() => None,
+                next = results.next() => Some(next),
+            };
+            let (chunk_start, chunk_end, tail_end, found, flows) = match next {
+                Some(Some(Ok(chunk))) => chunk,
+                Some(None) => break,
+                Some(Some(Err(e))) => {
+                    self.flush(index)?;
+                    return Err(e);
+                }
+                None => {
+                    self.flush(index)?;
+                    info!("Borrower scan cancelled after block {:?}", index.last_scanned_block);
+                    return Err(RiskEngineError::Cancelled);
+                }
+            };
+
+            match tail_end {
+                Some(to_block) => {
+                    let added: BTreeSet<Address> = found.into_iter().filter(|a| !index.borrowers.contains(a)).collect();
+                    index.borrowers.extend(&added);
+                    index.tail.push(TailSegment { from_block: chunk_start, to_block, added });
+                }
+                None => {
+                    index.borrowers.extend(found);
+                    if chunk_end == confirmed {
+                        index.finalized = Some(self.block_ref(chunk_end).await?);
+                    }
+                }
+            }
+            index.flows.extend(flows);
+            index.last_scanned_block = Some(chunk_end);
+            debug!("Scanned blocks {}-{} ({} borrowers known)", chunk_start, chunk_end, index.borrowers.len());
+            task.set(chunk_end + 1 - from_block);
+
+            chunks += 1;
+            if chunks % FLUSH_EVERY_CHUNKS == 0 {
+                self.flush(index)?;
+            }
+        }
+
+        index.flows.retain(|flow| flow.block >= flows_from);
+        self.flush(index)?;
+        let blocks = (head + 1).saturating_sub(from_block);
+        let elapsed = started.elapsed().as_secs_f64();
+        let range_splits = splits.load(Ordering::Relaxed);
+        debug!(
+            "Scanned {} blocks in {:.1}s ({:.0} blocks/sec, {} range splits)",
+            blocks,
+            elapsed,
+            blocks as f64 / elapsed.max(f64::EPSILON),
+            range_splits
+        );
+        Ok(ScanSummary {
+            from_block,
+            to_block: head,
+            new_borrowers: index.borrowers.len().saturating_sub(before),
+            reorg_from_block,
+            rolled_back_borrowers,
+            range_splits,
+            chunk_size: size.load(Ordering::Relaxed),
+        })
+    }
+
+    /// What `read` finds in blocks `from..=to`, splitting the range in two while the provider
+    /// refuses it as too large and lowering `size` to the halves' length
+    async fn fetch_range<T, F, Fut>(&self, from: u64, to: u64, size: &AtomicU64, splits: &AtomicUsize, read: F) -> Result<Vec<T>>
+    where
+        F: Fn(u64, u64) -> Fut,
+        Fut: Future<Output = Result<Vec<T>>>,
+    {
+        let mut found = Vec::new();
+        // Ranges still to read, the next one last
+        let mut pending = vec![(from, to)];
+        while let Some((from, to)) = pending.pop() {
+            match read(from, to).await {
+                Ok(items) => found.extend(items),
+                Err(e) if from < to && is_range_error(&e) => {
+                    let middle = from + (to - from) / 2;
+                    debug!("Provider refused blocks {}-{}, splitting at {}: {}", from, to, middle, e);
+                    size.fetch_min(middle + 1 - from, Ordering::Relaxed);
+                    splits.fetch_add(1, Ordering::Relaxed);
+                    pending.push((middle + 1, to));
+                    pending.push((from, middle));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Roll back the tail segments a reorg replaced, newest first, returning the first block
+    /// it replaced and the accounts removed
+    ///
+    /// Segments without a hash to compare are rolled back and rescanned too, but do not count
+    /// as replaced.
+    async fn verify_tail(&self, index: &mut BorrowerIndex) -> Result<(Option<u64>, usize)> {
+        let mut reorg_from_block = None;
+        let mut rolled_back = 0;
+        while let Some(segment) = index.tail.last() {
+            let current = self.source.block_hash(segment.to_block.number).await?;
+            let replaced = match (current, segment.to_block.hash) {
+                (Some(current), Some(scanned)) if current == scanned => break,
+                (None, None) | (Some(_), None) => false,
+                _ => true,
+            };
+            let segment = index.tail.pop().expect("checked above");
+            for account in &segment.added {
+                index.borrowers.remove(account);
+            }
+            index.flows.retain(|flow| flow.block < segment.from_block);
+            index.last_scanned_block = segment.from_block.checked_sub(1);
+            if replaced {
+                reorg_from_block = Some(segment.from_block);
+                rolled_back += segment.added.len();
+            }
+        }
+
+        // The final block only changes in a reorg deeper than the confirmation depth
+        if let Some(finalized) = index.finalized.filter(|f| f.hash.is_some() && index.tail.is_empty()) {
+            let current = self.source.block_hash(finalized.number).await?;
+            if current.is_some() && current != finalized.hash {
+                warn!(
+                    "Final block {} of the borrower index of {} was replaced: the reorg went deeper than scanner.confirmations; accounts found before it are kept",
+                    finalized.number, crate::utils::to_checksum_address(&index.comet_address)
+                );
+            }
+        }
+        Ok((reorg_from_block, rolled_back))
+    }
+
+    /// Make the tail segments ending at or before `confirmed` final
+    fn finalize_tail(&self, index: &mut BorrowerIndex, confirmed: u64) {
+        let count = index.tail.iter().take_while(|segment| segment.to_block.number <= confirmed).count();
+        if let Some(last) = index.tail.drain(..count).next_back() {
+            index.finalized = Some(last.to_block);
+        }
+    }
+
+    async fn block_ref(&self, number: u64) -> Result<BlockRef> {
+        Ok(BlockRef { number, hash: self.source.block_hash(number).await? })
+    }
+
+    fn flush(&self, index: &BorrowerIndex) -> Result<()> {
+        match &self.index_path {
+            Some(path) => index.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    /// Log source that takes `delay` per chunk and returns one account per chunk
+    struct SlowSource {
+        head: u64,
+        delay: Duration,
+        in_flight: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LogSource for SlowSource {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, from: u64, _to: u64) -> Result<Vec<Address>> {
+            self.in_flight.fetch_add(1, Ordering::SeqCst);
+            // Decrement even when the future is dropped mid-sleep
+            struct Guard(Arc<AtomicUsize>);
+            impl Drop for Guard {
+                fn drop(&mut self) {
+                    self.0.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+            let _guard = Guard(self.in_flight.clone());
+            tokio::time::sleep(self.delay).await;
+            Ok(vec![Address::from_low_u64_be(from + 1)])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_scan_is_resumable() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let source = Arc::new(SlowSource { head: 99, delay: Duration::ZERO, in_flight });
+        let scanner = BorrowerScanner::new(source, 10, None);
+        let mut index = BorrowerIndex::new(Address::from_low_u64_be(7));
+
+        let progress = ProgressReporter::new();
+        let mut reports = progress.subscribe();
+        let scanner = scanner.with_progress(progress);
+        let summary = scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(summary.new_borrowers, 10);
+        assert_eq!(index.last_scanned_block, Some(99));
+        // Finished scans leave the reporter idle
+        assert!(reports.has_changed().unwrap());
+        assert_eq!(*reports.borrow_and_update(), None);
+
+        // Resuming at the head scans nothing new
+        let summary = scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(summary.from_block, 100);
+        assert_eq!(summary.new_borrowers, 0);
+    }
+
+    /// Chain whose blocks each carry a hash and the accounts that withdrew in them
+    struct ReorgChain {
+        blocks: std::sync::Mutex<Vec<(H256, Vec<Address>)>>,
+    }
+
+    impl ReorgChain {
+        fn new(length: u64) -> Self {
+            let blocks = (0..length).map(|n| (H256::from_low_u64_be(n + 1), Vec::new())).collect();
+            Self { blocks: std::sync::Mutex::new(blocks) }
+        }
+
+        fn withdraw(&self, block: u64, account: Address) {
+            self.blocks.lock().unwrap()[block as usize].1.push(account);
+        }
+
+        /// Replace the blocks from `block` on with `length` new ones
+        fn reorg(&self, block: u64, length: u64) {
+            let mut blocks = self.blocks.lock().unwrap();
+            blocks.truncate(block as usize);
+            blocks.extend((block..block + length).map(|n| (H256::from_low_u64_be(0xf000 + n), Vec::new())));
+        }
+    }
+
+    #[async_trait]
+    impl LogSource for ReorgChain {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(self.blocks.lock().unwrap().len() as u64 - 1)
+        }
+
+        async fn block_hash(&self, number: u64) -> Result<Option<H256>> {
+            Ok(self.blocks.lock().unwrap().get(number as usize).map(|(hash, _)| *hash))
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+            let blocks = self.blocks.lock().unwrap();
+            Ok(blocks[from as usize..=to as usize].iter().flat_map(|(_, accounts)| accounts.clone()).collect())
+        }
+
+        /// Each withdrawal takes out as many units as its block number, twelve seconds a block
+        async fn base_flows_in_range(&self, _comet: Address, from: u64, to: u64) -> Result<Vec<BaseFlow>> {
+            let blocks = self.blocks.lock().unwrap();
+            let mut flows = Vec::new();
+            for block in from..=to {
+                for account in &blocks[block as usize].1 {
+                    flows.push(BaseFlow {
+                        block,
+                        timestamp: DateTime::from_timestamp(block as i64 * 12, 0).unwrap(),
+                        tx_hash: H256::from_low_u64_be(block),
+                        account: *account,
+                        direction: FlowDirection::Out,
+                        amount: U256::from(block),
+                    });
+                }
+            }
+            Ok(flows)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reorged_tail_is_rolled_back_and_rescanned() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("borrowers.json");
+        let chain = Arc::new(ReorgChain::new(100));
+        let (kept, phantom, replacement) = (Address::repeat_byte(1), Address::repeat_byte(2), Address::repeat_byte(3));
+        chain.withdraw(50, kept);
+        chain.withdraw(95, phantom);
+        let scanner = BorrowerScanner::new(chain.clone(), 20, Some(path.clone())).with_confirmations(10);
+        let comet = Address::from_low_u64_be(7);
+        let mut index = BorrowerIndex::new(comet);
+
+        scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(index.finalized, Some(BlockRef { number: 89, hash: Some(H256::from_low_u64_be(90)) }));
+        assert_eq!(index.tail.len(), 1);
+        assert_eq!((index.tail[0].from_block, index.tail[0].to_block.number), (90, 99));
+        assert_eq!(index.borrowers, BTreeSet::from([kept, phantom]));
+
+        // Blocks 93 on are replaced by a longer fork without the phantom withdrawal
+        chain.reorg(93, 9);
+        chain.withdraw(97, replacement);
+        let summary = scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!((summary.reorg_from_block, summary.rolled_back_borrowers), (Some(90), 1));
+        assert_eq!(summary.from_block, 90);
+        assert_eq!(index.borrowers, BTreeSet::from([kept, replacement]));
+        assert_eq!(index.finalized.map(|f| f.number), Some(91));
+        assert_eq!(BorrowerIndex::load_or_new(&path, comet).unwrap(), index);
+
+        // An unchanged tail is kept and becomes final as the chain grows past it
+        chain.reorg(102, 20);
+        let summary = scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!((summary.reorg_from_block, summary.from_block), (None, 102));
+        assert_eq!(index.finalized.map(|f| f.number), Some(111));
+        assert!(index.tail.iter().all(|segment| segment.from_block > 111));
+        assert_eq!(index.borrowers, BTreeSet::from([kept, replacement]));
+    }
+
+    #[tokio::test]
+    async fn test_base_flows_near_the_head_are_kept_and_rolled_back() {
+        let chain = Arc::new(ReorgChain::new(100));
+        let (old, recent, phantom, replacement) = (Address::repeat_byte(1), Address::repeat_byte(2), Address::repeat_byte(3), Address::repeat_byte(4));
+        chain.withdraw(50, old);
+        chain.withdraw(75, recent);
+        chain.withdraw(95, phantom);
+        let scanner = BorrowerScanner::new(chain.clone(), 20, None).with_confirmations(10).with_flow_blocks(30);
+        let mut index = BorrowerIndex::new(Address::from_low_u64_be(7));
+
+        // Borrowers come from every block, flows only from the last 30
+        scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(index.borrowers.len(), 3);
+        assert_eq!(index.flows.iter().map(|flow| (flow.block, flow.account)).collect::<Vec<_>>(), [(75, recent), (95, phantom)]);
+
+        // A reorg takes the flows of the replaced blocks with it
+        chain.reorg(93, 9);
+        chain.withdraw(97, replacement);
+        scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(index.flows.iter().map(|flow| flow.account).collect::<Vec<_>>(), [recent, replacement]);
+        assert_eq!(index.net_flows(), BTreeMap::from([(75, I256::from(-75)), (97, I256::from(-97))]));
+
+        // Flows older than the last 30 blocks are dropped as the chain grows
+        chain.reorg(102, 20);
+        scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(index.flows.iter().map(|flow| flow.block).collect::<Vec<_>>(), [97]);
+    }
+
+    /// Log source that refuses ranges over `max_range` blocks and has one withdrawal every
+    /// 1000 blocks
+    struct RangeLimitedSource {
+        head: u64,
+        max_range: u64,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        range_errors: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LogSource for RangeLimitedSource {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, from: u64, to: u64) -> Result<Vec<Address>> {
+            if to - from + 1 > self.max_range {
+                self.range_errors.fetch_add(1, Ordering::SeqCst);
+                let error = ethers::providers::ProviderError::CustomError(format!(
+                    "query exceeds max block range {}",
+                    self.max_range
+                ));
+                return Err(RiskEngineError::Provider { host: "mock".to_string(), source: error });
+            }
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok((from.div_ceil(1000) * 1000..=to).step_by(1000).map(|block| Address::from_low_u64_be(block + 1)).collect())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_scan_splits_refused_ranges_without_losing_logs() {
+        let source = Arc::new(RangeLimitedSource {
+            head: 999_999,
+            max_range: 5_000,
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+            range_errors: AtomicUsize::new(0),
+        });
+        let scanner = BorrowerScanner::new(source.clone(), 20_000, None).with_concurrency(8);
+        let mut index = BorrowerIndex::new(Address::from_low_u64_be(7));
+
+        let summary = scanner.scan(&mut index, 0, &CancellationToken::new()).await.unwrap();
+        assert_eq!(index.borrowers.len(), 1_000);
+        assert_eq!(index.last_scanned_block, Some(999_999));
+        assert!(source.max_in_flight.load(Ordering::SeqCst) > 1);
+        assert_eq!(summary.chunk_size, 5_000);
+        assert_eq!(summary.range_splits, source.range_errors.load(Ordering::SeqCst));
+        // Only chunks cut before the first split are refused; the other 190-odd are not
+        assert!(summary.range_splits > 0 && summary.range_splits < 50, "{}", summary.range_splits);
+
+        let timeout = RiskEngineError::Provider {
+            host: "mock".to_string(),
+            source: ethers::providers::ProviderError::CustomError("request timed out".to_string()),
+        };
+        assert!(!is_range_error(&timeout));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_scan_persists_partial_index() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("borrowers.json");
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let source = Arc::new(SlowSource {
+            head: 1_000_000,
+            delay: Duration::from_millis(10),
+            in_flight: in_flight.clone(),
+        });
+        let scanner = BorrowerScanner::new(source, 1_000, Some(path.clone()));
+        let comet = Address::from_low_u64_be(7);
+        let mut index = BorrowerIndex::new(comet);
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(55)).await;
+            trigger.cancel();
+        });
+
+        let err = scanner.scan(&mut index, 0, &cancel).await.unwrap_err();
+        assert!(err.is_cancelled());
+        assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+
+        let persisted = BorrowerIndex::load_or_new(&path, comet).unwrap();
+        assert_eq!(persisted, index);
+        let last = persisted.last_scanned_block.unwrap();
+        assert!(last > 0 && last < 1_000_000);
+        assert!(!persisted.borrowers.is_empty());
+    }
+}
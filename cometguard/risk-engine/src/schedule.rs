@@ -0,0 +1,190 @@
+//! Per-market assessment intervals for `RiskEngine::run_scheduled`
+//!
+//! A market that barely moves does not need the cadence of the one that worries you.
+//! `MarketSchedule` keeps each market's interval, the `--interval` of the command unless
+//! `schedule.intervals` sets one, and when it is due next. The scheduler sleeps until the
+//! earliest due market, then assesses every market due by then as one batch, taking along
+//! those due within `BATCH_WINDOW` (or a tenth of their interval, if shorter) so that nearby
+//! due times share a run. Batches go through the same bounded, budgeted run as `assess_risks`:
+//! at most `performance.max_concurrent_assessments` markets at a time, sharing `rpc.budget`.
+//! A market is due again its interval after its previous assessment finished, or failed.
+
+use crate::models::Market;
+use crate::risk::RiskAssessment;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Markets due this soon join the batch being assessed
+pub const BATCH_WINDOW: Duration = Duration::from_secs(5);
+
+/// When the scheduler assessed a market and assesses it next, as kept in
+/// `RiskAssessment::cadence`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cadence {
+    /// Interval of the market, in milliseconds
+    pub interval_ms: u64,
+    /// Previous scheduled assessment of the market; `None` for its first since the start
+    pub previous_assessed_at: Option<DateTime<Utc>>,
+    pub next_due_at: DateTime<Utc>,
+}
+
+/// Interval and next due time of each market
+#[derive(Debug, Clone)]
+pub struct MarketSchedule {
+    default: Duration,
+    intervals: HashMap<Address, Duration>,
+    next_due: HashMap<Address, Instant>,
+    last_assessed: HashMap<Address, DateTime<Utc>>,
+    /// Markets whose first assessment was checked against their interval
+    checked: HashSet<Address>,
+}
+
+impl MarketSchedule {
+    /// Schedule assessing markets every `default`, or at their entry of `intervals`
+    pub fn new(default: Duration, intervals: HashMap<Address, Duration>) -> Self {
+        Self {
+            default,
+            intervals,
+            next_due: HashMap::new(),
+            last_assessed: HashMap::new(),
+            checked: HashSet::new(),
+        }
+    }
+
+    /// Replace the intervals, e.g. after a configuration reload; due times already set stay
+    pub fn set_intervals(&mut self, intervals: HashMap<Address, Duration>) {
+        self.intervals = intervals;
+    }
+
+    /// Interval of `market`
+    pub fn interval(&self, market: Address) -> Duration {
+        self.intervals.get(&market).copied().unwrap_or(self.default)
+    }
+
+    /// The markets of `markets` due at `now`: never assessed, past their due time or due within
+    /// the batch window; markets no longer listed are forgotten
+    pub fn due(&mut self, markets: Vec<Market>, now: Instant) -> Vec<Market> {
+        let listed: HashSet<Address> = markets.iter().map(|market| market.comet_address).collect();
+        self.next_due.retain(|market, _| listed.contains(market));
+        markets
+            .into_iter()
+            .filter(|market| match self.next_due.get(&market.comet_address) {
+                Some(due) => {
+                    let window = BATCH_WINDOW.min(self.interval(market.comet_address) / 10);
+                    due.saturating_duration_since(now) <= window
+                }
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Record that `assessment` finished at `now`, returning its cadence
+    pub fn assessed(&mut self, assessment: &RiskAssessment, now: Instant) -> Cadence {
+        let market = assessment.market_address;
+        let interval = self.interval(market);
+        self.next_due.insert(market, now + interval);
+        let previous_assessed_at = self.last_assessed.insert(market, assessment.timestamp);
+        Cadence {
+            interval_ms: interval.as_millis() as u64,
+            previous_assessed_at,
+            next_due_at: Utc::now() + chrono::Duration::from_std(interval).unwrap_or(chrono::Duration::MAX),
+        }
+    }
+
+    /// Record that assessing `market` failed at `now`; it is retried after its interval
+    pub fn failed(&mut self, market: Address, now: Instant) {
+        self.next_due.insert(market, now + self.interval(market));
+    }
+
+    /// When the next market is due; `None` before any was assessed
+    pub fn next_due(&self) -> Option<Instant> {
+        self.next_due.values().min().copied()
+    }
+
+    /// Shortest interval among `markets`; the default for none
+    pub fn shortest_interval(&self, markets: impl IntoIterator<Item = Address>) -> Duration {
+        markets.into_iter().map(|market| self.interval(market)).min().unwrap_or(self.default)
+    }
+
+    /// Warning when the checks of the first assessment of a market took longer than its
+    /// interval, which the scheduler then cannot keep; `None` afterwards
+    pub fn interval_warning(&mut self, assessment: &RiskAssessment) -> Option<String> {
+        if !self.checked.insert(assessment.market_address) {
+            return None;
+        }
+        let interval = self.interval(assessment.market_address);
+        let took = checks_duration(assessment);
+        (!interval.is_zero() && took >= interval).then(|| {
+            format!(
+                "{} is scheduled every {}ms, but its checks took {}ms; it will be assessed back to back. Raise its entry in schedule.intervals",
+                assessment.market_name,
+                interval.as_millis(),
+                took.as_millis()
+            )
+        })
+    }
+}
+
+/// Time the checks of `assessment` took, from its `timings`; they run one after another
+pub fn checks_duration(assessment: &RiskAssessment) -> Duration {
+    Duration::from_millis(assessment.timings.values().map(|timing| timing.ms).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::risk::CheckTiming;
+    use crate::testing;
+
+    /// `testing::assessment` of `market`
+    fn assessment(market: &Market) -> RiskAssessment {
+        RiskAssessment {
+            market_name: market.name.clone(),
+            market_address: market.comet_address,
+            ..testing::assessment(10, Vec::new())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_markets_are_due_at_their_own_interval() {
+        let usdc = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap().data().markets[0].clone();
+        let usdbc = Market { name: "USDbC".to_string(), comet_address: Address::repeat_byte(0xb1), ..usdc.clone() };
+        let markets = || vec![usdc.clone(), usdbc.clone()];
+        let minute = Duration::from_secs(60);
+        let mut schedule = MarketSchedule::new(Duration::from_secs(3600), [(usdbc.comet_address, minute)].into());
+
+        // Everything is due at the start
+        let start = Instant::now();
+        assert_eq!(schedule.due(markets(), start).len(), 2);
+        let first = schedule.assessed(&assessment(&usdc), start);
+        schedule.assessed(&assessment(&usdbc), start);
+        assert_eq!((first.interval_ms, first.previous_assessed_at), (3_600_000, None));
+        assert_eq!(schedule.next_due(), Some(start + minute));
+        assert!(schedule.due(markets(), start + Duration::from_secs(30)).is_empty());
+
+        // Only USDbC a minute later, then USDC as well once its hour is nearly up
+        let due = schedule.due(markets(), start + minute);
+        assert_eq!(due.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), ["USDbC"]);
+        let again = schedule.assessed(&assessment(&usdbc), start + minute);
+        assert!(again.previous_assessed_at.is_some());
+        assert_eq!(schedule.due(markets(), start + Duration::from_secs(3596)).len(), 2);
+        assert_eq!(schedule.shortest_interval([usdc.comet_address, usdbc.comet_address]), minute);
+
+        // A market no longer listed is forgotten
+        schedule.due(vec![usdbc.clone()], start + minute);
+        assert_eq!(schedule.next_due(), Some(start + minute * 2));
+
+        // Checks slower than the interval are worth a warning, once
+        let mut slow = assessment(&usdbc);
+        slow.timings = [("price_volatility".to_string(), CheckTiming { ms: 45_000, rpc_calls: 3 }), ("utilization".to_string(), CheckTiming { ms: 20_000, rpc_calls: 1 })].into();
+        let warning = schedule.interval_warning(&slow).unwrap();
+        assert!(warning.starts_with("USDbC is scheduled every 60000ms, but its checks took 65000ms"), "{}", warning);
+        assert_eq!(schedule.interval_warning(&slow), None);
+        assert_eq!(schedule.interval_warning(&assessment(&usdc)), None);
+    }
+}
@@ -0,0 +1,657 @@
+//! JSON API over the engine's markets, assessments and positions
+//!
+//! Every endpoint except `/healthz` and `/readyz` requires `Authorization: Bearer <token>`
+//! when `server.auth_token` is set. Both serve the engine's `Diagnostics`: `/healthz` along
+//! with the freshness of its data and failing only when that is stale, `/readyz` failing when
+//! any check fails. Errors are returned as `{"error": "..."}` with a status
+//! derived from the `RiskEngineError` variant. `/events` streams the engine's `RiskEvent`s
+//! as server-sent events, and `POST /alerts/{fingerprint}/ack` acknowledges a finding.
+//! `GET /findings/{fingerprint}/annotations` lists the notes operators attached to a finding
+//! and `POST` to it, with `{"note": "...", "author": "..."}`, attaches another.
+
+use crate::alerts::AlertRecord;
+use crate::annotations::Annotation;
+use crate::diagnostics::Diagnostics;
+use crate::error::{Result, RiskEngineError};
+use crate::events::RiskEvent;
+use crate::models::{Market, UserPosition};
+use crate::ranking::{HealthFactorFilter, PositionSort, TopPositions};
+use crate::risk::{RiskAssessment, RiskSeverity};
+use crate::utils::{expand_env, parse_address};
+use crate::RiskEngine;
+pub use crate::{Health, HealthStatus};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use ethers::types::Address;
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Position of one account in one market, as returned by `GET /users/{address}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPosition {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub position: UserPosition,
+    /// Base asset the market can pay out to withdrawals (`Market::available_liquidity`)
+    #[serde(default)]
+    pub available_liquidity: f64,
+}
+
+/// Running API server; it stops when the engine's cancellation token fires
+pub struct ApiServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Bind `addr` and start serving `engine`
+    pub fn spawn(engine: Arc<RiskEngine>, addr: SocketAddr) -> Result<Self> {
+        let auth_token = match &engine.config().server.auth_token {
+            Some(token) => {
+                let token = expand_env("server.auth_token", token)?;
+                if token.is_empty() {
+                    return Err(RiskEngineError::config("server.auth_token", "the token is empty"));
+                }
+                Some(token)
+            }
+            None => None,
+        };
+        let cancel = engine.cancellation_token();
+        let app = router(Arc::new(ApiState { engine, auth_token }));
+
+        let server = axum::Server::try_bind(&addr)
+            .map_err(|e| RiskEngineError::config("server.bind_address", format!("cannot bind {}: {}", addr, e)))?
+            .serve(app.into_make_service());
+        let local_addr = server.local_addr();
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.with_graceful_shutdown(cancel.cancelled_owned()).await {
+                warn!("API server failed: {}", e);
+            }
+        });
+
+        info!("Serving the API on http://{}", local_addr);
+        Ok(Self { local_addr, task })
+    }
+
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait until the server has stopped and finished in-flight requests
+    pub async fn wait(self) {
+        let _ = self.task.await;
+    }
+}
+
+struct ApiState {
+    engine: Arc<RiskEngine>,
+    auth_token: Option<String>,
+}
+
+type SharedState = Arc<ApiState>;
+
+fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/markets", get(markets))
+        .route("/markets/:address/assessment", get(assessment))
+        .route("/markets/:address/positions", get(positions))
+        .route("/markets/:address/top-positions", get(top_positions))
+        .route("/users/:address", get(user))
+        .route("/events", get(events))
+        .route("/alerts/:fingerprint/ack", post(acknowledge))
+        .route("/findings/:fingerprint/annotations", get(annotations).post(annotate))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .layer(middleware::from_fn(log_request))
+        .with_state(state)
+}
+
+/// HTTP status for `error`
+fn status_for(error: &RiskEngineError) -> StatusCode {
+    match error {
+        RiskEngineError::Parse { .. } => StatusCode::BAD_REQUEST,
+        RiskEngineError::NotFound { .. } => StatusCode::NOT_FOUND,
+        RiskEngineError::Unavailable { .. } | RiskEngineError::Cancelled => StatusCode::SERVICE_UNAVAILABLE,
+        RiskEngineError::Provider { .. } | RiskEngineError::ContractCall { .. } | RiskEngineError::Assessment(_) => {
+            StatusCode::BAD_GATEWAY
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+struct ApiError(RiskEngineError);
+
+impl From<RiskEngineError> for ApiError {
+    fn from(error: RiskEngineError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = status_for(&self.0);
+        if status.is_server_error() {
+            warn!("API request failed: {}", self.0);
+        }
+        (status, Json(serde_json::json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+async fn log_request<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().clone();
+    // Query strings are left out of the log
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    info!("{} {} -> {} in {:?}", method, path, response.status().as_u16(), started.elapsed());
+    response
+}
+
+async fn require_token<B>(State(state): State<SharedState>, request: Request<B>, next: Next<B>) -> Response {
+    let Some(expected) = &state.auth_token else {
+        return next.run(request).await;
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if presented.is_some_and(|token| tokens_match(token, expected)) {
+        return next.run(request).await;
+    }
+
+    let mut response = (StatusCode::UNAUTHORIZED, Json(serde_json::json!({ "error": "missing or invalid bearer token" })))
+        .into_response();
+    response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+/// Compare tokens without returning early on the first differing byte
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn find_market(engine: &RiskEngine, address: Address) -> Result<Market> {
+    engine
+        .provider()
+        .get_markets()
+        .await?
+        .into_iter()
+        .find(|m| m.comet_address == address)
+        .ok_or_else(|| RiskEngineError::NotFound { kind: "market", id: crate::utils::to_checksum_address(&address) })
+}
+
+async fn markets(State(state): State<SharedState>) -> ApiResult<Vec<Market>> {
+    Ok(Json(state.engine.provider().get_markets().await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct AssessmentParams {
+    /// Assess now instead of returning the cached assessment
+    #[serde(default)]
+    fresh: bool,
+}
+
+async fn assessment(
+    State(state): State<SharedState>,
+    Path(address): Path<String>,
+    Query(params): Query<AssessmentParams>,
+) -> ApiResult<RiskAssessment> {
+    let address = parse_address(&address)?;
+    if !params.fresh {
+        if let Some(cached) = state.engine.latest_assessment(address) {
+            return Ok(Json(cached));
+        }
+    }
+    Ok(Json(state.engine.assess_market_by_address(address).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct PositionsParams {
+    /// Only return positions with a health factor at or below this
+    max_hf: Option<f64>,
+}
+
+/// Positions of the tracked accounts in a market, riskiest first
+async fn positions(
+    State(state): State<SharedState>,
+    Path(address): Path<String>,
+    Query(params): Query<PositionsParams>,
+) -> ApiResult<Vec<UserPosition>> {
+    let market = find_market(&state.engine, parse_address(&address)?).await?;
+    let mut positions = state.engine.tracked_positions(&market).await?;
+    positions.retain(|p| params.max_hf.is_none_or(|max| p.health_factor <= max));
+    Ok(Json(positions))
+}
+
+#[derive(Debug, Deserialize)]
+struct TopPositionsParams {
+    /// `borrow` (default) or `supply`
+    sort: Option<PositionSort>,
+    #[serde(default = "default_top_limit")]
+    limit: usize,
+    min_hf: Option<f64>,
+    max_hf: Option<f64>,
+}
+
+fn default_top_limit() -> usize {
+    20
+}
+
+/// Largest tracked positions in a market by borrow or supply
+async fn top_positions(
+    State(state): State<SharedState>,
+    Path(address): Path<String>,
+    Query(params): Query<TopPositionsParams>,
+) -> ApiResult<TopPositions> {
+    let sort = params.sort.unwrap_or(PositionSort::Borrow);
+    let filter = HealthFactorFilter { min_hf: params.min_hf, max_hf: params.max_hf };
+    Ok(Json(state.engine.top_positions(parse_address(&address)?, sort, params.limit, filter).await?))
+}
+
+/// Positions of one account in every market it has one in
+async fn user(State(state): State<SharedState>, Path(address): Path<String>) -> ApiResult<Vec<MarketPosition>> {
+    let user = parse_address(&address)?;
+    let provider = state.engine.provider();
+    let mut positions = Vec::new();
+    for market in provider.get_markets().await? {
+        match provider.get_user_position(&market, user).await {
+            Ok(position) => positions.push(MarketPosition {
+                available_liquidity: market.available_liquidity(),
+                market_name: market.name,
+                market_address: market.comet_address,
+                position,
+            }),
+            Err(RiskEngineError::NotFound { .. }) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Json(positions))
+}
+
+#[derive(Debug, Deserialize)]
+struct EventParams {
+    /// Only forward events about this market
+    market: Option<String>,
+    /// Only forward findings at or above this severity
+    min_severity: Option<RiskSeverity>,
+}
+
+/// Which events an `/events` client receives
+#[derive(Debug, Clone, Copy, Default)]
+struct EventFilter {
+    market: Option<Address>,
+    min_severity: Option<RiskSeverity>,
+}
+
+impl EventFilter {
+    /// Completion events carry no severity of their own, so only the market filter applies to them;
+    /// configuration reloads are about no market and only reach unfiltered clients
+    fn matches(&self, event: &RiskEvent) -> bool {
+        let market = self.market.is_none_or(|m| Some(m) == event.market_address());
+        let severity = match (self.min_severity, event.finding()) {
+            (Some(min), Some(finding)) => finding.severity >= min,
+            _ => true,
+        };
+        market && severity
+    }
+}
+
+/// Stream of risk events, one SSE message per event named after its variant
+///
+/// A client that falls `EVENT_CHANNEL_CAPACITY` events behind gets a final `lagged`
+/// message and is disconnected; the scheduler is never slowed down by it.
+async fn events(
+    State(state): State<SharedState>,
+    Query(params): Query<EventParams>,
+) -> std::result::Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>, ApiError> {
+    let filter = EventFilter {
+        market: params.market.as_deref().map(parse_address).transpose()?,
+        min_severity: params.min_severity,
+    };
+    let cancel = state.engine.cancellation_token();
+
+    // `None` once the client has been told it lagged
+    let stream = stream::unfold(Some(state.engine.subscribe()), move |receiver| {
+        let cancel = cancel.clone();
+        async move {
+            let mut receiver = receiver?;
+            loop {
+                // Open streams would otherwise hold up graceful shutdown
+                let received = tokio::select! {
+                    _ = cancel.cancelled() => return None,
+                    received = receiver.recv() => received,
+                };
+                match received {
+                    Ok(event) if filter.matches(&event) => match Event::default().event(event.kind()).json_data(&event) {
+                        Ok(message) => return Some((Ok(message), Some(receiver))),
+                        Err(e) => warn!("Failed to encode {} event: {}", event.kind(), e),
+                    },
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Dropping event stream client that fell {} events behind", skipped);
+                        let message = Event::default().event("lagged").data(serde_json::json!({ "skipped": skipped }).to_string());
+                        return Some((Ok(message), None));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Stop alerting on a finding until it escalates past its current severity
+async fn acknowledge(State(state): State<SharedState>, Path(fingerprint): Path<String>) -> ApiResult<AlertRecord> {
+    Ok(Json(state.engine.acknowledge_finding(&fingerprint)?))
+}
+
+/// Every note attached to a finding, oldest first
+async fn annotations(State(state): State<SharedState>, Path(fingerprint): Path<String>) -> ApiResult<Vec<Annotation>> {
+    Ok(Json(state.engine.finding_annotations(&fingerprint).await?))
+}
+
+/// Body of `POST /findings/{fingerprint}/annotations`
+#[derive(Debug, Deserialize)]
+struct AnnotationRequest {
+    note: String,
+    author: String,
+}
+
+/// Attach a note to a finding
+async fn annotate(
+    State(state): State<SharedState>,
+    Path(fingerprint): Path<String>,
+    Json(request): Json<AnnotationRequest>,
+) -> ApiResult<Annotation> {
+    Ok(Json(state.engine.annotate_finding(&fingerprint, &request.note, &request.author).await?))
+}
+
+/// Payload of `GET /healthz`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    #[serde(flatten)]
+    pub health: Health,
+    pub diagnostics: Diagnostics,
+}
+
+async fn healthz(State(state): State<SharedState>) -> (StatusCode, Json<HealthReport>) {
+    let (health, diagnostics) = tokio::join!(state.engine.health(), state.engine.diagnostics());
+    let code = if health.status == HealthStatus::Stale { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    (code, Json(HealthReport { health, diagnostics }))
+}
+
+async fn readyz(State(state): State<SharedState>) -> (StatusCode, Json<Diagnostics>) {
+    let diagnostics = state.engine.diagnostics().await;
+    let code = if diagnostics.is_failing() { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    (code, Json(diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ServerConfig};
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use ethers::providers::ProviderError;
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
+
+    const USDC: &str = "0xc3d688b66703497daa19211eedff47f25384cdc3";
+
+    fn fixture_engine(config: Config) -> Arc<RiskEngine> {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        Arc::new(RiskEngine::with_provider(config, Arc::new(fixture)))
+    }
+
+    fn spawn(engine: &Arc<RiskEngine>) -> String {
+        let server = ApiServer::spawn(engine.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        format!("http://{}", server.local_addr())
+    }
+
+    async fn get_json(url: String) -> (u16, serde_json::Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = response.status().as_u16();
+        (status, response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_serve_fixture_data() {
+        let config = Config {
+            watchlist: vec![
+                "0x1111111111111111111111111111111111111111".to_string(),
+                "0x2222222222222222222222222222222222222222".to_string(),
+            ],
+            ..Config::default()
+        };
+        let engine = fixture_engine(config);
+        let base = spawn(&engine);
+
+        let (status, health) = get_json(format!("{}/healthz", base)).await;
+        assert_eq!((status, health["status"].as_str()), (200, Some("pending")));
+        assert_eq!(health["diagnostics"]["status"], "pass");
+        let (status, ready) = get_json(format!("{}/readyz", base)).await;
+        assert_eq!((status, ready["checks"][0]["name"].as_str()), (200, Some("config")));
+
+        let (_, markets) = get_json(format!("{}/markets", base)).await;
+        assert_eq!(markets[0]["name"], "USDC");
+
+        let (status, first) = get_json(format!("{}/markets/{}/assessment", base, USDC)).await;
+        // The at-risk watchlist account adds a LiquidationCascade finding to the fixture's two
+        assert_eq!((status, first["findings"].as_array().map(Vec::len)), (200, Some(3)));
+        let (_, cached) = get_json(format!("{}/markets/{}/assessment", base, USDC)).await;
+        assert_eq!(cached["timestamp"], first["timestamp"]);
+        let (_, fresh) = get_json(format!("{}/markets/{}/assessment?fresh=true", base, USDC)).await;
+        assert_ne!(fresh["timestamp"], first["timestamp"]);
+
+        let (_, health) = get_json(format!("{}/healthz", base)).await;
+        assert_eq!((health["status"].as_str(), health["markets_assessed"].as_u64()), (Some("ok"), Some(1)));
+
+        let (_, all) = get_json(format!("{}/markets/{}/positions", base, USDC)).await;
+        assert_eq!(all.as_array().unwrap().len(), 2);
+        let (_, risky) = get_json(format!("{}/markets/{}/positions?max_hf=1.05", base, USDC)).await;
+        assert_eq!(risky.as_array().unwrap().len(), 1);
+        assert_eq!(risky[0]["address"], "0x2222222222222222222222222222222222222222");
+        let (_, top) = get_json(format!("{}/markets/{}/top-positions?limit=1&min_hf=1.5", base, USDC)).await;
+        assert_eq!((top["matching"].as_u64(), top["positions"][0]["borrow_value_usd"].as_f64()), (Some(1), Some(1000.0)));
+
+        let (status, user) = get_json(format!("{}/users/0x2222222222222222222222222222222222222222", base)).await;
+        assert_eq!((status, user[0]["market_name"].as_str()), (200, Some("USDC")));
+
+        let (status, missing) = get_json(format!("{}/markets/{:?}/assessment", base, Address::repeat_byte(0x11))).await;
+        assert_eq!(status, 404);
+        assert!(missing["error"].as_str().unwrap().contains("market not found"));
+        let (status, _) = get_json(format!("{}/users/not-an-address", base)).await;
+        assert_eq!(status, 400);
+
+        engine.cancellation_token().cancel();
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_is_required_except_for_health_probes() {
+        let config = Config {
+            server: ServerConfig { auth_token: Some("s3cret".to_string()), ..ServerConfig::default() },
+            ..Config::default()
+        };
+        let engine = fixture_engine(config);
+        let base = spawn(&engine);
+        let client = reqwest::Client::new();
+
+        let anonymous = client.get(format!("{}/markets", base)).send().await.unwrap();
+        assert_eq!(anonymous.status().as_u16(), 401);
+        assert_eq!(anonymous.headers()["www-authenticate"], "Bearer");
+        let wrong = client.get(format!("{}/markets", base)).bearer_auth("s3crex").send().await.unwrap();
+        assert_eq!(wrong.status().as_u16(), 401);
+        let authorized = client.get(format!("{}/markets", base)).bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(authorized.status().as_u16(), 200);
+        for probe in ["healthz", "readyz"] {
+            let health = client.get(format!("{}/{}", base, probe)).send().await.unwrap();
+            assert_eq!(health.status().as_u16(), 200, "{}", probe);
+        }
+
+        engine.cancellation_token().cancel();
+    }
+
+    #[tokio::test]
+    async fn test_server_stops_when_engine_is_cancelled() {
+        let engine = fixture_engine(Config::default());
+        let server = ApiServer::spawn(engine.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.local_addr();
+
+        engine.cancellation_token().cancel();
+        tokio::time::timeout(Duration::from_secs(5), server.wait()).await.unwrap();
+        assert!(reqwest::get(format!("http://{}/healthz", addr)).await.is_err());
+    }
+
+    /// Read SSE chunks from `response` until the text received so far contains `needle`
+    async fn read_until(response: &mut reqwest::Response, needle: &str) -> String {
+        let mut text = String::new();
+        let read = async {
+            while !text.contains(needle) {
+                let chunk = response.chunk().await.unwrap().expect("event stream ended early");
+                text.push_str(&String::from_utf8_lossy(&chunk));
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), read).await.expect("timed out waiting for event");
+        text
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_scheduled_assessments() {
+        let engine = fixture_engine(Config::default());
+        let server = ApiServer::spawn(engine.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        let base = format!("http://{}", server.local_addr());
+        let client = reqwest::Client::new();
+        let mut all = client.get(format!("{}/events", base)).send().await.unwrap();
+        assert_eq!(all.headers()["content-type"], "text/event-stream");
+        let mut critical = client.get(format!("{}/events?min_severity=Critical", base)).send().await.unwrap();
+        let bad = client.get(format!("{}/events?market=nope", base)).send().await.unwrap();
+        assert_eq!(bad.status().as_u16(), 400);
+
+        let cancel = CancellationToken::new();
+        let scheduler = tokio::spawn({
+            let engine = engine.clone();
+            let cancel = cancel.clone();
+            async move { engine.run_scheduled(Duration::from_millis(50), cancel).await }
+        });
+        let text = read_until(&mut all, "event:AssessmentCompleted").await;
+        assert!(text.contains("event:NewFinding"), "{}", text);
+        assert!(text.contains(r#""category":"HighUtilization""#), "{}", text);
+        // The fixture has no Critical findings, so only completions get through
+        let text = read_until(&mut critical, "event:AssessmentCompleted").await;
+        assert!(!text.contains("event:NewFinding"), "{}", text);
+
+        // Open streams do not hold up shutdown, and a disconnected client does not affect the engine
+        drop(critical);
+        cancel.cancel();
+        scheduler.await.unwrap().unwrap();
+        engine.cancellation_token().cancel();
+        tokio::time::timeout(Duration::from_secs(5), server.wait()).await.unwrap();
+        assert!(all.chunk().await.map(|c| c.is_none()).unwrap_or(true));
+    }
+
+    #[tokio::test]
+    async fn test_acknowledge_alerted_findings() {
+        let webhook = crate::testing::CaptureServer::new();
+        let mut config = Config::default();
+        config.alerts.webhooks = serde_json::from_value(serde_json::json!([{ "url": webhook.serve().await }])).unwrap();
+        let engine = fixture_engine(config);
+        let base = spawn(&engine);
+        let client = reqwest::Client::new();
+        let ack = |fingerprint: String| client.post(format!("{}/alerts/{}/ack", base, fingerprint)).send();
+
+        let unknown = ack("0123456789abcdef".to_string()).await.unwrap();
+        assert_eq!(unknown.status().as_u16(), 404);
+
+        engine.run_scheduled_times(Duration::from_millis(10), Some(1), CancellationToken::new()).await.unwrap();
+        let fingerprint = webhook.requests()[0]["finding"]["fingerprint"].as_str().unwrap().to_string();
+        let response = ack(fingerprint).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let record: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(record["acknowledged"]["severity"], "High");
+
+        engine.cancellation_token().cancel();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_annotate_findings() {
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let storage = Arc::new(crate::storage::SqliteStorage::open_in_memory().unwrap());
+        let engine = Arc::new(RiskEngine::with_provider(Config::default(), Arc::new(fixture)).with_storage(storage));
+        let base = spawn(&engine);
+        let client = reqwest::Client::new();
+        let annotate = |fingerprint: &str, note: &str| {
+            client
+                .post(format!("{}/findings/{}/annotations", base, fingerprint))
+                .json(&serde_json::json!({ "note": note, "author": "alice" }))
+                .send()
+        };
+
+        let unknown = annotate("0123456789abcdef", "feed migrated").await.unwrap();
+        assert_eq!(unknown.status().as_u16(), 404);
+        let (_, assessment) = get_json(format!("{}/markets/{}/assessment", base, USDC)).await;
+        let fingerprint = assessment["findings"][0]["fingerprint"].as_str().unwrap().to_string();
+        let (status, notes) = get_json(format!("{}/findings/{}/annotations", base, fingerprint)).await;
+        assert_eq!((status, notes.as_array().map(Vec::len)), (200, Some(0)));
+
+        let empty = annotate(&fingerprint, " ").await.unwrap();
+        assert_eq!(empty.status().as_u16(), 400);
+        let response = annotate(&fingerprint, "confirmed false positive").await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let annotation: serde_json::Value = response.json().await.unwrap();
+        assert_eq!((annotation["fingerprint"].as_str(), annotation["author"].as_str()), (Some(fingerprint.as_str()), Some("alice")));
+
+        let (_, notes) = get_json(format!("{}/findings/{}/annotations", base, fingerprint)).await;
+        assert_eq!(notes[0]["note"], "confirmed false positive");
+        let (_, cached) = get_json(format!("{}/markets/{}/assessment", base, USDC)).await;
+        assert_eq!(cached["annotations"][&fingerprint]["note"], "confirmed false positive");
+
+        engine.cancellation_token().cancel();
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let market = Address::repeat_byte(0x11);
+        let finding = |severity| RiskEvent::NewFinding {
+            market_name: "USDC".to_string(),
+            market_address: market,
+            finding: crate::testing::finding("f", severity),
+        };
+
+        let high = EventFilter { min_severity: Some(RiskSeverity::High), ..EventFilter::default() };
+        assert!(high.matches(&finding(RiskSeverity::Critical)));
+        assert!(!high.matches(&finding(RiskSeverity::Medium)));
+        let other_market = EventFilter { market: Some(Address::repeat_byte(0x22)), ..EventFilter::default() };
+        assert!(!other_market.matches(&finding(RiskSeverity::Critical)));
+        assert!(EventFilter { market: Some(market), ..EventFilter::default() }.matches(&finding(RiskSeverity::Low)));
+    }
+
+    #[test]
+    fn test_errors_map_to_status_codes() {
+        let not_found = RiskEngineError::NotFound { kind: "market", id: "x".to_string() };
+        assert_eq!(status_for(&not_found), StatusCode::NOT_FOUND);
+        let provider = RiskEngineError::provider("https://rpc.example.org", ProviderError::CustomError("down".into()));
+        assert_eq!(status_for(&provider), StatusCode::BAD_GATEWAY);
+        assert_eq!(status_for(&RiskEngineError::Cancelled), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status_for(&RiskEngineError::config("x", "y")), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(tokens_match("abc", "abc") && !tokens_match("abc", "abd") && !tokens_match("abc", "abcd"));
+    }
+}
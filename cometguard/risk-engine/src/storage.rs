@@ -0,0 +1,259 @@
+//! Assessment history: every completed assessment with its findings and market snapshot, and
+//! the notes operators attached to findings
+//!
+//! `SqliteStorage` keeps the history in a local file for a single engine; `PostgresStorage`
+//! shares one database between several engines, and the leases they elect a leader with. Both
+//! embed their schema migrations and apply them automatically.
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+use crate::annotations::{self, Annotation};
+use crate::collateral_changes::CollateralParameters;
+use crate::config::StorageConfig;
+use crate::error::{Result, RiskEngineError};
+use crate::leader::LeaseStore;
+use crate::models::Market;
+use crate::risk::{RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Risk score of a market at one assessment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScorePoint {
+    pub timestamp: DateTime<Utc>,
+    pub risk_score: u8,
+    /// Engine version that produced the assessment; `None` for assessments stored before
+    /// versions were recorded
+    #[serde(default)]
+    pub engine_version: Option<String>,
+    /// Sub-score of each risk category, as in `RiskAssessment::category_scores`; empty for
+    /// assessments stored before sub-scores were recorded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub category_scores: BTreeMap<RiskCategory, Option<u8>>,
+}
+
+/// Market state an assessment was computed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub base_symbol: String,
+    pub base_price: f64,
+    pub total_supply: f64,
+    pub total_borrow: f64,
+    pub utilization_rate: f64,
+    pub supply_apr: f64,
+    pub borrow_apr: f64,
+    /// Reserves in USD; `None` where the assessment did not read them
+    #[serde(default)]
+    pub reserves: Option<f64>,
+    /// Highest 30-day price volatility among the collateral assets; `None` without price history
+    #[serde(default)]
+    pub price_volatility: Option<f64>,
+    /// Parameters of each collateral asset; empty for snapshots stored before they were
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collateral: Vec<CollateralParameters>,
+}
+
+/// Market that has at least one stored assessment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredMarket {
+    pub name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub address: Address,
+    pub assessments: u64,
+    pub last_assessed: DateTime<Utc>,
+}
+
+/// Finding as reported by one stored assessment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFinding {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    /// When the assessment reporting the finding ran
+    pub assessed_at: DateTime<Utc>,
+    pub finding: RiskFinding,
+}
+
+/// When a finding (by fingerprint) was first and last reported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FingerprintSpan {
+    pub fingerprint: String,
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub category: RiskCategory,
+    /// Highest severity the finding was reported with
+    pub max_severity: RiskSeverity,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Number of assessments that reported it
+    pub occurrences: u64,
+    /// Description from the most recent report
+    pub last_description: String,
+}
+
+/// Filter for finding queries; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct FindingQuery {
+    pub market: Option<Address>,
+    pub category: Option<RiskCategory>,
+    pub min_severity: Option<RiskSeverity>,
+    /// Inclusive lower bound on the assessment time
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the assessment time
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Backend assessments are written to and history is queried from
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Short description of the backend for logs
+    fn name(&self) -> String;
+
+    /// Store `assessment` of `market` with its findings and market snapshot
+    async fn record(&self, chain_id: u64, market: &Market, assessment: &RiskAssessment) -> Result<()>;
+
+    /// Markets with stored assessments, by name
+    async fn markets(&self) -> Result<Vec<StoredMarket>>;
+
+    /// Risk score of `market` at every assessment in `[since, until)`, oldest first
+    async fn score_series(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<ScorePoint>>;
+
+    /// Market snapshots of `market` taken in `[since, until)`, oldest first
+    async fn snapshots(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<MarketSnapshot>>;
+
+    /// The `count` newest market snapshots of `market` taken before `until`, oldest first
+    async fn latest_snapshots(&self, market: Address, until: DateTime<Utc>, count: u32) -> Result<Vec<MarketSnapshot>>;
+
+    /// Newest market snapshot of `market` taken before `until`
+    async fn latest_snapshot(&self, market: Address, until: DateTime<Utc>) -> Result<Option<MarketSnapshot>> {
+        Ok(self.latest_snapshots(market, until, 1).await?.pop())
+    }
+
+    /// Findings matching `query`, oldest assessment first
+    async fn findings(&self, query: &FindingQuery) -> Result<Vec<StoredFinding>>;
+
+    /// First and last time each fingerprint matching `query` was reported, by first sighting
+    async fn fingerprint_spans(&self, query: &FindingQuery) -> Result<Vec<FingerprintSpan>>;
+
+    /// Market whose name (case-insensitive) or address is `name_or_address`
+    async fn find_market(&self, name_or_address: &str) -> Result<Option<StoredMarket>> {
+        let address = Address::from_str(name_or_address).ok();
+        Ok(self.markets().await?.into_iter().find(|m| {
+            Some(m.address) == address || m.name.eq_ignore_ascii_case(name_or_address)
+        }))
+    }
+
+    /// First and last sighting of `fingerprint`, if it was ever reported
+    async fn fingerprint_span(&self, fingerprint: &str) -> Result<Option<FingerprintSpan>> {
+        let spans = self.fingerprint_spans(&FindingQuery::default()).await?;
+        Ok(spans.into_iter().find(|s| s.fingerprint == fingerprint))
+    }
+
+    /// Store `annotation`; unlike `record`, the write has been committed once this returns
+    async fn annotate(&self, annotation: &Annotation) -> Result<()>;
+
+    /// Every annotation of the findings with one of `fingerprints`, oldest first
+    async fn annotations(&self, fingerprints: &[String]) -> Result<Vec<Annotation>>;
+
+    /// Newest annotation of each of `fingerprints` that has one
+    async fn latest_annotations(&self, fingerprints: &[String]) -> Result<BTreeMap<String, Annotation>> {
+        Ok(annotations::latest(self.annotations(fingerprints).await?))
+    }
+
+    /// Wait until every write accepted by `record` has been committed
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Leases the engines sharing this backend elect a leader with; `None` for backends
+    /// a single engine writes to
+    fn leases(self: Arc<Self>) -> Option<Arc<dyn LeaseStore>> {
+        None
+    }
+}
+
+/// Open the backend selected by `config`, if any
+pub async fn open(config: &StorageConfig) -> Result<Option<Arc<dyn Storage>>> {
+    match (&config.database_path, &config.postgres) {
+        (Some(_), Some(_)) => Err(RiskEngineError::config(
+            "storage",
+            "set either `database_path` or `postgres`, not both",
+        )),
+        #[cfg(feature = "sqlite")]
+        (Some(path), None) => Ok(Some(Arc::new(SqliteStorage::open(path)?))),
+        #[cfg(not(feature = "sqlite"))]
+        (Some(_), None) => Err(RiskEngineError::config(
+            "storage.database_path",
+            "this build does not include the `sqlite` feature",
+        )),
+        #[cfg(feature = "postgres")]
+        (None, Some(postgres)) => Ok(Some(Arc::new(PostgresStorage::connect(postgres)?))),
+        #[cfg(not(feature = "postgres"))]
+        (None, Some(_)) => Err(RiskEngineError::config(
+            "storage.postgres",
+            "this build does not include the `postgres` feature",
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Position of `severity` in `RiskSeverity::ALL`, so severities compare as integers
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn severity_rank(severity: RiskSeverity) -> usize {
+    RiskSeverity::ALL.iter().position(|s| *s == severity).unwrap_or(0)
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn severity_from_rank(rank: usize) -> Result<RiskSeverity> {
+    RiskSeverity::ALL.get(rank).copied().ok_or_else(|| RiskEngineError::Parse {
+        what: "severity rank",
+        input: rank.to_string(),
+        message: "out of range".to_string(),
+    })
+}
+
+/// Addresses are stored lowercase, so equal addresses always compare equal in SQL
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn address_key(address: &Address) -> String {
+    format!("{:?}", address)
+}
+
+/// `value`, or NULL for the empty strings of assessments that did not record it
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn non_empty(value: &str) -> Option<&str> {
+    (!value.is_empty()).then_some(value)
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn parse_address(input: &str) -> Result<Address> {
+    Address::from_str(input).map_err(|e| RiskEngineError::Parse {
+        what: "stored address",
+        input: input.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Enum stored by its variant name
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn parse_variant<T: serde::de::DeserializeOwned>(what: &'static str, input: &str) -> Result<T> {
+    serde_json::from_value(serde_json::Value::String(input.to_string())).map_err(|e| RiskEngineError::Parse {
+        what,
+        input: input.to_string(),
+        message: e.to_string(),
+    })
+}
@@ -0,0 +1,794 @@
+//! PostgreSQL `Storage` backend shared by several engines
+//!
+//! Writes go through a bounded write-behind queue drained by a background task, so an
+//! unreachable database never delays an assessment: failed writes are retried with backoff
+//! while new ones queue up, and once the queue is full further assessments are dropped.
+
+use super::{
+    address_key, non_empty, parse_address, parse_variant, severity_from_rank, severity_rank, FindingQuery, FingerprintSpan,
+    MarketSnapshot, ScorePoint, Storage, StoredFinding, StoredMarket,
+};
+use crate::annotations::Annotation;
+use crate::collateral_changes::CollateralParameters;
+use crate::config::{PostgresConfig, PostgresSslMode};
+use crate::error::{Result, RiskEngineError};
+use crate::leader::LeaseStore;
+use crate::models::Market;
+use crate::risk::{RiskAssessment, RiskCategory, RiskFinding};
+use crate::utils::expand_env;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgRow, PgSslMode};
+use sqlx::types::Json;
+use sqlx::{Executor, Row};
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::{debug, warn};
+
+/// Schema migrations, applied in order and recorded in `cometguard_schema_migrations`
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/postgres/0001_history.sql"),
+    include_str!("../../migrations/postgres/0002_baseline.sql"),
+    include_str!("../../migrations/postgres/0003_versions.sql"),
+    include_str!("../../migrations/postgres/0004_collateral.sql"),
+    include_str!("../../migrations/postgres/0005_leases.sql"),
+    include_str!("../../migrations/postgres/0006_category_scores.sql"),
+    include_str!("../../migrations/postgres/0007_annotations.sql"),
+];
+
+/// Advisory lock held while migrating, so concurrently starting engines migrate one at a time
+const MIGRATION_LOCK: i64 = 0x636f_6d65_7467_7264;
+
+/// Longest delay between two attempts at a failed write
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How long to wait for a pooled connection before treating the database as unreachable
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Assessment waiting in the write-behind queue
+struct PendingWrite {
+    chain_id: u64,
+    market: Market,
+    assessment: RiskAssessment,
+}
+
+enum WriteOp {
+    Record(Box<PendingWrite>),
+    /// Answered once every write queued before it has been handled
+    Flush(oneshot::Sender<()>),
+}
+
+/// State shared between the storage handle and its writer task
+struct Shared {
+    pool: PgPool,
+    instance_id: String,
+    /// `instance_id` and a suffix drawn at startup: this engine's name in the lease table,
+    /// distinct even from engines configured with the same `instance_id`
+    holder: String,
+    /// Whether this process has checked (and applied) the migrations
+    migrated: Mutex<bool>,
+}
+
+/// Assessment history in a PostgreSQL database shared by several engines
+///
+/// Every row carries the engine's `instance_id`. Queries span all instances.
+pub struct PostgresStorage {
+    shared: Arc<Shared>,
+    queue: mpsc::Sender<WriteOp>,
+    dropped: Arc<AtomicU64>,
+    host: String,
+}
+
+impl PostgresStorage {
+    /// Storage for `config`, starting its writer task
+    ///
+    /// No connection is made yet; the schema is migrated on first use.
+    pub fn connect(config: &PostgresConfig) -> Result<Self> {
+        let url = expand_env("storage.postgres.url", &config.url)?;
+        // Never echo the URL back: it usually holds the password
+        let mut options = PgConnectOptions::from_str(&url)
+            .map_err(|_| RiskEngineError::config("storage.postgres.url", "not a valid PostgreSQL connection string"))?;
+        if let Some(mode) = config.ssl_mode {
+            options = options.ssl_mode(match mode {
+                PostgresSslMode::Disable => PgSslMode::Disable,
+                PostgresSslMode::Prefer => PgSslMode::Prefer,
+                PostgresSslMode::Require => PgSslMode::Require,
+                PostgresSslMode::VerifyCa => PgSslMode::VerifyCa,
+                PostgresSslMode::VerifyFull => PgSslMode::VerifyFull,
+            });
+        }
+        if let Some(root_cert) = &config.root_cert {
+            options = options.ssl_root_cert(root_cert);
+        }
+        if config.write_queue_capacity == 0 {
+            return Err(RiskEngineError::config("storage.postgres.write_queue_capacity", "must be at least 1"));
+        }
+
+        let host = options.get_host().to_string();
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect_lazy_with(options);
+        let instance_id = config.instance_id.clone().unwrap_or_else(|| {
+            hostname::get()
+                .ok()
+                .and_then(|name| name.into_string().ok())
+                .unwrap_or_else(|| "cometguard".to_string())
+        });
+
+        let holder = format!("{}/{:08x}", instance_id, RandomState::new().build_hasher().finish() as u32);
+        let shared = Arc::new(Shared { pool, instance_id, holder, migrated: Mutex::new(false) });
+        let (queue, pending) = mpsc::channel(config.write_queue_capacity);
+        let retry_delay = Duration::from_millis(config.write_retry_ms.max(1));
+        tokio::spawn(run_writer(shared.clone(), pending, retry_delay));
+
+        Ok(Self { shared, queue, dropped: Arc::new(AtomicU64::new(0)), host })
+    }
+
+    /// Identifier stored with every assessment this engine writes
+    pub fn instance_id(&self) -> &str {
+        &self.shared.instance_id
+    }
+
+    /// Assessments dropped because the write-behind queue was full
+    pub fn dropped_writes(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Snapshots of `market` from `since` (or the first) until `until`, oldest first; with a
+    /// `latest` limit only that many of the newest
+    async fn select_snapshots(&self, market: Address, since: Option<DateTime<Utc>>, until: DateTime<Utc>, latest: Option<i64>) -> Result<Vec<MarketSnapshot>> {
+        let pool = self.shared.ready().await?;
+        let order = if latest.is_some() { "DESC" } else { "" };
+        let sql = format!(
+            "SELECT a.assessed_at, s.base_symbol, s.base_price, s.total_supply, s.total_borrow, s.utilization_rate, s.supply_apr, s.borrow_apr,
+                    s.reserves, s.price_volatility, s.collateral
+             FROM market_snapshots s JOIN assessments a ON a.id = s.assessment_id
+             WHERE a.market_address = $1 AND ($2::TIMESTAMPTZ IS NULL OR a.assessed_at >= $2) AND a.assessed_at < $3
+             ORDER BY a.assessed_at {order}, a.id {order} LIMIT $4",
+        );
+        let rows = sqlx::query(&sql)
+            .bind(address_key(&market))
+            .bind(since)
+            .bind(until)
+            .bind(latest)
+            .fetch_all(pool)
+            .await
+            .map_err(pg_error("query"))?;
+
+        let mut snapshots = rows
+            .iter()
+            .map(|row| {
+                let get = |i| row.try_get::<f64, _>(i).map_err(pg_error("query"));
+                let collateral: Option<Json<Vec<CollateralParameters>>> = row.try_get(10).map_err(pg_error("query"))?;
+                Ok(MarketSnapshot {
+                    timestamp: row.try_get(0).map_err(pg_error("query"))?,
+                    base_symbol: row.try_get(1).map_err(pg_error("query"))?,
+                    base_price: get(2)?,
+                    total_supply: get(3)?,
+                    total_borrow: get(4)?,
+                    utilization_rate: get(5)?,
+                    supply_apr: get(6)?,
+                    borrow_apr: get(7)?,
+                    reserves: row.try_get(8).map_err(pg_error("query"))?,
+                    price_volatility: row.try_get(9).map_err(pg_error("query"))?,
+                    collateral: collateral.map(|json| json.0).unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if latest.is_some() {
+            snapshots.reverse();
+        }
+        Ok(snapshots)
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    fn name(&self) -> String {
+        format!("postgres {}", self.host)
+    }
+
+    /// Queue `assessment` for writing; returns without waiting for the database
+    async fn record(&self, chain_id: u64, market: &Market, assessment: &RiskAssessment) -> Result<()> {
+        let write = PendingWrite { chain_id, market: market.clone(), assessment: assessment.clone() };
+        match self.queue.try_send(WriteOp::Record(Box::new(write))) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    "History write queue is full; dropped assessment of {} ({} dropped so far)",
+                    assessment.market_name, dropped
+                );
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(writer_stopped()),
+        }
+    }
+
+    async fn markets(&self) -> Result<Vec<StoredMarket>> {
+        let pool = self.shared.ready().await?;
+        let rows = sqlx::query(
+            "SELECT (array_agg(market_name ORDER BY assessed_at DESC))[1], market_address, COUNT(*), MAX(assessed_at)
+             FROM assessments GROUP BY market_address ORDER BY 1",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(pg_error("query"))?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(StoredMarket {
+                    name: row.try_get(0).map_err(pg_error("query"))?,
+                    address: parse_address(&row.try_get::<String, _>(1).map_err(pg_error("query"))?)?,
+                    assessments: row.try_get::<i64, _>(2).map_err(pg_error("query"))? as u64,
+                    last_assessed: row.try_get(3).map_err(pg_error("query"))?,
+                })
+            })
+            .collect()
+    }
+
+    async fn score_series(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<ScorePoint>> {
+        let pool = self.shared.ready().await?;
+        let rows = sqlx::query(
+            "SELECT assessed_at, risk_score, engine_version, category_scores FROM assessments
+             WHERE market_address = $1 AND assessed_at >= $2 AND assessed_at < $3 ORDER BY assessed_at, id",
+        )
+        .bind(address_key(&market))
+        .bind(since)
+        .bind(until)
+        .fetch_all(pool)
+        .await
+        .map_err(pg_error("query"))?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(ScorePoint {
+                    timestamp: row.try_get(0).map_err(pg_error("query"))?,
+                    risk_score: row.try_get::<i16, _>(1).map_err(pg_error("query"))? as u8,
+                    engine_version: row.try_get(2).map_err(pg_error("query"))?,
+                    category_scores: row
+                        .try_get::<Option<Json<BTreeMap<RiskCategory, Option<u8>>>>, _>(3)
+                        .map_err(pg_error("query"))?
+                        .map(|json| json.0)
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    async fn snapshots(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<MarketSnapshot>> {
+        self.select_snapshots(market, Some(since), until, None).await
+    }
+
+    async fn latest_snapshots(&self, market: Address, until: DateTime<Utc>, count: u32) -> Result<Vec<MarketSnapshot>> {
+        self.select_snapshots(market, None, until, Some(i64::from(count))).await
+    }
+
+    async fn findings(&self, query: &FindingQuery) -> Result<Vec<StoredFinding>> {
+        let pool = self.shared.ready().await?;
+        let sql = format!(
+            "SELECT a.market_name, a.market_address, a.assessed_at, f.fingerprint, f.category, f.severity, f.description, f.metadata, f.found_at
+             FROM findings f JOIN assessments a ON a.id = f.assessment_id
+             WHERE {} ORDER BY a.assessed_at, f.id",
+            FINDING_FILTER
+        );
+        let rows = bind_filter(sqlx::query(&sql), query).fetch_all(pool).await.map_err(pg_error("query"))?;
+        rows.iter().map(stored_finding).collect()
+    }
+
+    async fn fingerprint_spans(&self, query: &FindingQuery) -> Result<Vec<FingerprintSpan>> {
+        let pool = self.shared.ready().await?;
+        let sql = format!(
+            "SELECT f.fingerprint, MIN(a.market_name), MIN(a.market_address), MIN(f.category), MAX(f.severity_rank),
+                    MIN(a.assessed_at), MAX(a.assessed_at), COUNT(*),
+                    (array_agg(f.description ORDER BY a.assessed_at DESC, f.id DESC))[1]
+             FROM findings f JOIN assessments a ON a.id = f.assessment_id
+             WHERE {} GROUP BY f.fingerprint ORDER BY MIN(a.assessed_at), f.fingerprint",
+            FINDING_FILTER
+        );
+        let rows = bind_filter(sqlx::query(&sql), query).fetch_all(pool).await.map_err(pg_error("query"))?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(FingerprintSpan {
+                    fingerprint: row.try_get(0).map_err(pg_error("query"))?,
+                    market_name: row.try_get(1).map_err(pg_error("query"))?,
+                    market_address: parse_address(&row.try_get::<String, _>(2).map_err(pg_error("query"))?)?,
+                    category: parse_variant("category", &row.try_get::<String, _>(3).map_err(pg_error("query"))?)?,
+                    max_severity: severity_from_rank(row.try_get::<i16, _>(4).map_err(pg_error("query"))? as usize)?,
+                    first_seen: row.try_get(5).map_err(pg_error("query"))?,
+                    last_seen: row.try_get(6).map_err(pg_error("query"))?,
+                    occurrences: row.try_get::<i64, _>(7).map_err(pg_error("query"))? as u64,
+                    last_description: row.try_get(8).map_err(pg_error("query"))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Written directly rather than queued, so the operator learns whether the note was stored
+    async fn annotate(&self, annotation: &Annotation) -> Result<()> {
+        let pool = self.shared.ready().await?;
+        sqlx::query("INSERT INTO annotations (instance_id, fingerprint, note, author, created_at) VALUES ($1, $2, $3, $4, $5)")
+            .bind(&self.shared.instance_id)
+            .bind(&annotation.fingerprint)
+            .bind(&annotation.note)
+            .bind(&annotation.author)
+            .bind(annotation.created_at)
+            .execute(pool)
+            .await
+            .map_err(pg_error("write"))?;
+        Ok(())
+    }
+
+    async fn annotations(&self, fingerprints: &[String]) -> Result<Vec<Annotation>> {
+        if fingerprints.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pool = self.shared.ready().await?;
+        let rows = sqlx::query(
+            "SELECT fingerprint, note, author, created_at FROM annotations
+             WHERE fingerprint = ANY($1) ORDER BY created_at, id",
+        )
+        .bind(fingerprints)
+        .fetch_all(pool)
+        .await
+        .map_err(pg_error("query"))?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(Annotation {
+                    fingerprint: row.try_get(0).map_err(pg_error("query"))?,
+                    note: row.try_get(1).map_err(pg_error("query"))?,
+                    author: row.try_get(2).map_err(pg_error("query"))?,
+                    created_at: row.try_get(3).map_err(pg_error("query"))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Wait for the write-behind queue to drain; blocks while the database is unreachable
+    async fn flush(&self) -> Result<()> {
+        let (done, flushed) = oneshot::channel();
+        self.queue.send(WriteOp::Flush(done)).await.map_err(|_| writer_stopped())?;
+        flushed.await.map_err(|_| writer_stopped())
+    }
+
+    fn leases(self: Arc<Self>) -> Option<Arc<dyn LeaseStore>> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl LeaseStore for PostgresStorage {
+    fn holder(&self) -> &str {
+        &self.shared.holder
+    }
+
+    /// One statement, so two engines racing for an expired lease cannot both win it
+    async fn acquire(&self, name: &str, lease: Duration) -> Result<Option<u64>> {
+        let pool = self.shared.ready().await?;
+        let term: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO leases (name, holder, expires_at, term)
+             VALUES ($1, $2, now() + $3 * INTERVAL '1 millisecond', 1)
+             ON CONFLICT (name) DO UPDATE SET
+                 holder = EXCLUDED.holder,
+                 expires_at = EXCLUDED.expires_at,
+                 term = CASE WHEN leases.holder = EXCLUDED.holder THEN leases.term ELSE leases.term + 1 END
+             WHERE leases.holder = EXCLUDED.holder OR leases.expires_at <= now()
+             RETURNING term",
+        )
+        .bind(name)
+        .bind(&self.shared.holder)
+        .bind(lease.as_millis() as i64)
+        .fetch_optional(pool)
+        .await
+        .map_err(pg_error("lease"))?;
+        Ok(term.map(|term| term as u64))
+    }
+
+    async fn release(&self, name: &str) -> Result<()> {
+        let pool = self.shared.ready().await?;
+        sqlx::query("UPDATE leases SET expires_at = now() WHERE name = $1 AND holder = $2")
+            .bind(name)
+            .bind(&self.shared.holder)
+            .execute(pool)
+            .await
+            .map_err(pg_error("lease"))?;
+        Ok(())
+    }
+}
+
+impl Shared {
+    /// Pool to run statements on, once the schema is known to be current
+    async fn ready(&self) -> Result<&PgPool> {
+        self.migrate_once().await.map_err(pg_error("migration"))?;
+        Ok(&self.pool)
+    }
+
+    async fn migrate_once(&self) -> sqlx::Result<()> {
+        let mut migrated = self.migrated.lock().await;
+        if !*migrated {
+            migrate(&self.pool).await?;
+            *migrated = true;
+        }
+        Ok(())
+    }
+}
+
+/// Write queued assessments until every `PostgresStorage` handle is dropped
+async fn run_writer(shared: Arc<Shared>, mut pending: mpsc::Receiver<WriteOp>, retry_delay: Duration) {
+    while let Some(op) = pending.recv().await {
+        let write = match op {
+            WriteOp::Record(write) => write,
+            WriteOp::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+
+        let mut delay = retry_delay;
+        loop {
+            let result = match shared.migrate_once().await {
+                Ok(()) => insert_assessment(&shared.pool, &shared.instance_id, &write).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) if is_transient(&e) => {
+                    warn!("History write for {} failed, retrying in {:?}: {}", write.assessment.market_name, delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Err(e) => {
+                    warn!("Dropping history write for {}: {}", write.assessment.market_name, e);
+                    break;
+                }
+            }
+        }
+    }
+    debug!("History writer stopped");
+}
+
+/// Apply pending migrations in one transaction, holding the migration lock
+async fn migrate(pool: &PgPool) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(MIGRATION_LOCK).execute(&mut *tx).await?;
+    (&mut *tx)
+        .execute(
+            "CREATE TABLE IF NOT EXISTS cometguard_schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+    let applied: i32 = sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM cometguard_schema_migrations")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        // Without bind parameters this runs as a simple query, which allows several statements
+        (&mut *tx).execute(*migration).await?;
+        sqlx::query("INSERT INTO cometguard_schema_migrations (version) VALUES ($1)")
+            .bind(index as i32 + 1)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Insert one assessment with its findings and market snapshot in a single transaction
+async fn insert_assessment(pool: &PgPool, instance_id: &str, write: &PendingWrite) -> sqlx::Result<()> {
+    let PendingWrite { chain_id, market, assessment } = write;
+    let mut tx = pool.begin().await?;
+    let id: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO assessments (instance_id, chain_id, market_address, market_name, assessed_at, risk_score, mock_data, watchlist_min_health_factor, engine_version, config_hash, category_scores)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         ON CONFLICT (instance_id, market_address, assessed_at) DO NOTHING
+         RETURNING id",
+    )
+    .bind(instance_id)
+    .bind(*chain_id as i64)
+    .bind(address_key(&assessment.market_address))
+    .bind(&assessment.market_name)
+    .bind(assessment.timestamp)
+    .bind(assessment.risk_score as i16)
+    .bind(assessment.mock_data)
+    .bind(assessment.watchlist_min_health_factor)
+    .bind(non_empty(&assessment.engine_version))
+    .bind(non_empty(&assessment.config_hash))
+    .bind((!assessment.category_scores.is_empty()).then_some(Json(&assessment.category_scores)))
+    .fetch_optional(&mut *tx)
+    .await?;
+    // Already stored by an earlier attempt whose commit reached the server
+    let Some(id) = id else {
+        return tx.rollback().await;
+    };
+
+    for finding in &assessment.findings {
+        sqlx::query(
+            "INSERT INTO findings (assessment_id, fingerprint, category, severity, severity_rank, description, metadata, found_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(id)
+        .bind(&finding.fingerprint)
+        .bind(format!("{:?}", finding.category))
+        .bind(format!("{:?}", finding.severity))
+        .bind(severity_rank(finding.severity) as i16)
+        .bind(&finding.description)
+        .bind(Json(&finding.metadata))
+        .bind(finding.timestamp)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO market_snapshots (assessment_id, base_symbol, base_price, total_supply, total_borrow, utilization_rate, supply_apr, borrow_apr, reserves, price_volatility, collateral)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+    )
+    .bind(id)
+    .bind(&market.base_asset.symbol)
+    .bind(market.base_asset.price)
+    .bind(market.total_supply)
+    .bind(market.total_borrow)
+    .bind(market.utilization())
+    .bind(market.supply_apr)
+    .bind(market.borrow_apr)
+    .bind(assessment.reserves_usd)
+    .bind(assessment.price_volatility)
+    .bind(Json(CollateralParameters::of(market)))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// `WHERE` clause shared by the finding queries; see `bind_filter` for the parameters
+const FINDING_FILTER: &str = "($1::TEXT IS NULL OR a.market_address = $1) AND ($2::TEXT IS NULL OR f.category = $2)
+     AND f.severity_rank >= $3 AND ($4::TIMESTAMPTZ IS NULL OR a.assessed_at >= $4)
+     AND ($5::TIMESTAMPTZ IS NULL OR a.assessed_at < $5)";
+
+fn bind_filter<'q>(
+    statement: sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>,
+    query: &FindingQuery,
+) -> sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments> {
+    statement
+        .bind(query.market.as_ref().map(address_key))
+        .bind(query.category.as_ref().map(|c| format!("{:?}", c)))
+        .bind(query.min_severity.map(severity_rank).unwrap_or(0) as i16)
+        .bind(query.since)
+        .bind(query.until)
+}
+
+fn stored_finding(row: &PgRow) -> Result<StoredFinding> {
+    let text = |i| row.try_get::<String, _>(i).map_err(pg_error("query"));
+    let Json(metadata) = row.try_get::<Json<serde_json::Value>, _>(7).map_err(pg_error("query"))?;
+    Ok(StoredFinding {
+        market_name: text(0)?,
+        market_address: parse_address(&text(1)?)?,
+        assessed_at: row.try_get(2).map_err(pg_error("query"))?,
+        finding: RiskFinding {
+            fingerprint: text(3)?,
+            category: parse_variant("category", &text(4)?)?,
+            severity: parse_variant("severity", &text(5)?)?,
+            description: text(6)?,
+            metadata,
+            timestamp: row.try_get(8).map_err(pg_error("query"))?,
+            score_contribution: 0,
+        },
+    })
+}
+
+/// Whether retrying may succeed: connection loss, server restarts, serialization conflicts
+fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut | sqlx::Error::Protocol(_) => true,
+        // SQLSTATE class 08 is connection exceptions, 57P0x server shutdown, 40xxx transaction rollbacks
+        sqlx::Error::Database(db) => db.code().is_some_and(|code| {
+            code.starts_with("08") || code.starts_with("57P") || code.starts_with("40") || code == "53300"
+        }),
+        _ => false,
+    }
+}
+
+fn pg_error(operation: &'static str) -> impl Fn(sqlx::Error) -> RiskEngineError {
+    move |e| RiskEngineError::Storage { operation, message: e.to_string() }
+}
+
+fn writer_stopped() -> RiskEngineError {
+    RiskEngineError::Storage { operation: "write", message: "the history writer has stopped".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leader::Role;
+    use crate::provider::{FixtureProvider, MarketDataProvider};
+    use crate::risk::{finding_fingerprint, RiskCategory, RiskSeverity};
+    use crate::testing;
+    use std::time::Instant;
+
+    /// Connection string of a scratch database for the integration tests; they are skipped when unset
+    const TEST_DATABASE_ENV: &str = "COMETGUARD_TEST_POSTGRES_URL";
+
+    fn config(url: &str, instance_id: &str) -> PostgresConfig {
+        PostgresConfig {
+            url: url.to_string(),
+            ssl_mode: Some(PostgresSslMode::Disable),
+            root_cert: None,
+            instance_id: Some(instance_id.to_string()),
+            write_queue_capacity: 16,
+            write_retry_ms: 10,
+            leader_election: Default::default(),
+        }
+    }
+
+    /// Demo market moved to a fresh address, so runs against a shared database never overlap
+    async fn isolated_market() -> Market {
+        let mut market = FixtureProvider::demo().get_markets().await.unwrap().remove(0);
+        market.comet_address = Address::random();
+        market
+    }
+
+    /// `testing::assessment` of `market` made at `at`, with one utilization finding of `severity`
+    fn assessment(market: &Market, risk_score: u8, at: DateTime<Utc>, severity: RiskSeverity) -> RiskAssessment {
+        let fingerprint = finding_fingerprint(&market.comet_address, &RiskCategory::HighUtilization, "market");
+        let finding = RiskFinding {
+            description: format!("Utilization at {} risk", risk_score),
+            metadata: serde_json::json!({ "utilization": 0.9 }),
+            timestamp: at,
+            ..testing::finding(&fingerprint, severity)
+        };
+        RiskAssessment {
+            market_name: market.name.clone(),
+            market_address: market.comet_address,
+            timestamp: at,
+            ..testing::assessment(risk_score, vec![finding])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_never_block_on_unreachable_database() {
+        let storage = PostgresStorage::connect(&PostgresConfig {
+            write_queue_capacity: 2,
+            ..config("postgres://cometguard@127.0.0.1:1/cometguard", "offline")
+        })
+        .unwrap();
+        let market = isolated_market().await;
+
+        let started = Instant::now();
+        for score in 0..10 {
+            storage.record(1, &market, &assessment(&market, score, Utc::now(), RiskSeverity::Low)).await.unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+        // The writer holds at most one write while retrying; the rest queue up or are dropped
+        assert!(storage.dropped_writes() >= 7, "dropped {}", storage.dropped_writes());
+    }
+
+    #[tokio::test]
+    async fn test_connection_string_is_not_echoed() {
+        let err = PostgresStorage::connect(&config("postgres://user:hunter2@db:port/history", "a")).err().unwrap();
+        assert!(!err.to_string().contains("hunter2"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_instances_share_history() {
+        let Ok(url) = std::env::var(TEST_DATABASE_ENV) else {
+            eprintln!("{} not set; skipping", TEST_DATABASE_ENV);
+            return;
+        };
+        // Both engines start at once and race to migrate
+        let (first, second) = (
+            PostgresStorage::connect(&config(&url, "engine-a")).unwrap(),
+            PostgresStorage::connect(&config(&url, "engine-b")).unwrap(),
+        );
+        let (a, b) = tokio::join!(first.markets(), second.markets());
+        a.unwrap();
+        b.unwrap();
+
+        let market = isolated_market().await;
+        let at = DateTime::from_timestamp_millis(Utc::now().timestamp_millis()).unwrap();
+        let later = at + chrono::Duration::minutes(5);
+        first.record(1, &market, &assessment(&market, 40, at, RiskSeverity::Medium)).await.unwrap();
+        // The same assessment time from another engine is a separate row...
+        second.record(1, &market, &assessment(&market, 42, at, RiskSeverity::Medium)).await.unwrap();
+        // ...while a repeated write from the same engine is stored once
+        first.record(1, &market, &assessment(&market, 40, at, RiskSeverity::Medium)).await.unwrap();
+        first.record(1, &market, &assessment(&market, 75, later, RiskSeverity::High)).await.unwrap();
+        first.flush().await.unwrap();
+        second.flush().await.unwrap();
+
+        let series = first.score_series(market.comet_address, at, later + chrono::Duration::seconds(1)).await.unwrap();
+        let scores: Vec<u8> = series.iter().map(|p| p.risk_score).collect();
+        assert_eq!(scores.len(), 3);
+        assert!(scores[..2].contains(&40) && scores[..2].contains(&42));
+        assert_eq!((series[2].timestamp, series[2].risk_score), (later, 75));
+
+        let snapshots = second.snapshots(market.comet_address, at, later).await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].total_borrow, market.total_borrow);
+
+        let query = FindingQuery { market: Some(market.comet_address), ..FindingQuery::default() };
+        let high = FindingQuery { min_severity: Some(RiskSeverity::High), ..query.clone() };
+        let found = second.findings(&high).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].finding.metadata["utilization"], 0.9);
+        assert_eq!(found[0].finding.category, RiskCategory::HighUtilization);
+
+        let spans = first.fingerprint_spans(&query).await.unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].first_seen, spans[0].last_seen, spans[0].occurrences), (at, later, 3));
+        assert_eq!(spans[0].max_severity, RiskSeverity::High);
+        assert_eq!(spans[0].last_description, "Utilization at 75 risk");
+
+        let stored = first.find_market(&format!("{:?}", market.comet_address)).await.unwrap().unwrap();
+        assert_eq!((stored.assessments, stored.last_assessed), (3, later));
+
+        // Notes are written at once and read by every engine
+        let fingerprint = spans[0].fingerprint.clone();
+        let note = |note: &str, at| Annotation { fingerprint: fingerprint.clone(), note: note.to_string(), author: "alice".to_string(), created_at: at };
+        first.annotate(&note("looking into it", at)).await.unwrap();
+        second.annotate(&note("confirmed false positive", later)).await.unwrap();
+        let notes = second.annotations(&[fingerprint.clone(), "unknown".to_string()]).await.unwrap();
+        assert_eq!(notes, [note("looking into it", at), note("confirmed false positive", later)]);
+        assert_eq!(first.latest_annotations(std::slice::from_ref(&fingerprint)).await.unwrap()[&fingerprint].note, "confirmed false positive");
+    }
+
+    #[tokio::test]
+    async fn test_engines_sharing_a_database_elect_one_leader() {
+        let Ok(url) = std::env::var(TEST_DATABASE_ENV) else {
+            eprintln!("{} not set; skipping", TEST_DATABASE_ENV);
+            return;
+        };
+        let mut postgres = config(&url, "engine");
+        postgres.leader_election.enabled = true;
+        // A lease of its own, so runs against a shared database never overlap
+        postgres.leader_election.name = format!("test-{:?}", Address::random());
+        postgres.leader_election.lease_secs = 2;
+        postgres.leader_election.heartbeat_secs = 1;
+        let config = crate::config::Config {
+            storage: crate::config::StorageConfig { postgres: Some(postgres), ..Default::default() },
+            ..Default::default()
+        };
+        let engine = || async {
+            crate::RiskEngine::builder()
+                .config(config.clone())
+                .provider(FixtureProvider::demo())
+                .build()
+                .await
+                .unwrap()
+        };
+        // Both configured with the same instance_id, yet told apart in the lease table
+        let (a, b) = (engine().await, engine().await);
+        let (a_status, b_status) = (a.health().await.leadership.unwrap(), b.health().await.leadership.unwrap());
+        assert_eq!((a_status.role, b_status.role), (Role::Leader, Role::Standby));
+        assert_ne!(a_status.holder, b_status.holder);
+        assert!(a_status.holder.starts_with("engine/"), "{}", a_status.holder);
+
+        let since = Utc::now() - chrono::Duration::seconds(1);
+        a.assess_risks().await.unwrap();
+        b.assess_risks().await.unwrap();
+        a.storage().unwrap().flush().await.unwrap();
+        b.storage().unwrap().flush().await.unwrap();
+        let market = a.latest_assessments()[0].market_address;
+        let series = b.storage().unwrap().score_series(market, since, Utc::now()).await.unwrap();
+        assert_eq!(series.len(), 1, "only the leader writes history");
+
+        // The leader goes away without releasing the lease: the standby takes over once it
+        // expires, within lease_secs and a heartbeat
+        let started = Instant::now();
+        drop(a);
+        while !b.is_leader() {
+            assert!(started.elapsed() < Duration::from_secs(4), "no failover after {:?}", started.elapsed());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(b.leader_status().unwrap().term, Some(a_status.term.unwrap() + 1));
+        b.shutdown().await;
+    }
+}
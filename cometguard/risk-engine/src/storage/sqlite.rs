@@ -0,0 +1,628 @@
+//! SQLite `Storage` backend for a single engine
+
+use super::{
+    address_key, non_empty, parse_address, parse_variant, severity_from_rank, severity_rank, FindingQuery, FingerprintSpan,
+    MarketSnapshot, ScorePoint, Storage, StoredFinding, StoredMarket,
+};
+use crate::annotations::Annotation;
+use crate::collateral_changes::CollateralParameters;
+use crate::error::{Result, RiskEngineError};
+use crate::models::Market;
+use crate::risk::{RiskAssessment, RiskFinding};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order; `PRAGMA user_version` records how many have run
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/sqlite/0001_history.sql"),
+    include_str!("../../migrations/sqlite/0002_baseline.sql"),
+    include_str!("../../migrations/sqlite/0003_versions.sql"),
+    include_str!("../../migrations/sqlite/0004_collateral.sql"),
+    include_str!("../../migrations/sqlite/0005_category_scores.sql"),
+    include_str!("../../migrations/sqlite/0006_annotations.sql"),
+];
+
+/// Assessment history stored in a local SQLite database
+///
+/// Statements run on the blocking thread pool, so a slow disk never stalls the runtime.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+    path: String,
+}
+
+impl SqliteStorage {
+    /// Open (creating if needed) the database at `path` and apply pending migrations
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).map_err(sql_error("open"))?;
+        Self::from_connection(conn, path.display().to_string())
+    }
+
+    /// Database that lives only as long as the returned value
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().map_err(sql_error("open"))?;
+        Self::from_connection(conn, ":memory:".to_string())
+    }
+
+    fn from_connection(mut conn: Connection, path: String) -> Result<Self> {
+        conn.pragma_update(None, "foreign_keys", true).map_err(sql_error("open"))?;
+        // Take the write lock up front so concurrent openers migrate one at a time
+        let tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(sql_error("migration"))?;
+        let applied: usize = tx
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(sql_error("migration"))?;
+        if applied > MIGRATIONS.len() {
+            return Err(RiskEngineError::Storage {
+                operation: "migration",
+                message: format!(
+                    "database schema version {} is newer than this build supports ({})",
+                    applied,
+                    MIGRATIONS.len()
+                ),
+            });
+        }
+        for migration in &MIGRATIONS[applied..] {
+            tx.execute_batch(migration).map_err(sql_error("migration"))?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len()).map_err(sql_error("migration"))?;
+        tx.commit().map_err(sql_error("migration"))?;
+
+        Ok(Self { conn: Arc::new(Mutex::new(conn)), path })
+    }
+
+    /// Number of migrations applied to the database
+    pub fn schema_version(&self) -> Result<usize> {
+        self.lock()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(sql_error("query"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `f` against the connection on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .map_err(|e| RiskEngineError::Storage { operation: "query", message: e.to_string() })?
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn name(&self) -> String {
+        format!("sqlite {}", self.path)
+    }
+
+    async fn record(&self, chain_id: u64, market: &Market, assessment: &RiskAssessment) -> Result<()> {
+        let (market, assessment) = (market.clone(), assessment.clone());
+        self.blocking(move |conn| insert_assessment(conn, chain_id, &market, &assessment)).await
+    }
+
+    async fn markets(&self) -> Result<Vec<StoredMarket>> {
+        self.blocking(|conn| select_markets(conn)).await
+    }
+
+    async fn score_series(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<ScorePoint>> {
+        self.blocking(move |conn| select_score_series(conn, market, since, until)).await
+    }
+
+    async fn snapshots(&self, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<MarketSnapshot>> {
+        self.blocking(move |conn| select_snapshots(conn, market, since, until, None)).await
+    }
+
+    async fn latest_snapshots(&self, market: Address, until: DateTime<Utc>, count: u32) -> Result<Vec<MarketSnapshot>> {
+        self.blocking(move |conn| select_snapshots(conn, market, DateTime::<Utc>::MIN_UTC, until, Some(count))).await
+    }
+
+    async fn findings(&self, query: &FindingQuery) -> Result<Vec<StoredFinding>> {
+        let query = query.clone();
+        self.blocking(move |conn| select_findings(conn, &query)).await
+    }
+
+    async fn fingerprint_spans(&self, query: &FindingQuery) -> Result<Vec<FingerprintSpan>> {
+        let query = query.clone();
+        self.blocking(move |conn| select_fingerprint_spans(conn, &query)).await
+    }
+
+    async fn annotate(&self, annotation: &Annotation) -> Result<()> {
+        let annotation = annotation.clone();
+        self.blocking(move |conn| {
+            conn.execute(
+                "INSERT INTO annotations (fingerprint, note, author, created_at) VALUES (?1, ?2, ?3, ?4)",
+                params![annotation.fingerprint, annotation.note, annotation.author, annotation.created_at.timestamp_millis()],
+            )
+            .map_err(sql_error("write"))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn annotations(&self, fingerprints: &[String]) -> Result<Vec<Annotation>> {
+        let fingerprints = fingerprints.to_vec();
+        self.blocking(move |conn| select_annotations(conn, &fingerprints)).await
+    }
+}
+
+/// Insert `assessment` of `market` with its findings and market snapshot in one transaction
+fn insert_assessment(conn: &mut Connection, chain_id: u64, market: &Market, assessment: &RiskAssessment) -> Result<()> {
+    let category_scores = serde_json::to_string(&assessment.category_scores)
+        .map_err(|e| RiskEngineError::serialization("category scores", e))?;
+    let tx = conn.transaction().map_err(sql_error("write"))?;
+    tx.execute(
+        "INSERT INTO assessments (chain_id, market_address, market_name, assessed_at, risk_score, mock_data, watchlist_min_health_factor, engine_version, config_hash, category_scores)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            chain_id as i64,
+            address_key(&assessment.market_address),
+            assessment.market_name,
+            assessment.timestamp.timestamp_millis(),
+            assessment.risk_score,
+            assessment.mock_data,
+            assessment.watchlist_min_health_factor,
+            non_empty(&assessment.engine_version),
+            non_empty(&assessment.config_hash),
+            (!assessment.category_scores.is_empty()).then_some(category_scores),
+        ],
+    )
+    .map_err(sql_error("write"))?;
+    let id = tx.last_insert_rowid();
+
+    for finding in &assessment.findings {
+        let metadata = serde_json::to_string(&finding.metadata)
+            .map_err(|e| RiskEngineError::serialization("finding metadata", e))?;
+        tx.execute(
+            "INSERT INTO findings (assessment_id, fingerprint, category, severity, severity_rank, description, metadata, found_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id,
+                finding.fingerprint,
+                format!("{:?}", finding.category),
+                format!("{:?}", finding.severity),
+                severity_rank(finding.severity),
+                finding.description,
+                metadata,
+                finding.timestamp.timestamp_millis(),
+            ],
+        )
+        .map_err(sql_error("write"))?;
+    }
+
+    let collateral = serde_json::to_string(&CollateralParameters::of(market))
+        .map_err(|e| RiskEngineError::serialization("collateral parameters", e))?;
+    tx.execute(
+        "INSERT INTO market_snapshots (assessment_id, base_symbol, base_price, total_supply, total_borrow, utilization_rate, supply_apr, borrow_apr, reserves, price_volatility, collateral)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            id,
+            market.base_asset.symbol,
+            market.base_asset.price,
+            market.total_supply,
+            market.total_borrow,
+            market.utilization(),
+            market.supply_apr,
+            market.borrow_apr,
+            assessment.reserves_usd,
+            assessment.price_volatility,
+            collateral,
+        ],
+    )
+    .map_err(sql_error("write"))?;
+    tx.commit().map_err(sql_error("write"))?;
+    Ok(())
+}
+
+fn select_markets(conn: &Connection) -> Result<Vec<StoredMarket>> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT market_name, market_address, COUNT(*), MAX(assessed_at) FROM assessments
+             GROUP BY market_address ORDER BY market_name",
+        )
+        .map_err(sql_error("query"))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get(3)?)))
+        .map_err(sql_error("query"))?;
+
+    let mut markets = Vec::new();
+    for row in rows {
+        let (name, address, assessments, last_assessed) = row.map_err(sql_error("query"))?;
+        markets.push(StoredMarket {
+            name,
+            address: parse_address(&address)?,
+            assessments: assessments as u64,
+            last_assessed: from_millis(last_assessed)?,
+        });
+    }
+    Ok(markets)
+}
+
+fn select_score_series(conn: &Connection, market: Address, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<ScorePoint>> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT assessed_at, risk_score, engine_version, category_scores FROM assessments
+             WHERE market_address = ?1 AND assessed_at >= ?2 AND assessed_at < ?3 ORDER BY assessed_at",
+        )
+        .map_err(sql_error("query"))?;
+    let rows = stmt
+        .query_map(params![address_key(&market), since.timestamp_millis(), until.timestamp_millis()], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, u8>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+        })
+        .map_err(sql_error("query"))?;
+
+    let mut points = Vec::new();
+    for row in rows {
+        let (timestamp, risk_score, engine_version, category_scores) = row.map_err(sql_error("query"))?;
+        let category_scores = match category_scores {
+            Some(json) => serde_json::from_str(&json).map_err(|e| RiskEngineError::serialization("category scores", e))?,
+            None => BTreeMap::new(),
+        };
+        points.push(ScorePoint { timestamp: from_millis(timestamp)?, risk_score, engine_version, category_scores });
+    }
+    Ok(points)
+}
+
+/// Snapshots of `market` in `[since, until)`, oldest first; with a `latest` limit only that
+/// many of the newest
+fn select_snapshots(conn: &Connection, market: Address, since: DateTime<Utc>, until: DateTime<Utc>, latest: Option<u32>) -> Result<Vec<MarketSnapshot>> {
+    let order = match latest {
+        Some(limit) => format!("a.assessed_at DESC LIMIT {}", limit),
+        None => "a.assessed_at".to_string(),
+    };
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT a.assessed_at, s.base_symbol, s.base_price, s.total_supply, s.total_borrow, s.utilization_rate, s.supply_apr, s.borrow_apr,
+                    s.reserves, s.price_volatility, s.collateral
+             FROM market_snapshots s JOIN assessments a ON a.id = s.assessment_id
+             WHERE a.market_address = ?1 AND a.assessed_at >= ?2 AND a.assessed_at < ?3 ORDER BY {}",
+            order
+        ))
+        .map_err(sql_error("query"))?;
+    let rows = stmt
+        .query_map(params![address_key(&market), since.timestamp_millis(), until.timestamp_millis()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                MarketSnapshot {
+                    timestamp: DateTime::<Utc>::MIN_UTC,
+                    base_symbol: row.get(1)?,
+                    base_price: row.get(2)?,
+                    total_supply: row.get(3)?,
+                    total_borrow: row.get(4)?,
+                    utilization_rate: row.get(5)?,
+                    supply_apr: row.get(6)?,
+                    borrow_apr: row.get(7)?,
+                    reserves: row.get(8)?,
+                    price_volatility: row.get(9)?,
+                    collateral: Vec::new(),
+                },
+                row.get::<_, Option<String>>(10)?,
+            ))
+        })
+        .map_err(sql_error("query"))?;
+
+    let mut snapshots = Vec::new();
+    for row in rows {
+        let (timestamp, snapshot, collateral) = row.map_err(sql_error("query"))?;
+        let collateral = match collateral {
+            Some(json) => serde_json::from_str(&json).map_err(|e| RiskEngineError::serialization("collateral parameters", e))?,
+            None => Vec::new(),
+        };
+        snapshots.push(MarketSnapshot { timestamp: from_millis(timestamp)?, collateral, ..snapshot });
+    }
+    if latest.is_some() {
+        snapshots.reverse();
+    }
+    Ok(snapshots)
+}
+
+fn select_findings(conn: &Connection, query: &FindingQuery) -> Result<Vec<StoredFinding>> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT a.market_name, a.market_address, a.assessed_at, f.fingerprint, f.category, f.severity, f.description, f.metadata, f.found_at
+             FROM findings f JOIN assessments a ON a.id = f.assessment_id
+             WHERE {} ORDER BY a.assessed_at, f.id",
+            FINDING_FILTER
+        ))
+        .map_err(sql_error("query"))?;
+    let rows = stmt
+        .query_map(params_from_iter(filter_params(query)), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, i64>(8)?,
+            ))
+        })
+        .map_err(sql_error("query"))?;
+
+    let mut findings = Vec::new();
+    for row in rows {
+        let (market_name, address, assessed_at, fingerprint, category, severity, description, metadata, found_at) =
+            row.map_err(sql_error("query"))?;
+        findings.push(StoredFinding {
+            market_name,
+            market_address: parse_address(&address)?,
+            assessed_at: from_millis(assessed_at)?,
+            finding: RiskFinding {
+                category: parse_variant("category", &category)?,
+                severity: parse_variant("severity", &severity)?,
+                description,
+                metadata: serde_json::from_str(&metadata)
+                    .map_err(|e| RiskEngineError::serialization("stored finding metadata", e))?,
+                timestamp: from_millis(found_at)?,
+                fingerprint,
+                score_contribution: 0,
+            },
+        });
+    }
+    Ok(findings)
+}
+
+fn select_fingerprint_spans(conn: &Connection, query: &FindingQuery) -> Result<Vec<FingerprintSpan>> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT f.fingerprint, a.market_name, a.market_address, f.category, MAX(f.severity_rank),
+                    MIN(a.assessed_at), MAX(a.assessed_at), COUNT(*),
+                    (SELECT latest.description FROM findings latest WHERE latest.fingerprint = f.fingerprint
+                     ORDER BY latest.id DESC LIMIT 1)
+             FROM findings f JOIN assessments a ON a.id = f.assessment_id
+             WHERE {} GROUP BY f.fingerprint ORDER BY MIN(a.assessed_at), f.fingerprint",
+            FINDING_FILTER
+        ))
+        .map_err(sql_error("query"))?;
+    let rows = stmt
+        .query_map(params_from_iter(filter_params(query)), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, usize>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, i64>(7)?,
+                row.get::<_, String>(8)?,
+            ))
+        })
+        .map_err(sql_error("query"))?;
+
+    let mut spans = Vec::new();
+    for row in rows {
+        let (fingerprint, market_name, address, category, rank, first_seen, last_seen, occurrences, last_description) =
+            row.map_err(sql_error("query"))?;
+        spans.push(FingerprintSpan {
+            fingerprint,
+            market_name,
+            market_address: parse_address(&address)?,
+            category: parse_variant("category", &category)?,
+            max_severity: severity_from_rank(rank)?,
+            first_seen: from_millis(first_seen)?,
+            last_seen: from_millis(last_seen)?,
+            occurrences: occurrences as u64,
+            last_description,
+        });
+    }
+    Ok(spans)
+}
+
+/// Annotations of `fingerprints`, oldest first, queried in batches that stay under SQLite's
+/// limit on bound parameters
+fn select_annotations(conn: &Connection, fingerprints: &[String]) -> Result<Vec<Annotation>> {
+    let mut annotations = Vec::new();
+    for batch in fingerprints.chunks(500) {
+        let placeholders = vec!["?"; batch.len()].join(", ");
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, fingerprint, note, author, created_at FROM annotations WHERE fingerprint IN ({})",
+                placeholders
+            ))
+            .map_err(sql_error("query"))?;
+        let rows = stmt
+            .query_map(params_from_iter(batch), |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(sql_error("query"))?;
+        for row in rows {
+            let (id, fingerprint, note, author, created_at) = row.map_err(sql_error("query"))?;
+            annotations.push((id, Annotation { fingerprint, note, author, created_at: from_millis(created_at)? }));
+        }
+    }
+    annotations.sort_by_key(|(id, annotation)| (annotation.created_at, *id));
+    Ok(annotations.into_iter().map(|(_, annotation)| annotation).collect())
+}
+
+/// `WHERE` clause shared by the finding queries; see `filter_params` for the parameters
+const FINDING_FILTER: &str = "(?1 IS NULL OR a.market_address = ?1) AND (?2 IS NULL OR f.category = ?2)
+     AND f.severity_rank >= ?3 AND a.assessed_at >= ?4 AND a.assessed_at < ?5";
+
+fn filter_params(query: &FindingQuery) -> Vec<Box<dyn rusqlite::ToSql>> {
+    vec![
+        Box::new(query.market.as_ref().map(address_key)),
+        Box::new(query.category.as_ref().map(|c| format!("{:?}", c))),
+        Box::new(query.min_severity.map(severity_rank).unwrap_or(0)),
+        Box::new(query.since.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN)),
+        Box::new(query.until.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX)),
+    ]
+}
+
+fn from_millis(millis: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(millis).ok_or_else(|| RiskEngineError::Parse {
+        what: "stored timestamp",
+        input: millis.to_string(),
+        message: "out of range".to_string(),
+    })
+}
+
+fn sql_error(operation: &'static str) -> impl Fn(rusqlite::Error) -> RiskEngineError {
+    move |e| RiskEngineError::Storage { operation, message: e.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{FixtureProvider, MarketDataProvider};
+    use crate::risk::{finding_fingerprint, RiskCategory, RiskSeverity};
+    use crate::testing;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    /// `testing::finding` of `category` in `market`, found at `at`
+    fn finding(market: &Market, category: RiskCategory, severity: RiskSeverity, at: DateTime<Utc>) -> RiskFinding {
+        RiskFinding {
+            fingerprint: finding_fingerprint(&market.comet_address, &category, "market"),
+            category,
+            metadata: serde_json::json!({ "utilization": 0.93 }),
+            timestamp: at,
+            ..testing::finding(&market.name, severity)
+        }
+    }
+
+    /// `testing::assessment` of `market` from mock data, made at `at`
+    fn assessment(market: &Market, risk_score: u8, at: DateTime<Utc>, findings: Vec<RiskFinding>) -> RiskAssessment {
+        RiskAssessment {
+            market_name: market.name.clone(),
+            market_address: market.comet_address,
+            timestamp: at,
+            mock_data: true,
+            ..testing::assessment(risk_score, findings)
+        }
+    }
+
+    async fn demo_markets() -> Vec<Market> {
+        FixtureProvider::demo().get_markets().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_history_queries() {
+        let markets = demo_markets().await;
+        let (usdc, other) = (&markets[0], &markets[1]);
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let start = DateTime::from_timestamp(1_790_000_000, 0).unwrap();
+        let day = |n: i64| start + Duration::days(n);
+
+        let utilization = |severity, at| finding(usdc, RiskCategory::HighUtilization, severity, at);
+        storage.record(1, usdc, &assessment(usdc, 20, day(0), vec![])).await.unwrap();
+        let with_reserves = RiskAssessment {
+            reserves_usd: Some(25_000_000.0),
+            price_volatility: Some(0.4),
+            ..assessment(usdc, 45, day(1), vec![utilization(RiskSeverity::Medium, day(1))])
+        };
+        storage.record(1, usdc, &with_reserves).await.unwrap();
+        storage.record(1, usdc, &assessment(usdc, 70, day(2), vec![utilization(RiskSeverity::High, day(1))])).await.unwrap();
+        let volatility = finding(other, RiskCategory::PriceVolatility, RiskSeverity::Low, day(2));
+        storage.record(1, other, &assessment(other, 10, day(2), vec![volatility])).await.unwrap();
+
+        let series = storage.score_series(usdc.comet_address, day(1), day(3)).await.unwrap();
+        let point = |timestamp, risk_score| ScorePoint { timestamp, risk_score, engine_version: None, category_scores: Default::default() };
+        assert_eq!(series, vec![point(day(1), 45), point(day(2), 70)]);
+        let snapshots = storage.snapshots(usdc.comet_address, day(0), day(1)).await.unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].utilization_rate, usdc.utilization_rate);
+        // Reserves and volatility where the assessment read them
+        let snapshots = storage.snapshots(usdc.comet_address, day(0), day(2)).await.unwrap();
+        assert_eq!(snapshots.iter().map(|s| (s.reserves, s.price_volatility)).collect::<Vec<_>>(), [(None, None), (Some(25_000_000.0), Some(0.4))]);
+        // The latest before a time, with the collateral parameters of the market then
+        let latest = storage.latest_snapshot(usdc.comet_address, day(2)).await.unwrap().unwrap();
+        assert_eq!((latest.timestamp, latest.collateral), (day(1), CollateralParameters::of(usdc)));
+        assert!(storage.latest_snapshot(usdc.comet_address, day(0)).await.unwrap().is_none());
+
+        let high = storage
+            .findings(&FindingQuery { min_severity: Some(RiskSeverity::High), ..FindingQuery::default() })
+            .await
+            .unwrap();
+        assert_eq!(high.len(), 1);
+        assert_eq!(high[0].assessed_at, day(2));
+        assert_eq!(high[0].finding.metadata["utilization"], 0.93);
+        let volatility = storage
+            .findings(&FindingQuery { category: Some(RiskCategory::PriceVolatility), ..FindingQuery::default() })
+            .await
+            .unwrap();
+        assert_eq!(volatility[0].market_address, other.comet_address);
+        let day_one = FindingQuery { since: Some(day(1)), until: Some(day(2)), ..FindingQuery::default() };
+        assert_eq!(storage.findings(&day_one).await.unwrap().len(), 1);
+
+        let spans = storage
+            .fingerprint_spans(&FindingQuery { market: Some(usdc.comet_address), ..FindingQuery::default() })
+            .await
+            .unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].first_seen, spans[0].last_seen, spans[0].occurrences), (day(1), day(2), 2));
+        assert_eq!(spans[0].max_severity, RiskSeverity::High);
+        assert_eq!(storage.fingerprint_span(&spans[0].fingerprint).await.unwrap(), Some(spans[0].clone()));
+        assert_eq!(storage.fingerprint_span("unknown").await.unwrap(), None);
+
+        let stored = storage.markets().await.unwrap();
+        assert_eq!(stored.len(), 2);
+        let found = storage.find_market(&usdc.name.to_lowercase()).await.unwrap().unwrap();
+        assert_eq!((found.address, found.assessments, found.last_assessed), (usdc.comet_address, 3, day(2)));
+        assert_eq!(storage.find_market(&format!("{:?}", other.comet_address)).await.unwrap().unwrap().name, other.name);
+    }
+
+    #[tokio::test]
+    async fn test_annotations_by_fingerprint() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let at = DateTime::from_timestamp(1_790_000_000, 0).unwrap();
+        let note = |fingerprint: &str, note: &str, minutes: i64| Annotation {
+            fingerprint: fingerprint.to_string(),
+            note: note.to_string(),
+            author: "alice".to_string(),
+            created_at: at + Duration::minutes(minutes),
+        };
+        storage.annotate(&note("a", "confirmed false positive", 5)).await.unwrap();
+        storage.annotate(&note("a", "looking into it", 0)).await.unwrap();
+        storage.annotate(&note("b", "feed migrated", 1)).await.unwrap();
+
+        let a = storage.annotations(&["a".to_string()]).await.unwrap();
+        assert_eq!(a, [note("a", "looking into it", 0), note("a", "confirmed false positive", 5)]);
+        let latest = storage.latest_annotations(&["a".to_string(), "b".to_string(), "c".to_string()]).await.unwrap();
+        assert_eq!(latest.values().map(|a| a.note.as_str()).collect::<Vec<_>>(), ["confirmed false positive", "feed migrated"]);
+        assert!(storage.annotations(&[]).await.unwrap().is_empty());
+        // More fingerprints than SQLite binds in one statement
+        let many: Vec<String> = (0..1200).map(|i| i.to_string()).chain(["b".to_string()]).collect();
+        assert_eq!(storage.annotations(&many).await.unwrap(), [note("b", "feed migrated", 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_migrations_apply_once() {
+        let markets = demo_markets().await;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.db");
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+        storage.record(1, &markets[0], &assessment(&markets[0], 30, Utc::now(), vec![])).await.unwrap();
+        drop(storage);
+
+        let reopened = SqliteStorage::open(&path).unwrap();
+        assert_eq!(reopened.schema_version().unwrap(), MIGRATIONS.len());
+        assert_eq!(reopened.markets().await.unwrap()[0].assessments, 1);
+        drop(reopened);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        drop(conn);
+        assert!(matches!(SqliteStorage::open(&path), Err(RiskEngineError::Storage { operation: "migration", .. })));
+    }
+}
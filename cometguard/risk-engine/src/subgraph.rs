@@ -0,0 +1,930 @@
+//! The Graph subgraph as a data source alongside (or instead of) RPC
+//!
+//! `SubgraphProvider` reads markets, positions, borrower lists and daily price snapshots
+//! with paginated GraphQL queries. `RoutedProvider` picks RPC or the subgraph per kind of
+//! data as configured in `subgraph`, and can cross-check market state between the two.
+//! Responses carry the subgraph's indexed block; when it trails the chain head by more
+//! than `max_lag_blocks` the subgraph is flagged as lagging.
+//!
+//! The queries expect a Compound V3 subgraph with `markets`, `positions` and
+//! `tokenDailySnapshots` entities, amounts as decimals in token units and factors and
+//! rates as fractions.
+
+use crate::compound::PositionResult;
+use crate::config::{Config, SourceKind, SubgraphConfig};
+use crate::ens::EnsResolver;
+use crate::gas::GasQuote;
+use crate::error::{rpc_host, Result, RiskEngineError};
+use crate::models::{Asset, AssetType, ConfigurationPair, DataIssue, DataIssueKind, ManagerPermissions, Market, PriceHistory, ProtocolMetrics, Provenance, SequencerStatus, UserPosition};
+use crate::provider::{CacheStats, MarketDataProvider, SharedProvider};
+use crate::rpc::RpcBudget;
+use crate::scanner::LogSource;
+use crate::utils::{expand_env, parse_address, to_checksum_address};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::{Address, U256};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, instrument, warn};
+
+const MARKETS_QUERY: &str = r#"
+query Markets($first: Int!, $after: String!) {
+  _meta { block { number } }
+  markets(first: $first, where: { id_gt: $after }, orderBy: id) {
+    id
+    baseToken { address symbol decimals lastPriceUsd }
+    totalBaseSupply
+    totalBaseBorrow
+    supplyApr
+    borrowApr
+    collateralTokens {
+      token { address symbol decimals lastPriceUsd }
+      borrowCollateralFactor
+      liquidateCollateralFactor
+      liquidationFactor
+      supplyCap
+    }
+  }
+}"#;
+
+const POSITIONS_QUERY: &str = r#"
+query Positions($market: String!, $accounts: [String!]!, $first: Int!, $after: String!) {
+  _meta { block { number } }
+  positions(first: $first, where: { market: $market, account_in: $accounts, id_gt: $after }, orderBy: id) {
+    id
+    account { id }
+    baseBalance
+    collateralBalances { token { address } balance }
+  }
+}"#;
+
+const BORROWERS_QUERY: &str = r#"
+query Borrowers($market: String!, $first: Int!, $after: String!) {
+  _meta { block { number } }
+  positions(first: $first, where: { market: $market, baseBalance_lt: "0", id_gt: $after }, orderBy: id) {
+    id
+    account { id }
+  }
+}"#;
+
+const DAILY_PRICES_QUERY: &str = r#"
+query DailyPrices($token: String!, $since: BigInt!) {
+  tokenDailySnapshots(first: 1000, where: { token: $token, timestamp_gte: $since }, orderBy: timestamp) {
+    timestamp
+    priceUsd
+  }
+}"#;
+
+const RESERVES_QUERY: &str = r#"
+query Reserves($market: ID!) {
+  market(id: $market) { reserves }
+}"#;
+
+const META_QUERY: &str = r#"
+query Meta {
+  _meta { block { number } }
+}"#;
+
+/// Days of daily snapshots read for price history
+const PRICE_HISTORY_DAYS: i64 = 30;
+
+/// How far the subgraph trails the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubgraphLag {
+    /// Last block the subgraph has indexed
+    pub indexed_block: u64,
+    pub chain_head: u64,
+    pub lag_blocks: u64,
+    /// Whether `lag_blocks` exceeds `subgraph.max_lag_blocks`
+    pub lagging: bool,
+}
+
+/// Market data read from a Compound V3 subgraph
+pub struct SubgraphProvider {
+    http: reqwest::Client,
+    endpoint: String,
+    host: String,
+    page_size: usize,
+    max_lag_blocks: u64,
+    chain_head: Option<Arc<dyn LogSource>>,
+    last_lag: Mutex<Option<SubgraphLag>>,
+}
+
+impl SubgraphProvider {
+    /// Provider for the endpoint configured for `config.compound.chain_id`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let chain_id = config.compound.chain_id;
+        let endpoint = config.subgraph.endpoints.get(&chain_id).ok_or_else(|| {
+            RiskEngineError::config("subgraph.endpoints", format!("no subgraph endpoint for chain {}", chain_id))
+        })?;
+        let endpoint = expand_env("subgraph.endpoints", endpoint)?;
+        Self::new(endpoint, &config.subgraph, std::time::Duration::from_secs(config.performance.timeout_seconds))
+    }
+
+    /// Provider querying `endpoint`
+    pub fn new(endpoint: String, settings: &SubgraphConfig, timeout: std::time::Duration) -> Result<Self> {
+        if settings.page_size == 0 {
+            return Err(RiskEngineError::config("subgraph.page_size", "must be at least 1"));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| RiskEngineError::config("subgraph", e.to_string()))?;
+        Ok(Self {
+            http,
+            host: rpc_host(&endpoint),
+            endpoint,
+            page_size: settings.page_size,
+            max_lag_blocks: settings.max_lag_blocks,
+            chain_head: None,
+            last_lag: Mutex::new(None),
+        })
+    }
+
+    /// Compare the indexed block against the head reported by `source`
+    pub fn with_chain_head(mut self, source: Option<Arc<dyn LogSource>>) -> Self {
+        self.chain_head = source;
+        self
+    }
+
+    /// Lag observed with the most recent market read, if the chain head is known
+    pub fn last_lag(&self) -> Option<SubgraphLag> {
+        *self.last_lag.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Current lag behind the chain head
+    pub async fn lag(&self) -> Result<SubgraphLag> {
+        let data = self.query("Meta", META_QUERY, &json!({})).await?;
+        let indexed_block = indexed_block(&data).ok_or_else(|| self.unavailable("response has no `_meta.block`"))?;
+        self.observe_lag(Some(indexed_block))
+            .await
+            .ok_or_else(|| RiskEngineError::config("subgraph", "the chain head is unknown without an RPC log source"))
+    }
+
+    /// Accounts with an open borrow in the market with Comet proxy `comet`, and the block they are indexed at
+    #[instrument(level = "debug", skip(self), fields(subgraph_host = %self.host), err(level = "debug"))]
+    pub async fn borrowers(&self, comet: Address) -> Result<(Vec<Address>, Option<u64>)> {
+        let variables = json!({ "market": format!("{:?}", comet) });
+        let (positions, block): (Vec<PositionEntity>, _) =
+            self.paginate("Borrowers", BORROWERS_QUERY, "positions", variables).await?;
+        let borrowers = positions.iter().map(|p| parse_address(&p.account.id)).collect::<Result<_>>()?;
+        Ok((borrowers, block))
+    }
+
+    /// Run one GraphQL query and return its `data`
+    ///
+    /// Errors name the endpoint's host only, since subgraph URLs commonly embed API keys.
+    async fn query(&self, operation: &'static str, query: &str, variables: &Value) -> Result<Value> {
+        let body = json!({ "operationName": operation, "query": query, "variables": variables });
+        let response = self.http.post(&self.endpoint).json(&body).send().await.map_err(|e| self.unavailable(e.without_url()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(self.unavailable(format!("{} returned HTTP {}", operation, status)));
+        }
+        let mut payload: Value = response.json().await.map_err(|e| self.unavailable(e.without_url()))?;
+        if let Some(errors) = payload["errors"].as_array().filter(|errors| !errors.is_empty()) {
+            let messages: Vec<&str> = errors.iter().filter_map(|e| e["message"].as_str()).collect();
+            return Err(self.unavailable(format!("{} failed: {}", operation, messages.join("; "))));
+        }
+        Ok(payload["data"].take())
+    }
+
+    /// Every entity of `field`, following `id_gt` cursors one page at a time
+    ///
+    /// Returns the lowest indexed block seen across pages.
+    async fn paginate<T: DeserializeOwned + Paged>(
+        &self,
+        operation: &'static str,
+        query: &str,
+        field: &str,
+        mut variables: Value,
+    ) -> Result<(Vec<T>, Option<u64>)> {
+        let mut entities = Vec::new();
+        let mut block: Option<u64> = None;
+        let mut after = String::new();
+        loop {
+            variables["first"] = json!(self.page_size);
+            variables["after"] = json!(after);
+            let mut data = self.query(operation, query, &variables).await?;
+            block = match (block, indexed_block(&data)) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let page: Vec<T> = serde_json::from_value(data[field].take())
+                .map_err(|e| RiskEngineError::serialization(format!("subgraph {} response", operation), e))?;
+            let full = page.len() >= self.page_size;
+            if let Some(last) = page.last() {
+                after = last.id().to_string();
+            }
+            entities.extend(page);
+            if !full {
+                return Ok((entities, block));
+            }
+        }
+    }
+
+    /// Record the lag at `indexed_block`, warning when it is over the threshold
+    async fn observe_lag(&self, indexed_block: Option<u64>) -> Option<SubgraphLag> {
+        let (indexed_block, source) = (indexed_block?, self.chain_head.as_ref()?);
+        let chain_head = match source.head_block().await {
+            Ok(head) => head,
+            Err(e) => {
+                debug!("No chain head to compare the subgraph against: {}", e);
+                return None;
+            }
+        };
+        let lag_blocks = chain_head.saturating_sub(indexed_block);
+        let lag = SubgraphLag {
+            indexed_block,
+            chain_head,
+            lag_blocks,
+            lagging: lag_blocks > self.max_lag_blocks,
+        };
+        if lag.lagging {
+            warn!(
+                "Subgraph at {} is {} blocks behind the chain head (indexed {}, head {}); its data may be stale",
+                self.host, lag_blocks, indexed_block, chain_head
+            );
+        }
+        *self.last_lag.lock().unwrap_or_else(|e| e.into_inner()) = Some(lag);
+        Some(lag)
+    }
+
+    fn unavailable(&self, reason: impl std::fmt::Display) -> RiskEngineError {
+        RiskEngineError::Unavailable {
+            what: "subgraph data",
+            reason: format!("{}: {}", self.host, reason),
+        }
+    }
+
+    /// Positions of `users` in `market`, keyed by account
+    async fn fetch_positions(&self, market: &Market, users: &[Address]) -> Result<HashMap<Address, UserPosition>> {
+        let mut positions = HashMap::new();
+        for accounts in users.chunks(self.page_size) {
+            let variables = json!({
+                "market": format!("{:?}", market.comet_address),
+                "accounts": accounts.iter().map(|a| format!("{:?}", a)).collect::<Vec<_>>(),
+            });
+            let (entities, _): (Vec<PositionEntity>, _) =
+                self.paginate("Positions", POSITIONS_QUERY, "positions", variables).await?;
+            for entity in entities {
+                let position = entity.into_position(market)?;
+                positions.insert(position.address, position);
+            }
+        }
+        Ok(positions)
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for SubgraphProvider {
+    #[instrument(level = "debug", skip(self), fields(subgraph_host = %self.host), err(level = "debug"))]
+    async fn get_markets(&self) -> Result<Vec<Market>> {
+        let (entities, block): (Vec<MarketEntity>, _) =
+            self.paginate("Markets", MARKETS_QUERY, "markets", json!({})).await?;
+        let lag = self.observe_lag(block).await;
+        let mut markets: Vec<Market> = entities.into_iter().map(MarketEntity::into_market).collect::<Result<_>>()?;
+        if let Some(lag) = lag.filter(|lag| lag.lagging) {
+            let issue = DataIssue::new(
+                DataIssueKind::StaleSource,
+                "subgraph",
+                format!(
+                    "subgraph at {} is {} blocks behind the chain head (indexed {}, head {})",
+                    self.host, lag.lag_blocks, lag.indexed_block, lag.chain_head
+                ),
+            );
+            for market in &mut markets {
+                market.data_issues.push(issue.clone());
+            }
+        }
+        Ok(markets)
+    }
+
+    /// An account without a position entity in `market` is `NotFound`
+    async fn get_user_position(&self, market: &Market, user: Address) -> Result<UserPosition> {
+        self.fetch_positions(market, &[user]).await?.remove(&user).ok_or_else(|| RiskEngineError::NotFound {
+            kind: "position",
+            id: format!("{} in {}", to_checksum_address(&user), market.name),
+        })
+    }
+
+    async fn get_positions(&self, market: &Market, users: &[Address]) -> Result<Vec<PositionResult>> {
+        let mut positions = self.fetch_positions(market, users).await?;
+        Ok(users
+            .iter()
+            .map(|&user| {
+                let position = positions.remove(&user).ok_or_else(|| RiskEngineError::NotFound {
+                    kind: "position",
+                    id: format!("{} in {}", to_checksum_address(&user), market.name),
+                });
+                (user, position)
+            })
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip(self, market), fields(market = %market.name, subgraph_host = %self.host), err(level = "debug"))]
+    async fn get_price_history(&self, market: &Market, asset: Address) -> Result<PriceHistory> {
+        let asset = std::iter::once(&market.base_asset)
+            .chain(market.collateral_assets.values())
+            .find(|a| a.address == asset)
+            .ok_or_else(|| RiskEngineError::NotFound {
+                kind: "asset",
+                id: format!("{} in {}", to_checksum_address(&asset), market.name),
+            })?;
+
+        let since = (Utc::now() - Duration::days(PRICE_HISTORY_DAYS)).timestamp();
+        let variables = json!({ "token": format!("{:?}", asset.address), "since": since.to_string() });
+        let mut data = self.query("DailyPrices", DAILY_PRICES_QUERY, &variables).await?;
+        let snapshots: Vec<SnapshotEntity> = serde_json::from_value(data["tokenDailySnapshots"].take())
+            .map_err(|e| RiskEngineError::serialization("subgraph DailyPrices response", e))?;
+        let points = snapshots
+            .into_iter()
+            .filter_map(|s| DateTime::from_timestamp(s.timestamp as i64, 0).map(|t| (t, s.price_usd)))
+            .collect();
+        daily_price_history(asset, points)
+    }
+
+    async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics> {
+        let mut data = self
+            .query("Reserves", RESERVES_QUERY, &json!({ "market": format!("{:?}", market.comet_address) }))
+            .await?;
+        let reserves: ReservesEntity = serde_json::from_value(data["market"].take())
+            .map_err(|e| RiskEngineError::serialization("subgraph Reserves response", e))?;
+        Ok(ProtocolMetrics {
+            tvl: market.total_supply * market.base_asset.price,
+            total_borrow: market.total_borrow * market.base_asset.price,
+            utilization_rate: market.utilization(),
+            suppliers_count: 0,
+            borrowers_count: 0,
+            reserves: reserves.reserves * market.base_asset.price,
+            rates: Some(market.rates()),
+        })
+    }
+}
+
+/// Provider reading each kind of data from RPC or the subgraph, as configured
+pub struct RoutedProvider {
+    rpc: SharedProvider,
+    subgraph: Arc<SubgraphProvider>,
+    markets: SourceKind,
+    positions: SourceKind,
+    price_history: SourceKind,
+    /// Tolerance for cross-checking market state, when enabled
+    cross_check: Option<f64>,
+}
+
+impl RoutedProvider {
+    pub fn new(rpc: SharedProvider, subgraph: Arc<SubgraphProvider>, settings: &SubgraphConfig) -> Self {
+        Self {
+            rpc,
+            subgraph,
+            markets: settings.markets,
+            positions: settings.positions,
+            price_history: settings.price_history,
+            cross_check: settings.cross_check.then_some(settings.cross_check_tolerance),
+        }
+    }
+
+    fn source(&self, kind: SourceKind) -> &dyn MarketDataProvider {
+        match kind {
+            SourceKind::Rpc => self.rpc.as_ref(),
+            SourceKind::Subgraph => self.subgraph.as_ref(),
+        }
+    }
+
+    /// Warn about every market where the other source disagrees with `markets`
+    async fn cross_check(&self, markets: &[Market]) {
+        let Some(tolerance) = self.cross_check else { return };
+        let other = match self.markets {
+            SourceKind::Rpc => SourceKind::Subgraph,
+            SourceKind::Subgraph => SourceKind::Rpc,
+        };
+        match self.source(other).get_markets().await {
+            Ok(others) => {
+                for discrepancy in market_discrepancies(markets, &others, tolerance) {
+                    warn!("Cross-check against {:?} data: {}", other, discrepancy);
+                }
+            }
+            Err(e) => warn!("Could not cross-check market state against {:?} data: {}", other, e),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for RoutedProvider {
+    async fn get_markets(&self) -> Result<Vec<Market>> {
+        let markets = self.source(self.markets).get_markets().await?;
+        self.cross_check(&markets).await;
+        Ok(markets)
+    }
+
+    async fn refresh_markets(&self) -> Result<Vec<Market>> {
+        let markets = self.source(self.markets).refresh_markets().await?;
+        self.cross_check(&markets).await;
+        Ok(markets)
+    }
+
+    async fn get_user_position(&self, market: &Market, user: Address) -> Result<UserPosition> {
+        self.source(self.positions).get_user_position(market, user).await
+    }
+
+    async fn get_positions(&self, market: &Market, users: &[Address]) -> Result<Vec<PositionResult>> {
+        self.source(self.positions).get_positions(market, users).await
+    }
+
+    async fn get_borrows(&self, market: &Market, users: &[Address]) -> Result<Vec<(Address, Result<f64>)>> {
+        self.source(self.positions).get_borrows(market, users).await
+    }
+
+    async fn get_price_history(&self, market: &Market, asset: Address) -> Result<PriceHistory> {
+        self.source(self.price_history).get_price_history(market, asset).await
+    }
+
+    async fn get_protocol_metrics(&self, market: &Market) -> Result<ProtocolMetrics> {
+        self.source(self.markets).get_protocol_metrics(market).await
+    }
+
+    // Account permissions and sequencer state only exist on chain
+    async fn get_manager_permissions(&self, market: &Market, account: Address, candidates: &[Address]) -> Result<ManagerPermissions> {
+        self.rpc.get_manager_permissions(market, account, candidates).await
+    }
+
+    async fn get_sequencer_status(&self) -> Result<Option<SequencerStatus>> {
+        self.rpc.get_sequencer_status().await
+    }
+
+    async fn get_gas_quote(&self, fee_percentile: f64) -> Result<Option<GasQuote>> {
+        self.rpc.get_gas_quote(fee_percentile).await
+    }
+
+    async fn get_market_configurations(&self, market: &Market) -> Result<Option<ConfigurationPair>> {
+        self.rpc.get_market_configurations(market).await
+    }
+
+    fn log_source(&self) -> Option<Arc<dyn LogSource>> {
+        self.rpc.log_source()
+    }
+
+    fn name_resolver(&self) -> Option<Arc<EnsResolver>> {
+        self.rpc.name_resolver()
+    }
+
+    fn rpc_budget(&self) -> Option<Arc<RpcBudget>> {
+        self.rpc.rpc_budget()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.source(self.markets).cache_stats()
+    }
+}
+
+/// Differences above `tolerance` (relative) between markets present in both `primary` and `secondary`
+pub fn market_discrepancies(primary: &[Market], secondary: &[Market], tolerance: f64) -> Vec<String> {
+    let mut discrepancies = Vec::new();
+    for market in primary {
+        let Some(other) = secondary.iter().find(|m| m.comet_address == market.comet_address) else {
+            continue;
+        };
+        // Utilization is derived from the totals, so it differs only where they do
+        let fields = [
+            ("total supply", market.total_supply, other.total_supply),
+            ("total borrow", market.total_borrow, other.total_borrow),
+        ];
+        for (field, ours, theirs) in fields {
+            let difference = (ours - theirs).abs() / ours.abs().max(theirs.abs()).max(f64::EPSILON);
+            if difference > tolerance {
+                discrepancies.push(format!(
+                    "{} {} is {} here but {} in the other source ({:.2}% apart)",
+                    market.name,
+                    field,
+                    ours,
+                    theirs,
+                    difference * 100.0
+                ));
+            }
+        }
+    }
+    discrepancies
+}
+
+/// Price changes and volatility of `asset` from daily prices, oldest first
+fn daily_price_history(asset: &Asset, points: Vec<(DateTime<Utc>, f64)>) -> Result<PriceHistory> {
+    PriceHistory::from_points(asset.address, asset.symbol.clone(), points).ok_or_else(|| RiskEngineError::Unavailable {
+        what: "price history",
+        reason: format!("the subgraph has fewer than two daily prices for {}", asset.symbol),
+    })
+}
+
+fn indexed_block(data: &Value) -> Option<u64> {
+    data["_meta"]["block"]["number"].as_u64()
+}
+
+/// Entity with an `id` usable as a pagination cursor
+trait Paged {
+    fn id(&self) -> &str;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenEntity {
+    address: String,
+    #[serde(default)]
+    symbol: String,
+    #[serde(default)]
+    decimals: u8,
+    #[serde(default, deserialize_with = "decimal")]
+    last_price_usd: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollateralEntity {
+    token: TokenEntity,
+    #[serde(deserialize_with = "decimal")]
+    borrow_collateral_factor: f64,
+    #[serde(deserialize_with = "decimal")]
+    liquidate_collateral_factor: f64,
+    #[serde(deserialize_with = "decimal")]
+    liquidation_factor: f64,
+    #[serde(deserialize_with = "big_int")]
+    supply_cap: U256,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MarketEntity {
+    id: String,
+    base_token: TokenEntity,
+    #[serde(deserialize_with = "decimal")]
+    total_base_supply: f64,
+    #[serde(deserialize_with = "decimal")]
+    total_base_borrow: f64,
+    #[serde(deserialize_with = "decimal")]
+    supply_apr: f64,
+    #[serde(deserialize_with = "decimal")]
+    borrow_apr: f64,
+    #[serde(default)]
+    collateral_tokens: Vec<CollateralEntity>,
+}
+
+impl Paged for MarketEntity {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl MarketEntity {
+    fn into_market(self) -> Result<Market> {
+        let base = self.base_token;
+        let base_asset = Asset {
+            address: parse_address(&base.address)?,
+            symbol: base.symbol.clone(),
+            decimals: base.decimals,
+            price: base.last_price_usd,
+            asset_type: AssetType::Base,
+            collateral_factor: 0.0,
+            liquidation_factor: 0.0,
+            liquidation_penalty: 0.0,
+            supply_cap: U256::zero(),
+            borrow_cap: U256::zero(),
+            provenance: Provenance::of_symbol(&base.symbol),
+            total_supplied: 0.0,
+            protocol_held: None,
+        };
+        let mut collateral_assets = HashMap::new();
+        for collateral in self.collateral_tokens {
+            let address = parse_address(&collateral.token.address)?;
+            collateral_assets.insert(
+                address,
+                Asset {
+                    address,
+                    provenance: Provenance::of_symbol(&collateral.token.symbol),
+                    symbol: collateral.token.symbol,
+                    decimals: collateral.token.decimals,
+                    price: collateral.token.last_price_usd,
+                    asset_type: AssetType::Collateral,
+                    collateral_factor: collateral.borrow_collateral_factor,
+                    liquidation_factor: collateral.liquidate_collateral_factor,
+                    liquidation_penalty: 1.0 - collateral.liquidation_factor,
+                    supply_cap: collateral.supply_cap,
+                    borrow_cap: U256::zero(),
+                    // Not in the subgraph's collateral entity
+                    total_supplied: 0.0,
+                    protocol_held: None,
+                },
+            );
+        }
+
+        let mut market = Market {
+            name: base.symbol,
+            comet_address: parse_address(&self.id)?,
+            base_asset,
+            collateral_assets,
+            total_supply: self.total_base_supply,
+            total_borrow: self.total_base_borrow,
+            utilization_rate: Market::utilization_of(self.total_base_supply, self.total_base_borrow),
+            supply_apr: self.supply_apr,
+            borrow_apr: self.borrow_apr,
+            // Not indexed by the subgraph
+            base_tracking_supply_speed: U256::zero(),
+            base_tracking_borrow_speed: U256::zero(),
+            base_min_interest_rate: U256::zero(),
+            base_max_interest_rate: U256::zero(),
+            base_borrow_min: 0.0,
+            store_front_price_factor: None,
+            supply_reward_apr: 0.0,
+            borrow_reward_apr: 0.0,
+            net_supply_apr: self.supply_apr,
+            net_borrow_apr: self.borrow_apr,
+            rewards: None,
+            // Pages of a query may come from different indexed blocks
+            block_number: None,
+            data_issues: Vec::new(),
+        };
+        market.annotate_data_quality();
+        Ok(market)
+    }
+}
+
+#[derive(Deserialize)]
+struct AccountEntity {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct BalanceToken {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct CollateralBalanceEntity {
+    token: BalanceToken,
+    #[serde(deserialize_with = "decimal")]
+    balance: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionEntity {
+    id: String,
+    account: AccountEntity,
+    #[serde(default, deserialize_with = "decimal")]
+    base_balance: f64,
+    #[serde(default)]
+    collateral_balances: Vec<CollateralBalanceEntity>,
+}
+
+impl Paged for PositionEntity {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl PositionEntity {
+    fn into_position(self, market: &Market) -> Result<UserPosition> {
+        let mut collateral_balances = HashMap::new();
+        let mut total_collateral_value = 0.0;
+        for entry in self.collateral_balances.into_iter().filter(|b| b.balance > 0.0) {
+            let address = parse_address(&entry.token.address)?;
+            if let Some(asset) = market.collateral_assets.get(&address) {
+                total_collateral_value += entry.balance * asset.price;
+            }
+            collateral_balances.insert(address, entry.balance);
+        }
+        let health_factor = market.health_factor(self.base_balance, &collateral_balances);
+
+        let mut position = UserPosition {
+            address: parse_address(&self.account.id)?,
+            base_balance: self.base_balance,
+            collateral_balances,
+            total_collateral_value,
+            total_borrow_value: (-self.base_balance).max(0.0) * market.base_asset.price,
+            health_factor,
+            block_number: None,
+            borrow_capacity_usd: 0.0,
+            remaining_borrow_headroom_pct: None,
+        };
+        position.set_borrow_capacity(market);
+        Ok(position)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotEntity {
+    #[serde(deserialize_with = "decimal")]
+    timestamp: f64,
+    #[serde(deserialize_with = "decimal")]
+    price_usd: f64,
+}
+
+#[derive(Deserialize)]
+struct ReservesEntity {
+    #[serde(deserialize_with = "decimal")]
+    reserves: f64,
+}
+
+/// GraphQL `BigDecimal`/`BigInt` (a string) or a plain number
+fn decimal<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(f64),
+        Text(String),
+    }
+    match Raw::deserialize(deserializer)? {
+        Raw::Number(n) => Ok(n),
+        Raw::Text(s) => s.parse().map_err(de::Error::custom),
+    }
+}
+
+/// GraphQL `BigInt` as a decimal string
+fn big_int<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<U256, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    U256::from_dec_str(&text).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::testing::FakeSubgraph;
+
+    const USDC: &str = "0xc3d688b66703497daa19211eedff47f25384cdc3";
+    const WETH_MARKET: &str = "0xa17581a9e3356d9a858b789d68b4d866e593ae94";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    struct Head(u64);
+
+    #[async_trait]
+    impl LogSource for Head {
+        async fn head_block(&self) -> Result<u64> {
+            Ok(self.0)
+        }
+
+        async fn borrowers_in_range(&self, _comet: Address, _from: u64, _to: u64) -> Result<Vec<Address>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn market_entity(id: &str, symbol: &str, supply: &str, borrow: &str) -> Value {
+        json!({
+            "id": id,
+            "baseToken": { "address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "symbol": symbol, "decimals": 6, "lastPriceUsd": "1.0" },
+            "totalBaseSupply": supply,
+            "totalBaseBorrow": borrow,
+            "utilization": "0.9",
+            "supplyApr": "0.045",
+            "borrowApr": "0.071",
+            "collateralTokens": [{
+                "token": { "address": WETH, "symbol": "WETH", "decimals": 18, "lastPriceUsd": "2000" },
+                "borrowCollateralFactor": "0.825",
+                "liquidateCollateralFactor": "0.91",
+                "liquidationFactor": "0.95",
+                "supplyCap": "10000000000000000000000"
+            }]
+        })
+    }
+
+    /// Entities with an id after the `after` cursor, by id, at most `first` of them
+    fn page(entities: &[Value], variables: &Value) -> Vec<Value> {
+        let after = variables["after"].as_str().unwrap_or_default();
+        let first = variables["first"].as_u64().unwrap_or(100) as usize;
+        let mut entities: Vec<Value> = entities.iter().filter(|e| e["id"].as_str().unwrap() > after).cloned().collect();
+        entities.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        entities.truncate(first);
+        entities
+    }
+
+    fn settings(page_size: usize) -> SubgraphConfig {
+        SubgraphConfig { page_size, max_lag_blocks: 10, ..SubgraphConfig::default() }
+    }
+
+    async fn provider(fake: &FakeSubgraph, page_size: usize) -> SubgraphProvider {
+        SubgraphProvider::new(fake.serve().await, &settings(page_size), std::time::Duration::from_secs(5)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_markets_are_paginated_and_mapped() {
+        let fake = FakeSubgraph::new();
+        let markets = vec![
+            market_entity(USDC, "USDC", "1000000000", "900000000"),
+            market_entity(WETH_MARKET, "WETH", "50000", "40000"),
+        ];
+        fake.on("Markets", move |vars| {
+            json!({ "data": { "_meta": { "block": { "number": 19_000_000 } }, "markets": page(&markets, vars) } })
+        });
+        let subgraph = provider(&fake, 1).await.with_chain_head(Some(Arc::new(Head(19_000_005))));
+
+        let markets = subgraph.get_markets().await.unwrap();
+        // Two full pages and an empty one
+        assert_eq!(fake.requests().len(), 3);
+        assert_eq!(markets.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), ["WETH", "USDC"]);
+
+        let usdc = markets.iter().find(|m| m.name == "USDC").unwrap();
+        assert_eq!(format!("{:?}", usdc.comet_address), USDC);
+        assert_eq!((usdc.total_supply, usdc.total_borrow, usdc.utilization_rate), (1e9, 9e8, 0.9));
+        let weth = &usdc.collateral_assets[&parse_address(WETH).unwrap()];
+        assert_eq!((weth.price, weth.collateral_factor), (2000.0, 0.825));
+        assert!((weth.liquidation_penalty - 0.05).abs() < 1e-12);
+        assert_eq!(weth.supply_cap, U256::exp10(22));
+
+        let lag = subgraph.last_lag().unwrap();
+        assert_eq!((lag.lag_blocks, lag.lagging), (5, false));
+    }
+
+    #[tokio::test]
+    async fn test_positions_borrowers_and_price_history() {
+        let fake = FakeSubgraph::new();
+        let positions = vec![json!({
+            "id": "0x01",
+            "account": { "id": "0x2222222222222222222222222222222222222222" },
+            "baseBalance": "-1000",
+            "collateralBalances": [{ "token": { "address": WETH }, "balance": "0.625" }]
+        })];
+        let borrowers = positions.clone();
+        fake.on("Positions", move |vars| json!({ "data": { "positions": page(&positions, vars) } }))
+            .on("Borrowers", move |vars| {
+                json!({ "data": { "_meta": { "block": { "number": 42 } }, "positions": page(&borrowers, vars) } })
+            })
+            .on("DailyPrices", |_| {
+                let snapshots: Vec<Value> = [100.0, 110.0, 99.0]
+                    .iter()
+                    .enumerate()
+                    .map(|(day, price)| json!({ "timestamp": (1_700_000_000 + day * 86_400).to_string(), "priceUsd": price.to_string() }))
+                    .collect();
+                json!({ "data": { "tokenDailySnapshots": snapshots } })
+            });
+        let subgraph = provider(&fake, 100).await;
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+
+        let risky = parse_address("0x2222222222222222222222222222222222222222").unwrap();
+        let results = subgraph.get_positions(&market, &[risky, Address::repeat_byte(0x33)]).await.unwrap();
+        let position = results[0].1.as_ref().unwrap();
+        assert_eq!(position.total_borrow_value, 1000.0);
+        assert!((position.health_factor - 1.1375).abs() < 1e-9);
+        assert!(matches!(results[1].1, Err(RiskEngineError::NotFound { kind: "position", .. })));
+
+        let (accounts, block) = subgraph.borrowers(market.comet_address).await.unwrap();
+        assert_eq!((accounts, block), (vec![risky], Some(42)));
+
+        let history = subgraph.get_price_history(&market, parse_address(WETH).unwrap()).await.unwrap();
+        assert_eq!(history.price_points.len(), 3);
+        assert!((history.price_change_24h - (99.0 / 110.0 - 1.0)).abs() < 1e-12);
+        assert!((history.price_change_7d + 0.01).abs() < 1e-12);
+        assert!(history.volatility_30d > 0.09);
+    }
+
+    #[tokio::test]
+    async fn test_lag_and_graphql_errors_are_surfaced() {
+        let fake = FakeSubgraph::new();
+        fake.on("Meta", |_| json!({ "data": { "_meta": { "block": { "number": 900 } } } }));
+        let subgraph = provider(&fake, 100).await.with_chain_head(Some(Arc::new(Head(1_000))));
+
+        let lag = subgraph.lag().await.unwrap();
+        assert_eq!(lag, SubgraphLag { indexed_block: 900, chain_head: 1_000, lag_blocks: 100, lagging: true });
+
+        // No answer registered for `Markets`
+        let err = subgraph.get_markets().await.unwrap_err();
+        assert!(matches!(err, RiskEngineError::Unavailable { what: "subgraph data", .. }), "{}", err);
+        assert!(err.to_string().contains("unknown operation"));
+
+        // Markets read while lagging carry a stale-source issue
+        fake.on("Markets", |vars| {
+            let markets = [market_entity(USDC, "USDC", "1000000000", "800000000")];
+            json!({ "data": { "_meta": { "block": { "number": 900 } }, "markets": page(&markets, vars) } })
+        });
+        let issues = subgraph.get_markets().await.unwrap().remove(0).data_issues;
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].kind, issues[0].check.as_str()), (DataIssueKind::StaleSource, "subgraph"));
+        assert!(issues[0].message.contains("100 blocks behind"), "{}", issues[0].message);
+    }
+
+    #[tokio::test]
+    async fn test_routed_provider_picks_sources_and_cross_checks() {
+        let fake = FakeSubgraph::new();
+        fake.on("Markets", |vars| {
+            let markets = [market_entity(USDC, "USDC", "1000000000", "800000000")];
+            json!({ "data": { "_meta": { "block": { "number": 1 } }, "markets": page(&markets, vars) } })
+        });
+        let fixture = Arc::new(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap());
+        let subgraph = Arc::new(provider(&fake, 100).await);
+        let config = SubgraphConfig { markets: SourceKind::Subgraph, cross_check: true, ..SubgraphConfig::default() };
+        let routed = RoutedProvider::new(fixture.clone(), subgraph, &config);
+
+        let markets = routed.get_markets().await.unwrap();
+        assert_eq!(markets[0].total_borrow, 8e8);
+        // Positions still come from the "RPC" side
+        let position = routed.get_user_position(&markets[0], parse_address("0x2222222222222222222222222222222222222222").unwrap());
+        assert!((position.await.unwrap().health_factor - 1.04).abs() < 1e-9);
+
+        let discrepancies = market_discrepancies(&markets, &fixture.get_markets().await.unwrap(), 0.01);
+        assert_eq!(discrepancies.len(), 1, "{:?}", discrepancies);
+        assert!(discrepancies[0].contains("total borrow"));
+        assert!(market_discrepancies(&markets, &markets, 0.0).is_empty());
+    }
+}
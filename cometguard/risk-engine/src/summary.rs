@@ -0,0 +1,281 @@
+//! One figure and one page for every assessed market together
+//!
+//! Each assessment carries a `MarketExposure`: the market's TVL and borrow, plus what its
+//! tracked borrowers (borrower index and watchlist) owe beyond their collateral and how much
+//! they borrow close to liquidation. `ProtocolRiskSummary` adds those up across markets, and
+//! per chain, next to a weighted risk score and the findings by severity. Position figures only
+//! cover tracked borrowers, so they are lower bounds; in markets whose positions were sampled
+//! they are estimates, with their margins recorded under `sampling`.
+
+use crate::health_factors::HealthFactorDistribution;
+use crate::models::{Market, UserPosition};
+use crate::risk::{RiskAssessment, RiskSeverity};
+use crate::sampling::{self, PositionSample, SampleReport};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How market scores are weighted in the aggregate score
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreWeighting {
+    /// By total base supplied, in USD
+    #[default]
+    Tvl,
+    /// Every market counts the same
+    Equal,
+    /// By total base borrowed, in USD
+    Borrow,
+}
+
+impl ScoreWeighting {
+    /// Name in report headers, e.g. "TVL" in "TVL-weighted"
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Tvl => "TVL",
+            Self::Equal => "equal",
+            Self::Borrow => "borrow",
+        }
+    }
+}
+
+/// Size of a market and of its tracked borrowers' shortfalls
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketExposure {
+    pub chain_id: u64,
+    /// Total base supplied, in USD
+    pub tvl_usd: f64,
+    /// Total base borrowed, in USD
+    pub borrow_usd: f64,
+    /// Borrowing positions read from the borrower index and watchlist
+    pub positions: usize,
+    /// Borrow in excess of collateral value, summed over those positions
+    pub bad_debt_usd: f64,
+    /// Borrow of those positions with a health factor below 1 plus the margin, liquidatable
+    /// ones included
+    pub near_liquidation_usd: f64,
+    /// How the position figures were extrapolated, if the positions were sampled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SampledExposure>,
+}
+
+/// Sample behind a `MarketExposure` and the 95% margins of its figures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampledExposure {
+    #[serde(flatten)]
+    pub sample: SampleReport,
+    pub bad_debt_margin_usd: f64,
+    pub near_liquidation_margin_usd: f64,
+}
+
+impl MarketExposure {
+    /// Exposure of `market` on `chain_id` from the tracked `positions`, counting those with a
+    /// health factor below `1 + near_liquidation_margin` as near liquidation
+    pub fn new(chain_id: u64, market: &Market, positions: &[UserPosition], near_liquidation_margin: f64) -> Self {
+        let distribution = HealthFactorDistribution::new(positions, &[1.0 + near_liquidation_margin]);
+        Self::estimated(chain_id, market, positions, &distribution, near_liquidation_margin, None)
+    }
+
+    /// Exposure as in `new`, with the positions by health factor taken from `distribution`,
+    /// which must have an edge at `1 + near_liquidation_margin`, and extrapolated to all
+    /// borrowers when `positions` were read from `sample`
+    pub fn estimated(
+        chain_id: u64,
+        market: &Market,
+        positions: &[UserPosition],
+        distribution: &HealthFactorDistribution,
+        near_liquidation_margin: f64,
+        sample: Option<&PositionSample>,
+    ) -> Self {
+        let borrowing: Vec<UserPosition> = positions.iter().filter(|p| p.total_borrow_value > 0.0).cloned().collect();
+        let bad_debt = sampling::total(sample, &borrowing, |p| (p.total_borrow_value - p.total_collateral_value).max(0.0));
+        Self {
+            chain_id,
+            tvl_usd: market.total_supply * market.base_asset.price,
+            borrow_usd: market.total_borrow * market.base_asset.price,
+            positions: distribution.borrowing_positions(),
+            bad_debt_usd: bad_debt.value,
+            near_liquidation_usd: distribution.borrow_below(1.0 + near_liquidation_margin),
+            sampling: sample.map(|sample| SampledExposure {
+                sample: sample.report(),
+                bad_debt_margin_usd: bad_debt.margin,
+                // The distribution has the estimate but not its margin
+                near_liquidation_margin_usd: sampling::total(Some(sample), &borrowing, |p| {
+                    if p.health_factor < 1.0 + near_liquidation_margin { p.total_borrow_value } else { 0.0 }
+                })
+                .margin,
+            }),
+        }
+    }
+}
+
+/// Market with the highest risk score
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorstMarket {
+    pub market_name: String,
+    #[serde(serialize_with = "crate::utils::checksummed")]
+    pub market_address: Address,
+    pub risk_score: u8,
+}
+
+/// Totals over a set of markets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskTotals {
+    pub markets: usize,
+    /// Mean market score under the summary's weighting; equal weights when every market
+    /// weighs 0
+    pub risk_score: f64,
+    pub worst_market: Option<WorstMarket>,
+    pub tvl_usd: f64,
+    pub borrow_usd: f64,
+    pub bad_debt_usd: f64,
+    pub near_liquidation_usd: f64,
+    /// Every severity, with 0 for those without findings
+    pub findings: BTreeMap<RiskSeverity, usize>,
+}
+
+impl RiskTotals {
+    fn new(assessments: &[&RiskAssessment], weighting: ScoreWeighting) -> Self {
+        let exposure = |get: fn(&MarketExposure) -> f64| -> f64 {
+            total(assessments.iter().filter_map(|a| a.exposure.as_ref()).map(get))
+        };
+        let weight = |assessment: &RiskAssessment| match (weighting, &assessment.exposure) {
+            (ScoreWeighting::Equal, _) => 1.0,
+            (ScoreWeighting::Tvl, Some(exposure)) => exposure.tvl_usd,
+            (ScoreWeighting::Borrow, Some(exposure)) => exposure.borrow_usd,
+            (_, None) => 0.0,
+        };
+        let mut weights: Vec<f64> = assessments.iter().map(|a| weight(a)).collect();
+        if weights.iter().sum::<f64>() <= 0.0 {
+            weights.iter_mut().for_each(|w| *w = 1.0);
+        }
+        let weighted: f64 = assessments.iter().zip(&weights).map(|(a, w)| f64::from(a.risk_score) * w).sum();
+        let risk_score = if assessments.is_empty() { 0.0 } else { weighted / weights.iter().sum::<f64>() };
+
+        let mut findings: BTreeMap<RiskSeverity, usize> = RiskSeverity::ALL.into_iter().map(|s| (s, 0)).collect();
+        for finding in assessments.iter().flat_map(|a| &a.findings) {
+            *findings.entry(finding.severity).or_default() += 1;
+        }
+        // The first market wins a tie, so the order of the assessments decides
+        let worst_market = assessments
+            .iter()
+            .rev()
+            .max_by_key(|a| a.risk_score)
+            .map(|a| WorstMarket { market_name: a.market_name.clone(), market_address: a.market_address, risk_score: a.risk_score });
+
+        Self {
+            markets: assessments.len(),
+            risk_score,
+            worst_market,
+            tvl_usd: exposure(|e| e.tvl_usd),
+            borrow_usd: exposure(|e| e.borrow_usd),
+            bad_debt_usd: exposure(|e| e.bad_debt_usd),
+            near_liquidation_usd: exposure(|e| e.near_liquidation_usd),
+            findings,
+        }
+    }
+}
+
+/// Totals of the markets of one chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainSubtotal {
+    /// `None` for assessments without exposure, such as ones saved by older versions
+    pub chain_id: Option<u64>,
+    #[serde(flatten)]
+    pub totals: RiskTotals,
+}
+
+/// Rollup of a set of assessments, across markets and per chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolRiskSummary {
+    pub weighting: ScoreWeighting,
+    #[serde(flatten)]
+    pub totals: RiskTotals,
+    /// By chain id, markets without exposure last
+    pub chains: Vec<ChainSubtotal>,
+}
+
+impl ProtocolRiskSummary {
+    /// Rollup of `assessments` with market scores weighted by `weighting`
+    pub fn new(assessments: &[RiskAssessment], weighting: ScoreWeighting) -> Self {
+        let mut by_chain: BTreeMap<Option<u64>, Vec<&RiskAssessment>> = BTreeMap::new();
+        for assessment in assessments {
+            by_chain.entry(assessment.exposure.as_ref().map(|e| e.chain_id)).or_default().push(assessment);
+        }
+        let mut chains: Vec<ChainSubtotal> = by_chain
+            .into_iter()
+            .map(|(chain_id, assessments)| ChainSubtotal { chain_id, totals: RiskTotals::new(&assessments, weighting) })
+            .collect();
+        // `None` sorts first in the map
+        let unknown = chains.iter().take_while(|c| c.chain_id.is_none()).count();
+        chains.rotate_left(unknown);
+        Self {
+            weighting,
+            totals: RiskTotals::new(&assessments.iter().collect::<Vec<_>>(), weighting),
+            chains,
+        }
+    }
+}
+
+/// Sum of `values`, 0 when there are none; `Iterator::sum` starts from -0, which exporters
+/// and JSON print as "-0"
+fn total(values: impl Iterator<Item = f64>) -> f64 {
+    values.fold(0.0, |sum, value| sum + value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// `testing::assessment` of market `name` with a finding of each of `severities`
+    fn assessment(name: &str, score: u8, exposure: Option<MarketExposure>, severities: &[RiskSeverity]) -> RiskAssessment {
+        let findings = severities.iter().enumerate().map(|(i, &severity)| testing::finding(&i.to_string(), severity)).collect();
+        RiskAssessment {
+            market_name: name.to_string(),
+            market_address: Address::repeat_byte(score),
+            exposure,
+            ..testing::assessment(score, findings)
+        }
+    }
+
+    fn exposure(chain_id: u64, tvl_usd: f64, borrow_usd: f64, bad_debt_usd: f64) -> Option<MarketExposure> {
+        Some(MarketExposure {
+            chain_id,
+            tvl_usd,
+            borrow_usd,
+            positions: 1,
+            bad_debt_usd,
+            near_liquidation_usd: bad_debt_usd * 2.0,
+            sampling: None,
+        })
+    }
+
+    #[test]
+    fn test_summary_weights_scores_and_splits_chains() {
+        let assessments = [
+            assessment("USDC", 20, exposure(1, 300.0, 100.0, 5.0), &[RiskSeverity::Medium]),
+            assessment("WETH", 60, exposure(1, 100.0, 300.0, 0.0), &[RiskSeverity::High, RiskSeverity::Low]),
+            assessment("USDC.e", 60, exposure(42161, 0.0, 0.0, 1.0), &[]),
+            assessment("Saved", 90, None, &[RiskSeverity::Critical]),
+        ];
+
+        let summary = ProtocolRiskSummary::new(&assessments, ScoreWeighting::Tvl);
+        assert_eq!(summary.totals.risk_score, (20.0 * 300.0 + 60.0 * 100.0) / 400.0);
+        assert_eq!(summary.totals.worst_market.as_ref().map(|w| w.market_name.as_str()), Some("Saved"));
+        assert_eq!((summary.totals.bad_debt_usd, summary.totals.near_liquidation_usd), (6.0, 12.0));
+        let counts: Vec<usize> = summary.totals.findings.values().copied().collect();
+        assert_eq!(counts, [1, 1, 1, 1]);
+
+        let chains: Vec<(Option<u64>, usize, f64)> = summary.chains.iter().map(|c| (c.chain_id, c.totals.markets, c.totals.risk_score)).collect();
+        // A chain whose markets weigh nothing falls back to equal weights
+        assert_eq!(chains, [(Some(1), 2, 30.0), (Some(42161), 1, 60.0), (None, 1, 90.0)]);
+
+        assert_eq!(ProtocolRiskSummary::new(&assessments, ScoreWeighting::Borrow).totals.risk_score, 50.0);
+        assert_eq!(ProtocolRiskSummary::new(&assessments, ScoreWeighting::Equal).totals.risk_score, 57.5);
+        // Ties go to the first market
+        let tied = ProtocolRiskSummary::new(&assessments[1..3], ScoreWeighting::Equal);
+        assert_eq!(tied.totals.worst_market.map(|w| w.market_name), Some("WETH".to_string()));
+        assert_eq!(ProtocolRiskSummary::new(&[], ScoreWeighting::Tvl).totals.risk_score, 0.0);
+    }
+}
@@ -0,0 +1,378 @@
+//! Log output and trace export
+//!
+//! Assessments, risk checks, Compound reads and RPC requests run in `tracing` spans
+//! tagged with the market, its address, the check name, block range and RPC host. Log lines
+//! are filtered by `log_level` directives and written as `log_format` says; as JSON each
+//! line carries the fields of its innermost span, so pipelines such as Loki can query by
+//! market or check. With `telemetry.otlp_endpoint` set, those spans are also exported to an
+//! OpenTelemetry collector (Jaeger, Tempo...) alongside the usual log lines.
+//!
+//! With `logging.directory` set, the same lines are also written to rotating files there
+//! through a background writer; the `TelemetryGuard` flushes it, so the CLI holds the guard
+//! until it exits.
+
+use crate::config::{Config, LogFormat, LogRotation, LoggingConfig};
+use crate::error::{Result, RiskEngineError};
+use std::path::{Path, PathBuf};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Variable whose filter directives take precedence over `log_level`
+pub const LOG_ENV: &str = "RUST_LOG";
+
+/// Levels a bare directive may name
+const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Flushes exported spans and queued log file lines when dropped; keep it alive until the
+/// program exits
+#[must_use = "spans and log lines still buffered are lost when the guard is dropped"]
+pub struct TelemetryGuard {
+    #[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+    exporting: bool,
+    /// Stops the log file writer once it has written what is queued
+    _log_file: Option<WorkerGuard>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if self.exporting {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Filter of `directives`: a level, or comma-separated `target=level` directives with an
+/// optional default level, e.g. `risk_engine::compound=debug,info`
+///
+/// A bare word must be a level, so that a misspelled level fails rather than passing as a
+/// target; targets with a path (`risk_engine::scanner`) or span filters are accepted alone.
+pub fn parse_filter(directives: &str) -> Result<EnvFilter> {
+    let invalid = |message: String| RiskEngineError::Parse { what: "log filter", input: directives.to_string(), message };
+    for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let bare = directive.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if bare && !LEVELS.contains(&directive.to_ascii_lowercase().as_str()) {
+            return Err(invalid(format!(
+                "`{}` is neither a level ({}) nor a `target=level` directive",
+                directive,
+                LEVELS.join(", ")
+            )));
+        }
+    }
+    EnvFilter::builder().parse(directives).map_err(|e| invalid(e.to_string()))
+}
+
+/// Log line layer writing to `writer` in `format`, filtered by `directives`, and the most
+/// verbose level it lets through; `ansi` colors the text formats
+pub fn log_layer<S>(
+    directives: &str,
+    format: LogFormat,
+    writer: BoxMakeWriter,
+    ansi: bool,
+) -> Result<(Box<dyn Layer<S> + Send + Sync>, LevelFilter)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let filter = parse_filter(directives)?;
+    let level = filter.max_level_hint().unwrap_or(LevelFilter::TRACE);
+    let fmt = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    let layer = match format {
+        LogFormat::Pretty => fmt.pretty().with_target(false).with_filter(filter).boxed(),
+        LogFormat::Compact => fmt.compact().with_target(false).with_filter(filter).boxed(),
+        LogFormat::Json => fmt
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_filter(filter)
+            .boxed(),
+    };
+    Ok((layer, level))
+}
+
+/// Install the global subscriber: log lines filtered by `directives` on stderr and in the
+/// `logging` files, in `log_format`, plus OTLP export if configured
+///
+/// Must be called from within a Tokio runtime when export is enabled.
+pub fn init_telemetry(directives: &str, config: &Config) -> Result<TelemetryGuard> {
+    init_telemetry_to(directives, config, BoxMakeWriter::new(std::io::stderr))
+}
+
+/// Like `init_telemetry`, but writes log lines to `writer` (e.g. a file while a TUI owns the terminal)
+pub fn init_telemetry_to(directives: &str, config: &Config, writer: BoxMakeWriter) -> Result<TelemetryGuard> {
+    let (layer, level) = log_layer(directives, config.log_format, writer, true)?;
+    let (file, log_file) = match log_file(&config.logging)? {
+        Some((file, guard)) => {
+            let (layer, _) = log_layer(directives, config.log_format, BoxMakeWriter::new(file), false)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let registry = tracing_subscriber::registry().with(layer).with(file);
+
+    let telemetry = &config.telemetry;
+    let Some(endpoint) = &telemetry.otlp_endpoint else {
+        registry.init();
+        return Ok(TelemetryGuard { exporting: false, _log_file: log_file });
+    };
+
+    #[cfg(feature = "otlp")]
+    {
+        registry.with(otlp::layer(otlp::tracer(endpoint, telemetry)?, level)).init();
+        tracing::info!("Exporting traces to {}", crate::error::rpc_host(endpoint));
+        Ok(TelemetryGuard { exporting: true, _log_file: log_file })
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = (endpoint, level, log_file);
+        Err(RiskEngineError::config(
+            "telemetry.otlp_endpoint",
+            "this build does not include the `otlp` feature",
+        ))
+    }
+}
+
+/// Background writer to the rolling files of `settings`, after deleting files beyond
+/// `max_files`; `None` without a directory
+fn log_file(settings: &LoggingConfig) -> Result<Option<(NonBlocking, WorkerGuard)>> {
+    let Some(directory) = &settings.directory else {
+        return Ok(None);
+    };
+    let rotation = match settings.rotation {
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Hourly => Rotation::HOURLY,
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(&settings.file_prefix)
+        .filename_suffix("log")
+        .max_log_files(settings.max_files)
+        .build(directory)
+        .map_err(|e| RiskEngineError::config("logging.directory", format!("cannot write log files to {}: {}", directory.display(), e)))?;
+    prune_log_files(directory, &settings.file_prefix, settings.max_files)?;
+    Ok(Some(tracing_appender::non_blocking(appender)))
+}
+
+/// Delete the log files of `prefix` in `directory` beyond the newest `keep`, returning them
+///
+/// The appender only deletes files when it rotates, so this also runs at startup: a daemon
+/// restarted more often than it rotates, or with a lower `max_files`, would leave them behind.
+/// Files are ordered by name, whose date and hour sort chronologically.
+pub fn prune_log_files(directory: &Path, prefix: &str, keep: usize) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(directory).map_err(|e| RiskEngineError::io(directory, e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            path.is_file() && name.starts_with(&format!("{}.", prefix)) && name.ends_with(".log")
+        })
+        .collect();
+    files.sort();
+    let excess = files.len().saturating_sub(keep);
+    let pruned: Vec<PathBuf> = files.into_iter().take(excess).collect();
+    for path in &pruned {
+        std::fs::remove_file(path).map_err(|e| RiskEngineError::io(path, e))?;
+    }
+    Ok(pruned)
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use super::*;
+    use crate::config::TelemetryConfig;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{self as sdktrace, Sampler, Tracer};
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::filter::Targets;
+
+    /// Tracer batching spans to the collector at `endpoint`
+    pub(super) fn tracer(endpoint: &str, config: &TelemetryConfig) -> Result<Tracer> {
+        if !(0.0..=1.0).contains(&config.sample_ratio) {
+            return Err(RiskEngineError::config("telemetry.sample_ratio", "must be between 0.0 and 1.0"));
+        }
+        let trace_config = sdktrace::config()
+            .with_resource(Resource::new(vec![KeyValue::new("service.name", config.service_name.clone())]))
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))));
+        opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().http().with_endpoint(endpoint))
+            .with_trace_config(trace_config)
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+            .map_err(|e| RiskEngineError::config("telemetry.otlp_endpoint", e.to_string()))
+    }
+
+    /// Span export layer; the engine's own debug spans and events (RPC requests, retries,
+    /// errors) are exported even when the log level hides them
+    pub(super) fn layer<S>(tracer: Tracer, level: LevelFilter) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let filter = Targets::new()
+            .with_target("risk_engine", level.max(LevelFilter::DEBUG))
+            .with_default(level);
+        tracing_opentelemetry::layer().with_tracer(tracer).with_filter(filter)
+    }
+}
+
+#[cfg(all(test, feature = "otlp"))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::RiskEngine;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::Value;
+    use opentelemetry_sdk::export::trace::SpanData;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use std::sync::{Arc, Mutex};
+
+    fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+        span.attributes.iter().find(|kv| kv.key.as_str() == key).map(|kv| &kv.value)
+    }
+
+    #[tokio::test]
+    async fn test_assessment_spans_are_exported() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(otlp::layer(provider.tracer("test"), LevelFilter::INFO));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let engine = RiskEngine::with_provider(Config::default(), Arc::new(fixture));
+        engine.assess_risks().await.unwrap();
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let find = |name: &'static str| spans.iter().filter(move |s| s.name == name);
+        let run = find("assess_risks").next().expect("assess_risks span");
+        let market = find("assess_market").next().expect("assess_market span");
+        assert_eq!(market.parent_span_id, run.span_context.span_id());
+        assert_eq!(attribute(market, "market").map(Value::as_str).as_deref(), Some("USDC"));
+
+        let checks: Vec<String> = find("risk_check")
+            .filter_map(|s| attribute(s, "check").map(|v| v.as_str().into_owned()))
+            .collect();
+        assert_eq!(checks, ["sequencer", "utilization", "price_volatility", "watchlist", "asset_settings", "rewards_runway", "absorption", "liquidator_margin", "protocol_collateral", "collateral_provenance", "configuration_drift", "manager_permissions", "position_changes"]);
+        assert!(find("risk_check").all(|s| s.parent_span_id == market.span_context.span_id()));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_retries_and_errors_are_recorded() {
+        use crate::rpc::RecordingClient;
+        use ethers::providers::{Middleware, Provider};
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(otlp::layer(provider.tracer("test"), LevelFilter::INFO));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        // Bind and drop a listener to get a local port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = RecordingClient::live(&format!("http://127.0.0.1:{}", port)).unwrap().with_max_retries(2);
+        assert!(Provider::new(client).get_block_number().await.is_err());
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = spans.iter().find(|s| s.name == "rpc_request").expect("rpc_request span");
+        assert_eq!(attribute(request, "method").map(Value::as_str).as_deref(), Some("eth_blockNumber"));
+        assert_eq!(attribute(request, "rpc_host").map(Value::as_str).as_deref(), Some("127.0.0.1"));
+        assert_eq!(attribute(request, "retries").map(Value::as_str).as_deref(), Some("2"));
+        // Two retry warnings and the final failure
+        assert_eq!(request.events.len(), 3);
+    }
+
+    /// Log output kept in memory
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_configured_filter_and_format_are_applied() {
+        let config = Config {
+            log_level: "risk_engine::compound=debug,info".to_string(),
+            log_format: LogFormat::Json,
+            ..Config::default()
+        };
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let (layer, level) =
+            log_layer(&config.log_level, config.log_format, BoxMakeWriter::new(move || writer.clone()), false).unwrap();
+        assert_eq!(level, LevelFilter::DEBUG);
+        let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::debug!(target: "risk_engine::compound", block = 19_000_000u64, "read Comet");
+        tracing::debug!(target: "risk_engine::risk", "filtered out");
+        let span = tracing::info_span!(target: "risk_engine::risk", "risk_check", check = "utilization", market = "USDC");
+        span.in_scope(|| tracing::info!(target: "risk_engine::risk", "utilization checked"));
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+        assert_eq!((lines[0]["level"].as_str(), lines[0]["target"].as_str()), (Some("DEBUG"), Some("risk_engine::compound")));
+        assert_eq!((lines[0]["message"].as_str(), lines[0]["block"].as_u64()), (Some("read Comet"), Some(19_000_000)));
+        assert_eq!(lines[1]["span"]["check"].as_str(), Some("utilization"));
+        assert_eq!(lines[1]["span"]["market"].as_str(), Some("USDC"));
+    }
+
+    #[test]
+    fn test_log_files_beyond_the_retention_count_are_deleted() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        for day in 10..=13 {
+            std::fs::write(dir.path().join(format!("cometguard.2024-05-{}.log", day)), "old\n").unwrap();
+        }
+        std::fs::write(dir.path().join("other.2024-05-01.log"), "").unwrap();
+        std::fs::write(dir.path().join("cometguard.json"), "").unwrap();
+
+        let pruned = prune_log_files(dir.path(), "cometguard", 3).unwrap();
+        let names: Vec<&str> = pruned.iter().filter_map(|path| path.file_name()?.to_str()).collect();
+        assert_eq!(names, ["cometguard.2024-05-10.log"]);
+
+        // Opening the appender adds the current file and prunes down to max_files
+        let settings = LoggingConfig { directory: Some(dir.path().to_path_buf()), max_files: 2, ..LoggingConfig::default() };
+        let (mut writer, guard) = log_file(&settings).unwrap().unwrap();
+        writer.write_all(b"started\n").unwrap();
+        drop(guard);
+        let mut files: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("cometguard."))
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert_eq!(files[0], "cometguard.2024-05-13.log");
+        let current = dir.path().join(&files[1]);
+        assert_eq!(std::fs::read_to_string(current).unwrap(), "started\n");
+        assert_eq!(files[2], "cometguard.json");
+    }
+
+    #[test]
+    fn test_filters_name_levels_or_targets() {
+        assert_eq!(parse_filter("DEBUG").unwrap().max_level_hint(), Some(LevelFilter::DEBUG));
+        assert_eq!(parse_filter("warn,risk_engine::scanner=trace").unwrap().max_level_hint(), Some(LevelFilter::TRACE));
+        assert!(parse_filter("risk_engine::scanner").is_ok());
+        let error = parse_filter("info,verbose").unwrap_err().to_string();
+        assert!(error.contains("`verbose` is neither a level"), "{}", error);
+        assert!(parse_filter("risk_engine=loud").is_err());
+    }
+}
@@ -0,0 +1,391 @@
+//! Helpers shared by unit tests across modules
+
+use crate::compound::multicall_3::{Aggregate3Call, Aggregate3Return};
+use crate::compound::MULTICALL3_ADDRESS;
+use crate::error::Result;
+use crate::leader::LeaseStore;
+use crate::risk::{RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+use async_trait::async_trait;
+use chrono::Utc;
+use ethers::abi::{AbiDecode, AbiEncode};
+use ethers::types::{Address, Bytes, Filter, Log, ValueOrArray, H256};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// In-process JSON-RPC endpoint answering `eth_call`s from a lookup table
+///
+/// Calls that were not registered revert, like a call to a contract without that function.
+/// Multicall3 `aggregate3` at `MULTICALL3_ADDRESS` answers each packed call the same way,
+/// unless `without_multicall` leaves the chain without it.
+/// `eth_getLogs` is unsupported until logs are registered with `on_log`, and so are
+/// `eth_feeHistory` and `eth_gasPrice` until fees are set with `on_fees`. The head is block
+/// 100 unless `advancing_blocks` makes every request mine one.
+#[derive(Clone, Default)]
+pub struct FakeChain {
+    calls: Arc<Mutex<HashMap<(Address, Bytes), Bytes>>>,
+    logs: Arc<Mutex<Option<Vec<Log>>>>,
+    mined: Arc<AtomicU64>,
+    advancing: bool,
+    call_blocks: Arc<Mutex<Vec<Value>>>,
+    fees: Arc<Mutex<Option<(u64, u64)>>>,
+    no_multicall: bool,
+}
+
+impl FakeChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mine a block after every request, as a busy chain would between reads
+    pub fn advancing_blocks(mut self) -> Self {
+        self.advancing = true;
+        self
+    }
+
+    /// Revert calls to Multicall3, as on a chain it is not deployed on
+    pub fn without_multicall(mut self) -> Self {
+        self.no_multicall = true;
+        self
+    }
+
+    /// Block tags of the `eth_call`s answered since the last take, in order
+    pub fn take_call_blocks(&self) -> Vec<Value> {
+        std::mem::take(&mut *self.call_blocks.lock().unwrap())
+    }
+
+    /// Answer `call` sent to `to` with the ABI encoding of `ret`
+    pub fn on_call(&self, to: Address, call: impl AbiEncode, ret: impl AbiEncode) -> &Self {
+        self.on_raw_call(to, call.encode().into(), ret.encode().into())
+    }
+
+    /// Answer raw call data sent to `to` with raw return data
+    pub fn on_raw_call(&self, to: Address, data: Bytes, ret: Bytes) -> &Self {
+        self.calls.lock().unwrap().insert((to, data), ret);
+        self
+    }
+
+    /// Serve `log` to `eth_getLogs` filters whose address and topics it matches
+    pub fn on_log(&self, address: Address, topics: Vec<H256>, data: Bytes) -> &Self {
+        let log = Log { address, topics, data, ..Log::default() };
+        self.logs.lock().unwrap().get_or_insert_with(Vec::new).push(log);
+        self
+    }
+
+    /// Serve a base fee and a priority fee, in wei, in every block of `eth_feeHistory`, and
+    /// their sum as `eth_gasPrice`
+    pub fn on_fees(&self, base_fee: u64, priority_fee: u64) -> &Self {
+        *self.fees.lock().unwrap() = Some((base_fee, priority_fee));
+        self
+    }
+
+    /// Start serving on a random local port and return its URL
+    pub async fn serve(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let chain = self.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let chain = chain.clone();
+                tokio::spawn(async move { chain.handle(socket).await });
+            }
+        });
+        url
+    }
+
+    async fn handle(&self, mut socket: TcpStream) {
+        let Some(body) = read_request_body(&mut socket).await else { return };
+        let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"].clone() });
+        let response = match self.answer(&request) {
+            Ok(result) => merge(response, json!({ "result": result })),
+            Err(message) => merge(response, json!({ "error": { "code": -32000, "message": message } })),
+        };
+        write_response(&mut socket, 200, &response.to_string()).await;
+    }
+
+    fn answer(&self, request: &Value) -> Result<Value, String> {
+        let head = if self.advancing { 100 + self.mined.fetch_add(1, Ordering::SeqCst) } else { 100 };
+        match request["method"].as_str() {
+            Some("eth_call") => {
+                self.call_blocks.lock().unwrap().push(request["params"][1].clone());
+                let tx = &request["params"][0];
+                let to: Address = serde_json::from_value(tx["to"].clone()).map_err(|e| e.to_string())?;
+                let data = tx.get("data").or_else(|| tx.get("input")).cloned().unwrap_or(Value::Null);
+                let data: Bytes = serde_json::from_value(data).map_err(|e| e.to_string())?;
+                let calls = self.calls.lock().unwrap();
+                if to == MULTICALL3_ADDRESS && !self.no_multicall {
+                    let packed = Aggregate3Call::decode(&data).map_err(|e| e.to_string())?;
+                    let results = packed
+                        .calls
+                        .into_iter()
+                        .map(|call| match calls.get(&(call.target, call.call_data)) {
+                            Some(ret) => (true, ret.clone()),
+                            None => (false, Bytes::new()),
+                        })
+                        .collect();
+                    return Ok(json!(Bytes::from(Aggregate3Return(results).encode())));
+                }
+                let ret = calls.get(&(to, data)).ok_or("execution reverted")?;
+                Ok(json!(ret))
+            }
+            Some("eth_blockNumber") => Ok(json!(format!("{:#x}", head))),
+            Some("eth_getLogs") => {
+                let filter: Filter = serde_json::from_value(request["params"][0].clone()).map_err(|e| e.to_string())?;
+                let logs = self.logs.lock().unwrap();
+                let logs = logs.as_ref().ok_or("eth_getLogs is not supported")?;
+                let matches = |log: &&Log| {
+                    filter.address.as_ref().is_none_or(|a| one_of(a, &log.address))
+                        && filter.topics.iter().enumerate().all(|(i, topic)| match topic {
+                            Some(topic) => log.topics.get(i).is_some_and(|t| one_of(topic, &Some(*t))),
+                            None => true,
+                        })
+                };
+                Ok(json!(logs.iter().filter(matches).collect::<Vec<_>>()))
+            }
+            Some("eth_feeHistory") => {
+                let (base_fee, tip) = self.fees.lock().unwrap().ok_or("eth_feeHistory is not supported")?;
+                let blocks = u64::from_str_radix(request["params"][0].as_str().unwrap_or("0x1").trim_start_matches("0x"), 16).unwrap_or(1);
+                let hex = |wei: u64| format!("{:#x}", wei);
+                Ok(json!({
+                    "oldestBlock": hex(head + 1 - blocks),
+                    "baseFeePerGas": vec![hex(base_fee); blocks as usize + 1],
+                    "gasUsedRatio": vec![0.5; blocks as usize],
+                    "reward": vec![vec![hex(tip)]; blocks as usize],
+                }))
+            }
+            Some("eth_gasPrice") => {
+                let (base_fee, tip) = self.fees.lock().unwrap().ok_or("eth_gasPrice is not supported")?;
+                Ok(json!(format!("{:#x}", base_fee + tip)))
+            }
+            other => Err(format!("method {:?} not supported by FakeChain", other)),
+        }
+    }
+}
+
+/// Whether `value` is the value or one of the array of a filter field
+fn one_of<T: PartialEq>(field: &ValueOrArray<T>, value: &T) -> bool {
+    match field {
+        ValueOrArray::Value(v) => v == value,
+        ValueOrArray::Array(values) => values.contains(value),
+    }
+}
+
+/// In-process HTTP endpoint that records JSON request bodies
+///
+/// Replies with the queued status codes in order, then `200 OK` once the queue is empty.
+#[derive(Clone, Default)]
+pub struct CaptureServer {
+    requests: Arc<Mutex<Vec<Value>>>,
+    statuses: Arc<Mutex<VecDeque<u16>>>,
+}
+
+impl CaptureServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue status codes for the next requests
+    pub fn respond_with(&self, statuses: &[u16]) -> &Self {
+        self.statuses.lock().unwrap().extend(statuses);
+        self
+    }
+
+    /// Bodies received so far, including those answered with an error status
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Start serving on a random local port and return its URL
+    pub async fn serve(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = self.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let server = server.clone();
+                tokio::spawn(async move {
+                    let Some(body) = read_request_body(&mut socket).await else { return };
+                    server
+                        .requests
+                        .lock()
+                        .unwrap()
+                        .push(serde_json::from_slice(&body).unwrap_or(Value::Null));
+                    let status = server.statuses.lock().unwrap().pop_front().unwrap_or(200);
+                    write_response(&mut socket, status, r#"{"ok":true}"#).await;
+                });
+            }
+        });
+        url
+    }
+}
+
+type GraphqlAnswer = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+/// In-process GraphQL endpoint answering by operation name
+///
+/// Each answer receives the request's variables and returns the whole response payload
+/// (`{"data": ...}` or `{"errors": [...]}`); unknown operations get a GraphQL error.
+#[derive(Clone, Default)]
+pub struct FakeSubgraph {
+    answers: Arc<Mutex<HashMap<String, GraphqlAnswer>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl FakeSubgraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `operation` with `answer(variables)`
+    pub fn on(&self, operation: &str, answer: impl Fn(&Value) -> Value + Send + Sync + 'static) -> &Self {
+        self.answers.lock().unwrap().insert(operation.to_string(), Arc::new(answer));
+        self
+    }
+
+    /// Request bodies received so far
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Start serving on a random local port and return its URL
+    pub async fn serve(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let subgraph = self.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let subgraph = subgraph.clone();
+                tokio::spawn(async move {
+                    let Some(body) = read_request_body(&mut socket).await else { return };
+                    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                    subgraph.requests.lock().unwrap().push(request.clone());
+                    let operation = request["operationName"].as_str().unwrap_or_default();
+                    let answer = subgraph.answers.lock().unwrap().get(operation).cloned();
+                    let response = match answer {
+                        Some(answer) => answer(&request["variables"]),
+                        None => json!({ "errors": [{ "message": format!("unknown operation {:?}", operation) }] }),
+                    };
+                    write_response(&mut socket, 200, &response.to_string()).await;
+                });
+            }
+        });
+        url
+    }
+}
+
+/// Read one HTTP/1.1 request and return its body, or `None` if the client hung up
+async fn read_request_body(socket: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&buf);
+        if let Some(split) = text.find("\r\n\r\n") {
+            let length = text[..split]
+                .lines()
+                .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            if buf.len() >= split + 4 + length {
+                return Some(buf[split + 4..split + 4 + length].to_vec());
+            }
+        }
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, status: u16, body: &str) {
+    let reason = reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Unknown");
+    let reply = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    let _ = socket.write_all(reply.as_bytes()).await;
+}
+
+fn merge(mut base: Value, extra: Value) -> Value {
+    if let (Some(base), Value::Object(extra)) = (base.as_object_mut(), extra) {
+        base.extend(extra);
+    }
+    base
+}
+
+/// Holder, expiry and term of a lease
+type MemoryLease = (String, Instant, u64);
+
+/// Leases in memory, shared by the handles `handle` makes, with the clock of the process
+#[derive(Clone, Default)]
+pub struct MemoryLeases {
+    leases: Arc<Mutex<HashMap<String, MemoryLease>>>,
+    holder: String,
+}
+
+impl MemoryLeases {
+    pub fn handle(&self, holder: &str) -> Arc<dyn LeaseStore> {
+        Arc::new(Self { leases: self.leases.clone(), holder: holder.to_string() })
+    }
+}
+
+#[async_trait]
+impl LeaseStore for MemoryLeases {
+    fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    async fn acquire(&self, name: &str, lease: Duration) -> Result<Option<u64>> {
+        let mut leases = self.leases.lock().unwrap();
+        let now = Instant::now();
+        let term = match leases.get(name) {
+            Some((holder, _, term)) if *holder == self.holder => *term,
+            Some((_, expires, _)) if *expires > now => return Ok(None),
+            Some((_, _, term)) => term + 1,
+            None => 1,
+        };
+        leases.insert(name.to_string(), (self.holder.clone(), now + lease, term));
+        Ok(Some(term))
+    }
+
+    async fn release(&self, name: &str) -> Result<()> {
+        let mut leases = self.leases.lock().unwrap();
+        if let Some((holder, expires, _)) = leases.get_mut(name) {
+            if *holder == self.holder {
+                *expires = Instant::now();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `HighUtilization` finding of `severity` fingerprinted `fingerprint` and described as
+/// "<fingerprint> finding", found now; tests set any other field with struct update syntax
+pub fn finding(fingerprint: &str, severity: RiskSeverity) -> RiskFinding {
+    RiskFinding {
+        category: RiskCategory::HighUtilization,
+        severity,
+        description: format!("{} finding", fingerprint),
+        metadata: json!({}),
+        timestamp: Utc::now(),
+        fingerprint: fingerprint.to_string(),
+        score_contribution: 0,
+    }
+}
+
+/// Assessment scoring `risk_score` of a USDC market at `0x0000...0001` with `findings`; see
+/// `RiskAssessment::new` for the rest
+pub fn assessment(risk_score: u8, findings: Vec<RiskFinding>) -> RiskAssessment {
+    RiskAssessment { risk_score, ..RiskAssessment::new("USDC", Address::from_low_u64_be(1), findings) }
+}
@@ -0,0 +1,506 @@
+//! Risk score and utilization of a market over time, from the history store
+//!
+//! Points are the stored assessments as they are. When the engine was down, the spacing between
+//! two assessments exceeds `GAP_FACTOR` times the usual one; that stretch is reported as a `Gap`
+//! and left blank in sparklines rather than interpolated across. Reserves are not stored, so
+//! they are not part of the series.
+//!
+//! `ScoreTrend` puts a new assessment's score against that history: a moving average, so one
+//! noisy run does not swing it, and which way it is heading. A score creeping up across runs
+//! escalates no single check, so the average rising into a higher band is a finding of its own.
+//! Each category sub-score gets a trend of its own the same way, without band findings.
+
+use crate::config::TrendConfig;
+use crate::error::Result;
+use crate::risk::{finding_fingerprint, RiskAssessment, RiskCategory, RiskFinding, RiskSeverity};
+use crate::storage::{FindingQuery, ScorePoint, Storage, StoredMarket};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// Spacing between assessments, as a multiple of the median spacing, from which it is a gap
+pub const GAP_FACTOR: i32 = 2;
+
+/// Columns of the trend CSV
+///
+/// - `market_name`, `market_address`: market the assessment is about
+/// - `assessed_at`: RFC 3339 time of the assessment
+/// - `risk_score`: 0 to 100
+/// - `utilization_rate`: 0 to 1; empty if the assessment stored no market snapshot
+/// - `gap_before`: `true` if no assessments ran between the previous row of the market and this one
+pub const TREND_COLUMNS: [&str; 6] = ["market_name", "market_address", "assessed_at", "risk_score", "utilization_rate", "gap_before"];
+
+/// Days of history `ScoreTrend::load` reads: enough for a point a week before the assessment
+pub const SCORE_HISTORY_DAYS: i64 = 8;
+
+/// History shorter than this gives no slope, so a trend is stable until it has some
+const MIN_SLOPE_SPAN_HOURS: i64 = 1;
+
+/// Blocks of a sparkline, lowest to highest
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One stored assessment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrendPoint {
+    pub timestamp: DateTime<Utc>,
+    pub risk_score: u8,
+    pub utilization_rate: Option<f64>,
+}
+
+/// Span without assessments, between the last point before it and the first after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gap {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+/// Consecutive assessments, without a gap between them, that share a condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stretch {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub assessments: usize,
+}
+
+impl Stretch {
+    pub fn duration(&self) -> Duration {
+        self.to - self.from
+    }
+}
+
+/// Figures over the whole window; score figures are `None` without assessments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrendSummary {
+    pub assessments: usize,
+    pub min_score: Option<u8>,
+    pub max_score: Option<u8>,
+    pub avg_score: Option<f64>,
+    /// Distinct High or Critical findings reported in the window
+    pub high_findings: usize,
+    /// Assessments those findings were reported in, summed over the findings
+    pub high_reports: u64,
+    pub utilization_threshold: f64,
+    /// Longest stretch with utilization above `utilization_threshold`
+    pub longest_above_threshold: Option<Stretch>,
+}
+
+/// History of one market in `[since, until)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trend {
+    pub market: StoredMarket,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Oldest first
+    pub points: Vec<TrendPoint>,
+    pub gaps: Vec<Gap>,
+    pub summary: TrendSummary,
+}
+
+impl Trend {
+    /// Read the history of `market` in `[since, until)` from `storage`
+    pub async fn load(
+        storage: &dyn Storage,
+        market: &StoredMarket,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        utilization_threshold: f64,
+    ) -> Result<Self> {
+        let utilization: HashMap<DateTime<Utc>, f64> = storage
+            .snapshots(market.address, since, until)
+            .await?
+            .into_iter()
+            .map(|snapshot| (snapshot.timestamp, snapshot.utilization_rate))
+            .collect();
+        let points: Vec<TrendPoint> = storage
+            .score_series(market.address, since, until)
+            .await?
+            .into_iter()
+            .map(|point| TrendPoint {
+                utilization_rate: utilization.get(&point.timestamp).copied(),
+                timestamp: point.timestamp,
+                risk_score: point.risk_score,
+            })
+            .collect();
+        let high = storage
+            .fingerprint_spans(&FindingQuery {
+                market: Some(market.address),
+                min_severity: Some(RiskSeverity::High),
+                since: Some(since),
+                until: Some(until),
+                ..FindingQuery::default()
+            })
+            .await?;
+
+        let gaps = find_gaps(&points);
+        let scores = points.iter().map(|p| p.risk_score);
+        let summary = TrendSummary {
+            assessments: points.len(),
+            min_score: scores.clone().min(),
+            max_score: scores.clone().max(),
+            avg_score: (!points.is_empty()).then(|| scores.map(f64::from).sum::<f64>() / points.len() as f64),
+            high_findings: high.len(),
+            high_reports: high.iter().map(|span| span.occurrences).sum(),
+            utilization_threshold,
+            longest_above_threshold: longest_stretch(&points, &gaps, |p| p.utilization_rate.is_some_and(|u| u > utilization_threshold)),
+        };
+        Ok(Self { market: market.clone(), since, until, points, gaps, summary })
+    }
+
+    /// `value` of the points as at most `width` cells spanning `[since, until)`, scaled to
+    /// `[min, max]`; cells without an assessment are blank
+    ///
+    /// A cell covers at least `GAP_FACTOR` times the median spacing, so only gaps leave blanks.
+    pub fn sparkline(&self, width: usize, min: f64, max: f64, value: impl Fn(&TrendPoint) -> Option<f64>) -> String {
+        let window = (self.until - self.since).num_milliseconds().max(1);
+        let cell = (window / width.max(1) as i64).max(median_spacing(&self.points).map_or(0, |s| (s * GAP_FACTOR).num_milliseconds()));
+        let cells = ((window + cell - 1) / cell) as usize;
+        let mut sums = vec![(0.0, 0usize); cells];
+        for point in &self.points {
+            let Some(value) = value(point) else { continue };
+            let index = ((point.timestamp - self.since).num_milliseconds() / cell) as usize;
+            if let Some((sum, count)) = sums.get_mut(index) {
+                *sum += value;
+                *count += 1;
+            }
+        }
+        sums.into_iter()
+            .map(|(sum, count)| match count {
+                0 => ' ',
+                _ => {
+                    let level = ((sum / count as f64 - min) / (max - min)).clamp(0.0, 1.0);
+                    SPARK_LEVELS[(level * (SPARK_LEVELS.len() - 1) as f64).round() as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Which way a market's smoothed risk score is heading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Falling by at least `risk.trend.improving_slope` points a day
+    Improving,
+    Stable,
+    /// Rising by at least `risk.trend.deteriorating_slope` points a day
+    Deteriorating,
+}
+
+impl Direction {
+    /// Arrow shown next to the score: up for a rising score, which is worse
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Self::Improving => "↓",
+            Self::Stable => "→",
+            Self::Deteriorating => "↑",
+        }
+    }
+}
+
+/// Risk score of an assessment against the stored history of its market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreTrend {
+    /// Exponential moving average of the scores, this one included
+    pub smoothed_score: f64,
+    /// Moving average before this assessment; `None` without history
+    pub previous_smoothed_score: Option<f64>,
+    /// Score minus that of the last assessment at least a day old; `None` without one
+    pub delta_24h: Option<i16>,
+    /// Score minus that of the last assessment at least a week old; `None` without one
+    pub delta_7d: Option<i16>,
+    /// Change of the moving average in points per day over the last day, or over the whole
+    /// history if shorter; `None` with under an hour of history
+    pub slope_per_day: Option<f64>,
+    pub direction: Direction,
+    /// Trend of each category sub-score the assessment evaluated, from the history points that
+    /// evaluated it too; empty from `compute`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub categories: BTreeMap<RiskCategory, ScoreTrend>,
+}
+
+impl ScoreTrend {
+    /// Trend of `score` assessed at `at`, after `history` (oldest first, all before `at`)
+    pub fn compute(history: &[ScorePoint], at: DateTime<Utc>, score: u8, config: &TrendConfig) -> Self {
+        let alpha = config.smoothing.clamp(f64::EPSILON, 1.0);
+        let mut smoothed: Vec<(DateTime<Utc>, f64)> = Vec::with_capacity(history.len() + 1);
+        for (timestamp, score) in history.iter().map(|p| (p.timestamp, p.risk_score)).chain([(at, score)]) {
+            let average = match smoothed.last() {
+                Some(&(_, previous)) => alpha * f64::from(score) + (1.0 - alpha) * previous,
+                None => f64::from(score),
+            };
+            smoothed.push((timestamp, average));
+        }
+        let smoothed_score = smoothed[smoothed.len() - 1].1;
+
+        // Last point at least `age` old, or none
+        let before = |age: Duration| history.iter().rev().find(|p| p.timestamp <= at - age);
+        let delta = |age: Duration| before(age).map(|p| i16::from(score) - i16::from(p.risk_score));
+
+        let slope_per_day = smoothed
+            .iter()
+            .rev()
+            .find(|(timestamp, _)| *timestamp <= at - Duration::days(1))
+            .or(smoothed.first())
+            .filter(|(timestamp, _)| at - *timestamp >= Duration::hours(MIN_SLOPE_SPAN_HOURS))
+            .map(|&(timestamp, average)| (smoothed_score - average) / ((at - timestamp).num_seconds() as f64 / 86_400.0));
+        let direction = match slope_per_day {
+            Some(slope) if slope >= config.deteriorating_slope => Direction::Deteriorating,
+            Some(slope) if slope <= -config.improving_slope => Direction::Improving,
+            _ => Direction::Stable,
+        };
+
+        Self {
+            smoothed_score,
+            previous_smoothed_score: (!history.is_empty()).then(|| smoothed[smoothed.len() - 2].1),
+            delta_24h: delta(Duration::days(1)),
+            delta_7d: delta(Duration::days(7)),
+            slope_per_day,
+            direction,
+            categories: BTreeMap::new(),
+        }
+    }
+
+    /// Trend of `assessment` from the last `SCORE_HISTORY_DAYS` of its market in `storage`
+    pub async fn load(storage: &dyn Storage, assessment: &RiskAssessment, config: &TrendConfig) -> Result<Self> {
+        let since = assessment.timestamp - Duration::days(SCORE_HISTORY_DAYS);
+        let history = storage.score_series(assessment.market_address, since, assessment.timestamp).await?;
+        let mut trend = Self::compute(&history, assessment.timestamp, assessment.risk_score, config);
+        for (category, score) in &assessment.category_scores {
+            let Some(score) = *score else { continue };
+            let points: Vec<ScorePoint> = history
+                .iter()
+                .filter_map(|point| {
+                    let risk_score = point.category_scores.get(category).copied().flatten()?;
+                    Some(ScorePoint { risk_score, ..point.clone() })
+                })
+                .collect();
+            trend.categories.insert(category.clone(), Self::compute(&points, assessment.timestamp, score, config));
+        }
+        Ok(trend)
+    }
+
+    /// Highest of `bands` the moving average rose to or past with this assessment
+    pub fn band_entered(&self, bands: &[u8]) -> Option<u8> {
+        let previous = self.previous_smoothed_score?;
+        bands
+            .iter()
+            .copied()
+            .filter(|&band| previous < f64::from(band) && self.smoothed_score >= f64::from(band))
+            .max()
+    }
+
+    /// Finding for `assessment` if its moving average rose into a higher band; the band sets
+    /// the severity: High from 80, Medium from 60, Low below
+    pub fn band_finding(&self, assessment: &RiskAssessment, bands: &[u8]) -> Option<RiskFinding> {
+        let band = self.band_entered(bands)?;
+        let severity = match band {
+            80.. => RiskSeverity::High,
+            60..=79 => RiskSeverity::Medium,
+            _ => RiskSeverity::Low,
+        };
+        let upper = bands.iter().copied().filter(|&b| b > band).min();
+        let range = upper.map_or_else(|| format!("{}+", band), |upper| format!("{}-{}", band, upper));
+        let category = RiskCategory::RiskTrend;
+        Some(RiskFinding {
+            fingerprint: finding_fingerprint(&assessment.market_address, &category, "score_trend"),
+            category,
+            severity,
+            description: format!(
+                "Smoothed risk score rose from {:.1} to {:.1}, into the {} band",
+                self.previous_smoothed_score.unwrap_or_default(),
+                self.smoothed_score,
+                range
+            ),
+            metadata: serde_json::json!({
+                "smoothed_score": self.smoothed_score,
+                "previous_smoothed_score": self.previous_smoothed_score,
+                "band": band,
+                "delta_24h": self.delta_24h,
+                "delta_7d": self.delta_7d,
+                "slope_per_day": self.slope_per_day,
+                "direction": self.direction,
+            }),
+            timestamp: assessment.timestamp,
+            score_contribution: 0,
+        })
+    }
+}
+
+/// Write the points of `trends` as CSV with a header row; returns the number of rows written
+pub fn write_trend_csv<W: Write>(writer: W, trends: &[Trend]) -> Result<usize> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(TREND_COLUMNS).map_err(io::Error::from)?;
+    let mut rows = 0;
+    for trend in trends {
+        for point in &trend.points {
+            let gap_before = trend.gaps.iter().any(|gap| gap.to == point.timestamp);
+            csv.write_record([
+                trend.market.name.clone(),
+                crate::utils::to_checksum_address(&trend.market.address),
+                point.timestamp.to_rfc3339(),
+                point.risk_score.to_string(),
+                point.utilization_rate.map(|u| u.to_string()).unwrap_or_default(),
+                gap_before.to_string(),
+            ])
+            .map_err(io::Error::from)?;
+            rows += 1;
+        }
+    }
+    csv.flush()?;
+    Ok(rows)
+}
+
+/// Median time between consecutive points; `None` with fewer than two
+fn median_spacing(points: &[TrendPoint]) -> Option<Duration> {
+    let mut spacings: Vec<Duration> = points.windows(2).map(|w| w[1].timestamp - w[0].timestamp).collect();
+    spacings.sort();
+    spacings.get(spacings.len() / 2).copied()
+}
+
+/// Spans between consecutive points more than `GAP_FACTOR` times the median spacing apart
+fn find_gaps(points: &[TrendPoint]) -> Vec<Gap> {
+    let Some(median) = median_spacing(points) else { return Vec::new() };
+    points
+        .windows(2)
+        .filter(|w| w[1].timestamp - w[0].timestamp > median * GAP_FACTOR)
+        .map(|w| Gap { from: w[0].timestamp, to: w[1].timestamp })
+        .collect()
+}
+
+/// Longest run of points matching `condition`; a gap ends a run, since nothing is known about it
+fn longest_stretch(points: &[TrendPoint], gaps: &[Gap], condition: impl Fn(&TrendPoint) -> bool) -> Option<Stretch> {
+    let mut longest: Option<Stretch> = None;
+    let mut current: Option<Stretch> = None;
+    for point in points {
+        if !condition(point) {
+            current = None;
+            continue;
+        }
+        current = match current {
+            Some(stretch) if !gaps.iter().any(|gap| gap.to == point.timestamp) => {
+                Some(Stretch { to: point.timestamp, assessments: stretch.assessments + 1, ..stretch })
+            }
+            _ => Some(Stretch { from: point.timestamp, to: point.timestamp, assessments: 1 }),
+        };
+        let stretch = current.as_ref().expect("set above");
+        if longest.as_ref().is_none_or(|l| (stretch.duration(), stretch.assessments) > (l.duration(), l.assessments)) {
+            longest = current.clone();
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_trend_smooths_and_flags_band_crossings() {
+        let at = |hours: i64| "2024-05-08T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::hours(hours);
+        let config = TrendConfig { smoothing: 0.5, ..TrendConfig::default() };
+        // A week ago 20, a day ago 30, then rising every six hours
+        let history: Vec<ScorePoint> = [(-168, 20), (-24, 30), (-18, 40), (-12, 50), (-6, 60)]
+            .into_iter()
+            .map(|(hour, risk_score)| ScorePoint { timestamp: at(hour), risk_score, engine_version: None, category_scores: Default::default() })
+            .collect();
+
+        let trend = ScoreTrend::compute(&history, at(0), 70, &config);
+        // 20, 25, 32.5, 41.25, 50.625, 60.3125
+        assert_eq!((trend.previous_smoothed_score, trend.smoothed_score), (Some(50.625), 60.3125));
+        assert_eq!((trend.delta_24h, trend.delta_7d), (Some(40), Some(50)));
+        assert_eq!(trend.slope_per_day, Some(60.3125 - 25.0));
+        assert_eq!(trend.direction, Direction::Deteriorating);
+        assert_eq!(trend.band_entered(&config.bands), Some(60));
+
+        let assessment = RiskAssessment {
+            risk_score: 70,
+            timestamp: at(0),
+            trend: Some(trend.clone()),
+            ..RiskAssessment::new("USDC", ethers::types::Address::repeat_byte(1), Vec::new())
+        };
+        let finding = trend.band_finding(&assessment, &config.bands).unwrap();
+        assert_eq!((finding.category, finding.severity), (RiskCategory::RiskTrend, RiskSeverity::Medium));
+        assert!(finding.description.ends_with("into the 60-80 band"), "{}", finding.description);
+        assert_eq!(assessment.score_label(), "70/100 ↑");
+
+        // A drop to 0 brings the average back to about where it was a day ago
+        let falling = ScoreTrend::compute(&history, at(0), 0, &config);
+        assert_eq!((falling.direction, falling.band_entered(&config.bands)), (Direction::Stable, None));
+        // Without history there is no slope, and nothing to cross from
+        let first = ScoreTrend::compute(&[], at(0), 90, &config);
+        assert_eq!((first.smoothed_score, first.slope_per_day, first.band_entered(&config.bands)), (90.0, None, None));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_gaps_are_kept_and_summarized() {
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+        use crate::risk::RiskAssessment;
+        use crate::storage::SqliteStorage;
+
+        let at = |hours: i64| "2024-05-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::hours(hours);
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let mut market = fixture.get_markets().await.unwrap().remove(0);
+        // Hourly assessments with the engine down from hour 4 to hour 10
+        for (hour, score, utilization) in [(0, 10, 0.8), (1, 20, 0.9), (2, 30, 0.9), (3, 40, 0.9), (4, 30, 0.9), (10, 60, 0.95), (11, 50, 0.5)] {
+            market.total_borrow = market.total_supply * utilization;
+            let assessment = RiskAssessment {
+                risk_score: score,
+                timestamp: at(hour),
+                ..RiskAssessment::new(market.name.clone(), market.comet_address, Vec::new())
+            };
+            storage.record(1, &market, &assessment).await.unwrap();
+        }
+        let stored = storage.markets().await.unwrap().remove(0);
+
+        let trend = Trend::load(&storage, &stored, at(0), at(12), 0.85).await.unwrap();
+        assert_eq!(trend.gaps, [Gap { from: at(4), to: at(10) }]);
+        let summary = &trend.summary;
+        assert_eq!((summary.min_score, summary.max_score, summary.avg_score), (Some(10), Some(60), Some(240.0 / 7.0)));
+        // The gap ends the stretch: hour 10 alone does not extend it
+        assert_eq!(summary.longest_above_threshold, Some(Stretch { from: at(1), to: at(4), assessments: 4 }));
+
+        // Cells of two hours, twice the usual spacing: only the downtime is blank
+        let scores = trend.sparkline(12, 0.0, 100.0, |p| Some(f64::from(p.risk_score)));
+        assert_eq!(scores, "▂▃▃  ▅");
+
+        let mut csv = Vec::new();
+        assert_eq!(write_trend_csv(&mut csv, &[trend]).unwrap(), 7);
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("market_name,market_address,assessed_at,risk_score,utilization_rate,gap_before\n"), "{}", csv);
+        assert_eq!(csv.lines().filter(|line| line.ends_with(",true")).count(), 1);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_category_trends_follow_the_points_that_evaluated_them() {
+        use crate::provider::{bundled_fixture, FixtureProvider, MarketDataProvider};
+        use crate::storage::SqliteStorage;
+
+        let at = |hours: i64| "2024-05-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::hours(hours);
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let fixture = FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap();
+        let market = fixture.get_markets().await.unwrap().remove(0);
+        let scored = |hour: i64, utilization: Option<u8>, bad_debt: Option<u8>| RiskAssessment {
+            risk_score: 30,
+            category_scores: [(RiskCategory::HighUtilization, utilization), (RiskCategory::BadDebt, bad_debt)].into_iter().collect(),
+            timestamp: at(hour),
+            ..RiskAssessment::new(market.name.clone(), market.comet_address, Vec::new())
+        };
+        // Utilization climbs across runs while the overall score holds; bad debt was only
+        // evaluated once
+        for (hour, utilization, bad_debt) in [(0, Some(0), None), (12, Some(15), Some(0)), (24, None, None)] {
+            storage.record(1, &market, &scored(hour, utilization, bad_debt)).await.unwrap();
+        }
+        let series = storage.score_series(market.comet_address, at(0), at(25)).await.unwrap();
+        assert_eq!(series[1].category_scores[&RiskCategory::BadDebt], Some(0));
+        assert_eq!(series[0].category_scores[&RiskCategory::BadDebt], None);
+
+        let trend = ScoreTrend::load(&storage, &scored(36, Some(45), None), &TrendConfig::default()).await.unwrap();
+        assert_eq!(trend.direction, Direction::Stable);
+        let utilization = &trend.categories[&RiskCategory::HighUtilization];
+        assert_eq!((utilization.direction, utilization.delta_24h), (Direction::Deteriorating, Some(30)));
+        assert!(!trend.categories.contains_key(&RiskCategory::BadDebt));
+    }
+}
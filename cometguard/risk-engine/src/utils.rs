@@ -1,11 +1,39 @@
-use anyhow::{Result, Context};
+use crate::error::{Result, RiskEngineError};
 use ethers::core::types::{Address, U256};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::str::FromStr;
-use std::fmt::Write;
+use std::time::Duration;
 
-/// Format an Address for display (0x123...abc)
+/// EIP-55 checksummed form of an Address (e.g., "0xc3d688B66703497DAA19211EEdff47f25384cdc3"),
+/// the form block explorers and wallets print
+pub fn to_checksum_address(address: &Address) -> String {
+    ethers::utils::to_checksum(address, None)
+}
+
+/// Serialize an Address checksummed: `#[serde(serialize_with = "crate::utils::checksummed")]`;
+/// addresses deserialize in any case
+pub fn checksummed<S: Serializer>(address: &Address, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_checksum_address(address))
+}
+
+/// `checksummed` for an optional Address
+pub fn checksummed_option<S: Serializer>(address: &Option<Address>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match address {
+        Some(address) => checksummed(address, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// `checksummed` for a list of Addresses
+pub fn checksummed_list<S: Serializer>(addresses: &[Address], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(addresses.iter().map(to_checksum_address))
+}
+
+/// Format an Address for display, checksummed (0x123...aBc)
 pub fn format_address(address: &Address) -> String {
-    let addr_str = format!("{:?}", address);
+    let addr_str = to_checksum_address(address);
     let len = addr_str.len();
     if len <= 10 {
         addr_str
@@ -14,6 +42,21 @@ pub fn format_address(address: &Address) -> String {
     }
 }
 
+/// Format an Address with its address-book label (`Comet USDC (0xc3d6...cdc3)`), or as
+/// `format_address` does when it has none
+pub fn format_address_labeled(address: &Address) -> String {
+    match crate::labels::label_for(address) {
+        Some(label) => format!("{} ({})", label, format_address(address)),
+        None => format_address(address),
+    }
+}
+
+/// Format a named Address as `name (0x123...abc)`, preferring its address-book label over `name`
+pub fn format_named_address(name: &str, address: &Address) -> String {
+    let label = crate::labels::label_for(address);
+    format!("{} ({})", label.as_deref().unwrap_or(name), format_address(address))
+}
+
 /// Format a value with a given number of decimals
 pub fn format_decimals(value: f64, decimals: usize) -> String {
     format!("{:.*}", decimals, value)
@@ -24,37 +67,361 @@ pub fn format_percentage(value: f64) -> String {
     format!("{:.2}%", value * 100.0)
 }
 
+/// Separators of formatted numbers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberStyle {
+    /// 1,234.56
+    #[default]
+    Us,
+    /// 1.234,56
+    DecimalComma,
+    /// 1 234.56, grouped with thin spaces
+    ThinSpace,
+}
+
+impl NumberStyle {
+    pub fn decimal_separator(self) -> char {
+        match self {
+            NumberStyle::Us | NumberStyle::ThinSpace => '.',
+            NumberStyle::DecimalComma => ',',
+        }
+    }
+
+    pub fn group_separator(self) -> char {
+        match self {
+            NumberStyle::Us => ',',
+            NumberStyle::DecimalComma => '.',
+            NumberStyle::ThinSpace => '\u{2009}',
+        }
+    }
+
+    /// `text` formatted in the US style, such as `format_compact` output, with this style's
+    /// separators; any `,` and `.` in it are taken for separators
+    pub fn localize(self, text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                ',' => self.group_separator(),
+                '.' => self.decimal_separator(),
+                c => c,
+            })
+            .collect()
+    }
+}
+
 /// Format a monetary value with a symbol (e.g., 1000.0 -> "$1,000.00")
 pub fn format_money(value: f64, symbol: &str) -> String {
-    let abs_value = value.abs();
+    format_money_with(value, symbol, NumberStyle::Us)
+}
+
+/// Format a monetary value as `format_money` does with the separators of `style` (e.g.,
+/// 1000.0 -> "$1.000,00" with `DecimalComma`)
+///
+/// The digits are the ones `{:.2}` prints, exact for every finite `f64`; NaN and infinities
+/// are written as `f64` displays them.
+pub fn format_money_with(value: f64, symbol: &str, style: NumberStyle) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let digits = format!("{:.2}", value.abs());
+    let (whole, cents) = digits.split_once('.').unwrap_or((&digits, "00"));
+    // -0.0 and negatives that round to zero print without a sign
+    let zero = digits.bytes().all(|b| b == b'0' || b == b'.');
+    let sign = if value < 0.0 && !zero { "-" } else { "" };
+    format!("{}{}{}{}{}", sign, symbol, group_digits(whole, style.group_separator()), style.decimal_separator(), cents)
+}
+
+/// Significant digits `format_compact` keeps
+pub const COMPACT_DIGITS: usize = 3;
+
+/// Most decimals compact formats show for amounts under one
+const MAX_COMPACT_DECIMALS: usize = 6;
+
+/// Format a USD amount with a thousand, million or billion suffix and `COMPACT_DIGITS`
+/// significant digits (e.g., 1234567.0 -> "$1.23M", -42.0 -> "-$42.0")
+pub fn format_compact(value: f64) -> String {
+    format_compact_with(value, COMPACT_DIGITS)
+}
+
+/// Format a USD amount as `format_compact` does with `significant` digits (e.g., 456_700.0
+/// with 4 -> "$456.7K"); NaN and infinities are written as `f64` displays them
+pub fn format_compact_with(value: f64, significant: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
     let sign = if value < 0.0 { "-" } else { "" };
-    
-    let mut result = String::new();
-    let whole_part = abs_value.trunc() as u64;
-    let decimal_part = (abs_value.fract() * 100.0).round() as u64;
-    
-    let whole_str = whole_part.to_string();
-    let chunks: Vec<&str> = whole_str.as_bytes()
+    format!("{}${}", sign, compact(value.abs(), significant))
+}
+
+/// Format an amount of some unit as `format_compact` does, without the dollar sign (e.g.,
+/// 2500.0 -> "2.50K")
+pub fn format_compact_amount(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    format!("{}{}", sign, compact(value.abs(), COMPACT_DIGITS))
+}
+
+/// Non-negative finite `value` rounded to `significant` digits before it is scaled, so that
+/// 999,950 becomes "1.00M" rather than "1000K"
+fn compact(value: f64, significant: usize) -> String {
+    let significant = significant.max(1) as i32;
+    let value = round_significant(value, significant);
+    let (unit, suffix) = [(1e9, "B"), (1e6, "M"), (1e3, "K")]
+        .into_iter()
+        .find(|(unit, _)| value >= *unit)
+        .unwrap_or((1.0, ""));
+    let scaled = value / unit;
+    let decimals = if scaled == 0.0 {
+        significant - 1
+    } else {
+        significant - 1 - scaled.log10().floor() as i32
+    };
+    let decimals = (decimals.max(0) as usize).min(MAX_COMPACT_DECIMALS);
+    format!("{:.*}{}", decimals, scaled, suffix)
+}
+
+/// `value` rounded to `significant` digits; powers of ten are applied so that they stay exact
+fn round_significant(value: f64, significant: i32) -> f64 {
+    if value == 0.0 {
+        return 0.0;
+    }
+    let shift = significant - 1 - value.log10().floor() as i32;
+    if shift >= 0 {
+        let factor = 10f64.powi(shift);
+        (value * factor).round() / factor
+    } else {
+        let factor = 10f64.powi(-shift);
+        (value / factor).round() * factor
+    }
+}
+
+/// Format a token amount with a precision fitting its magnitude: abbreviated from a million
+/// up, two decimals from one up and four significant digits below, never more decimals than
+/// the token has (e.g., 1234.5 USDC -> "1,234.50 USDC", 0.004213 WETH -> "0.004213 WETH")
+pub fn format_token_amount(value: f64, symbol: &str, decimals: u8) -> String {
+    if !value.is_finite() {
+        return format!("{} {}", value, symbol);
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let abs_value = value.abs();
+    if round_significant(abs_value, COMPACT_DIGITS as i32) >= 1e6 {
+        return format!("{}{} {}", sign, compact(abs_value, COMPACT_DIGITS), symbol);
+    }
+    let precision = if abs_value >= 1.0 || abs_value == 0.0 {
+        2
+    } else {
+        (3 - abs_value.log10().floor() as i32) as usize
+    };
+    let formatted = format!("{:.*}", precision.min(usize::from(decimals)), abs_value);
+    let (whole, fraction) = formatted.split_once('.').map_or((formatted.as_str(), None), |(w, f)| (w, Some(f)));
+    let fraction = fraction.map(|f| format!(".{}", f)).unwrap_or_default();
+    format!("{}{}{} {}", sign, group_digits(whole, ','), fraction, symbol)
+}
+
+/// Digits of a whole number with `separator` between each group of three
+fn group_digits(digits: &str, separator: char) -> String {
+    let chunks: Vec<&str> = digits
+        .as_bytes()
         .rchunks(3)
         .map(|chunk| std::str::from_utf8(chunk).unwrap())
         .collect();
-    
-    write!(result, "{}{}", sign, symbol).unwrap();
-    for (i, chunk) in chunks.iter().rev().enumerate() {
-        if i > 0 {
-            write!(result, ",").unwrap();
+    chunks.into_iter().rev().collect::<Vec<_>>().join(&separator.to_string())
+}
+
+/// Parse an amount such as `250k`, `1.5m` or `2B`: a number, optionally grouped with `,` or
+/// `_`, and a suffix for thousands, millions or billions
+pub fn parse_amount(input: &str) -> Result<f64> {
+    let invalid = |message: &str| RiskEngineError::Parse {
+        what: "amount",
+        input: input.to_string(),
+        message: message.to_string(),
+    };
+    let trimmed = input.trim();
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier = match suffix.to_ascii_lowercase() {
+                'k' => 1e3,
+                'm' => 1e6,
+                'b' => 1e9,
+                _ => return Err(invalid("unknown suffix; use k, m or b")),
+            };
+            (&trimmed[..index], multiplier)
         }
-        write!(result, "{}", chunk).unwrap();
+        _ => (trimmed, 1.0),
+    };
+    let number: String = number.trim().chars().filter(|c| !matches!(c, ',' | '_')).collect();
+    if !number.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+')) {
+        return Err(invalid("expected a number, optionally followed by k, m or b"));
     }
-    
-    write!(result, ".{:02}", decimal_part).unwrap();
-    result
+    let value: f64 = number.parse().map_err(|_| invalid("expected a number, optionally followed by k, m or b"))?;
+    let amount = value * multiplier;
+    if !amount.is_finite() {
+        return Err(invalid("too large"));
+    }
+    Ok(amount)
+}
+
+/// Replace control characters (line breaks, escapes) with spaces so untrusted text stays on one line
+pub fn sanitize_inline(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
 }
 
-/// Convert a string to an Address
+/// Expand `${NAME}` references to environment variables in `value`
+///
+/// `field` names the config field in the error returned for an unset variable.
+pub fn expand_env(field: &str, value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else { break };
+        let name = &rest[start + 2..start + end];
+        let resolved = std::env::var(name).map_err(|_| {
+            RiskEngineError::config(field, format!("environment variable `{}` is not set", name))
+        })?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&resolved);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Convert a string to an Address, in any case
 pub fn parse_address(address_str: &str) -> Result<Address> {
-    Address::from_str(address_str)
-        .with_context(|| format!("Failed to parse address: {}", address_str))
+    Address::from_str(address_str).map_err(|e| RiskEngineError::Parse {
+        what: "address",
+        input: address_str.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Convert a string to an Address as `parse_address` does, but reject mixed-case input that
+/// fails its EIP-55 checksum, naming the checksummed form; all-lowercase and all-uppercase
+/// input carries no checksum and is accepted
+pub fn parse_address_strict(address_str: &str) -> Result<Address> {
+    let address = parse_address(address_str)?;
+    let hex = address_str.strip_prefix("0x").unwrap_or(address_str);
+    let mixed_case = hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+    let checksummed = to_checksum_address(&address);
+    if mixed_case && hex != &checksummed[2..] {
+        return Err(RiskEngineError::Parse {
+            what: "address",
+            input: address_str.to_string(),
+            message: format!("checksum mismatch; did you mean {}?", checksummed),
+        });
+    }
+    Ok(address)
+}
+
+/// Parse a duration such as `30s`, `5m`, `1h`, `2d` or a sum of them such as `1h30m` or
+/// `5m 30s`; a bare number is seconds
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let invalid = |message: &str| RiskEngineError::Parse {
+        what: "duration",
+        input: input.to_string(),
+        message: message.to_string(),
+    };
+    let trimmed = input.trim();
+    if !trimmed.is_empty() && trimmed.bytes().all(|b| b.is_ascii_digit()) {
+        let seconds: u64 = trimmed.parse().map_err(|_| invalid("too large"))?;
+        return non_zero(seconds).ok_or_else(|| invalid("must be greater than zero"));
+    }
+
+    let mut seconds: u64 = 0;
+    let mut rest = trimmed;
+    if rest.is_empty() {
+        return Err(invalid("expected a whole number followed by s, m, h or d"));
+    }
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let (number, after) = rest.split_at(split);
+        let number: u64 = number.parse().map_err(|_| invalid("expected whole numbers followed by s, m, h or d"))?;
+        let unit_end = after.find(|c: char| c.is_ascii_digit() || c.is_whitespace()).unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_end);
+        let unit_seconds: u64 = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86_400,
+            _ => return Err(invalid("unknown unit; use s, m, h or d after each number")),
+        };
+        seconds = number
+            .checked_mul(unit_seconds)
+            .and_then(|part| seconds.checked_add(part))
+            .ok_or_else(|| invalid("too large"))?;
+        rest = after.trim_start();
+    }
+    non_zero(seconds).ok_or_else(|| invalid("must be greater than zero"))
+}
+
+fn non_zero(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Deserialize a number of seconds written as a number or as a `parse_duration` string such
+/// as "1h30m": `#[serde(deserialize_with = "crate::utils::seconds")]`
+pub fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(u64),
+        Text(String),
+    }
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Number(seconds) => Ok(seconds),
+        Seconds::Text(text) => parse_duration(&text).map(|d| d.as_secs()).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Format a duration with its two largest units, in the units `parse_duration` reads (e.g., "1d 6h", "6h 20m", "45s")
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts = [(seconds / 86_400, "d"), (seconds / 3600 % 24, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")];
+    let first = parts.iter().position(|(n, _)| *n > 0).unwrap_or(parts.len() - 1);
+    parts[first..]
+        .iter()
+        .take(2)
+        .filter(|(n, _)| *n > 0 || first == parts.len() - 1)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// IANA timezone such as `Europe/Berlin`, as `display.timezone` names it
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.trim().parse().map_err(|_| RiskEngineError::Parse {
+        what: "timezone",
+        input: name.to_string(),
+        message: "expected an IANA timezone name such as `UTC`, `Europe/Berlin` or `America/New_York`".to_string(),
+    })
+}
+
+/// Format an instant in `tz` with the zone's abbreviation (e.g., "2024-05-01 14:30:00 CEST")
+pub fn format_timestamp(timestamp: &DateTime<Utc>, tz: Tz) -> String {
+    timestamp.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+/// How long ago `timestamp` was, in its largest unit (e.g., "14m ago", "in 3h", "just now")
+pub fn format_relative(timestamp: &DateTime<Utc>) -> String {
+    format_relative_to(timestamp, Utc::now())
+}
+
+/// `format_relative` as seen at `now`
+pub fn format_relative_to(timestamp: &DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = now.signed_duration_since(*timestamp).num_seconds();
+    if seconds.abs() < 5 {
+        return "just now".to_string();
+    }
+    let units = [(365 * 86_400, "y"), (86_400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let (unit_seconds, unit) = units.into_iter().find(|(unit, _)| seconds.unsigned_abs() >= *unit).unwrap_or((1, "s"));
+    let amount = seconds.unsigned_abs() / unit_seconds;
+    if seconds > 0 {
+        format!("{}{} ago", amount, unit)
+    } else {
+        format!("in {}{}", amount, unit)
+    }
 }
 
 /// Convert a U256 value to f64, accounting for decimals
@@ -71,23 +438,22 @@ pub fn f64_to_u256(value: f64, decimals: u8) -> U256 {
     U256::from(value_u128)
 }
 
-/// Initialize the logger
-pub fn init_logger(level: &str) -> Result<()> {
-    let level = match level.to_lowercase().as_str() {
-        "trace" => tracing::Level::TRACE,
-        "debug" => tracing::Level::DEBUG,
-        "info" => tracing::Level::INFO,
-        "warn" => tracing::Level::WARN,
-        "error" => tracing::Level::ERROR,
-        _ => tracing::Level::INFO,
-    };
-    
-    tracing_subscriber::fmt()
-        .with_max_level(level)
-        .with_target(false)
-        .init();
-    
-    Ok(())
+/// Base URL of the public block explorer for `chain_id`, if known
+pub fn explorer_base_url(chain_id: u64) -> Option<&'static str> {
+    match chain_id {
+        1 => Some("https://etherscan.io"),
+        10 => Some("https://optimistic.etherscan.io"),
+        137 => Some("https://polygonscan.com"),
+        8453 => Some("https://basescan.org"),
+        42161 => Some("https://arbiscan.io"),
+        11155111 => Some("https://sepolia.etherscan.io"),
+        _ => None,
+    }
+}
+
+/// Block explorer page for `address` on `chain_id`, if the chain has a known explorer
+pub fn explorer_address_url(chain_id: u64, address: &Address) -> Option<String> {
+    explorer_base_url(chain_id).map(|base| format!("{}/address/{}", base, to_checksum_address(address)))
 }
 
 #[cfg(test)]
@@ -99,8 +465,57 @@ mod tests {
         let address = Address::from_str("0x1234567890abcdef1234567890abcdef12345678").unwrap();
         let formatted = format_address(&address);
         assert_eq!(formatted, "0x1234...5678");
+        let comet = Address::from_str("0xc3d688b66703497daa19211eedff47f25384cdc3").unwrap();
+        assert_eq!(format_address(&comet), "0xc3d6...cdc3");
+        assert_eq!(to_checksum_address(&comet), "0xc3d688B66703497DAA19211EEdff47f25384cdc3");
+    }
+
+    #[test]
+    fn test_eip55_checksums() {
+        // Test vectors from EIP-55
+        let vectors = [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+            "0xde709f2102306220921060314715629080e2fb77",
+            "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        ];
+        for vector in vectors {
+            let address = parse_address_strict(vector).unwrap();
+            assert_eq!(to_checksum_address(&address), vector);
+            assert_eq!(parse_address_strict(&vector.to_lowercase()).unwrap(), address);
+        }
+
+        // One flipped letter breaks the checksum; the message names the right form
+        let error = parse_address_strict("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").unwrap_err();
+        assert!(error.to_string().contains("did you mean 0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed?"), "{}", error);
+        assert!(parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_ok());
+        assert!(parse_address_strict("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").is_ok());
     }
     
+    #[test]
+    fn test_explorer_address_url() {
+        let address = Address::from_str("0xc3d688B66703497DAA19211EEdff47f25384cdc3").unwrap();
+        assert_eq!(
+            explorer_address_url(1, &address).as_deref(),
+            Some("https://etherscan.io/address/0xc3d688B66703497DAA19211EEdff47f25384cdc3")
+        );
+        assert_eq!(explorer_address_url(31337, &address), None);
+    }
+
+    #[test]
+    fn test_expand_env() {
+        std::env::set_var("COMETGUARD_TEST_SMTP_USER", "ops");
+        assert_eq!(expand_env("f", "${COMETGUARD_TEST_SMTP_USER}@example.com").unwrap(), "ops@example.com");
+        assert_eq!(expand_env("f", "no references").unwrap(), "no references");
+        let err = expand_env("alerts.email.password", "${COMETGUARD_TEST_UNSET}").unwrap_err();
+        assert!(err.to_string().contains("COMETGUARD_TEST_UNSET"), "{}", err);
+        assert_eq!(sanitize_inline("a\r\nb\u{1b}[31m"), "a  b [31m");
+    }
+
     #[test]
     fn test_format_percentage() {
         assert_eq!(format_percentage(0.05), "5.00%");
@@ -112,8 +527,95 @@ mod tests {
         assert_eq!(format_money(1000.0, "$"), "$1,000.00");
         assert_eq!(format_money(1234567.89, "$"), "$1,234,567.89");
         assert_eq!(format_money(-9876.54, "$"), "-$9,876.54");
+
+        // The cents carry into the whole part
+        assert_eq!(format_money(999.999, "$"), "$1,000.00");
+        assert_eq!(format_money(99_999.996, ""), "100,000.00");
+        assert_eq!(format_money(-0.0, "$"), "$0.00");
+        assert_eq!(format_money(-0.004, "$"), "$0.00");
+        // Beyond 2^53 and u64::MAX the digits are still the value's
+        assert_eq!(format_money(9_007_199_254_740_993.0, "$"), "$9,007,199,254,740,992.00");
+        assert_eq!(format_money(1e20, "$"), "$100,000,000,000,000,000,000.00");
+        assert_eq!(format_money(f64::NAN, "$"), "NaN");
+
+        assert_eq!(format_money_with(-1_234_567.891, "€", NumberStyle::DecimalComma), "-€1.234.567,89");
+        assert_eq!(format_money_with(1_234.5, "$", NumberStyle::ThinSpace), "$1\u{2009}234.50");
+        assert_eq!(NumberStyle::DecimalComma.localize("$1.23M (4,567)"), "$1,23M (4.567)");
     }
-    
+
+    #[test]
+    fn test_format_money_matches_reference_formatter() {
+        // Deterministic pseudo-random values from a millionth to a sextillion, both signs
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..20_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let exponent = (state % 28) as i32 - 6;
+            let mantissa = (state >> 11) as f64 / (1u64 << 53) as f64;
+            let value = if state & 1 == 0 { mantissa * 10f64.powi(exponent) } else { -mantissa * 10f64.powi(exponent) };
+
+            let formatted = format_money(value, "");
+            let reference = format!("{:.2}", value.abs());
+            let negative = formatted.starts_with('-');
+            let digits = formatted.trim_start_matches('-').replace(',', "");
+            assert_eq!(digits, reference, "{}", value);
+            assert_eq!(negative, value < 0.0 && reference.bytes().any(|b| b.is_ascii_digit() && b != b'0'), "{}", value);
+            // Groups of three between the separators
+            let whole = formatted.trim_start_matches('-').split('.').next().unwrap();
+            assert!(whole.split(',').skip(1).all(|group| group.len() == 3), "{}", formatted);
+            assert!((1..=3).contains(&whole.split(',').next().unwrap().len()), "{}", formatted);
+
+            for style in [NumberStyle::DecimalComma, NumberStyle::ThinSpace] {
+                assert_eq!(format_money_with(value, "$", style), style.localize(&format_money(value, "$")));
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_amounts() {
+        assert_eq!(format_compact(1_234_567.0), "$1.23M");
+        assert_eq!(format_compact_with(456_700.0, 4), "$456.7K");
+        assert_eq!(format_compact(1.23e9), "$1.23B");
+        assert_eq!(format_compact(-42.0), "-$42.0");
+        assert_eq!(format_compact(0.0), "$0.00");
+        assert_eq!(format_compact(0.001234), "$0.00123");
+        assert_eq!(format_compact(2.5e12), "$2500B");
+        // Rounding carries into the next suffix
+        assert_eq!(format_compact(999.6), "$1.00K");
+        assert_eq!(format_compact(999_950.0), "$1.00M");
+        assert_eq!(format_compact(999_499.0), "$999K");
+        assert_eq!(format_compact_with(999_999_999.0, 0), "$1B");
+        assert_eq!(format_compact(f64::NAN), "NaN");
+        assert_eq!(format_compact(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn test_token_amounts() {
+        assert_eq!(format_token_amount(1234.5, "USDC", 6), "1,234.50 USDC");
+        assert_eq!(format_token_amount(0.004213, "WETH", 18), "0.004213 WETH");
+        assert_eq!(format_token_amount(0.004213, "USDC", 4), "0.0042 USDC");
+        assert_eq!(format_token_amount(-2_500_000.0, "USDC", 6), "-2.50M USDC");
+        assert_eq!(format_token_amount(999_999.0, "USDC", 6), "1.00M USDC");
+        assert_eq!(format_token_amount(0.0, "WBTC", 8), "0.00 WBTC");
+        assert_eq!(format_token_amount(12.0, "NFT", 0), "12 NFT");
+        assert_eq!(format_token_amount(f64::INFINITY, "WETH", 18), "inf WETH");
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("250k").unwrap(), 250_000.0);
+        assert_eq!(parse_amount(" 1.5M ").unwrap(), 1_500_000.0);
+        assert_eq!(parse_amount("2b").unwrap(), 2e9);
+        assert_eq!(parse_amount("1,000_000").unwrap(), 1e6);
+        assert_eq!(parse_amount("-3k").unwrap(), -3_000.0);
+        assert_eq!(parse_amount("0").unwrap(), 0.0);
+        for invalid in ["", "k", "1.5x", "inf", "NaN", "1e3", "one"] {
+            assert!(parse_amount(invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(parse_amount(&format!("{}b", "9".repeat(310))).is_err());
+    }
+
     #[test]
     fn test_u256_to_f64_and_back() {
         let original = 123.456;
@@ -123,4 +625,61 @@ mod tests {
         
         assert!((original - back_to_f64).abs() < 0.000001);
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("5m30s").unwrap(), Duration::from_secs(330));
+        assert_eq!(parse_duration(" 1h 30m ").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("1d1s").unwrap(), Duration::from_secs(86_401));
+        for invalid in ["", "m", "5 minutes", "0s", "0m0s", "-1s", "1.5h", "5m30", "5 30s", "99999999999999999999s"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+        assert_eq!(format_duration(Duration::from_secs(30 * 3600 + 59)), "1d 6h");
+        assert_eq!(format_duration(Duration::from_secs(3600 + 5)), "1h");
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+
+        #[derive(Deserialize)]
+        struct Cooldown {
+            #[serde(deserialize_with = "seconds")]
+            cooldown_seconds: u64,
+        }
+        let parse = |json: &str| serde_json::from_str::<Cooldown>(json).map(|c| c.cooldown_seconds);
+        assert_eq!(parse(r#"{"cooldown_seconds": 900}"#).unwrap(), 900);
+        assert_eq!(parse(r#"{"cooldown_seconds": "1h30m"}"#).unwrap(), 5400);
+        assert!(parse(r#"{"cooldown_seconds": "soon"}"#).unwrap_err().to_string().contains("soon"));
+    }
+
+    #[test]
+    fn test_timestamps_in_a_timezone() {
+        let at = |text: &str| DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc);
+        let berlin = parse_timezone("Europe/Berlin").unwrap();
+        assert_eq!(format_timestamp(&at("2024-05-01T12:30:00Z"), Tz::UTC), "2024-05-01 12:30:00 UTC");
+        assert_eq!(format_timestamp(&at("2024-05-01T12:30:00Z"), berlin), "2024-05-01 14:30:00 CEST");
+        assert_eq!(format_timestamp(&at("2024-01-15T12:30:00Z"), berlin), "2024-01-15 13:30:00 CET");
+        // Either side of the spring-forward gap and twice through the repeated autumn hour
+        assert_eq!(format_timestamp(&at("2024-03-31T00:59:59Z"), berlin), "2024-03-31 01:59:59 CET");
+        assert_eq!(format_timestamp(&at("2024-03-31T01:00:00Z"), berlin), "2024-03-31 03:00:00 CEST");
+        assert_eq!(format_timestamp(&at("2024-10-27T00:30:00Z"), berlin), "2024-10-27 02:30:00 CEST");
+        assert_eq!(format_timestamp(&at("2024-10-27T01:30:00Z"), berlin), "2024-10-27 02:30:00 CET");
+        let new_york = parse_timezone("America/New_York").unwrap();
+        assert_eq!(format_timestamp(&at("2024-07-04T16:00:00Z"), new_york), "2024-07-04 12:00:00 EDT");
+        // The ends of chrono's range
+        for tz in [berlin, new_york, parse_timezone("Pacific/Kiritimati").unwrap()] {
+            assert!(!format_timestamp(&DateTime::<Utc>::MIN_UTC, tz).is_empty());
+            assert!(!format_timestamp(&DateTime::<Utc>::MAX_UTC, tz).is_empty());
+        }
+        assert!(parse_timezone("Mars/Olympus_Mons").unwrap_err().to_string().contains("IANA timezone"));
+
+        let now = at("2024-05-01T12:30:00Z");
+        assert_eq!(format_relative_to(&at("2024-05-01T12:16:00Z"), now), "14m ago");
+        assert_eq!(format_relative_to(&at("2024-05-01T12:29:58Z"), now), "just now");
+        assert_eq!(format_relative_to(&at("2024-05-01T15:45:00Z"), now), "in 3h");
+        assert_eq!(format_relative_to(&at("2024-04-28T12:30:00Z"), now), "3d ago");
+        assert_eq!(format_relative_to(&DateTime::<Utc>::MIN_UTC, now), "264342y ago");
+        assert!(format_relative_to(&DateTime::<Utc>::MAX_UTC, DateTime::<Utc>::MIN_UTC).starts_with("in "));
+    }
+}
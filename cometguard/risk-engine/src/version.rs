@@ -0,0 +1,90 @@
+//! Versions stamped into every assessment
+//!
+//! Scoring semantics evolve, so an assessment records what produced it: the engine build
+//! (`ENGINE_VERSION`, the crate version plus the git commit), the version of each check that
+//! ran, and a hash of the configuration. Checks are versioned independently: bump a check's
+//! entry in `CHECK_VERSIONS` whenever what its findings mean or how they score changes, so
+//! `compare` can tell a changed market from a changed check.
+
+use crate::config::Config;
+use sha2::{Digest, Sha256};
+
+/// Crate version and git commit of this build, e.g. "0.1.0+3f2a9c1d0b7e"
+pub const ENGINE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("COMETGUARD_GIT_HASH"));
+
+/// Version of each built-in check, by the name it has in logs and `RiskAssessment::timed_out`
+pub const CHECK_VERSIONS: &[(&str, u32)] = &[
+    ("sequencer", 1),
+    ("utilization", 1),
+    ("planned_withdrawals", 1),
+    ("outflows", 1),
+    ("price_volatility", 2),
+    ("watchlist", 1),
+    ("asset_settings", 1),
+    ("rewards_runway", 1),
+    ("absorption", 1),
+    ("liquidator_margin", 2),
+    ("protocol_collateral", 1),
+    ("collateral_provenance", 1),
+    ("configuration_drift", 1),
+    ("manager_permissions", 1),
+    ("position_changes", 1),
+    ("borrower_concentration", 1),
+    ("collateral_changes", 1),
+    ("data_quality", 1),
+    ("trend", 1),
+    ("baseline", 1),
+];
+
+/// Checks the engine runs on stored history after the `RiskProcessor`, only with a history store
+pub const HISTORY_CHECKS: &[&str] = &["trend", "baseline"];
+
+/// Version of the built-in check `name`; 0 for names that are not built in
+pub fn check_version(name: &str) -> u32 {
+    CHECK_VERSIONS.iter().find(|(check, _)| *check == name).map_or(0, |(_, version)| *version)
+}
+
+/// Hex SHA-256 of `config` as JSON, so assessments can be matched to the configuration that
+/// produced them
+pub fn config_hash(config: &Config) -> String {
+    let json = serde_json::to_vec(config).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{bundled_fixture, FixtureProvider};
+    use crate::RiskEngine;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_assessments_are_stamped_with_stable_versions() {
+        assert!(ENGINE_VERSION.starts_with(concat!(env!("CARGO_PKG_VERSION"), "+")));
+        let names: std::collections::BTreeSet<_> = CHECK_VERSIONS.iter().map(|(name, _)| name).collect();
+        assert_eq!(names.len(), CHECK_VERSIONS.len(), "a check is listed twice");
+
+        let fixture = Arc::new(FixtureProvider::from_file(&bundled_fixture("basic.json")).unwrap());
+        let engine = RiskEngine::with_provider(Config::default(), fixture.clone());
+        let first = engine.assess_risks().await.unwrap().remove(0);
+        assert_eq!(first.engine_version, ENGINE_VERSION);
+        assert_eq!(first.config_hash, config_hash(&Config::default()));
+        assert_eq!(first.check_versions["utilization"], check_version("utilization"));
+        // History checks only run with a history store
+        assert!(HISTORY_CHECKS.iter().all(|check| !first.check_versions.contains_key(*check)));
+
+        // The same build and configuration stamp the same versions
+        let second = engine.assess_risks().await.unwrap().remove(0);
+        assert_eq!(
+            (&second.engine_version, &second.check_versions, &second.config_hash),
+            (&first.engine_version, &first.check_versions, &first.config_hash)
+        );
+
+        // Another configuration hashes differently
+        let mut config = Config::default();
+        config.risk.max_utilization_threshold = 0.9;
+        let other = RiskEngine::with_provider(config, fixture).assess_risks().await.unwrap().remove(0);
+        assert_ne!(other.config_hash, first.config_hash);
+        assert_eq!(other.check_versions, first.check_versions);
+    }
+}
@@ -0,0 +1,240 @@
+//! Compact change log for `risk-engine-cli watch`
+//!
+//! Built from the scheduler's `RiskEvent`s: changes to a market are held back until its
+//! assessment completes, then rendered together with the score change as one block.
+
+use crate::events::RiskEvent;
+use crate::report::mock_tag;
+use crate::risk::RiskSeverity;
+use crate::utils::{format_address, format_named_address, format_relative, sanitize_inline};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use ethers::types::Address;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// ANSI sequence that clears the terminal and moves the cursor to the top left
+pub const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
+
+/// Latest state of one market, as shown in the summary table
+#[derive(Debug, Clone)]
+struct MarketRow {
+    name: String,
+    score: u8,
+    findings: usize,
+    /// Findings at or above the `fail_on` severity
+    failing: usize,
+    assessed_at: DateTime<Utc>,
+    mock_data: bool,
+}
+
+/// Turns scheduler events into per-market change blocks and a summary table
+#[derive(Debug, Default)]
+pub struct WatchView {
+    fail_on: Option<RiskSeverity>,
+    /// Timezone of the times shown
+    timezone: Tz,
+    markets: BTreeMap<Address, MarketRow>,
+    pending: HashMap<Address, Vec<String>>,
+}
+
+impl WatchView {
+    /// Create a view that flags findings at or above `fail_on`
+    pub fn new(fail_on: Option<RiskSeverity>) -> Self {
+        Self {
+            fail_on,
+            ..Self::default()
+        }
+    }
+
+    /// Show times in `timezone` rather than UTC
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Feed one event; returns the block for a market once its assessment completes, or a note for a configuration reload
+    pub fn observe(&mut self, event: &RiskEvent) -> Option<String> {
+        let change = match event {
+            RiskEvent::NewFinding { finding, .. } => format!("+ {}", finding),
+            RiskEvent::FindingResolved { finding, .. } => format!("- {}", finding),
+            RiskEvent::SeverityEscalated { previous, finding, .. } => format!(
+                "↑ [{:?} → {:?}] {}",
+                previous,
+                finding.severity,
+                sanitize_inline(&finding.description)
+            ),
+            RiskEvent::PositionChanged { change, .. } => format!("Δ {}", sanitize_inline(&change.to_string())),
+            RiskEvent::AssessmentCompleted(assessment) => {
+                let changes = self.pending.remove(&assessment.market_address).unwrap_or_default();
+                let failing = self.fail_on.map_or(0, |threshold| {
+                    assessment.findings.iter().filter(|f| f.severity >= threshold).count()
+                });
+                let row = MarketRow {
+                    name: sanitize_inline(&assessment.market_name),
+                    score: assessment.risk_score,
+                    findings: assessment.findings.len(),
+                    failing,
+                    assessed_at: assessment.timestamp,
+                    mock_data: assessment.mock_data,
+                };
+                let previous = self.markets.insert(assessment.market_address, row.clone());
+                return Some(self.block(&assessment.market_address, &row, previous.map(|p| p.score), &changes));
+            }
+            RiskEvent::ConfigReloaded { changed } => {
+                return Some(format!("Configuration reloaded ({})\n", changed.join(", ")));
+            }
+        };
+        if let Some(market_address) = event.market_address() {
+            self.pending.entry(market_address).or_default().push(change);
+        }
+        None
+    }
+
+    /// One row per market: score, number of findings, findings at or above `fail_on` and when
+    /// it was last assessed
+    pub fn summary_table(&self) -> String {
+        let mut table = String::new();
+        writeln!(table, "{:<12} {:<14} {:>5} {:>8} {:>8}  ASSESSED", "MARKET", "ADDRESS", "SCORE", "FINDINGS", "FAILING").unwrap();
+        for (address, row) in &self.markets {
+            writeln!(
+                table,
+                "{:<12} {:<14} {:>5} {:>8} {:>8}  {} ({}){}",
+                row.name,
+                format_address(address),
+                row.score,
+                row.findings,
+                if self.fail_on.is_some() { row.failing.to_string() } else { "-".to_string() },
+                self.time_of_day(&row.assessed_at),
+                format_relative(&row.assessed_at),
+                mock_tag(row.mock_data)
+            )
+            .unwrap();
+        }
+        table
+    }
+
+    fn block(&self, address: &Address, row: &MarketRow, previous_score: Option<u8>, changes: &[String]) -> String {
+        let score = match previous_score {
+            Some(previous) if previous != row.score => {
+                format!("score {} → {} ({:+})", previous, row.score, i16::from(row.score) - i16::from(previous))
+            }
+            _ => format!("score {}", row.score),
+        };
+        let mut block = format!(
+            "[{}] {}{} {}",
+            self.time_of_day(&row.assessed_at),
+            format_named_address(&row.name, address),
+            mock_tag(row.mock_data),
+            score
+        );
+        if changes.is_empty() {
+            block.push_str(", no changes");
+        }
+        block.push('\n');
+        for change in changes {
+            writeln!(block, "  {}", change).unwrap();
+        }
+        if let (Some(threshold), true) = (self.fail_on, row.failing > 0) {
+            writeln!(block, "  ❌ FAIL: {} finding(s) at or above {:?}", row.failing, threshold).unwrap();
+        }
+        block
+    }
+
+    fn time_of_day(&self, timestamp: &DateTime<Utc>) -> String {
+        timestamp.with_timezone(&self.timezone).format("%H:%M:%S").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::FindingTracker;
+    use crate::position_changes::PositionDelta;
+    use crate::risk::RiskAssessment;
+    use crate::testing::{assessment, finding};
+    use chrono::{TimeZone, Utc};
+
+    /// Block of `assessment`, made at 12:00:05 UTC
+    fn render(view: &mut WatchView, tracker: &mut FindingTracker, assessment: RiskAssessment) -> String {
+        let assessment = RiskAssessment { timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 5).unwrap(), ..assessment };
+        let blocks: Vec<String> = tracker.observe(assessment).iter().filter_map(|e| view.observe(e)).collect();
+        assert_eq!(blocks.len(), 1);
+        blocks.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_blocks_show_only_changes_since_the_previous_run() {
+        let mut view = WatchView::new(Some(RiskSeverity::High));
+        let mut tracker = FindingTracker::new();
+
+        let first = render(&mut view, &mut tracker, assessment(15, vec![finding("a", RiskSeverity::Medium)]));
+        assert_eq!(first, "[12:00:05] USDC (0x0000...0001) score 15\n  + [Medium] a finding\n");
+
+        let unchanged = render(&mut view, &mut tracker, assessment(15, vec![finding("a", RiskSeverity::Medium)]));
+        assert_eq!(unchanged, "[12:00:05] USDC (0x0000...0001) score 15, no changes\n");
+
+        let escalated = render(
+            &mut view,
+            &mut tracker,
+            assessment(35, vec![finding("a", RiskSeverity::High), finding("b", RiskSeverity::Low)]),
+        );
+        assert!(escalated.contains("score 15 → 35 (+20)"), "{}", escalated);
+        assert!(escalated.contains("  ↑ [Medium → High] a finding\n"));
+        assert!(escalated.contains("  + [Low] b finding\n"));
+        assert!(escalated.contains("❌ FAIL: 1 finding(s) at or above High"));
+
+        let resolved = render(&mut view, &mut tracker, assessment(30, vec![finding("a", RiskSeverity::High)]));
+        assert!(resolved.contains("score 35 → 30 (-5)"));
+        assert!(resolved.contains("  - [Low] b finding\n"));
+    }
+
+    #[test]
+    fn test_position_changes_are_shown_with_the_market() {
+        let mut view = WatchView::new(None);
+        let mut tracker = FindingTracker::new();
+        let mut changed = assessment(15, vec![]);
+        changed.position_changes.push(PositionDelta {
+            account: Address::from_low_u64_be(0x5555),
+            previous_borrow_usd: 1_000_000.0,
+            borrow_usd: 1_300_000.0,
+            previous_collateral_usd: 2_000_000.0,
+            collateral_usd: 1_600_000.0,
+            previous_health_factor: 1.82,
+            health_factor: 1.12,
+            collateral_withdrawn: BTreeMap::from([("WETH".to_string(), 400_000.0)]),
+            managers_added: Vec::new(),
+        });
+
+        let block = render(&mut view, &mut tracker, changed);
+        assert_eq!(
+            block,
+            "[12:00:05] USDC (0x0000...0001) score 15\n  Δ 0x0000...5555: borrow $1.00M → $1.30M (+30.0%), withdrew $400K WETH, health factor 1.82 → 1.12\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_table_lists_latest_state_per_market() {
+        let mut view = WatchView::new(None);
+        let mut tracker = FindingTracker::new();
+        render(&mut view, &mut tracker, assessment(45, vec![finding("a", RiskSeverity::High)]));
+        render(&mut view, &mut tracker, assessment(60, vec![finding("a", RiskSeverity::Critical)]));
+
+        let table = view.summary_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("MARKET"));
+        let columns: Vec<&str> = lines[1].split_whitespace().collect();
+        assert_eq!(columns[..6], ["USDC", "0x0000...0001", "60", "1", "-", "12:00:05"]);
+        assert!(columns[6].starts_with('(') && lines[1].ends_with(" ago)"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn test_times_are_shown_in_the_configured_timezone() {
+        let mut view = WatchView::new(None).with_timezone(chrono_tz::Asia::Tokyo);
+        let mut tracker = FindingTracker::new();
+        let block = render(&mut view, &mut tracker, assessment(15, vec![]));
+        assert!(block.starts_with("[21:00:05] USDC"), "{}", block);
+        assert!(view.summary_table().contains(" 21:00:05 ("));
+    }
+}